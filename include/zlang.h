@@ -0,0 +1,50 @@
+/* The Z compiler as a C library, libz_lang, built with the capi feature:
+ *
+ *     cargo rustc --release --lib --features capi --crate-type cdylib
+ *
+ * Strings are NUL-terminated UTF-8. Those the library returns are freed
+ * with z_string_free, except the one of z_version.
+ */
+
+#ifndef ZLANG_H
+#define ZLANG_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The options of z_compile, as zc compile takes them */
+typedef struct ZOptions {
+    /* The optimization level, from 0 to 3 */
+    uint8_t opt_level;
+    /* Leaves out the runtime checks, as --release does */
+    bool release;
+    /* Rejects uses of strings, arrays and structs after they have been moved */
+    bool strict_memory;
+    /* Makes the warnings that are not allowed errors */
+    bool deny_warnings;
+} ZOptions;
+
+/* The options zc compile uses unless told otherwise */
+ZOptions z_options_default(void);
+
+/* Compiles the Z source text source to C, with options, or the default
+ * options when it is NULL. Returns the C, or NULL when the source has
+ * errors. Unless out_diag is NULL, it is set to the warnings and errors
+ * found, rendered as zc prints them, or to NULL when there are none. */
+char *z_compile(const char *source, const ZOptions *options, char **out_diag);
+
+/* Frees a string returned by z_compile; does nothing when it is NULL */
+void z_string_free(char *string);
+
+/* The version of the compiler, which the caller does not free */
+const char *z_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
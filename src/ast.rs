@@ -1,43 +1,123 @@
+// The syntax tree the parser builds and the stages after it work on: a
+// program is a list of statements, which hold expressions. The typechecker
+// fills in what the parser leaves out, such as the instances of generic
+// functions, and the optimization passes rewrite the tree it checked.
+//
+// The tree is public so that tools built on the compiler can read and change
+// it; `visit` walks it without matching every kind of node.
+
 use std::fmt;
 use std::ops::Range;
+use crate::mangle::Instances;
+use crate::source_map::FileId;
 
-#[derive(Debug, Clone, PartialEq)]
+/// The text a node was parsed from: the file it is in, and byte offsets
+/// into it. Nodes the compiler makes up have the default span, which is
+/// empty.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
+    pub file: FileId,
     pub start: usize,
     pub end: usize,
 }
 
-impl From<Range<usize>> for Span {
-    fn from(range: Range<usize>) -> Self {
+impl Span {
+    pub fn new(file: FileId, range: Range<usize>) -> Self {
         Self {
+            file,
             start: range.start,
             end: range.end,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Byte offsets at which each line of `source` starts
+pub fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+/// Zero-based line containing `offset`
+pub fn line_of(line_starts: &[usize], offset: usize) -> usize {
+    match line_starts.binary_search(&offset) {
+        Ok(line) => line,
+        Err(next) => next.saturating_sub(1),
+    }
+}
+
+/// A type as written in the program, or as the typechecker infers it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Int,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
     Float,
     Bool,
     String,
     Void,
     Array(Box<Type>),
+    Optional(Box<Type>),
     Function(Vec<Type>, Box<Type>),
     Struct(String),
+    Enum(String),
+    Generic(String),           // A type parameter such as `T`
+    Applied(String, Vec<Type>), // A generic struct applied to type arguments
     Inferred, // For type inference
 }
 
+impl Type {
+    /// Whether this is `int` or one of the sized integer types
+    pub fn is_integer(&self) -> bool {
+        self.integer_range().is_some()
+    }
+
+    /// Smallest and largest values of an integer type
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        match self {
+            Type::Int | Type::I64 => Some((i64::MIN as i128, i64::MAX as i128)),
+            Type::I8 => Some((i8::MIN as i128, i8::MAX as i128)),
+            Type::I16 => Some((i16::MIN as i128, i16::MAX as i128)),
+            Type::I32 => Some((i32::MIN as i128, i32::MAX as i128)),
+            Type::U8 => Some((0, u8::MAX as i128)),
+            Type::U16 => Some((0, u16::MAX as i128)),
+            Type::U32 => Some((0, u32::MAX as i128)),
+            Type::U64 => Some((0, u64::MAX as i128)),
+            _ => None,
+        }
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::U64)
+    }
+}
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Type::Int => write!(f, "int"),
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
             Type::Float => write!(f, "float"),
             Type::Bool => write!(f, "bool"),
             Type::String => write!(f, "string"),
             Type::Void => write!(f, "void"),
             Type::Array(elem_type) => write!(f, "[{}]", elem_type),
+            Type::Optional(inner) => write!(f, "{}?", inner),
             Type::Function(params, ret) => {
                 write!(f, "fn(")?;
                 for (i, param) in params.iter().enumerate() {
@@ -48,22 +128,37 @@ impl fmt::Display for Type {
                 }
                 write!(f, ") -> {}", ret)
             }
-            Type::Struct(name) => write!(f, "{}", name),
+            Type::Struct(name) | Type::Enum(name) | Type::Generic(name) => write!(f, "{}", name),
+            Type::Applied(name, args) => {
+                write!(f, "{}<", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ">")
+            }
             Type::Inferred => write!(f, "_"),
         }
     }
 }
 
+/// A value written out in the program
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Literal {
-    Int(i64),
+    /// An integer with its sign, which may only fit in `u64`
+    Int(i128),
     Float(f64),
     Bool(bool),
     String(String),
     Null,
 }
 
+/// An operator between two expressions
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -80,13 +175,38 @@ pub enum BinaryOp {
     Or,
 }
 
+impl BinaryOp {
+    /// The operator as C and Z write it
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Neq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Lte => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Gte => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+}
+
+/// An operator before an expression: `-` or `!`
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
     Neg,
     Not,
 }
 
+/// An expression, each kind with the span of its text
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Literal(Literal, Span),
     Identifier(String, Span),
@@ -98,30 +218,216 @@ pub enum Expr {
     Array(Vec<Expr>, Span),
     If(Box<Expr>, Box<Expr>, Option<Box<Expr>>, Span),
     Block(Vec<Stmt>, Span),
-    Lambda(Vec<(String, Option<Type>)>, Box<Expr>, Span),
+    Lambda(Vec<(String, Option<Type>)>, Option<Type>, Box<Expr>, Span), // Parameters, return type and body
+    Range(Box<Expr>, Box<Expr>, Span),
+    Cast(Box<Expr>, Type, Span),
+    Match(Box<Expr>, Vec<MatchArm>, Span),
+    Variant(String, String, Vec<Expr>, Span), // An enum variant with its payload
+    // `value?`, with the return type of the function it may return from,
+    // which the typechecker fills in
+    Try(Box<Expr>, Option<Type>, Span),
+    // The body of a call the optimizer inlined, with the name of the function
+    // called, whose frame debug builds still record
+    Inlined(String, Box<Expr>, Span),
+}
+
+/// A pattern of a `match` and the expression it leads to
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expr,
+    pub span: Span,
+}
+
+/// What a `match` arm matches
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    // `Circle(r)` or `Shape.Circle(r)`, binding the payload to names
+    Variant(Option<String>, String, Vec<String>, Span),
+    Wildcard(Span),
 }
 
+impl Expr {
+    /// The text the expression was parsed from
+    pub fn span(&self) -> &Span {
+        match self {
+            Expr::Literal(_, span)
+            | Expr::Identifier(_, span)
+            | Expr::Binary(_, _, _, span)
+            | Expr::Unary(_, _, span)
+            | Expr::Call(_, _, span)
+            | Expr::Index(_, _, span)
+            | Expr::Field(_, _, span)
+            | Expr::Array(_, span)
+            | Expr::If(_, _, _, span)
+            | Expr::Block(_, span)
+            | Expr::Lambda(_, _, _, span)
+            | Expr::Range(_, _, span)
+            | Expr::Cast(_, _, span)
+            | Expr::Match(_, _, span)
+            | Expr::Variant(_, _, _, span)
+            | Expr::Try(_, _, span)
+            | Expr::Inlined(_, _, span) => span,
+        }
+    }
+
+    /// The span of the expression, to move it
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Expr::Literal(_, span)
+            | Expr::Identifier(_, span)
+            | Expr::Binary(_, _, _, span)
+            | Expr::Unary(_, _, span)
+            | Expr::Call(_, _, span)
+            | Expr::Index(_, _, span)
+            | Expr::Field(_, _, span)
+            | Expr::Array(_, span)
+            | Expr::If(_, _, _, span)
+            | Expr::Block(_, span)
+            | Expr::Lambda(_, _, _, span)
+            | Expr::Range(_, _, span)
+            | Expr::Cast(_, _, span)
+            | Expr::Match(_, _, span)
+            | Expr::Variant(_, _, _, span)
+            | Expr::Try(_, _, span)
+            | Expr::Inlined(_, _, span) => span,
+        }
+    }
+}
+
+/// A statement, or a declaration at the top level of a program
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Stmt {
     Expr(Expr),
     Let(String, Option<Type>, Option<Expr>, Span),
+    Const(String, Option<Type>, Expr, Span),
     Assign(Expr, Expr, Span),
     Return(Option<Expr>, Span),
     While(Expr, Box<Stmt>, Span),
-    For(String, Expr, Box<Stmt>, Span),
+    // The loop variable and its span, what it goes over, and the body
+    For(String, Span, Expr, Box<Stmt>, Span),
     Block(Vec<Stmt>, Span),
-    Function(String, Vec<(String, Type)>, Type, Box<Stmt>, Span),
-    Struct(String, Vec<(String, Type)>, Span),
+    // Name, type parameters, parameters, return type, body, whether it is
+    // `pub`, and its attributes
+    Function(String, Vec<String>, Vec<(String, Type)>, Type, Box<Stmt>, bool, Vec<Attribute>, Span),
+    Struct(String, Vec<String>, Vec<(String, Type)>, Span),
+    Enum(String, Vec<(String, Vec<Type>)>, Span),
     Import(String, Span),
+    // A C function: name, parameters, return type and the library it is in
+    Extern(String, Vec<(String, Type)>, Type, Option<String>, Span),
 }
 
+impl Stmt {
+    /// The text the statement was parsed from
+    pub fn span(&self) -> &Span {
+        match self {
+            Stmt::Expr(expr) => expr.span(),
+            Stmt::Let(_, _, _, span)
+            | Stmt::Const(_, _, _, span)
+            | Stmt::Assign(_, _, span)
+            | Stmt::Return(_, span)
+            | Stmt::While(_, _, span)
+            | Stmt::For(_, _, _, _, span)
+            | Stmt::Block(_, span)
+            | Stmt::Function(_, _, _, _, _, _, _, span)
+            | Stmt::Struct(_, _, _, span)
+            | Stmt::Enum(_, _, span)
+            | Stmt::Import(_, span)
+            | Stmt::Extern(_, _, _, _, span) => span,
+        }
+    }
+
+    /// The span of the statement, to move it
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Stmt::Expr(expr) => expr.span_mut(),
+            Stmt::Let(_, _, _, span)
+            | Stmt::Const(_, _, _, span)
+            | Stmt::Assign(_, _, span)
+            | Stmt::Return(_, span)
+            | Stmt::While(_, _, span)
+            | Stmt::For(_, _, _, _, span)
+            | Stmt::Block(_, span)
+            | Stmt::Function(_, _, _, _, _, _, _, span)
+            | Stmt::Struct(_, _, _, span)
+            | Stmt::Enum(_, _, span)
+            | Stmt::Import(_, span)
+            | Stmt::Extern(_, _, _, _, span) => span,
+        }
+    }
+}
+
+/// An attribute written before a function, as in `@inline fn square(...)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Attribute {
+    /// Inline the function into every call
+    Inline,
+    /// Never inline the function
+    NoInline,
+    /// Optimize the function at this level instead of the program's
+    Optimize(u8),
+    /// The function rarely runs, so it is optimized for size and kept out
+    /// of the way of the code calling it
+    Cold,
+    /// A benchmark, which `zc bench` calls repeatedly and times
+    Bench,
+}
+
+impl Attribute {
+    /// The optimization level `attributes` give a function, if any
+    pub fn opt_level(attributes: &[Attribute]) -> Option<u8> {
+        attributes.iter().find_map(|attribute| match attribute {
+            Attribute::Optimize(level) => Some(*level),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Attribute::Inline => write!(f, "@inline"),
+            Attribute::NoInline => write!(f, "@noinline"),
+            Attribute::Optimize(level) => write!(f, "@optimize({})", level),
+            Attribute::Cold => write!(f, "@cold"),
+            Attribute::Bench => write!(f, "@bench"),
+        }
+    }
+}
+
+/// A source file, as parsed
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     pub statements: Vec<Stmt>,
+    /// Instances of generic functions and structs, recorded by the typechecker
+    pub instances: Instances,
+    /// Functions of the modules the program imports, which it calls by name
+    pub imports: Vec<ImportedFunction>,
 }
 
 impl Program {
+    /// A program of `statements`, before it is checked
     pub fn new(statements: Vec<Stmt>) -> Self {
-        Self { statements }
+        Self {
+            statements,
+            instances: Instances::new(),
+            imports: Vec::new(),
+        }
     }
+}
+
+/// A `pub` function of an imported module, compiled separately
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportedFunction {
+    /// Module declaring the function, which makes up its symbol
+    pub module: String,
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
 }
\ No newline at end of file
@@ -0,0 +1,170 @@
+// Benchmarks: the `@bench` functions of a program, built without run-time
+// checks into an executable whose `main` times one of them. Each benchmark
+// runs in a process of its own, which first doubles the number of calls per
+// sample until a sample takes long enough to time, warming up on the way,
+// and then prints the time of each sample.
+
+use crate::ast::{Attribute, Program, Stmt};
+
+/// How long a sample takes at least, in nanoseconds
+pub const SAMPLE_TIME: u64 = 10_000_000;
+
+/// Samples run and discarded after finding how many calls a sample makes
+pub const WARMUP_SAMPLES: usize = 3;
+
+/// The `@bench` functions of `program` whose names contain `filter`, made
+/// `pub` so that the harness calls them by name
+pub fn export_benches(program: &mut Program, filter: Option<&str>) -> Vec<String> {
+    let mut names = Vec::new();
+    for stmt in &mut program.statements {
+        if let Stmt::Function(name, _, _, _, _, public, attributes, _) = stmt {
+            if attributes.contains(&Attribute::Bench) && filter.iter().all(|filter| name.contains(filter)) {
+                *public = true;
+                names.push(name.clone());
+            }
+        }
+    }
+    names
+}
+
+/// `code`, the C of the program, with its own `main` renamed and one taking
+/// the index of the benchmark in `names`, the time a sample should take
+/// and the number of warmup samples and of samples instead
+pub fn harness(code: &str, names: &[String]) -> String {
+    let mut c_code = String::from("#define main z_bench_program_main\n");
+    c_code.push_str(code);
+    c_code.push_str("#undef main\n\n");
+    c_code.push_str("#include <time.h>\n\n");
+    c_code.push_str("static uint64_t z_bench_now(void) {\n");
+    c_code.push_str("    struct timespec now;\n");
+    c_code.push_str("    timespec_get(&now, TIME_UTC);\n");
+    c_code.push_str("    return (uint64_t)now.tv_sec * 1000000000u + (uint64_t)now.tv_nsec;\n");
+    c_code.push_str("}\n\n");
+
+    // Calls go through a volatile pointer, so the C compiler cannot see
+    // which function they call and move them out of the loop
+    c_code.push_str("static uint64_t z_bench_run(int index, uint64_t iterations) {\n");
+    c_code.push_str("    uint64_t start = z_bench_now();\n");
+    c_code.push_str("    switch (index) {\n");
+    for (index, name) in names.iter().enumerate() {
+        c_code.push_str(&format!("    case {}: {{\n", index));
+        c_code.push_str(&format!("        __typeof__(&{}) volatile function = &{};\n", name, name));
+        c_code.push_str("        for (uint64_t i = 0; i < iterations; i++) (void)function();\n");
+        c_code.push_str("        break;\n");
+        c_code.push_str("    }\n");
+    }
+    c_code.push_str("    }\n");
+    c_code.push_str("    return z_bench_now() - start;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("int main(int argc, char **argv) {\n");
+    c_code.push_str("    if (argc != 5) return 2;\n");
+    c_code.push_str("    int index = atoi(argv[1]);\n");
+    c_code.push_str("    uint64_t sample_time = strtoull(argv[2], NULL, 10);\n");
+    c_code.push_str("    int warmup = atoi(argv[3]);\n");
+    c_code.push_str("    int samples = atoi(argv[4]);\n");
+    c_code.push_str("    uint64_t iterations = 1;\n");
+    c_code.push_str("    while (z_bench_run(index, iterations) < sample_time && iterations < ((uint64_t)1 << 40)) iterations *= 2;\n");
+    c_code.push_str("    for (int i = 0; i < warmup; i++) z_bench_run(index, iterations);\n");
+    c_code.push_str("    for (int i = 0; i < samples; i++) {\n");
+    c_code.push_str("        uint64_t elapsed = z_bench_run(index, iterations);\n");
+    c_code.push_str("        printf(\"%llu %llu\\n\", (unsigned long long)iterations, (unsigned long long)elapsed);\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    return 0;\n");
+    c_code.push_str("}\n");
+    c_code
+}
+
+/// The timings of a benchmark
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    /// Calls per sample
+    pub iterations: u64,
+    /// The time of a call in each sample, in nanoseconds
+    pub times: Vec<f64>,
+}
+
+impl BenchResult {
+    /// Reads the samples the harness printed for benchmark `name`
+    pub(crate) fn parse(name: &str, output: &str) -> Option<BenchResult> {
+        let mut iterations = 0;
+        let mut times = Vec::new();
+        for line in output.lines() {
+            let (calls, elapsed) = line.split_once(' ')?;
+            iterations = calls.parse().ok()?;
+            let elapsed: u64 = elapsed.parse().ok()?;
+            times.push(elapsed as f64 / iterations as f64);
+        }
+        if times.is_empty() {
+            return None;
+        }
+        Some(BenchResult { name: name.to_string(), iterations, times })
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.times.iter().sum::<f64>() / self.times.len() as f64
+    }
+
+    pub fn median(&self) -> f64 {
+        let mut times = self.times.clone();
+        times.sort_by(f64::total_cmp);
+        let middle = times.len() / 2;
+        if times.len() % 2 == 1 {
+            times[middle]
+        } else {
+            (times[middle - 1] + times[middle]) / 2.0
+        }
+    }
+
+    /// The sample standard deviation
+    pub fn stddev(&self) -> f64 {
+        if self.times.len() < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let variance =
+            self.times.iter().map(|time| (time - mean) * (time - mean)).sum::<f64>() / (self.times.len() - 1) as f64;
+        variance.sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    /// A line per benchmark with the mean, median and standard deviation of
+    /// the time of a call
+    pub fn to_text(&self) -> String {
+        let width = self.results.iter().map(|result| result.name.len()).max().unwrap_or(0);
+        let mut text = String::new();
+        for result in &self.results {
+            text.push_str(&format!(
+                "{:<width$}  mean {:>10}  median {:>10}  stddev {:>10}  ({} samples of {} calls)\n",
+                result.name,
+                format_time(result.mean()),
+                format_time(result.median()),
+                format_time(result.stddev()),
+                result.times.len(),
+                result.iterations,
+                width = width
+            ));
+        }
+        text
+    }
+}
+
+/// `nanoseconds` in the largest unit it has one of
+fn format_time(nanoseconds: f64) -> String {
+    if nanoseconds < 1e3 {
+        format!("{:.2} ns", nanoseconds)
+    } else if nanoseconds < 1e6 {
+        format!("{:.2} µs", nanoseconds / 1e3)
+    } else if nanoseconds < 1e9 {
+        format!("{:.2} ms", nanoseconds / 1e6)
+    } else {
+        format!("{:.2} s", nanoseconds / 1e9)
+    }
+}
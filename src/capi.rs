@@ -0,0 +1,130 @@
+// The compiler over a C ABI, for editors, build systems and programs in
+// other languages that embed it. The `capi` feature builds it into a shared
+// library, libz_lang, declared for C in include/zlang.h:
+//
+//     cargo rustc --release --lib --features capi --crate-type cdylib
+//
+// Strings cross the boundary as NUL-terminated UTF-8. Those returned belong
+// to the caller, who frees them with `z_string_free`.
+
+use crate::codegen::{CodegenOptions, Gc};
+use crate::optimizer::PassManager;
+use crate::typechecker::CheckOptions;
+use crate::{check_source, diagnostic, generate_c, CompilerError, CrateType, Diagnostic, Result, SOURCE_NAME};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// The options of `z_compile`, as `zc compile` takes them
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ZOptions {
+    /// The optimization level, from 0 to 3
+    pub opt_level: u8,
+    /// Leaves out the runtime checks, as `--release` does
+    pub release: bool,
+    /// Rejects uses of strings, arrays and structs after they have been
+    /// moved
+    pub strict_memory: bool,
+    /// Makes the warnings that are not allowed errors
+    pub deny_warnings: bool,
+}
+
+impl Default for ZOptions {
+    fn default() -> Self {
+        ZOptions {
+            opt_level: 3,
+            release: false,
+            strict_memory: false,
+            deny_warnings: false,
+        }
+    }
+}
+
+/// The options `zc compile` uses unless told otherwise
+#[no_mangle]
+pub extern "C" fn z_options_default() -> ZOptions {
+    ZOptions::default()
+}
+
+/// Compiles the Z source text `source` to C, with `options`, or the default
+/// options when it is null. Returns the C, or null when the source has
+/// errors. Unless `out_diag` is null, it is set to the warnings and errors
+/// found, rendered as `zc` prints them, or to null when there are none.
+///
+/// # Safety
+///
+/// `source` is a NUL-terminated string, and `options` and `out_diag` are
+/// null or valid for reads and writes respectively.
+#[no_mangle]
+pub unsafe extern "C" fn z_compile(
+    source: *const c_char,
+    options: *const ZOptions,
+    out_diag: *mut *mut c_char,
+) -> *mut c_char {
+    let options = options.as_ref().copied().unwrap_or_default();
+    let (code, diagnostics) = if source.is_null() {
+        (None, "error: no source given\n".to_string())
+    } else {
+        let source = String::from_utf8_lossy(CStr::from_ptr(source).to_bytes());
+        // Unwinding into C is undefined, so a panic is reported as an error
+        match panic::catch_unwind(AssertUnwindSafe(|| compile(&source, &options))) {
+            Ok((Ok(code), warnings)) => (Some(code), render(&warnings)),
+            Ok((Err(CompilerError::Diagnostics(errors)), warnings)) => {
+                (None, render(&warnings.into_iter().chain(errors).collect::<Vec<_>>()))
+            }
+            Ok((Err(e), warnings)) => (None, format!("{}error: {}\n", render(&warnings), e)),
+            Err(_) => (None, "error: the compiler panicked\n".to_string()),
+        }
+    };
+    if !out_diag.is_null() {
+        *out_diag = if diagnostics.is_empty() { ptr::null_mut() } else { into_c(diagnostics) };
+    }
+    code.map_or(ptr::null_mut(), into_c)
+}
+
+/// Frees a string returned by `z_compile`; does nothing when it is null
+///
+/// # Safety
+///
+/// `string` is null or a string `z_compile` returned, not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn z_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// The version of the compiler, which the caller does not free
+#[no_mangle]
+pub extern "C" fn z_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Compiles `source` to C, with the warnings found rather than printed
+fn compile(source: &str, options: &ZOptions) -> (Result<String>, Vec<Diagnostic>) {
+    diagnostic::collect(|| {
+        let passes = PassManager::new(options.opt_level.min(3));
+        let check_options = CheckOptions {
+            strict_memory: options.strict_memory,
+            deny_warnings: options.deny_warnings,
+        };
+        let program = check_source(source, &passes, &check_options)?;
+        let codegen_options = CodegenOptions { gc: Gc::RefCount, checks: !options.release };
+        generate_c(program, CrateType::Bin, "main", SOURCE_NAME, source, &codegen_options, &passes, &mut Vec::new(), |_| {})
+    })
+}
+
+fn render(diagnostics: &[Diagnostic]) -> String {
+    diagnostics.iter().map(|diagnostic| diagnostic.render(false)).collect()
+}
+
+/// `text` as a C string owned by the caller. Code and diagnostics hold no
+/// NUL, but one would end the string there rather than fail.
+fn into_c(mut text: String) -> *mut c_char {
+    if let Some(nul) = text.find('\0') {
+        text.truncate(nul);
+    }
+    CString::new(text).unwrap_or_default().into_raw()
+}
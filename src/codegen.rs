@@ -1,11 +1,28 @@
-use crate::ast::{BinaryOp, Expr, Literal, Program, Stmt, Type, UnaryOp};
-use std::path::Path;
-use std::process::Command;
+use crate::ast::{self, Attribute, BinaryOp, Expr, Literal, MatchArm, Pattern, Program, Span, Stmt, Type, UnaryOp};
+use crate::format::{self, Piece};
+use crate::ir;
+use crate::mangle::{self, Instances};
+use crate::stdlib;
+use crate::typechecker::{array_method, string_method};
+use crate::visit::{walk_type, Visitor};
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "c")]
+use std::ffi::OsString;
+#[cfg(feature = "c")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "c")]
+use std::process::{Command, Stdio};
+#[cfg(feature = "c")]
 use std::fs;
+#[cfg(feature = "c")]
+use tempfile::TempDir;
 
 #[derive(Debug)]
 pub struct CodegenError {
     pub message: String,
+    /// The code the error is about, for errors in the program rather than
+    /// in building it
+    pub span: Option<Span>,
 }
 
 impl std::fmt::Display for CodegenError {
@@ -19,81 +36,265 @@ type Result<T> = std::result::Result<T, CodegenError>;
 /// Simple code generator that outputs C code
 pub struct CodeGenerator {
     indent_level: usize,
+    // Signatures of user functions, used for function values
+    functions: HashMap<String, (Vec<Type>, Type)>,
+    // Variants of each enum and their payload types
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
+    // Fields of each struct and their types
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // Types of the variables in scope
+    locals: HashMap<String, Type>,
+    // Optional variables known not to be null, which are read as their value
+    narrowed: HashSet<String>,
+    // Return type of the C function being generated
+    return_type: Type,
+    // Lambdas and wrappers for functions used as values, emitted before main
+    lifted: Vec<String>,
+    wrapped: HashSet<String>,
+    lambda_count: usize,
+    // Whether to build a library, which has no `main`
+    library: bool,
+    // Module the program is compiled as, and its generic instances, which
+    // make up the symbols of its functions
+    module: String,
+    instances: Instances,
+    // References held by the statement being generated, released after it
+    temps: Vec<(String, Type)>,
+    temp_count: usize,
+    // Reference-counted variables of each enclosing block of the function
+    // being generated, released as the block ends or the function returns
+    scopes: Vec<Vec<(String, Type)>>,
+    // How the runtime allocates and frees memory
+    gc: Gc,
+    // Whether to check for errors at run time
+    checks: bool,
+    // Source file of the program, for the locations of runtime errors
+    file: String,
+    line_starts: Vec<usize>,
+    // Functions of imported modules, with the module declaring each
+    imports: HashMap<String, String>,
+    // Whether this is a module imported by the program and compiled on its
+    // own, which exports nothing and shares the program's runtime state
+    imported: bool,
 }
 
 impl CodeGenerator {
     pub fn new() -> Self {
-        Self { indent_level: 0 }
+        Self {
+            indent_level: 0,
+            functions: HashMap::new(),
+            enums: HashMap::new(),
+            structs: HashMap::new(),
+            locals: HashMap::new(),
+            narrowed: HashSet::new(),
+            return_type: Type::Int,
+            lifted: Vec::new(),
+            wrapped: HashSet::new(),
+            lambda_count: 0,
+            library: false,
+            module: String::new(),
+            instances: Instances::new(),
+            temps: Vec::new(),
+            temp_count: 0,
+            scopes: Vec::new(),
+            gc: Gc::RefCount,
+            checks: false,
+            file: String::new(),
+            line_starts: Vec::new(),
+            imports: HashMap::new(),
+            imported: false,
+        }
     }
 
     fn indent(&self) -> String {
         "    ".repeat(self.indent_level)
     }
 
+    /// C name of a Z function. User functions get mangled symbols so they
+    /// cannot clash with the C library, the runtime or other modules;
+    /// `main` keeps its name.
+    fn c_function_name(&self, name: &str) -> String {
+        match (name, self.imports.get(name)) {
+            ("main", _) => name.to_string(),
+            (_, Some(module)) => mangle::symbol(module, name, &Instances::new()),
+            _ => mangle::symbol(&self.module, name, &self.instances),
+        }
+    }
+
+    /// `file:line` of `span`, quoted for a C string
+    fn location(&self, span: &Span) -> String {
+        let line = ast::line_of(&self.line_starts, span.start) + 1;
+        c_string_literal(&format!("{}:{}", self.file, line))
+    }
+
+    /// Name of a Z function as its programmers know it, for stack traces
+    fn display_name(&self, name: &str) -> String {
+        match self.instances.get(name) {
+            Some((generic, type_args)) => {
+                let type_args: Vec<String> = type_args.iter().map(Type::to_string).collect();
+                format!("{}<{}>", generic, type_args.join(", "))
+            }
+            None => name.to_string(),
+        }
+    }
+
+    fn function_signature(&self, name: &str, params: &[(String, Type)], return_type: &Type) -> String {
+        c_signature(&self.c_function_name(name), params, return_type)
+    }
+
     pub fn generate(&mut self, program: Program) -> Result<String> {
         // For now, we'll generate C code instead of LLVM IR
         // This is much simpler and doesn't require LLVM dependencies
         let mut c_code = String::new();
+        self.instances = program.instances.clone();
         
-        // Add standard includes
-        c_code.push_str("#include <stdio.h>\n");
-        c_code.push_str("#include <stdlib.h>\n");
-        c_code.push_str("#include <stdbool.h>\n");
-        c_code.push_str("#include <string.h>\n");
-        c_code.push_str("#include <math.h>\n\n");
-        
-        // Add Z runtime functions
-        c_code.push_str("// Z language runtime functions\n");
-        
-        // Print function for strings
-        c_code.push_str("void print(const char* message) {\n");
-        c_code.push_str("    printf(\"%s\\n\", message);\n");
-        c_code.push_str("}\n\n");
-        
-        // Print function for integers
-        c_code.push_str("void print_int(int value) {\n");
-        c_code.push_str("    printf(\"%d\\n\", value);\n");
-        c_code.push_str("}\n\n");
-        
-        // Print function for floats
-        c_code.push_str("void print_float(double value) {\n");
-        c_code.push_str("    printf(\"%f\\n\", value);\n");
-        c_code.push_str("}\n\n");
-        
-        // String concatenation with integers
-        c_code.push_str("char* concat_str_int(const char* str, int num) {\n");
-        c_code.push_str("    char buffer[32];\n");
-        c_code.push_str("    sprintf(buffer, \"%d\", num);\n");
-        c_code.push_str("    char* result = malloc(strlen(str) + strlen(buffer) + 1);\n");
-        c_code.push_str("    strcpy(result, str);\n");
-        c_code.push_str("    strcat(result, buffer);\n");
-        c_code.push_str("    return result;\n");
-        c_code.push_str("}\n\n");
-        
-        // String concatenation with floats
-        c_code.push_str("char* concat_str_float(const char* str, double num) {\n");
-        c_code.push_str("    char buffer[32];\n");
-        c_code.push_str("    sprintf(buffer, \"%f\", num);\n");
-        c_code.push_str("    char* result = malloc(strlen(str) + strlen(buffer) + 1);\n");
-        c_code.push_str("    strcpy(result, str);\n");
-        c_code.push_str("    strcat(result, buffer);\n");
-        c_code.push_str("    return result;\n");
-        c_code.push_str("}\n\n");
-        
+        c_code.push_str(&generate_runtime(self.gc, self.imported));
+
+        self.enums = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Enum(name, variants, _) => Some((name.clone(), variants.clone())),
+                _ => None,
+            })
+            .collect();
+        self.structs = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Struct(name, _, fields, _) => Some((name.clone(), fields.clone())),
+                _ => None,
+            })
+            .collect();
+
+        // Optionals other than of strings are structs, which like enums and
+        // structs are defined before anything holding them
+        let mut optionals = Optionals(Vec::new());
+        optionals.visit_program(&program);
+        for import in &program.imports {
+            for ty in import.params.iter().chain([&import.return_type]) {
+                optionals.visit_type(ty);
+            }
+        }
+        for ty in type_order(&self.enums, &self.structs, &optionals.0)? {
+            match &ty {
+                Type::Enum(name) => c_code.push_str(&generate_enum(name, &self.enums[name])),
+                Type::Struct(name) => c_code.push_str(&generate_struct(name, &self.structs[name])),
+                ty => c_code.push_str(&generate_optional(ty)),
+            }
+            if self.is_counted(&ty) {
+                c_code.push_str(&self.generate_counted(&ty));
+            }
+        }
+
+        // Forward declarations, so functions can call each other in any order
+        let mut exports = String::new();
+        for stmt in &program.statements {
+            if let Stmt::Function(name, _, params, return_type, _, public, attributes, _) = stmt {
+                let param_types = params.iter().map(|(_, ty)| ty.clone()).collect();
+                self.functions.insert(name.clone(), (param_types, return_type.clone()));
+                if name != "main" {
+                    c_code.push_str(&format!(
+                        "{}{};\n",
+                        function_specifiers(attributes, *public, false),
+                        self.function_signature(name, params, return_type)
+                    ));
+                }
+                if *public && name != "main" {
+                    exports.push_str(&generate_export(name, &self.c_function_name(name), params, return_type));
+                }
+            }
+            if let Stmt::Extern(name, params, return_type, _, _) = stmt {
+                let param_types = params.iter().map(|(_, ty)| ty.clone()).collect();
+                self.functions.insert(name.clone(), (param_types, return_type.clone()));
+                c_code.push_str(&generate_extern(name, &self.c_function_name(name), params, return_type));
+            }
+        }
+        for import in &program.imports {
+            self.imports.insert(import.name.clone(), import.module.clone());
+            self.functions
+                .insert(import.name.clone(), (import.params.clone(), import.return_type.clone()));
+            let params: Vec<(String, Type)> = import
+                .params
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (format!("a{}", i), ty.clone()))
+                .collect();
+            c_code.push_str(&format!("{};\n", self.function_signature(&import.name, &params, &import.return_type)));
+        }
+        c_code.push('\n');
+
+        if self.library {
+            let kind = if self.imported { "An imported module" } else { "A library" };
+            for stmt in &program.statements {
+                match stmt {
+                    Stmt::Function(name, ..) if name == "main" && self.imported => {
+                        return Err(CodegenError {
+                            message: "An imported module cannot define main".to_string(),
+                            span: Some(stmt.span().clone()),
+                        });
+                    }
+                    Stmt::Function(name, ..) if name == "main" => {
+                        return Err(CodegenError {
+                            message: "A library cannot define main; C code calls its pub functions instead".to_string(),
+                            span: Some(stmt.span().clone()),
+                        });
+                    }
+                    // Constants are inlined by the typechecker
+                    Stmt::Function(..) | Stmt::Struct(..) | Stmt::Enum(..) | Stmt::Import(..) | Stmt::Extern(..) | Stmt::Const(..) => {
+                        self.generate_statement(stmt)?;
+                    }
+                    _ => {
+                        return Err(CodegenError {
+                            message: format!("{} cannot have top-level statements, since nothing would run them", kind),
+                            span: Some(stmt.span().clone()),
+                        });
+                    }
+                }
+            }
+            for lifted in &self.lifted {
+                c_code.push_str(lifted);
+                c_code.push('\n');
+            }
+            if !self.imported {
+                c_code.push_str(&exports);
+            }
+            return Ok(c_code);
+        }
+
         // Generate main function
-        c_code.push_str("int main() {\n");
+        // The body of main is generated into it, so its parameters take the
+        // prefix of the runtime rather than names such as argv that the
+        // program's variables may have
+        let mut main_code = String::from("int main(int z_main_argc, char** z_main_argv) {\n");
         self.indent_level += 1;
+        self.scopes.push(Vec::new());
+        main_code.push_str(&format!("{}z_argc = z_main_argc;\n{}z_argv = z_main_argv;\n", self.indent(), self.indent()));
+        if self.gc == Gc::Boehm {
+            main_code.push_str(&format!("{}GC_INIT();\n", self.indent()));
+        }
+        if self.checks {
+            main_code.push_str(&format!("{}z_enter(\"main\");\n", self.indent()));
+        }
         
         // Generate code for each statement
         for stmt in &program.statements {
-            c_code.push_str(&self.generate_statement(stmt)?);
+            main_code.push_str(&self.generate_statement(stmt)?);
         }
         
         // Add a default return
-        c_code.push_str(&format!("{}return 0;\n", self.indent()));
+        main_code.push_str(&self.release_scope());
+        main_code.push_str(&format!("{}return 0;\n", self.indent()));
         
         self.indent_level -= 1;
-        c_code.push_str("}\n");
+        main_code.push_str("}\n");
+
+        for lifted in &self.lifted {
+            c_code.push_str(lifted);
+            c_code.push('\n');
+        }
+        c_code.push_str(&exports);
+        c_code.push_str(&main_code);
         
         Ok(c_code)
     }
@@ -103,52 +304,36 @@ impl CodeGenerator {
             Stmt::Expr(expr) => {
                 // Special handling for if expressions
                 if let Expr::If(cond, then_branch, else_branch, _) = expr {
-                    let cond_code = self.generate_expression(cond)?;
-                    let mut code = format!("{}if ({}) {{\n", self.indent(), cond_code);
-                    
-                    self.indent_level += 1;
-                    match then_branch.as_ref() {
-                        Expr::Block(stmts, _) => {
-                            for stmt in stmts {
-                                code.push_str(&self.generate_statement(&stmt)?);
-                            }
-                        },
-                        _ => {
-                            let expr_code = self.generate_expression(then_branch)?;
-                            code.push_str(&format!("{}{};\n", self.indent(), expr_code));
-                        }
-                    }
-                    self.indent_level -= 1;
-                    
+                    let (mut code, cond_code) = self.generate_condition(cond)?;
+                    // Within a branch guarded by a null check, the variable
+                    // is known not to be null
+                    let (then_narrowed, else_narrowed) = match self.null_guard(cond) {
+                        Some((name, true)) => (Some(name), None),
+                        Some((name, false)) => (None, Some(name)),
+                        None => (None, None),
+                    };
+                    code.push_str(&format!("{}if ({}) {{\n", self.indent(), cond_code));
+                    code.push_str(&self.with_narrowed(then_narrowed, |generator| generator.generate_branch(then_branch))?);
                     code.push_str(&format!("{}}}", self.indent()));
                     
                     if let Some(else_branch) = else_branch {
-                        code.push_str(&format!(" else {{\n"));
-                        self.indent_level += 1;
-                        match else_branch.as_ref() {
-                            Expr::Block(stmts, _) => {
-                                for stmt in stmts {
-                                    code.push_str(&self.generate_statement(&stmt)?);
-                                }
-                            },
-                            _ => {
-                                let expr_code = self.generate_expression(else_branch)?;
-                                code.push_str(&format!("{}{};\n", self.indent(), expr_code));
-                            }
-                        }
-                        self.indent_level -= 1;
+                        code.push_str(" else {\n");
+                        code.push_str(
+                            &self.with_narrowed(else_narrowed, |generator| generator.generate_branch(else_branch))?,
+                        );
                         code.push_str(&format!("{}}}", self.indent()));
                     }
                     
-                    code.push_str("\n");
+                    code.push('\n');
                     return Ok(code);
                 }
                 
-                // For other expressions
-                let expr_code = self.generate_expression(expr)?;
-                Ok(format!("{}{};\n", self.indent(), expr_code))
+                // For other expressions; a new string or array they produce
+                // is released right away
+                let expr_code = self.generate_borrowed(expr)?;
+                Ok(self.with_temporaries(format!("{}{};\n", self.indent(), expr_code)))
             },
-            Stmt::Function(name, _params, _return_type, body, _span) => {
+            Stmt::Function(name, _type_params, params, return_type, body, public, attributes, _span) => {
                 // For now, we'll just handle the main function specially
                 if name == "main" {
                     // We already generate the main function in the generate method
@@ -164,194 +349,3767 @@ impl CodeGenerator {
                         _ => Ok(String::new())
                     }
                 } else {
-                    // Other functions not implemented yet
-                    Ok(format!("{}// Function {} not implemented yet\n", self.indent(), name))
+                    self.generate_function(name, params, return_type, body, &function_specifiers(attributes, *public, true))?;
+                    Ok(String::new())
                 }
             },
-            Stmt::Let(name, _type, expr, _span) => {
-                let expr_code = match expr {
-                    Some(e) => self.generate_expression(e)?,
-                    None => "0".to_string() // Default initialization
+            Stmt::Let(name, ty, expr, _span) => {
+                let expr_code = match (expr, ty) {
+                    (Some(e), Some(ty)) => self.generate_stored(e, ty)?,
+                    (Some(e), None) => self.generate_owned(e)?,
+                    (None, Some(ty)) => zero_value(ty).to_string(),
+                    (None, None) => "0".to_string(),
                 };
                 
-                // For simplicity, we'll just use C types for now
-                Ok(format!("{}int {} = {};\n", self.indent(), name, expr_code))
+                let c_type = ty.as_ref().map(c_type).unwrap_or_else(|| "int64_t".to_string());
+                self.narrowed.remove(name);
+                if let Some(ty) = ty {
+                    self.locals.insert(name.clone(), ty.clone());
+                    self.declare_counted(name, ty);
+                }
+                Ok(self.with_temporaries(format!("{}{} {} = {};\n", self.indent(), c_type, name, expr_code)))
+            },
+            Stmt::Const(name, ty, value, _span) => {
+                let value_code = self.generate_expression(value)?;
+                let c_type = ty.as_ref().map(c_type).unwrap_or_else(|| "int64_t".to_string());
+                Ok(self.with_temporaries(format!("{}const {} {} = {};\n", self.indent(), c_type, name, value_code)))
             },
             Stmt::Assign(target, value, _span) => {
                 let target_code = self.generate_expression(target)?;
-                let value_code = self.generate_expression(value)?;
-                Ok(format!("{}{} = {};\n", self.indent(), target_code, value_code))
+                let value_code = match self.expr_type(target) {
+                    Some(ty) => self.generate_stored(value, &ty)?,
+                    None => self.generate_owned(value)?,
+                };
+                let code = match self.expr_type(target) {
+                    // The old value is released only once the new one is
+                    // stored, since it may be what the new one came from
+                    Some(ty) if self.is_counted(&ty) => format!(
+                        "{}{{ {ty}* z_target = &({}); {ty} z_old = *z_target; *z_target = {}; {}; }}\n",
+                        self.indent(),
+                        target_code,
+                        value_code,
+                        self.released("z_old", &ty),
+                        ty = c_type(&ty)
+                    ),
+                    _ => format!("{}{} = {};\n", self.indent(), target_code, value_code),
+                };
+                Ok(self.with_temporaries(code))
+            },
+            Stmt::Return(value, _span) => match value {
+                Some(value) => {
+                    let value_code = self.generate_stored(value, &self.return_type.clone())?;
+                    if !self.checks && self.temps.is_empty() && self.scopes.iter().all(|scope| scope.is_empty()) {
+                        return Ok(format!("{}return {};\n", self.indent(), value_code));
+                    }
+                    // The result is computed before anything it was computed
+                    // from is released, and before the function's frame is left
+                    self.indent_level += 1;
+                    let result = format!("{}{} z_result = {};\n", self.indent(), c_type(&self.return_type), value_code);
+                    let mut code = self.with_temporaries(result);
+                    code.push_str(&self.release_all());
+                    code.push_str(&self.leave());
+                    code.push_str(&format!("{}return z_result;\n", self.indent()));
+                    self.indent_level -= 1;
+                    Ok(format!("{}{{\n{}{}}}\n", self.indent(), code, self.indent()))
+                }
+                None => {
+                    let mut code = self.release_all();
+                    code.push_str(&self.leave());
+                    if self.return_type == Type::Void {
+                        code.push_str(&format!("{}return;\n", self.indent()));
+                    } else {
+                        // `main` returns an exit status even when declared without one
+                        code.push_str(&format!("{}return 0;\n", self.indent()));
+                    }
+                    Ok(code)
+                }
             },
 
             Stmt::While(cond, body, _span) => {
-                let cond_code = self.generate_expression(cond)?;
-                let mut code = format!("{}while ({}) {{\n", self.indent(), cond_code);
-                
+                // A condition holding temporaries is tested inside the loop,
+                // so they are released on every iteration
                 self.indent_level += 1;
+                let (setup, cond_code) = self.generate_condition(cond)?;
+                self.indent_level -= 1;
+                let mut code = if setup.is_empty() {
+                    format!("{}while ({}) {{\n", self.indent(), cond_code)
+                } else {
+                    let mut code = format!("{}while (true) {{\n{}", self.indent(), setup);
+                    code.push_str(&format!("{}    if (!{}) break;\n", self.indent(), cond_code));
+                    code
+                };
+                
                 match body.as_ref() {
-                    Stmt::Block(stmts, _) => {
-                        for stmt in stmts {
-                            code.push_str(&self.generate_statement(&stmt)?);
-                        }
-                    },
-                    _ => code.push_str(&self.generate_statement(&body.as_ref())?),
+                    Stmt::Block(stmts, _) => code.push_str(&self.generate_scope(stmts)?),
+                    _ => code.push_str(&self.generate_scope(std::slice::from_ref(body.as_ref()))?),
                 }
-                self.indent_level -= 1;
                 
                 code.push_str(&format!("{}}}\n", self.indent()));
                 Ok(code)
             },
-            // For other statement types, just generate placeholder code
-            _ => Ok(format!("{}// Statement not implemented yet\n", self.indent())),
+            Stmt::For(variable, _, iterable, body, _span) => {
+                let id = self.temp_count;
+                self.temp_count += 1;
+                let mut code = format!("{}{{\n", self.indent());
+                self.indent_level += 1;
+                self.scopes.push(Vec::new());
+                // The bounds or the array are evaluated once, before the loop
+                let (count, item_type, item) = match iterable {
+                    Expr::Range(start, end, _) => {
+                        let start_code = self.generate_expression(start)?;
+                        let end_code = self.generate_expression(end)?;
+                        code.push_str(&self.with_temporaries(format!(
+                            "{}int64_t z_start{id} = {};\n{}int64_t z_end{id} = {};\n",
+                            self.indent(),
+                            start_code,
+                            self.indent(),
+                            end_code,
+                            id = id
+                        )));
+                        (format!("z_end{} - z_start{}", id, id), Type::Int, format!("z_start{} + z_i{}", id, id))
+                    }
+                    // The loop holds a reference to the array, so that
+                    // changing the variable it came from leaves it whole
+                    _ => {
+                        let element = match self.expr_type(iterable) {
+                            Some(Type::Array(element)) => *element,
+                            _ => {
+                                return Err(CodegenError {
+                                    message: "Cannot determine the type of the iterated array".to_string(),
+                                    span: Some(iterable.span().clone()),
+                                });
+                            }
+                        };
+                        let items = format!("z_items{}", id);
+                        let items_code = self.generate_owned(iterable)?;
+                        code.push_str(&self.with_temporaries(format!("{}z_array {} = {};\n", self.indent(), items, items_code)));
+                        self.declare_counted(&items, &Type::Array(Box::new(element.clone())));
+                        let item = self.retained(format!("(({}*){}.data)[z_i{}]", c_type(&element), items, id), &element);
+                        (format!("{}.len", items), element, item)
+                    }
+                };
+                code.push_str(&format!(
+                    "{}for (int64_t z_i{id} = 0; z_i{id} < {}; z_i{id}++) {{\n",
+                    self.indent(),
+                    count,
+                    id = id
+                ));
+                code.push_str(&self.generate_for_body(variable, &item_type, item, body)?);
+                code.push_str(&format!("{}}}\n", self.indent()));
+                code.push_str(&self.release_scope());
+                self.indent_level -= 1;
+                code.push_str(&format!("{}}}\n", self.indent()));
+                Ok(code)
+            },
+            Stmt::Block(stmts, _) => {
+                let mut code = format!("{}{{\n", self.indent());
+                code.push_str(&self.generate_scope(stmts)?);
+                code.push_str(&format!("{}}}\n", self.indent()));
+                Ok(code)
+            }
+            // Emitted ahead of the functions using them
+            Stmt::Enum(..) | Stmt::Struct(..) | Stmt::Extern(..) => Ok(String::new()),
+            // Resolved by the compiler before code generation
+            Stmt::Import(..) => Ok(String::new()),
+        }
+    }
+
+    /// Statements of a nested block, one level deeper, releasing the
+    /// variables declared in it where it ends
+    fn generate_scope(&mut self, stmts: &[Stmt]) -> Result<String> {
+        let saved_locals = self.locals.clone();
+        let saved_narrowed = self.narrowed.clone();
+        self.indent_level += 1;
+        self.scopes.push(Vec::new());
+        let mut code = String::new();
+        for stmt in stmts {
+            code.push_str(&self.generate_statement(stmt)?);
+        }
+        let release = self.release_scope();
+        if !matches!(stmts.last(), Some(Stmt::Return(..))) {
+            code.push_str(&release);
+        }
+        self.indent_level -= 1;
+        self.locals = saved_locals;
+        self.narrowed = saved_narrowed;
+        Ok(code)
+    }
+
+    /// The body of a `for` loop, one level deeper, declaring the loop
+    /// variable `variable` as `value`
+    fn generate_for_body(&mut self, variable: &str, ty: &Type, value: String, body: &Stmt) -> Result<String> {
+        let saved_locals = self.locals.clone();
+        let saved_narrowed = self.narrowed.clone();
+        self.indent_level += 1;
+        self.scopes.push(Vec::new());
+        self.locals.insert(variable.to_string(), ty.clone());
+        self.narrowed.remove(variable);
+        self.declare_counted(variable, ty);
+        let mut code = format!("{}{} {} = {};\n", self.indent(), c_type(ty), variable, value);
+        let stmts = match body {
+            Stmt::Block(stmts, _) => stmts.as_slice(),
+            body => std::slice::from_ref(body),
+        };
+        for stmt in stmts {
+            code.push_str(&self.generate_statement(stmt)?);
+        }
+        let release = self.release_scope();
+        if !matches!(stmts.last(), Some(Stmt::Return(..))) {
+            code.push_str(&release);
+        }
+        self.indent_level -= 1;
+        self.locals = saved_locals;
+        self.narrowed = saved_narrowed;
+        Ok(code)
+    }
+
+    /// A branch of an `if` statement
+    fn generate_branch(&mut self, branch: &Expr) -> Result<String> {
+        match branch {
+            Expr::Block(stmts, _) => self.generate_scope(stmts),
+            _ => self.generate_scope(&[Stmt::Expr(branch.clone())]),
+        }
+    }
+
+    /// C for a condition. When it needs temporaries, it is evaluated into a
+    /// variable by the returned setup code, which releases them afterwards.
+    fn generate_condition(&mut self, cond: &Expr) -> Result<(String, String)> {
+        let cond_code = self.generate_expression(cond)?;
+        if self.temps.is_empty() {
+            return Ok((String::new(), cond_code));
+        }
+        let name = format!("z_cond{}", self.temp_count);
+        self.temp_count += 1;
+        let setup = self.with_temporaries(format!("{}bool {} = {};\n", self.indent(), name, cond_code));
+        Ok((setup, name))
+    }
+
+    /// C for an expression whose value is stored, and so must be a reference
+    /// of its own: variables, elements and fields are retained, while new
+    /// values already are one
+    fn generate_owned(&mut self, expr: &Expr) -> Result<String> {
+        let code = self.generate_expression(expr)?;
+        if !matches!(inlined_body(expr), Expr::Identifier(..) | Expr::Index(..) | Expr::Field(..)) {
+            return Ok(code);
+        }
+        Ok(match self.expr_type(expr) {
+            Some(ty) => self.retained(code, &ty),
+            None => code,
+        })
+    }
+
+    /// C for an expression whose value is only read. A new string or array
+    /// is held in a temporary, released once the statement is done with it.
+    fn generate_borrowed(&mut self, expr: &Expr) -> Result<String> {
+        let code = self.generate_expression(expr)?;
+        let held = matches!(inlined_body(expr), Expr::Identifier(..) | Expr::Index(..) | Expr::Field(..) | Expr::Literal(..));
+        if held || code.starts_with("Z_STR(") {
+            return Ok(code);
+        }
+        Ok(match self.expr_type(expr) {
+            Some(ty) if self.is_counted(&ty) => self.temporary(code, &ty),
+            _ => code,
+        })
+    }
+
+    /// C for `expr` stored as a value of type `ty`, a reference of its own
+    /// as `generate_owned` makes it. An array literal takes its element type
+    /// from `ty`, and the branches of an `if` or `match` their type.
+    fn generate_stored(&mut self, expr: &Expr, ty: &Type) -> Result<String> {
+        match (expr, ty) {
+            (Expr::Array(elements, _), Type::Array(element)) => return self.generate_array(elements, Some(element)),
+            (Expr::If(cond, then_branch, else_branch, _), _) => {
+                return self.generate_if_value(cond, then_branch, else_branch.as_deref(), Some(ty));
+            }
+            (Expr::Block(..), _) => return self.generate_block_value(expr, Some(ty)),
+            (Expr::Match(scrutinee, arms, _), _) => return self.generate_match(scrutinee, arms, Some(ty)),
+            _ => {}
+        }
+        let code = self.generate_owned(expr)?;
+        self.converted(expr, code, ty)
+    }
+
+    /// `code`, the C for `expr`, as a value of type `ty`: a value or `null`
+    /// used as an optional becomes one. `ty` may be the string an optional
+    /// string is represented by, as the type of a field or call is.
+    fn converted(&self, expr: &Expr, code: String, ty: &Type) -> Result<String> {
+        if let Expr::Literal(Literal::Null, _) = expr {
+            if matches!(ty, Type::Optional(_) | Type::String) {
+                return Ok(null_value(ty));
+            }
+        }
+        if !matches!(ty, Type::Optional(_)) {
+            return Ok(code);
+        }
+        if !is_tagged(ty) {
+            return Ok(code);
+        }
+        match self.expr_type(expr) {
+            Some(Type::Optional(_)) => Ok(code),
+            Some(_) => Ok(format!("(({}){{ true, {} }})", c_type(ty), code)),
+            None => Err(CodegenError {
+                message: format!("Cannot determine the type of the value used as {}", ty),
+                span: Some(expr.span().clone()),
+            }),
+        }
+    }
+
+    /// `==` or `!=` with an operand of the optional type `ty`. Optionals
+    /// are equal when both are null or both hold equal values.
+    fn optional_comparison(
+        &mut self,
+        op: &BinaryOp,
+        left: &Expr,
+        left_code: String,
+        right: &Expr,
+        right_code: String,
+        ty: &Type,
+    ) -> Result<String> {
+        let negation = if *op == BinaryOp::Eq { "!" } else { "" };
+        match (left, right) {
+            (_, Expr::Literal(Literal::Null, _)) => return Ok(format!("({}({}).has)", negation, left_code)),
+            (Expr::Literal(Literal::Null, _), _) => return Ok(format!("({}({}).has)", negation, right_code)),
+            _ => {}
+        }
+        let left_code = self.converted(left, left_code, ty)?;
+        let right_code = self.converted(right, right_code, ty)?;
+        let id = self.temp_count;
+        self.temp_count += 1;
+        Ok(format!(
+            "({{ {ty} z_left{id} = {}; {ty} z_right{id} = {}; \
+             {}(z_left{id}.has == z_right{id}.has && (!z_left{id}.has || z_left{id}.value == z_right{id}.value)); }})",
+            left_code,
+            right_code,
+            if *op == BinaryOp::Eq { "" } else { "!" },
+            ty = c_type(ty),
+            id = id
+        ))
+    }
+
+    /// An array literal of `elements`, of type `element_type` if given and
+    /// otherwise that of the first
+    fn generate_array(&mut self, elements: &[Expr], element_type: Option<&Type>) -> Result<String> {
+        let element_type = match (element_type, elements.first()) {
+            (Some(element_type), _) => element_type.clone(),
+            (None, Some(first)) => self.expr_type(first).ok_or_else(|| CodegenError {
+                message: "Cannot determine the element type of the array".to_string(),
+                span: Some(first.span().clone()),
+            })?,
+            (None, None) => return Ok("((z_array){ 0, NULL, NULL })".to_string()),
+        };
+        if elements.is_empty() {
+            return Ok("((z_array){ 0, NULL, NULL })".to_string());
+        }
+        let mut elements_code = Vec::new();
+        for element in elements {
+            elements_code.push(self.generate_stored(element, &element_type)?);
+        }
+        let drop = self.element_drop(&element_type);
+        let c_element = c_type(&element_type);
+        Ok(format!(
+            "z_array_new({}, sizeof({}), ({}[]){{ {} }}, {})",
+            elements.len(),
+            c_element,
+            c_element,
+            elements_code.join(", "),
+            drop
+        ))
+    }
+
+    /// C reading the variable `name`, which for an optional known not to be
+    /// null is its value
+    fn variable(&self, name: &str) -> String {
+        if self.narrowed.contains(name) {
+            format!("{}.value", name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Recognises `x != null` and `x == null` (either way round) where `x` is
+    /// an optional variable holding whether it has a value. Returns the
+    /// variable's name and whether the condition being true means it is not
+    /// null.
+    fn null_guard(&self, cond: &Expr) -> Option<(String, bool)> {
+        let (left, op, right) = match cond {
+            Expr::Binary(left, op @ (BinaryOp::Eq | BinaryOp::Neq), right, _) => (left, op, right),
+            _ => return None,
+        };
+        let name = match (left.as_ref(), right.as_ref()) {
+            (Expr::Identifier(name, _), Expr::Literal(Literal::Null, _))
+            | (Expr::Literal(Literal::Null, _), Expr::Identifier(name, _)) => name,
+            _ => return None,
+        };
+        match self.locals.get(name) {
+            Some(ty) if is_tagged(ty) => Some((name.clone(), *op == BinaryOp::Neq)),
+            _ => None,
+        }
+    }
+
+    /// Runs `generate` with the optional variable `name` (if any) read as its
+    /// value, as the typechecker narrows it
+    fn with_narrowed<T>(&mut self, name: Option<String>, generate: impl FnOnce(&mut Self) -> T) -> T {
+        let (name, optional) = match name.and_then(|name| self.locals.get(&name).cloned().map(|ty| (name, ty))) {
+            Some((name, Type::Optional(inner))) => {
+                self.locals.insert(name.clone(), *inner.clone());
+                self.narrowed.insert(name.clone());
+                (name, Type::Optional(inner))
+            }
+            _ => return generate(self),
+        };
+        let result = generate(self);
+        self.narrowed.remove(&name);
+        self.locals.insert(name, optional);
+        result
+    }
+
+    /// Whether values of `ty` hold references: strings, arrays and closures,
+    /// and the structs, enums and optionals holding any of them
+    fn is_counted(&self, ty: &Type) -> bool {
+        match represented(ty) {
+            Type::String | Type::Array(_) | Type::Function(..) => true,
+            Type::Struct(name) => self
+                .structs
+                .get(&name)
+                .is_some_and(|fields| fields.iter().any(|(_, ty)| self.is_counted(ty))),
+            Type::Enum(name) => self
+                .enums
+                .get(&name)
+                .is_some_and(|variants| variants.iter().flat_map(|(_, payload)| payload).any(|ty| self.is_counted(ty))),
+            Type::Optional(inner) => self.is_counted(&inner),
+            _ => false,
+        }
+    }
+
+    /// `code`, a value of type `ty` held elsewhere, as a reference of its own
+    fn retained(&self, code: String, ty: &Type) -> String {
+        match represented(ty) {
+            Type::String => format!("z_str_retain({})", code),
+            Type::Array(_) => format!("z_array_retain({})", code),
+            Type::Function(..) => format!("z_closure_retain({})", code),
+            ty if self.is_counted(&ty) => format!("z_retain_{}({})", c_type(&ty), code),
+            _ => code,
+        }
+    }
+
+    /// C retaining the references `code`, a value of the counted type `ty`,
+    /// holds
+    fn retain(&self, code: &str, ty: &Type) -> String {
+        match represented(ty) {
+            Type::String | Type::Array(_) | Type::Function(..) => format!("z_retain({}.rc)", code),
+            ty => format!("z_retain_{}({})", c_type(&ty), code),
+        }
+    }
+
+    /// C releasing the references `code`, a value of the counted type `ty`,
+    /// holds
+    fn released(&self, code: &str, ty: &Type) -> String {
+        match represented(ty) {
+            Type::String | Type::Array(_) | Type::Function(..) => format!("z_release({}.rc)", code),
+            ty => format!("z_release_{}({})", c_type(&ty), code),
+        }
+    }
+
+    /// The C function releasing the elements of an array of `element` along
+    /// with it, for elements holding references
+    fn element_drop(&self, element: &Type) -> String {
+        match represented(element) {
+            Type::String => "z_drop_strings".to_string(),
+            Type::Array(_) => "z_drop_arrays".to_string(),
+            Type::Function(..) => "z_drop_closures".to_string(),
+            ty if self.is_counted(&ty) => format!("z_drop_{}_items", c_type(&ty)),
+            _ => "NULL".to_string(),
+        }
+    }
+
+    /// The C function retaining an element of an array of `element` through
+    /// a pointer to it, for elements holding references
+    fn element_retain(&self, element: &Type) -> String {
+        match represented(element) {
+            Type::String | Type::Array(_) | Type::Function(..) => "z_retain_element".to_string(),
+            ty if self.is_counted(&ty) => format!("z_retain_{}_item", c_type(&ty)),
+            _ => "NULL".to_string(),
+        }
+    }
+
+    /// The functions releasing and retaining the references a value of the
+    /// struct, enum or optional `ty` holds, and the elements of arrays of it
+    fn generate_counted(&self, ty: &Type) -> String {
+        let name = c_type(ty);
+        // The statements applying `statement` to each counted value a value
+        // holds
+        let body = |statement: &dyn Fn(&str, &Type) -> String| {
+            let mut code = String::new();
+            match ty {
+                Type::Struct(struct_name) => {
+                    for (field, ty) in self.structs[struct_name].iter().filter(|(_, ty)| self.is_counted(ty)) {
+                        code.push_str(&format!("    {};\n", statement(&format!("value.{}", field), ty)));
+                    }
+                }
+                Type::Enum(enum_name) => {
+                    code.push_str("    switch (value.tag) {\n");
+                    for (variant, payload) in &self.enums[enum_name] {
+                        if !payload.iter().any(|ty| self.is_counted(ty)) {
+                            continue;
+                        }
+                        code.push_str(&format!("    case {}_{}:\n", name, variant));
+                        for (i, ty) in payload.iter().enumerate().filter(|(_, ty)| self.is_counted(ty)) {
+                            let payload = format!("value.payload.{}._{}", variant, i);
+                            code.push_str(&format!("        {};\n", statement(&payload, ty)));
+                        }
+                        code.push_str("        break;\n");
+                    }
+                    code.push_str("    }\n");
+                }
+                Type::Optional(inner) => {
+                    code.push_str(&format!("    if (value.has) {};\n", statement("value.value", inner)));
+                }
+                _ => {}
+            }
+            code
+        };
+        let release = body(&|code, ty| self.released(code, ty));
+        let retain = body(&|code, ty| self.retain(code, ty));
+
+        let mut code = format!("static void z_release_{}({} value) {{\n{}}}\n\n", name, name, release);
+        code.push_str(&format!("static {} z_retain_{}({} value) {{\n{}    return value;\n}}\n\n", name, name, name, retain));
+        code.push_str(&format!("static void z_drop_{}_items(z_rc* rc) {{\n", name));
+        code.push_str("    int64_t len = *(int64_t*)(rc + 1);\n");
+        code.push_str(&format!("    {}* elements = ({}*)((int64_t*)(rc + 1) + 2);\n", name, name));
+        code.push_str(&format!("    for (int64_t i = 0; i < len; i++) z_release_{}(elements[i]);\n", name));
+        code.push_str("}\n\n");
+        code.push_str(&format!("static void z_retain_{}_item(void* element) {{\n", name));
+        code.push_str(&format!("    z_retain_{}(*({}*)element);\n", name, name));
+        code.push_str("}\n\n");
+        code
+    }
+
+    /// Stores a new reference in a temporary of the current statement
+    fn temporary(&mut self, code: String, ty: &Type) -> String {
+        let name = format!("z_tmp{}", self.temp_count);
+        self.temp_count += 1;
+        self.temps.push((name.clone(), ty.clone()));
+        format!("({} = {})", name, code)
+    }
+
+    /// Declares the temporaries of the statement in `code` ahead of it and
+    /// releases them after it
+    fn with_temporaries(&mut self, code: String) -> String {
+        let temps = std::mem::take(&mut self.temps);
+        let mut result = String::new();
+        for (name, ty) in &temps {
+            result.push_str(&format!("{}{} {} = {};\n", self.indent(), c_type(ty), name, zero_value(ty)));
+        }
+        result.push_str(&code);
+        for (name, ty) in temps.iter().rev() {
+            result.push_str(&format!("{}{};\n", self.indent(), self.released(name, ty)));
+        }
+        result
+    }
+
+    /// Records a variable of the current block to release where it ends
+    fn declare_counted(&mut self, name: &str, ty: &Type) {
+        if let (true, Some(scope)) = (self.is_counted(ty), self.scopes.last_mut()) {
+            scope.push((name.to_string(), ty.clone()));
+        }
+    }
+
+    /// Releases the variables of the innermost block, which ends here
+    fn release_scope(&mut self) -> String {
+        let scope = self.scopes.pop().unwrap_or_default();
+        scope
+            .iter()
+            .rev()
+            .map(|(name, ty)| format!("{}{};\n", self.indent(), self.released(name, ty)))
+            .collect()
+    }
+
+    /// Leaves the frame of the function, which returns
+    fn leave(&self) -> String {
+        if self.checks {
+            format!("{}z_leave();\n", self.indent())
+        } else {
+            String::new()
         }
     }
+
+    /// Releases the variables of every block of the function, which returns
+    fn release_all(&self) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .map(|(name, ty)| format!("{}{};\n", self.indent(), self.released(name, ty)))
+            .collect()
+    }
     
     fn generate_expression(&mut self, expr: &Expr) -> Result<String> {
         match expr {
             Expr::Literal(lit, _) => {
                 match lit {
-                    Literal::Int(i) => Ok(i.to_string()),
-                    Literal::Float(f) => Ok(f.to_string()),
-                    Literal::Bool(b) => Ok(if *b { "1".to_string() } else { "0".to_string() }),
-                    Literal::String(s) => Ok(format!("\"{}\"", s)),
+                    Literal::Int(i) => Ok(c_int_literal(*i)),
+                    // Debug formatting keeps the decimal point, so C sees a double
+                    Literal::Float(f) => Ok(format!("{:?}", f)),
+                    Literal::Bool(b) => Ok(b.to_string()),
+                    Literal::String(s) => Ok(format!("Z_STR({})", c_string_literal(s))),
                     Literal::Null => Ok("NULL".to_string()),
                 }
             },
-            Expr::Identifier(name, _) => Ok(name.clone()),
-            Expr::Binary(left, op, right, _) => {
-                let left_code = self.generate_expression(left)?;
-                let right_code = self.generate_expression(right)?;
+            Expr::Identifier(name, _) if !self.locals.contains_key(name) && self.functions.contains_key(name) => {
+                self.function_value(name)
+            }
+            Expr::Identifier(name, _) => Ok(self.variable(name)),
+            Expr::Binary(left, op, right, span) => {
+                let mut left_code = self.generate_borrowed(left)?;
+                // `x != null && ...` and `x == null || ...` only evaluate their
+                // right side when `x` is not null
+                let narrowed = match (op, self.null_guard(left)) {
+                    (BinaryOp::And, Some((name, true))) | (BinaryOp::Or, Some((name, false))) => Some(name),
+                    _ => None,
+                };
+                let mut right_code = self.with_narrowed(narrowed, |generator| generator.generate_borrowed(right))?;
                 
-                // Special case for string concatenation
-                if let BinaryOp::Add = op {
-                    if left_code.starts_with("\"") && left_code.ends_with("\"") {
-                        // String + something
-                        if right_code.starts_with("\"") && right_code.ends_with("\"") {
-                            // String + String
-                            // For simplicity, we'll just use a C function to concatenate
-                            let left_without_quotes = &left_code[1..left_code.len()-1];
-                            let right_without_quotes = &right_code[1..right_code.len()-1];
-                            let combined = format!("{}{}", left_without_quotes, right_without_quotes);
-                            return Ok(format!("\"{}\"", combined));
-                        } else {
-                            // String + Int/Float
-                            return Ok(format!("concat_str_int({}, {})", left_code, right_code));
+                // An optional string, such as an element of `[string?]`, is
+                // compared as the string it is represented by
+                let mut left_type = self.expr_type(left).map(|ty| represented(&ty));
+                let mut right_type = self.expr_type(right).map(|ty| represented(&ty));
+                if let (BinaryOp::Eq | BinaryOp::Neq, Some(ty)) =
+                    (op, [&left_type, &right_type].into_iter().flatten().find(|ty| is_tagged(ty)).cloned())
+                {
+                    return self.optional_comparison(op, left, left_code, right, right_code, &ty);
+                }
+                if left_type == Some(Type::String) || right_type == Some(Type::String) {
+                    // The text of a value concatenated with a string is a new
+                    // string, released with the statement's temporaries
+                    if matches!(op, BinaryOp::Add) {
+                        if let Some(ty) = left_type.clone().filter(|ty| *ty != Type::String) {
+                            left_code = self.temporary(to_c_string(&left_code, ty), &Type::String);
+                            left_type = Some(Type::String);
                         }
+                        if let Some(ty) = right_type.clone().filter(|ty| *ty != Type::String) {
+                            right_code = self.temporary(to_c_string(&right_code, ty), &Type::String);
+                            right_type = Some(Type::String);
+                        }
+                    }
+                    if let Some(code) = string_operation(op, &left_code, left_type.clone(), &right_code, right_type.clone()) {
+                        return Ok(code);
                     }
                 }
                 
-                let op_str = match op {
-                    BinaryOp::Add => "+",
-                    BinaryOp::Sub => "-",
-                    BinaryOp::Mul => "*",
-                    BinaryOp::Div => "/",
-                    BinaryOp::Mod => "%",
-                    BinaryOp::Eq => "==",
-                    BinaryOp::Neq => "!=",
-                    BinaryOp::Lt => "<",
-                    BinaryOp::Lte => "<=",
-                    BinaryOp::Gt => ">",
-                    BinaryOp::Gte => ">=",
-                    BinaryOp::And => "&&",
-                    BinaryOp::Or => "||",
+                // Checked arithmetic is done in the operands' Z type, since C
+                // would widen small integers before overflowing them
+                let builtin = match op {
+                    BinaryOp::Add => Some("z_add_overflow"),
+                    BinaryOp::Sub => Some("z_sub_overflow"),
+                    BinaryOp::Mul => Some("z_mul_overflow"),
+                    _ => None,
+                };
+                let ty = match left.as_ref() {
+                    Expr::Literal(..) => right_type.or(left_type),
+                    _ => left_type.or(right_type),
                 };
+                let ty = ty.filter(Type::is_integer);
+                // Division is checked unless it is by a literal that can
+                // neither be zero nor overflow the smallest value
+                let divisor_checked = !matches!(right.as_ref(), Expr::Literal(Literal::Int(value), _) if *value != 0 && *value != -1);
+                if let (true, BinaryOp::Div | BinaryOp::Mod, true, Some(ty)) = (self.checks, op, divisor_checked, &ty) {
+                    let id = self.temp_count;
+                    self.temp_count += 1;
+                    let location = self.location(span);
+                    let overflow = match c_min(ty) {
+                        Some(min) => format!(
+                            " if (z_right{id} == -1 && z_left{id} == {}) z_panic(\"integer overflow\", {});",
+                            min,
+                            location,
+                            id = id
+                        ),
+                        None => String::new(),
+                    };
+                    return Ok(format!(
+                        "({{ {ty} z_left{id} = {}; {ty} z_right{id} = {}; if (z_right{id} == 0) z_panic(\"division by zero\", {});{} z_left{id} {} z_right{id}; }})",
+                        left_code,
+                        right_code,
+                        location,
+                        overflow,
+                        op.symbol(),
+                        ty = c_type(ty),
+                        id = id
+                    ));
+                }
+                if let (true, Some(builtin), Some(ty)) = (self.checks, builtin, &ty) {
+                    let name = format!("z_result{}", self.temp_count);
+                    self.temp_count += 1;
+                    return Ok(format!(
+                        "({{ {} {name}; if ({}({}, {}, &{name})) z_panic(\"integer overflow\", {}); {name}; }})",
+                        c_type(ty),
+                        builtin,
+                        left_code,
+                        right_code,
+                        self.location(span),
+                        name = name
+                    ));
+                }
+
+                if let Some(code) = ty.as_ref().and_then(|ty| wrapped_arithmetic(ty, op, &left_code, &right_code)) {
+                    return Ok(code);
+                }
+
+                let op_str = op.symbol();
                 
                 Ok(format!("({} {} {})", left_code, op_str, right_code))
             },
-            Expr::Unary(op, expr, _) => {
+            Expr::Unary(op, expr, span) => {
                 let expr_code = self.generate_expression(expr)?;
+                // Negating the smallest value overflows, as subtracting it
+                // from zero does; no literal is that value
+                let literal = matches!(expr.as_ref(), Expr::Literal(..));
+                if let (true, UnaryOp::Neg, false, Some(ty)) =
+                    (self.checks, op, literal, self.expr_type(expr).filter(Type::is_integer))
+                {
+                    let name = format!("z_result{}", self.temp_count);
+                    self.temp_count += 1;
+                    return Ok(format!(
+                        "({{ {} {name}; if (z_sub_overflow(0, {}, &{name})) z_panic(\"integer overflow\", {}); {name}; }})",
+                        c_type(&ty),
+                        expr_code,
+                        self.location(span),
+                        name = name
+                    ));
+                }
                 
+                if let (UnaryOp::Neg, Some(ty)) = (op, self.expr_type(expr).filter(|ty| wrapped_type(ty).is_some())) {
+                    return Ok(format!("(({})(-({}){}))", c_type(&ty), wrapped_type(&ty).unwrap_or_default(), expr_code));
+                }
+
                 let op_str = match op {
                     UnaryOp::Neg => "-",
                     UnaryOp::Not => "!",
                 };
-                
-                Ok(format!("({}{})", op_str, expr_code))
+                // `-` before a negative literal must not make `--`
+                let separator = if expr_code.starts_with('-') { " " } else { "" };
+                Ok(format!("({}{}{})", op_str, separator, expr_code))
             },
-            Expr::Call(func, args, _) => {
-                let func_code = self.generate_expression(func)?;
-                
+            Expr::Call(func, args, span) => {
+                if let Expr::Identifier(name, _) = func.as_ref() {
+                    if format::is_print(name) && !self.locals.contains_key(name) && !self.functions.contains_key(name) {
+                        return self.generate_print(name, args);
+                    }
+                }
+                if let Expr::Field(object, method, _) = func.as_ref() {
+                    match self.expr_type(object) {
+                        Some(Type::String) => return self.generate_string_method(object, method, args, span),
+                        Some(Type::Array(element)) => return self.generate_array_method(object, &element, method, args, span),
+                        _ => {}
+                    }
+                }
+
+                // Functions only read their arguments; a function keeping one
+                // takes a reference of its own
+                let param_types = match self.expr_type(func) {
+                    Some(Type::Function(params, _)) => params,
+                    _ => Vec::new(),
+                };
                 let mut args_code = Vec::new();
-                for arg in args {
-                    args_code.push(self.generate_expression(arg)?);
+                for (i, arg) in args.iter().enumerate() {
+                    let arg_code = match (arg, param_types.get(i)) {
+                        // An array literal is built with the parameter's
+                        // element type, which may be optional
+                        (Expr::Array(elements, _), Some(ty @ Type::Array(element))) => {
+                            let code = self.generate_array(elements, Some(element))?;
+                            self.temporary(code, ty)
+                        }
+                        _ => self.generate_borrowed(arg)?,
+                    };
+                    args_code.push(match param_types.get(i) {
+                        Some(ty) => self.converted(arg, arg_code, ty)?,
+                        None => arg_code,
+                    });
                 }
-                
-                Ok(format!("{}({})", func_code, args_code.join(", ")))
+
+                // Named functions are called directly, anything else through
+                // its closure
+                if let Expr::Identifier(name, _) = func.as_ref() {
+                    if name == "len" && !self.locals.contains_key(name) && !self.functions.contains_key(name) {
+                        return Ok(format!("({}).len", args_code.join(", ")));
+                    }
+                    if name == "panic" && !self.locals.contains_key(name) && !self.functions.contains_key(name) {
+                        return Ok(format!("z_panic(({}).data, {})", args_code.join(", "), self.location(span)));
+                    }
+                    if self.is_process(name) {
+                        return Ok(format!("z_{}({})", name, args_code.join(", ")));
+                    }
+                    if name == "assert" && !self.locals.contains_key(name) && !self.functions.contains_key(name) {
+                        let message = match args_code.get(1) {
+                            Some(message) => format!("({}).data", message),
+                            None => "NULL".to_string(),
+                        };
+                        return Ok(format!(
+                            "(({}) ? (void)0 : z_assert_failed({}, {}))",
+                            args_code[0],
+                            message,
+                            self.location(span)
+                        ));
+                    }
+                    if self.is_math(name) {
+                        return Ok(self.generate_math(name, args, &args_code, span));
+                    }
+                    if !self.locals.contains_key(name) {
+                        let call = format!("{}({})", self.c_function_name(name), args_code.join(", "));
+                        return Ok(self.traced_call(call, span));
+                    }
+                }
+                let (params, ret) = match self.expr_type(func) {
+                    Some(Type::Function(params, ret)) => (params, ret),
+                    _ => {
+                        return Err(CodegenError {
+                            message: "Cannot determine the type of the called expression".to_string(),
+                            span: Some(func.span().clone()),
+                        });
+                    }
+                };
+                let pointer_type = function_pointer_type(&params, &ret);
+                let closure = self.generate_expression(func)?;
+                let mut call_args = vec!["z_callee.env".to_string()];
+                call_args.extend(args_code);
+                let call = format!(
+                    "({{ z_closure z_callee = {}; (({})z_callee.fn)({}); }})",
+                    closure,
+                    pointer_type,
+                    call_args.join(", ")
+                );
+                Ok(self.traced_call(call, span))
+            },
+            Expr::Lambda(params, return_type, body, _) => {
+                let return_type = return_type.clone().unwrap_or(Type::Void);
+                self.generate_lambda(params, return_type, body)
+            },
+            Expr::Cast(operand, target, _) => {
+                let operand_code = self.generate_expression(operand)?;
+                Ok(format!("(({}){})", c_type(target), operand_code))
+            },
+            Expr::Array(elements, _) => self.generate_array(elements, None),
+            Expr::Variant(enum_name, variant, args, _) => {
+                let payload = self
+                    .enums
+                    .get(enum_name)
+                    .and_then(|variants| variants.iter().find(|(name, _)| name == variant))
+                    .map(|(_, payload)| payload.clone())
+                    .unwrap_or_default();
+                let mut args_code = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    args_code.push(match payload.get(i) {
+                        Some(ty) => self.generate_stored(arg, ty)?,
+                        None => self.generate_owned(arg)?,
+                    });
+                }
+                Ok(format!("{}_{}_new({})", mangle::type_name(enum_name), variant, args_code.join(", ")))
+            },
+            Expr::Match(scrutinee, arms, _) => {
+                let result_type = self.expr_type(expr).filter(|ty| *ty != Type::Void);
+                self.generate_match(scrutinee, arms, result_type.as_ref())
+            }
+            Expr::Try(value, return_type, _) => self.generate_try(value, return_type.as_ref()),
+            Expr::Inlined(function, body, span) => self.generate_inlined(function, body, span),
+            Expr::Index(array, index, span) => {
+                let element_type = match self.expr_type(array) {
+                    Some(Type::Array(element_type)) => *element_type,
+                    _ => {
+                        return Err(CodegenError {
+                            message: "Cannot determine the type of the indexed array".to_string(),
+                            span: Some(span.clone()),
+                        });
+                    }
+                };
+                let array_code = self.generate_borrowed(array)?;
+                let index_code = self.generate_expression(index)?;
+                if !self.checks {
+                    return Ok(format!("(({}*)({}).data)[{}]", c_type(&element_type), array_code, index_code));
+                }
+                // The element is reached through a pointer, so that the
+                // checked access can still be assigned to
+                let id = self.temp_count;
+                self.temp_count += 1;
+                Ok(format!(
+                    "(*({{ z_array z_indexed{id} = {}; int64_t z_index{id} = (int64_t)({}); \
+                     if (z_index{id} < 0 || z_index{id} >= z_indexed{id}.len) z_panic(\"index out of bounds\", {}); \
+                     &(({}*)z_indexed{id}.data)[z_index{id}]; }}))",
+                    array_code,
+                    index_code,
+                    self.location(span),
+                    c_type(&element_type),
+                    id = id
+                ))
             },
-            // For now, just generate placeholder code for other expressions
-            _ => Ok("/* Expression not implemented yet */".to_string()),
+            // A struct is a C value, reached through its variable or element,
+            // or through a temporary when it is new
+            Expr::Field(object, field, _) => Ok(format!("({}).{}", self.generate_borrowed(object)?, field)),
+            Expr::If(cond, then_branch, else_branch, _) => {
+                let result_type = self.expr_type(expr).filter(|ty| *ty != Type::Void);
+                self.generate_if_value(cond, then_branch, else_branch.as_deref(), result_type.as_ref())
+            }
+            Expr::Block(..) => {
+                let result_type = self.expr_type(expr).filter(|ty| *ty != Type::Void);
+                self.generate_block_value(expr, result_type.as_ref())
+            }
+            Expr::Range(..) => Err(CodegenError {
+                message: "A range can only be iterated over by a for loop".to_string(),
+                span: Some(expr.span().clone()),
+            }),
         }
     }
-}
 
-pub fn generate_ir(program: Program) -> Result<String> {
-    let mut codegen = CodeGenerator::new();
-    codegen.generate(program)
-}
+    /// The body of a call the optimizer inlined. Debug builds record the
+    /// call in the caller's frame and run the body in a frame of its own, as
+    /// they would the call.
+    fn generate_inlined(&mut self, function: &str, body: &Expr, span: &Span) -> Result<String> {
+        let body_code = self.generate_expression(body)?;
+        if !self.checks {
+            return Ok(body_code);
+        }
+        let enter = format!(
+            "z_at({}); z_enter({});",
+            self.location(span),
+            c_string_literal(&self.display_name(function))
+        );
+        Ok(match self.expr_type(body) {
+            Some(Type::Void) => format!("({{ {} {}; z_leave(); }})", enter, body_code),
+            ty => {
+                let name = format!("z_inlined{}", self.temp_count);
+                self.temp_count += 1;
+                let ty = match ty {
+                    Some(ty) => c_type(&ty),
+                    None => format!("__typeof__({})", body_code),
+                };
+                format!("({{ {} {} {name} = {}; z_leave(); {name}; }})", enter, ty, body_code, name = name)
+            }
+        })
+    }
 
-pub fn generate_executable(code: &str, output_path: &Path) -> Result<()> {
-    // Write C code to a temporary file
-    let temp_dir = std::env::temp_dir();
-    let c_path = temp_dir.join("z_program.c");
-    
-    fs::write(&c_path, code).map_err(|e| CodegenError {
-        message: format!("Failed to write C code to file: {}", e),
-    })?;
-    
-    // Compile C code to executable using GCC or Clang
-    let compiler = if Command::new("gcc").arg("--version").status().is_ok() {
-        "gcc"
-    } else if Command::new("clang").arg("--version").status().is_ok() {
-        "clang"
-    } else {
-        return Err(CodegenError {
-            message: "Neither GCC nor Clang found. Please install a C compiler.".to_string(),
-        });
-    };
-    
-    // Add optimization flags for maximum performance
-    let status = Command::new(compiler)
-        .arg("-O3")                // Maximum optimization
-        .arg("-march=native")      // Optimize for current CPU
-        .arg("-flto")              // Link-time optimization
-        .arg("-c")                 // Compile only
-        .arg(&c_path)
-        .arg("-o")
-        .arg(temp_dir.join("z_program.o"))
-        .status()
-        .map_err(|e| CodegenError {
-            message: format!("Failed to execute {}: {}", compiler, e),
-        })?;
-    
-    if !status.success() {
-        return Err(CodegenError {
-            message: format!("{} compilation failed", compiler),
-        });
+    /// A call that debug builds record the location of in the caller's
+    /// frame, for stack traces
+    fn traced_call(&self, call: String, span: &Span) -> String {
+        if self.checks {
+            format!("(z_at({}), {})", self.location(span), call)
+        } else {
+            call
+        }
     }
-    
-    // Link the object file
-    let status = Command::new(compiler)
-        .arg("-O3")
-        .arg("-march=native")
-        .arg("-flto")
-        .arg(temp_dir.join("z_program.o"))
-        .arg("-o")
-        .arg(output_path)
-        .arg("-lm")               // Link math library
-        .status()
-        .map_err(|e| CodegenError {
-            message: format!("Failed to link: {}", e),
+
+    /// Writes the pieces of a `print` or `printf` call, each value through
+    /// the runtime's writer for its type. With more than one value, all of
+    /// them are evaluated before anything is written.
+    fn generate_print(&mut self, name: &str, args: &[Expr]) -> Result<String> {
+        let (pieces, values) = format::call_pieces(name, args).ok_or_else(|| CodegenError {
+            message: format!("Invalid call of {}", name),
+            span: None,
         })?;
-    
-    if !status.success() {
-        return Err(CodegenError {
+
+        let mut code = String::from("({ ");
+        let mut written = Vec::new();
+        for (i, value) in values.iter().enumerate() {
+            let ty = self.expr_type(value).ok_or_else(|| CodegenError {
+                message: "Cannot determine the type of the printed value".to_string(),
+                span: None,
+            })?;
+            let value_code = self.generate_borrowed(value)?;
+            if values.len() == 1 {
+                written.push((value_code, ty));
+            } else {
+                code.push_str(&format!("{} z_value{} = {}; ", c_type(&ty), i, value_code));
+                written.push((format!("z_value{}", i), ty));
+            }
+        }
+        let mut written = written.into_iter();
+        for piece in pieces {
+            let (value_code, ty) = match piece {
+                Piece::Text(text) => (format!("Z_STR({})", c_string_literal(&text)), Type::String),
+                Piece::Value => written.next().unwrap(),
+            };
+            let writer = match ty {
+                Type::String => "z_write_str",
+                Type::Float => "z_write_float",
+                Type::Bool => "z_write_bool",
+                ty if ty.is_unsigned() => "z_write_uint",
+                _ => "z_write_int",
+            };
+            code.push_str(&format!("{}({}); ", writer, value_code));
+        }
+        code.push_str("})");
+        Ok(code)
+    }
+
+    /// Emits the C definition of a user function ahead of main, after the C
+    /// `specifiers` of its attributes
+    fn generate_function(
+        &mut self,
+        name: &str,
+        params: &[(String, Type)],
+        return_type: &Type,
+        body: &Stmt,
+        specifiers: &str,
+    ) -> Result<()> {
+        let saved_locals = std::mem::replace(&mut self.locals, params.iter().cloned().collect());
+        let saved_narrowed = std::mem::take(&mut self.narrowed);
+        let saved_indent = std::mem::replace(&mut self.indent_level, 1);
+        let saved_return = std::mem::replace(&mut self.return_type, return_type.clone());
+        let saved_scopes = std::mem::replace(&mut self.scopes, vec![Vec::new()]);
+        let saved_temps = std::mem::take(&mut self.temps);
+        let body_code = self.generate_body(&self.display_name(name), params, body);
+        self.locals = saved_locals;
+        self.narrowed = saved_narrowed;
+        self.indent_level = saved_indent;
+        self.return_type = saved_return;
+        self.scopes = saved_scopes;
+        self.temps = saved_temps;
+
+        self.lifted.push(format!(
+            "{}{} {{\n{}}}\n",
+            specifiers,
+            self.function_signature(name, params, return_type),
+            body_code?
+        ));
+        Ok(())
+    }
+
+    /// Statements of a function body. Parameters are retained on entry, so
+    /// the body owns them like its other variables, and released with them.
+    /// Debug builds also enter a frame named `function`.
+    fn generate_body(&mut self, function: &str, params: &[(String, Type)], body: &Stmt) -> Result<String> {
+        let mut code = String::new();
+        if self.checks {
+            code.push_str(&format!("{}z_enter({});\n", self.indent(), c_string_literal(function)));
+        }
+        for (name, ty) in params {
+            if self.is_counted(ty) {
+                code.push_str(&format!("{}{};\n", self.indent(), self.retain(name, ty)));
+                self.declare_counted(name, ty);
+            }
+        }
+        let stmts = match body {
+            Stmt::Block(stmts, _) => stmts.as_slice(),
+            _ => std::slice::from_ref(body),
+        };
+        for stmt in stmts {
+            code.push_str(&self.generate_statement(stmt)?);
+        }
+        let release = self.release_scope();
+        if !matches!(stmts.last(), Some(Stmt::Return(..))) {
+            code.push_str(&release);
+            code.push_str(&self.leave());
+        }
+        Ok(code)
+    }
+
+    /// The closure for a named function, calling it through a wrapper that
+    /// ignores the environment argument
+    fn function_value(&mut self, name: &str) -> Result<String> {
+        let wrapper = format!("z_fn_{}", self.c_function_name(name));
+        if self.wrapped.insert(name.to_string()) {
+            let (params, ret) = self.functions[name].clone();
+            let mut decl_params = vec!["void* z_env".to_string()];
+            let mut call_args = Vec::new();
+            for (i, param) in params.iter().enumerate() {
+                decl_params.push(format!("{} a{}", c_type(param), i));
+                call_args.push(format!("a{}", i));
+            }
+            let call = format!("{}({})", self.c_function_name(name), call_args.join(", "));
+            let body = if ret == Type::Void { call } else { format!("return {}", call) };
+            self.lifted.push(format!(
+                "static {} {}({}) {{\n    {};\n}}\n",
+                c_type(&ret),
+                wrapper,
+                decl_params.join(", "),
+                body
+            ));
+        }
+        Ok(format!("((z_closure){{ (void (*)(void)){}, NULL, NULL }})", wrapper))
+    }
+
+    /// Lifts a lambda into a C function taking its captured variables in an
+    /// environment struct, returning the expression that creates the closure
+    fn generate_lambda(&mut self, params: &[(String, Option<Type>)], return_type: Type, body: &Expr) -> Result<String> {
+        let id = self.lambda_count;
+        self.lambda_count += 1;
+        let name = format!("z_lambda_{}", id);
+        let env_name = format!("z_env_{}", id);
+
+        let params: Vec<(String, Type)> = params
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.clone().unwrap_or(Type::Inferred)))
+            .collect();
+        // Captured by value: the enclosing variables the body refers to
+        let mut names = Vec::new();
+        expr_identifiers(body, &mut names);
+        let captures: Vec<(String, Type)> = names
+            .into_iter()
+            .filter(|name| !params.iter().any(|(param, _)| param == name))
+            .filter_map(|name| self.locals.get(&name).map(|ty| (name, ty.clone())))
+            .collect();
+
+        let saved_locals = std::mem::replace(&mut self.locals, captures.iter().chain(&params).cloned().collect());
+        let saved_narrowed = std::mem::take(&mut self.narrowed);
+        let saved_indent = std::mem::replace(&mut self.indent_level, 1);
+        let saved_return = std::mem::replace(&mut self.return_type, return_type.clone());
+        let saved_scopes = std::mem::replace(&mut self.scopes, vec![Vec::new()]);
+        let saved_temps = std::mem::take(&mut self.temps);
+        let body_code = self.generate_body("<lambda>", &params, &self.lambda_body(body));
+        self.locals = saved_locals;
+        self.narrowed = saved_narrowed;
+        self.indent_level = saved_indent;
+        self.return_type = saved_return;
+        self.scopes = saved_scopes;
+        self.temps = saved_temps;
+
+        let mut decl_params = vec!["void* z_env".to_string()];
+        decl_params.extend(params.iter().map(|(name, ty)| format!("{} {}", c_type(ty), name)));
+        let mut code = String::new();
+        if !captures.is_empty() {
+            code.push_str(&format!("struct {} {{\n", env_name));
+            for (name, ty) in &captures {
+                code.push_str(&format!("    {} {};\n", c_type(ty), name));
+            }
+            code.push_str("};\n\n");
+            // Releases the environment's references along with its block
+            code.push_str(&format!("static void z_drop_{}(z_rc* rc) {{\n", env_name));
+            code.push_str(&format!("    struct {}* z_captured = (struct {}*)(rc + 1);\n", env_name, env_name));
+            for (name, ty) in &captures {
+                if self.is_counted(ty) {
+                    code.push_str(&format!("    {};\n", self.released(&format!("z_captured->{}", name), ty)));
+                }
+            }
+            code.push_str("    (void)z_captured;\n");
+            code.push_str("}\n\n");
+        }
+        code.push_str(&format!("static {} {}({}) {{\n", c_type(&return_type), name, decl_params.join(", ")));
+        if !captures.is_empty() {
+            code.push_str(&format!("    struct {}* z_captured = z_env;\n", env_name));
+            for (name, ty) in &captures {
+                code.push_str(&format!("    {} {} = z_captured->{};\n", c_type(ty), name, name));
+            }
+        }
+        code.push_str(&body_code?);
+        code.push_str("}\n");
+        self.lifted.push(code);
+
+        if captures.is_empty() {
+            return Ok(format!("((z_closure){{ (void (*)(void)){}, NULL, NULL }})", name));
+        }
+        let mut creation = format!(
+            "({{ z_rc* z_block = z_rc_new(sizeof(struct {}), z_drop_{}); struct {}* z_captured = (struct {}*)(z_block + 1); ",
+            env_name, env_name, env_name, env_name
+        );
+        // The environment keeps its own reference to captured values
+        for (name, ty) in &captures {
+            creation.push_str(&format!("z_captured->{} = {}; ", name, self.retained(self.variable(name), ty)));
+        }
+        creation.push_str(&format!(
+            "((z_closure){{ (void (*)(void)){}, z_captured, z_block }}); }})",
+            name
+        ));
+        Ok(creation)
+    }
+
+    /// The body of a lifted lambda as statements, returning the value of its
+    /// body. A body ending in a call returning nothing, such as `panic`,
+    /// never comes to its end.
+    fn lambda_body(&self, body: &Expr) -> Stmt {
+        let returns_value = self.return_type != Type::Void;
+        let mut stmts = match body {
+            Expr::Block(stmts, _) => stmts.clone(),
+            _ => vec![Stmt::Expr(body.clone())],
+        };
+        if let (true, Some(Stmt::Expr(expr))) = (returns_value, stmts.last()) {
+            if self.expr_type(expr) == Some(Type::Void) {
+                return Stmt::Block(stmts, body.span().clone());
+            }
+            let span = expr.span().clone();
+            *stmts.last_mut().unwrap() = Stmt::Return(Some(expr.clone()), span);
+        }
+        Stmt::Block(stmts, body.span().clone())
+    }
+
+    /// A call of the method `method` of the string `object`, through the
+    /// runtime's function for it
+    fn generate_string_method(&mut self, object: &Expr, method: &str, args: &[Expr], span: &Span) -> Result<String> {
+        let mut args_code = vec![self.generate_borrowed(object)?];
+        for arg in args {
+            args_code.push(self.generate_borrowed(arg)?);
+        }
+        Ok(match method {
+            "len" => format!("({}).len", args_code[0]),
+            // Debug builds check the bounds, as they do for indexing
+            "substring" => {
+                let location = if self.checks { self.location(span) } else { "NULL".to_string() };
+                format!("z_str_substring({}, {})", args_code.join(", "), location)
+            }
+            // Text that is not a number panics, whatever the build
+            "to_int" | "to_float" => format!("z_str_{}({}, {})", method, args_code.join(", "), self.location(span)),
+            method => format!("z_str_{}({})", method, args_code.join(", ")),
+        })
+    }
+
+    /// A call of the method `method` of the array `object`, of elements of
+    /// type `element`. `push` and `pop` change the array where it is stored.
+    fn generate_array_method(
+        &mut self,
+        object: &Expr,
+        element: &Type,
+        method: &str,
+        args: &[Expr],
+        span: &Span,
+    ) -> Result<String> {
+        if method == "len" {
+            return Ok(format!("({}).len", self.generate_borrowed(object)?));
+        }
+        let c_element = c_type(element);
+        let array_code = self.generate_expression(object)?;
+        match (method, args) {
+            // The value is computed before the array can move
+            ("push", [value]) => {
+                let value_code = self.generate_stored(value, element)?;
+                Ok(format!(
+                    "({{ {} z_pushed = {}; *({}*)z_array_push(&({}), sizeof({}), {}, {}) = z_pushed; }})",
+                    c_element,
+                    value_code,
+                    c_element,
+                    array_code,
+                    c_element,
+                    self.element_drop(element),
+                    self.element_retain(element)
+                ))
+            }
+            ("pop", []) => {
+                let location = if self.checks { self.location(span) } else { "NULL".to_string() };
+                Ok(format!(
+                    "(*({}*)z_array_pop(&({}), sizeof({}), {}, {}))",
+                    c_element,
+                    array_code,
+                    c_element,
+                    self.element_retain(element),
+                    location
+                ))
+            }
+            _ => Err(CodegenError {
+                message: format!("Invalid call of the array method {}", method),
+                span: None,
+            }),
+        }
+    }
+
+    /// A `match`, as a switch on the tag of the matched value in a statement
+    /// expression. Each arm is a scope of its own, holding the payload it
+    /// binds, and stores its value in the result of the whole `match`, of
+    /// type `result_type` unless it has none.
+    fn generate_match(&mut self, scrutinee: &Expr, arms: &[MatchArm], result_type: Option<&Type>) -> Result<String> {
+        let (enum_name, variants) = match self.expr_type(scrutinee) {
+            Some(Type::Enum(name)) if self.enums.contains_key(&name) => (mangle::type_name(&name), self.enums[&name].clone()),
+            _ => {
+                return Err(CodegenError {
+                    message: "Cannot determine the type of the matched value".to_string(),
+                    span: Some(scrutinee.span().clone()),
+                });
+            }
+        };
+        let id = self.temp_count;
+        self.temp_count += 1;
+        // A new value holding references is a temporary, which releases its
+        // payload, so the names bound to a payload always retain it
+        let scrutinee_code = self.generate_borrowed(scrutinee)?;
+
+        // The statements of the arms have temporaries of their own
+        let saved_temps = std::mem::take(&mut self.temps);
+        self.indent_level += 1;
+        let mut code = format!("({{\n{}{} z_matched{} = {};\n", self.indent(), enum_name, id, scrutinee_code);
+        if let Some(ty) = result_type {
+            code.push_str(&format!("{}{} z_arm{} = {};\n", self.indent(), c_type(ty), id, zero_value(ty)));
+        }
+        code.push_str(&format!("{}switch (z_matched{}.tag) {{\n", self.indent(), id));
+        // Arms after one matching the same variants are never taken
+        let mut covered = HashSet::new();
+        for arm in arms {
+            let (label, bindings) = match &arm.pattern {
+                Pattern::Wildcard(_) => ("default".to_string(), Vec::new()),
+                Pattern::Variant(_, variant, names, _) => {
+                    if !covered.insert(variant.clone()) {
+                        continue;
+                    }
+                    let payload = variants
+                        .iter()
+                        .find(|(name, _)| name == variant)
+                        .map(|(_, payload)| payload.clone())
+                        .unwrap_or_default();
+                    let bindings = names
+                        .iter()
+                        .zip(payload)
+                        .enumerate()
+                        .filter(|(_, (name, _))| *name != "_")
+                        .map(|(i, (name, ty))| {
+                            let payload = format!("z_matched{}.payload.{}._{}", id, variant, i);
+                            let value = self.retained(payload, &ty);
+                            (name.clone(), ty, value)
+                        })
+                        .collect();
+                    (format!("case {}_{}", enum_name, variant), bindings)
+                }
+            };
+            code.push_str(&format!("{}{}: {{\n", self.indent(), label));
+            let result = format!("z_arm{}", id);
+            code.push_str(&self.generate_arm(&bindings, &arm.body, result_type.map(|ty| (result.as_str(), ty)))?);
+            code.push_str(&format!("{}    break;\n{}}}\n", self.indent(), self.indent()));
+            if matches!(arm.pattern, Pattern::Wildcard(_)) {
+                break;
+            }
+        }
+        code.push_str(&format!("{}}}\n", self.indent()));
+        if result_type.is_some() {
+            code.push_str(&format!("{}z_arm{};\n", self.indent(), id));
+        }
+        self.indent_level -= 1;
+        code.push_str(&format!("{}}})", self.indent()));
+        self.temps = saved_temps;
+        Ok(code)
+    }
+
+    /// The body of a `match` arm, one level deeper, declaring the names it
+    /// binds, each with its type and the counted reference it owns.
+    /// The value of the body is stored in `result`, if given, as its type.
+    fn generate_arm(
+        &mut self,
+        bindings: &[(String, Type, String)],
+        body: &Expr,
+        result: Option<(&str, &Type)>,
+    ) -> Result<String> {
+        let saved_locals = self.locals.clone();
+        let saved_narrowed = self.narrowed.clone();
+        self.indent_level += 1;
+        self.scopes.push(Vec::new());
+        let mut code = String::new();
+        for (name, ty, payload) in bindings {
+            self.locals.insert(name.clone(), ty.clone());
+            self.narrowed.remove(name);
+            self.declare_counted(name, ty);
+            code.push_str(&format!("{}{} {} = {};\n", self.indent(), c_type(ty), name, payload));
+        }
+        let body = match body {
+            Expr::Block(stmts, _) => stmts.clone(),
+            body => vec![Stmt::Expr(body.clone())],
+        };
+        for (i, stmt) in body.iter().enumerate() {
+            match (stmt, result) {
+                (Stmt::Expr(value), Some((result, ty))) if i + 1 == body.len() => {
+                    let value_code = self.generate_stored(value, ty)?;
+                    code.push_str(&self.with_temporaries(format!("{}{} = {};\n", self.indent(), result, value_code)));
+                }
+                _ => code.push_str(&self.generate_statement(stmt)?),
+            }
+        }
+        let release = self.release_scope();
+        if !matches!(body.last(), Some(Stmt::Return(..))) {
+            code.push_str(&release);
+        }
+        self.indent_level -= 1;
+        self.locals = saved_locals;
+        self.narrowed = saved_narrowed;
+        Ok(code)
+    }
+
+    /// An `if` with a value, of type `result_type` unless it has none, as a
+    /// statement expression whose branches store it
+    fn generate_if_value(
+        &mut self,
+        cond: &Expr,
+        then_branch: &Expr,
+        else_branch: Option<&Expr>,
+        result_type: Option<&Type>,
+    ) -> Result<String> {
+        self.generate_value(result_type, |generator, result| {
+            let (mut code, cond_code) = generator.generate_condition(cond)?;
+            let (then_narrowed, else_narrowed) = match generator.null_guard(cond) {
+                Some((name, true)) => (Some(name), None),
+                Some((name, false)) => (None, Some(name)),
+                None => (None, None),
+            };
+            code.push_str(&format!("{}if ({}) {{\n", generator.indent(), cond_code));
+            code.push_str(&generator.with_narrowed(then_narrowed, |generator| generator.generate_arm(&[], then_branch, result))?);
+            code.push_str(&format!("{}}}", generator.indent()));
+            if let Some(else_branch) = else_branch {
+                code.push_str(" else {\n");
+                code.push_str(
+                    &generator.with_narrowed(else_narrowed, |generator| generator.generate_arm(&[], else_branch, result))?,
+                );
+                code.push_str(&format!("{}}}", generator.indent()));
+            }
+            code.push('\n');
+            Ok(code)
+        })
+    }
+
+    /// A block with a value, of type `result_type` unless it has none, as a
+    /// statement expression
+    fn generate_block_value(&mut self, block: &Expr, result_type: Option<&Type>) -> Result<String> {
+        self.generate_value(result_type, |generator, result| {
+            let body = generator.generate_arm(&[], block, result)?;
+            Ok(format!("{}{{\n{}{}}}\n", generator.indent(), body, generator.indent()))
+        })
+    }
+
+    /// A statement expression made of the statements `generate` gives, with
+    /// the temporaries of their own, which store its value of type
+    /// `result_type`, if it has one, in the result they are given
+    fn generate_value(
+        &mut self,
+        result_type: Option<&Type>,
+        generate: impl FnOnce(&mut Self, Option<(&str, &Type)>) -> Result<String>,
+    ) -> Result<String> {
+        let result = format!("z_value{}", self.temp_count);
+        self.temp_count += 1;
+        let saved_temps = std::mem::take(&mut self.temps);
+        self.indent_level += 1;
+        let mut code = String::from("({\n");
+        if let Some(ty) = result_type {
+            code.push_str(&format!("{}{} {} = {};\n", self.indent(), c_type(ty), result, zero_value(ty)));
+        }
+        code.push_str(&generate(self, result_type.map(|ty| (result.as_str(), ty)))?);
+        if result_type.is_some() {
+            code.push_str(&format!("{}{};\n", self.indent(), result));
+        }
+        self.indent_level -= 1;
+        code.push_str(&format!("{}}})", self.indent()));
+        self.temps = saved_temps;
+        Ok(code)
+    }
+
+    /// `value?`: the payload of `Some` or `Ok`, or else a return of `None`
+    /// or of the `Err` as `return_type`, releasing what a `return` would
+    fn generate_try(&mut self, value: &Expr, return_type: Option<&Type>) -> Result<String> {
+        let (enum_name, variants, return_name) = match (self.expr_type(value), return_type) {
+            (Some(Type::Enum(name)), Some(Type::Enum(return_name))) if self.enums.contains_key(&name) => {
+                (mangle::type_name(&name), self.enums[&name].clone(), mangle::type_name(return_name))
+            }
+            _ => {
+                return Err(CodegenError {
+                    message: "Cannot determine the type of the value of ?".to_string(),
+                    span: Some(value.span().clone()),
+                });
+            }
+        };
+        let (success, failure) = match variants.as_slice() {
+            [success, failure] => (success, failure),
+            _ => {
+                return Err(CodegenError {
+                    message: "? applies only to Option and Result".to_string(),
+                    span: None,
+                });
+            }
+        };
+        let id = self.temp_count;
+        self.temp_count += 1;
+        let value_code = self.generate_expression(value)?;
+        // A value that a variable still holds keeps its payload, so what
+        // is taken out of it is retained
+        let held = matches!(value, Expr::Identifier(..) | Expr::Index(..) | Expr::Field(..));
+        let take = |generator: &Self, payload: String, ty: &Type| {
+            if held {
+                generator.retained(payload, ty)
+            } else {
+                payload
+            }
+        };
+
+        self.indent_level += 1;
+        let mut code = format!("({{\n{}{} z_tried{} = {};\n", self.indent(), enum_name, id, value_code);
+        code.push_str(&format!("{}if (z_tried{}.tag == {}_{}) {{\n", self.indent(), id, enum_name, failure.0));
+        self.indent_level += 1;
+        let error = match failure.1.first() {
+            Some(ty) => take(self, format!("z_tried{}.payload.{}._0", id, failure.0), ty),
+            None => String::new(),
+        };
+        code.push_str(&format!(
+            "{}{} z_failed{} = {}_{}_new({});\n",
+            self.indent(),
+            return_name,
+            id,
+            return_name,
+            failure.0,
+            error
+        ));
+        for (name, ty) in self.temps.iter().rev() {
+            code.push_str(&format!("{}{};\n", self.indent(), self.released(name, ty)));
+        }
+        code.push_str(&self.release_all());
+        code.push_str(&self.leave());
+        code.push_str(&format!("{}return z_failed{};\n", self.indent(), id));
+        self.indent_level -= 1;
+        code.push_str(&format!("{}}}\n", self.indent()));
+        let payload = format!("z_tried{}.payload.{}._0", id, success.0);
+        let payload = match success.1.first() {
+            Some(ty) => take(self, payload, ty),
+            None => payload,
+        };
+        code.push_str(&format!("{}{};\n", self.indent(), payload));
+        self.indent_level -= 1;
+        code.push_str(&format!("{}}})", self.indent()));
+        Ok(code)
+    }
+
+    /// Whether `name` calls a function of `std.math`, which the typechecker
+    /// only lets through when the program imports it
+    fn is_math(&self, name: &str) -> bool {
+        stdlib::MATH_FUNCTIONS.contains(&name) && !self.locals.contains_key(name) && !self.functions.contains_key(name)
+    }
+
+    /// A call of the `std.math` function `name` at `span`. Floats go to
+    /// libm; ints are compared in place, each argument evaluated once. The
+    /// absolute value of the smallest int overflows, as negating it does.
+    fn generate_math(&self, name: &str, args: &[Expr], args_code: &[String], span: &Span) -> String {
+        let floats = args.iter().any(|arg| self.expr_type(arg) == Some(Type::Float));
+        match (name, args_code) {
+            ("abs", [x]) if floats => format!("fabs({})", x),
+            ("abs", [x]) if self.checks => format!(
+                "({{ __typeof__({0}) z_x = ({0}); if (z_x < 0 && z_sub_overflow(0, z_x, &z_x)) z_panic(\"integer overflow\", {1}); z_x; }})",
+                x,
+                self.location(span)
+            ),
+            ("abs", [x]) => format!("({{ __typeof__({0}) z_x = ({0}); z_x < 0 ? -z_x : z_x; }})", x),
+            ("min" | "max", [a, b]) if floats => format!("f{}({}, {})", name, a, b),
+            ("min" | "max", [a, b]) => format!(
+                "({{ __typeof__({0}) z_a = ({0}); __typeof__({1}) z_b = ({1}); z_a {2} z_b ? z_a : z_b; }})",
+                a,
+                b,
+                if name == "min" { "<" } else { ">" }
+            ),
+            _ => format!("{}({})", name, args_code.join(", ")),
+        }
+    }
+
+    /// Whether `name` is `args` or `env`, rather than a function the program
+    /// declares
+    fn is_process(&self, name: &str) -> bool {
+        matches!(name, "args" | "env") && !self.locals.contains_key(name) && !self.functions.contains_key(name)
+    }
+
+    /// Type of a checked expression, where it can be worked out from the
+    /// variables in scope
+    fn expr_type(&self, expr: &Expr) -> Option<Type> {
+        self.type_with(expr, &self.locals)
+    }
+
+    /// Type of a checked expression, where it can be worked out from the
+    /// variables `locals`
+    fn type_with(&self, expr: &Expr, locals: &HashMap<String, Type>) -> Option<Type> {
+        match expr {
+            Expr::Literal(lit, _) => match lit {
+                Literal::Int(_) => Some(Type::Int),
+                Literal::Float(_) => Some(Type::Float),
+                Literal::Bool(_) => Some(Type::Bool),
+                Literal::String(_) => Some(Type::String),
+                Literal::Null => None,
+            },
+            Expr::Binary(left, op, right, _) => match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                    let left_type = self.type_with(left, locals);
+                    if left_type == Some(Type::String) || self.type_with(right, locals) == Some(Type::String) {
+                        Some(Type::String)
+                    } else {
+                        left_type
+                    }
+                }
+                _ => Some(Type::Bool),
+            },
+            Expr::Unary(UnaryOp::Not, _, _) => Some(Type::Bool),
+            Expr::Unary(UnaryOp::Neg, operand, _) => self.type_with(operand, locals),
+            Expr::Cast(_, ty, _) => Some(ty.clone()),
+            Expr::Index(array, _, _) => match self.type_with(array, locals)? {
+                Type::Array(element_type) => Some(*element_type),
+                _ => None,
+            },
+            Expr::If(_, then_branch, _, _) => self.type_with(then_branch, locals),
+            Expr::Field(object, field, _) => match self.type_with(object, locals)? {
+                Type::Struct(name) => {
+                    let (_, ty) = self.structs.get(&name)?.iter().find(|(name, _)| name == field)?;
+                    Some(represented(ty))
+                }
+                _ => None,
+            },
+            Expr::Identifier(name, _) => match locals.get(name) {
+                Some(ty) => Some(represented(ty)),
+                None => self
+                    .functions
+                    .get(name)
+                    .map(|(params, ret)| Type::Function(params.clone(), Box::new(ret.clone()))),
+            },
+            Expr::Array(elements, _) => Some(Type::Array(Box::new(self.type_with(elements.first()?, locals)?))),
+            Expr::Variant(enum_name, _, _, _) => Some(Type::Enum(enum_name.clone())),
+            Expr::Call(callee, _, _) if matches!(callee.as_ref(), Expr::Identifier(name, _) if name == "len") => {
+                Some(Type::Int)
+            }
+            Expr::Call(callee, _, _)
+                if matches!(callee.as_ref(), Expr::Identifier(name, _)
+                    if matches!(name.as_str(), "panic" | "assert")
+                        && !locals.contains_key(name)
+                        && !self.functions.contains_key(name)) =>
+            {
+                Some(Type::Void)
+            }
+            Expr::Call(callee, _, _) if matches!(callee.as_ref(), Expr::Identifier(name, _) if self.is_process(name)) => {
+                match callee.as_ref() {
+                    Expr::Identifier(name, _) if name == "args" => Some(Type::Array(Box::new(Type::String))),
+                    _ => Some(Type::String),
+                }
+            }
+            Expr::Call(callee, args, _) if matches!(callee.as_ref(), Expr::Identifier(name, _) if self.is_math(name)) => {
+                match callee.as_ref() {
+                    Expr::Identifier(name, _) if matches!(name.as_str(), "abs" | "min" | "max") => {
+                        args.iter().find_map(|arg| self.type_with(arg, locals))
+                    }
+                    _ => Some(Type::Float),
+                }
+            }
+            Expr::Call(callee, _, _) if matches!(callee.as_ref(), Expr::Field(..)) => match callee.as_ref() {
+                Expr::Field(object, method, _) => match self.type_with(object, locals)? {
+                    Type::String => string_method(method).map(|(_, ret)| ret),
+                    Type::Array(element) => array_method(method, &element).map(|(_, ret)| ret),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Expr::Call(callee, _, _) => match self.type_with(callee, locals)? {
+                Type::Function(_, ret) => Some(represented(&ret)),
+                _ => None,
+            },
+            Expr::Lambda(params, ret, _, _) => {
+                let params = params.iter().map(|(_, ty)| ty.clone().unwrap_or(Type::Inferred)).collect();
+                Some(Type::Function(params, Box::new(ret.clone().unwrap_or(Type::Void))))
+            }
+            // The value of the last expression, with the variables declared
+            // before it
+            Expr::Block(stmts, _) => {
+                let mut locals = locals.clone();
+                for stmt in stmts {
+                    match stmt {
+                        Stmt::Let(name, Some(ty), _, _) => {
+                            locals.insert(name.clone(), ty.clone());
+                        }
+                        Stmt::Expr(value) if std::ptr::eq(stmt, stmts.last()?) => return self.type_with(value, &locals),
+                        _ => {}
+                    }
+                }
+                None
+            }
+            // The type of the first arm whose type is known, with the payload
+            // it binds
+            Expr::Match(scrutinee, arms, _) => {
+                let variants = match self.type_with(scrutinee, locals)? {
+                    Type::Enum(name) => self.enums.get(&name)?,
+                    _ => return None,
+                };
+                arms.iter().find_map(|arm| {
+                    let mut locals = locals.clone();
+                    if let Pattern::Variant(_, variant, names, _) = &arm.pattern {
+                        let (_, payload) = variants.iter().find(|(name, _)| name == variant)?;
+                        locals.extend(names.iter().cloned().zip(payload.iter().cloned()));
+                    }
+                    self.type_with(&arm.body, &locals)
+                })
+            }
+            Expr::Inlined(_, body, _) => self.type_with(body, locals),
+            // The payload of `Some` or `Ok`, the first variant
+            Expr::Try(value, _, _) => match self.type_with(value, locals)? {
+                Type::Enum(name) => self.enums.get(&name)?.first()?.1.first().cloned(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The includes and runtime the C of every program starts with, managing
+/// memory with `gc`. An `imported` module shares the call frames of the
+/// program importing it.
+fn generate_runtime(gc: Gc, imported: bool) -> String {
+    let mut c_code = String::new();
+    // Add standard includes
+    c_code.push_str("#include <stdio.h>\n");
+    c_code.push_str("#include <stdlib.h>\n");
+    c_code.push_str("#include <stdbool.h>\n");
+    c_code.push_str("#include <stdint.h>\n");
+    c_code.push_str("#include <string.h>\n");
+    c_code.push_str("#include <math.h>\n");
+    // DLLs export only the functions marked for it
+    c_code.push_str("#ifdef _WIN32\n");
+    c_code.push_str("#define Z_EXPORT __declspec(dllexport)\n");
+    c_code.push_str("#else\n");
+    c_code.push_str("#define Z_EXPORT\n");
+    c_code.push_str("#endif\n");
+    // Functions with an optimization level of their own. Clang can only
+    // leave a function unoptimized.
+    c_code.push_str("#if defined(__clang__)\n");
+    c_code.push_str("#define Z_OPTIMIZE(level) Z_OPTIMIZE_##level\n");
+    c_code.push_str("#define Z_OPTIMIZE_0 __attribute__((optnone))\n");
+    c_code.push_str("#define Z_OPTIMIZE_1\n");
+    c_code.push_str("#define Z_OPTIMIZE_2\n");
+    c_code.push_str("#define Z_OPTIMIZE_3\n");
+    c_code.push_str("#elif defined(__GNUC__) && !defined(__TINYC__)\n");
+    c_code.push_str("#define Z_OPTIMIZE(level) __attribute__((optimize(level)))\n");
+    c_code.push_str("#else\n");
+    c_code.push_str("#define Z_OPTIMIZE(level)\n");
+    c_code.push_str("#endif\n");
+    match gc {
+        Gc::RefCount => c_code.push_str("#define Z_ALLOC malloc\n\n"),
+        Gc::Boehm => {
+            c_code.push_str("#include <gc.h>\n");
+            c_code.push_str("#define Z_ALLOC GC_MALLOC\n\n");
+        }
+    }
+    
+    // Add Z runtime functions
+    c_code.push_str("// Z language runtime functions\n");
+    
+    // The calls in progress, which debug builds record for the stack
+    // traces of panics. Each frame holds the location of the call it is
+    // making. Calls nested deeper than Z_MAX_FRAMES are counted only.
+    c_code.push_str("#define Z_MAX_FRAMES 256\n\n");
+    c_code.push_str("typedef struct {\n");
+    c_code.push_str("    const char* function;\n");
+    c_code.push_str("    const char* location;\n");
+    c_code.push_str("} z_frame;\n\n");
+    // Imported modules share the calls of the program or library
+    // importing them
+    if imported {
+        c_code.push_str("extern z_frame z_frames[Z_MAX_FRAMES];\n");
+        c_code.push_str("extern int z_depth;\n\n");
+    } else {
+        c_code.push_str("z_frame z_frames[Z_MAX_FRAMES];\n");
+        c_code.push_str("int z_depth = 0;\n\n");
+    }
+
+    c_code.push_str("static void z_enter(const char* function) {\n");
+    c_code.push_str("    if (z_depth < Z_MAX_FRAMES) {\n");
+    c_code.push_str("        z_frames[z_depth].function = function;\n");
+    c_code.push_str("        z_frames[z_depth].location = \"?\";\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    z_depth++;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_leave(void) {\n");
+    c_code.push_str("    z_depth--;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_at(const char* location) {\n");
+    c_code.push_str("    if (z_depth > 0 && z_depth <= Z_MAX_FRAMES) z_frames[z_depth - 1].location = location;\n");
+    c_code.push_str("}\n\n");
+
+    // Runtime errors print their message and the calls that led to it,
+    // innermost first, and end the program
+    c_code.push_str("static void z_panic(const char* message, const char* location) {\n");
+    c_code.push_str("    fflush(stdout);\n");
+    c_code.push_str("    fprintf(stderr, \"panic: %s at %s\\n\", message, location);\n");
+    c_code.push_str("    if (z_depth > 0) fprintf(stderr, \"stack backtrace:\\n\");\n");
+    c_code.push_str("    if (z_depth > Z_MAX_FRAMES) fprintf(stderr, \"    (%d innermost calls not recorded)\\n\", z_depth - Z_MAX_FRAMES);\n");
+    c_code.push_str("    z_at(location);\n");
+    c_code.push_str("    for (int i = (z_depth < Z_MAX_FRAMES ? z_depth : Z_MAX_FRAMES) - 1; i >= 0; i--) {\n");
+    c_code.push_str("        fprintf(stderr, \"    %s at %s\\n\", z_frames[i].function, z_frames[i].location);\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    exit(1);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_assert_failed(const char* message, const char* location) {\n");
+    c_code.push_str("    if (message == NULL) z_panic(\"assertion failed\", location);\n");
+    c_code.push_str("    char* text = malloc(strlen(message) + sizeof(\"assertion failed: \"));\n");
+    c_code.push_str("    sprintf(text, \"assertion failed: %s\", message);\n");
+    c_code.push_str("    z_panic(text, location);\n");
+    c_code.push_str("}\n\n");
+
+    // Overflow checks, which TinyCC has no builtins for; its builds
+    // leave overflow unchecked
+    c_code.push_str("#ifdef __TINYC__\n");
+    c_code.push_str("#define z_add_overflow(a, b, result) (*(result) = (a) + (b), 0)\n");
+    c_code.push_str("#define z_sub_overflow(a, b, result) (*(result) = (a) - (b), 0)\n");
+    c_code.push_str("#define z_mul_overflow(a, b, result) (*(result) = (a) * (b), 0)\n");
+    c_code.push_str("#else\n");
+    c_code.push_str("#define z_add_overflow __builtin_add_overflow\n");
+    c_code.push_str("#define z_sub_overflow __builtin_sub_overflow\n");
+    c_code.push_str("#define z_mul_overflow __builtin_mul_overflow\n");
+    c_code.push_str("#endif\n\n");
+
+    // Strings, arrays and closure environments live in reference-counted
+    // blocks, starting with this header. The block is freed with its last
+    // reference, after `drop` releases the values stored in it. Values in static memory,
+    // such as string literals, have no block.
+    c_code.push_str("typedef struct z_rc {\n");
+    c_code.push_str("    int64_t refs;\n");
+    c_code.push_str("    void (*drop)(struct z_rc*);\n");
+    c_code.push_str("} z_rc;\n\n");
+
+    c_code.push_str("static z_rc* z_rc_new(size_t size, void (*drop)(z_rc*)) {\n");
+    c_code.push_str("    z_rc* rc = Z_ALLOC(sizeof(z_rc) + size);\n");
+    c_code.push_str("    rc->refs = 1;\n");
+    c_code.push_str("    rc->drop = drop;\n");
+    c_code.push_str("    return rc;\n");
+    c_code.push_str("}\n\n");
+
+    match gc {
+        Gc::RefCount => {
+            c_code.push_str("static void z_retain(z_rc* rc) {\n");
+            c_code.push_str("    if (rc != NULL) rc->refs++;\n");
+            c_code.push_str("}\n\n");
+
+            c_code.push_str("static void z_release(z_rc* rc) {\n");
+            c_code.push_str("    if (rc == NULL || --rc->refs > 0) return;\n");
+            c_code.push_str("    if (rc->drop != NULL) rc->drop(rc);\n");
+            c_code.push_str("    free(rc);\n");
+            c_code.push_str("}\n\n");
+        }
+        // The collector frees blocks once nothing points into them, so
+        // references need no counting
+        Gc::Boehm => {
+            c_code.push_str("static void z_retain(z_rc* rc) {\n");
+            c_code.push_str("    (void)rc;\n");
+            c_code.push_str("}\n\n");
+
+            c_code.push_str("static void z_release(z_rc* rc) {\n");
+            c_code.push_str("    (void)rc;\n");
+            c_code.push_str("}\n\n");
+        }
+    }
+
+    // Strings carry their length. Their data is also null-terminated, but
+    // may contain null bytes, so the runtime never relies on that.
+    c_code.push_str("typedef struct {\n");
+    c_code.push_str("    int64_t len;\n");
+    c_code.push_str("    const char* data;\n");
+    c_code.push_str("    z_rc* rc;\n");
+    c_code.push_str("} z_string;\n\n");
+    c_code.push_str("#define Z_STR(literal) ((z_string){ sizeof(literal) - 1, literal, NULL })\n\n");
+
+    c_code.push_str("static z_string z_str_retain(z_string text) {\n");
+    c_code.push_str("    z_retain(text.rc);\n");
+    c_code.push_str("    return text;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static z_string z_str_new(const char* data, int64_t len) {\n");
+    c_code.push_str("    z_rc* rc = z_rc_new(len + 1, NULL);\n");
+    c_code.push_str("    char* result = (char*)(rc + 1);\n");
+    c_code.push_str("    memcpy(result, data, len);\n");
+    c_code.push_str("    result[len] = '\\0';\n");
+    c_code.push_str("    return (z_string){ len, result, rc };\n");
+    c_code.push_str("}\n\n");
+
+    // Strings returned by C functions are copied; NULL stays null
+    c_code.push_str("static z_string z_str_from_c(const char* data) {\n");
+    c_code.push_str("    if (data == NULL) return (z_string){ 0, NULL, NULL };\n");
+    c_code.push_str("    return z_str_new(data, strlen(data));\n");
+    c_code.push_str("}\n\n");
+
+    // Output of `print` and `printf`, a writer for each printable type
+    c_code.push_str("static void z_write_str(z_string text) {\n");
+    c_code.push_str("    fwrite(text.data, 1, text.len, stdout);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_write_int(long long value) {\n");
+    c_code.push_str("    printf(\"%lld\", value);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_write_uint(unsigned long long value) {\n");
+    c_code.push_str("    printf(\"%llu\", value);\n");
+    c_code.push_str("}\n\n");
+
+    // Floats are written with the fewest digits that read back as the same
+    // value, so that different values never print the same
+    c_code.push_str("static int z_float_text(char* buffer, size_t size, double value) {\n");
+    c_code.push_str("    int len = 0;\n");
+    c_code.push_str("    for (int precision = 1; precision <= 17; precision++) {\n");
+    c_code.push_str("        len = snprintf(buffer, size, \"%.*g\", precision, value);\n");
+    c_code.push_str("        if (strtod(buffer, NULL) == value) break;\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    return len;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_write_float(double value) {\n");
+    c_code.push_str("    char buffer[32];\n");
+    c_code.push_str("    fwrite(buffer, 1, z_float_text(buffer, sizeof(buffer), value), stdout);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_write_bool(bool value) {\n");
+    c_code.push_str("    fputs(value ? \"true\" : \"false\", stdout);\n");
+    c_code.push_str("}\n\n");
+    
+    // String concatenation
+    c_code.push_str("static z_string z_str_concat(z_string left, z_string right) {\n");
+    c_code.push_str("    z_rc* rc = z_rc_new(left.len + right.len + 1, NULL);\n");
+    c_code.push_str("    char* result = (char*)(rc + 1);\n");
+    c_code.push_str("    memcpy(result, left.data, left.len);\n");
+    c_code.push_str("    memcpy(result + left.len, right.data, right.len);\n");
+    c_code.push_str("    result[left.len + right.len] = '\\0';\n");
+    c_code.push_str("    return (z_string){ left.len + right.len, result, rc };\n");
+    c_code.push_str("}\n\n");
+
+    // Comparison by content: equality, and byte-wise ordering
+    c_code.push_str("static bool z_str_eq(z_string left, z_string right) {\n");
+    c_code.push_str("    return left.len == right.len && memcmp(left.data, right.data, left.len) == 0;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static int z_str_cmp(z_string left, z_string right) {\n");
+    c_code.push_str("    int64_t len = left.len < right.len ? left.len : right.len;\n");
+    c_code.push_str("    int result = memcmp(left.data, right.data, len);\n");
+    c_code.push_str("    if (result != 0) return result;\n");
+    c_code.push_str("    return (left.len > right.len) - (left.len < right.len);\n");
+    c_code.push_str("}\n\n");
+
+    // Text of numbers concatenated with strings
+    c_code.push_str("static z_string z_int_to_str(long long value) {\n");
+    c_code.push_str("    char buffer[32];\n");
+    c_code.push_str("    int len = snprintf(buffer, sizeof(buffer), \"%lld\", value);\n");
+    c_code.push_str("    return z_str_new(buffer, len);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static z_string z_float_to_str(double value) {\n");
+    c_code.push_str("    char buffer[32];\n");
+    c_code.push_str("    return z_str_new(buffer, z_float_text(buffer, sizeof(buffer), value));\n");
+    c_code.push_str("}\n\n");
+
+    // Numbers parsed from strings; text that is not entirely a number
+    // panics at `location`
+    c_code.push_str("static void z_str_unconverted(z_string text, const char* type, const char* location) {\n");
+    c_code.push_str("    char* message = malloc(text.len + sizeof(\"cannot convert \\\"\\\" to float\"));\n");
+    c_code.push_str("    sprintf(message, \"cannot convert \\\"%.*s\\\" to %s\", (int)text.len, text.data, type);\n");
+    c_code.push_str("    z_panic(message, location);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static long long z_str_to_int(z_string text, const char* location) {\n");
+    c_code.push_str("    z_string copy = z_str_new(text.data, text.len);\n");
+    c_code.push_str("    char* end;\n");
+    c_code.push_str("    long long value = strtoll(copy.data, &end, 10);\n");
+    c_code.push_str("    if (text.len == 0 || *end != '\\0') z_str_unconverted(text, \"int\", location);\n");
+    c_code.push_str("    z_release(copy.rc);\n");
+    c_code.push_str("    return value;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static double z_str_to_float(z_string text, const char* location) {\n");
+    c_code.push_str("    z_string copy = z_str_new(text.data, text.len);\n");
+    c_code.push_str("    char* end;\n");
+    c_code.push_str("    double value = strtod(copy.data, &end);\n");
+    c_code.push_str("    if (text.len == 0 || *end != '\\0') z_str_unconverted(text, \"float\", location);\n");
+    c_code.push_str("    z_release(copy.rc);\n");
+    c_code.push_str("    return value;\n");
+    c_code.push_str("}\n\n");
+
+    // Arrays carry their length; elements are stored in a block after
+    // their count, which `drop` needs to release them, and the number of
+    // elements the block has room for
+    c_code.push_str("typedef struct {\n");
+    c_code.push_str("    int64_t len;\n");
+    c_code.push_str("    void* data;\n");
+    c_code.push_str("    z_rc* rc;\n");
+    c_code.push_str("} z_array;\n\n");
+
+    c_code.push_str("static z_array z_array_retain(z_array array) {\n");
+    c_code.push_str("    z_retain(array.rc);\n");
+    c_code.push_str("    return array;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static z_array z_array_new(int64_t len, size_t element_size, const void* elements, void (*drop)(z_rc*)) {\n");
+    c_code.push_str("    z_rc* rc = z_rc_new(2 * sizeof(int64_t) + len * element_size, drop);\n");
+    c_code.push_str("    int64_t* header = (int64_t*)(rc + 1);\n");
+    c_code.push_str("    header[0] = header[1] = len;\n");
+    c_code.push_str("    z_array array = { len, header + 2, rc };\n");
+    c_code.push_str("    memcpy(array.data, elements, len * element_size);\n");
+    c_code.push_str("    return array;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_drop_strings(z_rc* rc) {\n");
+    c_code.push_str("    int64_t len = *(int64_t*)(rc + 1);\n");
+    c_code.push_str("    z_string* elements = (z_string*)((int64_t*)(rc + 1) + 2);\n");
+    c_code.push_str("    for (int64_t i = 0; i < len; i++) z_release(elements[i].rc);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_drop_arrays(z_rc* rc) {\n");
+    c_code.push_str("    int64_t len = *(int64_t*)(rc + 1);\n");
+    c_code.push_str("    z_array* elements = (z_array*)((int64_t*)(rc + 1) + 2);\n");
+    c_code.push_str("    for (int64_t i = 0; i < len; i++) z_release(elements[i].rc);\n");
+    c_code.push_str("}\n\n");
+
+    // Retains the string, array or closure `element` points to. All of them
+    // keep their block third, so they are retained alike.
+    c_code.push_str("static void z_retain_element(void* element) {\n");
+    c_code.push_str("    z_retain(((z_array*)element)->rc);\n");
+    c_code.push_str("}\n\n");
+
+    // Room for one more element at the end of `array`. The block grows by
+    // doubling, and the array gets a block of its own first unless no other
+    // reference can see the change, retaining the elements it copies.
+    c_code.push_str("static void* z_array_push(z_array* array, size_t element_size, void (*drop)(z_rc*), void (*retain)(void*)) {\n");
+    c_code.push_str("    int64_t* header = array->rc == NULL ? NULL : (int64_t*)(array->rc + 1);\n");
+    c_code.push_str("    bool in_place = header != NULL && array->rc->refs == 1 && header[0] == array->len && array->len < header[1];\n");
+    c_code.push_str("    if (!in_place) {\n");
+    c_code.push_str("        int64_t capacity = array->len < 4 ? 8 : array->len * 2;\n");
+    c_code.push_str("        z_rc* rc = z_rc_new(2 * sizeof(int64_t) + capacity * element_size, drop);\n");
+    c_code.push_str("        header = (int64_t*)(rc + 1);\n");
+    c_code.push_str("        header[1] = capacity;\n");
+    // An empty array may have no data, which memcpy must not be given
+    c_code.push_str("        if (array->len > 0) memcpy(header + 2, array->data, array->len * element_size);\n");
+    c_code.push_str("        if (retain != NULL) {\n");
+    c_code.push_str("            for (int64_t i = 0; i < array->len; i++) retain((char*)(header + 2) + i * element_size);\n");
+    c_code.push_str("        }\n");
+    c_code.push_str("        z_release(array->rc);\n");
+    c_code.push_str("        array->data = header + 2;\n");
+    c_code.push_str("        array->rc = rc;\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    header[0] = ++array->len;\n");
+    c_code.push_str("    return (char*)array->data + (array->len - 1) * element_size;\n");
+    c_code.push_str("}\n\n");
+
+    // The last element of `array`, taken out of it. Other references to
+    // the block keep the element, which is then retained for the caller.
+    // A `location` of NULL leaves an empty array unchecked.
+    c_code.push_str("static void* z_array_pop(z_array* array, size_t element_size, void (*retain)(void*), const char* location) {\n");
+    c_code.push_str("    if (location != NULL && array->len == 0) z_panic(\"pop from an empty array\", location);\n");
+    c_code.push_str("    void* element = (char*)array->data + --array->len * element_size;\n");
+    c_code.push_str("    if (array->rc == NULL) return element;\n");
+    c_code.push_str("    int64_t* header = (int64_t*)(array->rc + 1);\n");
+    c_code.push_str("    if (array->rc->refs == 1 && header[0] == array->len + 1) {\n");
+    c_code.push_str("        header[0] = array->len;\n");
+    c_code.push_str("    } else if (retain != NULL) {\n");
+    c_code.push_str("        retain(element);\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    return element;\n");
+    c_code.push_str("}\n\n");
+
+    // Methods of strings, which work on bytes. A `location` of NULL leaves
+    // the bounds of a substring unchecked.
+    c_code.push_str("static z_string z_str_substring(z_string text, int64_t start, int64_t end, const char* location) {\n");
+    c_code.push_str("    if (location != NULL && (start < 0 || end < start || end > text.len)) {\n");
+    c_code.push_str("        z_panic(\"substring out of bounds\", location);\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    return z_str_new(text.data + start, end - start);\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static int64_t z_str_find(z_string text, z_string part, int64_t from) {\n");
+    c_code.push_str("    for (int64_t i = from; i + part.len <= text.len; i++) {\n");
+    c_code.push_str("        if (memcmp(text.data + i, part.data, part.len) == 0) return i;\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    return -1;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static bool z_str_contains(z_string text, z_string part) {\n");
+    c_code.push_str("    return z_str_find(text, part, 0) >= 0;\n");
+    c_code.push_str("}\n\n");
+
+    // An empty separator leaves the text whole
+    c_code.push_str("static z_array z_str_split(z_string text, z_string separator) {\n");
+    c_code.push_str("    int64_t count = 1;\n");
+    c_code.push_str("    for (int64_t at = 0; separator.len > 0 && (at = z_str_find(text, separator, at)) >= 0; at += separator.len) count++;\n");
+    c_code.push_str("    z_rc* rc = z_rc_new(2 * sizeof(int64_t) + count * sizeof(z_string), z_drop_strings);\n");
+    c_code.push_str("    int64_t* header = (int64_t*)(rc + 1);\n");
+    c_code.push_str("    header[0] = header[1] = count;\n");
+    c_code.push_str("    z_array pieces = { count, header + 2, rc };\n");
+    c_code.push_str("    z_string* elements = (z_string*)pieces.data;\n");
+    c_code.push_str("    int64_t start = 0;\n");
+    c_code.push_str("    for (int64_t i = 0; i < count - 1; i++) {\n");
+    c_code.push_str("        int64_t at = z_str_find(text, separator, start);\n");
+    c_code.push_str("        elements[i] = z_str_new(text.data + start, at - start);\n");
+    c_code.push_str("        start = at + separator.len;\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    elements[count - 1] = z_str_new(text.data + start, text.len - start);\n");
+    c_code.push_str("    return pieces;\n");
+    c_code.push_str("}\n\n");
+
+    // The command line, which main keeps for `args`, without the name of
+    // the program
+    c_code.push_str("static int z_argc = 0;\n");
+    c_code.push_str("static char** z_argv = NULL;\n\n");
+    c_code.push_str("static z_array z_args(void) {\n");
+    c_code.push_str("    int64_t count = z_argc > 1 ? z_argc - 1 : 0;\n");
+    c_code.push_str("    z_rc* rc = z_rc_new(2 * sizeof(int64_t) + count * sizeof(z_string), z_drop_strings);\n");
+    c_code.push_str("    int64_t* header = (int64_t*)(rc + 1);\n");
+    c_code.push_str("    header[0] = header[1] = count;\n");
+    c_code.push_str("    z_array args = { count, header + 2, rc };\n");
+    c_code.push_str("    z_string* elements = (z_string*)args.data;\n");
+    c_code.push_str("    for (int64_t i = 0; i < count; i++) elements[i] = z_str_from_c(z_argv[i + 1]);\n");
+    c_code.push_str("    return args;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static z_string z_env(z_string name) {\n");
+    c_code.push_str("    return z_str_from_c(getenv(name.data));\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static z_string z_str_to_upper(z_string text) {\n");
+    c_code.push_str("    z_string result = z_str_new(text.data, text.len);\n");
+    c_code.push_str("    char* data = (char*)result.data;\n");
+    c_code.push_str("    for (int64_t i = 0; i < result.len; i++) {\n");
+    c_code.push_str("        if (data[i] >= 'a' && data[i] <= 'z') data[i] -= 'a' - 'A';\n");
+    c_code.push_str("    }\n");
+    c_code.push_str("    return result;\n");
+    c_code.push_str("}\n\n");
+
+    // Function values carry the variables captured by a lambda, and every
+    // function called through one takes them as its first argument. They
+    // live in a block after its header, which `drop` releases them from;
+    // the block is third, as in strings and arrays.
+    c_code.push_str("typedef struct {\n");
+    c_code.push_str("    void (*fn)(void);\n");
+    c_code.push_str("    void* env;\n");
+    c_code.push_str("    z_rc* rc;\n");
+    c_code.push_str("} z_closure;\n\n");
+
+    c_code.push_str("static z_closure z_closure_retain(z_closure closure) {\n");
+    c_code.push_str("    z_retain(closure.rc);\n");
+    c_code.push_str("    return closure;\n");
+    c_code.push_str("}\n\n");
+
+    c_code.push_str("static void z_drop_closures(z_rc* rc) {\n");
+    c_code.push_str("    int64_t len = *(int64_t*)(rc + 1);\n");
+    c_code.push_str("    z_closure* elements = (z_closure*)((int64_t*)(rc + 1) + 2);\n");
+    c_code.push_str("    for (int64_t i = 0; i < len; i++) z_release(elements[i].rc);\n");
+    c_code.push_str("}\n\n");
+    c_code
+}
+
+/// A C literal of the int `value`, as wide as `int64_t` so that arithmetic
+/// on literals alone does not overflow C's `int`
+fn c_int_literal(value: i128) -> String {
+    match value {
+        // The digits of its magnitude do not fit in a long long
+        value if value == i64::MIN as i128 => "INT64_MIN".to_string(),
+        value if value > i64::MAX as i128 => format!("{}ULL", value),
+        value => format!("{}LL", value),
+    }
+}
+
+/// A C string literal with the bytes of `text`
+fn c_string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            b'\r' => literal.push_str("\\r"),
+            // `??` would start a trigraph in compilers that still read them
+            b'?' if literal.ends_with('?') => literal.push_str("\\?"),
+            b' '..=b'~' => literal.push(byte as char),
+            // Octal escapes, unlike hex ones, never run on into the next character
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// C for an operation with a string operand: concatenation, with the other
+/// operand converted to text, or comparison by content. Returns `None` for
+/// operations C's own operators handle, such as comparing with null.
+fn string_operation(
+    op: &BinaryOp,
+    left: &str,
+    left_type: Option<Type>,
+    right: &str,
+    right_type: Option<Type>,
+) -> Option<String> {
+    let both_strings = left_type == Some(Type::String) && right_type == Some(Type::String);
+    let comparison = match op {
+        BinaryOp::Add => {
+            let literal = |code: &str| code.strip_prefix("Z_STR(")?.strip_suffix(')').map(str::to_string);
+            if let (Some(left), Some(right)) = (literal(left), literal(right)) {
+                // Two literals are joined at compile time, by C itself
+                return Some(format!("Z_STR({} {})", left, right));
+            }
+            return Some(format!(
+                "z_str_concat({}, {})",
+                to_c_string(left, left_type?),
+                to_c_string(right, right_type?)
+            ));
+        }
+        BinaryOp::Eq => "==",
+        BinaryOp::Neq => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::Lte => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::Gte => ">=",
+        _ => return None,
+    };
+    if matches!(op, BinaryOp::Eq | BinaryOp::Neq) {
+        // A string that is not optional is a value, never a null pointer
+        let string_side = match (left_type == Some(Type::String), right_type == Some(Type::String)) {
+            (true, false) if right == "NULL" => Some(left),
+            (false, true) if left == "NULL" => Some(right),
+            _ => None,
+        };
+        if let Some(string) = string_side {
+            return Some(format!("(({}).data {} NULL)", string, comparison));
+        }
+    }
+    if !both_strings {
+        return None;
+    }
+    match op {
+        BinaryOp::Eq => Some(format!("z_str_eq({}, {})", left, right)),
+        BinaryOp::Neq => Some(format!("(!z_str_eq({}, {}))", left, right)),
+        _ => Some(format!("(z_str_cmp({}, {}) {} 0)", left, right, comparison)),
+    }
+}
+
+/// C expression for the text of a value concatenated with a string
+fn to_c_string(code: &str, ty: Type) -> String {
+    match ty {
+        Type::String => code.to_string(),
+        Type::Float => format!("z_float_to_str({})", code),
+        Type::Bool => format!("(({}) ? Z_STR(\"true\") : Z_STR(\"false\"))", code),
+        _ => format!("z_int_to_str({})", code),
+    }
+}
+
+/// C type of a pointer to the lifted function behind a closure
+fn function_pointer_type(params: &[Type], ret: &Type) -> String {
+    let mut param_types = vec!["void*".to_string()];
+    param_types.extend(params.iter().map(c_type));
+    format!("{} (*)({})", c_type(ret), param_types.join(", "))
+}
+
+/// The expression an inlined call stands for, whose value it is
+fn inlined_body(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Inlined(_, body, _) => inlined_body(body),
+        expr => expr,
+    }
+}
+
+/// Appends every identifier `expr` refers to, in order of first appearance
+fn expr_identifiers(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Identifier(name, _) => {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        Expr::Literal(..) => {}
+        Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) | Expr::Range(left, right, _) => {
+            expr_identifiers(left, names);
+            expr_identifiers(right, names);
+        }
+        Expr::Unary(_, operand, _)
+        | Expr::Field(operand, _, _)
+        | Expr::Cast(operand, _, _)
+        | Expr::Try(operand, _, _)
+        | Expr::Inlined(_, operand, _) => expr_identifiers(operand, names),
+        Expr::Call(callee, args, _) => {
+            expr_identifiers(callee, names);
+            args.iter().for_each(|arg| expr_identifiers(arg, names));
+        }
+        Expr::Array(elements, _) | Expr::Variant(_, _, elements, _) => {
+            elements.iter().for_each(|element| expr_identifiers(element, names));
+        }
+        Expr::If(cond, then_branch, else_branch, _) => {
+            expr_identifiers(cond, names);
+            expr_identifiers(then_branch, names);
+            if let Some(else_branch) = else_branch {
+                expr_identifiers(else_branch, names);
+            }
+        }
+        Expr::Block(stmts, _) => stmts.iter().for_each(|stmt| stmt_identifiers(stmt, names)),
+        Expr::Lambda(_, _, body, _) => expr_identifiers(body, names),
+        Expr::Match(scrutinee, arms, _) => {
+            expr_identifiers(scrutinee, names);
+            arms.iter().for_each(|arm| expr_identifiers(&arm.body, names));
+        }
+    }
+}
+
+fn stmt_identifiers(stmt: &Stmt, names: &mut Vec<String>) {
+    match stmt {
+        Stmt::Expr(expr) | Stmt::Const(_, _, expr, _) => expr_identifiers(expr, names),
+        Stmt::Let(_, _, init, _) | Stmt::Return(init, _) => {
+            if let Some(init) = init {
+                expr_identifiers(init, names);
+            }
+        }
+        Stmt::Assign(target, value, _) => {
+            expr_identifiers(target, names);
+            expr_identifiers(value, names);
+        }
+        Stmt::While(cond, body, _) | Stmt::For(_, _, cond, body, _) => {
+            expr_identifiers(cond, names);
+            stmt_identifiers(body, names);
+        }
+        Stmt::Block(stmts, _) => stmts.iter().for_each(|stmt| stmt_identifiers(stmt, names)),
+        Stmt::Function(..) | Stmt::Struct(..) | Stmt::Enum(..) | Stmt::Import(..) | Stmt::Extern(..) => {}
+    }
+}
+
+fn c_signature(c_name: &str, params: &[(String, Type)], return_type: &Type) -> String {
+    let params = if params.is_empty() {
+        "void".to_string()
+    } else {
+        params
+            .iter()
+            .map(|(name, ty)| format!("{} {}", c_type(ty), name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!("{} {}({})", c_type(return_type), c_name, params)
+}
+
+/// C specifiers for a function's `@inline` or `@noinline` attribute, before
+/// its prototype or its `definition`. Inlined functions are `static` unless
+/// they are `pub`, since other modules call those; their prototypes then leave
+/// out `inline`, so that the definition is also compiled on its own.
+fn function_specifiers(attributes: &[Attribute], public: bool, definition: bool) -> String {
+    let inline = if attributes.contains(&Attribute::NoInline) {
+        "__attribute__((noinline)) "
+    } else if !attributes.contains(&Attribute::Inline) {
+        ""
+    } else if !public {
+        "static inline __attribute__((always_inline)) "
+    } else if definition {
+        "inline __attribute__((always_inline)) "
+    } else {
+        ""
+    };
+    let mut specifiers = inline.to_string();
+    if let Some(level) = Attribute::opt_level(attributes) {
+        specifiers.push_str(&format!("Z_OPTIMIZE({}) ", level));
+    }
+    if attributes.contains(&Attribute::Cold) {
+        specifiers.push_str("__attribute__((cold)) ");
+    }
+    specifiers
+}
+
+/// A `pub` function under its own name, calling its `symbol`, so that C code
+/// can call it
+fn generate_export(name: &str, symbol: &str, params: &[(String, Type)], return_type: &Type) -> String {
+    let args: Vec<&str> = params.iter().map(|(param, _)| param.as_str()).collect();
+    let call = format!("{}({})", symbol, args.join(", "));
+    let body = if *return_type == Type::Void { call } else { format!("return {}", call) };
+    format!("Z_EXPORT {} {{\n    {};\n}}\n\n", c_signature(name, params, return_type), body)
+}
+
+/// A C header declaring the `pub` functions of a program, guarded by
+/// `guard`. Their parameters and results are limited to numbers, bools,
+/// strings and arrays, whose types the header defines.
+#[cfg(feature = "c")]
+pub fn generate_header(program: &Program, guard: &str) -> Result<String> {
+    let mut declarations = String::new();
+    let mut uses_strings = false;
+    let mut uses_arrays = false;
+    for stmt in &program.statements {
+        let (name, params, return_type) = match stmt {
+            Stmt::Function(name, _, params, return_type, _, true, _, _) if name != "main" => (name, params, return_type),
+            _ => continue,
+        };
+        for ty in params.iter().map(|(_, ty)| ty).chain([return_type]) {
+            match ty {
+                Type::String => uses_strings = true,
+                Type::Array(_) => uses_arrays = true,
+                Type::Float | Type::Bool | Type::Void => {}
+                ty if ty.is_integer() => {}
+                ty => {
+                    return Err(CodegenError {
+                        message: format!("Function {} cannot be exported to C, since it uses {:?}", name, ty),
+                        span: Some(stmt.span().clone()),
+                    })
+                }
+            }
+        }
+        declarations.push_str(&format!("{};\n", c_signature(name, params, return_type)));
+    }
+
+    let mut header = format!("#ifndef {}\n#define {}\n\n", guard, guard);
+    header.push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+    // Several Z libraries may be used together, and each header defines these.
+    // Values made in C leave `rc` null, so Z never frees them.
+    if uses_strings {
+        header.push_str("#ifndef Z_STRING_DEFINED\n#define Z_STRING_DEFINED\n");
+        header.push_str("typedef struct {\n    int64_t len;\n    const char* data;\n    struct z_rc* rc;\n} z_string;\n#endif\n\n");
+    }
+    if uses_arrays {
+        header.push_str("#ifndef Z_ARRAY_DEFINED\n#define Z_ARRAY_DEFINED\n");
+        header.push_str("typedef struct {\n    int64_t len;\n    void* data;\n    struct z_rc* rc;\n} z_array;\n#endif\n\n");
+    }
+    header.push_str(&declarations);
+    header.push_str(&format!("\n#endif // {}\n", guard));
+    Ok(header)
+}
+
+/// The prototype of a C function, and a Z function with C name `symbol`
+/// calling it, which passes strings as C strings and copies the strings it
+/// returns
+fn generate_extern(name: &str, symbol: &str, params: &[(String, Type)], return_type: &Type) -> String {
+    // Functions of the runtime, named with its `z_` prefix, take and return
+    // values as Z has them, strings with a length of their own
+    if name.starts_with("z_") {
+        let args: Vec<&str> = params.iter().map(|(param, _)| param.as_str()).collect();
+        let call = format!("{}({})", name, args.join(", "));
+        return format!(
+            "{};\n{} {{ {}{}; }}\n",
+            c_signature(name, params, return_type),
+            c_signature(symbol, params, return_type),
+            if *return_type == Type::Void { "" } else { "return " },
+            call
+        );
+    }
+    // As in the C library, strings are taken as `const char*` and returned
+    // as `char*`, NULL for null, and ints are C's `int`; the sized types keep
+    // their width
+    let ffi_type = |ty: &Type| match represented(ty) {
+        Type::String => "const char*".to_string(),
+        Type::Int => "int".to_string(),
+        ty => c_type(&ty),
+    };
+    let c_params = if params.is_empty() {
+        "void".to_string()
+    } else {
+        params
+            .iter()
+            .map(|(param, ty)| format!("{} {}", ffi_type(ty), param))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let args: Vec<String> = params
+        .iter()
+        .map(|(param, ty)| match represented(ty) {
+            Type::String => format!("{}.data", param),
+            _ => param.clone(),
+        })
+        .collect();
+    let call = format!("{}({})", name, args.join(", "));
+    let body = match represented(return_type) {
+        Type::Void => format!("{};", call),
+        Type::String => format!("return z_str_from_c({});", call),
+        _ => format!("return {};", call),
+    };
+    format!(
+        "{} {}({});\n{} {{ {} }}\n",
+        match represented(return_type) {
+            Type::String => "char*".to_string(),
+            ty => ffi_type(&ty),
+        },
+        name,
+        c_params,
+        c_signature(symbol, params, return_type),
+        body
+    )
+}
+
+/// The optional types a program names, other than of strings, which are
+/// structs in C
+struct Optionals(Vec<Type>);
+
+impl Visitor for Optionals {
+    fn visit_type(&mut self, ty: &Type) {
+        if is_tagged(ty) && !self.0.contains(ty) {
+            self.0.push(ty.clone());
+        }
+        walk_type(self, ty);
+    }
+}
+
+/// The enums, the structs and the `optionals` in an order where every one
+/// comes after those it holds, which C needs to know the size of
+fn type_order(
+    enums: &HashMap<String, Vec<(String, Vec<Type>)>>,
+    structs: &HashMap<String, Vec<(String, Type)>>,
+    optionals: &[Type],
+) -> Result<Vec<Type>> {
+    fn visit(
+        ty: &Type,
+        enums: &HashMap<String, Vec<(String, Vec<Type>)>>,
+        structs: &HashMap<String, Vec<(String, Type)>>,
+        visiting: &mut Vec<Type>,
+        order: &mut Vec<Type>,
+    ) -> Result<()> {
+        if order.contains(ty) {
+            return Ok(());
+        }
+        // The typechecker rejects structs holding themselves, so this is an
+        // enum holding itself through its payloads
+        if let Some(start) = visiting.iter().position(|visited| visited == ty) {
+            let name = visiting[start..].iter().find_map(|visited| match visited {
+                Type::Enum(name) => Some(name.clone()),
+                _ => None,
+            });
+            return Err(CodegenError {
+                message: format!("Enum {} holds itself in a payload, so it has no size in C", name.unwrap_or_default()),
+                span: None,
+            });
+        }
+        visiting.push(ty.clone());
+        let held: Vec<&Type> = match ty {
+            Type::Enum(name) => enums[name].iter().flat_map(|(_, payload)| payload).collect(),
+            Type::Struct(name) => structs[name].iter().map(|(_, ty)| ty).collect(),
+            Type::Optional(inner) => vec![inner],
+            _ => Vec::new(),
+        };
+        for inner in held {
+            let defined = match inner {
+                Type::Enum(name) => enums.contains_key(name),
+                Type::Struct(name) => structs.contains_key(name),
+                inner => is_tagged(inner),
+            };
+            if defined {
+                visit(inner, enums, structs, visiting, order)?;
+            }
+        }
+        visiting.pop();
+        order.push(ty.clone());
+        Ok(())
+    }
+
+    let mut names: Vec<Type> = enums
+        .keys()
+        .map(|name| Type::Enum(name.clone()))
+        .chain(structs.keys().map(|name| Type::Struct(name.clone())))
+        .collect();
+    names.sort_by_key(Type::to_string);
+    let mut order = Vec::new();
+    for ty in names.iter().chain(optionals) {
+        visit(ty, enums, structs, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+/// A struct, with a member per field. C does not allow empty structs, so
+/// one without fields gets a member of its own.
+fn generate_struct(name: &str, fields: &[(String, Type)]) -> String {
+    let name = mangle::type_name(name);
+    let mut code = format!("typedef struct {} {{\n", name);
+    for (field, ty) in fields {
+        code.push_str(&format!("    {} {};\n", c_type(ty), field));
+    }
+    if fields.is_empty() {
+        code.push_str("    char z_empty;\n");
+    }
+    code.push_str(&format!("}} {};\n\n", name));
+    code
+}
+
+/// An enum as a tagged union: a tag constant per variant, a struct holding
+/// the tag and the payload of the current variant, and a constructor per
+/// variant
+fn generate_enum(name: &str, variants: &[(String, Vec<Type>)]) -> String {
+    let name = &mangle::type_name(name);
+    let tags: Vec<String> = variants
+        .iter()
+        .enumerate()
+        .map(|(i, (variant, _))| format!("{}_{} = {}", name, variant, i))
+        .collect();
+    let mut code = format!("enum {{ {} }};\n\n", tags.join(", "));
+
+    code.push_str(&format!("typedef struct {} {{\n    int tag;\n", name));
+    // C does not allow empty structs or unions, so only payloads get a member
+    if variants.iter().any(|(_, payload)| !payload.is_empty()) {
+        code.push_str("    union {\n");
+        for (variant, payload) in variants.iter().filter(|(_, payload)| !payload.is_empty()) {
+            let fields: Vec<String> = payload
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| format!("{} _{};", c_type(ty), i))
+                .collect();
+            code.push_str(&format!("        struct {{ {} }} {};\n", fields.join(" "), variant));
+        }
+        code.push_str("    } payload;\n");
+    }
+    code.push_str(&format!("}} {};\n\n", name));
+
+    for (variant, payload) in variants {
+        let params: Vec<String> = payload
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| format!("{} _{}", c_type(ty), i))
+            .collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        code.push_str(&format!("static {} {}_{}_new({}) {{\n", name, name, variant, params));
+        code.push_str(&format!("    {} value;\n", name));
+        code.push_str(&format!("    value.tag = {}_{};\n", name, variant));
+        for i in 0..payload.len() {
+            code.push_str(&format!("    value.payload.{}._{} = _{};\n", variant, i, i));
+        }
+        code.push_str("    return value;\n");
+        code.push_str("}\n\n");
+    }
+    code
+}
+
+/// An optional other than of strings as a struct holding whether it has a
+/// value, and the value if it does
+fn generate_optional(ty: &Type) -> String {
+    let inner = match ty {
+        Type::Optional(inner) => inner,
+        _ => return String::new(),
+    };
+    format!("typedef struct {{\n    bool has;\n    {} value;\n}} {};\n\n", c_type(inner), c_type(ty))
+}
+
+/// The smallest value of a signed integer type, which overflows when
+/// negated or divided by -1
+fn c_min(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::Int | Type::I64 => Some("INT64_MIN"),
+        Type::I32 => Some("INT32_MIN"),
+        Type::I16 => Some("INT16_MIN"),
+        Type::I8 => Some("INT8_MIN"),
+        _ => None,
+    }
+}
+
+/// The C type arithmetic on the integer type `ty` is done in, where C
+/// would widen its values to an `int` or a `long long` and keep the result
+/// that wide: one no product of two of them overflows, or an unsigned one
+/// that wraps
+fn wrapped_type(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::I8 | Type::I16 | Type::I32 => Some("int64_t"),
+        Type::U8 | Type::U16 | Type::U32 => Some("uint32_t"),
+        _ => None,
+    }
+}
+
+/// `left op right` for operands of the integer type `ty`, wrapped to `ty`
+/// as Z's arithmetic is, where C would keep a wider result
+fn wrapped_arithmetic(ty: &Type, op: &BinaryOp, left: &str, right: &str) -> Option<String> {
+    let wide = wrapped_type(ty)?;
+    match op {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => Some(format!(
+            "(({})(({}){} {} ({}){}))",
+            c_type(ty),
+            wide,
+            left,
+            op.symbol(),
+            wide,
+            right
+        )),
+        _ => None,
+    }
+}
+
+/// C spelling of a Z type
+fn c_type(ty: &Type) -> String {
+    let name = match ty {
+        Type::Int => "int64_t",
+        Type::I8 => "int8_t",
+        Type::I16 => "int16_t",
+        Type::I32 => "int32_t",
+        Type::I64 => "int64_t",
+        Type::U8 => "uint8_t",
+        Type::U16 => "uint16_t",
+        Type::U32 => "uint32_t",
+        Type::U64 => "uint64_t",
+        Type::Float => "double",
+        Type::Bool => "bool",
+        Type::String | Type::Optional(_) if represented(ty) == Type::String => "z_string",
+        Type::Optional(inner) => return mangle::optional_name(inner),
+        Type::Void => "void",
+        Type::Function(..) => "z_closure",
+        Type::Array(_) => "z_array",
+        Type::Enum(name) | Type::Struct(name) => return mangle::type_name(name),
+        _ => "void*",
+    };
+    name.to_string()
+}
+
+/// The type values of `ty` have in C. An optional string is a string,
+/// whose data is NULL when it is null, as for strings C functions return.
+fn represented(ty: &Type) -> Type {
+    match ty {
+        Type::Optional(inner) if **inner == Type::String => Type::String,
+        ty => ty.clone(),
+    }
+}
+
+/// Whether `ty` is an optional other than of strings, which holds whether
+/// it has a value alongside the value
+fn is_tagged(ty: &Type) -> bool {
+    matches!(ty, Type::Optional(inner) if **inner != Type::String)
+}
+
+/// `null` as a value of the optional type `ty`, or of the string an
+/// optional string is represented by
+fn null_value(ty: &Type) -> String {
+    if is_tagged(ty) {
+        format!("(({}){{ false }})", c_type(ty))
+    } else {
+        "((z_string){ 0, NULL, NULL })".to_string()
+    }
+}
+
+/// C initializer for a variable declared without a value, so that strings
+/// start out empty rather than as null pointers
+fn zero_value(ty: &Type) -> &'static str {
+    match ty {
+        Type::Float => "0.0",
+        Type::Bool => "false",
+        Type::String => "Z_STR(\"\")",
+        Type::Function(..) => "(z_closure){0}",
+        Type::Array(_) => "(z_array){0}",
+        Type::Enum(_) | Type::Struct(_) => "{0}",
+        // A null optional
+        Type::Optional(_) => "{0}",
+        ty if ty.is_integer() => "0",
+        _ => "NULL",
+    }
+}
+
+/// How compiled programs manage memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Gc {
+    /// Strings and arrays count their references and are freed with the
+    /// last one
+    #[default]
+    RefCount,
+    /// The Boehm collector allocates all runtime memory and frees it once
+    /// unreachable. Programs link `libgc`.
+    Boehm,
+}
+
+impl std::str::FromStr for Gc {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "rc" => Ok(Gc::RefCount),
+            "boehm" => Ok(Gc::Boehm),
+            _ => Err(format!("unknown garbage collector {}, expected rc or boehm", name)),
+        }
+    }
+}
+
+/// Options for code generation
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    /// How the program manages memory
+    pub gc: Gc,
+    /// Whether to check for errors at run time, such as indexing out of
+    /// bounds or integer overflow, and panic on them, and record calls for
+    /// the stack traces of panics. Release builds leave the checks out.
+    pub checks: bool,
+}
+
+impl CodeGenerator {
+    fn with_options(module: &str, file: &str, source: &str, options: &CodegenOptions) -> Self {
+        let mut codegen = CodeGenerator::new();
+        codegen.module = module.to_string();
+        codegen.file = file.to_string();
+        codegen.line_starts = ast::line_starts(source);
+        codegen.gc = options.gc;
+        codegen.checks = options.checks;
+        codegen
+    }
+}
+
+/// Generates the IR for `program`, compiled as `module` from `source` in
+/// `file`. Fails with the first thing the program uses that the IR does not
+/// cover, for `generate_c` to generate its C instead.
+pub fn generate_ir(
+    program: &Program,
+    module: &str,
+    file: &str,
+    source: &str,
+    options: &CodegenOptions,
+) -> std::result::Result<ir::Module, ir::Uncovered> {
+    ir::build(program, module, file, source, options.checks)
+}
+
+/// Generates C for `program`, compiled as `module` from `source` in `file`
+pub fn generate_c(program: Program, module: &str, file: &str, source: &str, options: &CodegenOptions) -> Result<String> {
+    CodeGenerator::with_options(module, file, source, options).generate(program)
+}
+
+/// Generates C for a module the program imports, compiled to an object of
+/// its own. Like a library, it has only declarations at the top level.
+#[cfg(feature = "c")]
+pub fn generate_module_c(
+    program: Program,
+    module: &str,
+    file: &str,
+    source: &str,
+    options: &CodegenOptions,
+) -> Result<String> {
+    let mut codegen = CodeGenerator::with_options(module, file, source, options);
+    codegen.library = true;
+    codegen.imported = true;
+    codegen.generate(program)
+}
+
+/// Generates C for a library, which has no `main` and only declarations at
+/// the top level
+pub fn generate_library_c(
+    program: Program,
+    module: &str,
+    file: &str,
+    source: &str,
+    options: &CodegenOptions,
+) -> Result<String> {
+    let mut codegen = CodeGenerator::with_options(module, file, source, options);
+    codegen.library = true;
+    codegen.generate(program)
+}
+
+/// Lowers an IR module to C. Each function declares its variables and
+/// temporaries up front, and its blocks follow in order, labelled where
+/// other blocks jump to them.
+pub fn lower_ir(module: &ir::Module, options: &CodegenOptions) -> String {
+    let mut c_code = generate_runtime(options.gc, false);
+    for function in &module.externs {
+        c_code.push_str(&generate_extern(&function.name, &function.symbol, &function.params, &function.return_type));
+    }
+    for import in &module.imports {
+        let params: Vec<(String, Type)> = import
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (format!("a{}", i), ty.clone()))
+            .collect();
+        c_code.push_str(&format!("{};\n", c_signature(&import.symbol, &params, &import.return_type)));
+    }
+    // Pure functions write nothing and always return, so C may merge or
+    // drop their calls, and read nothing but their arguments, as the IR has
+    // no globals, which makes them `const` to C. The frames of debug builds
+    // are left as they were wherever calls go.
+    for function in module.functions.iter().filter(|function| function.symbol != "main") {
+        c_code.push_str(&format!(
+            "{}{}{};\n",
+            function_specifiers(&function.attributes, function.public, false),
+            if function.pure { "__attribute__((const)) " } else { "" },
+            c_signature(&function.symbol, &function.locals[..function.params], &function.return_type)
+        ));
+    }
+    c_code.push('\n');
+
+    let mut exports = String::new();
+    let mut main = String::new();
+    for function in &module.functions {
+        let params = &function.locals[..function.params];
+        if function.symbol == "main" {
+            main = lower_function("int main()", function, options);
+            continue;
+        }
+        let signature = format!(
+            "{}{}",
+            function_specifiers(&function.attributes, function.public, true),
+            c_signature(&function.symbol, params, &function.return_type)
+        );
+        c_code.push_str(&lower_function(&signature, function, options));
+        c_code.push('\n');
+        if function.public {
+            exports.push_str(&generate_export(&function.name, &function.symbol, params, &function.return_type));
+        }
+    }
+    c_code.push_str(&exports);
+    c_code.push_str(&main);
+    c_code
+}
+
+/// The C definition of an IR function, under `signature`
+fn lower_function(signature: &str, function: &ir::Function, options: &CodegenOptions) -> String {
+    let mut code = format!("{} {{\n", signature);
+    for (name, ty) in &function.locals[function.params..] {
+        code.push_str(&format!("    {} {} = {};\n", c_type(ty), name, zero_value(ty)));
+    }
+    // Temporaries of blocks the optimizer removed are left out
+    let computed: HashSet<ir::Temp> = function
+        .blocks
+        .iter()
+        .flat_map(|block| block.instructions.iter().filter_map(ir::Instruction::dest))
+        .collect();
+    for (temp, ty) in function.temps.iter().enumerate().filter(|(temp, _)| computed.contains(temp)) {
+        code.push_str(&format!("    {} z_t{};\n", c_type(ty), temp));
+    }
+    if function.symbol == "main" && options.gc == Gc::Boehm {
+        code.push_str("    GC_INIT();\n");
+    }
+    if let Some(frame) = &function.frame {
+        code.push_str(&format!("    z_enter({});\n", c_string_literal(frame)));
+    }
+
+    let targets: HashSet<ir::BlockId> = function
+        .blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(id, block)| goto_targets(&block.terminator, id + 1))
+        .collect();
+    for (id, block) in function.blocks.iter().enumerate() {
+        let mut body = String::new();
+        for instruction in &block.instructions {
+            body.push_str(&lower_instruction(instruction, function));
+        }
+        body.push_str(&lower_terminator(&block.terminator, id + 1, function));
+        if targets.contains(&id) {
+            // A label must label a statement
+            let empty = if body.is_empty() { " ;" } else { "" };
+            code.push_str(&format!("z_b{}:{}\n", id, empty));
+        }
+        code.push_str(&body);
+    }
+    code.push_str("}\n");
+    code
+}
+
+fn lower_operand(operand: &ir::Operand, function: &ir::Function) -> String {
+    match operand {
+        // Negative numbers come out of the optimizer, and stay apart from
+        // the operators before them
+        ir::Operand::Const(ir::Const::Int(value)) if *value < 0 => format!("({})", c_int_literal(*value as i128)),
+        ir::Operand::Const(ir::Const::Int(value)) => c_int_literal(*value as i128),
+        ir::Operand::Const(ir::Const::Float(value)) if *value < 0.0 => format!("({:?})", value),
+        ir::Operand::Const(ir::Const::Float(value)) => format!("{:?}", value),
+        ir::Operand::Const(ir::Const::Bool(value)) => value.to_string(),
+        ir::Operand::Local(local) => function.locals[*local].0.clone(),
+        ir::Operand::Temp(temp) => format!("z_t{}", temp),
+    }
+}
+
+fn lower_instruction(instruction: &ir::Instruction, function: &ir::Function) -> String {
+    let operand = |operand: &ir::Operand| lower_operand(operand, function);
+    match instruction {
+        ir::Instruction::Binary {
+            dest,
+            op: op @ (BinaryOp::Div | BinaryOp::Mod),
+            left,
+            right,
+            overflow: Some(location),
+        } => {
+            let location = c_string_literal(location);
+            let mut code = format!("    if ({} == 0) z_panic(\"division by zero\", {});\n", operand(right), location);
+            if let Some(min) = c_min(&function.temps[*dest]) {
+                code.push_str(&format!(
+                    "    if ({} == -1 && {} == {}) z_panic(\"integer overflow\", {});\n",
+                    operand(right),
+                    operand(left),
+                    min,
+                    location
+                ));
+            }
+            code.push_str(&format!("    z_t{} = {} {} {};\n", dest, operand(left), op.symbol(), operand(right)));
+            code
+        }
+        ir::Instruction::Binary {
+            dest,
+            op,
+            left,
+            right,
+            overflow: Some(location),
+        } => {
+            let builtin = match op {
+                BinaryOp::Add => "z_add_overflow",
+                BinaryOp::Sub => "z_sub_overflow",
+                _ => "z_mul_overflow",
+            };
+            format!(
+                "    if ({}({}, {}, &z_t{})) z_panic(\"integer overflow\", {});\n",
+                builtin,
+                operand(left),
+                operand(right),
+                dest,
+                c_string_literal(location)
+            )
+        }
+        ir::Instruction::Binary { dest, op, left, right, .. } => {
+            format!("    z_t{} = {} {} {};\n", dest, operand(left), op.symbol(), operand(right))
+        }
+        ir::Instruction::Unary { dest, op, operand: value } => {
+            let op = match op {
+                UnaryOp::Neg => "-",
+                UnaryOp::Not => "!",
+            };
+            format!("    z_t{} = {}{};\n", dest, op, operand(value))
+        }
+        // Shifting negative numbers left is undefined in C, unlike shifting
+        // their bits as unsigned. Shifting the result back gives another
+        // value where bits were lost.
+        ir::Instruction::Bitwise {
+            dest,
+            op: ir::BitOp::Shl,
+            left,
+            right,
+            overflow,
+        } => {
+            let ty = &function.temps[*dest];
+            let mut code = if ty.is_unsigned() {
+                format!("    z_t{} = {} << {};\n", dest, operand(left), operand(right))
+            } else {
+                format!("    z_t{} = ({})((uint64_t){} << {});\n", dest, c_type(ty), operand(left), operand(right))
+            };
+            if let Some(location) = overflow {
+                code.push_str(&format!(
+                    "    if ((z_t{} >> {}) != {}) z_panic(\"integer overflow\", {});\n",
+                    dest,
+                    operand(right),
+                    operand(left),
+                    c_string_literal(location)
+                ));
+            }
+            code
+        }
+        ir::Instruction::Bitwise { dest, op, left, right, .. } => {
+            format!("    z_t{} = {} {} {};\n", dest, operand(left), op.symbol(), operand(right))
+        }
+        ir::Instruction::Cast { dest, operand: value } => {
+            format!("    z_t{} = ({}){};\n", dest, c_type(&function.temps[*dest]), operand(value))
+        }
+        ir::Instruction::Store { local, value } => {
+            format!("    {} = {};\n", function.locals[*local].0, operand(value))
+        }
+        ir::Instruction::Call {
+            dest,
+            symbol,
+            args,
+            location,
+        } => {
+            let mut code = String::new();
+            if let Some(location) = location {
+                code.push_str(&format!("    z_at({});\n", c_string_literal(location)));
+            }
+            let args: Vec<String> = args.iter().map(operand).collect();
+            match dest {
+                Some(dest) => code.push_str(&format!("    z_t{} = {}({});\n", dest, symbol, args.join(", "))),
+                None => code.push_str(&format!("    {}({});\n", symbol, args.join(", "))),
+            }
+            code
+        }
+        ir::Instruction::Print(pieces) => pieces
+            .iter()
+            .map(|piece| match piece {
+                ir::Piece::Text(text) => format!("    z_write_str(Z_STR({}));\n", c_string_literal(text)),
+                ir::Piece::Value(value) => {
+                    let writer = match function.operand_type(value) {
+                        Type::Float => "z_write_float",
+                        Type::Bool => "z_write_bool",
+                        ty if ty.is_unsigned() => "z_write_uint",
+                        _ => "z_write_int",
+                    };
+                    format!("    {}({});\n", writer, operand(value))
+                }
+            })
+            .collect(),
+        ir::Instruction::Panic { message, location } => {
+            format!("    z_panic({}, {});\n", c_string_literal(message), c_string_literal(location))
+        }
+        ir::Instruction::Enter { frame, location } => {
+            format!("    z_at({});\n    z_enter({});\n", c_string_literal(location), c_string_literal(frame))
+        }
+        ir::Instruction::Leave => "    z_leave();\n".to_string(),
+    }
+}
+
+/// Blocks the C for the end of a block jumps to with `goto`, rather than
+/// falling through to the block at `next`
+fn goto_targets(terminator: &ir::Terminator, next: ir::BlockId) -> Vec<ir::BlockId> {
+    match *terminator {
+        ir::Terminator::Jump(target) if target != next => vec![target],
+        ir::Terminator::Branch(_, then_block, else_block) if else_block == next => vec![then_block],
+        ir::Terminator::Branch(_, then_block, else_block) if then_block == next => vec![else_block],
+        ir::Terminator::Branch(_, then_block, else_block) => vec![then_block, else_block],
+        _ => Vec::new(),
+    }
+}
+
+/// C for the end of a block, which falls through to the block at `next`
+fn lower_terminator(terminator: &ir::Terminator, next: ir::BlockId, function: &ir::Function) -> String {
+    let leave = if function.frame.is_some() { "    z_leave();\n" } else { "" };
+    match terminator {
+        ir::Terminator::Jump(_) => goto_targets(terminator, next)
+            .iter()
+            .map(|target| format!("    goto z_b{};\n", target))
+            .collect(),
+        ir::Terminator::Branch(cond, then_block, else_block) => {
+            let cond = lower_operand(cond, function);
+            if *else_block == next {
+                format!("    if ({}) goto z_b{};\n", cond, then_block)
+            } else if *then_block == next {
+                format!("    if (!{}) goto z_b{};\n", cond, else_block)
+            } else {
+                format!("    if ({}) goto z_b{};\n    goto z_b{};\n", cond, then_block, else_block)
+            }
+        }
+        ir::Terminator::Return(Some(value)) => {
+            format!("{}    return {};\n", leave, lower_operand(value, function))
+        }
+        // `main` returns an exit status even when declared without one
+        ir::Terminator::Return(None) if function.symbol == "main" => format!("{}    return 0;\n", leave),
+        ir::Terminator::Return(None) => format!("{}    return;\n", leave),
+        ir::Terminator::Unreachable => "    abort();\n".to_string(),
+    }
+}
+
+/// Where intermediate files go: next to the output with `keep_temps`, and
+/// otherwise in a directory of their own for this build, so concurrent builds
+/// never share files. The directory is removed when this is dropped, on error
+/// paths as well.
+#[cfg(feature = "c")]
+struct TempFiles {
+    output_path: PathBuf,
+    dir: Option<TempDir>,
+}
+
+#[cfg(feature = "c")]
+impl TempFiles {
+    fn new(output_path: &Path, keep_temps: bool) -> Result<Self> {
+        let dir = if keep_temps {
+            None
+        } else {
+            let dir = tempfile::Builder::new().prefix("zc-").tempdir().map_err(|e| CodegenError {
+                message: format!("Failed to create a temporary directory: {}", e),
+                span: None,
+            })?;
+            Some(dir)
+        };
+        Ok(TempFiles { output_path: output_path.to_path_buf(), dir })
+    }
+
+    /// The temporary directory, unless the files are kept
+    fn dir(&self) -> Option<&Path> {
+        self.dir.as_ref().map(TempDir::path)
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        match &self.dir {
+            Some(dir) => dir.path().join("z_program").with_extension(extension),
+            None => self.output_path.with_extension(extension),
+        }
+    }
+}
+
+#[cfg(feature = "c")]
+fn write_c_source(code: &str, c_path: &Path) -> Result<()> {
+    fs::write(c_path, code).map_err(|e| CodegenError {
+        message: format!("Failed to write C code to file: {}", e),
+        span: None,
+    })
+}
+
+/// The kinds of C compiler generated code can be built with, which differ in
+/// the flags they understand
+#[cfg(feature = "c")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CBackend {
+    Gcc,
+    Clang,
+    /// TinyCC, which compiles almost instantly but barely optimizes
+    Tcc,
+    /// `zig cc`, which ships its own headers and libc for cross builds
+    Zig,
+    /// `clang-cl`, which takes MSVC's flags and links with the MSVC tools on
+    /// Windows. MSVC's own `cl` cannot build the generated C, which uses GNU
+    /// extensions.
+    Msvc,
+}
+
+#[cfg(feature = "c")]
+impl CBackend {
+    /// Backends in the order they are looked for when none is chosen
+    pub const ALL: [CBackend; 5] = [CBackend::Gcc, CBackend::Clang, CBackend::Tcc, CBackend::Zig, CBackend::Msvc];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CBackend::Gcc => "gcc",
+            CBackend::Clang => "clang",
+            CBackend::Tcc => "tcc",
+            CBackend::Zig => "zig",
+            CBackend::Msvc => "msvc",
+        }
+    }
+
+    /// The command run when no compiler is named
+    pub fn default_command(self) -> &'static str {
+        match self {
+            CBackend::Msvc => "clang-cl",
+            backend => backend.name(),
+        }
+    }
+
+    /// Flags used unless `--cflags` or `ZC_CFLAGS` says otherwise. They
+    /// depend on nothing about the machine building, so every machine builds
+    /// the same executable; `CCompiler::native_cpu` and `CCompiler::lto` add
+    /// the rest.
+    fn default_flags(self) -> &'static [&'static str] {
+        match self {
+            CBackend::Gcc | CBackend::Clang | CBackend::Zig => &["-O3"],
+            // TinyCC ignores optimization levels
+            CBackend::Tcc => &[],
+            CBackend::Msvc => &["/nologo", "/O2"],
+        }
+    }
+
+    /// Whether the compiler can stop at assembly with `-S`
+    fn emits_assembly(self) -> bool {
+        matches!(self, CBackend::Gcc | CBackend::Clang | CBackend::Zig)
+    }
+
+    /// Whether the compiler can tune for the CPU, optimize at link time and
+    /// rewrite the paths it records in debug information with GCC's flags;
+    /// TinyCC and `clang-cl` cannot
+    pub fn optimizes_fully(self) -> bool {
+        matches!(self, CBackend::Gcc | CBackend::Clang | CBackend::Zig)
+    }
+
+    /// The extension of the object files the compiler writes
+    pub fn object_extension(self) -> &'static str {
+        match self {
+            CBackend::Msvc => "obj",
+            _ => "o",
+        }
+    }
+
+    /// The file name of a static library called `name`
+    pub fn archive_name(self, name: &str) -> String {
+        match self {
+            CBackend::Msvc => format!("{}.lib", name),
+            _ => format!("lib{}.a", name),
+        }
+    }
+
+    /// The backend a compiler command is, judged by its file name; unknown
+    /// compilers are assumed to take GCC's flags
+    fn of_command(command: &str) -> CBackend {
+        let name = Path::new(command)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name == "cl" || name == "clang-cl" {
+            return CBackend::Msvc;
+        }
+        CBackend::ALL
+            .into_iter()
+            .find(|backend| name == backend.name())
+            .or_else(|| name.contains("clang").then_some(CBackend::Clang))
+            .unwrap_or(CBackend::Gcc)
+    }
+}
+
+#[cfg(feature = "c")]
+impl std::str::FromStr for CBackend {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        CBackend::ALL
+            .into_iter()
+            .find(|backend| backend.name() == name)
+            .ok_or_else(|| format!("unknown backend {}, expected one of gcc, clang, tcc, zig, msvc", name))
+    }
+}
+
+/// What the link step produces
+#[cfg(feature = "c")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkKind {
+    #[default]
+    Executable,
+    /// An executable with the C library and every other library built in
+    Static,
+    /// A shared library, whose functions C code can call
+    Shared,
+}
+
+/// Options for the link step
+#[cfg(feature = "c")]
+#[derive(Debug, Clone, Default)]
+pub struct LinkOptions {
+    pub kind: LinkKind,
+    /// Directories searched for libraries, as with `-L`
+    pub search_paths: Vec<std::path::PathBuf>,
+    /// Libraries linked in, as with `-l`
+    pub libraries: Vec<String>,
+    /// Object files linked in, such as the program's imported modules
+    pub objects: Vec<PathBuf>,
+}
+
+/// The C compiler generated code is built with, and its flags
+#[cfg(feature = "c")]
+#[derive(Debug, Clone)]
+pub struct CCompiler {
+    pub backend: CBackend,
+    pub command: String,
+    pub flags: Vec<String>,
+    /// Tune the code for the CPU building it, which other CPUs may not run
+    pub native_cpu: bool,
+    /// Optimize across object files at link time
+    pub lto: bool,
+    /// The target triple to build for, instead of the machine building
+    pub target: Option<String>,
+    /// Generate debug information, for debuggers to show the C
+    pub debug_info: bool,
+    pub link: LinkOptions,
+}
+
+#[cfg(feature = "c")]
+impl CCompiler {
+    /// Resolves the compiler from `cc`, then `ZC_CC`, then `backend`, then
+    /// the first backend installed, and the flags from `cflags`, then
+    /// `ZC_CFLAGS`, then the backend's defaults. Flags are separated by
+    /// whitespace.
+    pub fn resolve(backend: Option<CBackend>, cc: Option<&str>, cflags: Option<&str>) -> Result<Self> {
+        let (backend, command) = match cc.map(str::to_string).or_else(|| std::env::var("ZC_CC").ok()) {
+            Some(command) => (backend.unwrap_or_else(|| CBackend::of_command(&command)), command),
+            None => {
+                let backend = match backend {
+                    Some(backend) => backend,
+                    None => CBackend::ALL
+                        .into_iter()
+                        .find(|backend| {
+                            Command::new(backend.default_command())
+                                .arg("--version")
+                                .stdout(Stdio::null())
+                                .stderr(Stdio::null())
+                                .status()
+                                .is_ok()
+                        })
+                        .ok_or_else(|| CodegenError {
+                            message: "No C compiler found. Please install GCC, Clang, TinyCC, Zig or clang-cl.".to_string(),
+                            span: None,
+                        })?,
+                };
+                (backend, backend.default_command().to_string())
+            }
+        };
+        if Path::new(&command).file_stem().is_some_and(|stem| stem == "cl") {
+            return Err(CodegenError {
+                message: "cl cannot build the generated C, which uses GNU extensions. Please use clang-cl, which takes the same flags.".to_string(),
+                span: None,
+            });
+        }
+        let flags = match cflags.map(str::to_string).or_else(|| std::env::var("ZC_CFLAGS").ok()) {
+            Some(flags) => flags.split_whitespace().map(str::to_string).collect(),
+            None => backend.default_flags().iter().map(|flag| flag.to_string()).collect(),
+        };
+        Ok(CCompiler {
+            backend,
+            command,
+            flags,
+            native_cpu: false,
+            lto: false,
+            target: None,
+            debug_info: false,
+            link: LinkOptions::default(),
+        })
+    }
+
+    /// The backend run as its default command with its default flags,
+    /// whether or not it is installed
+    pub fn of_backend(backend: CBackend) -> Self {
+        CCompiler {
+            backend,
+            command: backend.default_command().to_string(),
+            flags: backend.default_flags().iter().map(|flag| flag.to_string()).collect(),
+            native_cpu: false,
+            lto: false,
+            target: None,
+            debug_info: false,
+            link: LinkOptions::default(),
+        }
+    }
+
+    /// The first line the compiler prints for `--version`, or none when it
+    /// cannot be run
+    pub fn version(&self) -> Option<String> {
+        let output = self.base_command().arg("--version").stderr(Stdio::null()).output().ok()?;
+        let text = String::from_utf8_lossy(&output.stdout);
+        text.lines().map(str::trim).find(|line| !line.is_empty()).map(str::to_string)
+    }
+
+    /// The target triple the compiler builds for, as it names it
+    pub fn target_triple(&self) -> Option<String> {
+        if let Some(target) = &self.target {
+            return Some(target.clone());
+        }
+        let output = self.base_command().arg("-dumpmachine").stderr(Stdio::null()).output().ok()?;
+        let triple = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !triple.is_empty()).then_some(triple)
+    }
+
+    /// Whether the compiler builds `code` into an executable, its own output
+    /// kept quiet, to find out what it supports
+    pub fn can_build(&self, code: &str) -> bool {
+        let Ok(dir) = tempfile::Builder::new().prefix("zc-probe-").tempdir() else {
+            return false;
+        };
+        let c_path = dir.path().join("probe.c");
+        let object_path = dir.path().join("probe").with_extension(self.backend.object_extension());
+        let output_path = dir.path().join("probe").with_extension(std::env::consts::EXE_EXTENSION);
+        let succeeds = |command: &mut Command| {
+            command.stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok_and(|status| status.success())
+        };
+        fs::write(&c_path, code).is_ok()
+            && succeeds(self.command().args(self.compile_args(&c_path, &object_path)))
+            && succeeds(self.command().args(self.link_args(&[object_path.as_path()], &output_path)))
+    }
+
+    /// Builds for `target`, a target triple such as `aarch64-linux-gnu`.
+    /// Clang, `clang-cl` and Zig take it as a flag. GCC builds for a single
+    /// target, so the cross compiler named after it, as in
+    /// `aarch64-linux-gnu-gcc`, runs instead of `gcc`.
+    pub fn set_target(&mut self, target: &str) -> Result<()> {
+        match self.backend {
+            CBackend::Tcc => {
+                return Err(CodegenError {
+                    message: format!("The tcc backend cannot build for {}, only for the machine building", target),
+                    span: None,
+                })
+            }
+            CBackend::Gcc if self.command == CBackend::Gcc.default_command() => {
+                self.command = format!("{}-gcc", target);
+            }
+            _ => {}
+        }
+        self.target = Some(target.to_string());
+        Ok(())
+    }
+
+    /// Adds the libraries named by `@link` on the program's extern functions
+    pub fn link_externs(&mut self, program: &Program) {
+        for stmt in &program.statements {
+            if let Stmt::Extern(_, _, _, Some(library), _) = stmt {
+                if !self.link.libraries.contains(library) {
+                    self.link.libraries.push(library.clone());
+                }
+            }
+        }
+    }
+
+    /// Links the collector `gc` needs, if any
+    pub fn link_gc(&mut self, gc: Gc) {
+        if gc == Gc::Boehm && !self.link.libraries.iter().any(|library| library == "gc") {
+            self.link.libraries.push("gc".to_string());
+        }
+    }
+
+    /// The compiler without link-time optimization, for object code that is
+    /// not linked by this compiler
+    pub fn without_lto(&self) -> CCompiler {
+        let mut compiler = self.clone();
+        compiler.lto = false;
+        compiler.flags.retain(|flag| !flag.starts_with("-flto"));
+        compiler
+    }
+
+    /// A command running the compiler without its flags
+    fn base_command(&self) -> Command {
+        let mut command = Command::new(&self.command);
+        if self.backend == CBackend::Zig {
+            command.arg("cc");
+        }
+        command
+    }
+
+    /// A command running the compiler with its flags
+    pub fn command(&self) -> Command {
+        let mut command = self.base_command();
+        command.args(&self.flags);
+        if self.native_cpu && self.backend.optimizes_fully() {
+            command.arg("-march=native");
+        }
+        if self.lto && self.backend.optimizes_fully() {
+            command.arg("-flto");
+        }
+        if self.debug_info {
+            command.arg(if self.backend == CBackend::Msvc { "/Z7" } else { "-g" });
+        }
+        match (&self.target, self.backend) {
+            (Some(target), CBackend::Clang | CBackend::Msvc) => {
+                command.arg(format!("--target={}", target));
+            }
+            (Some(target), CBackend::Zig) => {
+                command.arg("-target").arg(target);
+            }
+            _ => {}
+        }
+        command
+    }
+
+    /// A command compiling sources from `dir`, a temporary directory when
+    /// given. Its name differs in every build, so it is kept out of debug
+    /// information.
+    fn command_in(&self, dir: Option<&Path>) -> Command {
+        let mut command = self.command();
+        if let (Some(dir), true) = (dir, self.backend.optimizes_fully()) {
+            command.arg(format!("-ffile-prefix-map={}=.", dir.display()));
+        }
+        command
+    }
+
+    /// Arguments compiling `c_path` to the object file `object_path` without
+    /// linking, and making position-independent code for shared libraries
+    fn compile_args(&self, c_path: &Path, object_path: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+        if self.backend == CBackend::Msvc {
+            // DLLs need no position-independent code
+            args.push("/c".into());
+            args.push(c_path.into());
+            let mut output = OsString::from("/Fo");
+            output.push(object_path);
+            args.push(output);
+        } else {
+            if self.link.kind == LinkKind::Shared {
+                args.push("-fPIC".into());
+            }
+            args.push("-c".into());
+            args.push(c_path.into());
+            args.push("-o".into());
+            args.push(object_path.into());
+        }
+        args
+    }
+
+    /// Arguments linking `objects` into `output_path`, as `link` says
+    fn link_args(&self, objects: &[&Path], output_path: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+        if self.backend == CBackend::Msvc {
+            // The static C runtime is MSVC's default, so static executables
+            // need no flag
+            if self.link.kind == LinkKind::Shared {
+                args.push("/LD".into());
+            }
+            args.extend(objects.iter().map(|object| object.as_os_str().to_owned()));
+            let mut output = OsString::from("/Fe");
+            output.push(output_path);
+            args.push(output);
+            // The math functions are part of the C runtime, which is linked
+            // anyway
+            args.extend(self.link.libraries.iter().map(|library| format!("{}.lib", library).into()));
+            if !self.link.search_paths.is_empty() {
+                args.push("/link".into());
+                for path in &self.link.search_paths {
+                    let mut arg = OsString::from("/LIBPATH:");
+                    arg.push(path);
+                    args.push(arg);
+                }
+            }
+        } else {
+            match self.link.kind {
+                LinkKind::Executable => {}
+                LinkKind::Static => args.push("-static".into()),
+                LinkKind::Shared => args.push("-shared".into()),
+            }
+            for path in &self.link.search_paths {
+                args.push("-L".into());
+                args.push(path.into());
+            }
+            args.extend(objects.iter().map(|object| object.as_os_str().to_owned()));
+            args.push("-o".into());
+            args.push(output_path.into());
+            args.extend(self.link.libraries.iter().map(|library| format!("-l{}", library).into()));
+            args.push("-lm".into());
+        }
+        args
+    }
+}
+
+/// Compiles C code to an executable
+#[cfg(feature = "c")]
+pub fn generate_executable(code: &str, output_path: &Path, keep_temps: bool, compiler: &CCompiler) -> Result<()> {
+    let temps = TempFiles::new(output_path, keep_temps)?;
+    let c_path = temps.path("c");
+    let object_path = temps.path(compiler.backend.object_extension());
+    write_c_source(code, &c_path)?;
+    
+    let status = compiler
+        .command_in(temps.dir())
+        .args(compiler.compile_args(&c_path, &object_path))
+        .status()
+        .map_err(|e| CodegenError {
+            message: format!("Failed to execute {}: {}", compiler.command, e),
+            span: None,
+        })?;
+    
+    if !status.success() {
+        return Err(CodegenError {
+            message: format!("{} compilation failed", compiler.command),
+            span: None,
+        });
+    }
+    
+    // Link the object file with the imported modules'
+    let objects: Vec<&Path> = [object_path.as_path()]
+        .into_iter()
+        .chain(compiler.link.objects.iter().map(PathBuf::as_path))
+        .collect();
+    let status = compiler
+        .command()
+        .args(compiler.link_args(&objects, output_path))
+        .status()
+        .map_err(|e| CodegenError {
+            message: format!("Failed to link: {}", e),
+            span: None,
+        })?;
+    
+    if !status.success() {
+        return Err(CodegenError {
+            message: "Linking failed".to_string(),
+            span: None,
+        });
+    }
+    
+    Ok(())
+}
+
+/// Links `object`, object code generated without C, into the executable
+/// `output_path`, with the objects and libraries `compiler` links
+#[cfg(feature = "llvm")]
+pub fn link_object(object: &[u8], output_path: &Path, compiler: &CCompiler) -> Result<()> {
+    let temps = TempFiles::new(output_path, false)?;
+    let object_path = temps.path(compiler.backend.object_extension());
+    fs::write(&object_path, object).map_err(|e| CodegenError {
+        message: format!("Failed to write {}: {}", object_path.display(), e),
+        span: None,
+    })?;
+
+    let objects: Vec<&Path> = [object_path.as_path()]
+        .into_iter()
+        .chain(compiler.link.objects.iter().map(PathBuf::as_path))
+        .collect();
+    let status = compiler
+        .command()
+        .args(compiler.link_args(&objects, output_path))
+        .status()
+        .map_err(|e| CodegenError {
+            message: format!("Failed to link: {}", e),
+            span: None,
+        })?;
+
+    if !status.success() {
+        return Err(CodegenError {
             message: "Linking failed".to_string(),
+            span: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// Compiles C code to the object file `object_path`, which appears only once
+/// it is complete, so a concurrent build never links half of one
+#[cfg(feature = "c")]
+pub fn generate_object(code: &str, object_path: &Path, compiler: &CCompiler) -> Result<()> {
+    // Built in a directory next to the object, from which it is renamed
+    let parent = object_path.parent().unwrap_or_else(|| Path::new("."));
+    let dir = tempfile::Builder::new().prefix("zc-").tempdir_in(parent).map_err(|e| CodegenError {
+        message: format!("Failed to create a temporary directory: {}", e),
+        span: None,
+    })?;
+    let c_path = dir.path().join("z_module.c");
+    let temp_object = dir.path().join("z_module").with_extension(compiler.backend.object_extension());
+    write_c_source(code, &c_path)?;
+
+    let status = compiler
+        .command_in(Some(dir.path()))
+        .args(compiler.compile_args(&c_path, &temp_object))
+        .status()
+        .map_err(|e| CodegenError {
+            message: format!("Failed to execute {}: {}", compiler.command, e),
+            span: None,
+        })?;
+
+    if !status.success() {
+        return Err(CodegenError {
+            message: format!("{} compilation failed", compiler.command),
+            span: None,
+        });
+    }
+
+    fs::rename(&temp_object, object_path).map_err(|e| CodegenError {
+        message: format!("Failed to write {}: {}", object_path.display(), e),
+        span: None,
+    })
+}
+
+/// Compiles C code to a static library archive. Link-time optimization is
+/// left out, since programs linking the archive may not use it.
+#[cfg(feature = "c")]
+pub fn generate_archive(code: &str, output_path: &Path, keep_temps: bool, compiler: &CCompiler) -> Result<()> {
+    let compiler = compiler.without_lto();
+    let temps = TempFiles::new(output_path, keep_temps)?;
+    let c_path = temps.path("c");
+    let object_path = temps.path(compiler.backend.object_extension());
+    write_c_source(code, &c_path)?;
+    
+    let status = compiler
+        .command_in(temps.dir())
+        .args(compiler.compile_args(&c_path, &object_path))
+        .status()
+        .map_err(|e| CodegenError {
+            message: format!("Failed to execute {}: {}", compiler.command, e),
+            span: None,
+        })?;
+    
+    if !status.success() {
+        return Err(CodegenError {
+            message: format!("{} compilation failed", compiler.command),
+            span: None,
         });
     }
     
-    // Clean up temporary files
-    let _ = fs::remove_file(c_path);
-    let _ = fs::remove_file(temp_dir.join("z_program.o"));
+    // `ar` replaces an existing archive's members rather than the archive.
+    // `D` leaves out timestamps and owners, so the archive is reproducible.
+    let _ = fs::remove_file(output_path);
+    let mut command = if compiler.backend == CBackend::Msvc {
+        let mut command = Command::new("llvm-lib");
+        let mut output = OsString::from("/OUT:");
+        output.push(output_path);
+        command.arg("/NOLOGO").arg(output);
+        command
+    } else {
+        let mut command = Command::new("ar");
+        command.arg("rcsD").arg(output_path);
+        command
+    };
+    let status = command
+        .arg(&object_path)
+        .args(&compiler.link.objects)
+        .status()
+        .map_err(|e| CodegenError {
+            message: format!("Failed to create the archive: {}", e),
+            span: None,
+        })?;
+    
+    if !status.success() {
+        return Err(CodegenError {
+            message: "Creating the archive failed".to_string(),
+            span: None,
+        });
+    }
     
     Ok(())
+}
+
+/// Compiles C code to assembly only, returning it. The intermediate files are
+/// named after the output. Link-time optimization is left out, since with it
+/// the compiler writes its intermediate representation instead of machine code.
+#[cfg(feature = "c")]
+pub fn generate_assembly(code: &str, output_path: &Path, keep_temps: bool, compiler: &CCompiler) -> Result<String> {
+    if !compiler.backend.emits_assembly() {
+        return Err(CodegenError {
+            message: format!("The {} backend cannot generate assembly", compiler.backend.name()),
+            span: None,
+        });
+    }
+    
+    let temps = TempFiles::new(output_path, keep_temps)?;
+    let c_path = temps.path("c");
+    let asm_path = temps.path("s");
+    write_c_source(code, &c_path)?;
+    
+    let status = compiler
+        .without_lto()
+        .command_in(temps.dir())
+        .arg("-S")                 // Stop after generating assembly
+        .arg(&c_path)
+        .arg("-o")
+        .arg(&asm_path)
+        .status()
+        .map_err(|e| CodegenError {
+            message: format!("Failed to execute {}: {}", compiler.command, e),
+            span: None,
+        })?;
+    
+    if !status.success() {
+        return Err(CodegenError {
+            message: format!("{} compilation failed", compiler.command),
+            span: None,
+        });
+    }
+    
+    fs::read_to_string(&asm_path).map_err(|e| CodegenError {
+        message: format!("Failed to read the assembly: {}", e),
+        span: None,
+    })
 }
\ No newline at end of file
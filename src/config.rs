@@ -0,0 +1,142 @@
+// The compiler configuration file, `zc.toml` or `.zconfig`: defaults for
+// the command line, so that everyone working on a project builds it the
+// same way without long command lines. The nearest one in the current
+// directory or above it is read:
+//
+//     opt-level = 2
+//     backend = "clang"
+//     cc = "/opt/llvm/bin/clang"
+//     cflags = "-O2 -g"
+//     target = "aarch64-linux-gnu"
+//
+//     [warnings]
+//     W0001 = "deny"
+//     W0002 = "allow"
+//
+//     [lint]
+//     max-function-lines = 80
+//
+// Flags on the command line override it, as do `ZC_CC` and `ZC_CFLAGS`, and
+// it overrides the `opt-level` and `[lint]` of `z.toml`.
+
+#[cfg(feature = "c")]
+use crate::codegen::CBackend;
+use crate::lint::{Level, LintConfig};
+use crate::project::{check_keys, read_lint, string};
+use crate::{explain, CompilerError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// The names of the configuration file, in the order they are looked for in
+/// a directory
+pub const CONFIG_FILES: [&str; 2] = ["zc.toml", ".zconfig"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// The file it was read from, or none for the defaults
+    pub path: Option<PathBuf>,
+    pub opt_level: Option<u8>,
+    #[cfg(feature = "c")]
+    pub backend: Option<CBackend>,
+    pub cc: Option<String>,
+    pub cflags: Option<String>,
+    /// The target triple the C compiler builds for
+    pub target: Option<String>,
+    /// How the warnings with these codes are reported
+    pub warnings: Vec<(String, Level)>,
+    // The `[lint]` table, checked when the file is read
+    lint: Table,
+}
+
+impl Config {
+    /// The configuration in `dir` or the nearest directory above it, or the
+    /// defaults when there is none
+    pub fn find(dir: &Path) -> Result<Config> {
+        let mut dir = dir;
+        loop {
+            if let Some(path) = CONFIG_FILES.iter().map(|name| dir.join(name)).find(|path| path.is_file()) {
+                return Config::load(&path);
+            }
+            match dir.parent() {
+                Some(parent) => dir = parent,
+                None => return Ok(Config::default()),
+            }
+        }
+    }
+
+    /// The configuration in the file `path`
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| CompilerError::ConfigError(format!("Cannot read {}: {}", path.display(), e)))?;
+        let mut config = Config::parse(&text)
+            .map_err(|message| CompilerError::ConfigError(format!("{}: {}", path.display(), message)))?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Reads the text of a configuration file
+    pub fn parse(text: &str) -> std::result::Result<Config, String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        check_keys(
+            &table,
+            "the configuration",
+            &["opt-level", "backend", "cc", "cflags", "target", "warnings", "lint"],
+        )?;
+
+        let opt_level = match table.get("opt-level") {
+            Some(Value::Integer(level @ 0..=3)) => Some(*level as u8),
+            Some(_) => return Err("opt-level must be a number from 0 to 3".to_string()),
+            None => None,
+        };
+        #[cfg(feature = "c")]
+        let backend = string(&table, "backend")?.map(str::parse::<CBackend>).transpose()?;
+
+        let mut warnings = Vec::new();
+        match table.get("warnings") {
+            Some(Value::Table(table)) => {
+                for (code, level) in table {
+                    if !code.starts_with('W') || explain(code).is_none() {
+                        return Err(format!("{} in [warnings] is not a warning code; zc explain lists them", code));
+                    }
+                    let level = match level {
+                        Value::String(level) => level.parse::<Level>().map_err(|e| format!("{}: {}", code, e))?,
+                        _ => return Err(format!("{} must be a string", code)),
+                    };
+                    warnings.push((code.clone(), level));
+                }
+            }
+            Some(_) => return Err("warnings must be a table".to_string()),
+            None => {}
+        }
+        let lint = match table.get("lint") {
+            Some(Value::Table(lint)) => {
+                read_lint(lint, &mut LintConfig::default())?;
+                lint.clone()
+            }
+            Some(_) => return Err("lint must be a table".to_string()),
+            None => Table::new(),
+        };
+
+        Ok(Config {
+            path: None,
+            opt_level,
+            #[cfg(feature = "c")]
+            backend,
+            cc: string(&table, "cc")?.map(str::to_string),
+            cflags: string(&table, "cflags")?.map(str::to_string),
+            target: string(&table, "target")?.map(str::to_string),
+            warnings,
+            lint,
+        })
+    }
+
+    /// The rules of `zc lint`: those of `base`, usually from `z.toml`, with
+    /// the ones the configuration sets replaced
+    pub fn lint(&self, base: &LintConfig) -> LintConfig {
+        let mut config = base.clone();
+        // The table was checked when the file was read
+        let _ = read_lint(&self.lint, &mut config);
+        config
+    }
+}
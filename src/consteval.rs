@@ -0,0 +1,145 @@
+// Compile-time evaluation of constant expressions
+
+use crate::ast::{BinaryOp, Expr, Literal, Span, Type, UnaryOp};
+use std::collections::HashMap;
+
+/// The value of an expression that can be computed at compile time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl Constant {
+    /// The literal expression with this value
+    pub fn to_expr(self, span: Span) -> Expr {
+        let literal = match self {
+            Constant::Int(i) => Literal::Int(i as i128),
+            Constant::Float(f) => Literal::Float(f),
+            Constant::Bool(b) => Literal::Bool(b),
+        };
+        Expr::Literal(literal, span)
+    }
+}
+
+#[derive(Debug)]
+pub struct ConstError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for ConstError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Constant evaluation error at position {}: {}", self.span.start, self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, ConstError>;
+
+fn error<T>(message: &str, span: &Span) -> Result<T> {
+    Err(ConstError {
+        message: message.to_string(),
+        span: span.clone(),
+    })
+}
+
+/// Evaluates `expr` if it only involves literals, the named `constants`,
+/// arithmetic, comparisons, logic and numeric casts. Returns `Ok(None)` for
+/// expressions that are not constant, and an error for constant expressions
+/// whose evaluation fails, such as integer overflow or division by zero.
+pub fn evaluate(expr: &Expr, constants: &HashMap<String, Constant>) -> Result<Option<Constant>> {
+    let value = match expr {
+        // A literal beyond `int` is left to be checked as the type it is used as
+        Expr::Literal(Literal::Int(i), _) => match i64::try_from(*i) {
+            Ok(i) => Constant::Int(i),
+            Err(_) => return Ok(None),
+        },
+        Expr::Literal(Literal::Float(f), _) => Constant::Float(*f),
+        Expr::Literal(Literal::Bool(b), _) => Constant::Bool(*b),
+        Expr::Identifier(name, _) => match constants.get(name) {
+            Some(value) => *value,
+            None => return Ok(None),
+        },
+        Expr::Unary(op, operand, span) => {
+            let operand = match evaluate(operand, constants)? {
+                Some(operand) => operand,
+                None => return Ok(None),
+            };
+            match (op, operand) {
+                (UnaryOp::Neg, Constant::Int(i)) => match i.checked_neg() {
+                    Some(i) => Constant::Int(i),
+                    None => return error("Integer overflow in constant expression", span),
+                },
+                (UnaryOp::Neg, Constant::Float(f)) => Constant::Float(-f),
+                (UnaryOp::Not, Constant::Bool(b)) => Constant::Bool(!b),
+                _ => return Ok(None),
+            }
+        }
+        Expr::Binary(left, op, right, span) => {
+            let (left, right) = match (evaluate(left, constants)?, evaluate(right, constants)?) {
+                (Some(left), Some(right)) => (left, right),
+                _ => return Ok(None),
+            };
+            match binary(op, left, right, span)? {
+                Some(value) => value,
+                None => return Ok(None),
+            }
+        }
+        Expr::Cast(operand, target, _) => {
+            let operand = match evaluate(operand, constants)? {
+                Some(operand) => operand,
+                None => return Ok(None),
+            };
+            match (operand, target) {
+                (Constant::Int(i), Type::Float) => Constant::Float(i as f64),
+                (Constant::Float(f), Type::Int) => Constant::Int(f as i64),
+                (Constant::Int(i), Type::Bool) => Constant::Bool(i != 0),
+                (Constant::Bool(b), Type::Int) => Constant::Int(b as i64),
+                (Constant::Int(_), Type::Int) | (Constant::Float(_), Type::Float) | (Constant::Bool(_), Type::Bool) => operand,
+                // Sized integer casts wrap, which is left to the backend
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+fn binary(op: &BinaryOp, left: Constant, right: Constant, span: &Span) -> Result<Option<Constant>> {
+    use Constant::{Bool, Float, Int};
+
+    let checked = |value: Option<i64>| match value {
+        Some(value) => Ok(Int(value)),
+        None => error("Integer overflow in constant expression", span),
+    };
+    let value = match (op, left, right) {
+        (BinaryOp::Add, Int(l), Int(r)) => checked(l.checked_add(r))?,
+        (BinaryOp::Sub, Int(l), Int(r)) => checked(l.checked_sub(r))?,
+        (BinaryOp::Mul, Int(l), Int(r)) => checked(l.checked_mul(r))?,
+        (BinaryOp::Div | BinaryOp::Mod, Int(_), Int(0)) => {
+            return error("Division by zero in constant expression", span);
+        }
+        (BinaryOp::Div, Int(l), Int(r)) => checked(l.checked_div(r))?,
+        (BinaryOp::Mod, Int(l), Int(r)) => checked(l.checked_rem(r))?,
+        (BinaryOp::Add, Float(l), Float(r)) => Float(l + r),
+        (BinaryOp::Sub, Float(l), Float(r)) => Float(l - r),
+        (BinaryOp::Mul, Float(l), Float(r)) => Float(l * r),
+        (BinaryOp::Div, Float(l), Float(r)) => Float(l / r),
+        (BinaryOp::Mod, Float(l), Float(r)) => Float(l % r),
+        (BinaryOp::Lt, Int(l), Int(r)) => Bool(l < r),
+        (BinaryOp::Lte, Int(l), Int(r)) => Bool(l <= r),
+        (BinaryOp::Gt, Int(l), Int(r)) => Bool(l > r),
+        (BinaryOp::Gte, Int(l), Int(r)) => Bool(l >= r),
+        (BinaryOp::Lt, Float(l), Float(r)) => Bool(l < r),
+        (BinaryOp::Lte, Float(l), Float(r)) => Bool(l <= r),
+        (BinaryOp::Gt, Float(l), Float(r)) => Bool(l > r),
+        (BinaryOp::Gte, Float(l), Float(r)) => Bool(l >= r),
+        (BinaryOp::Eq, l, r) => Bool(l == r),
+        (BinaryOp::Neq, l, r) => Bool(l != r),
+        (BinaryOp::And, Bool(l), Bool(r)) => Bool(l && r),
+        (BinaryOp::Or, Bool(l), Bool(r)) => Bool(l || r),
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
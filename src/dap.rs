@@ -0,0 +1,411 @@
+// Debug Adapter Protocol server that steps Z programs in the interpreter
+
+use crate::ast::{line_of, line_starts, Program, Span};
+use crate::interpreter::{DebugHook, Interpreter, StackFrame};
+use crate::{lexer, parser, typechecker, CompilerError};
+use serde_json::{json, Value as Json};
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The interpreter runs on a single thread, which DAP clients see as thread 1
+const THREAD_ID: i64 = 1;
+
+/// How the debuggee should proceed after being resumed
+#[derive(Debug, Clone, Copy)]
+enum Resume {
+    Continue,
+    StepIn,
+    /// Stop at the next statement at or above this call depth
+    StepOver(usize),
+    /// Stop at the next statement above this call depth
+    StepOut(usize),
+}
+
+/// Messages delivered to the server's main loop
+enum Event {
+    Request(Json),
+    Stopped(Vec<StackFrame>, &'static str),
+    Output(String),
+    Terminated(Option<String>),
+    ClientClosed,
+}
+
+/// Breakpoints shared between the protocol thread and the debuggee
+type Breakpoints = Arc<Mutex<HashSet<usize>>>;
+
+struct SteppingHook {
+    line_starts: Vec<usize>,
+    breakpoints: Breakpoints,
+    resume: Resume,
+    last_line: Option<(usize, usize)>,
+    events: Sender<Event>,
+    commands: Receiver<Resume>,
+    stop_reason: &'static str,
+}
+
+impl DebugHook for SteppingHook {
+    fn should_stop(&mut self, span: &Span, depth: usize) -> bool {
+        let line = line_of(&self.line_starts, span.start);
+
+        // Several statements can share a line; only stop on the first of them
+        if self.last_line == Some((line, depth)) {
+            return false;
+        }
+        self.last_line = Some((line, depth));
+
+        let stepping = match self.resume {
+            Resume::Continue => false,
+            Resume::StepIn => true,
+            Resume::StepOver(from) => depth <= from,
+            Resume::StepOut(from) => depth < from,
+        };
+        let on_breakpoint = self
+            .breakpoints
+            .lock()
+            .map(|breakpoints| breakpoints.contains(&line))
+            .unwrap_or(false);
+
+        if stepping {
+            self.stop_reason = "step";
+        } else if on_breakpoint {
+            self.stop_reason = "breakpoint";
+        }
+        stepping || on_breakpoint
+    }
+
+    fn stopped(&mut self, frames: Vec<StackFrame>) {
+        let depth = frames.len().saturating_sub(1);
+        let reason = std::mem::replace(&mut self.stop_reason, "step");
+        if self.events.send(Event::Stopped(frames, reason)).is_err() {
+            return;
+        }
+
+        // Block until the client asks us to resume; a closed channel means
+        // the session ended, so run to completion
+        self.resume = match self.commands.recv() {
+            Ok(Resume::StepOver(_)) => Resume::StepOver(depth),
+            Ok(Resume::StepOut(_)) => Resume::StepOut(depth),
+            Ok(resume) => resume,
+            Err(_) => Resume::Continue,
+        };
+    }
+
+    fn output(&mut self, text: &str) {
+        let _ = self.events.send(Event::Output(text.to_string()));
+    }
+}
+
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+struct Server<W: Write> {
+    writer: W,
+    seq: i64,
+    events: Sender<Event>,
+    program: Option<(PathBuf, Vec<usize>, Program)>,
+    stop_on_entry: bool,
+    breakpoints: Breakpoints,
+    frames: Vec<StackFrame>,
+    commands: Option<Sender<Resume>>,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.writer.flush()
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn respond_error(&mut self, request: &Json, message: String) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    /// Handles one request, returning false once the session should end
+    fn handle_request(&mut self, request: Json) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let args = request["arguments"].clone();
+
+        match command.as_str() {
+            "initialize" => {
+                self.respond(&request, json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsTerminateRequest": true,
+                }))?;
+                self.event("initialized", json!({}))?;
+            }
+            "launch" => {
+                let path = PathBuf::from(args["program"].as_str().unwrap_or_default());
+                match load_program(&path) {
+                    Ok((line_starts, program)) => {
+                        self.program = Some((path, line_starts, program));
+                        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                        self.respond(&request, json!({}))?;
+                    }
+                    Err(message) => self.respond_error(&request, message)?,
+                }
+            }
+            "setBreakpoints" => {
+                let lines: Vec<i64> = args["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints.iter().filter_map(|bp| bp["line"].as_i64()).collect()
+                    })
+                    .unwrap_or_default();
+                if let Ok(mut breakpoints) = self.breakpoints.lock() {
+                    breakpoints.clear();
+                    breakpoints.extend(lines.iter().map(|line| (*line as usize).saturating_sub(1)));
+                }
+                let verified: Vec<Json> = lines
+                    .iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect();
+                self.respond(&request, json!({ "breakpoints": verified }))?;
+            }
+            "configurationDone" => {
+                self.respond(&request, json!({}))?;
+                self.start();
+            }
+            "threads" => {
+                self.respond(&request, json!({
+                    "threads": [{ "id": THREAD_ID, "name": "main" }],
+                }))?;
+            }
+            "stackTrace" => {
+                let (path, line_starts) = match &self.program {
+                    Some((path, line_starts, _)) => (path.clone(), line_starts.clone()),
+                    None => (PathBuf::new(), Vec::new()),
+                };
+                let frames: Vec<Json> = self
+                    .frames
+                    .iter()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        let line = line_of(&line_starts, frame.span.start);
+                        let column = frame.span.start - line_starts.get(line).copied().unwrap_or(0);
+                        json!({
+                            "id": id,
+                            "name": frame.function,
+                            "source": { "path": path.display().to_string() },
+                            "line": line + 1,
+                            "column": column + 1,
+                        })
+                    })
+                    .collect();
+                let total = frames.len();
+                self.respond(&request, json!({ "stackFrames": frames, "totalFrames": total }))?;
+            }
+            "scopes" => {
+                // Variable references are frame ids offset by one, since 0 means "none"
+                let frame_id = args["frameId"].as_i64().unwrap_or(0);
+                self.respond(&request, json!({
+                    "scopes": [{
+                        "name": "Locals",
+                        "variablesReference": frame_id + 1,
+                        "expensive": false,
+                    }],
+                }))?;
+            }
+            "variables" => {
+                let reference = args["variablesReference"].as_i64().unwrap_or(0);
+                let variables: Vec<Json> = usize::try_from(reference - 1)
+                    .ok()
+                    .and_then(|frame_id| self.frames.get(frame_id))
+                    .map(|frame| {
+                        frame
+                            .variables
+                            .iter()
+                            .map(|(name, value)| json!({
+                                "name": name,
+                                "value": value.to_string(),
+                                "type": value.type_of().to_string(),
+                                "variablesReference": 0,
+                            }))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                self.respond(&request, json!({ "variables": variables }))?;
+            }
+            "continue" | "next" | "stepIn" | "stepOut" => {
+                let resume = match command.as_str() {
+                    "continue" => Resume::Continue,
+                    "next" => Resume::StepOver(0),
+                    "stepIn" => Resume::StepIn,
+                    _ => Resume::StepOut(0),
+                };
+                self.frames.clear();
+                if let Some(commands) = &self.commands {
+                    let _ = commands.send(resume);
+                }
+                let body = if command == "continue" {
+                    json!({ "allThreadsContinued": true })
+                } else {
+                    json!({})
+                };
+                self.respond(&request, body)?;
+            }
+            "disconnect" | "terminate" => {
+                self.respond(&request, json!({}))?;
+                return Ok(false);
+            }
+            _ => {
+                self.respond_error(&request, format!("Unsupported request: {}", command))?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Starts executing the launched program on a background thread
+    fn start(&mut self) {
+        let (_, line_starts, program) = match &self.program {
+            Some(launched) => launched.clone(),
+            None => return,
+        };
+        let (command_tx, command_rx) = mpsc::channel();
+        self.commands = Some(command_tx);
+
+        let hook = SteppingHook {
+            line_starts,
+            breakpoints: Arc::clone(&self.breakpoints),
+            resume: if self.stop_on_entry { Resume::StepIn } else { Resume::Continue },
+            last_line: None,
+            events: self.events.clone(),
+            commands: command_rx,
+            stop_reason: if self.stop_on_entry { "entry" } else { "step" },
+        };
+        let events = self.events.clone();
+
+        thread::spawn(move || {
+            let mut interpreter = Interpreter::new(&program);
+            interpreter.set_debug_hook(Box::new(hook));
+            let error = interpreter.run(&program).err().map(|e| e.to_string());
+            let _ = events.send(Event::Terminated(error));
+        });
+    }
+}
+
+fn load_program(path: &PathBuf) -> std::result::Result<(Vec<usize>, Program), String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let file = path.display().to_string();
+    let tokens = lexer::lex(&source).map_err(|e| CompilerError::at(&file, &source, &[e]).to_string())?;
+    let ast = parser::parse(tokens).map_err(|e| CompilerError::at(&file, &source, &[e]).to_string())?;
+    let program = typechecker::typecheck(ast)
+        .map_err(|errors| CompilerError::at(&file, &source, &errors).to_string())?
+        .program;
+    Ok((line_starts(&source), program))
+}
+
+/// Serves a single debug session over the given streams until the client
+/// disconnects.
+pub fn serve<R, W>(input: R, output: W) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write,
+{
+    let (events_tx, events_rx) = mpsc::channel();
+
+    let requests = events_tx.clone();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if requests.send(Event::Request(message)).is_err() {
+                return;
+            }
+        }
+        let _ = requests.send(Event::ClientClosed);
+    });
+
+    let mut server = Server {
+        writer: output,
+        seq: 0,
+        events: events_tx,
+        program: None,
+        stop_on_entry: false,
+        breakpoints: Arc::new(Mutex::new(HashSet::new())),
+        frames: Vec::new(),
+        commands: None,
+    };
+
+    for event in events_rx {
+        match event {
+            Event::Request(request) => {
+                if !server.handle_request(request)? {
+                    break;
+                }
+            }
+            Event::Stopped(frames, reason) => {
+                server.frames = frames;
+                server.event("stopped", json!({
+                    "reason": reason,
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                }))?;
+            }
+            Event::Output(text) => {
+                server.event("output", json!({ "category": "stdout", "output": text }))?;
+            }
+            Event::Terminated(error) => {
+                let exit_code = if error.is_some() { 1 } else { 0 };
+                if let Some(message) = error {
+                    server.event("output", json!({ "category": "stderr", "output": message + "\n" }))?;
+                }
+                server.event("exited", json!({ "exitCode": exit_code }))?;
+                server.event("terminated", json!({}))?;
+            }
+            Event::ClientClosed => break,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+// Report of the functions and struct fields a program never uses, built by
+// the typechecker from the references it records
+
+use crate::ast::Span;
+use serde_json::{json, Value as Json};
+
+/// A function that no code reachable from `main` refers to
+#[derive(Debug, Clone)]
+pub struct DeadFunction {
+    pub name: String,
+    pub span: Span,
+    /// Whether some unreachable code still refers to it, so that removing it
+    /// means removing its callers too
+    pub referenced: bool,
+}
+
+/// A struct field that no code reachable from `main` reads or writes
+#[derive(Debug, Clone)]
+pub struct DeadField {
+    pub struct_name: String,
+    pub field: String,
+    /// Span of the struct declaring the field
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeadCodeReport {
+    pub functions: Vec<DeadFunction>,
+    pub fields: Vec<DeadField>,
+}
+
+impl DeadCodeReport {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.fields.is_empty()
+    }
+
+    /// The report as JSON, for build tooling
+    pub fn to_json(&self) -> Json {
+        let functions: Vec<Json> = self
+            .functions
+            .iter()
+            .map(|function| {
+                json!({
+                    "name": function.name,
+                    "start": function.span.start,
+                    "end": function.span.end,
+                    "referenced": function.referenced,
+                })
+            })
+            .collect();
+        let fields: Vec<Json> = self
+            .fields
+            .iter()
+            .map(|field| {
+                json!({
+                    "struct": field.struct_name,
+                    "field": field.field,
+                    "start": field.span.start,
+                    "end": field.span.end,
+                })
+            })
+            .collect();
+        json!({ "functions": functions, "fields": fields })
+    }
+}
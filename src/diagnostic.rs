@@ -0,0 +1,576 @@
+// Errors and warnings as the terminal shows them: the severity and code, the
+// message, and the line of source the span starts on with the span
+// underlined.
+//
+//     error[E0201]: Type mismatch: expected String, found Int
+//      --> hello.z:2:5
+//       |
+//     2 |     let name: string = 42;
+//       |     ^^^^^^^^^^^^^^^^^^^^^^
+//
+// Colors are used as `--color` says: with `auto`, when stderr is a terminal
+// and `NO_COLOR` is not set. Warnings are left out, printed or made errors as
+// `set_warning_levels` says, and are reported to the `DiagnosticSink` of the
+// thread, stderr unless one is set, so that callers of the library show them
+// their own way.
+
+use crate::ast::{self, Span};
+use crate::lexer::LexerError;
+use crate::lint::Level;
+use crate::parser::ParseError;
+use crate::source_map::FileId;
+use crate::typechecker::{TypeError, TypeWarning};
+use serde_json::{json, Value as Json};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// An error or warning in a source file
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The stable code `zc explain` describes it by
+    pub code: &'static str,
+    pub message: String,
+    /// The file as it was named to the compiler
+    pub file: String,
+    /// The text of the file, to show the span in
+    pub source: String,
+    pub span: Option<Span>,
+    /// Another place in the file the diagnostic is about, shown after the
+    /// span with what happened there, as in `first defined at app.z:2:5`
+    pub label: Option<(String, Span)>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(
+        severity: Severity,
+        code: &'static str,
+        message: String,
+        file: &str,
+        source: &str,
+        span: Option<Span>,
+    ) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            file: file.to_string(),
+            source: source.to_string(),
+            span,
+            label: None,
+        }
+    }
+
+    /// The diagnostic with the `label` for the other place `span`
+    pub(crate) fn with_label(mut self, label: Option<(String, Span)>) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// The line and column, from 1, of the byte at `offset`
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line_starts = ast::line_starts(&self.source);
+        let offset = offset.min(self.source.len());
+        let line = ast::line_of(&line_starts, offset);
+        let before = self.source.get(line_starts[line]..offset).unwrap_or_default();
+        (line + 1, before.chars().count() + 1)
+    }
+
+    /// The diagnostic as a few lines of text, colored with `color`
+    pub fn render(&self, color: bool) -> String {
+        let paint = |style: &str, text: &str| if color { format!("{}{}{}", style, text, RESET) } else { text.to_string() };
+        let severity = self.severity.color();
+        let mut text = format!(
+            "{}{}\n",
+            paint(severity, &format!("{}[{}]", self.severity.label(), self.code)),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        let Some(span) = &self.span else {
+            text.push_str(&format!("{} {}\n", paint(BLUE, " -->"), self.file));
+            return text;
+        };
+        let line_starts = ast::line_starts(&self.source);
+        let start = span.start.min(self.source.len());
+        let line = ast::line_of(&line_starts, start);
+        let line_start = line_starts[line];
+        let line_end = self.source[line_start..].find('\n').map_or(self.source.len(), |end| line_start + end);
+        let line_text = self.source[line_start..line_end].trim_end_matches('\r');
+        let before = &self.source[line_start..start.min(line_start + line_text.len())];
+        // A span reaching past its first line is underlined to the end of it
+        let end = span.end.clamp(start, line_start + line_text.len());
+        let width = self.source[start..end].chars().count().max(1);
+
+        let number = (line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        text.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            paint(BLUE, "-->"),
+            self.file,
+            line + 1,
+            before.chars().count() + 1
+        ));
+        text.push_str(&format!("{} {}\n", gutter, paint(BLUE, "|")));
+        text.push_str(&format!("{} {} {}\n", paint(BLUE, &number), paint(BLUE, "|"), line_text));
+        // Tabs are kept so that the carets line up with the text above
+        let padding: String = before.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        text.push_str(&format!(
+            "{} {} {}{}\n",
+            gutter,
+            paint(BLUE, "|"),
+            padding,
+            paint(severity, &"^".repeat(width))
+        ));
+        if let Some((label, span)) = &self.label {
+            let (line, column) = self.position(span.start);
+            text.push_str(&format!(
+                "{} {} {} at {}:{}:{}\n",
+                gutter,
+                paint(BLUE, "="),
+                label,
+                self.file,
+                line,
+                column
+            ));
+        }
+        text
+    }
+
+    /// The diagnostic as JSON, with the line and column its span starts at
+    pub fn to_json(&self) -> Json {
+        let mut json = json!({
+            "severity": self.severity.label(),
+            "code": self.code,
+            "message": self.message,
+            "file": self.file,
+        });
+        if let Some(span) = &self.span {
+            let (line, column) = self.position(span.start);
+            json["line"] = json!(line);
+            json["column"] = json!(column);
+            json["start"] = json!(span.start);
+            json["end"] = json!(span.end);
+        }
+        if let Some((label, span)) = &self.label {
+            let (line, column) = self.position(span.start);
+            json["label"] = json!({
+                "message": label,
+                "line": line,
+                "column": column,
+                "start": span.start,
+                "end": span.end,
+            });
+        }
+        json
+    }
+}
+
+// The code has to be one `zc explain` knows, to be kept as `&'static str`
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Diagnostic {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            severity: Severity,
+            code: String,
+            message: String,
+            file: String,
+            source: String,
+            span: Option<Span>,
+            #[serde(default)]
+            label: Option<(String, Span)>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let code = crate::explain(&fields.code)
+            .map(|explanation| explanation.code)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown diagnostic code {}", fields.code)))?;
+        Ok(Diagnostic {
+            severity: fields.severity,
+            code,
+            message: fields.message,
+            file: fields.file,
+            source: fields.source,
+            span: fields.span,
+            label: fields.label,
+        })
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(false).trim_end())
+    }
+}
+
+/// When diagnostics are colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// When stderr is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether diagnostics written to stderr are colored
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").filter(|value| !value.is_empty()).is_none() && std::io::stderr().is_terminal()
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("unknown color choice {}, expected auto, always or never", name)),
+        }
+    }
+}
+
+// The choice the diagnostics the compiler prints as it goes are colored by,
+// for the whole process like the stderr they are printed to
+static COLOR: AtomicU8 = AtomicU8::new(0);
+
+/// Sets when the diagnostics the compiler prints, such as warnings, are
+/// colored
+pub fn set_color(choice: ColorChoice) {
+    let value = match choice {
+        ColorChoice::Auto => 0,
+        ColorChoice::Always => 1,
+        ColorChoice::Never => 2,
+    };
+    COLOR.store(value, Ordering::Relaxed);
+}
+
+fn color() -> ColorChoice {
+    match COLOR.load(Ordering::Relaxed) {
+        1 => ColorChoice::Always,
+        2 => ColorChoice::Never,
+        _ => ColorChoice::Auto,
+    }
+}
+
+/// Prints `diagnostic` to stderr, colored as `set_color` says, and a blank
+/// line after it
+pub fn emit(diagnostic: &Diagnostic) {
+    eprintln!("{}", diagnostic.render(color().enabled()));
+}
+
+/// Receives what the compiler reports as it goes, rather than returns: the
+/// warnings it finds, and notes on what it is doing, such as fetching a
+/// package
+pub trait DiagnosticSink {
+    fn report(&mut self, diagnostic: &Diagnostic);
+
+    /// A line on what the compiler is doing; dropped unless implemented
+    fn note(&mut self, _message: &str) {}
+}
+
+impl fmt::Debug for dyn DiagnosticSink + Send {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DiagnosticSink")
+    }
+}
+
+/// Prints diagnostics as `emit` does, and notes as they are, to stderr. This
+/// is where they go unless a sink is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl DiagnosticSink for StderrSink {
+    fn report(&mut self, diagnostic: &Diagnostic) {
+        emit(diagnostic);
+    }
+
+    fn note(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Writes each diagnostic to a writer as a line of JSON, as `to_json` gives
+/// it, and each note as `{"note": message}`
+#[derive(Debug)]
+pub struct JsonSink<W>(pub W);
+
+impl<W: Write> DiagnosticSink for JsonSink<W> {
+    fn report(&mut self, diagnostic: &Diagnostic) {
+        let _ = writeln!(self.0, "{}", diagnostic.to_json());
+    }
+
+    fn note(&mut self, message: &str) {
+        let _ = writeln!(self.0, "{}", json!({ "note": message }));
+    }
+}
+
+/// Keeps what is reported, for callers that show it their own way
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    pub diagnostics: Vec<Diagnostic>,
+    pub notes: Vec<String>,
+}
+
+impl DiagnosticSink for MemorySink {
+    fn report(&mut self, diagnostic: &Diagnostic) {
+        self.diagnostics.push(diagnostic.clone());
+    }
+
+    fn note(&mut self, message: &str) {
+        self.notes.push(message.to_string());
+    }
+}
+
+/// A sink that compilations on several threads can share
+pub type SharedSink = Arc<Mutex<dyn DiagnosticSink + Send>>;
+
+thread_local! {
+    // The sink of the compilations on this thread, kept per thread like the
+    // warning levels; stderr when none is set
+    static SINK: RefCell<Option<SharedSink>> = const { RefCell::new(None) };
+}
+
+/// Sends what compilations on this thread report to `sink`, or to stderr
+/// when it is none
+pub fn set_diagnostic_sink(sink: Option<SharedSink>) {
+    SINK.with(|current| *current.borrow_mut() = sink);
+}
+
+/// Runs `body` reporting to `sink`, then restores the sink set before
+#[cfg(any(feature = "c", feature = "wasm"))]
+pub(crate) fn with_sink<T>(sink: SharedSink, body: impl FnOnce() -> T) -> T {
+    let outer = SINK.with(|current| current.replace(Some(sink)));
+    let result = body();
+    SINK.with(|current| *current.borrow_mut() = outer);
+    result
+}
+
+/// Calls `send` on the sink of this thread
+fn to_sink(send: impl FnOnce(&mut dyn DiagnosticSink)) {
+    // Cloned out so that a sink reporting in turn finds the cell free
+    match SINK.with(|current| current.borrow().clone()) {
+        Some(sink) => send(&mut *sink.lock().unwrap_or_else(PoisonError::into_inner)),
+        None => send(&mut StderrSink),
+    }
+}
+
+/// Reports `diagnostic` to the sink of this thread
+pub(crate) fn report(diagnostic: &Diagnostic) {
+    to_sink(|sink| sink.report(diagnostic));
+}
+
+/// Reports the note `message` to the sink of this thread
+pub(crate) fn note(message: &str) {
+    to_sink(|sink| sink.note(message));
+}
+
+/// Reports what a program the compiler ran wrote to stderr, such as its
+/// panic, as a note, so it goes to the sink with the compiler's own output
+#[cfg(feature = "c")]
+pub(crate) fn program_stderr(output: &[u8]) {
+    let text = String::from_utf8_lossy(output);
+    if !text.is_empty() {
+        note(text.strip_suffix('\n').unwrap_or(&text));
+    }
+}
+
+thread_local! {
+    // The levels of the warnings whose codes `set_warning_levels` was given,
+    // kept per thread so that compilations on other threads can use levels
+    // of their own
+    static WARNING_LEVELS: RefCell<Vec<(String, Level)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Sets how the warnings with the given codes are reported in compilations
+/// on this thread: left out, printed, or made errors that stop the
+/// compilation. Other warnings are printed.
+pub fn set_warning_levels(levels: &[(String, Level)]) {
+    WARNING_LEVELS.with(|current| *current.borrow_mut() = levels.to_vec());
+}
+
+/// Runs `body` with the warning levels `levels`, then restores the levels
+/// set before
+#[cfg(feature = "c")]
+pub(crate) fn with_warning_levels<T>(levels: &[(String, Level)], body: impl FnOnce() -> T) -> T {
+    let outer = WARNING_LEVELS.with(|current| current.replace(levels.to_vec()));
+    let result = body();
+    WARNING_LEVELS.with(|current| *current.borrow_mut() = outer);
+    result
+}
+
+/// The diagnostic of `warning` in `file`, whose text is `source`, as its
+/// level says: none when it is allowed, and an error when it is denied or
+/// `deny` is set
+pub(crate) fn warning(warning: &TypeWarning, file: &str, source: &str, deny: bool) -> Option<Diagnostic> {
+    leveled(warning.to_diagnostic(file, source), deny)
+}
+
+/// `diagnostic`, a warning, as its level says, like `warning`
+pub(crate) fn leveled(mut diagnostic: Diagnostic, deny: bool) -> Option<Diagnostic> {
+    let level = WARNING_LEVELS.with(|levels| {
+        levels.borrow().iter().find(|(code, _)| *code == diagnostic.code).map(|(_, level)| *level)
+    });
+    match level {
+        Some(Level::Allow) => return None,
+        Some(Level::Deny) => diagnostic.severity = Severity::Error,
+        Some(Level::Warn) | None if deny => diagnostic.severity = Severity::Error,
+        Some(Level::Warn) | None => {}
+    }
+    Some(diagnostic)
+}
+
+/// Runs `body`, returning the warnings it reports on this thread instead of
+/// reporting them. Its notes go to the sink set before once it returns.
+#[cfg(any(feature = "c", feature = "wasm"))]
+pub(crate) fn collect<T>(body: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    let collected = Arc::new(Mutex::new(MemorySink::default()));
+    let result = with_sink(collected.clone(), body);
+    let collected = std::mem::take(&mut *collected.lock().unwrap_or_else(PoisonError::into_inner));
+    for message in &collected.notes {
+        note(message);
+    }
+    (result, collected.diagnostics)
+}
+
+/// Reports the warnings of `file`, whose text is `source`, as their levels
+/// say, failing with those that are denied, or with all that are not
+/// allowed when `deny` is set
+pub(crate) fn emit_warnings(file: &str, source: &str, warnings: &[TypeWarning], deny: bool) -> crate::Result<()> {
+    let mut denied = Vec::new();
+    for diagnostic in warnings.iter().filter_map(|problem| warning(problem, file, source, deny)) {
+        match diagnostic.severity {
+            Severity::Warning => report(&diagnostic),
+            Severity::Error => denied.push(diagnostic),
+        }
+    }
+    if denied.is_empty() {
+        Ok(())
+    } else {
+        Err(crate::CompilerError::Diagnostics(denied))
+    }
+}
+
+/// The warnings of `file` as their levels say, like `emit_warnings`, but
+/// returned rather than reported
+pub(crate) fn leveled_warnings(
+    file: &str,
+    source: &str,
+    warnings: &[TypeWarning],
+    deny: bool,
+) -> crate::Result<Vec<Diagnostic>> {
+    let (reported, denied): (Vec<_>, Vec<_>) = warnings
+        .iter()
+        .filter_map(|problem| warning(problem, file, source, deny))
+        .partition(|diagnostic| diagnostic.severity == Severity::Warning);
+    if denied.is_empty() {
+        Ok(reported)
+    } else {
+        Err(crate::CompilerError::Diagnostics(denied))
+    }
+}
+
+/// The problems the stages of the compiler find
+pub(crate) trait ToDiagnostic {
+    fn to_diagnostic(&self, file: &str, source: &str) -> Diagnostic;
+
+    /// The file of the source map the problem is in, when its span says
+    fn file(&self) -> Option<FileId> {
+        None
+    }
+}
+
+impl ToDiagnostic for LexerError {
+    fn to_diagnostic(&self, file: &str, source: &str) -> Diagnostic {
+        let span = Span::new(FileId::default(), self.span.clone());
+        Diagnostic::new(Severity::Error, self.code, self.message.clone(), file, source, Some(span))
+    }
+}
+
+impl ToDiagnostic for ParseError {
+    fn to_diagnostic(&self, file: &str, source: &str) -> Diagnostic {
+        Diagnostic::new(Severity::Error, self.code, self.message.clone(), file, source, Some(self.span.clone()))
+            .with_label(self.label.clone())
+    }
+
+    fn file(&self) -> Option<FileId> {
+        Some(self.span.file)
+    }
+}
+
+impl ToDiagnostic for TypeError {
+    fn to_diagnostic(&self, file: &str, source: &str) -> Diagnostic {
+        Diagnostic::new(Severity::Error, self.code, self.message.clone(), file, source, self.span.clone())
+            .with_label(self.label.clone())
+    }
+
+    fn file(&self) -> Option<FileId> {
+        self.span.as_ref().map(|span| span.file)
+    }
+}
+
+// Errors in the program that only generating code finds, such as a library
+// defining `main`
+#[cfg(feature = "codegen")]
+impl ToDiagnostic for crate::codegen::CodegenError {
+    fn to_diagnostic(&self, file: &str, source: &str) -> Diagnostic {
+        Diagnostic::new(Severity::Error, "E0501", self.message.clone(), file, source, self.span.clone())
+    }
+
+    fn file(&self) -> Option<FileId> {
+        self.span.as_ref().map(|span| span.file)
+    }
+}
+
+impl ToDiagnostic for TypeWarning {
+    fn to_diagnostic(&self, file: &str, source: &str) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, self.code, self.message.clone(), file, source, self.span.clone())
+    }
+
+    fn file(&self) -> Option<FileId> {
+        self.span.as_ref().map(|span| span.file)
+    }
+}
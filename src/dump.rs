@@ -0,0 +1,278 @@
+// What the frontend makes of a source file, for `zc dump`: its tokens, and
+// the syntax tree as parsed or as checked, as text or as JSON
+
+use crate::ast::{self, Expr, Literal, MatchArm, Pattern, Program, Span, Stmt, Type, UnaryOp};
+use crate::lexer;
+use serde_json::{json, Value as Json};
+
+/// The tokens of `source`, a line each with its position, kind and text
+pub fn tokens_text(tokens: &[lexer::Span], source: &str) -> String {
+    let line_starts = ast::line_starts(source);
+    let mut text = String::new();
+    for token in tokens {
+        let (line, column) = position(&line_starts, token.span.start);
+        text.push_str(&format!("{:<10}{:<16}{}\n", format!("{}:{}", line, column), format!("{:?}", token.token), token.text));
+    }
+    text
+}
+
+/// The tokens of `source` as JSON, for tooling
+pub fn tokens_json(tokens: &[lexer::Span], source: &str) -> Json {
+    let line_starts = ast::line_starts(source);
+    let tokens: Vec<Json> = tokens
+        .iter()
+        .map(|token| {
+            let (line, column) = position(&line_starts, token.span.start);
+            json!({
+                "kind": format!("{:?}", token.token),
+                "text": token.text,
+                "start": token.span.start,
+                "end": token.span.end,
+                "line": line,
+                "column": column,
+            })
+        })
+        .collect();
+    Json::Array(tokens)
+}
+
+/// The one-based line and column of `offset`
+fn position(line_starts: &[usize], offset: usize) -> (usize, usize) {
+    let line = ast::line_of(line_starts, offset);
+    (line + 1, offset - line_starts[line] + 1)
+}
+
+/// The statements of `program` as Rust prints them
+pub fn ast_text(program: &Program) -> String {
+    format!("{:#?}\n", program.statements)
+}
+
+/// The statements of `program` as JSON, each node an object whose `kind`
+/// names it
+pub fn ast_json(program: &Program) -> Json {
+    Json::Array(program.statements.iter().map(stmt_json).collect())
+}
+
+fn span_json(span: &Span) -> Json {
+    json!({ "start": span.start, "end": span.end })
+}
+
+fn type_json(ty: &Type) -> Json {
+    json!(ty.to_string())
+}
+
+fn params_json(params: &[(String, Type)]) -> Json {
+    params
+        .iter()
+        .map(|(name, ty)| json!({ "name": name, "type": type_json(ty) }))
+        .collect()
+}
+
+fn stmts_json(stmts: &[Stmt]) -> Json {
+    stmts.iter().map(stmt_json).collect()
+}
+
+fn stmt_json(stmt: &Stmt) -> Json {
+    match stmt {
+        Stmt::Expr(expr) => json!({ "kind": "Expr", "expr": expr_json(expr) }),
+        Stmt::Let(name, ty, init, span) => json!({
+            "kind": "Let",
+            "name": name,
+            "type": ty.as_ref().map(type_json),
+            "init": init.as_ref().map(expr_json),
+            "span": span_json(span),
+        }),
+        Stmt::Const(name, ty, value, span) => json!({
+            "kind": "Const",
+            "name": name,
+            "type": ty.as_ref().map(type_json),
+            "value": expr_json(value),
+            "span": span_json(span),
+        }),
+        Stmt::Assign(target, value, span) => json!({
+            "kind": "Assign",
+            "target": expr_json(target),
+            "value": expr_json(value),
+            "span": span_json(span),
+        }),
+        Stmt::Return(value, span) => json!({
+            "kind": "Return",
+            "value": value.as_ref().map(expr_json),
+            "span": span_json(span),
+        }),
+        Stmt::While(cond, body, span) => json!({
+            "kind": "While",
+            "cond": expr_json(cond),
+            "body": stmt_json(body),
+            "span": span_json(span),
+        }),
+        Stmt::For(name, _, iterable, body, span) => json!({
+            "kind": "For",
+            "name": name,
+            "iterable": expr_json(iterable),
+            "body": stmt_json(body),
+            "span": span_json(span),
+        }),
+        Stmt::Block(stmts, span) => json!({ "kind": "Block", "stmts": stmts_json(stmts), "span": span_json(span) }),
+        Stmt::Function(name, type_params, params, return_type, body, public, attributes, span) => json!({
+            "kind": "Function",
+            "name": name,
+            "type_params": type_params,
+            "params": params_json(params),
+            "return_type": type_json(return_type),
+            "body": stmt_json(body),
+            "public": public,
+            "attributes": attributes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "span": span_json(span),
+        }),
+        Stmt::Struct(name, type_params, fields, span) => json!({
+            "kind": "Struct",
+            "name": name,
+            "type_params": type_params,
+            "fields": params_json(fields),
+            "span": span_json(span),
+        }),
+        Stmt::Enum(name, variants, span) => {
+            let variants: Vec<Json> = variants
+                .iter()
+                .map(|(name, payload)| {
+                    json!({ "name": name, "payload": payload.iter().map(type_json).collect::<Vec<_>>() })
+                })
+                .collect();
+            json!({ "kind": "Enum", "name": name, "variants": variants, "span": span_json(span) })
+        }
+        Stmt::Import(name, span) => json!({ "kind": "Import", "name": name, "span": span_json(span) }),
+        Stmt::Extern(name, params, return_type, library, span) => json!({
+            "kind": "Extern",
+            "name": name,
+            "params": params_json(params),
+            "return_type": type_json(return_type),
+            "library": library,
+            "span": span_json(span),
+        }),
+    }
+}
+
+fn exprs_json(exprs: &[Expr]) -> Json {
+    exprs.iter().map(expr_json).collect()
+}
+
+fn expr_json(expr: &Expr) -> Json {
+    match expr {
+        Expr::Literal(literal, span) => {
+            let (ty, value) = match literal {
+                Literal::Int(value) => ("int", json!(value)),
+                Literal::Float(value) => ("float", json!(value)),
+                Literal::Bool(value) => ("bool", json!(value)),
+                Literal::String(value) => ("string", json!(value)),
+                Literal::Null => ("null", Json::Null),
+            };
+            json!({ "kind": "Literal", "type": ty, "value": value, "span": span_json(span) })
+        }
+        Expr::Identifier(name, span) => json!({ "kind": "Identifier", "name": name, "span": span_json(span) }),
+        Expr::Binary(left, op, right, span) => json!({
+            "kind": "Binary",
+            "op": op.symbol(),
+            "left": expr_json(left),
+            "right": expr_json(right),
+            "span": span_json(span),
+        }),
+        Expr::Unary(op, operand, span) => json!({
+            "kind": "Unary",
+            "op": if *op == UnaryOp::Neg { "-" } else { "!" },
+            "operand": expr_json(operand),
+            "span": span_json(span),
+        }),
+        Expr::Call(callee, args, span) => json!({
+            "kind": "Call",
+            "callee": expr_json(callee),
+            "args": exprs_json(args),
+            "span": span_json(span),
+        }),
+        Expr::Index(array, index, span) => json!({
+            "kind": "Index",
+            "array": expr_json(array),
+            "index": expr_json(index),
+            "span": span_json(span),
+        }),
+        Expr::Field(object, field, span) => json!({
+            "kind": "Field",
+            "object": expr_json(object),
+            "field": field,
+            "span": span_json(span),
+        }),
+        Expr::Array(elements, span) => json!({ "kind": "Array", "elements": exprs_json(elements), "span": span_json(span) }),
+        Expr::If(cond, then_branch, else_branch, span) => json!({
+            "kind": "If",
+            "cond": expr_json(cond),
+            "then": expr_json(then_branch),
+            "else": else_branch.as_deref().map(expr_json),
+            "span": span_json(span),
+        }),
+        Expr::Block(stmts, span) => json!({ "kind": "Block", "stmts": stmts_json(stmts), "span": span_json(span) }),
+        Expr::Lambda(params, return_type, body, span) => {
+            let params: Vec<Json> = params
+                .iter()
+                .map(|(name, ty)| json!({ "name": name, "type": ty.as_ref().map(type_json) }))
+                .collect();
+            json!({
+                "kind": "Lambda",
+                "params": params,
+                "return_type": return_type.as_ref().map(type_json),
+                "body": expr_json(body),
+                "span": span_json(span),
+            })
+        }
+        Expr::Range(start, end, span) => json!({
+            "kind": "Range",
+            "start": expr_json(start),
+            "end": expr_json(end),
+            "span": span_json(span),
+        }),
+        Expr::Cast(value, ty, span) => json!({
+            "kind": "Cast",
+            "value": expr_json(value),
+            "type": type_json(ty),
+            "span": span_json(span),
+        }),
+        Expr::Match(scrutinee, arms, span) => json!({
+            "kind": "Match",
+            "scrutinee": expr_json(scrutinee),
+            "arms": arms.iter().map(arm_json).collect::<Vec<_>>(),
+            "span": span_json(span),
+        }),
+        Expr::Variant(enum_name, variant, payload, span) => json!({
+            "kind": "Variant",
+            "enum": enum_name,
+            "variant": variant,
+            "payload": exprs_json(payload),
+            "span": span_json(span),
+        }),
+        Expr::Try(value, return_type, span) => json!({
+            "kind": "Try",
+            "value": expr_json(value),
+            "return_type": return_type.as_ref().map(type_json),
+            "span": span_json(span),
+        }),
+        Expr::Inlined(function, body, span) => json!({
+            "kind": "Inlined",
+            "function": function,
+            "body": expr_json(body),
+            "span": span_json(span),
+        }),
+    }
+}
+
+fn arm_json(arm: &MatchArm) -> Json {
+    let pattern = match &arm.pattern {
+        Pattern::Variant(enum_name, variant, bindings, span) => json!({
+            "kind": "Variant",
+            "enum": enum_name,
+            "variant": variant,
+            "bindings": bindings,
+            "span": span_json(span),
+        }),
+        Pattern::Wildcard(span) => json!({ "kind": "Wildcard", "span": span_json(span) }),
+    };
+    json!({ "pattern": pattern, "body": expr_json(&arm.body), "span": span_json(&arm.span) })
+}
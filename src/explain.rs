@@ -0,0 +1,1412 @@
+// Extended explanations of the diagnostics, for `zc explain`. Every error and
+// warning carries a code that stays the same from release to release; a code
+// is never given to another diagnostic, even once nothing reports it any more.
+// E00xx are syntax errors, E01xx names that cannot be resolved, E02xx type
+// errors, E03xx errors in `match`, E04xx errors of `--strict-memory`, E05xx
+// errors only generating code finds, W00xx warnings and L00xx the rules of
+// `zc lint`.
+
+pub struct Explanation {
+    pub code: &'static str,
+    pub title: &'static str,
+    /// What the diagnostic means and how to fix it
+    pub text: &'static str,
+    /// A program it is reported for
+    pub wrong: &'static str,
+    /// The same program fixed
+    pub fixed: &'static str,
+}
+
+impl Explanation {
+    /// The explanation as `zc explain` prints it, the examples indented
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}: {}\n\n{}\n\nWrong:\n\n{}\nFixed:\n\n{}",
+            self.code,
+            self.title,
+            self.text,
+            indent(self.wrong),
+            indent(self.fixed)
+        )
+    }
+}
+
+fn indent(code: &str) -> String {
+    code.lines()
+        .map(|line| if line.is_empty() { "\n".to_string() } else { format!("    {}\n", line) })
+        .collect()
+}
+
+/// The explanation of `code`, written as in `E0101`, `e0101` or `0101`
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    let code = code.trim().to_ascii_uppercase();
+    let code = if code.starts_with(|c: char| c.is_ascii_digit()) { format!("E{}", code) } else { code };
+    EXPLANATIONS.iter().find(|explanation| explanation.code == code)
+}
+
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "E0001",
+        title: "Invalid token",
+        text: r#"The source contains a character that does not start any token of Z, such as
+`$` or a backtick. Outside of strings and comments, only letters, digits,
+`_`, whitespace and the operators and punctuation of the language may
+appear."#,
+        wrong: r#"fn main() {
+    let price = $5;
+    print(price);
+}
+"#,
+        fixed: r#"fn main() {
+    let price = 5;
+    print(price);
+}
+"#,
+    },
+    Explanation {
+        code: "E0002",
+        title: "Unexpected token",
+        text: r#"The parser found a token where the grammar does not allow one, such as a
+missing semicolon, a missing parenthesis or a keyword used as a name. The
+message says what was expected; the mistake is often just before the
+position reported."#,
+        wrong: r#"fn main() {
+    print(1;
+}
+"#,
+        fixed: r#"fn main() {
+    print(1);
+}
+"#,
+    },
+    Explanation {
+        code: "E0003",
+        title: "Unexpected end of file",
+        text: r#"The file ended in the middle of a declaration, a statement or an
+expression. Usually a `}` or `)` is missing: check that every block opened
+is closed again."#,
+        wrong: r#"fn main() {
+    print(1);
+"#,
+        fixed: r#"fn main() {
+    print(1);
+}
+"#,
+    },
+    Explanation {
+        code: "E0004",
+        title: "Unknown attribute",
+        text: r#"An `@` names an attribute that Z does not have. The attributes of
+functions are `@inline`, `@noinline`, `@opt(0)` to `@opt(3)`, `@bench` and,
+for extern functions, `@link("library")`."#,
+        wrong: r#"@inlined
+fn square(x: int) -> int {
+    return x * x;
+}
+
+fn main() {
+    print(square(3));
+}
+"#,
+        fixed: r#"@inline
+fn square(x: int) -> int {
+    return x * x;
+}
+
+fn main() {
+    print(square(3));
+}
+"#,
+    },
+    Explanation {
+        code: "E0005",
+        title: "Attribute used where it does not apply",
+        text: r#"An attribute is known but does not fit the declaration it is on: `@link`
+only applies to extern functions and the others only to Z functions, a
+function is not both `@inline` and `@noinline` and has one `@opt` level, from
+0 to 3, `main` cannot be inlined or benchmarked, and a `@bench` function
+takes no parameters and returns a number, a bool or nothing."#,
+        wrong: r#"@inline
+@noinline
+fn square(x: int) -> int {
+    return x * x;
+}
+
+fn main() {
+    print(square(3));
+}
+"#,
+        fixed: r#"@noinline
+fn square(x: int) -> int {
+    return x * x;
+}
+
+fn main() {
+    print(square(3));
+}
+"#,
+    },
+    Explanation {
+        code: "E0006",
+        title: "Generic enum",
+        text: r#"Enums cannot have type parameters yet; only the builtin `Option<T>` and
+`Result<T, E>` do. Use those, or declare an enum for each payload type the
+program needs."#,
+        wrong: r#"enum Maybe<T> {
+    Some(T),
+    Nothing,
+}
+
+fn main() {
+    print(1);
+}
+"#,
+        fixed: r#"fn main() {
+    let value: Option<int> = Some(1);
+    match value {
+        Some(n) => print(n),
+        None => print(0),
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0007",
+        title: "Invalid number literal",
+        text: r#"A number literal cannot be represented: an integer literal is larger than
+the largest `int`, 9223372036854775807, or a float literal is malformed."#,
+        wrong: r#"fn main() {
+    let big = 99999999999999999999;
+    print(big);
+}
+"#,
+        fixed: r#"fn main() {
+    let big = 99999999999999999999.0;
+    print(big);
+}
+"#,
+    },
+    Explanation {
+        code: "E0101",
+        title: "Undefined name",
+        text: r#"A name is used that no `let`, `const`, parameter, loop or match arm
+declares where it is used, and that is not a function of this file, of an
+imported module or built in. Check the spelling, that a variable is
+declared before its use and in a block that encloses it, and that the
+module declaring a function is imported."#,
+        wrong: r#"fn main() {
+    if true {
+        let count = 3;
+    }
+    print(count);
+}
+"#,
+        fixed: r#"fn main() {
+    let count = 3;
+    print(count);
+}
+"#,
+    },
+    Explanation {
+        code: "E0102",
+        title: "Unknown type",
+        text: r#"A type is named that is neither built in nor declared as a struct or an
+enum. The built-in types are `int`, `float`, `bool`, `string`, the sized
+integer and float types such as `i32` and `f64`, arrays `[T]`, optionals
+`T?` and functions `fn(A) -> R`."#,
+        wrong: r#"fn half(x: integer) -> int {
+    return x / 2;
+}
+
+fn main() {
+    print(half(8));
+}
+"#,
+        fixed: r#"fn half(x: int) -> int {
+    return x / 2;
+}
+
+fn main() {
+    print(half(8));
+}
+"#,
+    },
+    Explanation {
+        code: "E0103",
+        title: "Name defined twice",
+        text: r#"Two functions, two types, or two variables or constants in the same scope
+have the same name. Rename one of them. A variable in an inner block may
+shadow one of an outer block."#,
+        wrong: r#"fn main() {
+    let total = 1;
+    let total = 2;
+    print(total);
+}
+"#,
+        fixed: r#"fn main() {
+    let total = 1;
+    let doubled = total * 2;
+    print(doubled);
+}
+"#,
+    },
+    Explanation {
+        code: "E0104",
+        title: "Duplicate name in a list",
+        text: r#"The same name appears twice where each must be different: the parameters
+or type parameters of a function or lambda, the fields of a struct, the
+variants of an enum, or the bindings of a pattern."#,
+        wrong: r#"fn add(a: int, a: int) -> int {
+    return a + a;
+}
+
+fn main() {
+    print(add(1, 2));
+}
+"#,
+        fixed: r#"fn add(a: int, b: int) -> int {
+    return a + b;
+}
+
+fn main() {
+    print(add(1, 2));
+}
+"#,
+    },
+    Explanation {
+        code: "E0105",
+        title: "Conflicting imports",
+        text: r#"Two imported modules declare a public function of the same name, or a
+module is imported twice, so a call could mean either function. Rename one
+of the functions, or import only one of the modules."#,
+        wrong: r#"// shapes.z and sizes.z both declare `pub fn area`
+import shapes;
+import sizes;
+
+fn main() {
+    print(area(2));
+}
+"#,
+        fixed: r#"// sizes.z now declares `pub fn size_area` instead
+import shapes;
+import sizes;
+
+fn main() {
+    print(area(2));
+}
+"#,
+    },
+    Explanation {
+        code: "E0106",
+        title: "Unknown field",
+        text: r#"A field is read or written that the struct does not declare. Check the
+spelling against the struct's declaration."#,
+        wrong: r#"struct Point {
+    x: int,
+    y: int,
+}
+
+pub fn sum(p: Point) -> int {
+    return p.x + p.z;
+}
+"#,
+        fixed: r#"struct Point {
+    x: int,
+    y: int,
+}
+
+pub fn sum(p: Point) -> int {
+    return p.x + p.y;
+}
+"#,
+    },
+    Explanation {
+        code: "E0107",
+        title: "Unknown variant",
+        text: r#"A variant is named that the enum does not declare, in a value such as
+`Color.Red` or in a pattern. Check the spelling against the enum's
+declaration."#,
+        wrong: r#"enum Color {
+    Red,
+    Green,
+}
+
+fn main() {
+    let color = Color.Blue;
+}
+"#,
+        fixed: r#"enum Color {
+    Red,
+    Green,
+}
+
+fn main() {
+    let color = Color.Green;
+    match color {
+        Color.Red => print(1),
+        Color.Green => print(2),
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0108",
+        title: "Extern function named like a builtin",
+        text: r#"An extern function has the name of a function Z provides, such as `print`
+or `len`, so calls could not tell them apart. Declare the C function under
+a name of its own."#,
+        wrong: r#"extern fn print(s: string) -> int;
+
+fn main() {
+    print("hello");
+}
+"#,
+        fixed: r#"extern fn puts(s: string) -> int;
+
+fn main() {
+    puts("hello");
+}
+"#,
+    },
+    Explanation {
+        code: "E0201",
+        title: "Type mismatch",
+        text: r#"A value has another type than the one its place requires: the declared
+type of a variable, the return type of a function, the type of an array's
+other elements, or a condition, which must be a `bool`. Convert the value
+with `as` where that makes sense, or change the declared type."#,
+        wrong: r#"fn main() {
+    let name: string = 42;
+    print(name);
+}
+"#,
+        fixed: r#"fn main() {
+    let name: string = "42";
+    print(name);
+}
+"#,
+    },
+    Explanation {
+        code: "E0202",
+        title: "Wrong number of arguments",
+        text: r#"A function, lambda or enum variant is given more or fewer values than it
+takes. Compare the call with the declaration."#,
+        wrong: r#"fn add(a: int, b: int) -> int {
+    return a + b;
+}
+
+fn main() {
+    print(add(1));
+}
+"#,
+        fixed: r#"fn add(a: int, b: int) -> int {
+    return a + b;
+}
+
+fn main() {
+    print(add(1, 2));
+}
+"#,
+    },
+    Explanation {
+        code: "E0203",
+        title: "Argument of the wrong type",
+        text: r#"An argument of a call, or a value of an enum variant, has another type
+than the parameter it is passed for."#,
+        wrong: r#"fn twice(x: int) -> int {
+    return x * 2;
+}
+
+fn main() {
+    print(twice("4"));
+}
+"#,
+        fixed: r#"fn twice(x: int) -> int {
+    return x * 2;
+}
+
+fn main() {
+    print(twice(4));
+}
+"#,
+    },
+    Explanation {
+        code: "E0204",
+        title: "Operator applied to the wrong types",
+        text: r#"An operator is used on values it is not defined for, such as `-` on
+strings, `!` on a number or `&&` on numbers. Strings support `+` and
+comparisons, numbers arithmetic and comparisons, and bools `!`, `&&` and
+`||`."#,
+        wrong: r#"fn main() {
+    let ready = 1 && 0;
+    print(ready);
+}
+"#,
+        fixed: r#"fn main() {
+    let ready = 1 != 0 && 0 != 0;
+    print(ready);
+}
+"#,
+    },
+    Explanation {
+        code: "E0205",
+        title: "Mixed number types",
+        text: r#"The operands of an arithmetic or comparison operator are numbers of
+different types, such as an `int` and a `float`. Z does not convert them
+silently, since that can lose precision: convert one side with `as`."#,
+        wrong: r#"fn main() {
+    let count = 3;
+    let average = 7.5 / count;
+    print(average);
+}
+"#,
+        fixed: r#"fn main() {
+    let count = 3;
+    let average = 7.5 / (count as float);
+    print(average);
+}
+"#,
+    },
+    Explanation {
+        code: "E0206",
+        title: "Invalid cast",
+        text: r#"`as` converts between number types and between numbers and bools, but not
+from or to strings, arrays, structs or functions."#,
+        wrong: r#"fn main() {
+    let text = "12";
+    print(text as int);
+}
+"#,
+        fixed: r#"fn main() {
+    let value = 12.7;
+    print(value as int);
+}
+"#,
+    },
+    Explanation {
+        code: "E0207",
+        title: "Indexing or field access on the wrong type",
+        text: r#"Only arrays can be indexed with `[]`, and only structs have fields to
+read with `.`."#,
+        wrong: r#"fn main() {
+    let count = 3;
+    print(count[0]);
+}
+"#,
+        fixed: r#"fn main() {
+    let counts = [3];
+    print(counts[0]);
+}
+"#,
+    },
+    Explanation {
+        code: "E0208",
+        title: "Call of a value that is not a function",
+        text: r#"Something is called that is not a function: a variable of another type,
+for instance, that shadows a function of the same name."#,
+        wrong: r#"fn main() {
+    let size = 3;
+    print(size(2));
+}
+"#,
+        fixed: r#"fn main() {
+    let size = |x: int| x * 3;
+    print(size(2));
+}
+"#,
+    },
+    Explanation {
+        code: "E0209",
+        title: "Type cannot be inferred",
+        text: r#"The type of a variable, lambda parameter or type parameter cannot be
+worked out from how it is used. Give the variable an initializer or a type,
+annotate the lambda parameter, or pass an argument that determines the type
+parameter. `None` and `Err(e)` leave a type argument of `Option` or `Result`
+open, which the type of the variable they are assigned to can give."#,
+        wrong: r#"fn main() {
+    let total;
+    total = 3;
+    print(total);
+}
+"#,
+        fixed: r#"fn main() {
+    let total: int;
+    total = 3;
+    print(total);
+}
+"#,
+    },
+    Explanation {
+        code: "E0210",
+        title: "Missing return",
+        text: r#"A function declares a return type but some path through its body reaches
+the end without returning a value. Return a value on every path, for
+instance after the last `if`. A path may instead end in a `panic`, or in a
+call of a function that never returns because its every path panics."#,
+        wrong: r#"fn sign(x: int) -> int {
+    if x < 0 {
+        return -1;
+    }
+}
+
+fn main() {
+    print(sign(5));
+}
+"#,
+        fixed: r#"fn sign(x: int) -> int {
+    if x < 0 {
+        return -1;
+    }
+    return 1;
+}
+
+fn main() {
+    print(sign(5));
+}
+"#,
+    },
+    Explanation {
+        code: "E0211",
+        title: "Wrong number of type arguments",
+        text: r#"A generic struct is named with more or fewer type arguments than it has
+type parameters."#,
+        wrong: r#"struct Pair<A, B> {
+    first: A,
+    second: B,
+}
+
+pub fn first(pair: Pair<int>) -> int {
+    return pair.first;
+}
+"#,
+        fixed: r#"struct Pair<A, B> {
+    first: A,
+    second: B,
+}
+
+pub fn first(pair: Pair<int, int>) -> int {
+    return pair.first;
+}
+"#,
+    },
+    Explanation {
+        code: "E0212",
+        title: "Value may be null",
+        text: r#"An optional value, of a type `T?`, is used as if it held a `T`. Check it
+first: inside `if x != null`, or the `else` of `if x == null`, `x` has the
+type `T`."#,
+        wrong: r#"fn find(values: [int], wanted: int) -> int? {
+    for i in 0..len(values) {
+        if values[i] == wanted {
+            return i;
+        }
+    }
+    return null;
+}
+
+fn main() {
+    let found = find([4, 5], 5);
+    print(found + 1);
+}
+"#,
+        fixed: r#"fn find(values: [int], wanted: int) -> int? {
+    for i in 0..len(values) {
+        if values[i] == wanted {
+            return i;
+        }
+    }
+    return null;
+}
+
+fn main() {
+    let found = find([4, 5], 5);
+    if found != null {
+        print(found + 1);
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0213",
+        title: "Integer out of range",
+        text: r#"An integer literal, or a constant expression, does not fit the integer
+type it has, such as 300 for a `u8`. Use a wider type, or a value in
+range."#,
+        wrong: r#"fn main() {
+    let level: u8 = 300;
+    print(level);
+}
+"#,
+        fixed: r#"fn main() {
+    let level: u16 = 300;
+    print(level);
+}
+"#,
+    },
+    Explanation {
+        code: "E0214",
+        title: "Value cannot be printed",
+        text: r#"`print` and `printf` print numbers, bools and strings, and optionals of
+them. Print the fields of a struct or the elements of an array one by
+one."#,
+        wrong: r#"fn main() {
+    let values = [1, 2];
+    print(values);
+}
+"#,
+        fixed: r#"fn main() {
+    let values = [1, 2];
+    for i in 0..len(values) {
+        print(values[i]);
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0215",
+        title: "Invalid printf format",
+        text: r#"The format of `printf` must be a string literal, so that its `{}`
+placeholders can be checked against the arguments, and its braces must
+match: write `{{` and `}}` for a brace of its own."#,
+        wrong: r#"fn main() {
+    printf("{ is a brace\n");
+}
+"#,
+        fixed: r#"fn main() {
+    printf("{{ is a brace\n");
+}
+"#,
+    },
+    Explanation {
+        code: "E0216",
+        title: "Generic function used as a value",
+        text: r#"A generic function has a copy for each type it is called with, so there
+is no single function to store in a variable or pass along. Wrap the call
+in a lambda, which fixes the types."#,
+        wrong: r#"fn identity<T>(x: T) -> T {
+    return x;
+}
+
+fn apply(f: fn(int) -> int, x: int) -> int {
+    return f(x);
+}
+
+fn main() {
+    print(apply(identity, 3));
+}
+"#,
+        fixed: r#"fn identity<T>(x: T) -> T {
+    return x;
+}
+
+fn apply(f: fn(int) -> int, x: int) -> int {
+    return f(x);
+}
+
+fn main() {
+    print(apply(|x: int| identity(x), 3));
+}
+"#,
+    },
+    Explanation {
+        code: "E0217",
+        title: "Generic function marked pub",
+        text: r#"A `pub` function keeps its name in the compiled code so that C can call
+it, and C needs a single signature for it. A generic function has one per
+type it is called with, so it cannot be `pub`: export a function for each
+type instead."#,
+        wrong: r#"pub fn identity<T>(x: T) -> T {
+    return x;
+}
+
+fn main() {
+    print(identity(3));
+}
+"#,
+        fixed: r#"fn identity<T>(x: T) -> T {
+    return x;
+}
+
+pub fn identity_int(x: int) -> int {
+    return identity(x);
+}
+
+fn main() {
+    print(identity_int(3));
+}
+"#,
+    },
+    Explanation {
+        code: "E0218",
+        title: "Extern function with a type C cannot take",
+        text: r#"Only numbers, bools and strings can be passed to and returned from C.
+Arrays, structs, enums and functions have no representation C functions
+agree on."#,
+        wrong: r#"extern fn sum(values: [int]) -> int;
+
+fn main() {
+    print(sum([1, 2]));
+}
+"#,
+        fixed: r#"extern fn abs(value: i32) -> i32;
+
+fn main() {
+    print(abs(-2 as i32));
+}
+"#,
+    },
+    Explanation {
+        code: "E0219",
+        title: "Extern function declared in a block",
+        text: r#"Extern functions declare symbols of the whole program, so they are
+declared at the top level of a file, not inside a function."#,
+        wrong: r#"fn main() {
+    extern fn puts(s: string) -> int;
+    puts("hello");
+}
+"#,
+        fixed: r#"extern fn puts(s: string) -> int;
+
+fn main() {
+    puts("hello");
+}
+"#,
+    },
+    Explanation {
+        code: "E0220",
+        title: "Struct containing itself",
+        text: r#"A struct has a field of its own type, directly or through other structs,
+so a value would contain itself forever. Store the nested value in an
+array, which holds its elements elsewhere in memory and may be empty."#,
+        wrong: r#"struct Node {
+    value: int,
+    next: Node,
+}
+
+pub fn value(node: Node) -> int {
+    return node.value;
+}
+"#,
+        fixed: r#"struct Node {
+    value: int,
+    next: [Node],
+}
+
+pub fn sum(node: Node) -> int {
+    if len(node.next) == 0 {
+        return node.value;
+    }
+    return node.value + sum(node.next[0]);
+}
+"#,
+    },
+    Explanation {
+        code: "E0221",
+        title: "Constant not computable at compile time",
+        text: r#"The value of a `const` must be computed while compiling, so it can only
+involve literals, other constants and operators, not variables or function
+calls. Use `let` for values computed as the program runs."#,
+        wrong: r#"fn size() -> int {
+    return 4;
+}
+
+fn main() {
+    const AREA = size() * size();
+    print(AREA);
+}
+"#,
+        fixed: r#"fn size() -> int {
+    return 4;
+}
+
+fn main() {
+    let area = size() * size();
+    print(area);
+}
+"#,
+    },
+    Explanation {
+        code: "E0222",
+        title: "Assignment to a constant",
+        text: r#"A constant keeps the value it is declared with. Declare the name with
+`let` to change it."#,
+        wrong: r#"fn main() {
+    const LIMIT = 10;
+    LIMIT = 20;
+    print(LIMIT);
+}
+"#,
+        fixed: r#"fn main() {
+    let limit = 10;
+    limit = 20;
+    print(limit);
+}
+"#,
+    },
+    Explanation {
+        code: "E0223",
+        title: "Assignment to a captured variable",
+        text: r#"A lambda captures the variables it uses by value, so assigning to one
+inside it would change the lambda's copy and not the variable. Return the
+new value from the lambda instead and assign it outside."#,
+        wrong: r#"fn main() {
+    let count = 0;
+    let increment = || { count = count + 1; };
+    increment();
+    print(count);
+}
+"#,
+        fixed: r#"fn main() {
+    let count = 0;
+    let increment = |n: int| n + 1;
+    count = increment(count);
+    print(count);
+}
+"#,
+    },
+    Explanation {
+        code: "E0224",
+        title: "Constant expression cannot be evaluated",
+        text: r#"Evaluating a constant expression while compiling overflowed an integer or
+divided by zero, which the program would do every time it ran."#,
+        wrong: r#"fn main() {
+    const MILLIS = 1000 / 0;
+    print(MILLIS);
+}
+"#,
+        fixed: r#"fn main() {
+    const MILLIS = 1000 / 1;
+    print(MILLIS);
+}
+"#,
+    },
+    Explanation {
+        code: "E0225",
+        title: "pub function named like C or the runtime",
+        text: r#"A `pub` function keeps its name in the compiled code, where it would clash
+with a C keyword, a function of the C library, such as `puts`, or the
+runtime, whose names start with `z_`. Give the function another name."#,
+        wrong: r#"pub fn puts(s: string) {
+    print(s);
+}
+
+fn main() {
+    puts("hello");
+}
+"#,
+        fixed: r#"pub fn say(s: string) {
+    print(s);
+}
+
+fn main() {
+    say("hello");
+}
+"#,
+    },
+    Explanation {
+        code: "E0301",
+        title: "Match on a value that is not an enum",
+        text: r#"`match` chooses between the variants of an enum. Use `if` and `else` to
+choose by a number, a bool or a string."#,
+        wrong: r#"fn main() {
+    let n = 2;
+    match n {
+        _ => print(n),
+    }
+}
+"#,
+        fixed: r#"fn main() {
+    let n = 2;
+    if n == 2 {
+        print(n);
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0302",
+        title: "Match arms of different types",
+        text: r#"The arms of a `match` that produces a value must all produce a value of
+the same type."#,
+        wrong: r#"enum Answer {
+    Yes,
+    No,
+}
+
+fn main() {
+    let answer = Answer.Yes;
+    let text = match answer {
+        Answer.Yes => "yes",
+        Answer.No => 0,
+    };
+    print(text);
+}
+"#,
+        fixed: r#"enum Answer {
+    Yes,
+    No,
+}
+
+fn main() {
+    let answer = Answer.Yes;
+    let text = match answer {
+        Answer.Yes => "yes",
+        Answer.No => "no",
+    };
+    print(text);
+}
+"#,
+    },
+    Explanation {
+        code: "E0303",
+        title: "Match does not cover every variant",
+        text: r#"A `match` has no arm for some variants of the enum, so it would not know
+what to do with them. Add arms for the missing variants, or a `_` arm for
+all the others."#,
+        wrong: r#"enum Light {
+    Red,
+    Amber,
+    Green,
+}
+
+fn main() {
+    let light = Light.Amber;
+    match light {
+        Light.Red => print("stop"),
+        Light.Green => print("go"),
+    }
+}
+"#,
+        fixed: r#"enum Light {
+    Red,
+    Amber,
+    Green,
+}
+
+fn main() {
+    let light = Light.Amber;
+    match light {
+        Light.Red => print("stop"),
+        Light.Green => print("go"),
+        _ => print("wait"),
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0304",
+        title: "Pattern of another enum",
+        text: r#"A pattern names a variant of another enum than the one the `match` is
+on."#,
+        wrong: r#"enum Light {
+    Red,
+    Green,
+}
+
+enum Switch {
+    On,
+    Off,
+}
+
+fn main() {
+    let light = Light.Red;
+    match light {
+        Switch.On => print("on"),
+        _ => print("off"),
+    }
+}
+"#,
+        fixed: r#"enum Light {
+    Red,
+    Green,
+}
+
+fn main() {
+    let light = Light.Red;
+    match light {
+        Light.Red => print("red"),
+        _ => print("green"),
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0305",
+        title: "Pattern binding the wrong number of values",
+        text: r#"A pattern binds more or fewer names than the variant has values. Bind one
+name for each value, using `_` for those not needed."#,
+        wrong: r#"enum Shape {
+    Rect(int, int),
+}
+
+fn main() {
+    let shape = Shape.Rect(2, 3);
+    match shape {
+        Shape.Rect(width) => print(width),
+    }
+}
+"#,
+        fixed: r#"enum Shape {
+    Rect(int, int),
+}
+
+fn main() {
+    let shape = Shape.Rect(2, 3);
+    match shape {
+        Shape.Rect(width, _) => print(width),
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0306",
+        title: "`?` the function cannot pass on",
+        text: r#"`value?` returns `None` or `Err(e)` from the function when `value` is one,
+so it needs a function returning an `Option`, for an `Option`, or a `Result`
+with the same error type, for a `Result`. Change the return type, or match
+on the value instead."#,
+        wrong: r#"fn main() {
+    let value: Option<int> = Some(1);
+    print(value?);
+}
+"#,
+        fixed: r#"fn twice(value: Option<int>) -> Option<int> {
+    return Some(value? * 2);
+}
+
+fn main() {
+    match twice(Some(1)) {
+        Some(n) => print(n),
+        None => print(0),
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0401",
+        title: "Use of a moved value",
+        text: r#"Strings, arrays and structs have a single owner. Assigning one to another
+variable or passing it to a function moves it, and with
+`--strict-memory` the old variable cannot be used after that. Use the
+value before moving it, or move a copy."#,
+        wrong: r#"fn consume(values: [int]) -> int {
+    return len(values);
+}
+
+fn main() {
+    let values = [1, 2, 3];
+    let count = consume(values);
+    print(values[0] + count);
+}
+"#,
+        fixed: r#"fn consume(values: [int]) -> int {
+    return len(values);
+}
+
+fn main() {
+    let values = [1, 2, 3];
+    let first = values[0];
+    let count = consume(values);
+    print(first + count);
+}
+"#,
+    },
+    Explanation {
+        code: "E0402",
+        title: "Move inside a loop",
+        text: r#"With `--strict-memory`, a value declared outside a loop is moved inside
+it, so the next iteration would use it after it moved. Declare the value
+inside the loop, or move it after the loop."#,
+        wrong: r#"fn consume(values: [int]) -> int {
+    return len(values);
+}
+
+fn main() {
+    let values = [1, 2, 3];
+    for _i in 0..2 {
+        print(consume(values));
+    }
+}
+"#,
+        fixed: r#"fn consume(values: [int]) -> int {
+    return len(values);
+}
+
+fn main() {
+    for _i in 0..2 {
+        let values = [1, 2, 3];
+        print(consume(values));
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0403",
+        title: "Move of a borrowed value",
+        text: r#"With `--strict-memory`, a variable that borrows from another value, such
+as a loop variable over an array of strings, cannot be moved, since both
+would then own the same memory."#,
+        wrong: r#"fn keep(name: string) -> string {
+    return name;
+}
+
+fn main() {
+    let names = ["ada", "alan"];
+    for name in names {
+        let kept = keep(name);
+        print(kept);
+    }
+}
+"#,
+        fixed: r#"fn main() {
+    let names = ["ada", "alan"];
+    for name in names {
+        print(name);
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "E0404",
+        title: "Move out of an array element or field",
+        text: r#"With `--strict-memory`, a string, array or struct cannot be moved out of
+the array element or struct field holding it, since both would then own
+the same memory."#,
+        wrong: r#"struct Person {
+    name: string,
+}
+
+fn keep(name: string) -> string {
+    return name;
+}
+
+pub fn name_of(person: Person) -> string {
+    return keep(person.name);
+}
+"#,
+        fixed: r#"struct Person {
+    name: string,
+}
+
+pub fn show(person: Person) {
+    print(person.name);
+}
+"#,
+    },
+    Explanation {
+        code: "E0501",
+        title: "Program the C generator cannot compile",
+        text: r#"The program checks, but generating its C finds something C cannot be given,
+such as a range used as a value rather than iterated over by a `for` loop,
+a library or imported module defining `main` or statements outside
+functions, or an exported function taking a value C has no type for."#,
+        wrong: r#"fn main() {
+    let digits = 0..10;
+    print(1);
+}
+"#,
+        fixed: r#"fn main() {
+    for digit in 0..10 {
+        print(digit);
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "W0001",
+        title: "Unused variable",
+        text: r#"A variable is declared but never read. Remove it, or prefix its name
+with `_` if it is unused on purpose."#,
+        wrong: r#"fn main() {
+    let unused = 3;
+    print(1);
+}
+"#,
+        fixed: r#"fn main() {
+    let _unused = 3;
+    print(1);
+}
+"#,
+    },
+    Explanation {
+        code: "W0002",
+        title: "Function never called",
+        text: r#"A function is declared but nothing calls it. Remove it, make it `pub` if
+other programs call it, or mark it `@bench` if it is a benchmark."#,
+        wrong: r#"fn helper() -> int {
+    return 1;
+}
+
+fn main() {
+    print(2);
+}
+"#,
+        fixed: r#"pub fn helper() -> int {
+    return 1;
+}
+
+fn main() {
+    print(2);
+}
+"#,
+    },
+    Explanation {
+        code: "W0003",
+        title: "Function only called by dead code",
+        text: r#"A function is called, but only by functions that `main` never reaches,
+so it never runs either."#,
+        wrong: r#"fn inner() -> int {
+    return 1;
+}
+
+fn outer() -> int {
+    return inner();
+}
+
+fn main() {
+    print(2);
+}
+"#,
+        fixed: r#"fn inner() -> int {
+    return 1;
+}
+
+fn outer() -> int {
+    return inner();
+}
+
+fn main() {
+    print(outer());
+}
+"#,
+    },
+    Explanation {
+        code: "W0004",
+        title: "Field never read",
+        text: r#"A field of a struct is set but never read anywhere in the program.
+Remove it, or use it."#,
+        wrong: r#"struct Point {
+    x: int,
+    y: int,
+}
+
+pub fn x_of(p: Point) -> int {
+    return p.x;
+}
+"#,
+        fixed: r#"struct Point {
+    x: int,
+    y: int,
+}
+
+pub fn sum(p: Point) -> int {
+    return p.x + p.y;
+}
+"#,
+    },
+    Explanation {
+        code: "L0001",
+        title: "Variable shadows another",
+        text: r#"A `let`, `for` or `match` binding has the name of a variable, parameter or
+constant of an enclosing scope, which is then out of reach until the inner
+scope ends. It is legal, but easy to misread: give one of them another name.
+Names starting with `_` are not reported. Set `shadowing` in the `[lint]`
+table of `z.toml` to change how this is reported."#,
+        wrong: r#"fn main() {
+    let total = 10;
+    if total > 5 {
+        let total = total * 2;
+        print(total);
+    }
+}
+"#,
+        fixed: r#"fn main() {
+    let total = 10;
+    if total > 5 {
+        let doubled = total * 2;
+        print(doubled);
+    }
+}
+"#,
+    },
+    Explanation {
+        code: "L0002",
+        title: "Name not in the expected case",
+        text: r#"Functions, parameters, variables and fields are written in snake_case,
+structs, enums, variants and type parameters in CamelCase and constants in
+UPPER_SNAKE_CASE. The message shows the name in the expected case. Leading
+underscores are kept, and extern functions are named as in C. Set `naming`
+in the `[lint]` table of `z.toml` to change how this is reported."#,
+        wrong: r#"const maxRetries: int = 3;
+
+fn retryCount() -> int {
+    return maxRetries;
+}
+
+fn main() {
+    print(retryCount());
+}
+"#,
+        fixed: r#"const MAX_RETRIES: int = 3;
+
+fn retry_count() -> int {
+    return MAX_RETRIES;
+}
+
+fn main() {
+    print(retry_count());
+}
+"#,
+    },
+    Explanation {
+        code: "L0003",
+        title: "Function too long",
+        text: r#"A function spans more lines than `max-function-lines` in the `[lint]`
+table of `z.toml`, 50 unless set. Long functions are hard to follow: move
+parts of it into functions of their own. The examples assume
+`max-function-lines = 5`. Set `long-functions` to change how this is
+reported."#,
+        wrong: r#"fn main() {
+    let width = 3;
+    let height = 4;
+    print(width * height);
+    print(width + width + height + height);
+}
+"#,
+        fixed: r#"fn report(width: int, height: int) {
+    print(width * height);
+    print(width + width + height + height);
+}
+
+fn main() {
+    report(3, 4);
+}
+"#,
+    },
+    Explanation {
+        code: "L0004",
+        title: "Condition always true or false",
+        text: r#"The condition of an `if` or `while` can be worked out before the program
+runs, from literals and constants, so one branch is never taken. It is
+often a debugging leftover or a typo. `while true`, the way Z writes a loop
+that runs until it returns, is not reported. Set `constant-conditions` in
+the `[lint]` table of `z.toml` to change how this is reported."#,
+        wrong: r#"const LIMIT: int = 10;
+
+fn main() {
+    if LIMIT > 100 {
+        print(LIMIT);
+    }
+}
+"#,
+        fixed: r#"const LIMIT: int = 10;
+
+fn main() {
+    let value = 150;
+    if value > LIMIT {
+        print(value);
+    }
+}
+"#,
+    },
+];
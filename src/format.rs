@@ -0,0 +1,74 @@
+// Formatted output. `print(value)` writes a value followed by a newline, and
+// `printf(format, values...)` writes its format with each `{}` replaced by the
+// next value, without adding a newline. `{{` and `}}` write single braces.
+//
+// The format must be a string literal, so the typechecker can match its
+// placeholders to the values, and each backend writes a value by its checked
+// type rather than through a signature.
+
+use crate::ast::Type;
+#[cfg(feature = "codegen")]
+use crate::ast::{Expr, Literal};
+
+/// Part of a format: text written as it is, or the place of the next value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Text(String),
+    Value,
+}
+
+/// Splits a format into text and placeholders
+pub fn parse(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '{' if chars.peek() == Some(&'}') => {
+                chars.next();
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Value);
+            }
+            '{' => return Err("Unmatched { in format; write {{ for a brace".to_string()),
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => return Err("Unmatched } in format; write }} for a brace".to_string()),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+
+    Ok(pieces)
+}
+
+/// Whether `name` is one of the printing builtins
+pub fn is_print(name: &str) -> bool {
+    name == "print" || name == "printf"
+}
+
+/// What a checked call of `print` or `printf` writes: the pieces of its
+/// format, and the values for their placeholders
+#[cfg(feature = "codegen")]
+pub fn call_pieces<'a>(name: &str, args: &'a [Expr]) -> Option<(Vec<Piece>, &'a [Expr])> {
+    match (name, args) {
+        ("print", [_]) => Some((vec![Piece::Value, Piece::Text("\n".to_string())], args)),
+        ("printf", [Expr::Literal(Literal::String(format), _), values @ ..]) => Some((parse(format).ok()?, values)),
+        _ => None,
+    }
+}
+
+/// Whether values of `ty` can be printed
+pub fn is_printable(ty: &Type) -> bool {
+    ty.is_integer() || matches!(ty, Type::Float | Type::Bool | Type::String | Type::Inferred)
+}
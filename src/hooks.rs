@@ -0,0 +1,37 @@
+// Hooks into the stages of `Compiler`, for tools built on the compiler:
+// research prototypes, custom lints and instrumentation. An observer sees
+// each artifact of the file compiled as the stage generating it finishes,
+// and may change it before the next stage runs. The modules the file
+// imports are compiled without it.
+
+use crate::ast::Program;
+use crate::ir;
+use crate::lexer::Span;
+use std::fmt;
+
+/// Watches the stages a file goes through. Each method is called with the
+/// artifact of its stage, which the following stages use as the method
+/// leaves it; they do nothing unless implemented.
+pub trait Observer {
+    /// The tokens of the file
+    fn on_tokens(&mut self, _tokens: &mut Vec<Span>) {}
+
+    /// The syntax tree as parsed, before the imported modules are loaded
+    fn on_ast(&mut self, _program: &mut Program) {}
+
+    /// The syntax tree as checked, before the optimization passes
+    fn on_typed_ast(&mut self, _program: &mut Program) {}
+
+    /// The intermediate representation after the optimization passes, for
+    /// the executables it covers
+    fn on_ir(&mut self, _module: &mut ir::Module) {}
+
+    /// The generated C
+    fn on_c(&mut self, _code: &mut String) {}
+}
+
+impl fmt::Debug for dyn Observer + Send {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
@@ -0,0 +1,378 @@
+// Checking that redoes only what an edit affects, for tools that check the
+// same files over and over as they change, such as editors and watchers. A
+// `Database` holds the text of each file and remembers the result of each
+// query on it, with a fingerprint of everything the query read. Asking again
+// runs the query only when the fingerprint changed:
+//
+//     parse(file)              the text of the file
+//     check_top_level(file)    the text of the file but the bodies of its
+//                              functions, which of them never return, and
+//                              the functions each module it imports offers
+//                              it
+//     check_function(file, f)  the same, and the body of the function f
+//     check(file)              the text of the file, and what the queries
+//                              on its parts found
+//
+// Editing the body of a function reparses its file and checks that body
+// again, but not the other functions of the file, nor the files importing
+// it, since the functions it offers them are the same. What checking the
+// other parts found is moved along with their text. Generic functions are
+// checked with each instantiation, wherever it is, so their bodies count as
+// top-level code.
+
+use crate::ast::{ImportedFunction, Program, Stmt};
+use crate::diagnostic::ToDiagnostic;
+use crate::modules::{self, ImportPaths};
+use crate::source_map::{FileId, SourceMap};
+use crate::stdlib;
+use crate::symbols::SymbolTable;
+use crate::typechecker::{self, CheckOptions, CheckedUnit, Unit};
+use crate::{diagnostic, lexer, parser, CompilerError, Diagnostic, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A query a `Database` answers
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Query {
+    /// The syntax tree of a file
+    Parse,
+    /// What checking a file but the bodies of its functions finds
+    CheckTopLevel,
+    /// What checking the body of a function of a file finds
+    CheckFunction(String),
+    /// What checking a file finds, put together from the queries on its parts
+    Check,
+}
+
+/// A file as checked
+#[derive(Debug, Clone)]
+pub struct CheckedFile {
+    pub program: Program,
+    /// Every warning found, whatever its level
+    pub warnings: Vec<Diagnostic>,
+    pub symbols: SymbolTable,
+    /// The functions the file offers the modules importing it
+    pub interface: Vec<ImportedFunction>,
+}
+
+// The result of a query, and the fingerprint of what it read. Failures
+// other than diagnostics, such as files that cannot be read, are not
+// remembered.
+struct Memo<T> {
+    fingerprint: u64,
+    value: std::result::Result<Arc<T>, Vec<Diagnostic>>,
+}
+
+impl<T> Memo<T> {
+    fn get(&self) -> Result<Arc<T>> {
+        self.value.clone().map_err(CompilerError::Diagnostics)
+    }
+}
+
+// What checking a unit of a file found, and where the bodies of the
+// functions of the file were then, to move the spans of the unit as edits
+// to the other bodies move its text
+struct UnitMemo {
+    fingerprint: u64,
+    bodies: Vec<Range<usize>>,
+    unit: CheckedUnit,
+}
+
+// Where the functions of a file have their bodies, which the units of the
+// file are checked apart by
+struct Layout {
+    functions: Vec<(String, Range<usize>)>,
+    // The fingerprint of everything else: the text of the file but those
+    // bodies, the functions the file imports, the functions of the file that
+    // never return, whose bodies the others depend on, and the options
+    top_level: u64,
+}
+
+/// The files of a program, and what the queries on them found
+#[derive(Default)]
+pub struct Database {
+    files: SourceMap,
+    paths: ImportPaths,
+    options: CheckOptions,
+    // The name each imported file is imported by
+    modules: HashMap<FileId, String>,
+    parsed: HashMap<FileId, Memo<Program>>,
+    checked: HashMap<FileId, Memo<CheckedFile>>,
+    units: HashMap<(FileId, Unit), UnitMemo>,
+    // Files being checked, each imported by the one before, to find cycles
+    checking: Vec<FileId>,
+    executed: Vec<(Query, FileId)>,
+}
+
+impl Database {
+    /// A database finding the modules files import in `paths`
+    pub fn new(paths: ImportPaths) -> Self {
+        Database {
+            paths,
+            ..Default::default()
+        }
+    }
+
+    /// Rejects uses of strings, arrays and structs after they have been
+    /// moved, as `zc compile --strict-memory` does
+    pub fn strict_memory(&mut self, strict_memory: bool) -> &mut Self {
+        self.options.strict_memory = strict_memory;
+        self
+    }
+
+    /// Sets the text of the file `name`, as an editor does when it changes.
+    /// Queries on the file and on those importing it give the new results
+    /// from then on.
+    pub fn set_source(&mut self, name: &str, source: &str) -> FileId {
+        self.files.add(name, source)
+    }
+
+    /// Reads the file `path` into the database, again if it was read before
+    pub fn load(&mut self, path: &Path) -> Result<FileId> {
+        let source = fs::read_to_string(path)?;
+        Ok(self.set_source(&path.display().to_string(), &source))
+    }
+
+    /// The files read, with the modules they import
+    pub fn files(&self) -> &SourceMap {
+        &self.files
+    }
+
+    /// The queries run since this was last called, rather than answered
+    /// from what the database remembered
+    pub fn take_executed(&mut self) -> Vec<(Query, FileId)> {
+        std::mem::take(&mut self.executed)
+    }
+
+    /// The syntax tree of `file`, as parsed
+    pub fn parse(&mut self, file: FileId) -> Result<Arc<Program>> {
+        let fingerprint = fingerprint(&self.files.file(file).source);
+        if let Some(memo) = self.parsed.get(&file).filter(|memo| memo.fingerprint == fingerprint) {
+            return memo.get();
+        }
+
+        self.executed.push((Query::Parse, file));
+        let source = &self.files.file(file).source;
+        let value = lexer::lex(source)
+            .map_err(|e| self.files.diagnostics(file, &[e]))
+            .and_then(|tokens| parser::parse_file(tokens, file).map_err(|e| self.files.diagnostics(file, &[e])))
+            .map(Arc::new);
+        let memo = Memo { fingerprint, value };
+        let program = memo.get();
+        self.parsed.insert(file, memo);
+        program
+    }
+
+    /// What checking `file` finds, after checking the modules it imports
+    pub fn check(&mut self, file: FileId) -> Result<Arc<CheckedFile>> {
+        let parsed = self.parse(file)?;
+        if let Some(start) = self.checking.iter().position(|checking| *checking == file) {
+            let mut cycle: Vec<&str> = self.checking[start..].iter().map(|id| self.files.file(*id).name.as_str()).collect();
+            cycle.push(&self.files.file(file).name);
+            return Err(CompilerError::TypeError(format!("Modules import each other: {}", cycle.join(" -> "))));
+        }
+        self.checking.push(file);
+        let imports = self.imports_of(file, &parsed);
+        self.checking.pop();
+        let imports = imports?;
+
+        let fingerprint = fingerprint(&(&self.files.file(file).source, self.options.strict_memory, &imports));
+        if let Some(memo) = self.checked.get(&file).filter(|memo| memo.fingerprint == fingerprint) {
+            return memo.get();
+        }
+
+        self.executed.push((Query::Check, file));
+        let mut program = Program::clone(&parsed);
+        program.imports = imports;
+        let layout = self.layout(file, &program);
+        let mut units = vec![Unit::TopLevel];
+        for (name, _) in &layout.functions {
+            let unit = Unit::Function(name.clone());
+            if !units.contains(&unit) {
+                units.push(unit);
+            }
+        }
+        for unit in &units {
+            self.check_unit(file, &program, unit, &layout);
+        }
+        // Those of functions since removed
+        self.units.retain(|(id, unit), _| *id != file || units.contains(unit));
+
+        let checked: Vec<&CheckedUnit> = units.iter().map(|unit| &self.units[&(file, unit.clone())].unit).collect();
+        let value = match typechecker::merge(&program, &checked) {
+            Ok(checked) => {
+                let source = self.files.file(file);
+                let name = self.modules.get(&file).map_or(source.name.as_str(), String::as_str);
+                Ok(Arc::new(CheckedFile {
+                    interface: modules::interface(name, &checked.program),
+                    warnings: checked
+                        .warnings
+                        .iter()
+                        .map(|warning| warning.to_diagnostic(&source.name, &source.source))
+                        .collect(),
+                    program: checked.program,
+                    symbols: checked.symbols,
+                }))
+            }
+            Err(errors) => Err(self.files.diagnostics(file, &errors)),
+        };
+        let memo = Memo { fingerprint, value };
+        let checked = memo.get();
+        self.checked.insert(file, memo);
+        checked
+    }
+
+    /// The errors of `file`, or else its warnings at the levels
+    /// `set_warning_levels` set, leaving out those allowed
+    pub fn diagnostics(&mut self, file: FileId) -> Result<Vec<Diagnostic>> {
+        match self.check(file) {
+            Ok(checked) => Ok(checked
+                .warnings
+                .iter()
+                .filter_map(|warning| diagnostic::leveled(warning.clone(), false))
+                .collect()),
+            Err(CompilerError::Diagnostics(errors)) => Ok(errors),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks `unit` of `file`, whose syntax tree with the functions it
+    /// imports is `program`, unless what checking it found is remembered
+    fn check_unit(&mut self, file: FileId, program: &Program, unit: &Unit, layout: &Layout) {
+        let source = &self.files.file(file).source;
+        let fingerprint = match unit {
+            Unit::TopLevel => layout.top_level,
+            Unit::Function(name) => {
+                let bodies: Vec<&str> = layout
+                    .functions
+                    .iter()
+                    .filter(|(function, _)| function == name)
+                    .map(|(_, body)| &source[body.clone()])
+                    .collect();
+                self::fingerprint(&(layout.top_level, bodies))
+            }
+        };
+        let bodies: Vec<Range<usize>> = layout.functions.iter().map(|(_, body)| body.clone()).collect();
+        let key = (file, unit.clone());
+        if let Some(memo) = self.units.get_mut(&key).filter(|memo| memo.fingerprint == fingerprint) {
+            if memo.bodies != bodies {
+                let old = std::mem::replace(&mut memo.bodies, bodies);
+                memo.unit.move_spans(|position| moved(position, &old, &memo.bodies));
+            }
+            return;
+        }
+
+        self.executed.push((
+            match unit {
+                Unit::TopLevel => Query::CheckTopLevel,
+                Unit::Function(name) => Query::CheckFunction(name.clone()),
+            },
+            file,
+        ));
+        let checked = typechecker::typecheck_unit(program.clone(), unit.clone(), &self.options);
+        self.units.insert(
+            key,
+            UnitMemo {
+                fingerprint,
+                bodies,
+                unit: checked,
+            },
+        );
+    }
+
+    /// Where the functions of `file`, whose syntax tree is `program`, have
+    /// their bodies
+    fn layout(&self, file: FileId, program: &Program) -> Layout {
+        let functions: Vec<(String, Range<usize>)> = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Function(name, type_params, _, _, body, ..) if type_params.is_empty() => {
+                    Some((name.clone(), body.span().start..body.span().end))
+                }
+                _ => None,
+            })
+            .collect();
+        let source = &self.files.file(file).source;
+        let mut rest = Vec::new();
+        let mut start = 0;
+        for (_, body) in &functions {
+            rest.push(&source[start..body.start]);
+            start = body.end;
+        }
+        rest.push(&source[start..]);
+        Layout {
+            top_level: fingerprint(&(
+                rest,
+                &program.imports,
+                typechecker::never_returning_of(program),
+                self.options.strict_memory,
+            )),
+            functions,
+        }
+    }
+
+    /// The functions of the modules `program`, the syntax tree of `file`,
+    /// imports, checking them first
+    fn imports_of(&mut self, file: FileId, program: &Program) -> Result<Vec<ImportedFunction>> {
+        let package = self.modules.get(&file).and_then(|name| self.paths.package_of(name));
+        let mut imports = Vec::new();
+        let mut seen = HashSet::new();
+        for stmt in &program.statements {
+            if let Stmt::Import(name, _) = stmt {
+                let name = self.paths.qualify(name, package.as_deref());
+                // The typechecker provides std.math itself
+                if name != stdlib::MATH && seen.insert(name.clone()) {
+                    let module = self.module(&name)?;
+                    imports.extend(self.check(module)?.interface.iter().cloned());
+                }
+            }
+        }
+        Ok(imports)
+    }
+
+    /// The file of module `name`, read when it is first imported
+    fn module(&mut self, name: &str) -> Result<FileId> {
+        if let Some((file, source)) = stdlib::load(name) {
+            let path = file.display().to_string();
+            let id = match self.files.find(&path) {
+                Some(id) => id,
+                None => self.set_source(&path, source),
+            };
+            self.modules.insert(id, name.to_string());
+            return Ok(id);
+        }
+        let path = self.paths.file_of(name);
+        let id = match self.files.find(&path.display().to_string()) {
+            Some(id) => id,
+            None => self.load(&path).map_err(|e| {
+                CompilerError::TypeError(format!("Cannot import {}, since {} cannot be read: {}", name, path.display(), e))
+            })?,
+        };
+        self.modules.insert(id, name.to_string());
+        Ok(id)
+    }
+}
+
+/// Where `position` in a file is after the bodies of its functions at `old`
+/// moved to `new`, the rest of the file staying the same
+fn moved(position: usize, old: &[Range<usize>], new: &[Range<usize>]) -> usize {
+    let mut moved = position;
+    for (old, new) in old.iter().zip(new) {
+        if old.end <= position {
+            moved = moved + new.len() - old.len();
+        }
+    }
+    moved
+}
+
+fn fingerprint(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,1196 @@
+// Tree-walking interpreter for Z, used for evaluating expressions in-process
+
+use crate::ast::{BinaryOp, Expr, Literal, Pattern, Program, Span, Stmt, Type, UnaryOp};
+use crate::format::{self, Piece};
+use crate::stdlib;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Maximum call depth before the interpreter reports a stack overflow
+const MAX_CALL_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    /// A value of one of the sized integer types, `i8` to `u64`, which
+    /// wraps and overflows as that type does in compiled programs
+    Sized(Type, i128),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<Value>),
+    Enum(String, String, Vec<Value>),
+    /// A struct's name and its fields, in the order they are declared
+    Struct(String, Vec<(String, Value)>),
+    Function(Function),
+    Null,
+    Void,
+}
+
+/// A function used as a value
+#[derive(Debug, Clone)]
+pub enum Function {
+    Named(String),
+    Lambda(Arc<Closure>),
+}
+
+/// A lambda with the variables visible where it was created, which it
+/// captures by value. Shared with `Arc` since values are sent to the
+/// debugger thread.
+#[derive(Debug)]
+pub struct Closure {
+    params: Vec<(String, Option<Type>)>,
+    body: Expr,
+    captured: HashMap<String, Value>,
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Function::Named(a), Function::Named(b)) => a == b,
+            (Function::Lambda(a), Function::Lambda(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    pub fn type_of(&self) -> Type {
+        match self {
+            Value::Int(_) => Type::Int,
+            Value::Sized(ty, _) => ty.clone(),
+            Value::Float(_) => Type::Float,
+            Value::Bool(_) => Type::Bool,
+            Value::String(_) => Type::String,
+            Value::Array(elements) => Type::Array(Box::new(
+                elements.first().map(Value::type_of).unwrap_or(Type::Inferred),
+            )),
+            Value::Enum(name, _, _) => Type::Enum(name.clone()),
+            Value::Struct(name, _) => Type::Struct(name.clone()),
+            // Parameter types are not kept at runtime
+            Value::Function(_) => Type::Inferred,
+            Value::Null => Type::Optional(Box::new(Type::Inferred)),
+            Value::Void => Type::Void,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Sized(_, i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Enum(name, variant, payload) => {
+                write!(f, "{}.{}", name, variant)?;
+                if !payload.is_empty() {
+                    write!(f, "(")?;
+                    for (i, value) in payload.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", value)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Value::Struct(name, fields) => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Function(Function::Named(name)) => write!(f, "<fn {}>", name),
+            Value::Function(Function::Lambda(_)) => write!(f, "<lambda>"),
+            Value::Null => write!(f, "null"),
+            Value::Void => write!(f, "void"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InterpreterError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "Runtime error at position {}: {}", span.start, self.message),
+            None => write!(f, "Runtime error: {}", self.message),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, InterpreterError>;
+
+/// Non-local exits that unwind through statement execution
+enum Unwind {
+    Return(Value),
+    Paused,
+    Error(InterpreterError),
+}
+
+impl From<InterpreterError> for Unwind {
+    fn from(error: InterpreterError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type Exec<T> = std::result::Result<T, Unwind>;
+
+fn error<T>(message: String, span: &Span) -> Exec<T> {
+    Err(Unwind::Error(InterpreterError {
+        message,
+        span: Some(span.clone()),
+    }))
+}
+
+/// A snapshot of one active call, as shown in a debugger's call stack
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "dap"), allow(dead_code))]
+pub struct StackFrame {
+    pub function: String,
+    /// Span of the statement currently executing in this frame
+    pub span: Span,
+    pub variables: Vec<(String, Value)>,
+}
+
+/// Observer notified before every statement, used to implement stepping
+/// and breakpoints on top of the interpreter
+pub trait DebugHook {
+    /// Returns whether execution should stop before the statement at `span`.
+    /// `depth` is the current call depth, with `main` at depth 1.
+    fn should_stop(&mut self, span: &Span, depth: usize) -> bool;
+
+    /// Called when stopped, innermost frame first. Execution resumes when
+    /// this returns.
+    fn stopped(&mut self, frames: Vec<StackFrame>);
+
+    /// Receives text printed by the program
+    fn output(&mut self, text: &str);
+}
+
+/// The parameters, return type and body of a function the program defines
+type UserFunction = (Vec<(String, Type)>, Type, Rc<Stmt>);
+
+/// A suspended caller, saved while a callee executes
+struct CallFrame {
+    function: String,
+    scopes: Vec<HashMap<String, Value>>,
+    span: Span,
+}
+
+pub struct Interpreter {
+    // User-defined functions: parameters, return type and body
+    functions: HashMap<String, UserFunction>,
+    // The fields of each struct and the payloads of each enum's variants,
+    // for the values declared without one and the types values are stored as
+    structs: HashMap<String, Vec<(String, Type)>>,
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
+    // C functions declared with `extern`, which only compiled programs can call
+    externs: HashSet<String>,
+    // Variables defined by top-level statements
+    globals: HashMap<String, Value>,
+    // Lexical scopes of the function currently executing
+    scopes: Vec<HashMap<String, Value>>,
+    call_depth: usize,
+    // Callers of the function currently executing, and where they are
+    call_stack: Vec<CallFrame>,
+    current_function: String,
+    current_span: Span,
+    debug_hook: Option<Box<dyn DebugHook + Send>>,
+    // Source offset at which execution should stop, and the scope captured there
+    pause_at: Option<usize>,
+    paused_scope: Option<HashMap<String, Value>>,
+    /// Everything the program printed
+    pub output: String,
+}
+
+impl Interpreter {
+    pub fn new(program: &Program) -> Self {
+        let mut functions = HashMap::new();
+        let mut structs = HashMap::new();
+        let mut enums = HashMap::new();
+        let mut externs = HashSet::new();
+        for stmt in &program.statements {
+            match stmt {
+                Stmt::Function(name, _, params, return_type, body, _, _, _) => {
+                    functions.insert(name.clone(), (params.clone(), return_type.clone(), Rc::new(body.as_ref().clone())));
+                }
+                Stmt::Struct(name, _, fields, _) => {
+                    structs.insert(name.clone(), fields.clone());
+                }
+                Stmt::Enum(name, variants, _) => {
+                    enums.insert(name.clone(), variants.clone());
+                }
+                Stmt::Extern(name, ..) => {
+                    externs.insert(name.clone());
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            functions,
+            structs,
+            enums,
+            externs,
+            globals: HashMap::new(),
+            scopes: Vec::new(),
+            call_depth: 0,
+            call_stack: Vec::new(),
+            current_function: "<top level>".to_string(),
+            current_span: Span::default(),
+            debug_hook: None,
+            pause_at: None,
+            paused_scope: None,
+            output: String::new(),
+        }
+    }
+
+    /// Installs a hook that is consulted before every statement executes.
+    /// While a hook is installed, program output goes to the hook.
+    #[cfg(feature = "dap")]
+    pub fn set_debug_hook(&mut self, hook: Box<dyn DebugHook + Send>) {
+        self.debug_hook = Some(hook);
+    }
+
+    /// Runs the top-level statements followed by `main`, returning main's result
+    pub fn run(&mut self, program: &Program) -> Result<Value> {
+        match self.run_program(program) {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Paused) => Ok(Value::Void),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+
+    /// Runs the program until execution reaches `location` (a byte offset into
+    /// the source), returning the variables visible at that point. Returns
+    /// `None` if the program finishes without reaching the location.
+    pub fn run_until(&mut self, program: &Program, location: usize) -> Result<Option<HashMap<String, Value>>> {
+        self.pause_at = Some(location);
+        match self.run_program(program) {
+            Ok(_) | Err(Unwind::Return(_)) => Ok(None),
+            Err(Unwind::Paused) => Ok(self.paused_scope.take()),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+
+    /// Evaluates an expression with the given variables in scope
+    pub fn evaluate(&mut self, expr: &Expr, scope: HashMap<String, Value>) -> Result<Value> {
+        self.pause_at = None;
+        let saved = std::mem::replace(&mut self.scopes, vec![scope]);
+        let result = self.eval_expression(expr);
+        self.scopes = saved;
+
+        match result {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Paused) => Ok(Value::Void),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+
+    fn run_program(&mut self, program: &Program) -> Exec<Value> {
+        // Only stop in top-level code if the location is not inside a function
+        let pause_in_globals = match self.pause_at {
+            Some(location) => !program.statements.iter().any(|stmt| {
+                matches!(stmt, Stmt::Function(..)) && contains(stmt.span(), location)
+            }),
+            None => false,
+        };
+
+        for stmt in &program.statements {
+            if matches!(
+                stmt,
+                Stmt::Function(..) | Stmt::Struct(..) | Stmt::Enum(..) | Stmt::Import(..) | Stmt::Extern(..)
+            ) {
+                continue;
+            }
+            if pause_in_globals && self.should_pause(stmt) {
+                return self.pause();
+            }
+            self.debug_check(stmt);
+            self.scopes.push(HashMap::new());
+            let result = self.execute_statement(stmt);
+            let scope = self.scopes.pop().unwrap_or_default();
+            self.globals.extend(scope);
+            result?;
+        }
+
+        if self.functions.contains_key("main") {
+            let span = Span::default();
+            self.call_function("main", Vec::new(), &span)
+        } else {
+            Ok(Value::Void)
+        }
+    }
+
+    fn should_pause(&self, stmt: &Stmt) -> bool {
+        let location = match self.pause_at {
+            Some(location) if self.paused_scope.is_none() => location,
+            _ => return false,
+        };
+        let span = stmt.span();
+        let compound = matches!(
+            stmt,
+            Stmt::While(..) | Stmt::For(..) | Stmt::Block(..) | Stmt::Function(..)
+                | Stmt::Expr(Expr::If(..)) | Stmt::Expr(Expr::Block(..))
+        );
+        span.start >= location || (!compound && contains(span, location))
+    }
+
+    fn pause<T>(&mut self) -> Exec<T> {
+        let mut visible = self.globals.clone();
+        for scope in &self.scopes {
+            visible.extend(scope.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        self.paused_scope = Some(visible);
+        Err(Unwind::Paused)
+    }
+
+    fn debug_check(&mut self, stmt: &Stmt) {
+        self.current_span = stmt.span().clone();
+        let depth = self.call_stack.len();
+
+        let stop = match self.debug_hook.as_mut() {
+            Some(hook) => hook.should_stop(stmt.span(), depth),
+            None => false,
+        };
+        if stop {
+            let frames = self.stack_frames();
+            if let Some(hook) = self.debug_hook.as_mut() {
+                hook.stopped(frames);
+            }
+        }
+    }
+
+    fn stack_frames(&self) -> Vec<StackFrame> {
+        fn flatten(scopes: &[HashMap<String, Value>]) -> Vec<(String, Value)> {
+            let mut visible: HashMap<String, Value> = HashMap::new();
+            for scope in scopes {
+                visible.extend(scope.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+            let mut variables: Vec<_> = visible.into_iter().collect();
+            variables.sort_by(|a, b| a.0.cmp(&b.0));
+            variables
+        }
+
+        let mut frames = vec![StackFrame {
+            function: self.current_function.clone(),
+            span: self.current_span.clone(),
+            variables: flatten(&self.scopes),
+        }];
+        for caller in self.call_stack.iter().rev() {
+            frames.push(StackFrame {
+                function: caller.function.clone(),
+                span: caller.span.clone(),
+                variables: flatten(&caller.scopes),
+            });
+        }
+        frames
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Value> {
+        match self.scopes.iter_mut().rev().find(|scope| scope.contains_key(name)) {
+            Some(scope) => scope.get_mut(name),
+            None => self.globals.get_mut(name),
+        }
+    }
+
+    fn define(&mut self, name: String, value: Value) {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(name, value);
+            }
+            None => {
+                self.globals.insert(name, value);
+            }
+        }
+    }
+
+    /// Executes statements in a fresh scope, yielding the value of a trailing expression
+    fn execute_block(&mut self, stmts: &[Stmt]) -> Exec<Value> {
+        self.scopes.push(HashMap::new());
+        let result = self.execute_statements(stmts);
+        self.scopes.pop();
+        result
+    }
+
+    fn execute_statements(&mut self, stmts: &[Stmt]) -> Exec<Value> {
+        let mut last = Value::Void;
+        for (i, stmt) in stmts.iter().enumerate() {
+            if self.should_pause(stmt) {
+                return self.pause();
+            }
+            self.debug_check(stmt);
+            last = match stmt {
+                Stmt::Expr(expr) if i + 1 == stmts.len() => self.eval_expression(expr)?,
+                _ => {
+                    self.execute_statement(stmt)?;
+                    Value::Void
+                }
+            };
+        }
+        Ok(last)
+    }
+
+    fn execute_statement(&mut self, stmt: &Stmt) -> Exec<()> {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.eval_expression(expr)?;
+            }
+            Stmt::Let(name, ty, initializer, _) => {
+                let value = match (initializer, ty) {
+                    (Some(expr), Some(ty)) => typed(self.eval_expression(expr)?, ty),
+                    (Some(expr), None) => self.eval_expression(expr)?,
+                    (None, Some(ty)) => self.zero_value(ty),
+                    (None, None) => Value::Void,
+                };
+                self.define(name.clone(), value);
+            }
+            Stmt::Const(name, ty, value, _) => {
+                let mut value = self.eval_expression(value)?;
+                if let Some(ty) = ty {
+                    value = typed(value, ty);
+                }
+                self.define(name.clone(), value);
+            }
+            Stmt::Assign(target, value, span) => {
+                let value = self.eval_expression(value)?;
+                self.assign(target, value, span)?;
+            }
+            Stmt::Return(value, _) => {
+                let value = match value {
+                    Some(expr) => self.eval_expression(expr)?,
+                    None => Value::Void,
+                };
+                return Err(Unwind::Return(value));
+            }
+            Stmt::While(cond, body, span) => loop {
+                match self.eval_expression(cond)? {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => break,
+                    other => return error(format!("While condition must be a bool, found {}", other.type_of()), span),
+                }
+                self.execute_body(body)?;
+            },
+            Stmt::For(variable, _, iterable, body, span) => {
+                let items = match iterable {
+                    Expr::Range(start, end, _) => {
+                        let (start, end) = (self.eval_expression(start)?, self.eval_expression(end)?);
+                        match (integer(&start), integer(&end)) {
+                            (Some((start, start_type)), Some((end, end_type))) => {
+                                let ty = wider_than_int(start_type, end_type);
+                                (start..end).map(|i| integer_value(i, &ty)).collect()
+                            }
+                            _ => return error("Range bounds must be integers".to_string(), span),
+                        }
+                    }
+                    _ => match self.eval_expression(iterable)? {
+                        Value::Array(elements) => elements,
+                        other => return error(format!("Cannot iterate over {}", other.type_of()), span),
+                    },
+                };
+                for item in items {
+                    self.scopes.push(HashMap::from([(variable.clone(), item)]));
+                    let result = self.execute_body(body);
+                    self.scopes.pop();
+                    result?;
+                }
+            }
+            Stmt::Block(stmts, _) => {
+                self.execute_block(stmts)?;
+            }
+            Stmt::Function(_, _, _, _, _, _, _, span)
+            | Stmt::Struct(_, _, _, span)
+            | Stmt::Enum(_, _, span)
+            | Stmt::Import(_, span)
+            | Stmt::Extern(_, _, _, _, span) => {
+                return error("Declarations are only allowed at the top level".to_string(), span);
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_body(&mut self, body: &Stmt) -> Exec<Value> {
+        match body {
+            Stmt::Block(stmts, _) => self.execute_block(stmts),
+            other => {
+                self.execute_statement(other)?;
+                Ok(Value::Void)
+            }
+        }
+    }
+
+    /// Calls the method `method` of the array `object`, whose elements are
+    /// `elements`, with `args`. Pushing and popping store the changed array
+    /// back where it came from.
+    fn array_method(&mut self, object: &Expr, mut elements: Vec<Value>, method: &str, mut args: Vec<Value>, span: &Span) -> Exec<Value> {
+        let result = match (method, args.pop()) {
+            ("len", None) => return Ok(Value::Int(elements.len() as i64)),
+            // A value pushed takes the type of the elements already there
+            ("push", Some(value)) => {
+                let value = match elements.first() {
+                    Some(first) => typed(value, &first.type_of()),
+                    None => value,
+                };
+                elements.push(value);
+                Value::Void
+            }
+            ("pop", None) => match elements.pop() {
+                Some(value) => value,
+                None => return error("pop from an empty array".to_string(), span),
+            },
+            _ => return error(format!("Invalid arguments for {}", method), span),
+        };
+        self.assign(object, Value::Array(elements), span)?;
+        Ok(result)
+    }
+
+    /// Stores `value` where `target` is: a variable, an element of an
+    /// array or a field of a struct, through any number of those. The value
+    /// takes the type of the one it replaces.
+    fn assign(&mut self, target: &Expr, value: Value, span: &Span) -> Exec<()> {
+        let (name, path) = self.place(target, span)?;
+        let mut slot = match self.lookup_mut(&name) {
+            Some(slot) => slot,
+            None => return error(format!("Undefined variable: {}", name), span),
+        };
+        for step in path {
+            slot = match (step, slot) {
+                (Step::Index(i), Value::Array(elements)) => {
+                    let len = elements.len();
+                    match usize::try_from(i).ok().and_then(|i| elements.get_mut(i)) {
+                        Some(element) => element,
+                        None => return error(format!("Index {} out of bounds for array of length {}", i, len), span),
+                    }
+                }
+                (Step::Field(field), Value::Struct(_, fields)) => match fields.iter_mut().find(|(name, _)| *name == field) {
+                    Some((_, value)) => value,
+                    None => return error(format!("No field {}", field), span),
+                },
+                (Step::Index(_), value) => return error(format!("Cannot index into {}", value.type_of()), span),
+                (Step::Field(field), value) => {
+                    return error(format!("Cannot assign to field {} of {}", field, value.type_of()), span)
+                }
+            };
+        }
+        *slot = typed(value, &slot.type_of());
+        Ok(())
+    }
+
+    /// The variable `target` is in and the elements and fields leading to
+    /// it, with the indices evaluated
+    fn place(&mut self, target: &Expr, span: &Span) -> Exec<(String, Vec<Step>)> {
+        match target {
+            Expr::Identifier(name, _) => Ok((name.clone(), Vec::new())),
+            Expr::Index(array, index, _) => {
+                let (name, mut path) = self.place(array, span)?;
+                match self.eval_expression(index)? {
+                    Value::Int(i) => path.push(Step::Index(i)),
+                    other => return error(format!("Index must be an int, found {}", other.type_of()), span),
+                }
+                Ok((name, path))
+            }
+            Expr::Field(object, field, _) => {
+                let (name, mut path) = self.place(object, span)?;
+                path.push(Step::Field(field.clone()));
+                Ok((name, path))
+            }
+            _ => error("Invalid assignment target".to_string(), span),
+        }
+    }
+
+    /// The value a variable of type `ty` declared without one starts with,
+    /// as in compiled programs: zero, empty or null, and for an enum its
+    /// first variant
+    fn zero_value(&self, ty: &Type) -> Value {
+        match ty {
+            ty if ty.is_integer() => integer_value(0, ty),
+            Type::Float => Value::Float(0.0),
+            Type::Bool => Value::Bool(false),
+            Type::String => Value::String(String::new()),
+            Type::Array(_) => Value::Array(Vec::new()),
+            Type::Optional(_) => Value::Null,
+            Type::Struct(name) => match self.structs.get(name) {
+                Some(fields) => Value::Struct(
+                    name.clone(),
+                    fields.iter().map(|(field, ty)| (field.clone(), self.zero_value(ty))).collect(),
+                ),
+                None => Value::Void,
+            },
+            Type::Enum(name) => match self.enums.get(name).and_then(|variants| variants.first()) {
+                Some((variant, payload)) => Value::Enum(
+                    name.clone(),
+                    variant.clone(),
+                    payload.iter().map(|ty| self.zero_value(ty)).collect(),
+                ),
+                None => Value::Void,
+            },
+            _ => Value::Void,
+        }
+    }
+
+    fn eval_expression(&mut self, expr: &Expr) -> Exec<Value> {
+        match expr {
+            Expr::Literal(lit, _) => Ok(match lit {
+                // A literal beyond `int` is a `u64`, as the typechecker has it
+                Literal::Int(i) => match i64::try_from(*i) {
+                    Ok(i) => Value::Int(i),
+                    Err(_) => Value::Sized(Type::U64, *i),
+                },
+                Literal::Float(f) => Value::Float(*f),
+                Literal::Bool(b) => Value::Bool(*b),
+                Literal::String(s) => Value::String(s.clone()),
+                Literal::Null => Value::Null,
+            }),
+            Expr::Identifier(name, span) => match self.lookup(name) {
+                Some(value) => Ok(value.clone()),
+                None if self.functions.contains_key(name) => {
+                    Ok(Value::Function(Function::Named(name.clone())))
+                }
+                None => error(format!("Undefined variable: {}", name), span),
+            },
+            Expr::Binary(left, BinaryOp::And, right, span) | Expr::Binary(left, BinaryOp::Or, right, span) => {
+                let is_and = matches!(expr, Expr::Binary(_, BinaryOp::And, _, _));
+                match self.eval_expression(left)? {
+                    Value::Bool(b) if b != is_and => Ok(Value::Bool(b)),
+                    Value::Bool(_) => match self.eval_expression(right)? {
+                        Value::Bool(b) => Ok(Value::Bool(b)),
+                        other => error(format!("Expected bool operand, found {}", other.type_of()), span),
+                    },
+                    other => error(format!("Expected bool operand, found {}", other.type_of()), span),
+                }
+            }
+            Expr::Binary(left, op, right, span) => {
+                let left = self.eval_expression(left)?;
+                let right = self.eval_expression(right)?;
+                binary_op(op, left, right, span)
+            }
+            Expr::Unary(op, operand, span) => match (op, self.eval_expression(operand)?) {
+                (UnaryOp::Neg, Value::Float(f)) => Ok(Value::Float(-f)),
+                (UnaryOp::Neg, value) if integer(&value).is_some() => {
+                    let (i, ty) = integer(&value).unwrap_or((0, Type::Int));
+                    checked(-i, &ty, span)
+                }
+                (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                (op, value) => error(format!("Invalid operand for {:?}: {}", op, value.type_of()), span),
+            },
+            Expr::Call(callee, args, span) => {
+                // `text.len()` calls a method of a string, `items.push(x)` one
+                // of an array
+                if let Expr::Field(object, method, _) = callee.as_ref() {
+                    let mut values = vec![self.eval_expression(object)?];
+                    for arg in args {
+                        values.push(self.eval_expression(arg)?);
+                    }
+                    return match values.remove(0) {
+                        Value::Array(elements) => self.array_method(object, elements, method, values, span),
+                        text => {
+                            values.insert(0, text);
+                            string_method(method, &values, span)
+                        }
+                    };
+                }
+                let function = match callee.as_ref() {
+                    // Named functions are called directly unless a variable shadows them
+                    Expr::Identifier(name, _) if self.lookup(name).is_none() => Function::Named(name.clone()),
+                    callee => match self.eval_expression(callee)? {
+                        Value::Function(function) => function,
+                        other => return error(format!("Cannot call a value of type {}", other.type_of()), span),
+                    },
+                };
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.eval_expression(arg)?);
+                }
+                match function {
+                    Function::Named(name) => self.call_function(&name, values, span),
+                    Function::Lambda(closure) => self.call_closure(&closure, values, span),
+                }
+            }
+            Expr::Lambda(params, _, body, _) => {
+                let mut captured = HashMap::new();
+                for scope in &self.scopes {
+                    captured.extend(scope.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+                Ok(Value::Function(Function::Lambda(Arc::new(Closure {
+                    params: params.clone(),
+                    body: body.as_ref().clone(),
+                    captured,
+                }))))
+            }
+            Expr::Index(array, index, span) => {
+                match (self.eval_expression(array)?, self.eval_expression(index)?) {
+                    (Value::Array(elements), Value::Int(i)) => {
+                        match usize::try_from(i).ok().and_then(|i| elements.get(i)) {
+                            Some(value) => Ok(value.clone()),
+                            None => error(format!("Index {} out of bounds for array of length {}", i, elements.len()), span),
+                        }
+                    }
+                    (value, _) => error(format!("Cannot index into {}", value.type_of()), span),
+                }
+            }
+            Expr::Array(elements, _) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.eval_expression(element)?);
+                }
+                Ok(Value::Array(values))
+            }
+            Expr::If(cond, then_branch, else_branch, span) => match self.eval_expression(cond)? {
+                Value::Bool(true) => self.eval_expression(then_branch),
+                Value::Bool(false) => match else_branch {
+                    Some(else_branch) => self.eval_expression(else_branch),
+                    None => Ok(Value::Void),
+                },
+                other => error(format!("If condition must be a bool, found {}", other.type_of()), span),
+            },
+            Expr::Block(stmts, _) => self.execute_block(stmts),
+            Expr::Variant(name, variant, args, _) => {
+                let types = self
+                    .enums
+                    .get(name)
+                    .and_then(|variants| variants.iter().find(|(name, _)| name == variant))
+                    .map(|(_, payload)| payload.clone())
+                    .unwrap_or_default();
+                let mut payload = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    let value = self.eval_expression(arg)?;
+                    payload.push(match types.get(i) {
+                        Some(ty) => typed(value, ty),
+                        None => value,
+                    });
+                }
+                Ok(Value::Enum(name.clone(), variant.clone(), payload))
+            }
+            Expr::Match(scrutinee, arms, span) => {
+                let value = self.eval_expression(scrutinee)?;
+                for arm in arms {
+                    let bindings = match (&arm.pattern, &value) {
+                        (Pattern::Wildcard(_), _) => HashMap::new(),
+                        (Pattern::Variant(_, variant, names, _), Value::Enum(_, actual, payload)) if variant == actual => {
+                            names.iter().cloned().zip(payload.iter().cloned()).collect()
+                        }
+                        _ => continue,
+                    };
+                    self.scopes.push(bindings);
+                    let result = self.eval_expression(&arm.body);
+                    self.scopes.pop();
+                    return result;
+                }
+                error(format!("No match arm matches {}", value), span)
+            }
+            Expr::Inlined(_, body, _) => self.eval_expression(body),
+            // `None` and `Err(e)` return from the function, as values of
+            // its return type
+            Expr::Try(value, return_type, span) => match (self.eval_expression(value)?, return_type) {
+                (Value::Enum(_, variant, payload), Some(Type::Enum(name))) if variant == "None" || variant == "Err" => {
+                    Err(Unwind::Return(Value::Enum(name.clone(), variant, payload)))
+                }
+                (Value::Enum(_, _, mut payload), _) if payload.len() == 1 => Ok(payload.remove(0)),
+                (value, _) => error(format!("Cannot apply ? to {}", value.type_of()), span),
+            },
+            // Casts between integers wrap to the width of the target, as C's do
+            Expr::Cast(operand, target, span) => {
+                let value = self.eval_expression(operand)?;
+                match (integer(&value), value, target) {
+                    (Some((i, _)), _, Type::Float) => Ok(Value::Float(i as f64)),
+                    (Some((i, _)), _, Type::Bool) => Ok(Value::Bool(i != 0)),
+                    (Some((i, _)), _, target) if target.is_integer() => Ok(integer_value(wrap_integer(i, target), target)),
+                    (_, Value::Float(f), target) if target.is_integer() => {
+                        Ok(integer_value(wrap_integer(f as i128, target), target))
+                    }
+                    (_, Value::Bool(b), target) if target.is_integer() => Ok(integer_value(b as i128, target)),
+                    (_, value, target) if value.type_of() == *target => Ok(value),
+                    (_, value, target) => error(format!("Cannot cast {} to {}", value.type_of(), target), span),
+                }
+            }
+            Expr::Field(object, field, span) => match self.eval_expression(object)? {
+                Value::Struct(name, fields) => match fields.into_iter().find(|(name, _)| name == field) {
+                    Some((_, value)) => Ok(value),
+                    None => error(format!("Struct {} has no field {}", name, field), span),
+                },
+                other => error(format!("Cannot read field {} of {}", field, other.type_of()), span),
+            },
+            Expr::Range(_, _, span) => error("Expression is not supported by the interpreter".to_string(), span),
+        }
+    }
+
+    fn call_function(&mut self, name: &str, args: Vec<Value>, span: &Span) -> Exec<Value> {
+        if format::is_print(name) && !self.functions.contains_key(name) {
+            let text = match args.split_first() {
+                Some((Value::String(format), values)) if name == "printf" => formatted(format, values, span)?,
+                _ => format!("{}\n", args.iter().map(Value::to_string).collect::<String>()),
+            };
+            match self.debug_hook.as_mut() {
+                Some(hook) => hook.output(&text),
+                None => self.output.push_str(&text),
+            }
+            return Ok(Value::Void);
+        }
+        if name == "len" && !self.functions.contains_key(name) {
+            return match args.as_slice() {
+                [Value::Array(elements)] => Ok(Value::Int(elements.len() as i64)),
+                _ => error("len expects an array".to_string(), span),
+            };
+        }
+        if name == "panic" && !self.functions.contains_key(name) {
+            return match args.as_slice() {
+                [Value::String(message)] => error(format!("panic: {}", message), span),
+                _ => error("panic expects a string".to_string(), span),
+            };
+        }
+        // Programs run here have no command line of their own
+        if name == "args" && !self.functions.contains_key(name) {
+            return Ok(Value::Array(Vec::new()));
+        }
+        if name == "env" && !self.functions.contains_key(name) {
+            return match args.as_slice() {
+                [Value::String(name)] => Ok(std::env::var(name).map_or(Value::Null, Value::String)),
+                _ => error("env expects a string".to_string(), span),
+            };
+        }
+        if name == "assert" && !self.functions.contains_key(name) {
+            return match args.as_slice() {
+                [Value::Bool(true), ..] => Ok(Value::Void),
+                [Value::Bool(false)] => error("panic: assertion failed".to_string(), span),
+                [Value::Bool(false), Value::String(message)] => {
+                    error(format!("panic: assertion failed: {}", message), span)
+                }
+                _ => error("assert expects a bool and a string".to_string(), span),
+            };
+        }
+        if stdlib::MATH_FUNCTIONS.contains(&name) && !self.functions.contains_key(name) {
+            return math(name, &args, span);
+        }
+
+        if self.externs.contains(name) {
+            return error(
+                format!("Extern function {} can only be called from a compiled program", name),
+                span,
+            );
+        }
+
+        let (params, return_type, body) = match self.functions.get(name) {
+            Some((params, return_type, body)) => (params.clone(), return_type.clone(), Rc::clone(body)),
+            None => return error(format!("Undefined function: {}", name), span),
+        };
+        if params.len() != args.len() {
+            return error(
+                format!("Function {} expects {} arguments, found {}", name, params.len(), args.len()),
+                span,
+            );
+        }
+
+        let frame = params.into_iter().zip(args).map(|((name, ty), value)| (name, typed(value, &ty))).collect();
+        let result = self.with_frame(name, vec![frame], span, |interpreter| {
+            interpreter.execute_body(&body).map(|_| Value::Void)
+        })?;
+        Ok(typed(result, &return_type))
+    }
+
+    fn call_closure(&mut self, closure: &Closure, args: Vec<Value>, span: &Span) -> Exec<Value> {
+        if closure.params.len() != args.len() {
+            return error(
+                format!("Lambda expects {} arguments, found {}", closure.params.len(), args.len()),
+                span,
+            );
+        }
+
+        let frame = closure
+            .params
+            .iter()
+            .zip(args)
+            .map(|((name, ty), value)| match ty {
+                Some(ty) => (name.clone(), typed(value, ty)),
+                None => (name.clone(), value),
+            })
+            .collect();
+        let scopes = vec![closure.captured.clone(), frame];
+        self.with_frame("<lambda>", scopes, span, |interpreter| interpreter.eval_expression(&closure.body))
+    }
+
+    /// Runs `body` as a call to `function`, with `scopes` as its variables
+    fn with_frame(
+        &mut self,
+        function: &str,
+        scopes: Vec<HashMap<String, Value>>,
+        span: &Span,
+        body: impl FnOnce(&mut Self) -> Exec<Value>,
+    ) -> Exec<Value> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return error(format!("Stack overflow while calling {}", function), span);
+        }
+
+        self.call_stack.push(CallFrame {
+            function: std::mem::replace(&mut self.current_function, function.to_string()),
+            scopes: std::mem::replace(&mut self.scopes, scopes),
+            span: self.current_span.clone(),
+        });
+        self.call_depth += 1;
+        let result = body(self);
+        self.call_depth -= 1;
+        if let Some(caller) = self.call_stack.pop() {
+            self.current_function = caller.function;
+            self.scopes = caller.scopes;
+            self.current_span = caller.span;
+        }
+
+        match result {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(other) => Err(other),
+        }
+    }
+}
+
+/// The text `printf` writes for `format` with its placeholders filled in
+fn formatted(format: &str, values: &[Value], span: &Span) -> Exec<String> {
+    let pieces = match format::parse(format) {
+        Ok(pieces) => pieces,
+        Err(message) => return error(message, span),
+    };
+    let mut values = values.iter();
+    let mut text = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Text(piece) => text.push_str(&piece),
+            Piece::Value => match values.next() {
+                Some(value) => text.push_str(&value.to_string()),
+                None => return error("Too few values for the format of printf".to_string(), span),
+            },
+        }
+    }
+    Ok(text)
+}
+
+/// The result of the method `method` of the string that `args` start with
+fn string_method(method: &str, args: &[Value], span: &Span) -> Exec<Value> {
+    let value = match (method, args) {
+        ("len", [Value::String(text)]) => Value::Int(text.len() as i64),
+        ("substring", [Value::String(text), Value::Int(start), Value::Int(end)]) => {
+            let bytes = usize::try_from(*start)
+                .ok()
+                .zip(usize::try_from(*end).ok())
+                .and_then(|(start, end)| text.as_bytes().get(start..end));
+            match bytes {
+                Some(bytes) => Value::String(String::from_utf8_lossy(bytes).into_owned()),
+                None => return error("substring out of bounds".to_string(), span),
+            }
+        }
+        // An empty separator leaves the text whole
+        ("split", [Value::String(text), Value::String(separator)]) if separator.is_empty() => {
+            Value::Array(vec![Value::String(text.clone())])
+        }
+        ("split", [Value::String(text), Value::String(separator)]) => {
+            Value::Array(text.split(separator.as_str()).map(|piece| Value::String(piece.to_string())).collect())
+        }
+        ("contains", [Value::String(text), Value::String(part)]) => Value::Bool(text.contains(part.as_str())),
+        ("to_upper", [Value::String(text)]) => Value::String(text.to_ascii_uppercase()),
+        ("to_int", [Value::String(text)]) => match text.parse() {
+            Ok(value) => Value::Int(value),
+            Err(_) => return error(format!("cannot convert \"{}\" to int", text), span),
+        },
+        ("to_float", [Value::String(text)]) => match text.parse() {
+            Ok(value) => Value::Float(value),
+            Err(_) => return error(format!("cannot convert \"{}\" to float", text), span),
+        },
+        (method, [object, ..]) => return error(format!("Cannot call method {} on {}", method, object.type_of()), span),
+        _ => return error(format!("Invalid arguments for {}", method), span),
+    };
+    Ok(value)
+}
+
+/// The result of the `std.math` function `name`
+fn math(name: &str, args: &[Value], span: &Span) -> Exec<Value> {
+    use Value::Float;
+
+    let integers: Option<Vec<_>> = args.iter().map(integer).collect();
+    if let (Some(integers), "abs" | "min" | "max") = (integers, name) {
+        return match (name, integers.as_slice()) {
+            // The absolute value of the smallest value overflows
+            ("abs", [(x, ty)]) => checked(x.abs(), ty, span),
+            ("min", [(a, a_type), (b, b_type)]) => Ok(integer_value(*a.min(b), &wider_than_int(a_type.clone(), b_type.clone()))),
+            ("max", [(a, a_type), (b, b_type)]) => Ok(integer_value(*a.max(b), &wider_than_int(a_type.clone(), b_type.clone()))),
+            _ => error(format!("Invalid arguments for {}", name), span),
+        };
+    }
+    let value = match (name, args) {
+        ("sqrt", [Float(x)]) => Float(x.sqrt()),
+        ("floor", [Float(x)]) => Float(x.floor()),
+        ("ceil", [Float(x)]) => Float(x.ceil()),
+        ("pow", [Float(x), Float(y)]) => Float(x.powf(*y)),
+        ("abs", [Float(x)]) => Float(x.abs()),
+        ("min", [Float(a), Float(b)]) => Float(a.min(*b)),
+        ("max", [Float(a), Float(b)]) => Float(a.max(*b)),
+        _ => return error(format!("Invalid arguments for {}", name), span),
+    };
+    Ok(value)
+}
+
+fn contains(span: &Span, location: usize) -> bool {
+    span.start <= location && location < span.end
+}
+
+/// A step from a variable to the part of it an assignment stores into
+enum Step {
+    Index(i64),
+    Field(String),
+}
+
+/// The value and type of an integer
+fn integer(value: &Value) -> Option<(i128, Type)> {
+    match value {
+        Value::Int(i) => Some((*i as i128, Type::Int)),
+        Value::Sized(ty, i) => Some((*i, ty.clone())),
+        _ => None,
+    }
+}
+
+/// `value` as an integer of type `ty`, which it fits in
+fn integer_value(value: i128, ty: &Type) -> Value {
+    match ty {
+        Type::Int => Value::Int(value as i64),
+        ty => Value::Sized(ty.clone(), value),
+    }
+}
+
+/// The type of an operation on integers of types `a` and `b`. The
+/// typechecker has made them the same, except that a literal is an `int`
+/// until it is used with a sized integer.
+fn wider_than_int(a: Type, b: Type) -> Type {
+    if a == Type::Int {
+        b
+    } else {
+        a
+    }
+}
+
+/// `value` as an integer of type `ty`, or the panic of an overflow where
+/// it does not fit
+fn checked(value: i128, ty: &Type, span: &Span) -> Exec<Value> {
+    match ty.integer_range() {
+        Some((min, max)) if value < min || value > max => error("panic: integer overflow".to_string(), span),
+        _ => Ok(integer_value(value, ty)),
+    }
+}
+
+/// `value` stored as a value of type `ty`: integers take on the sized type
+/// they are stored as, in arrays and optionals too
+fn typed(value: Value, ty: &Type) -> Value {
+    match (value, ty) {
+        (value, ty) if ty.is_integer() => match integer(&value) {
+            Some((i, _)) => integer_value(i, ty),
+            None => value,
+        },
+        (Value::Array(elements), Type::Array(element)) => {
+            Value::Array(elements.into_iter().map(|value| typed(value, element)).collect())
+        }
+        (Value::Null, _) => Value::Null,
+        (value, Type::Optional(inner)) => typed(value, inner),
+        (value, _) => value,
+    }
+}
+
+/// Truncates `value` to the width of an integer type, as a C cast would
+fn wrap_integer(value: i128, ty: &Type) -> i128 {
+    let bits = match ty {
+        Type::I8 | Type::U8 => 8,
+        Type::I16 | Type::U16 => 16,
+        Type::I32 | Type::U32 => 32,
+        _ => 64,
+    };
+    let modulus = 1i128 << bits;
+    let value = value.rem_euclid(modulus);
+    if !ty.is_unsigned() && value >= modulus / 2 {
+        value - modulus
+    } else {
+        value
+    }
+}
+
+/// The result of `op` on two integers of type `ty`, which panics on
+/// overflow as compiled programs do
+fn integer_op(op: &BinaryOp, left: i128, right: i128, ty: &Type, span: &Span) -> Exec<Value> {
+    let value = match op {
+        BinaryOp::Add => left + right,
+        BinaryOp::Sub => left - right,
+        BinaryOp::Mul => left * right,
+        BinaryOp::Div | BinaryOp::Mod if right == 0 => return error("Division by zero".to_string(), span),
+        // Both round toward zero, as in C
+        BinaryOp::Div => left / right,
+        BinaryOp::Mod => left % right,
+        BinaryOp::Lt => return Ok(Value::Bool(left < right)),
+        BinaryOp::Lte => return Ok(Value::Bool(left <= right)),
+        BinaryOp::Gt => return Ok(Value::Bool(left > right)),
+        BinaryOp::Gte => return Ok(Value::Bool(left >= right)),
+        BinaryOp::Eq => return Ok(Value::Bool(left == right)),
+        BinaryOp::Neq => return Ok(Value::Bool(left != right)),
+        op => return error(format!("Invalid operand types for {:?}: {} and {}", op, ty, ty), span),
+    };
+    checked(value, ty, span)
+}
+
+fn binary_op(op: &BinaryOp, left: Value, right: Value, span: &Span) -> Exec<Value> {
+    use Value::{Bool, Float};
+
+    if let (Some((l, left_type)), Some((r, right_type))) = (integer(&left), integer(&right)) {
+        return integer_op(op, l, r, &wider_than_int(left_type, right_type), span);
+    }
+    let value = match (op, left, right) {
+        (BinaryOp::Add, Value::String(l), r) => Value::String(format!("{}{}", l, r)),
+        (BinaryOp::Add, l, Value::String(r)) => Value::String(format!("{}{}", l, r)),
+        (BinaryOp::Lt, Value::String(l), Value::String(r)) => Bool(l < r),
+        (BinaryOp::Lte, Value::String(l), Value::String(r)) => Bool(l <= r),
+        (BinaryOp::Gt, Value::String(l), Value::String(r)) => Bool(l > r),
+        (BinaryOp::Gte, Value::String(l), Value::String(r)) => Bool(l >= r),
+        (BinaryOp::Add, Float(l), Float(r)) => Float(l + r),
+        (BinaryOp::Sub, Float(l), Float(r)) => Float(l - r),
+        (BinaryOp::Mul, Float(l), Float(r)) => Float(l * r),
+        (BinaryOp::Div, Float(l), Float(r)) => Float(l / r),
+        (BinaryOp::Mod, Float(l), Float(r)) => Float(l % r),
+        (BinaryOp::Lt, Float(l), Float(r)) => Bool(l < r),
+        (BinaryOp::Lte, Float(l), Float(r)) => Bool(l <= r),
+        (BinaryOp::Gt, Float(l), Float(r)) => Bool(l > r),
+        (BinaryOp::Gte, Float(l), Float(r)) => Bool(l >= r),
+        (BinaryOp::Eq, l, r) => Bool(l == r),
+        (BinaryOp::Neq, l, r) => Bool(l != r),
+        (op, l, r) => {
+            return error(
+                format!("Invalid operand types for {:?}: {} and {}", op, l.type_of(), r.type_of()),
+                span,
+            );
+        }
+    };
+
+    Ok(value)
+}
@@ -0,0 +1,1344 @@
+// Typed intermediate representation between the checked program and C. Each
+// function is a list of basic blocks, each a list of instructions ending in a
+// terminator that jumps, branches or returns. Instructions compute into
+// temporaries, each assigned exactly once and used in the block defining it,
+// and read and store the function's variables, which the blocks share:
+//
+//     fn zN4main6squareE(x: int) -> int
+//     b0:
+//         t0 = x * x  checked at square.z:2
+//         return t0
+//
+// The IR covers executables working with numbers and bools: variables,
+// arithmetic, `if`, `while` and `for` over ranges, calls of Z functions,
+// generic instances, imported functions and C functions, `print`, `printf`,
+// `panic` and `assert`, the last four with literal strings only. For anything
+// else, such as strings, arrays or structs, `build` fails with an `Uncovered`
+// naming it. Those programs, and libraries, are generated as C straight from
+// the checked program, with a remark in the optimization report saying why.
+
+use crate::ast::{self, Attribute, BinaryOp, Expr, Literal, Program, Span, Stmt, Type, UnaryOp};
+use crate::format;
+use crate::mangle::{self, Instances};
+use std::collections::HashMap;
+use std::fmt;
+
+pub type BlockId = usize;
+pub type Local = usize;
+pub type Temp = usize;
+
+/// Something a program uses that the IR does not cover, such as strings,
+/// which makes it generated as C straight from the checked program
+#[derive(Debug, Clone)]
+pub struct Uncovered {
+    /// What is not covered, as in `strings`
+    pub what: String,
+    /// The function, or other declaration, using it
+    pub function: String,
+    pub span: Span,
+}
+
+impl Uncovered {
+    fn new(function: &str, what: impl Into<String>, span: &Span) -> Self {
+        Uncovered {
+            what: what.into(),
+            function: function.to_string(),
+            span: span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Uncovered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the intermediate representation does not cover {}", self.what)
+    }
+}
+
+type Build<T> = std::result::Result<T, Uncovered>;
+
+/// A program in the IR: its functions, `main` among them, and the C and
+/// imported functions they call
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub externs: Vec<Extern>,
+    pub imports: Vec<Import>,
+}
+
+/// A C function declared with `extern`, called through a Z function named
+/// `symbol`
+#[derive(Debug, Clone)]
+pub struct Extern {
+    pub name: String,
+    pub symbol: String,
+    pub params: Vec<(String, Type)>,
+    pub return_type: Type,
+}
+
+/// A function of another module, compiled separately
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub symbol: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    /// C name of the function, `main` for the program's entry
+    pub symbol: String,
+    /// Name of the function's frame in stack traces, with checks only
+    pub frame: Option<String>,
+    /// Variables with their C names, unique in the function. The first
+    /// `params` of them are the parameters.
+    pub locals: Vec<(String, Type)>,
+    pub params: usize,
+    pub temps: Vec<Type>,
+    pub return_type: Type,
+    /// Blocks of the body, starting with the entry
+    pub blocks: Vec<Block>,
+    pub public: bool,
+    pub attributes: Vec<Attribute>,
+    /// Where the function is declared, or `main` for the statements outside
+    /// functions
+    pub span: Span,
+    /// Whether calls can be treated as the values they return, see
+    /// `Module::infer_purity`
+    pub pure: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    /// The statement or expression each instruction comes from, in the
+    /// same order, for the optimization report
+    pub spans: Vec<Span>,
+    pub terminator: Terminator,
+    /// The statement or expression the terminator comes from
+    pub terminator_span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Const {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+/// A value an instruction reads
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Const(Const),
+    Local(Local),
+    Temp(Temp),
+}
+
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    /// `dest = left op right`. Checked integer arithmetic panics at
+    /// `overflow` when the result does not fit the type of `dest`, or when
+    /// dividing by zero.
+    Binary {
+        dest: Temp,
+        op: BinaryOp,
+        left: Operand,
+        right: Operand,
+        overflow: Option<String>,
+    },
+    Unary {
+        dest: Temp,
+        op: UnaryOp,
+        operand: Operand,
+    },
+    /// `dest = left op right` on the bits of integers, which the optimizer
+    /// turns arithmetic into. Shifting left wraps like unchecked arithmetic,
+    /// or panics at `overflow` when the bits shifted out, or into the sign,
+    /// change the value, as the multiplication it replaces would.
+    Bitwise {
+        dest: Temp,
+        op: BitOp,
+        left: Operand,
+        right: Operand,
+        overflow: Option<String>,
+    },
+    /// Converts `operand` to the type of `dest`
+    Cast { dest: Temp, operand: Operand },
+    Store { local: Local, value: Operand },
+    /// Calls the function named `symbol`, keeping its result in `dest`.
+    /// With checks, the caller's frame records `location` first.
+    Call {
+        dest: Option<Temp>,
+        symbol: String,
+        args: Vec<Operand>,
+        location: Option<String>,
+    },
+    /// Writes text and values, each by its type
+    Print(Vec<Piece>),
+    /// Stops the program with `message`, reported at `location`
+    Panic { message: String, location: String },
+    /// Records `location` in the caller's frame and enters the frame of the
+    /// function named `frame`, whose call was inlined. With checks only.
+    Enter { frame: String, location: String },
+    /// Leaves the frame of an inlined call
+    Leave,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+    Shl,
+    /// Shifts signed integers arithmetically, keeping their sign
+    Shr,
+    And,
+}
+
+impl BitOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BitOp::Shl => "<<",
+            BitOp::Shr => ">>",
+            BitOp::And => "&",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Piece {
+    Text(String),
+    Value(Operand),
+}
+
+/// How a block ends
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// To the first block when the bool is true, to the second otherwise
+    Branch(Operand, BlockId, BlockId),
+    Return(Option<Operand>),
+    /// Ends a block no path reaches the end of
+    Unreachable,
+}
+
+impl Instruction {
+    /// The temporary the instruction computes, if any
+    pub fn dest(&self) -> Option<Temp> {
+        match self {
+            Instruction::Binary { dest, .. }
+            | Instruction::Unary { dest, .. }
+            | Instruction::Bitwise { dest, .. }
+            | Instruction::Cast { dest, .. } => Some(*dest),
+            Instruction::Call { dest, .. } => *dest,
+            Instruction::Store { .. }
+            | Instruction::Print(_)
+            | Instruction::Panic { .. }
+            | Instruction::Enter { .. }
+            | Instruction::Leave => None,
+        }
+    }
+
+    fn dest_mut(&mut self) -> Option<&mut Temp> {
+        match self {
+            Instruction::Binary { dest, .. }
+            | Instruction::Unary { dest, .. }
+            | Instruction::Bitwise { dest, .. }
+            | Instruction::Cast { dest, .. } => Some(dest),
+            Instruction::Call { dest, .. } => dest.as_mut(),
+            Instruction::Store { .. }
+            | Instruction::Print(_)
+            | Instruction::Panic { .. }
+            | Instruction::Enter { .. }
+            | Instruction::Leave => None,
+        }
+    }
+
+    /// The values the instruction reads
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Instruction::Binary { left, right, .. } | Instruction::Bitwise { left, right, .. } => vec![left, right],
+            Instruction::Unary { operand, .. } | Instruction::Cast { operand, .. } => vec![operand],
+            Instruction::Store { value, .. } => vec![value],
+            Instruction::Call { args, .. } => args.iter().collect(),
+            Instruction::Print(pieces) => pieces
+                .iter()
+                .filter_map(|piece| match piece {
+                    Piece::Value(value) => Some(value),
+                    Piece::Text(_) => None,
+                })
+                .collect(),
+            Instruction::Panic { .. } | Instruction::Enter { .. } | Instruction::Leave => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Instruction::Binary { left, right, .. } | Instruction::Bitwise { left, right, .. } => vec![left, right],
+            Instruction::Unary { operand, .. } | Instruction::Cast { operand, .. } => vec![operand],
+            Instruction::Store { value, .. } => vec![value],
+            Instruction::Call { args, .. } => args.iter_mut().collect(),
+            Instruction::Print(pieces) => pieces
+                .iter_mut()
+                .filter_map(|piece| match piece {
+                    Piece::Value(value) => Some(value),
+                    Piece::Text(_) => None,
+                })
+                .collect(),
+            Instruction::Panic { .. } | Instruction::Enter { .. } | Instruction::Leave => Vec::new(),
+        }
+    }
+}
+
+impl Terminator {
+    /// Blocks this terminator may continue in
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then_block, else_block) => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    /// Points the jumps of this terminator at the blocks `target` maps them to
+    pub fn retarget(&mut self, target: impl Fn(BlockId) -> BlockId) {
+        match self {
+            Terminator::Jump(block) => *block = target(*block),
+            Terminator::Branch(_, then_block, else_block) => {
+                *then_block = target(*then_block);
+                *else_block = target(*else_block);
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+    }
+
+    /// The value the terminator reads, if any
+    pub fn operand_mut(&mut self) -> Option<&mut Operand> {
+        match self {
+            Terminator::Branch(operand, _, _) | Terminator::Return(Some(operand)) => Some(operand),
+            Terminator::Jump(_) | Terminator::Return(None) | Terminator::Unreachable => None,
+        }
+    }
+}
+
+impl Const {
+    pub fn ty(&self) -> Type {
+        match self {
+            Const::Int(_) => Type::Int,
+            Const::Float(_) => Type::Float,
+            Const::Bool(_) => Type::Bool,
+        }
+    }
+}
+
+impl Module {
+    /// Marks the functions that only compute a result from their arguments:
+    /// they print nothing, cannot panic or trap, always return, and call only
+    /// other such functions of the module. Functions with loops, recursive
+    /// ones and those calling C or other modules are left unmarked. The
+    /// frames of debug builds do not count, as only the stack trace of a
+    /// panic shows them.
+    pub fn infer_purity(&mut self) {
+        for function in &mut self.functions {
+            function.pure = false;
+        }
+        loop {
+            let pure: Vec<&str> = self
+                .functions
+                .iter()
+                .filter(|function| function.pure)
+                .map(|function| function.symbol.as_str())
+                .collect();
+            let found: Vec<usize> = (0..self.functions.len())
+                .filter(|&index| !self.functions[index].pure && self.functions[index].is_pure_with(&pure))
+                .collect();
+            if found.is_empty() {
+                return;
+            }
+            for index in found {
+                self.functions[index].pure = true;
+            }
+        }
+    }
+}
+
+impl Function {
+    /// Whether the function is pure when the functions named in `pure` are
+    fn is_pure_with(&self, pure: &[&str]) -> bool {
+        if self.symbol == "main" || self.return_type == Type::Void || self.has_loop() {
+            return false;
+        }
+        self.blocks.iter().flat_map(|block| &block.instructions).all(|instruction| match instruction {
+            Instruction::Binary { overflow: Some(_), .. }
+            | Instruction::Bitwise { overflow: Some(_), .. }
+            | Instruction::Print(_)
+            | Instruction::Panic { .. } => false,
+            // Dividing integers by zero traps
+            Instruction::Binary {
+                op: BinaryOp::Div | BinaryOp::Mod,
+                left,
+                ..
+            } => !self.operand_type(left).is_integer(),
+            // So does converting a float out of the range of an integer
+            Instruction::Cast { dest, operand } => {
+                !(self.operand_type(operand) == Type::Float && self.temps[*dest].is_integer())
+            }
+            Instruction::Call { symbol, .. } => pure.contains(&symbol.as_str()),
+            Instruction::Binary { .. }
+            | Instruction::Unary { .. }
+            | Instruction::Bitwise { .. }
+            | Instruction::Store { .. }
+            | Instruction::Enter { .. }
+            | Instruction::Leave => true,
+        })
+    }
+
+    /// Whether some path through the blocks comes back to a block
+    fn has_loop(&self) -> bool {
+        // 0 for blocks not visited yet, 1 for those on the current path and
+        // 2 for those done
+        let mut state = vec![0u8; self.blocks.len()];
+        let mut stack = vec![(0, 0)];
+        state[0] = 1;
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.blocks[block].terminator.successors();
+            match successors.get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    match state[successor] {
+                        0 => {
+                            state[successor] = 1;
+                            stack.push((successor, 0));
+                        }
+                        1 => return true,
+                        _ => {}
+                    }
+                }
+                None => state[block] = 2,
+            }
+        }
+        false
+    }
+
+    pub fn operand_type(&self, operand: &Operand) -> Type {
+        match operand {
+            Operand::Const(value) => value.ty(),
+            Operand::Local(local) => self.locals[*local].1.clone(),
+            Operand::Temp(temp) => self.temps[*temp].clone(),
+        }
+    }
+
+    /// Number of jumps and branches into each block, the entry counting one
+    /// more for the call entering it
+    pub fn predecessors(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.blocks.len()];
+        if let Some(entry) = counts.first_mut() {
+            *entry = 1;
+        }
+        for block in &self.blocks {
+            for successor in block.terminator.successors() {
+                counts[successor] += 1;
+            }
+        }
+        counts
+    }
+
+    /// A copy of `instructions` computing into new temporaries, so that it
+    /// can run alongside them
+    pub fn copy_instructions(&mut self, instructions: &[Instruction]) -> Vec<Instruction> {
+        let mut temps = HashMap::new();
+        let mut copies = Vec::with_capacity(instructions.len());
+        for instruction in instructions {
+            let mut copy = instruction.clone();
+            for operand in copy.operands_mut() {
+                if let Operand::Temp(temp) = operand {
+                    *temp = temps.get(temp).copied().unwrap_or(*temp);
+                }
+            }
+            if let Some(dest) = copy.dest_mut() {
+                self.temps.push(self.temps[*dest].clone());
+                temps.insert(*dest, self.temps.len() - 1);
+                *dest = self.temps.len() - 1;
+            }
+            copies.push(copy);
+        }
+        copies
+    }
+
+    /// Blocks jumping or branching to each block
+    pub fn predecessor_lists(&self) -> Vec<Vec<BlockId>> {
+        let mut lists = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                lists[successor].push(id);
+            }
+        }
+        lists
+    }
+
+    fn operand_name(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Const(Const::Int(value)) => value.to_string(),
+            Operand::Const(Const::Float(value)) => format!("{:?}", value),
+            Operand::Const(Const::Bool(value)) => value.to_string(),
+            Operand::Local(local) => self.locals[*local].0.clone(),
+            Operand::Temp(temp) => format!("t{}", temp),
+        }
+    }
+}
+
+/// Builds the IR for `program`, compiled as `module` from `source` in `file`,
+/// recording the locations of runtime errors and calls with `checks`. Fails
+/// with the first feature the program uses that the IR does not cover.
+pub fn build(program: &Program, module: &str, file: &str, source: &str, checks: bool) -> Build<Module> {
+    let mut builder = Builder {
+        module,
+        instances: &program.instances,
+        file,
+        line_starts: ast::line_starts(source),
+        checks,
+        functions: HashMap::new(),
+    };
+    let mut ir = Module {
+        functions: Vec::new(),
+        externs: Vec::new(),
+        imports: Vec::new(),
+    };
+
+    for import in &program.imports {
+        let symbol = mangle::symbol(&import.module, &import.name, &Instances::new());
+        builder
+            .functions
+            .insert(import.name.clone(), (symbol.clone(), import.params.clone(), import.return_type.clone()));
+        ir.imports.push(Import {
+            symbol,
+            params: import.params.clone(),
+            return_type: import.return_type.clone(),
+        });
+    }
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::Function(name, _, params, return_type, ..) if name != "main" => {
+                let param_types = params.iter().map(|(_, ty)| ty.clone()).collect();
+                let symbol = mangle::symbol(module, name, &program.instances);
+                builder.functions.insert(name.clone(), (symbol, param_types, return_type.clone()));
+            }
+            Stmt::Extern(name, params, return_type, _, span) => {
+                if !params.iter().all(|(_, ty)| is_scalar(ty)) || !is_result(return_type) {
+                    let what = "C functions taking or returning other values than numbers and bools";
+                    return Err(Uncovered::new(name, what, span));
+                }
+                let param_types = params.iter().map(|(_, ty)| ty.clone()).collect();
+                let symbol = mangle::symbol(module, name, &program.instances);
+                builder
+                    .functions
+                    .insert(name.clone(), (symbol.clone(), param_types, return_type.clone()));
+                ir.externs.push(Extern {
+                    name: name.clone(),
+                    symbol,
+                    params: params.clone(),
+                    return_type: return_type.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // Statements outside functions make up `main` along with its body
+    let mut main = FunctionBuilder::new("main", "main".to_string(), &[], Type::Int, checks.then(|| "main".to_string()));
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::Function(name, _, _, _, body, _, _, span) if name == "main" => {
+                main.function.span = span.clone();
+                match body.as_ref() {
+                    Stmt::Block(stmts, _) => {
+                        for stmt in stmts {
+                            builder.statement(&mut main, stmt)?;
+                        }
+                    }
+                    body => builder.statement(&mut main, body)?,
+                }
+            }
+            Stmt::Function(name, _, params, return_type, body, public, attributes, span) => {
+                let mut function = builder.function(name, params, return_type, body, *public, attributes)?;
+                function.span = span.clone();
+                ir.functions.push(function);
+            }
+            Stmt::Extern(..) | Stmt::Import(..) => {}
+            Stmt::Struct(name, _, _, span) | Stmt::Enum(name, _, span) => {
+                return Err(Uncovered::new(name, "structs and enums", span))
+            }
+            stmt => builder.statement(&mut main, stmt)?,
+        }
+    }
+    main.terminate(Terminator::Return(None));
+    ir.functions.push(main.function);
+    ir.infer_purity();
+    Ok(ir)
+}
+
+/// Whether the IR holds values of `ty`
+fn is_scalar(ty: &Type) -> bool {
+    ty.is_integer() || matches!(ty, Type::Float | Type::Bool)
+}
+
+/// Whether the IR can call functions returning `ty`
+fn is_result(ty: &Type) -> bool {
+    is_scalar(ty) || *ty == Type::Void
+}
+
+struct Builder<'a> {
+    module: &'a str,
+    instances: &'a Instances,
+    file: &'a str,
+    line_starts: Vec<usize>,
+    checks: bool,
+    // Functions called by name, with their symbols and signatures
+    functions: HashMap<String, (String, Vec<Type>, Type)>,
+}
+
+/// A function whose blocks are being built, and the variables in scope
+struct FunctionBuilder {
+    function: Function,
+    // Block the next instruction goes in
+    current: BlockId,
+    // The statement or expression being built
+    span: Span,
+    scopes: Vec<HashMap<String, Local>>,
+}
+
+impl FunctionBuilder {
+    fn new(name: &str, symbol: String, params: &[(String, Type)], return_type: Type, frame: Option<String>) -> Self {
+        let mut builder = FunctionBuilder {
+            function: Function {
+                name: name.to_string(),
+                symbol,
+                frame,
+                locals: Vec::new(),
+                params: params.len(),
+                temps: Vec::new(),
+                return_type,
+                blocks: Vec::new(),
+                public: false,
+                attributes: Vec::new(),
+                span: Span::default(),
+                pure: false,
+            },
+            current: 0,
+            span: Span::default(),
+            scopes: vec![HashMap::new()],
+        };
+        builder.current = builder.new_block();
+        for (name, ty) in params {
+            builder.declare(name, ty.clone());
+        }
+        builder
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(Block {
+            instructions: Vec::new(),
+            spans: Vec::new(),
+            terminator: Terminator::Unreachable,
+            terminator_span: Span::default(),
+        });
+        self.function.blocks.len() - 1
+    }
+
+    /// Ends the current block, continuing in a new one that nothing jumps
+    /// to yet
+    fn terminate(&mut self, terminator: Terminator) {
+        self.end(self.current, terminator);
+        self.current = self.new_block();
+    }
+
+    /// Ends `block` with `terminator`, which comes from the code being built
+    fn end(&mut self, block: BlockId, terminator: Terminator) {
+        let block = &mut self.function.blocks[block];
+        block.terminator = terminator;
+        block.terminator_span = self.span.clone();
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        let block = &mut self.function.blocks[self.current];
+        block.instructions.push(instruction);
+        block.spans.push(self.span.clone());
+    }
+
+    fn temp(&mut self, ty: Type) -> Temp {
+        self.function.temps.push(ty);
+        self.function.temps.len() - 1
+    }
+
+    /// A new variable of the innermost scope. Variables shadowing others get
+    /// the number of their local in their C names.
+    fn declare(&mut self, name: &str, ty: Type) -> Local {
+        let local = self.function.locals.len();
+        let c_name = if self.function.locals.iter().any(|(other, _)| other == name) {
+            format!("{}_{}", name, local)
+        } else {
+            name.to_string()
+        };
+        self.function.locals.push((c_name, ty));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), local);
+        }
+        local
+    }
+
+    fn lookup(&self, name: &str) -> Option<Local> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    /// Gives up on the function, which uses `what` at `span`
+    fn uncovered<T>(&self, what: impl Into<String>, span: &Span) -> Build<T> {
+        Err(Uncovered::new(&self.function.name, what, span))
+    }
+}
+
+impl Builder<'_> {
+    fn location(&self, span: &Span) -> String {
+        format!("{}:{}", self.file, ast::line_of(&self.line_starts, span.start) + 1)
+    }
+
+    fn function(
+        &self,
+        name: &str,
+        params: &[(String, Type)],
+        return_type: &Type,
+        body: &Stmt,
+        public: bool,
+        attributes: &[Attribute],
+    ) -> Build<Function> {
+        if !params.iter().all(|(_, ty)| is_scalar(ty)) || !is_result(return_type) {
+            let what = "functions taking or returning other values than numbers and bools";
+            return Err(Uncovered::new(name, what, body.span()));
+        }
+        let frame = match self.instances.get(name) {
+            Some((generic, type_args)) => {
+                let type_args: Vec<String> = type_args.iter().map(Type::to_string).collect();
+                format!("{}<{}>", generic, type_args.join(", "))
+            }
+            None => name.to_string(),
+        };
+        let symbol = mangle::symbol(self.module, name, self.instances);
+        let mut function =
+            FunctionBuilder::new(name, symbol, params, return_type.clone(), self.checks.then_some(frame));
+        function.function.public = public;
+        function.function.attributes = attributes.to_vec();
+        match body {
+            Stmt::Block(stmts, _) => {
+                for stmt in stmts {
+                    self.statement(&mut function, stmt)?;
+                }
+            }
+            body => self.statement(&mut function, body)?,
+        }
+        // Functions with results return on every path
+        let end = if *return_type == Type::Void { Terminator::Return(None) } else { Terminator::Unreachable };
+        function.terminate(end);
+        Ok(function.function)
+    }
+
+    /// Builds `stmt`, whose instructions come from it unless they come from
+    /// one of its expressions
+    fn statement(&self, function: &mut FunctionBuilder, stmt: &Stmt) -> Build<()> {
+        let outer = std::mem::replace(&mut function.span, stmt.span().clone());
+        let result = self.statement_at(function, stmt);
+        function.span = outer;
+        result
+    }
+
+    fn statement_at(&self, function: &mut FunctionBuilder, stmt: &Stmt) -> Build<()> {
+        match stmt {
+            Stmt::Expr(Expr::If(cond, then_branch, else_branch, _)) => {
+                let cond = self.expression(function, cond)?;
+                let start = function.current;
+                let then_block = function.new_block();
+                function.current = then_block;
+                self.branch(function, then_branch)?;
+                let mut ends = vec![function.current];
+                let mut else_block = None;
+                if let Some(else_branch) = else_branch {
+                    let block = function.new_block();
+                    else_block = Some(block);
+                    function.current = block;
+                    self.branch(function, else_branch)?;
+                    ends.push(function.current);
+                }
+
+                let join = function.new_block();
+                function.end(start, Terminator::Branch(cond, then_block, else_block.unwrap_or(join)));
+                for end in ends {
+                    function.end(end, Terminator::Jump(join));
+                }
+                function.current = join;
+            }
+            Stmt::Expr(Expr::Block(stmts, _)) | Stmt::Block(stmts, _) => self.scope(function, stmts)?,
+            Stmt::Expr(Expr::Call(callee, args, span)) => {
+                self.call(function, callee, args, span, false)?;
+            }
+            Stmt::Expr(Expr::Inlined(name, body, span)) => {
+                self.inlined(function, name, body, span, false)?;
+            }
+            Stmt::Expr(expr) => {
+                self.expression(function, expr)?;
+            }
+            Stmt::Let(name, Some(ty), value, _) if is_scalar(ty) => {
+                let value = match value {
+                    Some(value) => self.expression(function, value)?,
+                    None => Operand::Const(match ty {
+                        Type::Float => Const::Float(0.0),
+                        Type::Bool => Const::Bool(false),
+                        _ => Const::Int(0),
+                    }),
+                };
+                let local = function.declare(name, ty.clone());
+                function.emit(Instruction::Store { local, value });
+            }
+            Stmt::Const(name, ty, value, span) => {
+                let stmt = Stmt::Let(name.clone(), ty.clone(), Some(value.clone()), span.clone());
+                return self.statement(function, &stmt);
+            }
+            Stmt::Assign(Expr::Identifier(name, _), value, span) => {
+                let Some(local) = function.lookup(name) else {
+                    return function.uncovered("global variables", span);
+                };
+                let value = self.expression(function, value)?;
+                function.emit(Instruction::Store { local, value });
+            }
+            Stmt::Return(value, _) => {
+                let value = match value {
+                    Some(value) => Some(self.expression(function, value)?),
+                    None => None,
+                };
+                function.terminate(Terminator::Return(value));
+            }
+            Stmt::While(cond, body, _) => {
+                let header = function.new_block();
+                function.end(function.current, Terminator::Jump(header));
+                function.current = header;
+                let cond = self.expression(function, cond)?;
+                let header_end = function.current;
+
+                let body_block = function.new_block();
+                function.current = body_block;
+                match body.as_ref() {
+                    Stmt::Block(stmts, _) => self.scope(function, stmts)?,
+                    body => self.scope(function, std::slice::from_ref(body))?,
+                }
+                function.end(function.current, Terminator::Jump(header));
+
+                let exit = function.new_block();
+                function.end(header_end, Terminator::Branch(cond, body_block, exit));
+                function.current = exit;
+            }
+            // Counted by a variable of its own, which sets the loop variable
+            // at the start of each run, so that assigning that does not
+            // change the runs. Both bounds are evaluated once, before the
+            // loop; a constant end is compared with as it is, which lets
+            // `unroll` count the runs.
+            Stmt::For(variable, _, Expr::Range(start, end, _), body, _) => {
+                let start = self.expression(function, start)?;
+                let counter = function.declare(&format!("z_i{}", function.function.locals.len()), Type::Int);
+                function.emit(Instruction::Store {
+                    local: counter,
+                    value: start,
+                });
+                let end = match self.expression(function, end)? {
+                    end @ Operand::Const(_) => end,
+                    end => {
+                        let local = function.declare(&format!("z_end{}", function.function.locals.len()), Type::Int);
+                        function.emit(Instruction::Store { local, value: end });
+                        Operand::Local(local)
+                    }
+                };
+                let header = function.new_block();
+                function.end(function.current, Terminator::Jump(header));
+                function.current = header;
+                let cond = function.temp(Type::Bool);
+                function.emit(Instruction::Binary {
+                    dest: cond,
+                    op: BinaryOp::Lt,
+                    left: Operand::Local(counter),
+                    right: end,
+                    overflow: None,
+                });
+
+                let body_block = function.new_block();
+                function.current = body_block;
+                function.scopes.push(HashMap::new());
+                let local = function.declare(variable, Type::Int);
+                function.emit(Instruction::Store {
+                    local,
+                    value: Operand::Local(counter),
+                });
+                let stmts = match body.as_ref() {
+                    Stmt::Block(stmts, _) => stmts.as_slice(),
+                    body => std::slice::from_ref(body),
+                };
+                let result = stmts.iter().try_for_each(|stmt| self.statement(function, stmt));
+                function.scopes.pop();
+                result?;
+                // Below the end, the counter cannot overflow
+                let next = function.temp(Type::Int);
+                function.emit(Instruction::Binary {
+                    dest: next,
+                    op: BinaryOp::Add,
+                    left: Operand::Local(counter),
+                    right: Operand::Const(Const::Int(1)),
+                    overflow: None,
+                });
+                function.emit(Instruction::Store {
+                    local: counter,
+                    value: Operand::Temp(next),
+                });
+                function.end(function.current, Terminator::Jump(header));
+
+                let exit = function.new_block();
+                function.end(header, Terminator::Branch(Operand::Temp(cond), body_block, exit));
+                function.current = exit;
+            }
+            stmt => return function.uncovered(uncovered_statement(stmt), stmt.span()),
+        }
+        Ok(())
+    }
+
+    /// Statements of a nested block, whose variables go out of scope where
+    /// it ends
+    fn scope(&self, function: &mut FunctionBuilder, stmts: &[Stmt]) -> Build<()> {
+        function.scopes.push(HashMap::new());
+        let result = stmts.iter().try_for_each(|stmt| self.statement(function, stmt));
+        function.scopes.pop();
+        result
+    }
+
+    /// A branch of an `if` statement
+    fn branch(&self, function: &mut FunctionBuilder, branch: &Expr) -> Build<()> {
+        match branch {
+            Expr::Block(stmts, _) => self.scope(function, stmts),
+            branch => self.scope(function, &[Stmt::Expr(branch.clone())]),
+        }
+    }
+
+    /// The body of a call the optimizer inlined, which runs in a frame of its
+    /// own with checks, as the call would. Its value is only computed when
+    /// `used`.
+    fn inlined(&self, function: &mut FunctionBuilder, name: &str, body: &Expr, span: &Span, used: bool) -> Build<Operand> {
+        if self.checks {
+            function.emit(Instruction::Enter {
+                frame: name.to_string(),
+                location: self.location(span),
+            });
+        }
+        let value = if used {
+            self.expression(function, body)?
+        } else {
+            self.statement(function, &Stmt::Expr(body.clone()))?;
+            Operand::Const(Const::Bool(false))
+        };
+        if self.checks {
+            function.emit(Instruction::Leave);
+        }
+        Ok(value)
+    }
+
+    /// Builds `expr`, whose instructions come from it unless they come from
+    /// one of its operands
+    fn expression(&self, function: &mut FunctionBuilder, expr: &Expr) -> Build<Operand> {
+        let outer = std::mem::replace(&mut function.span, expr.span().clone());
+        let result = self.expression_at(function, expr);
+        function.span = outer;
+        result
+    }
+
+    fn expression_at(&self, function: &mut FunctionBuilder, expr: &Expr) -> Build<Operand> {
+        match expr {
+            Expr::Inlined(name, body, span) => self.inlined(function, name, body, span, true),
+            // Literals beyond `i64` are `u64`, as the typechecker has them,
+            // made from the bits of an `int`
+            Expr::Literal(Literal::Int(value), _) if *value > i64::MAX as i128 => {
+                let dest = function.temp(Type::U64);
+                function.emit(Instruction::Cast {
+                    dest,
+                    operand: Operand::Const(Const::Int(*value as i64)),
+                });
+                Ok(Operand::Temp(dest))
+            }
+            Expr::Literal(Literal::Int(value), _) => Ok(Operand::Const(Const::Int(*value as i64))),
+            Expr::Literal(Literal::Float(value), _) => Ok(Operand::Const(Const::Float(*value))),
+            Expr::Literal(Literal::Bool(value), _) => Ok(Operand::Const(Const::Bool(*value))),
+            Expr::Identifier(name, span) => match function.lookup(name) {
+                Some(local) => Ok(Operand::Local(local)),
+                None => function.uncovered("global variables and functions as values", span),
+            },
+            Expr::Binary(left, op @ (BinaryOp::And | BinaryOp::Or), right, _) => {
+                self.short_circuit(function, left, op, right)
+            }
+            Expr::Binary(left, op, right, span) => {
+                let left = self.expression(function, left)?;
+                let right = self.expression(function, right)?;
+                // An integer literal takes the type of the other operand
+                let operand_type = match left {
+                    Operand::Const(_) => function.function.operand_type(&right),
+                    _ => function.function.operand_type(&left),
+                };
+                // Division is checked unless it is by a constant that can
+                // neither be zero nor overflow the smallest value
+                let checked = match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => true,
+                    BinaryOp::Div | BinaryOp::Mod => !matches!(right, Operand::Const(Const::Int(value)) if value != 0 && value != -1),
+                    _ => false,
+                };
+                let overflow = (self.checks && checked && operand_type.is_integer()).then(|| self.location(span));
+                let ty = match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => operand_type,
+                    _ => Type::Bool,
+                };
+                let dest = function.temp(ty);
+                function.emit(Instruction::Binary {
+                    dest,
+                    op: op.clone(),
+                    left,
+                    right,
+                    overflow,
+                });
+                Ok(Operand::Temp(dest))
+            }
+            Expr::Unary(op, operand, span) => {
+                let operand = self.expression(function, operand)?;
+                let ty = match op {
+                    UnaryOp::Neg => function.function.operand_type(&operand),
+                    UnaryOp::Not => Type::Bool,
+                };
+                // Checked, negating is subtracting from zero, which
+                // overflows for the smallest value; no constant is that value
+                if *op == UnaryOp::Neg && self.checks && ty.is_integer() && !matches!(operand, Operand::Const(_)) {
+                    let dest = function.temp(ty);
+                    function.emit(Instruction::Binary {
+                        dest,
+                        op: BinaryOp::Sub,
+                        left: Operand::Const(Const::Int(0)),
+                        right: operand,
+                        overflow: Some(self.location(span)),
+                    });
+                    return Ok(Operand::Temp(dest));
+                }
+                let dest = function.temp(ty);
+                function.emit(Instruction::Unary {
+                    dest,
+                    op: op.clone(),
+                    operand,
+                });
+                Ok(Operand::Temp(dest))
+            }
+            Expr::Cast(operand, ty, _) if is_scalar(ty) => {
+                let operand = self.expression(function, operand)?;
+                let dest = function.temp(ty.clone());
+                function.emit(Instruction::Cast { dest, operand });
+                Ok(Operand::Temp(dest))
+            }
+            Expr::Call(callee, args, span) => self.call(function, callee, args, span, true),
+            expr => function.uncovered(uncovered_expression(expr), expr.span()),
+        }
+    }
+
+    /// `left && right` or `left || right`, which evaluates `right` only when
+    /// `left` does not decide the result. A `right` computing nothing is read
+    /// either way; otherwise it gets blocks of its own.
+    fn short_circuit(&self, function: &mut FunctionBuilder, left: &Expr, op: &BinaryOp, right: &Expr) -> Build<Operand> {
+        let left = self.expression(function, left)?;
+        let before = function.current;
+        let right_block = function.new_block();
+        function.current = right_block;
+        let right = self.expression(function, right)?;
+
+        if function.current == right_block && function.function.blocks[right_block].instructions.is_empty() {
+            function.function.blocks.pop();
+            function.current = before;
+            let dest = function.temp(Type::Bool);
+            function.emit(Instruction::Binary {
+                dest,
+                op: op.clone(),
+                left,
+                right,
+                overflow: None,
+            });
+            return Ok(Operand::Temp(dest));
+        }
+
+        let result = function.declare(&format!("z_cond{}", function.function.locals.len()), Type::Bool);
+        let join = function.new_block();
+        function.emit(Instruction::Store { local: result, value: right });
+        function.end(function.current, Terminator::Jump(join));
+        function.current = before;
+        function.emit(Instruction::Store {
+            local: result,
+            value: left.clone(),
+        });
+        let branch = match op {
+            BinaryOp::And => Terminator::Branch(left, right_block, join),
+            _ => Terminator::Branch(left, join, right_block),
+        };
+        function.end(before, branch);
+        function.current = join;
+        Ok(Operand::Local(result))
+    }
+
+    /// A call of a function by name, or of a builtin the IR writes,
+    /// keeping the result when `used`
+    fn call(
+        &self,
+        function: &mut FunctionBuilder,
+        callee: &Expr,
+        args: &[Expr],
+        span: &Span,
+        used: bool,
+    ) -> Build<Operand> {
+        let name = match callee {
+            Expr::Identifier(name, _) if function.lookup(name).is_none() => name,
+            _ => return function.uncovered("calls of functions as values", span),
+        };
+        let Some((symbol, _, return_type)) = self.functions.get(name) else {
+            return self.builtin(function, name, args, span);
+        };
+        if !is_result(return_type) {
+            return function.uncovered("calls returning other values than numbers and bools", span);
+        }
+
+        let mut arg_operands = Vec::new();
+        for arg in args {
+            arg_operands.push(self.expression(function, arg)?);
+        }
+        if used && *return_type == Type::Void {
+            return function.uncovered("values of calls returning nothing", span);
+        }
+        let dest = used.then(|| function.temp(return_type.clone()));
+        function.emit(Instruction::Call {
+            dest,
+            symbol: symbol.clone(),
+            args: arg_operands,
+            location: self.checks.then(|| self.location(span)),
+        });
+        // Void results are never used
+        Ok(dest.map(Operand::Temp).unwrap_or(Operand::Const(Const::Bool(false))))
+    }
+
+    /// `print`, `printf`, `panic` and `assert`, which the IR writes with
+    /// literal strings only
+    fn builtin(&self, function: &mut FunctionBuilder, name: &str, args: &[Expr], span: &Span) -> Build<Operand> {
+        if name == "assert" {
+            let message = match args {
+                [_] => "assertion failed".to_string(),
+                [_, Expr::Literal(Literal::String(message), _)] => format!("assertion failed: {}", message),
+                _ => return function.uncovered("assertions with other messages than literal strings", span),
+            };
+            let cond = self.expression(function, &args[0])?;
+            let failed = function.new_block();
+            let passed = function.new_block();
+            function.end(function.current, Terminator::Branch(cond, passed, failed));
+            function.current = failed;
+            function.emit(Instruction::Panic {
+                message,
+                location: self.location(span),
+            });
+            function.current = passed;
+            return Ok(Operand::Const(Const::Bool(false)));
+        }
+        if name == "panic" {
+            let [Expr::Literal(Literal::String(message), _)] = args else {
+                return function.uncovered("panics with other messages than literal strings", span);
+            };
+            function.emit(Instruction::Panic {
+                message: message.clone(),
+                location: self.location(span),
+            });
+            return Ok(Operand::Const(Const::Bool(false)));
+        }
+        if !format::is_print(name) {
+            return function.uncovered(format!("the builtin {}", name), span);
+        }
+
+        let printf = "printf with other formats than literal strings";
+        let Some((format_pieces, values)) = format::call_pieces(name, args) else {
+            return function.uncovered(printf, span);
+        };
+        let mut values_written = Vec::new();
+        for value in values {
+            values_written.push(match value {
+                Expr::Literal(Literal::String(text), _) => Piece::Text(text.clone()),
+                value => Piece::Value(self.expression(function, value)?),
+            });
+        }
+        let mut values_written = values_written.into_iter();
+        let mut pieces: Vec<Piece> = Vec::new();
+        for piece in format_pieces {
+            let piece = match piece {
+                format::Piece::Text(text) => Piece::Text(text),
+                format::Piece::Value => match values_written.next() {
+                    Some(piece) => piece,
+                    None => return function.uncovered(printf, span),
+                },
+            };
+            // Neighbouring text is written at once
+            match (pieces.last_mut(), piece) {
+                (Some(Piece::Text(text)), Piece::Text(more)) => text.push_str(&more),
+                (_, piece) => pieces.push(piece),
+            }
+        }
+        function.emit(Instruction::Print(pieces));
+        Ok(Operand::Const(Const::Bool(false)))
+    }
+}
+
+/// What the IR does not cover in `stmt`, a statement it cannot build
+fn uncovered_statement(stmt: &Stmt) -> &'static str {
+    match stmt {
+        Stmt::Let(..) | Stmt::Const(..) => "variables of other types than numbers and bools",
+        Stmt::Assign(..) => "assignments to fields and elements",
+        Stmt::For(..) => "for loops over arrays",
+        Stmt::Function(..) => "nested functions",
+        Stmt::Struct(..) | Stmt::Enum(..) => "structs and enums",
+        _ => "such statements",
+    }
+}
+
+/// What the IR does not cover in `expr`, an expression it cannot build
+fn uncovered_expression(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::Literal(Literal::String(_), _) => "strings",
+        Expr::Literal(Literal::Null, _) => "null",
+        Expr::Index(..) => "indexing",
+        Expr::Field(..) => "fields",
+        Expr::Array(..) => "arrays",
+        Expr::If(..) | Expr::Block(..) => "if and block expressions",
+        Expr::Lambda(..) => "lambdas",
+        Expr::Range(..) => "ranges",
+        Expr::Cast(..) => "casts to other types than numbers and bools",
+        Expr::Match(..) => "match",
+        Expr::Variant(..) => "enums",
+        Expr::Try(..) => "the ? operator",
+        _ => "such expressions",
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for import in &self.imports {
+            let params: Vec<String> = import.params.iter().map(Type::to_string).collect();
+            writeln!(f, "import fn {}({}) -> {}", import.symbol, params.join(", "), import.return_type)?;
+        }
+        for function in &self.externs {
+            let params: Vec<String> = function.params.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+            writeln!(f, "extern fn {}({}) -> {}", function.name, params.join(", "), function.return_type)?;
+        }
+        for function in &self.functions {
+            writeln!(f)?;
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.locals[..self.params]
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect();
+        for attribute in &self.attributes {
+            writeln!(f, "{}", attribute)?;
+        }
+        let pure = if self.pure { "pure " } else { "" };
+        writeln!(f, "{}fn {}({}) -> {}", pure, self.symbol, params.join(", "), self.return_type)?;
+        for (name, ty) in &self.locals[self.params..] {
+            writeln!(f, "    let {}: {}", name, ty)?;
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", id)?;
+            for instruction in &block.instructions {
+                write!(f, "    ")?;
+                match instruction {
+                    Instruction::Binary {
+                        dest,
+                        op,
+                        left,
+                        right,
+                        overflow,
+                    } => {
+                        write!(
+                            f,
+                            "t{} = {} {} {}",
+                            dest,
+                            self.operand_name(left),
+                            op.symbol(),
+                            self.operand_name(right)
+                        )?;
+                        if let Some(location) = overflow {
+                            write!(f, "  checked at {}", location)?;
+                        }
+                    }
+                    Instruction::Unary { dest, op, operand } => {
+                        let op = if *op == UnaryOp::Neg { "-" } else { "!" };
+                        write!(f, "t{} = {}{}", dest, op, self.operand_name(operand))?;
+                    }
+                    Instruction::Bitwise {
+                        dest,
+                        op,
+                        left,
+                        right,
+                        overflow,
+                    } => {
+                        write!(
+                            f,
+                            "t{} = {} {} {}",
+                            dest,
+                            self.operand_name(left),
+                            op.symbol(),
+                            self.operand_name(right)
+                        )?;
+                        if let Some(location) = overflow {
+                            write!(f, "  checked at {}", location)?;
+                        }
+                    }
+                    Instruction::Cast { dest, operand } => {
+                        write!(f, "t{} = {} as {}", dest, self.operand_name(operand), self.temps[*dest])?;
+                    }
+                    Instruction::Store { local, value } => {
+                        write!(f, "{} = {}", self.locals[*local].0, self.operand_name(value))?;
+                    }
+                    Instruction::Call { dest, symbol, args, .. } => {
+                        if let Some(dest) = dest {
+                            write!(f, "t{} = ", dest)?;
+                        }
+                        let args: Vec<String> = args.iter().map(|arg| self.operand_name(arg)).collect();
+                        write!(f, "call {}({})", symbol, args.join(", "))?;
+                    }
+                    Instruction::Print(pieces) => {
+                        let pieces: Vec<String> = pieces
+                            .iter()
+                            .map(|piece| match piece {
+                                Piece::Text(text) => format!("{:?}", text),
+                                Piece::Value(value) => self.operand_name(value),
+                            })
+                            .collect();
+                        write!(f, "print {}", pieces.join(", "))?;
+                    }
+                    Instruction::Panic { message, location } => {
+                        write!(f, "panic {:?} at {}", message, location)?;
+                    }
+                    Instruction::Enter { frame, location } => write!(f, "enter {} at {}", frame, location)?,
+                    Instruction::Leave => write!(f, "leave")?,
+                }
+                writeln!(f)?;
+            }
+            match &block.terminator {
+                Terminator::Jump(target) => writeln!(f, "    jump b{}", target)?,
+                Terminator::Branch(cond, then_block, else_block) => {
+                    writeln!(f, "    branch {}, b{}, b{}", self.operand_name(cond), then_block, else_block)?
+                }
+                Terminator::Return(Some(value)) => writeln!(f, "    return {}", self.operand_name(value))?,
+                Terminator::Return(None) => writeln!(f, "    return")?,
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        Ok(())
+    }
+}
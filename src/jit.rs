@@ -0,0 +1,850 @@
+// In-process execution for `zc run`, enabled by the `jit` feature. The
+// optimized IR of the program is compiled with Cranelift into memory and
+// called directly, skipping the C compiler and the child process. It covers
+// what the IR does, but for calls of C functions and imported modules, with
+// the checks and stack traces of debug builds; other programs report
+// themselves unsupported and run through C instead.
+//
+// A panic cannot unwind the compiled code, so the runtime raises a flag the
+// code checks after each call, returning at once while it is up, and every
+// function checks the stack it has left on entry, to panic rather than
+// overflow it.
+
+use crate::ast::{self, BinaryOp, Type, UnaryOp};
+use crate::ir;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{types, AbiParam, Block, InstBuilder, MemFlags, Signature, TrapCode, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::io::Write;
+use std::os::raw::{c_char, c_int};
+use std::thread;
+
+#[derive(Debug)]
+pub struct JitError {
+    pub message: String,
+    /// Whether the program uses a feature the JIT does not cover, so it
+    /// should run through the C backend instead
+    pub unsupported: bool,
+}
+
+impl std::fmt::Display for JitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JIT error: {}", self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, JitError>;
+
+fn failed(message: String) -> JitError {
+    JitError {
+        message,
+        unsupported: false,
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T> {
+    Err(JitError {
+        message: format!("{} is not supported by the JIT", what),
+        unsupported: true,
+    })
+}
+
+// Writers for `print` and `printf`, called by the compiled code
+extern "C" fn z_write_str(text: *const c_char) {
+    let text = unsafe { CStr::from_ptr(text) };
+    let _ = std::io::stdout().write_all(text.to_bytes());
+}
+
+extern "C" fn z_write_int(value: i64) {
+    print!("{}", value);
+}
+
+extern "C" fn z_write_uint(value: u64) {
+    print!("{}", value);
+}
+
+// Floats are written by the C library as the C backend writes them, with
+// the fewest `%g` digits that read back as the same value, rather than by
+// Rust, whose formatting differs
+extern "C" {
+    fn snprintf(buffer: *mut c_char, size: usize, format: *const c_char, ...) -> c_int;
+}
+
+extern "C" fn z_write_float(value: f64) {
+    let mut buffer = [0 as c_char; 32];
+    let mut text: &[u8] = &[];
+    for precision in 1..=17 {
+        // Safety: the format takes an int and a double, and snprintf writes
+        // at most the buffer's length, null terminator included
+        text = unsafe {
+            snprintf(buffer.as_mut_ptr(), buffer.len(), b"%.*g\0".as_ptr().cast(), precision as c_int, value);
+            CStr::from_ptr(buffer.as_ptr()).to_bytes()
+        };
+        if std::str::from_utf8(text).ok().and_then(|text| text.parse::<f64>().ok()) == Some(value) {
+            break;
+        }
+    }
+    let _ = std::io::stdout().write_all(text);
+}
+
+extern "C" fn z_write_bool(value: i8) {
+    print!("{}", value != 0);
+}
+
+// The calls in progress, which debug builds record for the stack traces of
+// panics as the C runtime does. Each frame holds the location of the call
+// it is making. Calls nested deeper than MAX_FRAMES are counted only.
+const MAX_FRAMES: usize = 256;
+
+struct Frame {
+    function: *const c_char,
+    location: *const c_char,
+}
+
+#[derive(Default)]
+struct Runtime {
+    frames: Vec<Frame>,
+    depth: usize,
+    // The flag of the program running, raised by its panic
+    panicked: Option<*mut u8>,
+}
+
+thread_local! {
+    static RUNTIME: RefCell<Runtime> = RefCell::default();
+}
+
+fn text(text: *const c_char) -> String {
+    // Safety: the compiled code only passes null-terminated data of its module
+    unsafe { CStr::from_ptr(text) }.to_string_lossy().into_owned()
+}
+
+extern "C" fn z_enter(function: *const c_char) {
+    RUNTIME.with(|runtime| {
+        let mut runtime = runtime.borrow_mut();
+        if runtime.depth < MAX_FRAMES {
+            let depth = runtime.depth;
+            runtime.frames.truncate(depth);
+            runtime.frames.push(Frame {
+                function,
+                location: b"?\0".as_ptr().cast(),
+            });
+        }
+        runtime.depth += 1;
+    });
+}
+
+extern "C" fn z_leave() {
+    RUNTIME.with(|runtime| runtime.borrow_mut().depth -= 1);
+}
+
+extern "C" fn z_at(location: *const c_char) {
+    RUNTIME.with(|runtime| {
+        let mut runtime = runtime.borrow_mut();
+        let depth = runtime.depth;
+        if depth > 0 && depth <= MAX_FRAMES {
+            runtime.frames[depth - 1].location = location;
+        }
+    });
+}
+
+// Runtime errors print their message and the calls that led to it,
+// innermost first, and raise the program's flag, which ends it
+extern "C" fn z_panic(message: *const c_char, location: *const c_char) {
+    let _ = std::io::stdout().flush();
+    z_at(location);
+    RUNTIME.with(|runtime| {
+        let runtime = runtime.borrow();
+        let mut stderr = std::io::stderr().lock();
+        let _ = writeln!(stderr, "panic: {} at {}", text(message), text(location));
+        if runtime.depth > 0 {
+            let _ = writeln!(stderr, "stack backtrace:");
+        }
+        if runtime.depth > MAX_FRAMES {
+            let _ = writeln!(stderr, "    ({} innermost calls not recorded)", runtime.depth - MAX_FRAMES);
+        }
+        for frame in runtime.frames[..runtime.depth.min(MAX_FRAMES)].iter().rev() {
+            let _ = writeln!(stderr, "    {} at {}", text(frame.function), text(frame.location));
+        }
+        if let Some(panicked) = runtime.panicked {
+            // Safety: `JitProgram::run` points it at the flag of the module
+            // it runs, which outlives the run
+            unsafe { *panicked = 1 };
+        }
+    });
+}
+
+// As much stack as the main thread of a C program usually gets
+const STACK_SIZE: usize = 8 << 20;
+
+// The stack kept for the runtime, which functions are not entered into
+const STACK_RESERVE: usize = 256 << 10;
+
+/// A program compiled into executable memory
+pub struct JitProgram {
+    // Owns the memory the code and its data live in
+    _module: JITModule,
+    main: *const u8,
+    // The flag raised by a panic
+    panicked: *mut u8,
+    // The lowest address of the stack functions may be entered at
+    stack_limit: *mut u64,
+}
+
+impl JitProgram {
+    /// Runs `main` on a thread of its own, returning its exit status, which
+    /// is 1 when the program panics
+    pub fn run(&self) -> std::io::Result<i64> {
+        // Raw pointers cannot be sent to the thread, addresses can
+        let main = self.main as usize;
+        let panicked = self.panicked as usize;
+        let stack_limit = self.stack_limit as usize;
+        let thread = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+            let panicked = panicked as *mut u8;
+            let top = &panicked as *const _ as usize;
+            // Safety: both point into the data of the module, which the
+            // thread is done with before `run` returns
+            unsafe {
+                *panicked = 0;
+                *(stack_limit as *mut u64) = (top - STACK_SIZE + STACK_RESERVE) as u64;
+            }
+            RUNTIME.with(|runtime| runtime.borrow_mut().panicked = Some(panicked));
+            // Safety: `main` was compiled with the signature checked here,
+            // and the module keeping its code alive outlives the call
+            let status = unsafe {
+                let main: extern "C" fn() -> i64 = std::mem::transmute(main);
+                main()
+            };
+            // Safety: as above
+            if unsafe { *panicked } != 0 {
+                1
+            } else {
+                status
+            }
+        })?;
+        // The thread only ends without a status if zc itself panicked in it
+        let status = thread.join().unwrap_or(1);
+        let _ = std::io::stdout().flush();
+        Ok(status)
+    }
+}
+
+/// Compiles the IR of a program, made from `source` in `file`, into memory.
+/// Its checks of integer overflow, and the frames its panics print, are
+/// those of the build the IR was made for; division is always checked, as
+/// the fault of a division by zero would end zc too.
+pub fn compile(program: &ir::Module, file: &str, source: &str) -> Result<JitProgram> {
+    if !program.externs.is_empty() {
+        return unsupported("Calling C functions");
+    }
+    if !program.imports.is_empty() {
+        return unsupported("Calling imported modules");
+    }
+    let mut flags = settings::builder();
+    flags
+        .set("use_colocated_libcalls", "false")
+        .map_err(|e| failed(e.to_string()))?;
+    flags.set("is_pic", "false").map_err(|e| failed(e.to_string()))?;
+    let isa = cranelift_native::builder()
+        .map_err(|e| failed(e.to_string()))?
+        .finish(settings::Flags::new(flags))
+        .map_err(|e| failed(e.to_string()))?;
+    let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+    builder.symbol("z_write_str", z_write_str as *const u8);
+    builder.symbol("z_write_int", z_write_int as *const u8);
+    builder.symbol("z_write_uint", z_write_uint as *const u8);
+    builder.symbol("z_write_float", z_write_float as *const u8);
+    builder.symbol("z_write_bool", z_write_bool as *const u8);
+    builder.symbol("z_enter", z_enter as *const u8);
+    builder.symbol("z_leave", z_leave as *const u8);
+    builder.symbol("z_at", z_at as *const u8);
+    builder.symbol("z_panic", z_panic as *const u8);
+    let mut module = JITModule::new(builder);
+
+    // Declare every function first, so calls may come before definitions
+    let mut functions = HashMap::new();
+    for (name, params) in [
+        ("z_write_str", vec![Type::String]),
+        ("z_write_int", vec![Type::Int]),
+        ("z_write_uint", vec![Type::U64]),
+        ("z_write_float", vec![Type::Float]),
+        ("z_write_bool", vec![Type::Bool]),
+        ("z_enter", vec![Type::String]),
+        ("z_leave", vec![]),
+        ("z_at", vec![Type::String]),
+        ("z_panic", vec![Type::String, Type::String]),
+    ] {
+        let signature = signature(&module, &params, &Type::Void)?;
+        let id = module
+            .declare_function(name, Linkage::Import, &signature)
+            .map_err(|e| failed(e.to_string()))?;
+        functions.insert(name.to_string(), (id, params, Type::Void));
+    }
+    for function in &program.functions {
+        let params: Vec<Type> = function.locals[..function.params].iter().map(|(_, ty)| ty.clone()).collect();
+        let signature = signature(&module, &params, &function.return_type)?;
+        let id = module
+            .declare_function(&function.symbol, Linkage::Local, &signature)
+            .map_err(|e| failed(e.to_string()))?;
+        functions.insert(function.symbol.clone(), (id, params, function.return_type.clone()));
+    }
+    let mut data = Vec::new();
+    for (name, size) in [("z_panicked", 1), ("z_stack_limit", 8)] {
+        let id = module
+            .declare_data(name, Linkage::Local, true, false)
+            .map_err(|e| failed(e.to_string()))?;
+        let mut description = DataDescription::new();
+        description.define_zeroinit(size);
+        module
+            .define_data(id, &description)
+            .map_err(|e| failed(e.to_string()))?;
+        data.push(id);
+    }
+    let line_starts = ast::line_starts(source);
+
+    let mut context = module.make_context();
+    let mut builder_context = FunctionBuilderContext::new();
+    for function in &program.functions {
+        let (id, params, _) = functions[&function.symbol].clone();
+        context.func.signature = signature(&module, &params, &function.return_type)?;
+        let translator = Translator {
+            builder: FunctionBuilder::new(&mut context.func, &mut builder_context),
+            module: &mut module,
+            functions: &functions,
+            function,
+            panicked: data[0],
+            stack_limit: data[1],
+            file,
+            line_starts: &line_starts,
+            blocks: Vec::new(),
+            temps: HashMap::new(),
+        };
+        translator.function()?;
+        module
+            .define_function(id, &mut context)
+            .map_err(|e| failed(e.to_string()))?;
+        module.clear_context(&mut context);
+    }
+    module
+        .finalize_definitions()
+        .map_err(|e| failed(e.to_string()))?;
+
+    // `main` returns the exit status, 0 unless the program returns another
+    let main = match functions.get("main") {
+        Some((main, _, Type::Int)) => *main,
+        _ => return Err(failed("The program has no main function".to_string())),
+    };
+    Ok(JitProgram {
+        main: module.get_finalized_function(main),
+        panicked: module.get_finalized_data(data[0]).0 as *mut u8,
+        stack_limit: module.get_finalized_data(data[1]).0 as *mut u64,
+        _module: module,
+    })
+}
+
+fn value_type(ty: &Type) -> Result<types::Type> {
+    Ok(match ty {
+        Type::Int | Type::I64 | Type::U64 => types::I64,
+        Type::I32 | Type::U32 => types::I32,
+        Type::I16 | Type::U16 => types::I16,
+        Type::I8 | Type::U8 | Type::Bool => types::I8,
+        Type::Float => types::F64,
+        // Only the text the runtime writes, null-terminated data in the
+        // module
+        Type::String => types::I64,
+        other => return unsupported(&format!("Type {:?}", other)),
+    })
+}
+
+fn signature(module: &JITModule, params: &[Type], ret: &Type) -> Result<Signature> {
+    let mut signature = module.make_signature();
+    for ty in params {
+        signature.params.push(AbiParam::new(value_type(ty)?));
+    }
+    if *ret != Type::Void {
+        signature.returns.push(AbiParam::new(value_type(ret)?));
+    }
+    Ok(signature)
+}
+
+/// The bits of a value of `ty`, which its constants are limited to
+fn mask(ty: types::Type) -> i64 {
+    (u64::MAX >> (64 - ty.bits())) as i64
+}
+
+// The trap of blocks no path reaches the end of
+const UNREACHABLE: TrapCode = TrapCode::unwrap_user(1);
+
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    module: &'a mut JITModule,
+    functions: &'a HashMap<String, (FuncId, Vec<Type>, Type)>,
+    function: &'a ir::Function,
+    panicked: DataId,
+    stack_limit: DataId,
+    file: &'a str,
+    line_starts: &'a [usize],
+    // The block of each block of the function
+    blocks: Vec<Block>,
+    temps: HashMap<ir::Temp, Value>,
+}
+
+impl Translator<'_> {
+    /// The function, whose variables are those of Cranelift, starting at
+    /// zero but for the parameters. It panics where too little of the stack
+    /// is left to enter it, and debug builds run it in a frame of its own.
+    fn function(mut self) -> Result<()> {
+        let function = self.function;
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        self.builder.switch_to_block(entry);
+        for (local, (_, ty)) in function.locals.iter().enumerate() {
+            let variable = Variable::from_u32(local as u32);
+            self.builder.declare_var(variable, value_type(ty)?);
+            let value = if local < function.params {
+                self.builder.block_params(entry)[local]
+            } else {
+                self.zero(ty)?
+            };
+            self.builder.def_var(variable, value);
+        }
+        let stack_pointer = self.builder.ins().get_stack_pointer(types::I64);
+        let stack_limit = self.data(self.stack_limit);
+        let stack_limit = self.builder.ins().load(types::I64, MemFlags::trusted(), stack_limit, 0);
+        let overflow = self
+            .builder
+            .ins()
+            .icmp(IntCC::UnsignedLessThan, stack_pointer, stack_limit);
+        let location = self.location(&function.span);
+        self.check(overflow, "stack overflow", &location)?;
+        if let Some(frame) = &function.frame {
+            let frame = self.string_literal(frame)?;
+            self.runtime("z_enter", &[frame]);
+        }
+
+        // The entry of the IR may be jumped to, which the entry here may not
+        self.blocks = function.blocks.iter().map(|_| self.builder.create_block()).collect();
+        self.builder.ins().jump(self.blocks[0], &[]);
+        for (id, block) in function.blocks.iter().enumerate() {
+            self.builder.switch_to_block(self.blocks[id]);
+            self.block(block)?;
+        }
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+        Ok(())
+    }
+
+    fn block(&mut self, block: &ir::Block) -> Result<()> {
+        for (instruction, span) in block.instructions.iter().zip(&block.spans) {
+            self.instruction(instruction, span)?;
+            // Nothing after a panic runs
+            if let ir::Instruction::Panic { .. } = instruction {
+                return Ok(());
+            }
+        }
+        self.terminator(&block.terminator)
+    }
+
+    /// Calls the runtime function `name`
+    fn runtime(&mut self, name: &str, args: &[Value]) {
+        let callee = self.module.declare_func_in_func(self.functions[name].0, self.builder.func);
+        self.builder.ins().call(callee, args);
+    }
+
+    /// Panics with `message` at `location`, ending the current block
+    fn panic(&mut self, message: &str, location: &str) -> Result<()> {
+        let message = self.string_literal(message)?;
+        let location = self.string_literal(location)?;
+        self.runtime("z_panic", &[message, location]);
+        self.bail()
+    }
+
+    /// Returns from the function once a panic is raised, with a value
+    /// nothing reads
+    fn bail(&mut self) -> Result<()> {
+        let values = match &self.function.return_type {
+            Type::Void => Vec::new(),
+            ty => vec![self.zero(ty)?],
+        };
+        self.builder.ins().return_(&values);
+        Ok(())
+    }
+
+    /// The address of the module's `data`
+    fn data(&mut self, data: DataId) -> Value {
+        let global = self.module.declare_data_in_func(data, self.builder.func);
+        self.builder.ins().global_value(types::I64, global)
+    }
+
+    /// Where `span` starts, as the panics of the C backend print it
+    fn location(&self, span: &ast::Span) -> String {
+        format!("{}:{}", self.file, ast::line_of(self.line_starts, span.start) + 1)
+    }
+
+    /// Panics with `message` at `location` where `failed` holds, and carries
+    /// on in a new block otherwise
+    fn check(&mut self, failed: Value, message: &str, location: &str) -> Result<()> {
+        let panic_block = self.builder.create_block();
+        let ok_block = self.builder.create_block();
+        self.builder.set_cold_block(panic_block);
+        self.builder.ins().brif(failed, panic_block, &[], ok_block, &[]);
+        self.builder.switch_to_block(panic_block);
+        self.panic(message, location)?;
+        self.builder.switch_to_block(ok_block);
+        Ok(())
+    }
+
+    /// The value of `operand` as a value of `ty`. Constants, which are
+    /// `int`s, take on the type they are used as, as C converts them.
+    fn operand(&mut self, operand: &ir::Operand, ty: &Type) -> Result<Value> {
+        let value = match operand {
+            ir::Operand::Const(ir::Const::Int(value)) if *ty == Type::Float => self.builder.ins().f64const(*value as f64),
+            ir::Operand::Const(ir::Const::Int(value)) => {
+                let value_type = value_type(ty)?;
+                return Ok(self.builder.ins().iconst(value_type, value & mask(value_type)));
+            }
+            ir::Operand::Const(ir::Const::Float(value)) => self.builder.ins().f64const(*value),
+            ir::Operand::Const(ir::Const::Bool(value)) => self.builder.ins().iconst(types::I8, *value as i64),
+            ir::Operand::Local(local) => self.builder.use_var(Variable::from_u32(*local as u32)),
+            ir::Operand::Temp(temp) => self.temps[temp],
+        };
+        Ok(self.convert(value, &self.function.operand_type(operand), ty))
+    }
+
+    /// Changes the width of an integer, keeping its value where it fits
+    fn convert(&mut self, value: Value, from: &Type, to: &Type) -> Value {
+        let (from_type, to_type) = match (value_type(from), value_type(to)) {
+            (Ok(from_type), Ok(to_type)) if from.is_integer() && to.is_integer() => (from_type, to_type),
+            _ => return value,
+        };
+        if to_type.bits() > from_type.bits() {
+            if from.is_unsigned() {
+                self.builder.ins().uextend(to_type, value)
+            } else {
+                self.builder.ins().sextend(to_type, value)
+            }
+        } else if to_type.bits() < from_type.bits() {
+            self.builder.ins().ireduce(to_type, value)
+        } else {
+            value
+        }
+    }
+
+    fn zero(&mut self, ty: &Type) -> Result<Value> {
+        Ok(match ty {
+            Type::Float => self.builder.ins().f64const(0.0),
+            ty => self.builder.ins().iconst(value_type(ty)?, 0),
+        })
+    }
+
+    fn string_literal(&mut self, text: &str) -> Result<Value> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        let mut description = DataDescription::new();
+        description.define(bytes.into_boxed_slice());
+        let id = self
+            .module
+            .declare_anonymous_data(false, false)
+            .map_err(|e| failed(e.to_string()))?;
+        self.module
+            .define_data(id, &description)
+            .map_err(|e| failed(e.to_string()))?;
+        let global = self.module.declare_data_in_func(id, self.builder.func);
+        Ok(self.builder.ins().global_value(types::I64, global))
+    }
+
+    /// `instruction`, which comes from the code at `span`
+    fn instruction(&mut self, instruction: &ir::Instruction, span: &ast::Span) -> Result<()> {
+        let function = self.function;
+        match instruction {
+            ir::Instruction::Binary {
+                dest,
+                op,
+                left,
+                right,
+                overflow,
+            } => {
+                // Division is checked where the build does not, at the code
+                // it comes from
+                let location = match overflow {
+                    Some(location) => location.clone(),
+                    None => self.location(span),
+                };
+                let value = self.binary(&function.temps[*dest], op, left, right, overflow.is_some(), &location)?;
+                self.temps.insert(*dest, value);
+            }
+            ir::Instruction::Unary { dest, op, operand } => {
+                let ty = &function.temps[*dest];
+                let value = self.operand(operand, ty)?;
+                let value = match op {
+                    UnaryOp::Neg if *ty == Type::Float => self.builder.ins().fneg(value),
+                    UnaryOp::Neg => self.builder.ins().ineg(value),
+                    UnaryOp::Not => self.builder.ins().bxor_imm(value, 1),
+                };
+                self.temps.insert(*dest, value);
+            }
+            ir::Instruction::Bitwise {
+                dest,
+                op,
+                left,
+                right,
+                overflow,
+            } => {
+                let ty = &function.temps[*dest];
+                let left = self.operand(left, ty)?;
+                let right = self.operand(right, ty)?;
+                let value = self.bitwise(ty, op, left, right);
+                // The value shifted back differs where bits were lost
+                if let (ir::BitOp::Shl, Some(location)) = (op, overflow) {
+                    let back = self.bitwise(ty, &ir::BitOp::Shr, value, right);
+                    let lost = self.builder.ins().icmp(IntCC::NotEqual, back, left);
+                    self.check(lost, "integer overflow", location)?;
+                }
+                self.temps.insert(*dest, value);
+            }
+            ir::Instruction::Cast { dest, operand } => {
+                let from = function.operand_type(operand);
+                let value = self.operand(operand, &from)?;
+                let value = self.cast(value, &from, &function.temps[*dest])?;
+                self.temps.insert(*dest, value);
+            }
+            ir::Instruction::Store { local, value } => {
+                let value = self.operand(value, &function.locals[*local].1)?;
+                self.builder.def_var(Variable::from_u32(*local as u32), value);
+            }
+            ir::Instruction::Call {
+                dest,
+                symbol,
+                args,
+                location,
+            } => {
+                let (id, params, _) = match self.functions.get(symbol) {
+                    Some(callee) => callee.clone(),
+                    None => return Err(failed(format!("Call of the undeclared function {}", symbol))),
+                };
+                let mut values = Vec::new();
+                for (arg, ty) in args.iter().zip(&params) {
+                    values.push(self.operand(arg, ty)?);
+                }
+                if let Some(location) = location {
+                    let location = self.string_literal(location)?;
+                    self.runtime("z_at", &[location]);
+                }
+                let callee = self.module.declare_func_in_func(id, self.builder.func);
+                let call = self.builder.ins().call(callee, &values);
+                if let (Some(dest), Some(value)) = (dest, self.builder.inst_results(call).first()) {
+                    self.temps.insert(*dest, *value);
+                }
+                // Returning with the callee where it panicked
+                let panicked = self.data(self.panicked);
+                let panicked = self.builder.ins().load(types::I8, MemFlags::trusted(), panicked, 0);
+                let bail_block = self.builder.create_block();
+                let next_block = self.builder.create_block();
+                self.builder.set_cold_block(bail_block);
+                self.builder.ins().brif(panicked, bail_block, &[], next_block, &[]);
+                self.builder.switch_to_block(bail_block);
+                self.bail()?;
+                self.builder.switch_to_block(next_block);
+            }
+            ir::Instruction::Print(pieces) => {
+                for piece in pieces {
+                    let (writer, value) = match piece {
+                        ir::Piece::Text(text) => ("z_write_str", self.string_literal(text)?),
+                        ir::Piece::Value(value) => match function.operand_type(value) {
+                            Type::Float => ("z_write_float", self.operand(value, &Type::Float)?),
+                            Type::Bool => ("z_write_bool", self.operand(value, &Type::Bool)?),
+                            ty if ty.is_unsigned() => ("z_write_uint", self.operand(value, &Type::U64)?),
+                            _ => ("z_write_int", self.operand(value, &Type::Int)?),
+                        },
+                    };
+                    self.runtime(writer, &[value]);
+                }
+            }
+            ir::Instruction::Panic { message, location } => self.panic(message, location)?,
+            ir::Instruction::Enter { frame, location } => {
+                let location = self.string_literal(location)?;
+                self.runtime("z_at", &[location]);
+                let frame = self.string_literal(frame)?;
+                self.runtime("z_enter", &[frame]);
+            }
+            ir::Instruction::Leave => self.runtime("z_leave", &[]),
+        }
+        Ok(())
+    }
+
+    fn bitwise(&mut self, ty: &Type, op: &ir::BitOp, left: Value, right: Value) -> Value {
+        let ins = self.builder.ins();
+        match op {
+            ir::BitOp::Shl => ins.ishl(left, right),
+            ir::BitOp::Shr if ty.is_unsigned() => ins.ushr(left, right),
+            ir::BitOp::Shr => ins.sshr(left, right),
+            ir::BitOp::And => ins.band(left, right),
+        }
+    }
+
+    /// `left op right` for a result of `ty`, which panics at `location` on
+    /// a division by zero or, where `checked`, an overflow as C's checks do
+    fn binary(&mut self, ty: &Type, op: &BinaryOp, left: &ir::Operand, right: &ir::Operand, checked: bool, location: &str) -> Result<Value> {
+        // Comparisons are of operands of the type of the one not constant
+        let operand_type = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => ty.clone(),
+            _ => match left {
+                ir::Operand::Const(_) => self.function.operand_type(right),
+                _ => self.function.operand_type(left),
+            },
+        };
+        let left = self.operand(left, &operand_type)?;
+        let right = self.operand(right, &operand_type)?;
+        let ty = operand_type;
+
+        if ty.is_integer() && (checked || matches!(op, BinaryOp::Div | BinaryOp::Mod)) {
+            if let Some(result) = self.checked(left, op, right, &ty, location)? {
+                return Ok(result);
+            }
+        }
+
+        let ins = self.builder.ins();
+        if ty == Type::Float {
+            let condition = match op {
+                BinaryOp::Add => return Ok(ins.fadd(left, right)),
+                BinaryOp::Sub => return Ok(ins.fsub(left, right)),
+                BinaryOp::Mul => return Ok(ins.fmul(left, right)),
+                BinaryOp::Div => return Ok(ins.fdiv(left, right)),
+                BinaryOp::Mod => return unsupported("Float remainder"),
+                BinaryOp::Eq => FloatCC::Equal,
+                BinaryOp::Neq => FloatCC::NotEqual,
+                BinaryOp::Lt => FloatCC::LessThan,
+                BinaryOp::Lte => FloatCC::LessThanOrEqual,
+                BinaryOp::Gt => FloatCC::GreaterThan,
+                BinaryOp::Gte => FloatCC::GreaterThanOrEqual,
+                BinaryOp::And | BinaryOp::Or => unreachable!("logical operators are of bools"),
+            };
+            return Ok(ins.fcmp(condition, left, right));
+        }
+
+        let unsigned = ty.is_unsigned() || ty == Type::Bool;
+        let condition = match op {
+            BinaryOp::Add => return Ok(ins.iadd(left, right)),
+            BinaryOp::Sub => return Ok(ins.isub(left, right)),
+            BinaryOp::Mul => return Ok(ins.imul(left, right)),
+            BinaryOp::Div if unsigned => return Ok(ins.udiv(left, right)),
+            BinaryOp::Div => return Ok(ins.sdiv(left, right)),
+            BinaryOp::Mod if unsigned => return Ok(ins.urem(left, right)),
+            BinaryOp::Mod => return Ok(ins.srem(left, right)),
+            // Both operands are already evaluated
+            BinaryOp::And => return Ok(ins.band(left, right)),
+            BinaryOp::Or => return Ok(ins.bor(left, right)),
+            BinaryOp::Eq => IntCC::Equal,
+            BinaryOp::Neq => IntCC::NotEqual,
+            BinaryOp::Lt if unsigned => IntCC::UnsignedLessThan,
+            BinaryOp::Lt => IntCC::SignedLessThan,
+            BinaryOp::Lte if unsigned => IntCC::UnsignedLessThanOrEqual,
+            BinaryOp::Lte => IntCC::SignedLessThanOrEqual,
+            BinaryOp::Gt if unsigned => IntCC::UnsignedGreaterThan,
+            BinaryOp::Gt => IntCC::SignedGreaterThan,
+            BinaryOp::Gte if unsigned => IntCC::UnsignedGreaterThanOrEqual,
+            BinaryOp::Gte => IntCC::SignedGreaterThanOrEqual,
+        };
+        Ok(ins.icmp(condition, left, right))
+    }
+
+    /// Checked integer arithmetic, which panics at `location` on overflow,
+    /// and on division by zero. Division returns no value once checked, to
+    /// be done as usual.
+    fn checked(&mut self, left: Value, op: &BinaryOp, right: Value, ty: &Type, location: &str) -> Result<Option<Value>> {
+        let unsigned = ty.is_unsigned();
+        let ins = self.builder.ins();
+        let (result, overflow) = match op {
+            BinaryOp::Add if unsigned => ins.uadd_overflow(left, right),
+            BinaryOp::Add => ins.sadd_overflow(left, right),
+            BinaryOp::Sub if unsigned => ins.usub_overflow(left, right),
+            BinaryOp::Sub => ins.ssub_overflow(left, right),
+            BinaryOp::Mul if unsigned => ins.umul_overflow(left, right),
+            BinaryOp::Mul => ins.smul_overflow(left, right),
+            BinaryOp::Div | BinaryOp::Mod => {
+                let zero = self.builder.ins().icmp_imm(IntCC::Equal, right, 0);
+                self.check(zero, "division by zero", location)?;
+                if let Some((min, _)) = ty.integer_range().filter(|_| !unsigned) {
+                    let value_type = value_type(ty)?;
+                    let min = self.builder.ins().iconst(value_type, min as i64 & mask(value_type));
+                    let minus_one = self.builder.ins().iconst(value_type, mask(value_type));
+                    let smallest = self.builder.ins().icmp(IntCC::Equal, left, min);
+                    let by_minus_one = self.builder.ins().icmp(IntCC::Equal, right, minus_one);
+                    let overflow = self.builder.ins().band(smallest, by_minus_one);
+                    self.check(overflow, "integer overflow", location)?;
+                }
+                return Ok(None);
+            }
+            _ => return Ok(None),
+        };
+        self.check(overflow, "integer overflow", location)?;
+        Ok(Some(result))
+    }
+
+    fn cast(&mut self, value: Value, from: &Type, to: &Type) -> Result<Value> {
+        let signed = !from.is_unsigned() && *from != Type::Bool;
+        Ok(match (from, to) {
+            (from, to) if from == to => value,
+            (Type::Float, to) if to.is_integer() => {
+                // Through a 64-bit integer, wrapping like the interpreter does
+                let wide = self.builder.ins().fcvt_to_sint_sat(types::I64, value);
+                self.convert(wide, &Type::Int, to)
+            }
+            (Type::Float, Type::Bool) => {
+                let zero = self.builder.ins().f64const(0.0);
+                self.builder.ins().fcmp(FloatCC::NotEqual, value, zero)
+            }
+            (_, Type::Float) if signed => self.builder.ins().fcvt_from_sint(types::F64, value),
+            (_, Type::Float) => self.builder.ins().fcvt_from_uint(types::F64, value),
+            (_, Type::Bool) => self.builder.ins().icmp_imm(IntCC::NotEqual, value, 0),
+            (Type::Bool, to) => {
+                let to_type = value_type(to)?;
+                if to_type == types::I8 {
+                    value
+                } else {
+                    self.builder.ins().uextend(to_type, value)
+                }
+            }
+            // Between integers, extending by the signedness of the source
+            (from, to) => self.convert(value, from, to),
+        })
+    }
+
+    /// The end of a block, which leaves the function's frame where it returns
+    fn terminator(&mut self, terminator: &ir::Terminator) -> Result<()> {
+        match terminator {
+            ir::Terminator::Jump(target) => {
+                self.builder.ins().jump(self.blocks[*target], &[]);
+            }
+            ir::Terminator::Branch(condition, then_block, else_block) => {
+                let condition = self.operand(condition, &Type::Bool)?;
+                self.builder
+                    .ins()
+                    .brif(condition, self.blocks[*then_block], &[], self.blocks[*else_block], &[]);
+            }
+            ir::Terminator::Return(value) => {
+                let return_type = &self.function.return_type;
+                let values = match value {
+                    Some(value) => vec![self.operand(value, return_type)?],
+                    // `main` returns an exit status even when declared
+                    // without one
+                    None if *return_type != Type::Void => vec![self.zero(return_type)?],
+                    None => Vec::new(),
+                };
+                if self.function.frame.is_some() {
+                    self.runtime("z_leave", &[]);
+                }
+                self.builder.ins().return_(&values);
+            }
+            ir::Terminator::Unreachable => {
+                self.builder.ins().trap(UNREACHABLE);
+            }
+        }
+        Ok(())
+    }
+}
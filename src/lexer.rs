@@ -11,6 +11,9 @@ pub enum Token {
     #[token("let")]
     Let,
     
+    #[token("const")]
+    Const,
+    
     #[token("if")]
     If,
     
@@ -38,6 +41,18 @@ pub enum Token {
     #[token("import")]
     Import,
     
+    #[token("extern")]
+    Extern,
+    
+    #[token("pub")]
+    Pub,
+    
+    #[token("in")]
+    In,
+    
+    #[token("as")]
+    As,
+    
     #[token("true")]
     True,
     
@@ -89,6 +104,9 @@ pub enum Token {
     
     #[token("||")]
     Or,
+
+    #[token("|")]
+    Pipe,
     
     #[token("!")]
     Not,
@@ -121,12 +139,24 @@ pub enum Token {
     #[token(".")]
     Dot,
     
+    #[token("..")]
+    DotDot,
+    
     #[token(":")]
     Colon,
     
     #[token("->")]
     Arrow,
     
+    #[token("=>")]
+    FatArrow,
+    
+    #[token("?")]
+    Question,
+    
+    #[token("@")]
+    At,
+    
     // Literals
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*")]
     Identifier,
@@ -170,16 +200,18 @@ impl fmt::Display for Token {
 pub struct Span {
     pub token: Token,
     pub span: Range<usize>,
+    pub text: String,
 }
 
 pub struct LexerError {
+    pub code: &'static str,
     pub message: String,
     pub span: Range<usize>,
 }
 
 impl fmt::Display for LexerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Lexer error at position {}: {}", self.span.start, self.message)
+        write!(f, "Lexer error[{}] at position {}: {}", self.code, self.span.start, self.message)
     }
 }
 
@@ -190,9 +222,14 @@ pub fn lex(source: &str) -> Result<Vec<Span>, LexerError> {
     while let Some(token) = lexer.next() {
         let span = lexer.span();
         match token {
-            Ok(token) => tokens.push(Span { token, span }),
+            Ok(token) => tokens.push(Span {
+                token,
+                text: lexer.slice().to_string(),
+                span,
+            }),
             Err(_) => {
                 return Err(LexerError {
+                    code: "E0001",
                     message: format!("Invalid token: '{}'", &source[span.clone()]),
                     span,
                 });
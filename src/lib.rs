@@ -1,177 +1,1394 @@
-mod lexer;
+pub mod lexer;
 mod parser;
-mod ast;
+pub mod ast;
+#[cfg(feature = "c")]
+mod bench;
+#[cfg(feature = "capi")]
+mod capi;
+#[cfg(feature = "codegen")]
 mod codegen;
-mod error;
+#[cfg(feature = "codegen")]
+pub mod ir;
+#[cfg(feature = "jit")]
+mod jit;
+#[cfg(feature = "llvm")]
+mod llvm;
 mod typechecker;
+mod config;
+mod consteval;
+mod symbols;
+mod ownership;
+mod deadcode;
+mod diagnostic;
+mod dump;
+mod explain;
+mod format;
+#[cfg(feature = "c")]
+mod hooks;
+mod incremental;
+mod lint;
+#[cfg(feature = "codegen")]
 mod optimizer;
+#[cfg(feature = "c")]
+mod optreport;
+mod mangle;
+mod modules;
+mod packages;
+mod project;
+mod interpreter;
+#[cfg(feature = "c")]
+mod sandbox;
+mod semantic;
+pub mod source_map;
+mod stdlib;
+mod timings;
+#[cfg(feature = "c")]
+mod toolchain;
+#[cfg(feature = "dap")]
+mod dap;
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 use std::path::Path;
+#[cfg(feature = "c")]
+use std::path::PathBuf;
+#[cfg(feature = "c")]
+use std::sync::{Arc, Mutex, PoisonError};
 use std::fs;
+use diagnostic::ToDiagnostic;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[cfg(feature = "c")]
+pub use bench::{BenchReport, BenchResult};
+pub use config::{Config, CONFIG_FILES};
+#[cfg(feature = "c")]
+pub use codegen::{CBackend, CCompiler, LinkKind, LinkOptions};
+#[cfg(feature = "codegen")]
+pub use codegen::Gc;
+pub use deadcode::{DeadCodeReport, DeadField, DeadFunction};
+pub use diagnostic::{
+    emit, set_color, set_diagnostic_sink, set_warning_levels, ColorChoice, Diagnostic, DiagnosticSink, JsonSink,
+    MemorySink, Severity, SharedSink, StderrSink,
+};
+pub use explain::{explain, Explanation, EXPLANATIONS};
+#[cfg(feature = "c")]
+pub use hooks::Observer;
+pub use incremental::{CheckedFile, Database, Query};
+pub use interpreter::Value;
+pub use lint::{Level as LintLevel, LintConfig};
+pub use modules::ImportPaths;
+#[cfg(feature = "codegen")]
+pub use optimizer::{Pass, PassManager, PassStage, Remark};
+#[cfg(feature = "c")]
+pub use optreport::{OptRemark, OptReport};
+pub use packages::LOCK_FILE;
+pub use project::{create as create_project, Dependency, Manifest, Source, MANIFEST};
+#[cfg(feature = "c")]
+pub use sandbox::{RunLimits, TIMEOUT_EXIT_CODE};
+pub use semantic::SemanticModel;
+pub use source_map::{FileId, SourceFile, SourceMap};
+pub use symbols::{Symbol, SymbolKind, SymbolTable};
+#[cfg(feature = "c")]
+pub use toolchain::{compilers, targets, CompilerInfo, Environment, TargetSupport, TARGETS};
+pub use visit::{Visitor, VisitorMut};
+pub use timings::{finish as finish_timings, start as start_timings, Stage, Timings, TimingsFormat};
+
+#[derive(Error, Debug, miette::Diagnostic)]
 pub enum CompilerError {
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     
-    #[error("Lexer error: {0}")]
-    LexerError(String),
-    
-    #[error("Parser error: {0}")]
-    ParserError(String),
-    
     #[error("Type error: {0}")]
     TypeError(String),
     
     #[error("Code generation error: {0}")]
     CodegenError(String),
+    
+    #[error("Evaluation error: {0}")]
+    EvalError(String),
+    
+    #[error("Manifest error: {0}")]
+    ManifestError(String),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    /// Errors in source files, which the terminal shows with the code they
+    /// point at
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))]
+    Diagnostics(Vec<Diagnostic>),
+}
+
+impl CompilerError {
+    /// The errors `problems` found in `file`, whose text is `source`
+    pub(crate) fn at<T: ToDiagnostic>(file: &str, source: &str, problems: &[T]) -> Self {
+        CompilerError::Diagnostics(problems.iter().map(|problem| problem.to_diagnostic(file, source)).collect())
+    }
 }
 
 pub type Result<T> = std::result::Result<T, CompilerError>;
 
-/// Compiles a Z source file to an executable
-pub fn compile_file(input: &Path, output: &Path, opt_level: u8) -> Result<()> {
-    // Read the source file
-    let source = fs::read_to_string(input)?;
-    
-    // Lexical analysis
-    let tokens = lexer::lex(&source)
-        .map_err(|e| CompilerError::LexerError(e.to_string()))?;
+// Compilations keep their state in these and in thread-locals, so that
+// several can run at once on different threads, as in a language server
+const _: () = {
+    fn send<T: Send + Sync>() {}
+    #[allow(dead_code)]
+    fn sessions() {
+        #[cfg(feature = "c")]
+        send::<Compiler>();
+        #[cfg(feature = "c")]
+        send::<CompileResult>();
+        send::<SemanticModel>();
+    }
+};
+
+/// What `compile_file` builds
+#[cfg(feature = "codegen")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrateType {
+    /// An executable running `main`
+    #[default]
+    Bin,
+    /// A library of the `pub` functions, static unless linked as shared,
+    /// with a header declaring them
+    Lib,
+}
+
+#[cfg(feature = "codegen")]
+impl std::str::FromStr for CrateType {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "bin" => Ok(CrateType::Bin),
+            "lib" => Ok(CrateType::Lib),
+            _ => Err(format!("unknown crate type {}, expected bin or lib", name)),
+        }
+    }
+}
+
+/// The stage `compile_file` stops at, whose artifact it writes to the output
+#[cfg(feature = "c")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Emit {
+    /// The tokens, with their positions
+    Tokens,
+    /// The syntax tree as parsed
+    Ast,
+    /// The syntax tree as checked, before optimization
+    TypedAst,
+    /// The intermediate representation after the optimization passes, for
+    /// the executables it covers
+    Ir,
+    /// The generated C
+    C,
+    /// The assembly the C compiler generates
+    Asm,
+    /// An object file, without the imported modules
+    Obj,
+    /// The executable, or the library
+    #[default]
+    Exe,
+}
+
+#[cfg(feature = "c")]
+impl Emit {
+    /// Whether the artifact is text, which can be written to stdout
+    pub fn is_text(self) -> bool {
+        self < Emit::Obj
+    }
+}
+
+#[cfg(feature = "c")]
+impl std::str::FromStr for Emit {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "tokens" => Ok(Emit::Tokens),
+            "ast" => Ok(Emit::Ast),
+            "typed-ast" => Ok(Emit::TypedAst),
+            "ir" => Ok(Emit::Ir),
+            "c" => Ok(Emit::C),
+            "asm" => Ok(Emit::Asm),
+            "obj" => Ok(Emit::Obj),
+            "exe" => Ok(Emit::Exe),
+            _ => Err(format!(
+                "unknown stage {}, expected tokens, ast, typed-ast, ir, c, asm, obj or exe",
+                name
+            )),
+        }
+    }
+}
+
+/// The output path standing for stdout
+#[cfg(feature = "c")]
+pub const STDOUT: &str = "-";
+
+/// Writes a text artifact to `output`, or to stdout for `STDOUT`
+#[cfg(feature = "c")]
+fn write_text(output: &Path, text: &str) -> Result<()> {
+    if output == Path::new(STDOUT) {
+        print!("{}", text);
+    } else {
+        fs::write(output, text)?;
+    }
+    Ok(())
+}
+
+/// The module a source file is compiled as: its name without the extension
+fn module_name(input: &Path) -> String {
+    input.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+/// Compiles Z source files, as `zc compile` does, with the options set on
+/// it. Options left unset are those of `zc compile` without flags, so that
+/// options can be added without breaking callers.
+#[cfg(feature = "c")]
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    imports: Option<ImportPaths>,
+    passes: PassManager,
+    strict_memory: bool,
+    warnings_as_errors: bool,
+    warning_levels: Option<Vec<(String, LintLevel)>>,
+    emit: Emit,
+    emit_c: Option<PathBuf>,
+    emit_header: bool,
+    keep_temps: bool,
+    crate_type: CrateType,
+    gc: Gc,
+    release: bool,
+    c_compiler: Option<CCompiler>,
+    cc: Option<String>,
+    target: Option<String>,
+    debug_info: bool,
+    observers: Vec<Arc<Mutex<dyn Observer + Send>>>,
+    sink: Option<SharedSink>,
+}
+
+#[cfg(feature = "c")]
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler {
+            imports: None,
+            passes: PassManager::new(3),
+            strict_memory: false,
+            warnings_as_errors: false,
+            warning_levels: None,
+            emit: Emit::Exe,
+            emit_c: None,
+            emit_header: false,
+            keep_temps: false,
+            crate_type: CrateType::Bin,
+            gc: Gc::RefCount,
+            release: false,
+            c_compiler: None,
+            cc: None,
+            target: None,
+            debug_info: false,
+            observers: Vec::new(),
+            sink: None,
+        }
+    }
+}
+
+#[cfg(feature = "c")]
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler::default()
+    }
+
+    /// Where imported modules are found, by default the directory of the
+    /// file compiled
+    pub fn imports(&mut self, imports: ImportPaths) -> &mut Self {
+        self.imports = Some(imports);
+        self
+    }
+
+    /// Runs the optimizations of level `opt_level`, from 0 to 3; 3 by default
+    pub fn opt_level(&mut self, opt_level: u8) -> &mut Self {
+        self.passes = PassManager::new(opt_level);
+        self
+    }
+
+    /// Runs `passes`, for optimizations turned on or off by name
+    pub fn passes(&mut self, passes: PassManager) -> &mut Self {
+        self.passes = passes;
+        self
+    }
+
+    /// Runs `pass` along with the built-in passes. `opt_level` and `passes`
+    /// start over from their passes, so they come first.
+    pub fn pass(&mut self, pass: Arc<dyn Pass>) -> &mut Self {
+        self.passes.register(pass);
+        self
+    }
+
+    /// Rejects uses of heap values after they are moved
+    pub fn strict_memory(&mut self, strict_memory: bool) -> &mut Self {
+        self.strict_memory = strict_memory;
+        self
+    }
+
+    /// Fails on the warnings that are not allowed, in the file and the
+    /// modules it imports
+    pub fn warnings_as_errors(&mut self, warnings_as_errors: bool) -> &mut Self {
+        self.warnings_as_errors = warnings_as_errors;
+        self
+    }
+
+    /// Reports the warnings with the given codes at these levels, instead
+    /// of those `set_warning_levels` set
+    pub fn warning_levels(&mut self, levels: &[(String, LintLevel)]) -> &mut Self {
+        self.warning_levels = Some(levels.to_vec());
+        self
+    }
+
+    /// Stops at `emit`, whose artifact is written to the output; text
+    /// artifacts go to stdout for `STDOUT`, which the others refuse
+    pub fn emit(&mut self, emit: Emit) -> &mut Self {
+        self.emit = emit;
+        self
+    }
+
+    /// Also writes the generated C to `path`
+    pub fn emit_c(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.emit_c = Some(path.into());
+        self
+    }
+
+    /// Writes a C header declaring the `pub` functions next to an object
+    /// file or the executable, as libraries always do
+    pub fn emit_header(&mut self, emit_header: bool) -> &mut Self {
+        self.emit_header = emit_header;
+        self
+    }
+
+    /// Keeps the intermediate C and object files next to the output
+    pub fn keep_temps(&mut self, keep_temps: bool) -> &mut Self {
+        self.keep_temps = keep_temps;
+        self
+    }
+
+    pub fn crate_type(&mut self, crate_type: CrateType) -> &mut Self {
+        self.crate_type = crate_type;
+        self
+    }
+
+    /// How the program manages memory
+    pub fn gc(&mut self, gc: Gc) -> &mut Self {
+        self.gc = gc;
+        self
+    }
+
+    /// Leaves out the checks for errors at run time
+    pub fn release(&mut self, release: bool) -> &mut Self {
+        self.release = release;
+        self
+    }
+
+    /// Builds with `compiler`, with its flags and the libraries it links,
+    /// instead of the C compiler found as `zc compile` finds it
+    pub fn c_compiler(&mut self, compiler: CCompiler) -> &mut Self {
+        self.c_compiler = Some(compiler);
+        self
+    }
+
+    /// Runs `command` as the C compiler, as `--cc` does
+    pub fn cc(&mut self, command: &str) -> &mut Self {
+        self.cc = Some(command.to_string());
+        self
+    }
+
+    /// Builds for the target triple `target`, as `--target` does
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Has the C compiler generate debug information
+    pub fn debug_info(&mut self, debug_info: bool) -> &mut Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    /// Adds `observer` to watch, and possibly change, the artifacts of each
+    /// stage. Observers are called in the order they were added.
+    pub fn observer(&mut self, observer: Arc<Mutex<dyn Observer + Send>>) -> &mut Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Reports the warnings found, and notes such as packages fetched, to
+    /// `sink` as they come instead of returning the warnings
+    pub fn diagnostic_sink(&mut self, sink: SharedSink) -> &mut Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Compiles the file `input` to `output`. The warnings found are
+    /// returned rather than printed, unless a sink is set; when compiling
+    /// fails with diagnostics, they come before the errors.
+    pub fn compile(&self, input: &Path, output: &Path) -> Result<CompileResult> {
+        let mut files = SourceMap::new();
+        let mut report = OptReport::default();
+        let mut compile = || match &self.sink {
+            Some(sink) => {
+                let compiled = diagnostic::with_sink(sink.clone(), || self.compile_into(input, output, &mut files, &mut report));
+                (compiled, Vec::new())
+            }
+            None => diagnostic::collect(|| self.compile_into(input, output, &mut files, &mut report)),
+        };
+        let ((compiled, diagnostics), stages) = timings::record(|| match &self.warning_levels {
+            Some(levels) => diagnostic::with_warning_levels(levels, compile),
+            None => compile(),
+        });
+        match compiled {
+            Ok(()) => {}
+            Err(CompilerError::Diagnostics(errors)) => {
+                return Err(CompilerError::Diagnostics(diagnostics.into_iter().chain(errors).collect()))
+            }
+            Err(e) => return Err(e),
+        }
+
+        let mut artifacts = Vec::new();
+        if output != Path::new(STDOUT) {
+            artifacts.push(output.to_path_buf());
+        }
+        if (self.emit_header || self.crate_type == CrateType::Lib) && !self.emit.is_text() {
+            artifacts.push(output.with_extension("h"));
+        }
+        if let Some(path) = self.emit_c.as_ref().filter(|_| self.emit >= Emit::C) {
+            artifacts.push(path.clone());
+        }
+        Ok(CompileResult {
+            artifacts,
+            stages,
+            diagnostics,
+            files,
+            report,
+        })
+    }
+
+    /// Calls `hook` on each observer, in the order they were added
+    fn observe(&self, mut hook: impl FnMut(&mut dyn Observer)) {
+        for observer in &self.observers {
+            let mut observer = observer.lock().unwrap_or_else(PoisonError::into_inner);
+            hook(&mut *observer);
+        }
+    }
+
+    /// The C compiler building `program`: the one set, or else the one
+    /// found, once a build turns out to need one
+    fn resolve_c_compiler(&self, program: &ast::Program) -> Result<CCompiler> {
+        let mut compiler = match &self.c_compiler {
+            Some(compiler) => compiler.clone(),
+            None => CCompiler::resolve(None, self.cc.as_deref(), None)
+                .map_err(|e| CompilerError::CodegenError(e.message))?,
+        };
+        if let Some(cc) = &self.cc {
+            compiler.command = cc.clone();
+        }
+        if let Some(target) = &self.target {
+            compiler.set_target(target).map_err(|e| CompilerError::CodegenError(e.message))?;
+        }
+        compiler.debug_info |= self.debug_info;
+        compiler.link_externs(program);
+        compiler.link_gc(self.gc);
+        Ok(compiler)
+    }
+
+    /// Compiles `input` to `output`, adding the files read to `files` and
+    /// what the passes did to `report`
+    fn compile_into(&self, input: &Path, output: &Path, files: &mut SourceMap, report: &mut OptReport) -> Result<()> {
+        // Only text goes to stdout, whichever backend builds the rest
+        if !self.emit.is_text() && output == Path::new(STDOUT) {
+            let artifacts = match self.emit {
+                Emit::Obj => "Object files",
+                _ if self.crate_type == CrateType::Lib => "Libraries",
+                _ => "Executables",
+            };
+            return Err(CompilerError::CodegenError(format!("{} cannot be written to stdout", artifacts)));
+        }
+
+        // Read the source file
+        let source = timings::stage("read", || fs::read_to_string(input))?;
+        let file = input.display().to_string();
+        let id = files.add(&file, &source);
     
-    // Parsing
-    let ast = parser::parse(tokens)
-        .map_err(|e| CompilerError::ParserError(e.to_string()))?;
+        // Lexical analysis
+        let mut tokens = timings::stage("lex", || lexer::lex(&source))
+            .map_err(|e| files.errors(id, &[e]))?;
+        self.observe(|observer| observer.on_tokens(&mut tokens));
+        if self.emit == Emit::Tokens {
+            return write_text(output, &dump::tokens_text(&tokens, &source));
+        }
     
-    // Type checking
-    let typed_ast = typechecker::typecheck(ast)
-        .map_err(|e| CompilerError::TypeError(e.to_string()))?;
+        // Parsing
+        let mut ast = timings::stage("parse", || parser::parse_file(tokens, id))
+            .map_err(|e| files.errors(id, &[e]))?;
+        self.observe(|observer| observer.on_ast(&mut ast));
+        if self.emit == Emit::Ast {
+            return write_text(output, &dump::ast_text(&ast));
+        }
     
-    // Code generation
-    let ir = codegen::generate_ir(typed_ast)
-        .map_err(|e| CompilerError::CodegenError(e.to_string()))?;
+        // Type checking, after the imported modules
+        let options = typechecker::CheckOptions {
+            strict_memory: self.strict_memory,
+            deny_warnings: self.warnings_as_errors,
+        };
+        let imports = self.imports.clone().unwrap_or_else(|| ImportPaths::of_file(input));
+        let modules = timings::stage("imports", || modules::load_imports(&mut ast, input, &imports, &options, files))?;
+        let mut checked = timings::stage("typecheck", || typechecker::typecheck_with(ast, &options))
+            .map_err(|errors| files.errors(id, &errors))?;
+        diagnostic::emit_warnings(&file, &source, &checked.warnings, self.warnings_as_errors)?;
+        self.observe(|observer| observer.on_typed_ast(&mut checked.program));
+        if self.emit == Emit::TypedAst {
+            return write_text(output, &dump::ast_text(&checked.program));
+        }
+        let mut remarks = Vec::new();
+        let typed_ast = timings::stage("optimize", || self.passes.run_program(checked.program, &mut remarks));
+        if (self.emit_header || self.crate_type == CrateType::Lib) && !self.emit.is_text() {
+            let stem = output.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+            let guard: String = stem
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+                .collect();
+            let header = codegen::generate_header(&typed_ast, &format!("{}_H", guard))
+                .map_err(|e| CompilerError::at(&file, &source, &[e]))?;
+            fs::write(output.with_extension("h"), header)?;
+        }
     
-    // Optimization
-    let optimized_ir = optimizer::optimize(ir, opt_level)
-        .map_err(|e| CompilerError::CodegenError(e.to_string()))?;
+        // Code generation and optimization
+        let module = module_name(input);
+        let codegen_options = codegen::CodegenOptions { gc: self.gc, checks: !self.release };
+        if self.emit == Emit::Ir {
+            let ir = match self.crate_type {
+                CrateType::Bin => codegen::generate_ir(&typed_ast, &module, &file, &source, &codegen_options),
+                CrateType::Lib => {
+                    return Err(CompilerError::CodegenError(
+                        "The intermediate representation only covers executables".to_string(),
+                    ))
+                }
+            }
+            .map_err(|uncovered| {
+                let error = codegen::CodegenError {
+                    message: format!(
+                        "The intermediate representation does not cover {}, used in {}",
+                        uncovered.what, uncovered.function
+                    ),
+                    span: Some(uncovered.span),
+                };
+                CompilerError::at(&file, &source, &[error])
+            })?;
+            let mut ir = self.passes.run_ir(ir, &mut remarks);
+            self.observe(|observer| observer.on_ir(&mut ir));
+            report.add(&file, &source, remarks);
+            return write_text(output, &ir.to_string());
+        }
+
+        // Assembly and object code straight from LLVM, from the optimized IR
+        // of the executables it covers. Builds keeping the C or observing
+        // it, builds with debug information, which LLVM is not asked for,
+        // and those linking imported modules go through the C compiler.
+        #[cfg(feature = "llvm")]
+        if self.emit >= Emit::Asm
+            && self.crate_type == CrateType::Bin
+            && modules.is_empty()
+            && self.emit_c.is_none()
+            && !self.keep_temps
+            && self.observers.is_empty()
+            && !self.debug_info
+            && !self.c_compiler.as_ref().is_some_and(|compiler| compiler.debug_info)
+        {
+            // Programs the IR does not cover go through C, which says why
+            if let Ok(ir) = codegen::generate_ir(&typed_ast, &module, &file, &source, &codegen_options) {
+                let mut ir_remarks = Vec::new();
+                let ir = self.passes.run_ir(ir, &mut ir_remarks);
+                let options = llvm::Options {
+                    opt_level: self.passes.opt_level(),
+                    target: self.target.clone().or_else(|| self.c_compiler.as_ref()?.target.clone()),
+                    native_cpu: self.c_compiler.as_ref().is_some_and(|compiler| compiler.native_cpu),
+                };
+                let kind = if self.emit == Emit::Asm { llvm::Output::Assembly } else { llvm::Output::Object };
+                match timings::stage("llvm", || llvm::compile(&ir, &file, &options, kind)) {
+                    Ok(code) => {
+                        remarks.append(&mut ir_remarks);
+                        report.add(&file, &source, remarks);
+                        return self.write_machine_code(&code, output, &typed_ast);
+                    }
+                    Err(e) if e.unsupported => {}
+                    Err(e) => return Err(CompilerError::CodegenError(e.message)),
+                }
+            }
+        }
+        let mut compiler = self.resolve_c_compiler(&typed_ast)?;
+        let mut c_code = timings::stage("codegen", || {
+            let on_ir = |ir: &mut ir::Module| self.observe(|observer| observer.on_ir(ir));
+            generate_c(
+                typed_ast,
+                self.crate_type,
+                &module,
+                &file,
+                &source,
+                &codegen_options,
+                &self.passes,
+                &mut remarks,
+                on_ir,
+            )
+        })?;
+        self.observe(|observer| observer.on_c(&mut c_code));
+        timings::output_size(c_code.len() as u64);
+        report.add(&file, &source, remarks);
     
-    // Generate executable
-    codegen::generate_executable(&optimized_ir, output)
-        .map_err(|e| CompilerError::CodegenError(e.to_string()))?;
+        if let Some(path) = &self.emit_c {
+            fs::write(path, &c_code)?;
+        }
+        match self.emit {
+            Emit::C => return write_text(output, &c_code),
+            Emit::Asm => {
+                // Intermediate files kept for stdout are named after the input
+                let temps = if output == Path::new(STDOUT) { input } else { output };
+                let assembly = timings::stage("cc", || codegen::generate_assembly(&c_code, temps, self.keep_temps, &compiler))
+                    .map_err(|e| CompilerError::CodegenError(e.message))?;
+                timings::output_size(assembly.len() as u64);
+                return write_text(output, &assembly);
+            }
+            Emit::Obj => {
+                let compiler = if self.crate_type == CrateType::Lib { compiler.without_lto() } else { compiler };
+                timings::stage("cc", || codegen::generate_object(&c_code, output, &compiler))
+                    .map_err(|e| CompilerError::CodegenError(e.message))?;
+                record_file_size(output);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        // Imported modules are linked in, and archived with a library
+        let archive = self.crate_type == CrateType::Lib && compiler.link.kind != LinkKind::Shared;
+        let module_compiler = if archive { compiler.without_lto() } else { compiler.clone() };
+        timings::stage("cc", || {
+            let objects = modules::build_objects(
+                modules,
+                &modules::cache_dir(input),
+                &codegen_options,
+                &self.passes,
+                &module_compiler,
+                report,
+            )?;
+            compiler.link.objects.extend(objects);
+
+            // Generate executable, or the library
+            if archive {
+                codegen::generate_archive(&c_code, output, self.keep_temps, &compiler)
+            } else {
+                codegen::generate_executable(&c_code, output, self.keep_temps, &compiler)
+            }
+            .map_err(|e| CompilerError::CodegenError(e.message))
+        })?;
+        record_file_size(output);
     
-    Ok(())
+        Ok(())
+    }
 }
 
-use std::time::{Duration, Instant};
+#[cfg(feature = "llvm")]
+impl Compiler {
+    /// Writes the assembly or the object file LLVM generated for `program`
+    /// to `output`, or links the object file into the executable
+    fn write_machine_code(&self, code: &[u8], output: &Path, program: &ast::Program) -> Result<()> {
+        match self.emit {
+            Emit::Asm => {
+                timings::output_size(code.len() as u64);
+                return write_text(output, &String::from_utf8_lossy(code));
+            }
+            Emit::Obj => fs::write(output, code)?,
+            _ => {
+                let compiler = self.resolve_c_compiler(program)?;
+                timings::stage("link", || codegen::link_object(code, output, &compiler))
+                    .map_err(|e| CompilerError::CodegenError(e.message))?
+            }
+        }
+        record_file_size(output);
+        Ok(())
+    }
+}
 
-/// Runs a Z source file directly
-pub fn run_file(input: &Path) -> Result<()> {
-    println!("Z Compiler - The fastest programming language ever!");
-    println!("----------------------------------------------------");
-    
-    // Start timing the compilation process
-    let compilation_start = Instant::now();
+/// What `Compiler::compile` did
+#[cfg(feature = "c")]
+#[derive(Debug, Clone, Default)]
+pub struct CompileResult {
+    /// The files written: the output, unless it went to stdout, then the
+    /// header and the C when they were asked for
+    pub artifacts: Vec<PathBuf>,
+    /// The stages compiling went through, with their durations
+    pub stages: Vec<Stage>,
+    /// The warnings found in the file and the modules it imports, unless
+    /// they went to the sink of `Compiler::diagnostic_sink`
+    pub diagnostics: Vec<Diagnostic>,
+    /// The file and the modules it imports, which the spans of their syntax
+    /// trees point into
+    pub files: SourceMap,
+    /// What the optimization passes did
+    pub report: OptReport,
+}
+
+/// Compiles a Z source file to an executable, or a library for
+/// `CrateType::Lib`, with the options of `Compiler` as arguments. New code
+/// should use `Compiler`, which can set the ones added since.
+#[cfg(feature = "c")]
+#[allow(clippy::too_many_arguments)]
+pub fn compile_file(
+    input: &Path,
+    output: &Path,
+    imports: &ImportPaths,
+    passes: &PassManager,
+    strict_memory: bool,
+    emit_c: Option<&Path>,
+    keep_temps: bool,
+    emit: Emit,
+    emit_header: bool,
+    crate_type: CrateType,
+    gc: Gc,
+    release: bool,
+    compiler: &CCompiler,
+    report: &mut OptReport,
+) -> Result<()> {
+    let mut options = Compiler::new();
+    options
+        .imports(imports.clone())
+        .passes(passes.clone())
+        .strict_memory(strict_memory)
+        .keep_temps(keep_temps)
+        .emit(emit)
+        .emit_header(emit_header)
+        .crate_type(crate_type)
+        .gc(gc)
+        .release(release)
+        .c_compiler(compiler.clone());
+    if let Some(path) = emit_c {
+        options.emit_c(path);
+    }
+    options.compile_into(input, output, &mut SourceMap::new(), report)
+}
+
+/// Records the size of `path` as what the last stage generated
+#[cfg(feature = "c")]
+fn record_file_size(path: &Path) {
+    if let Ok(metadata) = fs::metadata(path) {
+        timings::output_size(metadata.len());
+    }
+}
+
+/// The name source text compiled by `compile_source` goes by in
+/// diagnostics and in the locations of panics
+const SOURCE_NAME: &str = "<source>";
+
+/// Compiles Z source text to C, as `zc compile --emit c` compiles a file, at
+/// optimization level 3 and with the checks at run time. Nothing is read or
+/// written, so the text cannot import modules. Warnings go to the
+/// `DiagnosticSink` of the thread, stderr unless one is set.
+#[cfg(feature = "codegen")]
+pub fn compile_source(source: &str) -> Result<String> {
+    let passes = PassManager::new(3);
+    let program = check_source(source, &passes, &typechecker::CheckOptions::default())?;
+    let options = codegen::CodegenOptions { gc: Gc::RefCount, checks: true };
+    generate_c(program, CrateType::Bin, "main", SOURCE_NAME, source, &options, &passes, &mut Vec::new(), |_| {})
+}
+
+/// Compiles Z source text to the executable `output` with the first C
+/// compiler installed, or the one `ZC_CC` names. Only the executable is
+/// written.
+#[cfg(feature = "c")]
+pub fn compile_source_to_path(source: &str, output: &Path) -> Result<()> {
+    let passes = PassManager::new(3);
+    let program = check_source(source, &passes, &typechecker::CheckOptions::default())?;
+    let mut compiler = CCompiler::resolve(None, None, None).map_err(|e| CompilerError::CodegenError(e.message))?;
+    compiler.link_externs(&program);
+    let options = codegen::CodegenOptions { gc: Gc::RefCount, checks: true };
+    let c_code =
+        generate_c(program, CrateType::Bin, "main", SOURCE_NAME, source, &options, &passes, &mut Vec::new(), |_| {})?;
+    codegen::generate_executable(&c_code, output, false, &compiler)
+        .map_err(|e| CompilerError::CodegenError(e.message))
+}
+
+/// Checks source text with no file, with the checks of `options`, and runs
+/// `passes` on it
+#[cfg(feature = "codegen")]
+fn check_source(source: &str, passes: &PassManager, options: &typechecker::CheckOptions) -> Result<ast::Program> {
+    let mut files = SourceMap::new();
+    let id = files.add(SOURCE_NAME, source);
+    let (checked, _) = frontend(&mut files, id, None, options, true)?;
+    Ok(timings::stage("optimize", || passes.run_program(checked.program, &mut Vec::new())))
+}
+
+/// Lexes, parses and typechecks the file `id` of `files` with `options`,
+/// after the modules it imports, which are looked for as `imports` says and
+/// added to `files`. Source text, with no `imports`, can only import the
+/// math module. Its warnings are reported if `emit_warnings` is set, and
+/// otherwise left in the checked program.
+fn frontend(
+    files: &mut SourceMap,
+    id: FileId,
+    imports: Option<(&Path, &ImportPaths)>,
+    options: &typechecker::CheckOptions,
+    emit_warnings: bool,
+) -> Result<(typechecker::CheckedProgram, Vec<modules::Module>)> {
+    let tokens = timings::stage("lex", || lexer::lex(&files.file(id).source))
+        .map_err(|e| files.errors(id, &[e]))?;
+    let mut ast = timings::stage("parse", || parser::parse_file(tokens, id))
+        .map_err(|e| files.errors(id, &[e]))?;
+    let modules = match imports {
+        Some((input, imports)) => {
+            timings::stage("imports", || modules::load_imports(&mut ast, input, imports, options, files))?
+        }
+        None if ast.statements.iter().any(|stmt| matches!(stmt, ast::Stmt::Import(name, _) if name != stdlib::MATH)) => {
+            return Err(CompilerError::TypeError(
+                "Source text cannot import modules; compile it from a file with Compiler".to_string(),
+            ));
+        }
+        None => Vec::new(),
+    };
+    let checked = timings::stage("typecheck", || typechecker::typecheck_with(ast, options))
+        .map_err(|errors| files.errors(id, &errors))?;
+    if emit_warnings {
+        let file = files.file(id);
+        diagnostic::emit_warnings(&file.name, &file.source, &checked.warnings, options.deny_warnings)?;
+    }
+    Ok((checked, modules))
+}
+
+/// Evaluates Z source text in process, with the interpreter, for programs
+/// embedding Z as a scripting or configuration language. The text is an
+/// expression, such as `{ let width = 4; width * 2 }`, whose value is
+/// returned, or a program, whose `main` is run and its result returned.
+/// What the text prints and the warnings found in it are left out;
+/// `evaluate` returns them too.
+pub fn eval(source: &str) -> Result<Value> {
+    evaluate(source).map(|evaluation| evaluation.value)
+}
+
+/// What `evaluate` found
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    /// The value of the expression, or the result of `main`
+    pub value: Value,
+    /// Everything the text printed
+    pub output: String,
+    /// The warnings found in the text, as their levels say
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Evaluates Z source text as `eval` does, returning what it printed and
+/// the warnings found in it with its value. Nothing is written to stdout or
+/// stderr.
+pub fn evaluate(source: &str) -> Result<Evaluation> {
+    let mut files = SourceMap::new();
+    let id = files.add(SOURCE_NAME, source);
+    let tokens = lexer::lex(source).map_err(|e| files.errors(id, &[e]))?;
+    let (program, expr, diagnostics) = match parser::parse_expression(tokens) {
+        Ok(expr) => {
+            let (expr, _) = typechecker::TypeChecker::new()
+                .check_expression(expr)
+                .map_err(|e| files.errors(id, &[e]))?;
+            (ast::Program::new(Vec::new()), Some(expr), Vec::new())
+        }
+        Err(_) => {
+            let options = typechecker::CheckOptions::default();
+            let (checked, _) = frontend(&mut files, id, None, &options, false)?;
+            let file = files.file(id);
+            let diagnostics =
+                diagnostic::leveled_warnings(&file.name, &file.source, &checked.warnings, options.deny_warnings)?;
+            (checked.program, None, diagnostics)
+        }
+    };
+
+    let mut interpreter = interpreter::Interpreter::new(&program);
+    let value = match &expr {
+        Some(expr) => interpreter.evaluate(expr, Default::default()),
+        None => interpreter.run(&program),
+    };
+    Ok(Evaluation {
+        value: value.map_err(|e| CompilerError::EvalError(e.to_string()))?,
+        output: interpreter.output,
+        diagnostics,
+    })
+}
+
+/// Builds the executable of the project `manifest` describes into its
+/// output directory, returning its path. `passes`, `release` and `compiler`
+/// are as for `Compiler`.
+#[cfg(feature = "c")]
+pub fn build_project(
+    manifest: &Manifest,
+    passes: &PassManager,
+    release: bool,
+    compiler: &CCompiler,
+    report: &mut OptReport,
+) -> Result<std::path::PathBuf> {
+    if !manifest.entry.is_file() {
+        return Err(CompilerError::ManifestError(format!(
+            "The entry point {} does not exist",
+            manifest.entry.display()
+        )));
+    }
+    let output = manifest.output();
+    fs::create_dir_all(&manifest.output_dir)?;
+    Compiler::new()
+        .imports(manifest.import_paths()?)
+        .passes(passes.clone())
+        .release(release)
+        .c_compiler(compiler.clone())
+        .compile_into(&manifest.entry, &output, &mut SourceMap::new(), report)?;
+    Ok(output)
+}
+
+/// C for a checked program. Executables the IR covers are generated through
+/// it, and optimized there by `passes`, which add what they did to
+/// `remarks`, then given to `on_ir`. Libraries, and executables using what
+/// the IR does not cover, are generated straight from the program; for an
+/// executable, a remark of `remarks` says what made it so.
+#[cfg(feature = "codegen")]
+#[allow(clippy::too_many_arguments)]
+fn generate_c(
+    program: ast::Program,
+    crate_type: CrateType,
+    module: &str,
+    file: &str,
+    source: &str,
+    options: &codegen::CodegenOptions,
+    passes: &PassManager,
+    remarks: &mut Vec<optimizer::Remark>,
+    on_ir: impl FnOnce(&mut ir::Module),
+) -> Result<String> {
+    let code = match crate_type {
+        CrateType::Bin => match codegen::generate_ir(&program, module, file, source, options) {
+            Ok(ir) => {
+                let mut ir = passes.run_ir(ir, remarks);
+                on_ir(&mut ir);
+                Ok(codegen::lower_ir(&ir, options))
+            }
+            Err(uncovered) => {
+                remarks.push(optimizer::Remark {
+                    pass: "ir",
+                    message: format!("generated from the checked program, since {}", uncovered),
+                    function: uncovered.function,
+                    span: uncovered.span,
+                    count: 0,
+                });
+                codegen::generate_c(program, module, file, source, options)
+            }
+        },
+        CrateType::Lib => codegen::generate_library_c(program, module, file, source, options),
+    }
+    .map_err(|e| CompilerError::at(file, source, &[e]))?;
+    optimizer::annotate(code, passes.opt_level()).map_err(|e| CompilerError::CodegenError(e.message))
+}
+
+/// Runs a Z source file directly with the command line arguments `args`,
+/// managing memory with `gc`. `release`
+/// leaves out the checks for errors at run time, and `passes` are the
+/// optimizations to run, adding what they did to `report`. The program
+/// reads and writes the terminal as it runs, unless `capture` collects its
+/// output to print once it exits. With `cache`, the executable is kept in
+/// the run cache, and a later run of the same sources with the same
+/// compiler and options runs it without generating C or compiling it again.
+/// `limits` bound how long the program runs and what it may do; a program
+/// running out of time is killed, exiting with `TIMEOUT_EXIT_CODE`.
+/// With `verbose`, a banner and the source file are noted to the sink of the
+/// thread, and the program's output is set off from the compiler's. Returns
+/// the program's exit code.
+#[cfg(feature = "c")]
+#[allow(clippy::too_many_arguments)]
+pub fn run_file(
+    input: &Path,
+    args: &[String],
+    strict_memory: bool,
+    gc: Gc,
+    release: bool,
+    passes: &PassManager,
+    compiler: &CCompiler,
+    capture: bool,
+    cache: bool,
+    limits: &RunLimits,
+    verbose: bool,
+    report: &mut OptReport,
+) -> Result<i32> {
+    // Kept off stdout, which belongs to the program
+    macro_rules! chatter {
+        ($($arg:tt)*) => {
+            if verbose {
+                diagnostic::note(&format!($($arg)*));
+            }
+        };
+    }
+
+    chatter!("Z Compiler - The fastest programming language ever!");
+    chatter!("----------------------------------------------------");
     
     // Read the source file
-    let source = fs::read_to_string(input)?;
-    println!("Source file: {}", input.display());
-    
-    // Lexical analysis
-    let lexer_start = Instant::now();
-    let tokens = lexer::lex(&source)
-        .map_err(|e| CompilerError::LexerError(e.to_string()))?;
-    let lexer_time = lexer_start.elapsed();
-    println!("Lexical analysis: {:?}", lexer_time);
-    
-    // Parsing
-    let parser_start = Instant::now();
-    let ast = parser::parse(tokens)
-        .map_err(|e| CompilerError::ParserError(e.to_string()))?;
-    let parser_time = parser_start.elapsed();
-    println!("Parsing: {:?}", parser_time);
-    
-    // Type checking
-    let typecheck_start = Instant::now();
-    let typed_ast = typechecker::typecheck(ast)
-        .map_err(|e| CompilerError::TypeError(e.to_string()))?;
-    let typecheck_time = typecheck_start.elapsed();
-    println!("Type checking: {:?}", typecheck_time);
-    
-    // Code generation
-    let codegen_start = Instant::now();
-    let c_code = codegen::generate_ir(typed_ast)
-        .map_err(|e| CompilerError::CodegenError(e.to_string()))?;
-    let codegen_time = codegen_start.elapsed();
-    println!("Code generation: {:?}", codegen_time);
-    
-    // Create a temporary output file
-    let temp_dir = std::env::temp_dir();
-    let c_file = temp_dir.join("z_temp_program.c");
-    let output = temp_dir.join("z_temp_executable");
-    
-    // Write the C code to a file
-    fs::write(&c_file, &c_code)?;
-    
-    // Compile the C code
-    let compiler = if std::process::Command::new("gcc").arg("--version").status().is_ok() {
-        "gcc"
-    } else if std::process::Command::new("clang").arg("--version").status().is_ok() {
-        "clang"
+    let source = timings::stage("read", || fs::read_to_string(input))?;
+    let file = input.display().to_string();
+    let mut files = SourceMap::new();
+    let id = files.add(&file, &source);
+    chatter!("Source file: {}", input.display());
+    
+    // Lexing, parsing and type checking, after the imported modules
+    let options = typechecker::CheckOptions { strict_memory, ..Default::default() };
+    let imports = ImportPaths::of_file(input);
+    let (checked, modules) = frontend(&mut files, id, Some((input, &imports)), &options, true)?;
+    let mut remarks = Vec::new();
+    let typed_ast = timings::stage("optimize", || passes.run_program(checked.program, &mut remarks));
+    let mut compiler = compiler.clone();
+    compiler.link_externs(&typed_ast);
+    compiler.link_gc(gc);
+
+    // Compile the optimized IR in-process and call main directly, unless the
+    // program needs features only the C backend has, has to be limited, or
+    // has its output collected
+    #[cfg(feature = "jit")]
+    if !capture && gc == Gc::RefCount && !limits.limited() {
+        // Programs the IR does not cover run through C, which says why
+        let codegen_options = codegen::CodegenOptions { gc, checks: !release };
+        if let Ok(ir) = codegen::generate_ir(&typed_ast, &module_name(input), &file, &source, &codegen_options) {
+            let mut ir_remarks = Vec::new();
+            let ir = passes.run_ir(ir, &mut ir_remarks);
+            match timings::stage("jit", || jit::compile(&ir, &file, &source)) {
+                Ok(program) => {
+                    remarks.append(&mut ir_remarks);
+                    report.add(&file, &source, remarks);
+                    chatter!("\n----------------------------------------------------");
+                    chatter!("Program output:");
+                    chatter!("----------------------------------------------------");
+
+                    let status = timings::stage("run", || program.run())?;
+                    chatter!("----------------------------------------------------");
+                    return Ok(status as i32);
+                }
+                Err(e) if e.unsupported => {}
+                Err(e) => return Err(CompilerError::CodegenError(e.message)),
+            }
+        }
+    }
+    
+    // The executable of an earlier run of the same sources, built the same way
+    let cached = if cache {
+        run_cache_dir().map(|dir| {
+            let key = run_cache_key(&source, &modules, strict_memory, gc, release, passes, &compiler);
+            dir.join(format!("{:016x}{}", key, std::env::consts::EXE_SUFFIX))
+        })
     } else {
-        return Err(CompilerError::CodegenError(
-            "Neither GCC nor Clang found. Please install a C compiler.".to_string()
-        ));
+        None
     };
+
+    // A directory of its own for a build, removed when it goes out of scope
+    let temp_dir;
+    let output = match cached {
+        Some(cached) if cached.is_file() => {
+            report.add(&file, &source, remarks);
+            chatter!("Cached executable: {}", cached.display());
+            cached
+        }
+        cached => {
+            // Code generation
+            let codegen_options = codegen::CodegenOptions { gc, checks: !release };
+            let c_code = timings::stage("codegen", || {
+                generate_c(
+                    typed_ast,
+                    CrateType::Bin,
+                    &module_name(input),
+                    &file,
+                    &source,
+                    &codegen_options,
+                    passes,
+                    &mut remarks,
+                    |_| {},
+                )
+            })?;
+            timings::output_size(c_code.len() as u64);
+            report.add(&file, &source, remarks);
     
-    // Compile the C code to an executable with maximum optimization
-    println!("Compiling with {}", compiler);
-    let native_compile_start = Instant::now();
-    let compile_status = std::process::Command::new(compiler)
-        .arg("-O3")                // Maximum optimization
-        .arg("-march=native")      // Optimize for current CPU
-        .arg("-flto")              // Link-time optimization
-        .arg("-o")
-        .arg(&output)
-        .arg(&c_file)
-        .arg("-lm")                // Link math library
-        .status()?;
-    let native_compile_time = native_compile_start.elapsed();
-    println!("Native compilation: {:?}", native_compile_time);
-    
-    if !compile_status.success() {
-        return Err(CompilerError::CodegenError(
-            format!("Compilation failed with status: {}", compile_status)
-        ));
-    }
-    
-    // Total compilation time
-    let total_compilation_time = compilation_start.elapsed();
-    println!("Total compilation time: {:?}", total_compilation_time);
-    println!("\n----------------------------------------------------");
+            temp_dir = tempfile::Builder::new().prefix("zc-").tempdir()?;
+            let output = temp_dir.path().join("z_program").with_extension(std::env::consts::EXE_EXTENSION);
     
-    // Execute the compiled program
-    println!("Program output:");
-    println!("----------------------------------------------------");
+            // Compile the C code to an executable, with the imported modules
+            chatter!("Compiling with {}", compiler.command);
+            timings::stage("cc", || {
+                let objects = modules::build_objects(
+                    modules,
+                    &modules::cache_dir(input),
+                    &codegen_options,
+                    passes,
+                    &compiler,
+                    report,
+                )?;
+                compiler.link.objects.extend(objects);
+                codegen::generate_executable(&c_code, &output, false, &compiler)
+                    .map_err(|e| CompilerError::CodegenError(e.message))
+            })?;
+            record_file_size(&output);
+
+            if let Some(cached) = cached {
+                // Not having a cache only makes the next run slower
+                if let Err(e) = store_cached(&output, &cached) {
+                    chatter!("Could not cache the executable: {}", e);
+                }
+            }
+            output
+        }
+    };
     
-    let execution_start = Instant::now();
-    let output_result = std::process::Command::new(output.clone())
-        .output()?;
-    let execution_time = execution_start.elapsed();
+    chatter!("\n----------------------------------------------------");
     
-    // Print the output
-    println!("{}", String::from_utf8_lossy(&output_result.stdout));
-    println!("----------------------------------------------------");
-    println!("Execution time: {:?}", execution_time);
+    // Execute the compiled program
+    chatter!("Program output:");
+    chatter!("----------------------------------------------------");
     
-    // Clean up the temporary files
-    let _ = fs::remove_file(c_file);
-    let _ = fs::remove_file(output);
+    let mut command = std::process::Command::new(output.clone());
+    command.args(args);
+    let status = timings::stage("run", || sandbox::run(command, capture, limits))?;
+    chatter!("----------------------------------------------------");
     
-    if !output_result.status.success() {
-        return Err(CompilerError::CodegenError(
-            format!("Program exited with status: {}", output_result.status)
-        ));
+    match status {
+        Some(status) => Ok(exit_code(status)),
+        None => {
+            let timeout = limits.timeout.unwrap_or_default();
+            diagnostic::note(&format!("The program was killed after running for {}s", timeout.as_secs_f64()));
+            Ok(TIMEOUT_EXIT_CODE)
+        }
     }
-    
+}
+
+/// The code a shell reports for a process exiting with `status`: 128 plus
+/// the signal for a process killed by one
+#[cfg(feature = "c")]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// The directory `zc run` caches executables in: `zc` in `$XDG_CACHE_HOME`,
+/// or in `~/.cache`
+#[cfg(feature = "c")]
+pub(crate) fn run_cache_dir() -> Option<std::path::PathBuf> {
+    if let Some(cache) = std::env::var_os("XDG_CACHE_HOME").filter(|cache| !cache.is_empty()) {
+        return Some(Path::new(&cache).join("zc"));
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("LOCALAPPDATA"))
+        .map(|home| Path::new(&home).join(".cache").join("zc"))
+}
+
+/// Identifies the executable of a run by everything it is built from: the
+/// sources, this build of the compiler and the options it is given
+#[cfg(feature = "c")]
+fn run_cache_key(
+    source: &str,
+    modules: &[modules::Module],
+    strict_memory: bool,
+    gc: Gc,
+    release: bool,
+    passes: &PassManager,
+    compiler: &CCompiler,
+) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    // Builds of the same version differ while it is being worked on
+    let built = std::env::current_exe().and_then(fs::metadata).and_then(|metadata| metadata.modified());
+    if let Ok(built) = built {
+        built.hash(&mut hasher);
+    }
+    source.hash(&mut hasher);
+    for module in modules {
+        module.name.hash(&mut hasher);
+        module.source.hash(&mut hasher);
+    }
+    (strict_memory, release).hash(&mut hasher);
+    format!("{:?} {:?} {:?}", gc, passes, compiler).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Copies `executable` to `cached`, through a file of its own in the cache
+/// so that runs at the same time never see it half written
+#[cfg(feature = "c")]
+fn store_cached(executable: &Path, cached: &Path) -> std::io::Result<()> {
+    let dir = cached.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let partial = tempfile::Builder::new().prefix(".partial-").tempfile_in(dir)?;
+    fs::copy(executable, partial.path())?;
+    partial.persist(cached).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Benchmarks the `@bench` functions of a Z source file whose names contain
+/// `filter`, built without checks at run time, timing `samples` samples of
+/// each. `passes` are the optimizations to run, adding what they did to
+/// `report`.
+#[cfg(feature = "c")]
+pub fn bench_file(
+    input: &Path,
+    filter: Option<&str>,
+    samples: usize,
+    passes: &PassManager,
+    compiler: &CCompiler,
+    report: &mut OptReport,
+) -> Result<BenchReport> {
+    let source = timings::stage("read", || fs::read_to_string(input))?;
+    let file = input.display().to_string();
+    let mut files = SourceMap::new();
+    let id = files.add(&file, &source);
+    let imports = ImportPaths::of_file(input);
+    let options = typechecker::CheckOptions::default();
+    let (checked, modules) = frontend(&mut files, id, Some((input, &imports)), &options, true)?;
+    let mut program = checked.program;
+    let names = bench::export_benches(&mut program, filter);
+    if names.is_empty() {
+        return Err(CompilerError::TypeError(match filter {
+            Some(filter) => format!("No @bench function has {} in its name", filter),
+            None => format!("{} has no @bench functions", input.display()),
+        }));
+    }
+
+    let mut remarks = Vec::new();
+    let program = timings::stage("optimize", || passes.run_program(program, &mut remarks));
+    let mut compiler = compiler.clone();
+    compiler.link_externs(&program);
+    let codegen_options = codegen::CodegenOptions { gc: Gc::RefCount, checks: false };
+    let c_code = timings::stage("codegen", || {
+        let module = module_name(input);
+        generate_c(program, CrateType::Bin, &module, &file, &source, &codegen_options, passes, &mut remarks, |_| {})
+    })?;
+    timings::output_size(c_code.len() as u64);
+    report.add(&file, &source, remarks);
+
+    let temp_dir = tempfile::Builder::new().prefix("zc-").tempdir()?;
+    let output = temp_dir.path().join("z_bench").with_extension(std::env::consts::EXE_EXTENSION);
+    timings::stage("cc", || {
+        let objects =
+            modules::build_objects(modules, &modules::cache_dir(input), &codegen_options, passes, &compiler, report)?;
+        compiler.link.objects.extend(objects);
+        codegen::generate_executable(&bench::harness(&c_code, &names), &output, false, &compiler)
+            .map_err(|e| CompilerError::CodegenError(e.message))
+    })?;
+    record_file_size(&output);
+
+    let results = timings::stage("bench", || {
+        let mut results = Vec::new();
+        for (index, name) in names.iter().enumerate() {
+            let run = std::process::Command::new(&output)
+                .arg(index.to_string())
+                .arg(bench::SAMPLE_TIME.to_string())
+                .arg(bench::WARMUP_SAMPLES.to_string())
+                .arg(samples.to_string())
+                .output()?;
+            let result =
+                BenchResult::parse(name, &String::from_utf8_lossy(&run.stdout)).filter(|_| run.status.success());
+            match result {
+                Some(result) => results.push(result),
+                None => {
+                    diagnostic::program_stderr(&run.stderr);
+                    return Err(CompilerError::EvalError(format!("The benchmark {} failed: {}", name, run.status)));
+                }
+            }
+        }
+        Ok(results)
+    })?;
+    Ok(BenchReport { results })
+}
+
+/// Typechecks a Z source file and reports the functions and struct fields
+/// that `main` never reaches
+pub fn dead_code_report(input: &Path) -> Result<DeadCodeReport> {
+    let source = timings::stage("read", || fs::read_to_string(input))?;
+    let file = input.display().to_string();
+    let mut files = SourceMap::new();
+    let id = files.add(&file, &source);
+    let imports = ImportPaths::of_file(input);
+    let options = typechecker::CheckOptions::default();
+    let (checked, _) = frontend(&mut files, id, Some((input, &imports)), &options, false)?;
+    Ok(checked.dead_code)
+}
+
+/// Typechecks a Z source file and returns what the rules of `config` find in
+/// it and in the modules of the project it imports, after the warnings of
+/// the type checker. Modules of dependencies are not linted.
+pub fn lint_file(input: &Path, imports: &ImportPaths, config: &LintConfig) -> Result<Vec<Diagnostic>> {
+    let source = timings::stage("read", || fs::read_to_string(input))?;
+    let file = input.display().to_string();
+    let mut files = SourceMap::new();
+    let id = files.add(&file, &source);
+    let options = typechecker::CheckOptions::default();
+    let (checked, modules) = frontend(&mut files, id, Some((input, imports)), &options, false)?;
+
+    let mut diagnostics: Vec<Diagnostic> =
+        checked.warnings.iter().filter_map(|warning| diagnostic::warning(warning, &file, &source, false)).collect();
+    timings::stage("lint", || -> Result<()> {
+        // The file and the modules of the project are kept as the type
+        // checker rewrote them, so they are parsed again
+        let project = modules.iter().filter(|module| {
+            let package = module.name.split('.').next().unwrap_or_default();
+            imports.package(package).is_none()
+        });
+        let sources = [(file.clone(), source.as_str())]
+            .into_iter()
+            .chain(project.map(|module| (module.file.display().to_string(), module.source.as_str())));
+        for (file, source) in sources {
+            let id = files.add(&file, source);
+            let tokens = lexer::lex(source).map_err(|e| files.errors(id, &[e]))?;
+            let program = parser::parse_file(tokens, id).map_err(|e| files.errors(id, &[e]))?;
+            diagnostics.extend(lint::lint(&program, &file, source, config));
+        }
+        Ok(())
+    })?;
+    Ok(diagnostics)
+}
+
+/// The tokens of a Z source file, as text or as JSON
+pub fn dump_tokens(input: &Path, json: bool) -> Result<String> {
+    let source = timings::stage("read", || fs::read_to_string(input))?;
+    let file = input.display().to_string();
+    let mut files = SourceMap::new();
+    let id = files.add(&file, &source);
+    let tokens = timings::stage("lex", || lexer::lex(&source))
+        .map_err(|e| files.errors(id, &[e]))?;
+    Ok(if json {
+        format!("{:#}\n", dump::tokens_json(&tokens, &source))
+    } else {
+        dump::tokens_text(&tokens, &source)
+    })
+}
+
+/// The syntax tree of a Z source file as parsed, as text or as JSON
+pub fn dump_ast(input: &Path, json: bool) -> Result<String> {
+    let source = timings::stage("read", || fs::read_to_string(input))?;
+    let file = input.display().to_string();
+    let mut files = SourceMap::new();
+    let id = files.add(&file, &source);
+    let tokens = timings::stage("lex", || lexer::lex(&source))
+        .map_err(|e| files.errors(id, &[e]))?;
+    let ast = timings::stage("parse", || parser::parse_file(tokens, id))
+        .map_err(|e| files.errors(id, &[e]))?;
+    Ok(if json {
+        format!("{:#}\n", dump::ast_json(&ast))
+    } else {
+        dump::ast_text(&ast)
+    })
+}
+
+/// Serves a Debug Adapter Protocol session over stdin/stdout
+#[cfg(feature = "dap")]
+pub fn run_dap_server() -> Result<()> {
+    dap::serve(std::io::stdin(), std::io::stdout())?;
     Ok(())
-}
\ No newline at end of file
+}
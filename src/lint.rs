@@ -0,0 +1,351 @@
+// `zc lint`: rules about style and likely mistakes that a program can break
+// and still compile. Each rule is set in the `[lint]` table of `z.toml`:
+//
+//     [lint]
+//     shadowing = "warn"
+//     naming = "warn"
+//     long-functions = "warn"
+//     max-function-lines = 50
+//     constant-conditions = "warn"
+//
+// "allow" turns a rule off and "deny" makes what it finds an error. Rules
+// look at the program as written, before the type checker rewrites it.
+
+use crate::ast::{Expr, Pattern, Program, Span, Stmt};
+use crate::consteval::{self, Constant};
+use crate::diagnostic::{Diagnostic, Severity};
+use std::collections::HashMap;
+
+/// What a rule does with what it finds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl std::str::FromStr for Level {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(format!("unknown lint level {}, expected allow, warn or deny", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    /// A variable named like one of an enclosing scope
+    pub shadowing: Level,
+    /// Functions and variables not in snake_case, types and variants not in
+    /// CamelCase and constants not in UPPER_SNAKE_CASE
+    pub naming: Level,
+    /// Functions spanning more than `max_function_lines` lines
+    pub long_functions: Level,
+    pub max_function_lines: usize,
+    /// `if` and `while` conditions that are always true or always false
+    pub constant_conditions: Level,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            shadowing: Level::Warn,
+            naming: Level::Warn,
+            long_functions: Level::Warn,
+            max_function_lines: 50,
+            constant_conditions: Level::Warn,
+        }
+    }
+}
+
+/// What the rules of `config` find in `program`, parsed from `source` in
+/// `file`
+pub fn lint(program: &Program, file: &str, source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        file,
+        source,
+        scopes: vec![HashMap::new()],
+        diagnostics: Vec::new(),
+    };
+    // Constants are visible throughout the file, even above their declaration
+    for stmt in &program.statements {
+        if let Stmt::Const(name, _, value, _) = stmt {
+            let value = linter.evaluate(value);
+            linter.declare(name, value);
+        }
+    }
+    for stmt in &program.statements {
+        linter.statement(stmt);
+    }
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    file: &'a str,
+    source: &'a str,
+    // The names declared in each enclosing scope, with the values of those
+    // that are constants
+    scopes: Vec<HashMap<String, Option<Constant>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, level: Level, code: &'static str, message: String, span: &Span) {
+        let severity = match level {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+        self.diagnostics
+            .push(Diagnostic::new(severity, code, message, self.file, self.source, Some(span.clone())));
+    }
+
+    fn declare(&mut self, name: &str, value: Option<Constant>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value);
+        }
+    }
+
+    /// Declares a variable, reporting it if it shadows another
+    fn declare_variable(&mut self, name: &str, span: &Span) {
+        self.check_name(name, "Variable", Case::Snake, span);
+        let enclosing = &self.scopes[..self.scopes.len() - 1];
+        if !name.starts_with('_') && enclosing.iter().any(|scope| scope.contains_key(name)) {
+            let message = format!("{} shadows a variable of an enclosing scope; give one of them another name", name);
+            self.report(self.config.shadowing, "L0001", message, span);
+        }
+        self.declare(name, None);
+    }
+
+    fn check_name(&mut self, name: &str, what: &str, case: Case, span: &Span) {
+        let expected = case.convert(name);
+        if expected != name {
+            let message = format!("{} {} should be in {}, as {}", what, name, case.name(), expected);
+            self.report(self.config.naming, "L0002", message, span);
+        }
+    }
+
+    /// The value of `expr` if it is constant, reading the constants in scope
+    fn evaluate(&self, expr: &Expr) -> Option<Constant> {
+        let mut constants = HashMap::new();
+        for scope in &self.scopes {
+            for (name, value) in scope {
+                match value {
+                    Some(value) => constants.insert(name.clone(), *value),
+                    None => constants.remove(name),
+                };
+            }
+        }
+        consteval::evaluate(expr, &constants).ok().flatten()
+    }
+
+    fn condition(&mut self, keyword: &str, condition: &Expr) {
+        self.expression(condition);
+        // `while true` is how Z writes a loop that runs until it returns
+        match self.evaluate(condition) {
+            Some(Constant::Bool(true)) if keyword == "while" => {}
+            Some(Constant::Bool(value)) => {
+                let message = format!("This {} condition is always {}", keyword, value);
+                self.report(self.config.constant_conditions, "L0004", message, condition.span());
+            }
+            _ => {}
+        }
+    }
+
+    fn in_scope(&mut self, body: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        body(self);
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => self.expression(expr),
+            Stmt::Let(name, _, init, span) => {
+                if let Some(init) = init {
+                    self.expression(init);
+                }
+                self.declare_variable(name, span);
+            }
+            Stmt::Const(name, _, value, span) => {
+                self.expression(value);
+                self.check_name(name, "Constant", Case::UpperSnake, span);
+                let value = self.evaluate(value);
+                self.declare(name, value);
+            }
+            Stmt::Assign(target, value, _) => {
+                self.expression(target);
+                self.expression(value);
+            }
+            Stmt::Return(value, _) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::While(condition, body, _) => {
+                self.condition("while", condition);
+                self.statement(body);
+            }
+            Stmt::For(name, name_span, iterable, body, _) => {
+                self.expression(iterable);
+                self.in_scope(|linter| {
+                    linter.declare_variable(name, name_span);
+                    linter.statement(body);
+                });
+            }
+            Stmt::Block(stmts, _) => self.in_scope(|linter| stmts.iter().for_each(|stmt| linter.statement(stmt))),
+            Stmt::Function(name, type_params, params, _, body, _, _, span) => {
+                self.check_name(name, "Function", Case::Snake, span);
+                for type_param in type_params {
+                    self.check_name(type_param, "Type parameter", Case::Camel, span);
+                }
+                let end = span.end.min(self.source.len());
+                let lines = self.source[span.start.min(end)..end].lines().count();
+                if lines > self.config.max_function_lines {
+                    let message = format!(
+                        "Function {} is {} lines long, more than the {} allowed; split it into smaller functions",
+                        name, lines, self.config.max_function_lines
+                    );
+                    self.report(self.config.long_functions, "L0003", message, span);
+                }
+                self.in_scope(|linter| {
+                    for (param, _) in params {
+                        linter.check_name(param, "Parameter", Case::Snake, span);
+                        linter.declare(param, None);
+                    }
+                    linter.statement(body);
+                });
+            }
+            Stmt::Struct(name, type_params, fields, span) => {
+                self.check_name(name, "Struct", Case::Camel, span);
+                for type_param in type_params {
+                    self.check_name(type_param, "Type parameter", Case::Camel, span);
+                }
+                for (field, _) in fields {
+                    self.check_name(field, "Field", Case::Snake, span);
+                }
+            }
+            Stmt::Enum(name, variants, span) => {
+                self.check_name(name, "Enum", Case::Camel, span);
+                for (variant, _) in variants {
+                    self.check_name(variant, "Variant", Case::Camel, span);
+                }
+            }
+            // Extern functions are named as in C
+            Stmt::Import(..) | Stmt::Extern(..) => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(..) | Expr::Identifier(..) => {}
+            Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) | Expr::Range(left, right, _) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Unary(_, operand, _)
+            | Expr::Field(operand, _, _)
+            | Expr::Cast(operand, _, _)
+            | Expr::Try(operand, _, _)
+            | Expr::Inlined(_, operand, _) => self.expression(operand),
+            Expr::Call(callee, args, _) => {
+                self.expression(callee);
+                args.iter().for_each(|arg| self.expression(arg));
+            }
+            Expr::Array(elements, _) | Expr::Variant(_, _, elements, _) => {
+                elements.iter().for_each(|element| self.expression(element))
+            }
+            Expr::If(condition, then_branch, else_branch, _) => {
+                self.condition("if", condition);
+                self.expression(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expression(else_branch);
+                }
+            }
+            Expr::Block(stmts, _) => self.in_scope(|linter| stmts.iter().for_each(|stmt| linter.statement(stmt))),
+            Expr::Lambda(params, _, body, span) => self.in_scope(|linter| {
+                for (param, _) in params {
+                    linter.check_name(param, "Parameter", Case::Snake, span);
+                    linter.declare(param, None);
+                }
+                linter.expression(body);
+            }),
+            Expr::Match(scrutinee, arms, _) => {
+                self.expression(scrutinee);
+                for arm in arms {
+                    self.in_scope(|linter| {
+                        if let Pattern::Variant(_, _, bindings, span) = &arm.pattern {
+                            for binding in bindings.iter().filter(|binding| *binding != "_") {
+                                linter.declare_variable(binding, span);
+                            }
+                        }
+                        linter.expression(&arm.body);
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// How a kind of name is written
+#[derive(Debug, Clone, Copy)]
+enum Case {
+    Snake,
+    Camel,
+    UpperSnake,
+}
+
+impl Case {
+    fn name(self) -> &'static str {
+        match self {
+            Case::Snake => "snake_case",
+            Case::Camel => "CamelCase",
+            Case::UpperSnake => "UPPER_SNAKE_CASE",
+        }
+    }
+
+    /// `name` written in this case, keeping leading underscores
+    fn convert(self, name: &str) -> String {
+        let trimmed = name.trim_start_matches('_');
+        let prefix = &name[..name.len() - trimmed.len()];
+        // The words of the name, split at underscores and before capitals
+        // that follow a lowercase letter or a digit
+        let mut words: Vec<String> = Vec::new();
+        let mut previous = '_';
+        for c in trimmed.chars() {
+            if c == '_' {
+                words.push(String::new());
+            } else {
+                let starts_word = c.is_uppercase() && (previous.is_lowercase() || previous.is_ascii_digit());
+                match words.last_mut() {
+                    Some(word) if !starts_word => word.push(c),
+                    _ => words.push(c.to_string()),
+                }
+            }
+            previous = c;
+        }
+        words.retain(|word| !word.is_empty());
+
+        let converted = match self {
+            Case::Snake => words.iter().map(|word| word.to_lowercase()).collect::<Vec<_>>().join("_"),
+            Case::UpperSnake => words.iter().map(|word| word.to_uppercase()).collect::<Vec<_>>().join("_"),
+            Case::Camel => words
+                .iter()
+                .map(|word| {
+                    // The rest is kept as it is, as in acronyms such as IO
+                    let mut chars = word.chars();
+                    let first = chars.next().map(|c| c.to_uppercase().to_string()).unwrap_or_default();
+                    first + chars.as_str()
+                })
+                .collect(),
+        };
+        format!("{}{}", prefix, converted)
+    }
+}
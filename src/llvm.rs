@@ -0,0 +1,873 @@
+// Object code without a C compiler, enabled by the `llvm` feature. The
+// optimized IR of the program is translated with inkwell into an LLVM
+// module, optimized by LLVM's own pipeline and written as assembly or an
+// object file; only linking an executable runs the C compiler, as a linker
+// driver. It covers what the JIT does: the IR but for calls of C functions
+// and imported modules, with the checks and stack traces of debug builds.
+// Other programs report themselves unsupported and are compiled through C
+// instead.
+
+use crate::ast::{BinaryOp, Type, UnaryOp};
+use crate::ir;
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::{Builder, BuilderError};
+use inkwell::context::Context;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::{Linkage, Module};
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum};
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue};
+use inkwell::{FloatPredicate, IntPredicate, OptimizationLevel};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct LlvmError {
+    pub message: String,
+    /// Whether the program uses a feature the LLVM backend does not cover,
+    /// so it should be compiled through C instead
+    pub unsupported: bool,
+}
+
+impl std::fmt::Display for LlvmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LLVM error: {}", self.message)
+    }
+}
+
+impl From<BuilderError> for LlvmError {
+    fn from(error: BuilderError) -> Self {
+        failed(error.to_string())
+    }
+}
+
+type Result<T> = std::result::Result<T, LlvmError>;
+
+fn failed(message: String) -> LlvmError {
+    LlvmError {
+        message,
+        unsupported: false,
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T> {
+    Err(LlvmError {
+        message: format!("{} is not supported by the LLVM backend", what),
+        unsupported: true,
+    })
+}
+
+/// What `compile` generates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Assembly,
+    Object,
+}
+
+/// How `compile` generates code
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The level of LLVM's optimization pipeline, from 0 to 3
+    pub opt_level: u8,
+    /// The target triple to build for, instead of the machine building
+    pub target: Option<String>,
+    /// Tune the code for the CPU building it
+    pub native_cpu: bool,
+}
+
+/// A C string constant of the runtime, null-terminated
+fn runtime_string(name: &str, text: &str) -> String {
+    let mut escaped = String::new();
+    for byte in text.bytes().chain([0]) {
+        if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("\\{:02X}", byte));
+        }
+    }
+    format!("@{} = private unnamed_addr constant [{} x i8] c\"{}\"\n", name, text.len() + 1, escaped)
+}
+
+/// The first character of the runtime string constant `name`
+fn runtime_pointer(name: &str, text: &str) -> String {
+    let ty = format!("[{} x i8]", text.len() + 1);
+    format!("i8* getelementptr inbounds ({ty}, {ty}* @{name}, i64 0, i64 0)", ty = ty, name = name)
+}
+
+// Output, panics and stack traces, as the runtime of the C backend has them.
+// They are written in LLVM's own text, calling only the C library, since no
+// C compiler builds them. Panics write to file descriptor 2, which needs no
+// `stderr` symbol, whose name differs between C libraries.
+const STRINGS: [(&str, &str); 11] = [
+    ("z_str_format", "%s"),
+    ("z_int_format", "%lld"),
+    ("z_uint_format", "%llu"),
+    ("z_float_format", "%.*g"),
+    ("z_true", "true"),
+    ("z_false", "false"),
+    ("z_unknown", "?"),
+    ("z_panic_format", "panic: %s at %s\n"),
+    ("z_backtrace", "stack backtrace:\n"),
+    ("z_unrecorded_format", "    (%lld innermost calls not recorded)\n"),
+    ("z_frame_format", "    %s at %s\n"),
+];
+
+fn runtime() -> String {
+    let string = |name: &str| {
+        let (name, text) = STRINGS.iter().find(|(string, _)| *string == name).unwrap();
+        runtime_pointer(name, text)
+    };
+    let mut ir = String::new();
+    for (name, text) in STRINGS {
+        ir.push_str(&runtime_string(name, text));
+    }
+    ir.push_str(
+        "
+declare i32 @printf(i8*, ...)
+declare i32 @dprintf(i32, i8*, ...)
+declare i32 @snprintf(i8*, i64, i8*, ...)
+declare double @strtod(i8*, i8**)
+declare i32 @fflush(i8*)
+declare void @exit(i32) noreturn
+
+; The calls in progress, which debug builds record for the stack traces of
+; panics. Each frame holds the location of the call it is making. Calls
+; nested deeper than 256 are counted only.
+%z_frame = type { i8*, i8* }
+@z_frames = internal global [256 x %z_frame] zeroinitializer
+@z_depth = internal global i64 0
+",
+    );
+    ir.push_str(&format!(
+        "
+define internal void @z_write_str(i8* %text) {{
+  call i32 (i8*, ...) @printf({str}, i8* %text)
+  ret void
+}}
+
+define internal void @z_write_int(i64 %value) {{
+  call i32 (i8*, ...) @printf({int}, i64 %value)
+  ret void
+}}
+
+define internal void @z_write_uint(i64 %value) {{
+  call i32 (i8*, ...) @printf({uint}, i64 %value)
+  ret void
+}}
+
+; Floats are written with the fewest digits that read back as the same value
+define internal void @z_write_float(double %value) {{
+entry:
+  %buffer = alloca [32 x i8]
+  %text = getelementptr inbounds [32 x i8], [32 x i8]* %buffer, i64 0, i64 0
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (i8*, i64, i8*, ...) @snprintf(i8* %text, i64 32, {float}, i32 %precision, double %value)
+  %back = call double @strtod(i8* %text, i8** null)
+  %same = fcmp oeq double %back, %value
+  br i1 %same, label %write, label %retry
+retry:
+  %next = add i32 %precision, 1
+  %more = icmp sle i32 %next, 17
+  br i1 %more, label %format, label %write
+write:
+  call i32 (i8*, ...) @printf({str}, i8* %text)
+  ret void
+}}
+
+define internal void @z_write_bool(i1 %value) {{
+  %text = select i1 %value, {yes}, {no}
+  call i32 (i8*, ...) @printf({str}, i8* %text)
+  ret void
+}}
+
+define internal void @z_enter(i8* %function) {{
+entry:
+  %depth = load i64, i64* @z_depth
+  %room = icmp slt i64 %depth, 256
+  br i1 %room, label %record, label %count
+record:
+  %function_slot = getelementptr inbounds [256 x %z_frame], [256 x %z_frame]* @z_frames, i64 0, i64 %depth, i32 0
+  store i8* %function, i8** %function_slot
+  %location_slot = getelementptr inbounds [256 x %z_frame], [256 x %z_frame]* @z_frames, i64 0, i64 %depth, i32 1
+  store {unknown}, i8** %location_slot
+  br label %count
+count:
+  %deeper = add i64 %depth, 1
+  store i64 %deeper, i64* @z_depth
+  ret void
+}}
+
+define internal void @z_leave() {{
+  %depth = load i64, i64* @z_depth
+  %shallower = sub i64 %depth, 1
+  store i64 %shallower, i64* @z_depth
+  ret void
+}}
+
+define internal void @z_at(i8* %location) {{
+entry:
+  %depth = load i64, i64* @z_depth
+  %inside = icmp sgt i64 %depth, 0
+  %recorded = icmp sle i64 %depth, 256
+  %both = and i1 %inside, %recorded
+  br i1 %both, label %record, label %done
+record:
+  %index = sub i64 %depth, 1
+  %location_slot = getelementptr inbounds [256 x %z_frame], [256 x %z_frame]* @z_frames, i64 0, i64 %index, i32 1
+  store i8* %location, i8** %location_slot
+  br label %done
+done:
+  ret void
+}}
+
+; Runtime errors print their message and the calls that led to it,
+; innermost first, and end the program
+define internal void @z_panic(i8* %message, i8* %location) noreturn {{
+entry:
+  call i32 @fflush(i8* null)
+  call i32 (i32, i8*, ...) @dprintf(i32 2, {panic}, i8* %message, i8* %location)
+  %depth = load i64, i64* @z_depth
+  %any = icmp sgt i64 %depth, 0
+  br i1 %any, label %header, label %unrecorded
+header:
+  call i32 (i32, i8*, ...) @dprintf(i32 2, {backtrace})
+  br label %unrecorded
+unrecorded:
+  %over = icmp sgt i64 %depth, 256
+  br i1 %over, label %count, label %frames
+count:
+  %missing = sub i64 %depth, 256
+  call i32 (i32, i8*, ...) @dprintf(i32 2, {unrecorded}, i64 %missing)
+  br label %frames
+frames:
+  call void @z_at(i8* %location)
+  %recorded = select i1 %over, i64 256, i64 %depth
+  br label %loop
+loop:
+  %i = phi i64 [ %recorded, %frames ], [ %previous, %frame ]
+  %more = icmp sgt i64 %i, 0
+  br i1 %more, label %frame, label %end
+frame:
+  %previous = sub i64 %i, 1
+  %function_slot = getelementptr inbounds [256 x %z_frame], [256 x %z_frame]* @z_frames, i64 0, i64 %previous, i32 0
+  %function = load i8*, i8** %function_slot
+  %location_slot = getelementptr inbounds [256 x %z_frame], [256 x %z_frame]* @z_frames, i64 0, i64 %previous, i32 1
+  %frame_location = load i8*, i8** %location_slot
+  call i32 (i32, i8*, ...) @dprintf(i32 2, {frame}, i8* %function, i8* %frame_location)
+  br label %loop
+end:
+  call void @exit(i32 1)
+  unreachable
+}}
+",
+        str = string("z_str_format"),
+        int = string("z_int_format"),
+        uint = string("z_uint_format"),
+        float = string("z_float_format"),
+        yes = string("z_true"),
+        no = string("z_false"),
+        unknown = string("z_unknown"),
+        panic = string("z_panic_format"),
+        backtrace = string("z_backtrace"),
+        unrecorded = string("z_unrecorded_format"),
+        frame = string("z_frame_format"),
+    ));
+    ir
+}
+
+/// The machine code is generated for: the target, or the machine building
+fn target_machine(options: &Options) -> Result<TargetMachine> {
+    let (triple, cpu, features) = match &options.target {
+        Some(target) => {
+            Target::initialize_all(&InitializationConfig::default());
+            (TargetTriple::create(target), "generic".to_string(), String::new())
+        }
+        None => {
+            Target::initialize_native(&InitializationConfig::default()).map_err(failed)?;
+            let triple = TargetMachine::get_default_triple();
+            if options.native_cpu {
+                let cpu = TargetMachine::get_host_cpu_name().to_string();
+                (triple, cpu, TargetMachine::get_host_cpu_features().to_string())
+            } else {
+                (triple, "generic".to_string(), String::new())
+            }
+        }
+    };
+    let level = match options.opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    };
+    let target = Target::from_triple(&triple).map_err(|e| failed(e.to_string()))?;
+    // Position-independent, as the executables C compilers link by default
+    target
+        .create_target_machine(&triple, &cpu, &features, level, RelocMode::PIC, CodeModel::Default)
+        .ok_or_else(|| failed(format!("LLVM cannot generate code for {}", triple)))
+}
+
+/// Compiles the optimized IR of a program, from `file`, to assembly or an
+/// object file defining `main`, optimized by LLVM at the build's level. Its
+/// checks, and the frames its panics print, are those of the build the IR
+/// was made for.
+pub fn compile(program: &ir::Module, file: &str, options: &Options, output: Output) -> Result<Vec<u8>> {
+    if !program.externs.is_empty() {
+        return unsupported("Calling C functions");
+    }
+    if !program.imports.is_empty() {
+        return unsupported("Calling imported modules");
+    }
+    let context = Context::create();
+    let runtime = MemoryBuffer::create_from_memory_range_copy(runtime().as_bytes(), "z_runtime");
+    let module = context
+        .create_module_from_ir(runtime)
+        .map_err(|e| failed(e.to_string()))?;
+    module.set_source_file_name(file);
+
+    // Declare every function first, so calls may come before definitions
+    let mut functions = HashMap::new();
+    for function in &program.functions {
+        let params: Vec<Type> = function.locals[..function.params].iter().map(|(_, ty)| ty.clone()).collect();
+        let mut llvm_params: Vec<BasicMetadataTypeEnum> = Vec::new();
+        for ty in &params {
+            llvm_params.push(value_type(&context, ty)?.into());
+        }
+        let function_type = match &function.return_type {
+            Type::Void => context.void_type().fn_type(&llvm_params, false),
+            ty => value_type(&context, ty)?.fn_type(&llvm_params, false),
+        };
+        // The program's `main` is called by the C entry point below
+        let name = if function.symbol == "main" { "z_main" } else { &function.symbol };
+        let value = module.add_function(name, function_type, Some(Linkage::Internal));
+        functions.insert(function.symbol.clone(), (value, params));
+    }
+    let main = match functions.get("main") {
+        Some((main, _)) => *main,
+        None => return Err(failed("The program has no main function".to_string())),
+    };
+
+    for function in &program.functions {
+        let translator = Translator {
+            context: &context,
+            builder: context.create_builder(),
+            module: &module,
+            value: functions[&function.symbol].0,
+            functions: &functions,
+            function,
+            locals: Vec::new(),
+            blocks: Vec::new(),
+            temps: HashMap::new(),
+        };
+        translator.function()?;
+    }
+
+    // The C entry point, whose status is that of the program's main
+    let entry = module.add_function("main", context.i32_type().fn_type(&[], false), None);
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(entry, "entry"));
+    let status = builder
+        .build_call(main, &[], "status")?
+        .try_as_basic_value()
+        .left()
+        .ok_or_else(|| failed("The main function returns nothing".to_string()))?;
+    let status = builder.build_int_truncate(status.into_int_value(), context.i32_type(), "")?;
+    builder.build_return(Some(&status))?;
+    module.verify().map_err(|e| failed(e.to_string()))?;
+
+    let machine = target_machine(options)?;
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    module
+        .run_passes(&format!("default<O{}>", options.opt_level.min(3)), &machine, PassBuilderOptions::create())
+        .map_err(|e| failed(e.to_string()))?;
+    let file_type = match output {
+        Output::Assembly => FileType::Assembly,
+        Output::Object => FileType::Object,
+    };
+    let code = machine
+        .write_to_memory_buffer(&module, file_type)
+        .map_err(|e| failed(e.to_string()))?;
+    Ok(code.as_slice().to_vec())
+}
+
+fn value_type<'ctx>(context: &'ctx Context, ty: &Type) -> Result<BasicTypeEnum<'ctx>> {
+    Ok(match ty {
+        Type::Int | Type::I64 | Type::U64 => context.i64_type().into(),
+        Type::I32 | Type::U32 => context.i32_type().into(),
+        Type::I16 | Type::U16 => context.i16_type().into(),
+        Type::I8 | Type::U8 => context.i8_type().into(),
+        Type::Bool => context.bool_type().into(),
+        Type::Float => context.f64_type().into(),
+        other => return unsupported(&format!("Type {:?}", other)),
+    })
+}
+
+struct Translator<'a, 'ctx> {
+    context: &'ctx Context,
+    builder: Builder<'ctx>,
+    module: &'a Module<'ctx>,
+    // The function being defined
+    value: FunctionValue<'ctx>,
+    functions: &'a HashMap<String, (FunctionValue<'ctx>, Vec<Type>)>,
+    function: &'a ir::Function,
+    // Each variable lives in a stack slot, which LLVM promotes to registers
+    locals: Vec<PointerValue<'ctx>>,
+    // The basic block of each block of the function
+    blocks: Vec<BasicBlock<'ctx>>,
+    temps: HashMap<ir::Temp, BasicValueEnum<'ctx>>,
+}
+
+impl<'ctx> Translator<'_, 'ctx> {
+    /// The function, whose variables start at zero but for the parameters.
+    /// Debug builds run it in a frame of its own.
+    fn function(mut self) -> Result<()> {
+        let function = self.function;
+        let entry = self.context.append_basic_block(self.value, "entry");
+        self.builder.position_at_end(entry);
+        for (local, (name, ty)) in function.locals.iter().enumerate() {
+            let slot = self.builder.build_alloca(value_type(self.context, ty)?, name)?;
+            let value = match self.value.get_nth_param(local as u32) {
+                Some(param) if local < function.params => param,
+                _ => value_type(self.context, ty)?.const_zero(),
+            };
+            self.builder.build_store(slot, value)?;
+            self.locals.push(slot);
+        }
+        if let Some(frame) = &function.frame {
+            let frame = self.string_literal(frame)?;
+            self.runtime("z_enter", &[frame])?;
+        }
+
+        // The entry of the IR may be jumped to, which the entry here may not
+        for id in 0..function.blocks.len() {
+            let block = self.context.append_basic_block(self.value, &format!("b{}", id));
+            self.blocks.push(block);
+        }
+        self.builder.build_unconditional_branch(self.blocks[0])?;
+        for (id, block) in function.blocks.iter().enumerate() {
+            self.builder.position_at_end(self.blocks[id]);
+            self.block(block)?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &ir::Block) -> Result<()> {
+        for instruction in &block.instructions {
+            self.instruction(instruction)?;
+            // Nothing after a panic runs
+            if let ir::Instruction::Panic { .. } = instruction {
+                return Ok(());
+            }
+        }
+        self.terminator(&block.terminator)
+    }
+
+    /// Calls the runtime function `name`
+    fn runtime(&mut self, name: &str, args: &[BasicValueEnum<'ctx>]) -> Result<()> {
+        let function = self
+            .module
+            .get_function(name)
+            .ok_or_else(|| failed(format!("The runtime has no {}", name)))?;
+        let args: Vec<BasicMetadataValueEnum> = args.iter().map(|arg| (*arg).into()).collect();
+        self.builder.build_call(function, &args, "")?;
+        Ok(())
+    }
+
+    /// Panics with `message` at `location`, ending the current block
+    fn panic(&mut self, message: &str, location: &str) -> Result<()> {
+        let message = self.string_literal(message)?;
+        let location = self.string_literal(location)?;
+        self.runtime("z_panic", &[message, location])?;
+        self.builder.build_unreachable()?;
+        Ok(())
+    }
+
+    /// Panics with `message` at `location` where `failed` holds, and carries
+    /// on in a new block otherwise
+    fn check(&mut self, failed: IntValue<'ctx>, message: &str, location: &str) -> Result<()> {
+        let panic_block = self.context.append_basic_block(self.value, "panic");
+        let ok_block = self.context.append_basic_block(self.value, "ok");
+        self.builder.build_conditional_branch(failed, panic_block, ok_block)?;
+        self.builder.position_at_end(panic_block);
+        self.panic(message, location)?;
+        self.builder.position_at_end(ok_block);
+        Ok(())
+    }
+
+    /// The value of `operand` as a value of `ty`. Constants, which are
+    /// `int`s, take on the type they are used as, as C converts them.
+    fn operand(&mut self, operand: &ir::Operand, ty: &Type) -> Result<BasicValueEnum<'ctx>> {
+        let value: BasicValueEnum = match operand {
+            ir::Operand::Const(ir::Const::Int(value)) if *ty == Type::Float => {
+                self.context.f64_type().const_float(*value as f64).into()
+            }
+            ir::Operand::Const(ir::Const::Int(value)) => {
+                let int_type = value_type(self.context, ty)?.into_int_type();
+                return Ok(int_type.const_int(*value as u64, true).into());
+            }
+            ir::Operand::Const(ir::Const::Float(value)) => self.context.f64_type().const_float(*value).into(),
+            ir::Operand::Const(ir::Const::Bool(value)) => self.context.bool_type().const_int(*value as u64, false).into(),
+            ir::Operand::Local(local) => {
+                let name = &self.function.locals[*local].0;
+                self.builder.build_load(self.locals[*local], name)?
+            }
+            ir::Operand::Temp(temp) => self.temps[temp],
+        };
+        self.convert(value, &self.function.operand_type(operand), ty)
+    }
+
+    /// Changes the width of an integer, keeping its value where it fits
+    fn convert(&mut self, value: BasicValueEnum<'ctx>, from: &Type, to: &Type) -> Result<BasicValueEnum<'ctx>> {
+        if !from.is_integer() || !to.is_integer() {
+            return Ok(value);
+        }
+        let value = value.into_int_value();
+        let to_type = value_type(self.context, to)?.into_int_type();
+        let (from_bits, to_bits) = (value.get_type().get_bit_width(), to_type.get_bit_width());
+        Ok(if to_bits > from_bits {
+            if from.is_unsigned() {
+                self.builder.build_int_z_extend(value, to_type, "")?
+            } else {
+                self.builder.build_int_s_extend(value, to_type, "")?
+            }
+        } else if to_bits < from_bits {
+            self.builder.build_int_truncate(value, to_type, "")?
+        } else {
+            value
+        }
+        .into())
+    }
+
+    fn string_literal(&mut self, text: &str) -> Result<BasicValueEnum<'ctx>> {
+        let global = self.builder.build_global_string_ptr(text, "z_literal")?;
+        Ok(global.as_pointer_value().into())
+    }
+
+    fn instruction(&mut self, instruction: &ir::Instruction) -> Result<()> {
+        let function = self.function;
+        match instruction {
+            ir::Instruction::Binary {
+                dest,
+                op,
+                left,
+                right,
+                overflow,
+            } => {
+                let value = self.binary(&function.temps[*dest], op, left, right, overflow.as_deref())?;
+                self.temps.insert(*dest, value);
+            }
+            ir::Instruction::Unary { dest, op, operand } => {
+                let ty = &function.temps[*dest];
+                let value = self.operand(operand, ty)?;
+                let value: BasicValueEnum = match op {
+                    UnaryOp::Neg if *ty == Type::Float => {
+                        self.builder.build_float_neg(value.into_float_value(), "")?.into()
+                    }
+                    UnaryOp::Neg => self.builder.build_int_neg(value.into_int_value(), "")?.into(),
+                    UnaryOp::Not => self.builder.build_not(value.into_int_value(), "")?.into(),
+                };
+                self.temps.insert(*dest, value);
+            }
+            ir::Instruction::Bitwise {
+                dest,
+                op,
+                left,
+                right,
+                overflow,
+            } => {
+                let ty = &function.temps[*dest];
+                let left = self.operand(left, ty)?.into_int_value();
+                let right = self.operand(right, ty)?.into_int_value();
+                let value = match op {
+                    ir::BitOp::Shl => self.builder.build_left_shift(left, right, "")?,
+                    ir::BitOp::Shr => self.builder.build_right_shift(left, right, !ty.is_unsigned(), "")?,
+                    ir::BitOp::And => self.builder.build_and(left, right, "")?,
+                };
+                // The value shifted back differs where bits were lost
+                if let (ir::BitOp::Shl, Some(location)) = (op, overflow) {
+                    let back = self.builder.build_right_shift(value, right, !ty.is_unsigned(), "")?;
+                    let lost = self.builder.build_int_compare(IntPredicate::NE, back, left, "")?;
+                    self.check(lost, "integer overflow", location)?;
+                }
+                self.temps.insert(*dest, value.into());
+            }
+            ir::Instruction::Cast { dest, operand } => {
+                let from = function.operand_type(operand);
+                let value = self.operand(operand, &from)?;
+                let value = self.cast(value, &from, &function.temps[*dest])?;
+                self.temps.insert(*dest, value);
+            }
+            ir::Instruction::Store { local, value } => {
+                let value = self.operand(value, &function.locals[*local].1)?;
+                self.builder.build_store(self.locals[*local], value)?;
+            }
+            ir::Instruction::Call {
+                dest,
+                symbol,
+                args,
+                location,
+            } => {
+                let (callee, params) = match self.functions.get(symbol) {
+                    Some(callee) => callee.clone(),
+                    None => return Err(failed(format!("Call of the undeclared function {}", symbol))),
+                };
+                let mut values: Vec<BasicMetadataValueEnum> = Vec::new();
+                for (arg, ty) in args.iter().zip(&params) {
+                    values.push(self.operand(arg, ty)?.into());
+                }
+                if let Some(location) = location {
+                    let location = self.string_literal(location)?;
+                    self.runtime("z_at", &[location])?;
+                }
+                let call = self.builder.build_call(callee, &values, "")?;
+                if let (Some(dest), Some(value)) = (dest, call.try_as_basic_value().left()) {
+                    self.temps.insert(*dest, value);
+                }
+            }
+            ir::Instruction::Print(pieces) => {
+                for piece in pieces {
+                    let (writer, value) = match piece {
+                        ir::Piece::Text(text) => ("z_write_str", self.string_literal(text)?),
+                        ir::Piece::Value(value) => match function.operand_type(value) {
+                            Type::Float => ("z_write_float", self.operand(value, &Type::Float)?),
+                            Type::Bool => ("z_write_bool", self.operand(value, &Type::Bool)?),
+                            ty if ty.is_unsigned() => ("z_write_uint", self.operand(value, &Type::U64)?),
+                            _ => ("z_write_int", self.operand(value, &Type::Int)?),
+                        },
+                    };
+                    self.runtime(writer, &[value])?;
+                }
+            }
+            ir::Instruction::Panic { message, location } => self.panic(message, location)?,
+            ir::Instruction::Enter { frame, location } => {
+                let location = self.string_literal(location)?;
+                self.runtime("z_at", &[location])?;
+                let frame = self.string_literal(frame)?;
+                self.runtime("z_enter", &[frame])?;
+            }
+            ir::Instruction::Leave => self.runtime("z_leave", &[])?,
+        }
+        Ok(())
+    }
+
+    /// `left op right` for a result of `ty`. Checked integer arithmetic
+    /// panics at `overflow` where C's checks would.
+    fn binary(
+        &mut self,
+        ty: &Type,
+        op: &BinaryOp,
+        left: &ir::Operand,
+        right: &ir::Operand,
+        overflow: Option<&str>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        // Comparisons are of operands of the type of the one not constant
+        let ty = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => ty.clone(),
+            _ => match left {
+                ir::Operand::Const(_) => self.function.operand_type(right),
+                _ => self.function.operand_type(left),
+            },
+        };
+        let left_value = self.operand(left, &ty)?;
+        let right_value = self.operand(right, &ty)?;
+
+        if let Some(location) = overflow.filter(|_| ty.is_integer()) {
+            let (left, right) = (left_value.into_int_value(), right_value.into_int_value());
+            if let Some(result) = self.checked(left, op, right, &ty, location)? {
+                return Ok(result);
+            }
+        }
+
+        let builder = &self.builder;
+        if ty == Type::Float {
+            let (left, right) = (left_value.into_float_value(), right_value.into_float_value());
+            let predicate = match op {
+                BinaryOp::Add => return Ok(builder.build_float_add(left, right, "")?.into()),
+                BinaryOp::Sub => return Ok(builder.build_float_sub(left, right, "")?.into()),
+                BinaryOp::Mul => return Ok(builder.build_float_mul(left, right, "")?.into()),
+                BinaryOp::Div => return Ok(builder.build_float_div(left, right, "")?.into()),
+                BinaryOp::Mod => return Ok(builder.build_float_rem(left, right, "")?.into()),
+                BinaryOp::Eq => FloatPredicate::OEQ,
+                BinaryOp::Neq => FloatPredicate::UNE,
+                BinaryOp::Lt => FloatPredicate::OLT,
+                BinaryOp::Lte => FloatPredicate::OLE,
+                BinaryOp::Gt => FloatPredicate::OGT,
+                BinaryOp::Gte => FloatPredicate::OGE,
+                BinaryOp::And | BinaryOp::Or => unreachable!("logical operators are of bools"),
+            };
+            return Ok(builder.build_float_compare(predicate, left, right, "")?.into());
+        }
+
+        let (left, right) = (left_value.into_int_value(), right_value.into_int_value());
+        let unsigned = ty.is_unsigned() || ty == Type::Bool;
+        let predicate = match op {
+            BinaryOp::Add => return Ok(builder.build_int_add(left, right, "")?.into()),
+            BinaryOp::Sub => return Ok(builder.build_int_sub(left, right, "")?.into()),
+            BinaryOp::Mul => return Ok(builder.build_int_mul(left, right, "")?.into()),
+            BinaryOp::Div if unsigned => return Ok(builder.build_int_unsigned_div(left, right, "")?.into()),
+            BinaryOp::Div => return Ok(builder.build_int_signed_div(left, right, "")?.into()),
+            BinaryOp::Mod if unsigned => return Ok(builder.build_int_unsigned_rem(left, right, "")?.into()),
+            BinaryOp::Mod => return Ok(builder.build_int_signed_rem(left, right, "")?.into()),
+            // Both operands are already evaluated
+            BinaryOp::And => return Ok(builder.build_and(left, right, "")?.into()),
+            BinaryOp::Or => return Ok(builder.build_or(left, right, "")?.into()),
+            BinaryOp::Eq => IntPredicate::EQ,
+            BinaryOp::Neq => IntPredicate::NE,
+            BinaryOp::Lt if unsigned => IntPredicate::ULT,
+            BinaryOp::Lt => IntPredicate::SLT,
+            BinaryOp::Lte if unsigned => IntPredicate::ULE,
+            BinaryOp::Lte => IntPredicate::SLE,
+            BinaryOp::Gt if unsigned => IntPredicate::UGT,
+            BinaryOp::Gt => IntPredicate::SGT,
+            BinaryOp::Gte if unsigned => IntPredicate::UGE,
+            BinaryOp::Gte => IntPredicate::SGE,
+        };
+        Ok(builder.build_int_compare(predicate, left, right, "")?.into())
+    }
+
+    /// Calls the overflow intrinsic `name` on `left` and `right`, returning
+    /// the result and whether it overflowed
+    fn overflowing(&mut self, name: &str, left: IntValue<'ctx>, right: IntValue<'ctx>) -> Result<BasicValueEnum<'ctx>> {
+        let intrinsic = Intrinsic::find(name).ok_or_else(|| failed(format!("LLVM has no {}", name)))?;
+        let function = intrinsic
+            .get_declaration(self.module, &[left.get_type().into()])
+            .ok_or_else(|| failed(format!("LLVM has no {} for {}", name, left.get_type())))?;
+        let call = self.builder.build_call(function, &[left.into(), right.into()], "")?;
+        call.try_as_basic_value()
+            .left()
+            .ok_or_else(|| failed(format!("{} returns nothing", name)))
+    }
+
+    /// Checked integer arithmetic, which panics at `location` on overflow,
+    /// and on division by zero. Division returns no value once checked, to
+    /// be done as usual.
+    fn checked(
+        &mut self,
+        left: IntValue<'ctx>,
+        op: &BinaryOp,
+        right: IntValue<'ctx>,
+        ty: &Type,
+        location: &str,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let unsigned = ty.is_unsigned();
+        let name = match op {
+            BinaryOp::Add if unsigned => "llvm.uadd.with.overflow",
+            BinaryOp::Add => "llvm.sadd.with.overflow",
+            BinaryOp::Sub if unsigned => "llvm.usub.with.overflow",
+            BinaryOp::Sub => "llvm.ssub.with.overflow",
+            BinaryOp::Mul if unsigned => "llvm.umul.with.overflow",
+            BinaryOp::Mul => "llvm.smul.with.overflow",
+            BinaryOp::Div | BinaryOp::Mod => {
+                let int_type = left.get_type();
+                let zero = self.builder.build_int_compare(IntPredicate::EQ, right, int_type.const_zero(), "")?;
+                self.check(zero, "division by zero", location)?;
+                if let Some((min, _)) = ty.integer_range().filter(|_| !unsigned) {
+                    let min = int_type.const_int(min as u64, true);
+                    let minus_one = int_type.const_all_ones();
+                    let smallest = self.builder.build_int_compare(IntPredicate::EQ, left, min, "")?;
+                    let by_minus_one = self.builder.build_int_compare(IntPredicate::EQ, right, minus_one, "")?;
+                    let overflow = self.builder.build_and(smallest, by_minus_one, "")?;
+                    self.check(overflow, "integer overflow", location)?;
+                }
+                return Ok(None);
+            }
+            _ => return Ok(None),
+        };
+        let result = self.overflowing(name, left, right)?.into_struct_value();
+        let value = self.builder.build_extract_value(result, 0, "")?;
+        let overflow = self.builder.build_extract_value(result, 1, "")?.into_int_value();
+        self.check(overflow, "integer overflow", location)?;
+        Ok(Some(value))
+    }
+
+    fn cast(&mut self, value: BasicValueEnum<'ctx>, from: &Type, to: &Type) -> Result<BasicValueEnum<'ctx>> {
+        let signed = !from.is_unsigned() && *from != Type::Bool;
+        let f64_type = self.context.f64_type();
+        Ok(match (from, to) {
+            (from, to) if from == to => value,
+            (Type::Float, to) if to.is_integer() => {
+                // Through a 64-bit integer, wrapping like the interpreter does
+                let i64_type = self.context.i64_type();
+                let intrinsic = Intrinsic::find("llvm.fptosi.sat")
+                    .ok_or_else(|| failed("LLVM has no llvm.fptosi.sat".to_string()))?;
+                let function = intrinsic
+                    .get_declaration(self.module, &[i64_type.into(), f64_type.into()])
+                    .ok_or_else(|| failed("LLVM has no llvm.fptosi.sat for i64".to_string()))?;
+                let wide = self
+                    .builder
+                    .build_call(function, &[value.into()], "")?
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or_else(|| failed("llvm.fptosi.sat returns nothing".to_string()))?;
+                self.convert(wide, &Type::Int, to)?
+            }
+            (Type::Bool, Type::Float) => self
+                .builder
+                .build_unsigned_int_to_float(value.into_int_value(), f64_type, "")?
+                .into(),
+            (_, Type::Float) if signed => self
+                .builder
+                .build_signed_int_to_float(value.into_int_value(), f64_type, "")?
+                .into(),
+            (_, Type::Float) => self
+                .builder
+                .build_unsigned_int_to_float(value.into_int_value(), f64_type, "")?
+                .into(),
+            (Type::Float, Type::Bool) => {
+                let zero = f64_type.const_zero();
+                self.builder
+                    .build_float_compare(FloatPredicate::UNE, value.into_float_value(), zero, "")?
+                    .into()
+            }
+            (_, Type::Bool) => {
+                let value = value.into_int_value();
+                let zero = value.get_type().const_zero();
+                self.builder.build_int_compare(IntPredicate::NE, value, zero, "")?.into()
+            }
+            (Type::Bool, to) => {
+                let to_type = value_type(self.context, to)?.into_int_type();
+                self.builder.build_int_z_extend(value.into_int_value(), to_type, "")?.into()
+            }
+            // Between integers, extending by the signedness of the source
+            (from, to) => self.convert(value, from, to)?,
+        })
+    }
+
+    /// The end of a block, which leaves the function's frame where it returns
+    fn terminator(&mut self, terminator: &ir::Terminator) -> Result<()> {
+        match terminator {
+            ir::Terminator::Jump(target) => {
+                self.builder.build_unconditional_branch(self.blocks[*target])?;
+            }
+            ir::Terminator::Branch(condition, then_block, else_block) => {
+                let condition = self.operand(condition, &Type::Bool)?.into_int_value();
+                self.builder
+                    .build_conditional_branch(condition, self.blocks[*then_block], self.blocks[*else_block])?;
+            }
+            ir::Terminator::Return(value) => {
+                let return_type = &self.function.return_type;
+                let value = match value {
+                    Some(value) => Some(self.operand(value, return_type)?),
+                    // `main` returns an exit status even when declared
+                    // without one
+                    None if *return_type != Type::Void => Some(value_type(self.context, return_type)?.const_zero()),
+                    None => None,
+                };
+                if self.function.frame.is_some() {
+                    self.runtime("z_leave", &[])?;
+                }
+                match value {
+                    Some(value) => self.builder.build_return(Some(&value))?,
+                    None => self.builder.build_return(None)?,
+                };
+            }
+            ir::Terminator::Unreachable => {
+                self.builder.build_unreachable()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,23 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
+#[cfg(feature = "c")]
+use clap::Args;
 use miette::{IntoDiagnostic, Result};
-use std::path::PathBuf;
-use z_lang::{compile_file, run_file};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "c")]
+use std::time::Duration;
+use z_lang::{
+    create_project, dead_code_report, dump_ast, dump_tokens, emit, explain, finish_timings, lint_file, set_color,
+    set_warning_levels, start_timings, ColorChoice, CompilerError, Config, Database, Dependency, EXPLANATIONS,
+    ImportPaths, LintConfig, Manifest, Severity, Source, TimingsFormat,
+};
+#[cfg(feature = "c")]
+use z_lang::{
+    bench_file, build_project, compilers, run_file, targets, CBackend, CCompiler, Compiler, CrateType, Emit,
+    Environment, Gc, LinkKind, LinkOptions, OptReport, PassManager, RunLimits, STDOUT,
+};
+#[cfg(feature = "dap")]
+use z_lang::run_dap_server;
 
 #[derive(Parser)]
 #[command(name = "zc")]
@@ -9,57 +25,746 @@ use z_lang::{compile_file, run_file};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Print nothing but errors and warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print a banner around the output of zc run, and the timings of each
+    /// stage unless --timings says how
+    #[arg(long, global = true)]
+    verbose: bool,
+
+    /// Print the wall time, peak memory and output size of each stage of the
+    /// compiler to stderr when the command finishes, as a table or as a line
+    /// of JSON
+    #[arg(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text"
+    )]
+    timings: Option<TimingsFormat>,
+
+    /// Color errors and warnings: auto colors them on a terminal unless
+    /// NO_COLOR is set, always and never regardless
+    #[arg(long, global = true, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    #[cfg(feature = "c")]
     /// Compile a Z source file to an executable
     Compile {
         /// Input file
         #[arg(value_name = "FILE")]
         input: PathBuf,
 
-        /// Output file
+        /// Output file, or - for stdout
         #[arg(short, long, value_name = "FILE")]
         output: Option<PathBuf>,
 
-        /// Optimization level (0-3)
-        #[arg(short, long, default_value_t = 3)]
-        opt_level: u8,
+        /// The stage to stop at: tokens, ast, typed-ast, ir, c or asm, written
+        /// to stdout unless -o is given, obj, or exe for the executable or
+        /// library
+        #[arg(long, value_name = "STAGE", default_value = "exe")]
+        emit: Emit,
+
+        #[command(flatten)]
+        optimization: OptimizationArgs,
+
+        /// Reject uses of strings, arrays and structs after they are moved
+        #[arg(long)]
+        strict_memory: bool,
+
+        /// How the program manages memory: rc counts references, boehm links
+        /// the Boehm garbage collector
+        #[arg(long, value_name = "MODE", default_value = "rc")]
+        gc: Gc,
+
+        /// Leave out the checks for errors at run time, such as indexing out
+        /// of bounds
+        #[arg(long)]
+        release: bool,
+
+        /// Also write the generated C to this file
+        #[arg(long, value_name = "FILE")]
+        emit_c: Option<PathBuf>,
+
+        /// Keep the intermediate C and object files next to the output
+        #[arg(long)]
+        keep_temps: bool,
+
+        /// Write a C header declaring the pub functions next to the output as .h
+        #[arg(long)]
+        emit_header: bool,
+
+        /// What to build: bin for an executable, or lib for a static library
+        /// (shared with --shared) of the pub functions and its header
+        #[arg(long, value_name = "TYPE", default_value = "bin")]
+        crate_type: CrateType,
+
+        #[command(flatten)]
+        c_compiler: CCompilerArgs,
+
+        /// Link statically, so the executable needs no shared libraries
+        #[arg(long, conflicts_with = "shared")]
+        r#static: bool,
+
+        /// Build a shared library instead of an executable
+        #[arg(long)]
+        shared: bool,
+
+        /// Add a directory to search for libraries
+        #[arg(short = 'L', value_name = "DIR")]
+        library_paths: Vec<PathBuf>,
+
+        /// Link a library
+        #[arg(short = 'l', value_name = "LIB")]
+        libraries: Vec<String>,
     },
+    #[cfg(feature = "c")]
     /// Run a Z source file directly
     Run {
         /// Input file
         #[arg(value_name = "FILE")]
         input: PathBuf,
+
+        /// Reject uses of strings, arrays and structs after they are moved
+        #[arg(long)]
+        strict_memory: bool,
+
+        /// How the program manages memory: rc counts references, boehm links
+        /// the Boehm garbage collector
+        #[arg(long, value_name = "MODE", default_value = "rc")]
+        gc: Gc,
+
+        /// Leave out the checks for errors at run time, such as indexing out
+        /// of bounds
+        #[arg(long)]
+        release: bool,
+
+        /// Collect the program's output and print it once it exits, instead
+        /// of letting it use the terminal as it runs
+        #[arg(long)]
+        capture: bool,
+
+        /// Build the program again even if an earlier run of the same
+        /// sources built it already, and leave it out of the cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Kill the program if it runs for longer than this many seconds,
+        /// exiting with 124
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: Option<u64>,
+
+        /// Run the program without network access and without writing,
+        /// creating or removing files or starting processes (Linux only)
+        #[arg(long)]
+        sandbox: bool,
+
+        #[command(flatten)]
+        optimization: OptimizationArgs,
+
+        #[command(flatten)]
+        c_compiler: CCompilerArgs,
+
+        /// Arguments for the program, after --
+        #[arg(last = true, value_name = "ARGS")]
+        args: Vec<String>,
+    },
+    /// Create a project in a new directory
+    New {
+        /// The directory, whose name is the project's unless --name is given
+        #[arg(value_name = "DIR")]
+        path: PathBuf,
+
+        /// The name of the project
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Make the current directory a project
+    Init {
+        /// The name of the project, by default the directory's
+        #[arg(long)]
+        name: Option<String>,
+    },
+    #[cfg(feature = "c")]
+    /// Build the project whose z.toml is in this directory or above it
+    Build {
+        /// Leave out the checks for errors at run time, such as indexing out
+        /// of bounds
+        #[arg(long)]
+        release: bool,
+
+        #[command(flatten)]
+        optimization: OptimizationArgs,
+
+        #[command(flatten)]
+        c_compiler: CCompilerArgs,
+    },
+    /// Add a dependency to the project whose z.toml is in this directory or
+    /// above it, and fetch it
+    #[command(group(ArgGroup::new("source").required(true).args(["path", "git"])))]
+    Add {
+        /// The name the project imports it by
+        name: String,
+
+        /// A directory of modules or another project, relative to the
+        /// project directory
+        #[arg(long, value_name = "DIR")]
+        path: Option<PathBuf>,
+
+        /// A git repository
+        #[arg(long, value_name = "URL")]
+        git: Option<String>,
+
+        /// The branch, tag or commit of the repository, or else its default
+        /// branch
+        #[arg(long, requires = "git")]
+        rev: Option<String>,
+    },
+    #[cfg(feature = "c")]
+    /// Time the @bench functions of a Z source file, built with --release
+    Bench {
+        /// Input file
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Only run the benchmarks whose names contain this
+        #[arg(long, value_name = "TEXT")]
+        filter: Option<String>,
+
+        /// How many samples to time of each benchmark
+        #[arg(long, value_name = "N", default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+        samples: u32,
+
+        #[command(flatten)]
+        optimization: OptimizationArgs,
+
+        #[command(flatten)]
+        c_compiler: CCompilerArgs,
     },
+    /// Check a Z source file and the modules it imports for errors without
+    /// building it
+    Check {
+        /// Input file
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Reject uses of strings, arrays and structs after they are moved
+        #[arg(long)]
+        strict_memory: bool,
+    },
+    /// Print the functions and struct fields main never reaches, as JSON
+    DeadCode {
+        /// Input file
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+    },
+    /// Check a Z source file, or the project whose z.toml is in this
+    /// directory or above it, for style problems and likely mistakes, as
+    /// set in the [lint] table of z.toml
+    Lint {
+        /// Input file, instead of the project
+        #[arg(value_name = "FILE")]
+        input: Option<PathBuf>,
+    },
+    /// Print the tokens or the syntax tree of a Z source file
+    #[command(group(ArgGroup::new("stage").required(true).args(["tokens", "ast"])))]
+    Dump {
+        /// Input file
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Print the tokens, with their positions
+        #[arg(long)]
+        tokens: bool,
+
+        /// Print the syntax tree as parsed
+        #[arg(long)]
+        ast: bool,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Explain an error or warning code, such as E0101, with an example of
+    /// wrong and fixed code, or list the codes
+    Explain {
+        #[arg(value_name = "CODE")]
+        code: Option<String>,
+    },
+    #[cfg(feature = "c")]
+    /// Print the C compilers found, the compiler builds use, the runtime
+    /// features it supports and where zc keeps its caches
+    Env,
+    #[cfg(feature = "c")]
+    /// List target triples and the installed backends that can build for
+    /// each, for --target
+    Targets,
+    #[cfg(feature = "dap")]
+    /// Start a Debug Adapter Protocol server on stdin/stdout
+    Dap,
+}
+
+#[cfg(feature = "c")]
+#[derive(Args)]
+struct OptimizationArgs {
+    /// Optimization level (0-3), 3 unless the project sets another
+    #[arg(short = 'O', long, value_name = "LEVEL")]
+    opt_level: Option<u8>,
+
+    /// Turn optimization passes on or off, as in +inline,-dce, to narrow down
+    /// optimizer bugs. The passes are inline, fold-branches, skip-empty,
+    /// merge-blocks, strength-reduce, unroll, cse and dce
+    #[arg(long, value_name = "PASSES", allow_hyphen_values = true)]
+    passes: Option<String>,
+
+    /// Print what each optimization pass did, and where
+    #[arg(long)]
+    opt_report: bool,
+
+    /// Write what each optimization pass did to this file as JSON
+    #[arg(long, value_name = "FILE")]
+    opt_report_json: Option<PathBuf>,
 }
 
-fn main() -> Result<()> {
+#[cfg(feature = "c")]
+impl OptimizationArgs {
+    /// The passes to run, at `default_level` unless -O says otherwise
+    fn resolve(&self, default_level: u8) -> Result<PassManager> {
+        let mut passes = PassManager::new(self.opt_level.unwrap_or(default_level));
+        if let Some(toggles) = &self.passes {
+            passes.configure(toggles).map_err(|e| miette::miette!("--passes: {}", e))?;
+        }
+        Ok(passes)
+    }
+
+    /// Whether a report of the passes is asked for
+    fn reports(&self) -> bool {
+        self.opt_report || self.opt_report_json.is_some()
+    }
+
+    /// Prints or writes `report` as asked
+    fn write_report(&self, report: &OptReport) -> Result<()> {
+        if self.opt_report {
+            eprint!("{}", report.to_text());
+        }
+        if let Some(path) = &self.opt_report_json {
+            std::fs::write(path, format!("{:#}\n", report.to_json())).into_diagnostic()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "c")]
+#[derive(Args, Default)]
+struct CCompilerArgs {
+    /// Kind of C compiler to use: gcc, clang, tcc, zig or msvc (clang-cl). By
+    /// default the first one installed
+    #[arg(long, value_name = "BACKEND")]
+    backend: Option<CBackend>,
+
+    /// C compiler to build the generated code with [env: ZC_CC]
+    #[arg(long, value_name = "CC")]
+    cc: Option<String>,
+
+    /// Flags for the C compiler, replacing the backend's defaults [env: ZC_CFLAGS]
+    #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+    cflags: Option<String>,
+
+    /// Tune for the CPU building the program. Other CPUs may not run it, and
+    /// the build is only reproducible on the same kind of CPU
+    #[arg(long)]
+    native_cpu: bool,
+
+    /// Optimize across object files at link time
+    #[arg(long)]
+    lto: bool,
+
+    /// Build for this target triple, such as aarch64-linux-gnu, with the
+    /// clang, zig or msvc backend, or with the gcc cross compiler named
+    /// after it
+    #[arg(long, value_name = "TRIPLE")]
+    target: Option<String>,
+}
+
+#[cfg(feature = "c")]
+impl CCompilerArgs {
+    /// The C compiler these flags ask for, and `config` where they say
+    /// nothing. `ZC_CC` and `ZC_CFLAGS` come before `config`.
+    fn resolve(&self, config: &Config) -> Result<CCompiler> {
+        let cc = self.cc.clone().or_else(|| std::env::var("ZC_CC").ok()).or_else(|| config.cc.clone());
+        let cflags = self.cflags.clone().or_else(|| std::env::var("ZC_CFLAGS").ok()).or_else(|| config.cflags.clone());
+        let mut compiler = CCompiler::resolve(self.backend.or(config.backend), cc.as_deref(), cflags.as_deref())
+            .map_err(|e| miette::miette!("{}", e))?;
+        if let Some(target) = self.target.as_ref().or(config.target.as_ref()) {
+            compiler.set_target(target).map_err(|e| miette::miette!("{}", e))?;
+        }
+        if (self.native_cpu || self.lto) && !compiler.backend.optimizes_fully() {
+            return Err(miette::miette!(
+                "--native-cpu and --lto are not supported by the {} backend",
+                compiler.backend.name()
+            ));
+        }
+        compiler.native_cpu = self.native_cpu;
+        compiler.lto = self.lto;
+        Ok(compiler)
+    }
+
+    /// Sets the C compiler and the target these flags and `config` ask for
+    /// on `options`, for it to find the compiler when it needs one
+    fn configure<'a>(&self, options: &'a mut Compiler, config: &Config) -> &'a mut Compiler {
+        if let Some(cc) = self.cc.clone().or_else(|| std::env::var("ZC_CC").ok()).or_else(|| config.cc.clone()) {
+            options.cc(&cc);
+        }
+        if let Some(target) = self.target.as_ref().or(config.target.as_ref()) {
+            options.target(target);
+        }
+        options
+    }
+}
+
+/// The last component of `path`, which names a project by default
+fn dir_name(path: &std::path::Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn main() {
     let cli = Cli::parse();
+    set_color(cli.color);
+    // Without it, miette reports errors in plain text
+    #[cfg(feature = "fancy")]
+    {
+        let color = cli.color.enabled();
+        miette::set_hook(Box::new(move |_| Box::new(miette::MietteHandlerOpts::new().color(color).build())))
+            .expect("the report handler is set once");
+    }
 
+    let timings = cli.timings.or(cli.verbose.then_some(TimingsFormat::Text));
+    if timings.is_some() {
+        start_timings();
+    }
+    let code = run(cli).unwrap_or_else(|report| {
+        match report.downcast_ref::<CompilerError>() {
+            // Shown with the code they point at rather than as a report
+            Some(CompilerError::Diagnostics(diagnostics)) => diagnostics.iter().for_each(emit),
+            _ => eprintln!("Error: {:?}", report),
+        }
+        1
+    });
+    // Also for a command that failed, up to the stage it failed in
+    match timings {
+        Some(TimingsFormat::Text) => eprint!("{}", finish_timings().to_text()),
+        Some(TimingsFormat::Json) => eprintln!("{}", finish_timings().to_json()),
+        None => {}
+    }
+    if code != 0 {
+        std::process::exit(code);
+    }
+}
+
+/// Runs the command, returning the code to exit with
+fn run(cli: Cli) -> Result<i32> {
+    let config = Config::find(&std::env::current_dir().into_diagnostic()?)?;
+    set_warning_levels(&config.warnings);
     match cli.command {
+        #[cfg(feature = "c")]
         Commands::Compile {
             input,
             output,
-            opt_level,
+            emit,
+            optimization,
+            strict_memory,
+            gc,
+            release,
+            emit_c,
+            keep_temps,
+            emit_header,
+            crate_type,
+            c_compiler,
+            r#static,
+            shared,
+            library_paths,
+            libraries,
         } => {
+            let passes = optimization.resolve(config.opt_level.unwrap_or(3))?;
+            // Text short of assembly needs no C compiler, nor does what LLVM
+            // builds; the build asks for one once it turns out to need it
+            let llvm = cfg!(feature = "llvm") && crate_type == CrateType::Bin && emit <= Emit::Obj;
+            let compiler = match c_compiler.resolve(&config) {
+                Ok(mut compiler) => {
+                    compiler.link = LinkOptions {
+                        kind: if r#static {
+                            LinkKind::Static
+                        } else if shared {
+                            LinkKind::Shared
+                        } else {
+                            LinkKind::Executable
+                        },
+                        search_paths: library_paths,
+                        libraries,
+                        ..LinkOptions::default()
+                    };
+                    Some(compiler)
+                }
+                Err(_) if emit < Emit::Asm || llvm => None,
+                Err(e) => return Err(e),
+            };
+            // Named as for GCC without a C compiler
+            let backend = compiler.as_ref().map_or(CBackend::Gcc, |compiler| compiler.backend);
             let output = output.unwrap_or_else(|| {
-                let mut out = input.file_stem().unwrap().to_owned();
-                out.to_string_lossy().to_string().into()
+                let stem = input.file_stem().unwrap().to_string_lossy();
+                match crate_type {
+                    _ if emit.is_text() => STDOUT.into(),
+                    _ if emit == Emit::Obj => format!("{}.{}", stem, backend.object_extension()).into(),
+                    CrateType::Bin => format!("{}{}", stem, std::env::consts::EXE_SUFFIX).into(),
+                    CrateType::Lib if shared => {
+                        format!("{}{}{}", std::env::consts::DLL_PREFIX, stem, std::env::consts::DLL_SUFFIX).into()
+                    }
+                    CrateType::Lib => backend.archive_name(&stem).into(),
+                }
             });
             
-            println!("Compiling {} to {} with optimization level {}", 
-                input.display(), output.display(), opt_level);
+            if !cli.quiet {
+                eprintln!("Compiling {} to {} with optimization level {}", 
+                    input.display(), output.display(), passes.opt_level());
+            }
                 
-            compile_file(&input, &output, opt_level).into_diagnostic()?;
-            println!("Compilation successful!");
+            let mut options = Compiler::new();
+            options
+                .passes(passes)
+                .strict_memory(strict_memory)
+                .keep_temps(keep_temps)
+                .emit(emit)
+                .emit_header(emit_header)
+                .crate_type(crate_type)
+                .gc(gc)
+                .release(release);
+            match compiler {
+                Some(compiler) => options.c_compiler(compiler),
+                None => c_compiler.configure(&mut options, &config),
+            };
+            if let Some(path) = emit_c {
+                options.emit_c(path);
+            }
+            let compiled = options.compile(&input, &output)?;
+            compiled.diagnostics.iter().for_each(z_lang::emit);
+            optimization.write_report(&compiled.report)?;
+            if !cli.quiet {
+                eprintln!("Compilation successful!");
+            }
+        }
+        #[cfg(feature = "c")]
+        Commands::Run {
+            input,
+            strict_memory,
+            gc,
+            release,
+            capture,
+            no_cache,
+            timeout,
+            sandbox,
+            optimization,
+            c_compiler,
+            args,
+        } => {
+            if !cli.quiet {
+                eprintln!("Running {}", input.display());
+            }
+            let mut report = OptReport::default();
+            let code = run_file(
+                &input,
+                &args,
+                strict_memory,
+                gc,
+                release,
+                &optimization.resolve(config.opt_level.unwrap_or(3))?,
+                &c_compiler.resolve(&config)?,
+                capture,
+                // A cached executable comes without the report of its passes
+                !no_cache && !optimization.reports(),
+                &RunLimits {
+                    timeout: timeout.map(Duration::from_secs),
+                    sandbox,
+                },
+                cli.verbose,
+                &mut report,
+            )?;
+            optimization.write_report(&report)?;
+            // Scripts see the program's own exit code
+            return Ok(code);
+        }
+        Commands::New { path, name } => {
+            if path.exists() {
+                return Err(miette::miette!("{} exists already; zc init makes a directory a project", path.display()));
+            }
+            let name = name.unwrap_or_else(|| dir_name(&path));
+            create_project(&path, &name)?;
+            if !cli.quiet {
+                eprintln!("Created project {} in {}", name, path.display());
+            }
+        }
+        Commands::Init { name } => {
+            let dir = std::env::current_dir().into_diagnostic()?;
+            let name = name.unwrap_or_else(|| dir_name(&dir));
+            create_project(&dir, &name)?;
+            if !cli.quiet {
+                eprintln!("Created project {}", name);
+            }
+        }
+        #[cfg(feature = "c")]
+        Commands::Build {
+            release,
+            optimization,
+            c_compiler,
+        } => {
+            let manifest = Manifest::find(&std::env::current_dir().into_diagnostic()?)?;
+            let passes = optimization.resolve(config.opt_level.unwrap_or(manifest.opt_level))?;
+            if !cli.quiet {
+                eprintln!("Building {} with optimization level {}", manifest.name, passes.opt_level());
+            }
+            let mut report = OptReport::default();
+            let output = build_project(&manifest, &passes, release, &c_compiler.resolve(&config)?, &mut report)?;
+            optimization.write_report(&report)?;
+            if !cli.quiet {
+                eprintln!("Built {}", output.display());
+            }
+        }
+        Commands::Add { name, path, git, rev } => {
+            let manifest = Manifest::find(&std::env::current_dir().into_diagnostic()?)?;
+            let source = match (path, git) {
+                (Some(path), _) => Source::Path(path),
+                (None, git) => Source::Git { url: git.unwrap_or_default(), rev },
+            };
+            manifest.add_dependency(&Dependency { name: name.clone(), source })?;
+            if !cli.quiet {
+                eprintln!("Added {}", name);
+            }
+        }
+        #[cfg(feature = "c")]
+        Commands::Bench {
+            input,
+            filter,
+            samples,
+            optimization,
+            c_compiler,
+        } => {
+            if !cli.quiet {
+                eprintln!("Benchmarking {}", input.display());
+            }
+            let mut report = OptReport::default();
+            let benches = bench_file(
+                &input,
+                filter.as_deref(),
+                samples as usize,
+                &optimization.resolve(config.opt_level.unwrap_or(3))?,
+                &c_compiler.resolve(&config)?,
+                &mut report,
+            )?;
+            optimization.write_report(&report)?;
+            print!("{}", benches.to_text());
+        }
+        Commands::Check { input, strict_memory } => {
+            let mut database = Database::new(ImportPaths::of_file(&input));
+            database.strict_memory(strict_memory);
+            let file = database.load(&input)?;
+            let diagnostics = database.diagnostics(file)?;
+            diagnostics.iter().for_each(emit);
+            let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+            if !cli.quiet {
+                eprintln!("Checked {}: {} errors, {} warnings", input.display(), errors, diagnostics.len() - errors);
+            }
+            return Ok(if errors > 0 { 1 } else { 0 });
+        }
+        Commands::DeadCode { input } => {
+            let report = dead_code_report(&input)?;
+            println!("{}", report.to_json());
+        }
+        Commands::Explain { code: Some(code) } => {
+            let explanation = explain(&code)
+                .ok_or_else(|| miette::miette!("{} is not an error code; zc explain lists them", code))?;
+            print!("{}", explanation.to_text());
+        }
+        Commands::Explain { code: None } => {
+            let mut stdout = std::io::stdout().lock();
+            for explanation in EXPLANATIONS {
+                match writeln!(stdout, "{}  {}", explanation.code, explanation.title) {
+                    Ok(()) => {}
+                    // Such as when piped into head, which has read enough
+                    Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
+                    Err(e) => return Err(e).into_diagnostic(),
+                }
+            }
+        }
+        Commands::Lint { input } => {
+            let (input, imports, config) = match input {
+                Some(input) => {
+                    let dir = input.parent().map(Path::to_path_buf).unwrap_or_default();
+                    // A file of a project is linted with the rules and imports
+                    // of the project
+                    let file = std::fs::canonicalize(&input).into_diagnostic()?;
+                    match Manifest::search(file.parent().unwrap_or(&file))? {
+                        Some(manifest) => {
+                            let mut imports = manifest.import_paths()?;
+                            imports.roots.insert(0, dir);
+                            (input, imports, config.lint(&manifest.lint))
+                        }
+                        None => {
+                            let imports = ImportPaths::of_file(&input);
+                            (input, imports, config.lint(&LintConfig::default()))
+                        }
+                    }
+                }
+                None => {
+                    let manifest = Manifest::find(&std::env::current_dir().into_diagnostic()?)?;
+                    let imports = manifest.import_paths()?;
+                    (manifest.entry, imports, config.lint(&manifest.lint))
+                }
+            };
+            let diagnostics = lint_file(&input, &imports, &config)?;
+            for diagnostic in &diagnostics {
+                emit(diagnostic);
+            }
+            let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+            if !cli.quiet {
+                eprintln!(
+                    "Linted {}: {} errors, {} warnings",
+                    input.display(),
+                    errors,
+                    diagnostics.len() - errors
+                );
+            }
+            if errors > 0 {
+                return Ok(1);
+            }
+        }
+        Commands::Dump { input, tokens, ast: _, json } => {
+            let dump = if tokens { dump_tokens(&input, json) } else { dump_ast(&input, json) };
+            print!("{}", dump?);
+        }
+        #[cfg(feature = "c")]
+        Commands::Env => {
+            // The compiler builds use without flags on the command line
+            let compiler = CCompilerArgs::default().resolve(&config).ok();
+            print!("{}", Environment::detect(compiler, &config).to_text());
+        }
+        #[cfg(feature = "c")]
+        Commands::Targets => {
+            for target in targets(&compilers()) {
+                println!("{}", target.to_text());
+            }
         }
-        Commands::Run { input } => {
-            println!("Running {}", input.display());
-            run_file(&input).into_diagnostic()?;
+        #[cfg(feature = "dap")]
+        Commands::Dap => {
+            run_dap_server()?;
         }
     }
 
-    Ok(())
+    Ok(0)
 }
\ No newline at end of file
@@ -0,0 +1,222 @@
+// Symbol names for Z functions in generated C. A symbol is built from the
+// module declaring the function, the function's name and, for an instance of
+// a generic function, its type arguments, so the same declaration gets the
+// same symbol in every build and separately compiled modules never share one:
+//
+//     symbol = "zN" ident(module) name "E"
+//     name   = ident | ident "I" type+ "E"       instance of a generic
+//     ident  = decimal length, then the identifier
+//     type   = "i" int | "a" i8 | "s" i16 | "l" i32 | "x" i64
+//            | "h" u8  | "t" u16 | "j" u32 | "y" u64
+//            | "d" float | "b" bool | "S" string | "v" void
+//            | "A" type                          array
+//            | "O" type                          optional
+//            | "F" type type* "E"                function: result, then parameters
+//            | "N" name                          struct or enum
+//            | "T" ident                         type parameter
+//
+// The module is the source file's name without its extension, with every
+// character other than ASCII letters and digits, `_` included, written as `_`
+// and its code in hex, so that `my_util.z` and `my-util.z` get different
+// symbols. For example, `identity<[int]>` from `util.z` is
+// `zN4util8identityIAiEE`, and `f` from `my-util.z` is `zN9my_2dutil1fE`.
+//
+// `main` and `pub` functions keep their own names, and C functions declared
+// with `extern` are called by theirs. A `pub` function may therefore not be
+// named like a C keyword, something of the C headers the generated code
+// includes, or the runtime, whose names start with `z_`.
+//
+// Enums and structs are C types named as in Z. Instances of generic ones, such
+// as `Option` and `Pair`, are named after their type arguments instead, with
+// every character that cannot appear in a C identifier, and `_`, written as
+// `_` and its code in hex, after `zT`: `Option<int>` is `zTOption_3cint_3e`. Optionals other than strings are
+// structs named after the code of the type they hold, written the same way
+// after `zO`: `int?` is `zOi` and `Option<int>?` is `zON11Option_3cint_3e`.
+
+use crate::ast::Type;
+use std::collections::HashMap;
+
+/// Instances of generic declarations by name, with the generic declaration
+/// and its type arguments
+pub type Instances = HashMap<String, (String, Vec<Type>)>;
+
+/// C's keywords, and the functions, types and objects of the headers the
+/// generated C includes that C code is likely to call or name
+const C_NAMES: &[&str] = &[
+    // Keywords
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+    "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed",
+    "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while",
+    // <stdbool.h> and <stdint.h>
+    "bool", "true", "false", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t",
+    "uint64_t", "intptr_t", "uintptr_t", "intmax_t", "uintmax_t", "size_t", "ptrdiff_t",
+    // <stdio.h>
+    "printf", "fprintf", "sprintf", "snprintf", "vprintf", "vfprintf", "vsprintf", "vsnprintf", "scanf",
+    "fscanf", "sscanf", "puts", "fputs", "gets", "fgets", "putchar", "putc", "fputc", "getchar", "getc",
+    "fgetc", "ungetc", "fopen", "freopen", "fclose", "fflush", "fread", "fwrite", "fseek", "ftell", "rewind",
+    "fgetpos", "fsetpos", "feof", "ferror", "clearerr", "perror", "remove", "rename", "tmpfile", "tmpnam",
+    "setbuf", "setvbuf", "stdin", "stdout", "stderr",
+    // <stdlib.h>
+    "malloc", "calloc", "realloc", "free", "abort", "exit", "atexit", "quick_exit", "at_quick_exit", "_Exit",
+    "getenv", "system", "atoi", "atol", "atoll", "atof", "strtol", "strtoll", "strtoul", "strtoull", "strtod",
+    "strtof", "strtold", "rand", "srand", "qsort", "bsearch", "abs", "labs", "llabs", "div", "ldiv", "lldiv",
+    "aligned_alloc",
+    // <string.h>
+    "memcpy", "memmove", "memset", "memcmp", "memchr", "strcpy", "strncpy", "strcat", "strncat", "strcmp",
+    "strncmp", "strcoll", "strxfrm", "strchr", "strrchr", "strstr", "strspn", "strcspn", "strpbrk", "strtok",
+    "strlen", "strerror", "strdup", "strndup",
+    // <math.h>
+    "sin", "cos", "tan", "asin", "acos", "atan", "atan2", "sinh", "cosh", "tanh", "exp", "exp2", "log",
+    "log2", "log10", "pow", "sqrt", "cbrt", "hypot", "ceil", "floor", "round", "trunc", "fabs", "fmod",
+    "fmin", "fmax", "frexp", "ldexp", "modf", "isnan", "isinf", "nan",
+    // The process, which the runtime and sandbox use
+    "errno", "environ", "read", "write", "open", "close", "fork", "execve", "_exit", "signal", "raise",
+];
+
+/// What a `pub` function named `name` would collide with in C, which keeps
+/// its name: C itself, the C library or the runtime
+pub fn reserved_c_name(name: &str) -> Option<&'static str> {
+    let underscored = name.strip_prefix('_').is_some_and(|rest| rest.starts_with(|c: char| c == '_' || c.is_ascii_uppercase()));
+    if name.starts_with("z_") {
+        Some("the runtime")
+    } else if underscored {
+        Some("C, which reserves names starting with __ or _ and a capital letter")
+    } else if C_NAMES.contains(&name) {
+        Some("C or its library")
+    } else {
+        None
+    }
+}
+
+/// The symbol of function `name` declared in `module`
+#[cfg(feature = "codegen")]
+pub fn symbol(module: &str, name: &str, instances: &Instances) -> String {
+    let mut symbol = String::from("zN");
+    ident(&module_ident(module), &mut symbol);
+    mangle_name(name, instances, &mut symbol);
+    symbol.push('E');
+    symbol
+}
+
+/// The C name of the enum or struct `name`
+#[cfg(feature = "codegen")]
+pub fn type_name(name: &str) -> String {
+    if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return name.to_string();
+    }
+    escaped("zT", name)
+}
+
+/// The C name of the struct holding optional values of `inner`, which is
+/// not a string
+#[cfg(feature = "codegen")]
+pub fn optional_name(inner: &Type) -> String {
+    let mut code = String::new();
+    mangle_type(inner, &Instances::new(), &mut code);
+    escaped("zO", &code)
+}
+
+/// `prefix` followed by `name`, with every character other than ASCII
+/// letters and digits written as `_` and its code in hex
+#[cfg(feature = "codegen")]
+fn escaped(prefix: &str, name: &str) -> String {
+    let mut c_name = String::from(prefix);
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            c_name.push(c);
+        } else {
+            c_name.push_str(&format!("_{:02x}", c as u32));
+        }
+    }
+    c_name
+}
+
+/// The module part of a symbol, which must be valid in a C identifier, and
+/// differ between modules of different names
+#[cfg(feature = "codegen")]
+fn module_ident(module: &str) -> String {
+    if module.is_empty() {
+        return "main".to_string();
+    }
+    escaped("", module)
+}
+
+#[cfg(feature = "codegen")]
+fn ident(name: &str, out: &mut String) {
+    out.push_str(&name.len().to_string());
+    out.push_str(name);
+}
+
+#[cfg(feature = "codegen")]
+fn mangle_name(name: &str, instances: &Instances, out: &mut String) {
+    match instances.get(name) {
+        Some((generic, type_args)) => {
+            ident(generic, out);
+            out.push('I');
+            for ty in type_args {
+                mangle_type(ty, instances, out);
+            }
+            out.push('E');
+        }
+        None => ident(name, out),
+    }
+}
+
+#[cfg(feature = "codegen")]
+fn mangle_type(ty: &Type, instances: &Instances, out: &mut String) {
+    let code = match ty {
+        Type::Int => "i",
+        Type::I8 => "a",
+        Type::I16 => "s",
+        Type::I32 => "l",
+        Type::I64 => "x",
+        Type::U8 => "h",
+        Type::U16 => "t",
+        Type::U32 => "j",
+        Type::U64 => "y",
+        Type::Float => "d",
+        Type::Bool => "b",
+        Type::String => "S",
+        Type::Void => "v",
+        Type::Array(element_type) => {
+            out.push('A');
+            return mangle_type(element_type, instances, out);
+        }
+        Type::Optional(inner) => {
+            out.push('O');
+            return mangle_type(inner, instances, out);
+        }
+        Type::Function(params, ret) => {
+            out.push('F');
+            mangle_type(ret, instances, out);
+            for param in params {
+                mangle_type(param, instances, out);
+            }
+            "E"
+        }
+        Type::Struct(name) | Type::Enum(name) => {
+            out.push('N');
+            return mangle_name(name, instances, out);
+        }
+        // Resolved before code generation; mangled like the instance they
+        // resolve to
+        Type::Applied(name, type_args) => {
+            out.push('N');
+            ident(name, out);
+            out.push('I');
+            for ty in type_args {
+                mangle_type(ty, instances, out);
+            }
+            "E"
+        }
+        Type::Generic(name) => {
+            out.push('T');
+            return ident(name, out);
+        }
+        Type::Inferred => {
+            out.push('T');
+            return ident("_", out);
+        }
+    };
+    out.push_str(code);
+}
@@ -0,0 +1,278 @@
+// Modules a program imports. `import util;` names `util.z`, and
+// `import geometry.shapes;` names `geometry/shapes.z`, both in the directory
+// of the file being compiled, or in the source directories of its project.
+// `import name.module;` imports `module.z` of the package `name` a project
+// depends on, and `import name;` its `lib.z`. A module offers its importers
+// its `pub` functions, except those whose signatures use structs or enums,
+// and they call them by name. `import std.io;` imports a module of the
+// standard library, which ships with the compiler.
+//
+// Every imported module is compiled to an object file of its own in
+// `.zc-cache` next to the program, named by the hash of its C and of the
+// compiler building it. A rebuild finds the objects of unchanged modules
+// there and only compiles the modules that changed.
+
+use crate::ast::{ImportedFunction, Program, Stmt, Type};
+#[cfg(feature = "c")]
+use crate::codegen::{self, CCompiler, CodegenOptions};
+use crate::source_map::SourceMap;
+use crate::stdlib;
+use crate::typechecker::{self, CheckOptions};
+use crate::{diagnostic, lexer, module_name, parser, CompilerError, Result};
+#[cfg(feature = "c")]
+use crate::{optimizer, OptReport, PassManager};
+#[cfg(feature = "c")]
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs;
+#[cfg(feature = "c")]
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// An imported module, checked and ready for code generation
+pub struct Module {
+    /// The name it is imported by, e.g. `geometry.shapes`
+    pub name: String,
+    pub file: PathBuf,
+    pub source: String,
+    pub program: Program,
+}
+
+/// Where imported modules are looked for
+#[derive(Debug, Clone, Default)]
+pub struct ImportPaths {
+    /// Directories searched in order for modules
+    pub roots: Vec<PathBuf>,
+    /// Packages by name, each with the directory of its modules
+    pub packages: Vec<(String, PathBuf)>,
+}
+
+impl ImportPaths {
+    /// Modules of a single file, found from its directory
+    pub fn of_file(input: &Path) -> Self {
+        ImportPaths {
+            roots: vec![input.parent().map(Path::to_path_buf).unwrap_or_default()],
+            packages: Vec::new(),
+        }
+    }
+
+    pub(crate) fn package(&self, name: &str) -> Option<&Path> {
+        self.packages.iter().find(|(package, _)| package == name).map(|(_, dir)| dir.as_path())
+    }
+
+    /// The name of the module `import name;` imports in a module of
+    /// `package`, whose modules import its other modules by their names in it
+    pub(crate) fn qualify(&self, name: &str, package: Option<&str>) -> String {
+        let first = name.split('.').next().unwrap_or_default();
+        match package {
+            Some(package) if self.package(first).is_none() && !stdlib::contains(name) => format!("{}.{}", package, name),
+            _ => name.to_string(),
+        }
+    }
+
+    /// The package module `name` is in, if it is in one
+    pub(crate) fn package_of(&self, name: &str) -> Option<String> {
+        let first = name.split('.').next().unwrap_or_default();
+        self.package(first).map(|_| first.to_string())
+    }
+
+    /// The file of module `name`: the first of the roots having it, or the
+    /// first root's when none does
+    pub(crate) fn file_of(&self, name: &str) -> PathBuf {
+        let (package, module) = name.split_once('.').unwrap_or((name, ""));
+        if let Some(dir) = self.package(package) {
+            let module = if module.is_empty() { "lib" } else { module };
+            return dir.join(module.replace('.', "/")).with_extension("z");
+        }
+        let path = PathBuf::from(name.replace('.', "/")).with_extension("z");
+        self.roots
+            .iter()
+            .map(|root| root.join(&path))
+            .find(|file| file.exists())
+            .unwrap_or_else(|| self.roots.first().cloned().unwrap_or_default().join(&path))
+    }
+}
+
+/// Loads the modules the program in `input` imports, directly or through
+/// other modules, from `paths`, and records the functions of those it
+/// imports directly in `program.imports`. Each module comes after the
+/// modules it imports, and its file is added to `files`.
+pub fn load_imports(
+    program: &mut Program,
+    input: &Path,
+    paths: &ImportPaths,
+    options: &CheckOptions,
+    files: &mut SourceMap,
+) -> Result<Vec<Module>> {
+    let mut loader = Loader {
+        paths,
+        options,
+        files,
+        modules: Vec::new(),
+        loading: vec![module_name(input)],
+    };
+    program.imports = loader.imports_of(program, None)?;
+    Ok(loader.modules)
+}
+
+/// The directory of cached module objects for the program in `input`
+#[cfg(feature = "c")]
+pub fn cache_dir(input: &Path) -> PathBuf {
+    input.parent().map(Path::to_path_buf).unwrap_or_default().join(".zc-cache")
+}
+
+/// Compiles each module to an object file in `cache`, unless the object for
+/// its C is there already, and returns the objects to link. What the passes
+/// did to each module is added to `report`.
+#[cfg(feature = "c")]
+pub fn build_objects(
+    modules: Vec<Module>,
+    cache: &Path,
+    options: &CodegenOptions,
+    passes: &PassManager,
+    compiler: &CCompiler,
+    report: &mut OptReport,
+) -> Result<Vec<PathBuf>> {
+    let mut objects = Vec::new();
+    for module in modules {
+        let file = module.file.display().to_string();
+        let mut remarks = Vec::new();
+        let program = passes.run_program(module.program, &mut remarks);
+        report.add(&file, &module.source, remarks);
+        let code = codegen::generate_module_c(program, &module.name, &file, &module.source, options)
+            .map_err(|e| CompilerError::at(&file, &module.source, &[e]))?;
+        let mut code = optimizer::annotate(code, passes.opt_level())
+            .map_err(|e| CompilerError::CodegenError(format!("{}: {}", file, e.message)))?;
+        if let Some(runtime) = stdlib::runtime(&module.name) {
+            code.push_str(runtime);
+        }
+
+        let mut hasher = DefaultHasher::new();
+        code.hash(&mut hasher);
+        compiler.command.hash(&mut hasher);
+        compiler.flags.hash(&mut hasher);
+        compiler.native_cpu.hash(&mut hasher);
+        compiler.lto.hash(&mut hasher);
+        compiler.target.hash(&mut hasher);
+        compiler.debug_info.hash(&mut hasher);
+        compiler.backend.name().hash(&mut hasher);
+        (compiler.link.kind == codegen::LinkKind::Shared).hash(&mut hasher);
+        let object = cache.join(format!(
+            "{}-{:016x}.{}",
+            module.name.replace('.', "_"),
+            hasher.finish(),
+            compiler.backend.object_extension()
+        ));
+
+        if !object.exists() {
+            fs::create_dir_all(cache)?;
+            codegen::generate_object(&code, &object, compiler)
+                .map_err(|e| CompilerError::CodegenError(format!("{}: {}", file, e.message)))?;
+        }
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+struct Loader<'a> {
+    paths: &'a ImportPaths,
+    options: &'a CheckOptions,
+    files: &'a mut SourceMap,
+    modules: Vec<Module>,
+    // Modules being loaded, each imported by the one before, to find cycles
+    loading: Vec<String>,
+}
+
+impl Loader<'_> {
+    /// The functions of the modules `program` imports, loading them first.
+    /// Modules of `package` import its other modules by their names in it.
+    fn imports_of(&mut self, program: &Program, package: Option<&str>) -> Result<Vec<ImportedFunction>> {
+        let mut imports = Vec::new();
+        let mut seen = HashSet::new();
+        for stmt in &program.statements {
+            if let Stmt::Import(name, _) = stmt {
+                let name = self.paths.qualify(name, package);
+                // The typechecker provides std.math itself
+                if name != stdlib::MATH && seen.insert(name.clone()) {
+                    let index = self.load(&name)?;
+                    let module = &self.modules[index];
+                    imports.extend(interface(&module.name, &module.program));
+                }
+            }
+        }
+        Ok(imports)
+    }
+
+    /// Loads module `name` unless it has been, returning its index
+    fn load(&mut self, name: &str) -> Result<usize> {
+        if let Some(index) = self.modules.iter().position(|module| module.name == name) {
+            return Ok(index);
+        }
+        if let Some(start) = self.loading.iter().position(|loading| loading == name) {
+            let mut cycle = self.loading[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(CompilerError::TypeError(format!("Modules import each other: {}", cycle.join(" -> "))));
+        }
+
+        let (file, source) = match stdlib::load(name) {
+            Some((file, source)) => (file, source.to_string()),
+            None => {
+                let file = self.paths.file_of(name);
+                let source = fs::read_to_string(&file).map_err(|e| {
+                    CompilerError::TypeError(format!("Cannot import {}, since {} cannot be read: {}", name, file.display(), e))
+                })?;
+                (file, source)
+            }
+        };
+        let path = file.display().to_string();
+        let id = self.files.add(&path, &source);
+        let tokens = lexer::lex(&source).map_err(|e| self.files.errors(id, &[e]))?;
+        let mut ast = parser::parse_file(tokens, id).map_err(|e| self.files.errors(id, &[e]))?;
+
+        let package = self.paths.package_of(name);
+        self.loading.push(name.to_string());
+        ast.imports = self.imports_of(&ast, package.as_deref())?;
+        self.loading.pop();
+
+        let checked = typechecker::typecheck_with(ast, self.options)
+            .map_err(|errors| self.files.errors(id, &errors))?;
+        diagnostic::emit_warnings(&path, &source, &checked.warnings, self.options.deny_warnings)?;
+        self.modules.push(Module {
+            name: name.to_string(),
+            file,
+            source,
+            program: checked.program,
+        });
+        Ok(self.modules.len() - 1)
+    }
+}
+
+/// The functions the module `module`, whose checked program is `program`,
+/// offers its importers
+pub(crate) fn interface(module: &str, program: &Program) -> Vec<ImportedFunction> {
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Function(name, _, params, return_type, _, true, _, _) if name != "main" => Some(ImportedFunction {
+                module: module.to_string(),
+                name: name.clone(),
+                params: params.iter().map(|(_, ty)| ty.clone()).collect(),
+                return_type: return_type.clone(),
+            }),
+            _ => None,
+        })
+        .filter(|function| function.params.iter().chain([&function.return_type]).all(is_shared_type))
+        .collect()
+}
+
+/// Whether modules can pass values of `ty` to each other. Structs and enums
+/// are declared in the C of their own module only.
+fn is_shared_type(ty: &Type) -> bool {
+    match ty {
+        Type::Array(element_type) | Type::Optional(element_type) => is_shared_type(element_type),
+        Type::Function(params, ret) => params.iter().chain([ret.as_ref()]).all(is_shared_type),
+        Type::Float | Type::Bool | Type::String | Type::Void => true,
+        ty => ty.is_integer(),
+    }
+}
@@ -1,4 +1,13 @@
-// Optimizer for Z language that works with C code output
+// Optimizer for Z language. A `PassManager` runs the passes of an
+// optimization level, first on the checked program and then on its IR, and
+// `annotate` works on the C code output.
+
+use crate::ast::{Attribute, BinaryOp, Expr, MatchArm, Pattern, Program, Span, Stmt, Type};
+use crate::ir::{self, Const, Operand, Terminator};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct OptimizerError {
@@ -13,7 +22,8 @@ impl std::fmt::Display for OptimizerError {
 
 type Result<T> = std::result::Result<T, OptimizerError>;
 
-pub fn optimize(code: String, opt_level: u8) -> Result<String> {
+/// Heads C code with its optimization level and the hints the level defines
+pub fn annotate(code: String, opt_level: u8) -> Result<String> {
     // Since we're generating C code now, we'll add optimization directives
     // and compiler hints based on the optimization level
     
@@ -43,7 +53,7 @@ pub fn optimize(code: String, opt_level: u8) -> Result<String> {
             optimized_code.push_str("#define likely(x)   __builtin_expect(!!(x), 1)\n");
             optimized_code.push_str("#define unlikely(x) __builtin_expect(!!(x), 0)\n");
         },
-        3 | _ => {
+        _ => {
             optimized_code.push_str("// - Maximum optimizations\n");
             optimized_code.push_str("// - Aggressive inlining\n");
             optimized_code.push_str("// - SIMD vectorization\n");
@@ -69,8 +79,1309 @@ pub fn optimize(code: String, opt_level: u8) -> Result<String> {
         },
     }
     
-    optimized_code.push_str("\n");
+    optimized_code.push('\n');
     optimized_code.push_str(&code);
     
     Ok(optimized_code)
-}
\ No newline at end of file
+}
+
+/// An optimization pass, run from optimization level `level` on
+struct BuiltinPass {
+    name: &'static str,
+    level: u8,
+    run: Run,
+    /// What the pass counts in reports, for one change and for several
+    changes: (&'static str, &'static str),
+}
+
+/// The level a pass runs at in a function with some attributes, if it runs
+/// there
+type LevelOf<'a> = dyn Fn(&[Attribute]) -> Option<u8> + 'a;
+
+enum Run {
+    /// Rewrites the checked program, given the levels it runs at, recording
+    /// each change
+    Program(fn(Program, &LevelOf<'_>, &mut Vec<Remark>) -> Program),
+    /// Rewrites each function of the IR, given the level it runs at, and
+    /// gives where each change was made
+    Function(fn(&mut ir::Function, u8) -> Vec<Span>),
+    /// Rewrites each function of the IR like `Function`, given also the
+    /// symbols of the pure functions of the module
+    WithPurity(fn(&mut ir::Function, u8, &HashSet<String>) -> Vec<Span>),
+}
+
+/// Something a pass did in a function
+#[derive(Debug, Clone)]
+pub struct Remark {
+    pub pass: &'static str,
+    pub function: String,
+    /// The code changed, or the function for registered passes on the IR
+    pub span: Span,
+    /// How many changes the remark stands for
+    pub count: usize,
+    pub message: String,
+}
+
+/// `count` changes of the pass named `pass`, as in `3 calls inlined`
+pub fn describe_changes(pass: &str, count: usize) -> String {
+    let (one, many) = PASSES.iter().find(|p| p.name == pass).map_or(("change", "changes"), |p| p.changes);
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
+
+/// The names of the passes, in the order they run
+pub fn pass_names() -> impl Iterator<Item = &'static str> {
+    PASSES.iter().map(|pass| pass.name)
+}
+
+/// Every pass, in the order they run. A pass runs at its level and above,
+/// unless `--passes` turns it on or off.
+const PASSES: &[BuiltinPass] = &[
+    BuiltinPass {
+        name: "inline",
+        level: 1,
+        run: Run::Program(inline_functions),
+        changes: ("call inlined", "calls inlined"),
+    },
+    BuiltinPass {
+        name: "fold-branches",
+        level: 1,
+        run: Run::Function(|function, _| fold_branches(function)),
+        changes: ("branch folded", "branches folded"),
+    },
+    BuiltinPass {
+        name: "skip-empty",
+        level: 1,
+        run: Run::Function(|function, _| skip_empty_blocks(function)),
+        changes: ("jump shortened", "jumps shortened"),
+    },
+    BuiltinPass {
+        name: "merge-blocks",
+        level: 1,
+        run: Run::Function(|function, _| merge_blocks(function)),
+        changes: ("block merged", "blocks merged"),
+    },
+    BuiltinPass {
+        name: "strength-reduce",
+        level: 1,
+        run: Run::Function(|function, _| reduce_strength(function)),
+        changes: ("operation reduced", "operations reduced"),
+    },
+    BuiltinPass {
+        name: "unroll",
+        level: 2,
+        run: Run::Function(unroll_loops),
+        changes: ("loop unrolled", "loops unrolled"),
+    },
+    BuiltinPass {
+        name: "cse",
+        level: 1,
+        run: Run::WithPurity(|function, _, pure| eliminate_common_subexpressions(function, pure)),
+        changes: ("value reused", "values reused"),
+    },
+    // Removing the blocks after a `return` keeps the C readable, so even
+    // level 0 does it
+    BuiltinPass {
+        name: "dce",
+        level: 0,
+        run: Run::Function(|function, _| remove_unreachable(function)),
+        changes: ("instruction removed", "instructions removed"),
+    },
+];
+
+/// What a pass of `Pass` runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassStage {
+    /// The checked program, before it is lowered
+    Ast,
+    /// Each function of the IR
+    Ir,
+}
+
+/// A pass of a crate using the compiler, for analyses and rewrites the
+/// built-in passes do not make. Registered passes run after the built-in
+/// passes of their stage, in the order they were registered, and are turned
+/// on and off by name like them.
+pub trait Pass: Send + Sync {
+    /// The name of the pass in reports and `--passes`
+    fn name(&self) -> &'static str;
+
+    fn stage(&self) -> PassStage;
+
+    /// The optimization level the pass runs from; 1 unless implemented
+    fn level(&self) -> u8 {
+        1
+    }
+
+    /// Runs on the checked program, adding what it did to `remarks`, for
+    /// passes of `PassStage::Ast`
+    fn run_ast(&self, _program: &mut Program, _remarks: &mut Vec<Remark>) {}
+
+    /// Runs on a function of the IR and gives the number of changes, for
+    /// passes of `PassStage::Ir`
+    fn run_ir(&self, _function: &mut ir::Function) -> usize {
+        0
+    }
+}
+
+impl fmt::Debug for dyn Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pass({})", self.name())
+    }
+}
+
+/// The passes a build runs: those of its optimization level, or of the
+/// level a function has of its own, with the ones turned on or off by name
+#[derive(Debug, Clone)]
+pub struct PassManager {
+    opt_level: u8,
+    /// The passes registered, after the built-in ones
+    custom: Vec<Arc<dyn Pass>>,
+    /// Whether each pass, built-in then registered, was turned on or off,
+    /// rather than left to the level
+    toggles: Vec<Option<bool>>,
+}
+
+impl PassManager {
+    /// The passes of optimization level `opt_level`
+    pub fn new(opt_level: u8) -> Self {
+        PassManager {
+            opt_level,
+            custom: Vec::new(),
+            toggles: vec![None; PASSES.len()],
+        }
+    }
+
+    /// Adds `pass` to run after the built-in passes of its stage and those
+    /// registered before it
+    pub fn register(&mut self, pass: Arc<dyn Pass>) -> &mut Self {
+        self.custom.push(pass);
+        self.toggles.push(None);
+        self
+    }
+
+    /// The names of the passes, built-in then registered
+    fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        pass_names().chain(self.custom.iter().map(|pass| pass.name()))
+    }
+
+    pub fn opt_level(&self) -> u8 {
+        self.opt_level
+    }
+
+    /// Whether passes were registered to run on the IR, which only the C
+    /// backend lowers programs to
+    pub fn has_ir_passes(&self) -> bool {
+        self.custom.iter().any(|pass| pass.stage() == PassStage::Ir)
+    }
+
+    /// Turns passes on and off by a comma-separated list such as
+    /// `+inline,-dce`, where `+` runs a pass and `-` leaves it out
+    pub fn configure(&mut self, toggles: &str) -> std::result::Result<(), String> {
+        for toggle in toggles.split(',').map(str::trim).filter(|toggle| !toggle.is_empty()) {
+            let (enable, name) = match toggle.split_at(1) {
+                ("+", name) => (true, name),
+                ("-", name) => (false, name),
+                _ => return Err(format!("expected +pass or -pass, found {}", toggle)),
+            };
+            let index = self.names().position(|pass| pass == name).ok_or_else(|| {
+                let names: Vec<&str> = self.names().collect();
+                format!("unknown pass {}, expected one of {}", name, names.join(", "))
+            })?;
+            self.toggles[index] = Some(enable);
+        }
+        Ok(())
+    }
+
+    /// The level a function with `attributes` is optimized at: its
+    /// `@optimize` level, at most 1 when it is `@cold`, or the build's
+    fn function_level(&self, attributes: &[Attribute]) -> u8 {
+        match Attribute::opt_level(attributes) {
+            Some(level) => level,
+            None if attributes.contains(&Attribute::Cold) => self.opt_level.min(1),
+            None => self.opt_level,
+        }
+    }
+
+    /// The level the pass `PASSES[index]` runs at in a function optimized at
+    /// `level`, if it runs there. Passes turned on below their level run as
+    /// they would at their level.
+    fn pass_level(&self, index: usize, level: u8) -> Option<u8> {
+        let pass = &PASSES[index];
+        self.toggles[index].unwrap_or(pass.level <= level).then(|| level.max(pass.level))
+    }
+
+    /// The registered passes of `stage`, each with whether it runs at
+    /// `level`
+    fn custom_passes(&self, stage: PassStage, level: u8) -> impl Iterator<Item = (&dyn Pass, bool)> + '_ {
+        self.custom
+            .iter()
+            .zip(&self.toggles[PASSES.len()..])
+            .filter(move |(pass, _)| pass.stage() == stage)
+            .map(move |(pass, toggle)| (pass.as_ref(), toggle.unwrap_or(pass.level() <= level)))
+    }
+
+    /// Runs the passes on the checked program, adding what they did to
+    /// `remarks`
+    pub fn run_program(&self, mut program: Program, remarks: &mut Vec<Remark>) -> Program {
+        for (index, pass) in PASSES.iter().enumerate() {
+            if let Run::Program(run) = pass.run {
+                let level_of = |attributes: &[Attribute]| self.pass_level(index, self.function_level(attributes));
+                program = run(program, &level_of, remarks);
+            }
+        }
+        for (pass, runs) in self.custom_passes(PassStage::Ast, self.opt_level) {
+            if runs {
+                pass.run_ast(&mut program, remarks);
+            }
+        }
+        program
+    }
+
+    /// Runs the passes on each function of an IR module, adding what they
+    /// did to `remarks`
+    pub fn run_ir(&self, mut module: ir::Module, remarks: &mut Vec<Remark>) -> ir::Module {
+        // What the passes do keeps pure functions pure
+        module.infer_purity();
+        let pure: HashSet<String> = module
+            .functions
+            .iter()
+            .filter(|function| function.pure)
+            .map(|function| function.symbol.clone())
+            .collect();
+        for function in &mut module.functions {
+            let function_level = self.function_level(&function.attributes);
+            for (index, pass) in PASSES.iter().enumerate() {
+                let changes = match (&pass.run, self.pass_level(index, function_level)) {
+                    (Run::Function(run), Some(level)) => run(function, level),
+                    (Run::WithPurity(run), Some(level)) => run(function, level, &pure),
+                    _ => continue,
+                };
+                remark_on(function, pass.name, changes, remarks);
+            }
+            for (pass, runs) in self.custom_passes(PassStage::Ir, function_level) {
+                if runs {
+                    let count = pass.run_ir(function);
+                    remark_on(function, pass.name(), vec![function.span.clone(); count], remarks);
+                }
+            }
+        }
+        // The passes may have removed what kept a function impure
+        module.infer_purity();
+        module
+    }
+}
+
+/// Records the changes of the pass `pass` to `function`, made at `changes`,
+/// with a remark for each place changed, in the order of the source
+fn remark_on(function: &ir::Function, pass: &'static str, changes: Vec<Span>, remarks: &mut Vec<Remark>) {
+    let mut places: Vec<(Span, usize)> = Vec::new();
+    for span in changes {
+        match places.iter_mut().find(|(place, _)| place.start == span.start) {
+            Some((_, count)) => *count += 1,
+            None => places.push((span, 1)),
+        }
+    }
+    places.sort_by_key(|(span, _)| span.start);
+    remarks.extend(places.into_iter().map(|(span, count)| Remark {
+        pass,
+        function: function.name.clone(),
+        span,
+        count,
+        message: describe_changes(pass, count),
+    }));
+}
+
+/// Turns branches whose condition is known, or whose targets are the same,
+/// into jumps
+fn fold_branches(function: &mut ir::Function) -> Vec<Span> {
+    let mut folded = Vec::new();
+    for block in &mut function.blocks {
+        block.terminator = match std::mem::replace(&mut block.terminator, Terminator::Unreachable) {
+            Terminator::Branch(Operand::Const(Const::Bool(cond)), then_block, else_block) => {
+                folded.push(block.terminator_span.clone());
+                Terminator::Jump(if cond { then_block } else { else_block })
+            }
+            Terminator::Branch(_, then_block, else_block) if then_block == else_block => {
+                folded.push(block.terminator_span.clone());
+                Terminator::Jump(then_block)
+            }
+            terminator => terminator,
+        };
+    }
+    folded
+}
+
+/// Points jumps and branches to blocks that only jump on at where those
+/// jump to
+fn skip_empty_blocks(function: &mut ir::Function) -> Vec<Span> {
+    let forward: Vec<Option<ir::BlockId>> = function
+        .blocks
+        .iter()
+        .map(|block| match block.terminator {
+            Terminator::Jump(target) if block.instructions.is_empty() => Some(target),
+            _ => None,
+        })
+        .collect();
+    // Following at most as many jumps as there are blocks ends even in
+    // loops of empty blocks
+    let destination = |mut target: ir::BlockId| {
+        for _ in 0..forward.len() {
+            match forward[target] {
+                Some(next) if next != target => target = next,
+                _ => break,
+            }
+        }
+        target
+    };
+    let mut shortened = Vec::new();
+    for block in &mut function.blocks {
+        let before = block.terminator.clone();
+        block.terminator.retarget(destination);
+        if block.terminator != before {
+            shortened.push(block.terminator_span.clone());
+        }
+    }
+    shortened
+}
+
+/// Appends each block jumped to from only one other block to that block.
+/// The merged blocks are left unreached.
+fn merge_blocks(function: &mut ir::Function) -> Vec<Span> {
+    let mut merges = Vec::new();
+    let mut predecessors = function.predecessors();
+    for id in 0..function.blocks.len() {
+        while let Terminator::Jump(target) = function.blocks[id].terminator {
+            if target == id || target == 0 || predecessors[target] != 1 {
+                break;
+            }
+            let merged = std::mem::replace(
+                &mut function.blocks[target],
+                ir::Block {
+                    instructions: Vec::new(),
+                    spans: Vec::new(),
+                    terminator: Terminator::Unreachable,
+                    terminator_span: Span::default(),
+                },
+            );
+            predecessors[target] = 0;
+            // Where the jump merged away comes from
+            let block = &mut function.blocks[id];
+            merges.push(std::mem::replace(&mut block.terminator_span, merged.terminator_span));
+            block.instructions.extend(merged.instructions);
+            block.spans.extend(merged.spans);
+            block.terminator = merged.terminator;
+        }
+    }
+    merges
+}
+
+/// Turns multiplying and dividing integers by powers of two into shifts, and
+/// the remainder of such a division into a mask. A negative dividend is
+/// raised by the divisor less one before shifting, so that signed division
+/// still rounds towards zero, and signed remainders are only masked when
+/// they are just compared with zero, since those of negative dividends are
+/// negative in C. Checked multiplications shift with the same check.
+fn reduce_strength(function: &mut ir::Function) -> Vec<Span> {
+    let mut reduced = Vec::new();
+    for id in 0..function.blocks.len() {
+        // Temporaries are only used in the block computing them
+        let mut uses = HashMap::new();
+        let block = &function.blocks[id];
+        let terminator_operand = match &block.terminator {
+            Terminator::Branch(cond, _, _) | Terminator::Return(Some(cond)) => Some(cond),
+            _ => None,
+        };
+        for operand in block.instructions.iter().flat_map(ir::Instruction::operands).chain(terminator_operand) {
+            if let Operand::Temp(temp) = operand {
+                *uses.entry(*temp).or_insert(0) += 1;
+            }
+        }
+        let zero_tested: HashSet<ir::Temp> = block
+            .instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                ir::Instruction::Binary {
+                    op: BinaryOp::Eq | BinaryOp::Neq,
+                    left: Operand::Temp(temp),
+                    right: Operand::Const(Const::Int(0)),
+                    ..
+                }
+                | ir::Instruction::Binary {
+                    op: BinaryOp::Eq | BinaryOp::Neq,
+                    left: Operand::Const(Const::Int(0)),
+                    right: Operand::Temp(temp),
+                    ..
+                } if uses[temp] == 1 => Some(*temp),
+                _ => None,
+            })
+            .collect();
+
+        let instructions = std::mem::take(&mut function.blocks[id].instructions);
+        let spans = std::mem::take(&mut function.blocks[id].spans);
+        let mut rewritten = Vec::with_capacity(instructions.len());
+        let mut rewritten_spans = Vec::with_capacity(spans.len());
+        for (instruction, span) in instructions.into_iter().zip(spans) {
+            match reduced_instructions(function, &instruction, &zero_tested) {
+                Some(replacement) => {
+                    rewritten_spans.extend(std::iter::repeat(span.clone()).take(replacement.len()));
+                    rewritten.extend(replacement);
+                    reduced.push(span);
+                }
+                None => {
+                    rewritten.push(instruction);
+                    rewritten_spans.push(span);
+                }
+            }
+        }
+        function.blocks[id].instructions = rewritten;
+        function.blocks[id].spans = rewritten_spans;
+    }
+    reduced
+}
+
+/// Cheaper instructions computing what `instruction` does, if it is a
+/// multiplication, or an unchecked division or remainder, by a power of
+/// two. Signed remainders must be in `zero_tested`.
+fn reduced_instructions(
+    function: &mut ir::Function,
+    instruction: &ir::Instruction,
+    zero_tested: &HashSet<ir::Temp>,
+) -> Option<Vec<ir::Instruction>> {
+    let ir::Instruction::Binary {
+        dest,
+        op,
+        left,
+        right,
+        overflow,
+    } = instruction
+    else {
+        return None;
+    };
+    if overflow.is_some() && *op != BinaryOp::Mul {
+        return None;
+    }
+    let ty = function.temps[*dest].clone();
+    let bits = c_bits(&ty)?;
+    // Powers small enough to shift by in the type, sign bit aside
+    let power = |operand: &Operand| match operand {
+        Operand::Const(Const::Int(value)) if *value > 1 && value.count_ones() == 1 => {
+            Some(value.trailing_zeros() as i64).filter(|shift| *shift < bits - 1)
+        }
+        _ => None,
+    };
+    let bitwise = |dest, op, left: Operand, right| ir::Instruction::Bitwise {
+        dest,
+        op,
+        left,
+        right: Operand::Const(Const::Int(right)),
+        overflow: None,
+    };
+    let signed = !ty.is_unsigned();
+    match op {
+        BinaryOp::Mul => {
+            let (value, shift) = match (power(left), power(right)) {
+                (_, Some(shift)) => (left, shift),
+                (Some(shift), None) => (right, shift),
+                (None, None) => return None,
+            };
+            Some(vec![ir::Instruction::Bitwise {
+                dest: *dest,
+                op: ir::BitOp::Shl,
+                left: value.clone(),
+                right: Operand::Const(Const::Int(shift)),
+                overflow: overflow.clone(),
+            }])
+        }
+        BinaryOp::Div if !signed => Some(vec![bitwise(*dest, ir::BitOp::Shr, left.clone(), power(right)?)]),
+        BinaryOp::Div => {
+            let shift = power(right)?;
+            // All ones for negative dividends, masked to the divisor less one
+            let mut temp = || {
+                function.temps.push(ty.clone());
+                function.temps.len() - 1
+            };
+            let (sign, bias, sum) = (temp(), temp(), temp());
+            Some(vec![
+                bitwise(sign, ir::BitOp::Shr, left.clone(), bits - 1),
+                bitwise(bias, ir::BitOp::And, Operand::Temp(sign), (1 << shift) - 1),
+                ir::Instruction::Binary {
+                    dest: sum,
+                    op: BinaryOp::Add,
+                    left: left.clone(),
+                    right: Operand::Temp(bias),
+                    overflow: None,
+                },
+                bitwise(*dest, ir::BitOp::Shr, Operand::Temp(sum), shift),
+            ])
+        }
+        BinaryOp::Mod if !signed || zero_tested.contains(dest) => {
+            Some(vec![bitwise(*dest, ir::BitOp::And, left.clone(), (1 << power(right)?) - 1)])
+        }
+        _ => None,
+    }
+}
+
+/// Computes each value once in a block: an instruction computing what one
+/// before it did, from the same operands with no store to their locals in
+/// between, is left out for the temporary of the first. Calls of the
+/// functions in `pure` are values too, and checked arithmetic already
+/// panicked the first time if it does.
+fn eliminate_common_subexpressions(function: &mut ir::Function, pure: &HashSet<String>) -> Vec<Span> {
+    let mut reused = Vec::new();
+    for id in 0..function.blocks.len() {
+        // Temporaries are only used in the block computing them
+        let mut replaced: HashMap<ir::Temp, ir::Temp> = HashMap::new();
+        let mut available: Vec<ir::Instruction> = Vec::new();
+        let instructions = std::mem::take(&mut function.blocks[id].instructions);
+        let spans = std::mem::take(&mut function.blocks[id].spans);
+        let mut kept = Vec::with_capacity(instructions.len());
+        let mut kept_spans = Vec::with_capacity(spans.len());
+        for (mut instruction, span) in instructions.into_iter().zip(spans) {
+            for operand in instruction.operands_mut() {
+                if let Operand::Temp(temp) = operand {
+                    *temp = replaced.get(temp).copied().unwrap_or(*temp);
+                }
+            }
+            if let ir::Instruction::Store { local, .. } = instruction {
+                available.retain(|earlier| !earlier.operands().contains(&&Operand::Local(local)));
+            }
+            if let (true, Some(dest)) = (is_value(&instruction, pure), instruction.dest()) {
+                if let Some(first) = available.iter().find(|earlier| same_value(function, earlier, &instruction)) {
+                    replaced.insert(dest, first.dest().unwrap_or(dest));
+                    reused.push(span);
+                    continue;
+                }
+                available.push(instruction.clone());
+            }
+            kept.push(instruction);
+            kept_spans.push(span);
+        }
+        let block = &mut function.blocks[id];
+        if let Some(Operand::Temp(temp)) = block.terminator.operand_mut() {
+            *temp = replaced.get(temp).copied().unwrap_or(*temp);
+        }
+        block.instructions = kept;
+        block.spans = kept_spans;
+    }
+    reused
+}
+
+/// Whether `instruction` only computes a value from its operands
+fn is_value(instruction: &ir::Instruction, pure: &HashSet<String>) -> bool {
+    match instruction {
+        ir::Instruction::Binary { .. }
+        | ir::Instruction::Unary { .. }
+        | ir::Instruction::Bitwise { .. }
+        | ir::Instruction::Cast { .. } => true,
+        ir::Instruction::Call { symbol, .. } => pure.contains(symbol),
+        _ => false,
+    }
+}
+
+/// Whether the values `first` and `second` compute are the same, checked
+/// alike
+fn same_value(function: &ir::Function, first: &ir::Instruction, second: &ir::Instruction) -> bool {
+    use ir::Instruction::{Binary, Bitwise, Call, Cast, Unary};
+    let same_operation = match (first, second) {
+        (Binary { op, overflow, .. }, Binary { op: other, overflow: other_overflow, .. }) => {
+            op == other && overflow.is_some() == other_overflow.is_some()
+        }
+        (Bitwise { op, overflow, .. }, Bitwise { op: other, overflow: other_overflow, .. }) => {
+            op == other && overflow.is_some() == other_overflow.is_some()
+        }
+        (Unary { op, .. }, Unary { op: other, .. }) => op == other,
+        (Cast { .. }, Cast { .. }) => true,
+        (Call { symbol, .. }, Call { symbol: other, .. }) => symbol == other,
+        _ => false,
+    };
+    let ty = |instruction: &ir::Instruction| instruction.dest().map(|dest| &function.temps[dest]);
+    same_operation && first.operands() == second.operands() && ty(first) == ty(second)
+}
+
+/// The width of the C type of integers of type `ty`
+fn c_bits(ty: &Type) -> Option<i64> {
+    match ty {
+        Type::I8 | Type::U8 => Some(8),
+        Type::I16 | Type::U16 => Some(16),
+        Type::I32 | Type::U32 => Some(32),
+        Type::Int | Type::I64 | Type::U64 => Some(64),
+        _ => None,
+    }
+}
+
+/// A `while` loop whose condition is a single comparison and whose body is
+/// a single block
+struct SimpleLoop {
+    /// The block before the loop, which jumps to its header
+    preheader: ir::BlockId,
+    header: ir::BlockId,
+    body: ir::BlockId,
+    exit: ir::BlockId,
+}
+
+impl SimpleLoop {
+    fn at(function: &ir::Function, header: ir::BlockId, predecessors: &[Vec<ir::BlockId>]) -> Option<SimpleLoop> {
+        let (body, exit) = match (&function.blocks[header].instructions[..], &function.blocks[header].terminator) {
+            ([ir::Instruction::Binary { dest, .. }], Terminator::Branch(Operand::Temp(cond), body, exit))
+                if dest == cond =>
+            {
+                (*body, *exit)
+            }
+            _ => return None,
+        };
+        let preheader = match predecessors[header][..] {
+            [first, second] if first == body => second,
+            [first, second] if second == body => first,
+            _ => return None,
+        };
+        let distinct = header != 0 && body != header && exit != header && body != exit && preheader != exit;
+        let simple = function.blocks[body].terminator == Terminator::Jump(header)
+            && function.blocks[preheader].terminator == Terminator::Jump(header)
+            && predecessors[body] == [header];
+        (distinct && simple).then_some(SimpleLoop {
+            preheader,
+            header,
+            body,
+            exit,
+        })
+    }
+
+    /// How many times the body runs, when the condition compares a counter
+    /// with a constant, the counter starts at a constant and the body steps it
+    /// by one, up to `max` times
+    fn trip_count(&self, function: &ir::Function, max: usize) -> Option<usize> {
+        let ir::Instruction::Binary { op, left, right, .. } = &function.blocks[self.header].instructions[0] else {
+            return None;
+        };
+        let (counter, op, bound) = match (left, right) {
+            (Operand::Local(counter), Operand::Const(Const::Int(bound))) => (*counter, op.clone(), *bound),
+            (Operand::Const(Const::Int(bound)), Operand::Local(counter)) => (*counter, mirrored(op)?, *bound),
+            _ => return None,
+        };
+        let (min_value, max_value) = function.locals[counter].1.integer_range()?;
+
+        let stores = |block: ir::BlockId| {
+            function.blocks[block].instructions.iter().filter_map(move |instruction| match instruction {
+                ir::Instruction::Store { local, value } if *local == counter => Some(value),
+                _ => None,
+            })
+        };
+        let Some(Operand::Const(Const::Int(start))) = stores(self.preheader).next_back() else {
+            return None;
+        };
+        let [Operand::Temp(next)] = stores(self.body).collect::<Vec<_>>()[..] else {
+            return None;
+        };
+        let step = function.blocks[self.body].instructions.iter().find_map(|instruction| match instruction {
+            ir::Instruction::Binary {
+                dest,
+                op: step_op @ (BinaryOp::Add | BinaryOp::Sub),
+                left,
+                right,
+                ..
+            } if dest == next => match (step_op, left, right) {
+                (BinaryOp::Add, Operand::Local(local), Operand::Const(Const::Int(step)))
+                | (BinaryOp::Add, Operand::Const(Const::Int(step)), Operand::Local(local)) => {
+                    (*local == counter).then_some(*step as i128)
+                }
+                (BinaryOp::Sub, Operand::Local(local), Operand::Const(Const::Int(step))) => {
+                    (*local == counter).then_some(-(*step as i128))
+                }
+                _ => None,
+            },
+            _ => None,
+        })?;
+
+        let holds = |value: i128| match op {
+            BinaryOp::Lt => value < bound as i128,
+            BinaryOp::Lte => value <= bound as i128,
+            BinaryOp::Gt => value > bound as i128,
+            BinaryOp::Gte => value >= bound as i128,
+            BinaryOp::Neq => value != bound as i128,
+            _ => false,
+        };
+        let mut value = *start as i128;
+        let mut trips = 0;
+        while holds(value) {
+            trips += 1;
+            value += step;
+            // A counter leaving its type would overflow
+            if trips > max || value < min_value || value > max_value {
+                return None;
+            }
+        }
+        Some(trips)
+    }
+}
+
+/// The comparison `op` with its operands swapped
+fn mirrored(op: &BinaryOp) -> Option<BinaryOp> {
+    match op {
+        BinaryOp::Lt => Some(BinaryOp::Gt),
+        BinaryOp::Lte => Some(BinaryOp::Gte),
+        BinaryOp::Gt => Some(BinaryOp::Lt),
+        BinaryOp::Gte => Some(BinaryOp::Lte),
+        BinaryOp::Neq => Some(BinaryOp::Neq),
+        _ => None,
+    }
+}
+
+/// Unrolls loops whose condition is a single comparison and whose body is a
+/// single block. A loop counting a few times from one constant to another
+/// is replaced by copies of its body: up to 8 runs of at most 64
+/// instructions at level 2, and up to 16 runs of at most 128 at level 3.
+/// Level 3 also unrolls other small loops 4 times, testing the condition
+/// between the copies.
+fn unroll_loops(function: &mut ir::Function, opt_level: u8) -> Vec<Span> {
+    let mut unrolled = Vec::new();
+    let (max_trips, max_size, factor) = match opt_level {
+        0 | 1 => return unrolled,
+        2 => (8, 64, 1),
+        _ => (16, 128, 4),
+    };
+    for header in 0..function.blocks.len() {
+        let predecessors = function.predecessor_lists();
+        let Some(simple_loop) = SimpleLoop::at(function, header, &predecessors) else {
+            continue;
+        };
+        // Where the loop is, which its condition's branch comes from
+        let span = function.blocks[header].terminator_span.clone();
+        let body = function.blocks[simple_loop.body].instructions.clone();
+        let body_spans = function.blocks[simple_loop.body].spans.clone();
+        if let Some(trips) = simple_loop.trip_count(function, max_trips) {
+            if trips * body.len() <= max_size {
+                for _ in 0..trips {
+                    let copy = function.copy_instructions(&body);
+                    let preheader = &mut function.blocks[simple_loop.preheader];
+                    preheader.instructions.extend(copy);
+                    preheader.spans.extend(body_spans.iter().cloned());
+                }
+                function.blocks[simple_loop.preheader].terminator = Terminator::Jump(simple_loop.exit);
+                unrolled.push(span);
+                continue;
+            }
+        }
+        if factor == 1 || body.len() * factor > max_size / 4 {
+            continue;
+        }
+
+        // The body, then a test of the condition and another copy, until
+        // the last copy goes back to the header
+        let condition = function.blocks[header].instructions.clone();
+        let condition_spans = function.blocks[header].spans.clone();
+        let body_end = function.blocks[simple_loop.body].terminator_span.clone();
+        let mut last = simple_loop.body;
+        for _ in 1..factor {
+            let mut test = function.copy_instructions(&condition);
+            let cond = test[0].dest().map(Operand::Temp);
+            function.blocks[last].instructions.append(&mut test);
+            function.blocks[last].spans.extend(condition_spans.iter().cloned());
+            let instructions = function.copy_instructions(&body);
+            function.blocks.push(ir::Block {
+                instructions,
+                spans: body_spans.clone(),
+                terminator: Terminator::Jump(header),
+                terminator_span: body_end.clone(),
+            });
+            let copy = function.blocks.len() - 1;
+            if let Some(cond) = cond {
+                function.blocks[last].terminator = Terminator::Branch(cond, copy, simple_loop.exit);
+                function.blocks[last].terminator_span = span.clone();
+            }
+            last = copy;
+        }
+        unrolled.push(span);
+    }
+    unrolled
+}
+
+/// Removes the blocks no path from the entry reaches, numbering the rest in
+/// their order, and gives where each instruction removed with them came from
+fn remove_unreachable(function: &mut ir::Function) -> Vec<Span> {
+    let mut reached = vec![false; function.blocks.len()];
+    let mut pending = vec![0];
+    while let Some(id) = pending.pop() {
+        if !std::mem::replace(&mut reached[id], true) {
+            pending.extend(function.blocks[id].terminator.successors());
+        }
+    }
+
+    let mut numbers = Vec::with_capacity(reached.len());
+    let mut count = 0;
+    for &reached in &reached {
+        numbers.push(count);
+        count += reached as usize;
+    }
+    let removed = function
+        .blocks
+        .iter()
+        .zip(&reached)
+        .filter(|(_, reached)| !**reached)
+        .flat_map(|(block, _)| block.spans.iter().cloned())
+        .collect();
+    let blocks = std::mem::take(&mut function.blocks);
+    function.blocks = blocks
+        .into_iter()
+        .zip(&reached)
+        .filter(|(_, reached)| **reached)
+        .map(|(mut block, _)| {
+            block.terminator.retarget(|target| numbers[target]);
+            block
+        })
+        .collect();
+    removed
+}
+
+/// How many calls deep inlined bodies are inlined into in turn, which also
+/// bounds functions inlining each other
+const MAX_INLINE_DEPTH: usize = 4;
+
+/// Replaces calls to small functions with their bodies. A function is
+/// inlined when its body is a single `return` of an expression, or a single
+/// expression for a function returning nothing, it does not call itself, and
+/// the expression has at most as many nodes as the level of the calling
+/// function allows, or it is `@inline`. `@noinline` and `@cold` functions
+/// are not inlined, and functions at level 0 inline nothing. `level_of`
+/// gives the level of a function by its attributes, if the pass runs there.
+/// Each call inlined is added to `remarks`.
+pub fn inline_functions(
+    mut program: Program,
+    level_of: &LevelOf<'_>,
+    remarks: &mut Vec<Remark>,
+) -> Program {
+    let candidates: HashMap<String, Candidate> = program.statements.iter().filter_map(Candidate::of).collect();
+    if candidates.is_empty() {
+        return program;
+    }
+
+    // Statements outside functions make up `main` along with its body
+    let mut main_names = HashSet::new();
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::Function(name, ..) if name != "main" => {}
+            stmt => declared_names(stmt, &mut main_names),
+        }
+    }
+    program.statements = program
+        .statements
+        .into_iter()
+        .map(|stmt| {
+            // `main` has no attributes
+            let (function, attributes) = match &stmt {
+                Stmt::Function(name, _, _, _, _, _, attributes, _) if name != "main" => (name.clone(), &attributes[..]),
+                _ => ("main".to_string(), &[][..]),
+            };
+            let max_size = match level_of(attributes) {
+                None | Some(0) => return stmt,
+                Some(1) => 8,
+                Some(2) => 16,
+                Some(_) => 32,
+            };
+            let inliner = Inliner {
+                candidates: &candidates,
+                max_size,
+                inlined: RefCell::new(Vec::new()),
+            };
+            let stmt = if function == "main" {
+                inliner.stmt(stmt, &main_names, 0)
+            } else {
+                let mut names = HashSet::new();
+                declared_names(&stmt, &mut names);
+                inliner.stmt(stmt, &names, 0)
+            };
+            remarks.extend(inliner.inlined.into_inner().into_iter().map(|(callee, span)| Remark {
+                pass: "inline",
+                function: function.clone(),
+                span,
+                count: 1,
+                message: format!("inlined call to {}", callee),
+            }));
+            stmt
+        })
+        .collect();
+    program
+}
+
+/// A function whose calls can be replaced with its body
+struct Candidate {
+    /// The number of nodes in the body
+    size: usize,
+    /// Whether the function is `@inline`, whatever its size
+    always: bool,
+    params: Vec<(String, Type)>,
+    return_type: Type,
+    body: Expr,
+    /// How many times the body uses each parameter
+    uses: HashMap<String, usize>,
+    /// Identifiers in the body other than the parameters, such as the
+    /// functions it calls, which callers must not declare themselves
+    free: HashSet<String>,
+    /// Whether the body calls functions, which could change what the
+    /// arguments evaluate to if they were evaluated later
+    calls: bool,
+}
+
+impl Candidate {
+    fn of(stmt: &Stmt) -> Option<(String, Candidate)> {
+        let Stmt::Function(name, type_params, params, return_type, body, _, attributes, _) = stmt else {
+            return None;
+        };
+        let always = attributes.contains(&Attribute::Inline);
+        let cold = attributes.contains(&Attribute::Cold) && !always;
+        if name == "main" || !type_params.is_empty() || attributes.contains(&Attribute::NoInline) || cold {
+            return None;
+        }
+        // Optionals are the one type values convert to implicitly, which the
+        // body would no longer do
+        if params.iter().map(|(_, ty)| ty).chain([return_type]).any(|ty| !is_exact_type(ty)) {
+            return None;
+        }
+        let body = match body.as_ref() {
+            Stmt::Block(stmts, _) => match stmts.as_slice() {
+                [Stmt::Return(Some(expr), _)] => expr,
+                [Stmt::Expr(expr)] if *return_type == Type::Void => expr,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let size = inlinable_size(body)?;
+
+        let mut identifiers = Vec::new();
+        let mut calls = false;
+        body_identifiers(body, &mut identifiers, &mut calls);
+        if identifiers.iter().any(|identifier| identifier == name) {
+            return None;
+        }
+        let mut uses = HashMap::new();
+        let mut free = HashSet::new();
+        for identifier in identifiers {
+            if params.iter().any(|(param, _)| *param == identifier) {
+                *uses.entry(identifier).or_insert(0) += 1;
+            } else {
+                free.insert(identifier);
+            }
+        }
+        Some((
+            name.clone(),
+            Candidate {
+                size,
+                always,
+                params: params.clone(),
+                return_type: return_type.clone(),
+                body: body.clone(),
+                uses,
+                free,
+                calls,
+            },
+        ))
+    }
+
+    /// The body with `args` in place of the parameters, if the call can be
+    /// inlined into a function declaring `names`
+    fn instantiate(&self, args: &[Expr], names: &HashSet<String>) -> Option<Expr> {
+        if self.free.iter().any(|name| names.contains(name)) {
+            return None;
+        }
+        let mut substitutions = HashMap::new();
+        for ((param, ty), arg) in self.params.iter().zip(args) {
+            let uses = self.uses.get(param).copied().unwrap_or(0);
+            // Variables and literals can be evaluated any number of times, at
+            // any point; other arguments only once, where the call was
+            let movable = match arg {
+                Expr::Identifier(..) | Expr::Literal(..) => true,
+                Expr::Cast(value, _, _) if matches!(value.as_ref(), Expr::Literal(..)) => true,
+                arg => uses == 1 && !self.calls && is_pure(arg),
+            };
+            if !movable {
+                return None;
+            }
+            // The parameter converted the argument to its type; variables
+            // have it already, and so do literals of type int
+            let arg = match arg {
+                Expr::Identifier(..) => arg.clone(),
+                Expr::Literal(..) if *ty == Type::Int => arg.clone(),
+                arg if ty.is_integer() || *ty == Type::Float => Expr::Cast(Box::new(arg.clone()), ty.clone(), arg.span().clone()),
+                arg => arg.clone(),
+            };
+            substitutions.insert(param.as_str(), arg);
+        }
+        let body = substitute(self.body.clone(), &substitutions);
+        // So did returning the result
+        if self.return_type.is_integer() {
+            let span = body.span().clone();
+            return Some(Expr::Cast(Box::new(body), self.return_type.clone(), span));
+        }
+        Some(body)
+    }
+}
+
+/// Inlines calls in one function
+struct Inliner<'a> {
+    candidates: &'a HashMap<String, Candidate>,
+    /// The largest body the function inlines, unless it is `@inline`
+    max_size: usize,
+    /// The functions whose calls were inlined, with the spans of the calls
+    inlined: RefCell<Vec<(String, Span)>>,
+}
+
+impl Inliner<'_> {
+    /// `stmt` with calls inlined, in a function declaring `names`
+    fn stmt(&self, stmt: Stmt, names: &HashSet<String>, depth: usize) -> Stmt {
+        let expr = |expr: Expr| self.expr(expr, names, depth);
+        let stmt_box = |stmt: Box<Stmt>| Box::new(self.stmt(*stmt, names, depth));
+        match stmt {
+            Stmt::Expr(e) => Stmt::Expr(expr(e)),
+            Stmt::Let(name, ty, init, span) => Stmt::Let(name, ty, init.map(expr), span),
+            Stmt::Const(name, ty, value, span) => Stmt::Const(name, ty, expr(value), span),
+            Stmt::Assign(target, value, span) => Stmt::Assign(expr(target), expr(value), span),
+            Stmt::Return(value, span) => Stmt::Return(value.map(expr), span),
+            Stmt::While(cond, body, span) => Stmt::While(expr(cond), stmt_box(body), span),
+            Stmt::For(name, name_span, iterable, body, span) => {
+                Stmt::For(name, name_span, expr(iterable), stmt_box(body), span)
+            }
+            Stmt::Block(stmts, span) => {
+                Stmt::Block(stmts.into_iter().map(|stmt| self.stmt(stmt, names, depth)).collect(), span)
+            }
+            Stmt::Function(name, type_params, params, return_type, body, public, attributes, span) => {
+                Stmt::Function(name, type_params, params, return_type, stmt_box(body), public, attributes, span)
+            }
+            stmt @ (Stmt::Struct(..) | Stmt::Enum(..) | Stmt::Import(..) | Stmt::Extern(..)) => stmt,
+        }
+    }
+
+    fn expr(&self, expr: Expr, names: &HashSet<String>, depth: usize) -> Expr {
+        let boxed = |expr: Box<Expr>| Box::new(self.expr(*expr, names, depth));
+        let all = |exprs: Vec<Expr>| -> Vec<Expr> { exprs.into_iter().map(|expr| self.expr(expr, names, depth)).collect() };
+        match expr {
+            Expr::Call(callee, args, span) => {
+                let args = all(args);
+                if let Expr::Identifier(name, _) = callee.as_ref() {
+                    let candidate = self.candidates.get(name).filter(|candidate| {
+                        (candidate.always || candidate.size <= self.max_size)
+                            && depth < MAX_INLINE_DEPTH
+                            && !names.contains(name)
+                    });
+                    if let Some(body) = candidate.and_then(|candidate| candidate.instantiate(&args, names)) {
+                        self.inlined.borrow_mut().push((name.clone(), span.clone()));
+                        return Expr::Inlined(name.clone(), Box::new(self.expr(body, names, depth + 1)), span);
+                    }
+                }
+                Expr::Call(boxed(callee), args, span)
+            }
+            Expr::Binary(left, op, right, span) => Expr::Binary(boxed(left), op, boxed(right), span),
+            Expr::Unary(op, operand, span) => Expr::Unary(op, boxed(operand), span),
+            Expr::Index(array, index, span) => Expr::Index(boxed(array), boxed(index), span),
+            Expr::Field(object, field, span) => Expr::Field(boxed(object), field, span),
+            Expr::Array(elements, span) => Expr::Array(all(elements), span),
+            Expr::If(cond, then_branch, else_branch, span) => {
+                Expr::If(boxed(cond), boxed(then_branch), else_branch.map(boxed), span)
+            }
+            Expr::Block(stmts, span) => {
+                Expr::Block(stmts.into_iter().map(|stmt| self.stmt(stmt, names, depth)).collect(), span)
+            }
+            Expr::Lambda(params, return_type, body, span) => Expr::Lambda(params, return_type, boxed(body), span),
+            Expr::Range(start, end, span) => Expr::Range(boxed(start), boxed(end), span),
+            Expr::Cast(value, ty, span) => Expr::Cast(boxed(value), ty, span),
+            Expr::Match(scrutinee, arms, span) => Expr::Match(
+                boxed(scrutinee),
+                arms.into_iter()
+                    .map(|arm| MatchArm { body: self.expr(arm.body, names, depth), ..arm })
+                    .collect(),
+                span,
+            ),
+            Expr::Variant(enum_name, variant, payload, span) => Expr::Variant(enum_name, variant, all(payload), span),
+            Expr::Try(value, return_type, span) => Expr::Try(boxed(value), return_type, span),
+            Expr::Inlined(function, body, span) => Expr::Inlined(function, boxed(body), span),
+            expr @ (Expr::Literal(..) | Expr::Identifier(..)) => expr,
+        }
+    }
+}
+
+/// Whether values of `ty` are never converted to another type implicitly
+fn is_exact_type(ty: &Type) -> bool {
+    match ty {
+        Type::Optional(_) | Type::Inferred | Type::Generic(_) | Type::Applied(..) => false,
+        Type::Array(element_type) => is_exact_type(element_type),
+        Type::Function(params, ret) => params.iter().chain([ret.as_ref()]).all(is_exact_type),
+        _ => true,
+    }
+}
+
+/// The number of nodes in `expr`, if it has no parts that declare names or
+/// need statements of their own in C
+fn inlinable_size(expr: &Expr) -> Option<usize> {
+    let size = match expr {
+        Expr::Literal(..) | Expr::Identifier(..) => 0,
+        Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) => inlinable_size(left)? + inlinable_size(right)?,
+        Expr::Unary(_, operand, _)
+        | Expr::Field(operand, _, _)
+        | Expr::Cast(operand, _, _)
+        | Expr::Inlined(_, operand, _) => inlinable_size(operand)?,
+        // Pushing to or popping from a parameter would change the caller's
+        // array once inlined, rather than the function's own reference
+        Expr::Call(callee, _, _) if matches!(callee.as_ref(), Expr::Field(_, method, _) if method == "push" || method == "pop") => {
+            return None;
+        }
+        Expr::Call(callee, args, _) => {
+            inlinable_size(callee)? + args.iter().map(inlinable_size).sum::<Option<usize>>()?
+        }
+        Expr::Array(elements, _) | Expr::Variant(_, _, elements, _) => {
+            elements.iter().map(inlinable_size).sum::<Option<usize>>()?
+        }
+        Expr::If(..) | Expr::Block(..) | Expr::Lambda(..) | Expr::Range(..) | Expr::Match(..) => return None,
+        // `?` would return from the function it was inlined into
+        Expr::Try(..) => return None,
+    };
+    Some(size + 1)
+}
+
+/// Collects the identifiers in an inlinable body, noting whether it calls
+/// anything
+fn body_identifiers(expr: &Expr, identifiers: &mut Vec<String>, calls: &mut bool) {
+    match expr {
+        Expr::Identifier(name, _) => identifiers.push(name.clone()),
+        Expr::Literal(..) => {}
+        Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) => {
+            body_identifiers(left, identifiers, calls);
+            body_identifiers(right, identifiers, calls);
+        }
+        Expr::Unary(_, operand, _)
+        | Expr::Field(operand, _, _)
+        | Expr::Cast(operand, _, _)
+        | Expr::Inlined(_, operand, _) => body_identifiers(operand, identifiers, calls),
+        Expr::Call(callee, args, _) => {
+            *calls = true;
+            body_identifiers(callee, identifiers, calls);
+            args.iter().for_each(|arg| body_identifiers(arg, identifiers, calls));
+        }
+        Expr::Array(elements, _) | Expr::Variant(_, _, elements, _) => {
+            elements.iter().for_each(|element| body_identifiers(element, identifiers, calls))
+        }
+        Expr::If(..) | Expr::Block(..) | Expr::Lambda(..) | Expr::Range(..) | Expr::Match(..) | Expr::Try(..) => {}
+    }
+}
+
+/// Whether evaluating `expr` has no effects, so it may happen later than
+/// written
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(..) | Expr::Identifier(..) => true,
+        Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) => is_pure(left) && is_pure(right),
+        Expr::Unary(_, operand, _) | Expr::Field(operand, _, _) | Expr::Cast(operand, _, _) | Expr::Inlined(_, operand, _) => {
+            is_pure(operand)
+        }
+        _ => false,
+    }
+}
+
+/// `expr`, an inlinable body, with identifiers replaced
+fn substitute(expr: Expr, substitutions: &HashMap<&str, Expr>) -> Expr {
+    let boxed = |expr: Box<Expr>| Box::new(substitute(*expr, substitutions));
+    match expr {
+        Expr::Identifier(name, span) => match substitutions.get(name.as_str()) {
+            Some(arg) => arg.clone(),
+            None => Expr::Identifier(name, span),
+        },
+        Expr::Binary(left, op, right, span) => Expr::Binary(boxed(left), op, boxed(right), span),
+        Expr::Unary(op, operand, span) => Expr::Unary(op, boxed(operand), span),
+        Expr::Index(array, index, span) => Expr::Index(boxed(array), boxed(index), span),
+        Expr::Field(object, field, span) => Expr::Field(boxed(object), field, span),
+        Expr::Cast(value, ty, span) => Expr::Cast(boxed(value), ty, span),
+        Expr::Call(callee, args, span) => Expr::Call(
+            boxed(callee),
+            args.into_iter().map(|arg| substitute(arg, substitutions)).collect(),
+            span,
+        ),
+        Expr::Array(elements, span) => {
+            Expr::Array(elements.into_iter().map(|element| substitute(element, substitutions)).collect(), span)
+        }
+        Expr::Variant(enum_name, variant, payload, span) => Expr::Variant(
+            enum_name,
+            variant,
+            payload.into_iter().map(|value| substitute(value, substitutions)).collect(),
+            span,
+        ),
+        expr => expr,
+    }
+}
+
+/// Collects the names `stmt` declares: parameters, variables and the
+/// bindings of lambdas, loops and match arms
+fn declared_names(stmt: &Stmt, names: &mut HashSet<String>) {
+    match stmt {
+        Stmt::Expr(expr) | Stmt::Const(_, _, expr, _) => expr_declared_names(expr, names),
+        Stmt::Let(name, _, init, _) => {
+            names.insert(name.clone());
+            if let Some(init) = init {
+                expr_declared_names(init, names);
+            }
+        }
+        Stmt::Return(value, _) => {
+            if let Some(value) = value {
+                expr_declared_names(value, names);
+            }
+        }
+        Stmt::Assign(target, value, _) => {
+            expr_declared_names(target, names);
+            expr_declared_names(value, names);
+        }
+        Stmt::While(cond, body, _) => {
+            expr_declared_names(cond, names);
+            declared_names(body, names);
+        }
+        Stmt::For(name, _, iterable, body, _) => {
+            names.insert(name.clone());
+            expr_declared_names(iterable, names);
+            declared_names(body, names);
+        }
+        Stmt::Block(stmts, _) => stmts.iter().for_each(|stmt| declared_names(stmt, names)),
+        Stmt::Function(_, _, params, _, body, _, _, _) => {
+            names.extend(params.iter().map(|(param, _)| param.clone()));
+            declared_names(body, names);
+        }
+        Stmt::Struct(..) | Stmt::Enum(..) | Stmt::Import(..) | Stmt::Extern(..) => {}
+    }
+}
+
+fn expr_declared_names(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Literal(..) | Expr::Identifier(..) => {}
+        Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) | Expr::Range(left, right, _) => {
+            expr_declared_names(left, names);
+            expr_declared_names(right, names);
+        }
+        Expr::Unary(_, operand, _)
+        | Expr::Field(operand, _, _)
+        | Expr::Cast(operand, _, _)
+        | Expr::Try(operand, _, _)
+        | Expr::Inlined(_, operand, _) => expr_declared_names(operand, names),
+        Expr::Call(callee, args, _) => {
+            expr_declared_names(callee, names);
+            args.iter().for_each(|arg| expr_declared_names(arg, names));
+        }
+        Expr::Array(elements, _) | Expr::Variant(_, _, elements, _) => {
+            elements.iter().for_each(|element| expr_declared_names(element, names))
+        }
+        Expr::If(cond, then_branch, else_branch, _) => {
+            expr_declared_names(cond, names);
+            expr_declared_names(then_branch, names);
+            if let Some(else_branch) = else_branch {
+                expr_declared_names(else_branch, names);
+            }
+        }
+        Expr::Block(stmts, _) => stmts.iter().for_each(|stmt| declared_names(stmt, names)),
+        Expr::Lambda(params, _, body, _) => {
+            names.extend(params.iter().map(|(param, _)| param.clone()));
+            expr_declared_names(body, names);
+        }
+        Expr::Match(scrutinee, arms, _) => {
+            expr_declared_names(scrutinee, names);
+            for arm in arms {
+                if let Pattern::Variant(_, _, bindings, _) = &arm.pattern {
+                    names.extend(bindings.iter().cloned());
+                }
+                expr_declared_names(&arm.body, names);
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// Report of what the optimization passes did to a program and the modules it
+// imports, for `--opt-report`
+
+use crate::ast::{self, Span};
+use crate::optimizer::{self, Remark};
+use serde_json::{json, Value as Json};
+
+/// Something a pass did, and where
+#[derive(Debug, Clone)]
+pub struct OptRemark {
+    pub file: String,
+    /// One-based line and column of the start of `span`
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+    pub pass: String,
+    pub function: String,
+    /// How many changes the remark stands for
+    pub count: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OptReport {
+    pub remarks: Vec<OptRemark>,
+}
+
+impl OptReport {
+    /// Adds the remarks of the passes on `file`, whose text is `source`
+    pub(crate) fn add(&mut self, file: &str, source: &str, remarks: Vec<Remark>) {
+        let line_starts = ast::line_starts(source);
+        self.remarks.extend(remarks.into_iter().map(|remark| {
+            let line = ast::line_of(&line_starts, remark.span.start);
+            OptRemark {
+                file: file.to_string(),
+                line: line + 1,
+                column: remark.span.start.saturating_sub(line_starts[line]) + 1,
+                span: remark.span,
+                pass: remark.pass.to_string(),
+                function: remark.function,
+                count: remark.count,
+                message: remark.message,
+            }
+        }));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remarks.is_empty()
+    }
+
+    /// How many changes each pass made, in the order the passes run,
+    /// leaving out those that made none. Registered passes come after the
+    /// built-in ones.
+    pub fn totals(&self) -> Vec<(&str, usize)> {
+        let mut names: Vec<&str> = optimizer::pass_names().collect();
+        for remark in &self.remarks {
+            if !names.contains(&remark.pass.as_str()) {
+                names.push(&remark.pass);
+            }
+        }
+        names
+            .into_iter()
+            .map(|pass| {
+                let count = self.remarks.iter().filter(|remark| remark.pass == pass).map(|remark| remark.count).sum();
+                (pass, count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    /// The report as text: a line per remark, then the totals of each pass
+    pub fn to_text(&self) -> String {
+        if self.is_empty() {
+            return "No optimizations applied\n".to_string();
+        }
+        let mut text = String::new();
+        for remark in &self.remarks {
+            text.push_str(&format!(
+                "{}:{}:{}: {}: {} in {}\n",
+                remark.file, remark.line, remark.column, remark.pass, remark.message, remark.function
+            ));
+        }
+        text.push('\n');
+        for (pass, count) in self.totals() {
+            text.push_str(&format!("{}: {}\n", pass, optimizer::describe_changes(pass, count)));
+        }
+        text
+    }
+
+    /// The report as JSON, for build tooling
+    pub fn to_json(&self) -> Json {
+        let remarks: Vec<Json> = self
+            .remarks
+            .iter()
+            .map(|remark| {
+                json!({
+                    "file": remark.file,
+                    "line": remark.line,
+                    "column": remark.column,
+                    "start": remark.span.start,
+                    "end": remark.span.end,
+                    "pass": remark.pass,
+                    "function": remark.function,
+                    "count": remark.count,
+                    "message": remark.message,
+                })
+            })
+            .collect();
+        let totals: serde_json::Map<String, Json> =
+            self.totals().into_iter().map(|(pass, count)| (pass.to_string(), json!(count))).collect();
+        json!({ "remarks": remarks, "totals": totals })
+    }
+}
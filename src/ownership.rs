@@ -0,0 +1,411 @@
+// Move checking for `--strict-memory`. Strings, arrays and structs are heap
+// values with a single owner; passing one to a function, storing it or
+// returning it moves it, and the old name may not be used until it is
+// assigned again. The C backend counts references to these values, so
+// programs are memory safe without these rules; they keep ownership explicit
+// for code that wants every copy of a value to be visible.
+
+use crate::ast::{Expr, Literal, MatchArm, Pattern, Program, Span, Stmt, Type};
+use crate::typechecker::TypeError;
+use std::collections::{HashMap, HashSet};
+
+/// Builtins that only read their arguments
+const BORROWING_BUILTINS: &[&str] = &["print", "printf", "len", "panic", "assert", "env"];
+
+struct Variable {
+    name: String,
+    ty: Type,
+    // Where the value was last moved, unless it has been assigned since
+    moved: Option<Span>,
+    // Loop variables and match bindings alias part of another value, so they
+    // may be read but not moved
+    borrowed: bool,
+}
+
+struct MoveChecker {
+    // Every variable declared so far; scopes only change `visible`
+    variables: Vec<Variable>,
+    visible: HashMap<String, usize>,
+    structs: HashMap<String, HashMap<String, Type>>,
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
+    // C functions only read the strings passed to them
+    externs: HashSet<String>,
+    // Whether the statements checked last always return
+    diverged: bool,
+    errors: Vec<TypeError>,
+}
+
+fn is_heap(ty: &Type) -> bool {
+    match ty {
+        Type::String | Type::Array(_) | Type::Struct(_) | Type::Applied(..) => true,
+        Type::Optional(inner) => is_heap(inner),
+        _ => false,
+    }
+}
+
+/// Checks a typechecked program for uses of moved heap values
+pub fn check(program: &Program) -> Vec<TypeError> {
+    let mut checker = MoveChecker {
+        variables: Vec::new(),
+        visible: HashMap::new(),
+        structs: HashMap::new(),
+        enums: HashMap::new(),
+        externs: HashSet::new(),
+        diverged: false,
+        errors: Vec::new(),
+    };
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::Struct(name, _, fields, _) => {
+                checker.structs.insert(name.clone(), fields.iter().cloned().collect());
+            }
+            Stmt::Enum(name, variants, _) => {
+                checker.enums.insert(name.clone(), variants.clone());
+            }
+            Stmt::Extern(name, ..) => {
+                checker.externs.insert(name.clone());
+            }
+            _ => {}
+        }
+    }
+    for stmt in &program.statements {
+        checker.check_statement(stmt);
+    }
+
+    let mut errors = checker.errors;
+    errors.sort_by_key(|error| error.span.as_ref().map(|span| span.start));
+    errors
+}
+
+impl MoveChecker {
+    fn error(&mut self, code: &'static str, message: String, span: &Span) {
+        self.errors.push(TypeError {
+            code,
+            message,
+            span: Some(span.clone()),
+            label: None,
+        });
+    }
+
+    /// An error at `span` about something that happened at `other`, as
+    /// `label` says
+    fn error_with(&mut self, code: &'static str, message: String, span: &Span, label: &str, other: Span) {
+        self.errors.push(TypeError {
+            code,
+            message,
+            span: Some(span.clone()),
+            label: Some((label.to_string(), other)),
+        });
+    }
+
+    fn declare(&mut self, name: &str, ty: Type, borrowed: bool) {
+        self.variables.push(Variable {
+            name: name.to_string(),
+            ty,
+            moved: None,
+            borrowed,
+        });
+        self.visible.insert(name.to_string(), self.variables.len() - 1);
+    }
+
+    fn moved_states(&self) -> Vec<Option<Span>> {
+        self.variables.iter().map(|variable| variable.moved.clone()).collect()
+    }
+
+    fn set_moved_states(&mut self, states: &[Option<Span>]) {
+        for (variable, state) in self.variables.iter_mut().zip(states) {
+            variable.moved = state.clone();
+        }
+    }
+
+    /// Checks each branch from the current state, then continues with a value
+    /// counted as moved if any branch that falls through moved it
+    fn check_branches(&mut self, branches: Vec<&dyn Fn(&mut Self)>) {
+        let start = self.moved_states();
+        let mut outcomes = Vec::new();
+        for branch in branches {
+            self.set_moved_states(&start);
+            self.diverged = false;
+            branch(self);
+            outcomes.push((self.moved_states(), self.diverged));
+        }
+
+        let all_diverged = outcomes.iter().all(|(_, diverged)| *diverged);
+        let mut merged = start;
+        for (states, diverged) in &outcomes {
+            if *diverged && !all_diverged {
+                continue;
+            }
+            for (merged, state) in merged.iter_mut().zip(states) {
+                if merged.is_none() {
+                    *merged = state.clone();
+                }
+            }
+        }
+        self.set_moved_states(&merged);
+        self.diverged = all_diverged;
+    }
+
+    /// Checks a loop body, which runs any number of times
+    fn check_loop(&mut self, body: impl FnOnce(&mut Self)) {
+        let before = self.moved_states();
+        self.diverged = false;
+        body(self);
+
+        if self.diverged {
+            // The body never reaches a second iteration
+            self.set_moved_states(&before);
+        } else {
+            let mut errors = Vec::new();
+            for (variable, moved_before) in self.variables.iter().zip(&before) {
+                if let (None, Some(moved)) = (moved_before, &variable.moved) {
+                    errors.push((
+                        "E0402",
+                        format!(
+                            "Value {} is moved inside a loop, so the next iteration would use it after the move",
+                            variable.name
+                        ),
+                        moved.clone(),
+                    ));
+                }
+            }
+            for (code, message, span) in errors {
+                self.error(code, message, &span);
+            }
+        }
+        self.diverged = false;
+    }
+
+    fn check_statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => self.check_expression(expr, false),
+            Stmt::Let(name, ty, init, _) => {
+                if let Some(init) = init {
+                    self.check_expression(init, true);
+                }
+                self.declare(name, ty.clone().unwrap_or(Type::Inferred), false);
+            }
+            Stmt::Const(..) | Stmt::Struct(..) | Stmt::Enum(..) | Stmt::Import(..) | Stmt::Extern(..) => {}
+            Stmt::Assign(target, value, _) => {
+                self.check_expression(value, true);
+                match target {
+                    // Assigning gives the variable a new value to own
+                    Expr::Identifier(name, _) => {
+                        if let Some(&index) = self.visible.get(name) {
+                            self.variables[index].moved = None;
+                        }
+                    }
+                    _ => self.check_expression(target, false),
+                }
+            }
+            Stmt::Return(value, _) => {
+                if let Some(value) = value {
+                    self.check_expression(value, true);
+                }
+                self.diverged = true;
+            }
+            Stmt::While(condition, body, _) => {
+                self.check_expression(condition, false);
+                self.check_loop(|checker| checker.check_statement(body));
+            }
+            Stmt::For(name, _, iterable, body, _) => {
+                self.check_expression(iterable, false);
+                let element_type = match self.type_of(iterable) {
+                    Some(Type::Array(element_type)) => *element_type,
+                    _ => Type::Int,
+                };
+                self.check_loop(|checker| {
+                    let saved = checker.visible.clone();
+                    checker.declare(name, element_type, true);
+                    checker.check_statement(body);
+                    checker.visible = saved;
+                });
+            }
+            Stmt::Block(statements, _) => {
+                let saved = self.visible.clone();
+                for stmt in statements {
+                    self.check_statement(stmt);
+                }
+                self.visible = saved;
+            }
+            Stmt::Function(_, _, params, _, body, _, _, _) => {
+                let saved = self.visible.clone();
+                let saved_moves = self.moved_states();
+                for (name, ty) in params {
+                    self.declare(name, ty.clone(), false);
+                }
+                self.check_statement(body);
+                self.visible = saved;
+                self.set_moved_states(&saved_moves);
+                self.diverged = false;
+            }
+        }
+    }
+
+    /// Checks `expr`, whose value is moved into a new owner when `consume`
+    /// is set and only read otherwise
+    fn check_expression(&mut self, expr: &Expr, consume: bool) {
+        match expr {
+            Expr::Literal(..) => {}
+            Expr::Identifier(name, span) => {
+                let index = match self.visible.get(name) {
+                    Some(&index) => index,
+                    // Functions, constants and enums are not owned values
+                    None => return,
+                };
+                if let Some(moved) = self.variables[index].moved.take() {
+                    self.error_with("E0401", format!("Use of moved value {}", name), span, "moved", moved);
+                }
+                let variable = &self.variables[index];
+                if consume && is_heap(&variable.ty) {
+                    if variable.borrowed {
+                        self.error(
+                            "E0403",
+                            format!(
+                                "Cannot move {}, which borrows from another value; both would own the same memory",
+                                name
+                            ),
+                            span,
+                        );
+                    } else {
+                        self.variables[index].moved = Some(span.clone());
+                    }
+                }
+            }
+            Expr::Binary(left, _, right, _) => {
+                self.check_expression(left, false);
+                self.check_expression(right, false);
+            }
+            Expr::Unary(_, operand, _) | Expr::Cast(operand, _, _) => self.check_expression(operand, false),
+            // The payload is taken out of the value, which is moved with it
+            Expr::Try(value, _, _) => self.check_expression(value, consume),
+            Expr::Inlined(_, body, _) => self.check_expression(body, consume),
+            Expr::Call(callee, args, _) => {
+                let borrows = match callee.as_ref() {
+                    Expr::Identifier(name, _) => {
+                        (BORROWING_BUILTINS.contains(&name.as_str()) || self.externs.contains(name))
+                            && !self.visible.contains_key(name)
+                    }
+                    // Methods of strings only read their arguments
+                    Expr::Field(object, _, _) => self.type_of(object) == Some(Type::String),
+                    _ => false,
+                };
+                self.check_expression(callee, false);
+                for arg in args {
+                    self.check_expression(arg, !borrows);
+                }
+            }
+            Expr::Index(array, index, _) => {
+                self.check_expression(array, false);
+                self.check_expression(index, false);
+                if consume {
+                    self.check_move_out(expr);
+                }
+            }
+            Expr::Field(object, _, _) => {
+                self.check_expression(object, false);
+                if consume {
+                    self.check_move_out(expr);
+                }
+            }
+            Expr::Array(elements, _) | Expr::Variant(_, _, elements, _) => {
+                for element in elements {
+                    self.check_expression(element, true);
+                }
+            }
+            Expr::Range(start, end, _) => {
+                self.check_expression(start, false);
+                self.check_expression(end, false);
+            }
+            Expr::If(condition, then_branch, else_branch, _) => {
+                self.check_expression(condition, false);
+                let then_branch = |checker: &mut Self| checker.check_expression(then_branch, consume);
+                let else_branch = |checker: &mut Self| {
+                    if let Some(else_branch) = else_branch {
+                        checker.check_expression(else_branch, consume);
+                    }
+                };
+                self.check_branches(vec![&then_branch, &else_branch]);
+            }
+            Expr::Block(statements, _) => {
+                let saved = self.visible.clone();
+                for (i, stmt) in statements.iter().enumerate() {
+                    match stmt {
+                        // The last expression is the value of the block
+                        Stmt::Expr(expr) if i + 1 == statements.len() => self.check_expression(expr, consume),
+                        _ => self.check_statement(stmt),
+                    }
+                }
+                self.visible = saved;
+            }
+            Expr::Lambda(params, _, body, _) => {
+                let saved = self.visible.clone();
+                for (name, ty) in params {
+                    self.declare(name, ty.clone().unwrap_or(Type::Inferred), false);
+                }
+                self.check_expression(body, true);
+                self.visible = saved;
+            }
+            Expr::Match(scrutinee, arms, _) => {
+                self.check_expression(scrutinee, false);
+                let variants = match self.type_of(scrutinee) {
+                    Some(Type::Enum(name)) => self.enums.get(&name).cloned().unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+                let variants = &variants;
+                let arms: Vec<_> = arms
+                    .iter()
+                    .map(|arm| move |checker: &mut Self| checker.check_match_arm(arm, variants, consume))
+                    .collect();
+                self.check_branches(arms.iter().map(|arm| arm as &dyn Fn(&mut Self)).collect());
+            }
+        }
+    }
+
+    fn check_match_arm(&mut self, arm: &MatchArm, variants: &[(String, Vec<Type>)], consume: bool) {
+        let saved = self.visible.clone();
+        if let Pattern::Variant(_, variant, names, _) = &arm.pattern {
+            let payload = variants
+                .iter()
+                .find(|(name, _)| name == variant)
+                .map(|(_, payload)| payload.as_slice())
+                .unwrap_or(&[]);
+            for (i, name) in names.iter().enumerate() {
+                let ty = payload.get(i).cloned().unwrap_or(Type::Inferred);
+                self.declare(name, ty, true);
+            }
+        }
+        self.check_expression(&arm.body, consume);
+        self.visible = saved;
+    }
+
+    /// Reports moving a heap value out of the array or struct holding it
+    fn check_move_out(&mut self, place: &Expr) {
+        if self.type_of(place).is_some_and(|ty| is_heap(&ty)) {
+            self.error(
+                "E0404",
+                "Cannot move a value out of an array element or struct field; both would own the same memory"
+                    .to_string(),
+                place.span(),
+            );
+        }
+    }
+
+    /// The type of a variable, or of a field or element of one
+    fn type_of(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Literal(Literal::String(_), _) => Some(Type::String),
+            Expr::Array(elements, _) => Some(Type::Array(Box::new(self.type_of(elements.first()?)?))),
+            Expr::Identifier(name, _) => self.visible.get(name).map(|&index| self.variables[index].ty.clone()),
+            Expr::Field(object, field, _) => match self.type_of(object)? {
+                Type::Struct(name) => self.structs.get(&name)?.get(field).cloned(),
+                _ => None,
+            },
+            Expr::Index(array, _, _) => match self.type_of(array)? {
+                Type::Array(element_type) => Some(*element_type),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
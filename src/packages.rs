@@ -0,0 +1,244 @@
+// Resolving the dependencies of a project to the directories of their modules.
+//
+// Path dependencies are used where they are. Git dependencies are cloned
+// once into `git/db` of the cache (`$ZC_HOME`, or `~/.zc`), and each commit
+// a project uses is checked out into `git/checkouts`. The commit each git
+// dependency resolved to is recorded in `z.lock` next to `z.toml`, so later
+// builds use the same one until the lock file is deleted or the dependency
+// changes in the manifest.
+
+use crate::diagnostic;
+use crate::project::{Dependency, Manifest, Source, MANIFEST};
+use crate::{CompilerError, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use toml::{Table, Value};
+
+/// The file pinning git dependencies to commits
+pub const LOCK_FILE: &str = "z.lock";
+
+/// A git dependency and the commit it resolved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Locked {
+    name: String,
+    url: String,
+    rev: Option<String>,
+    commit: String,
+}
+
+/// The packages `manifest` depends on, directly or through other packages,
+/// each with the directory of its modules. Git dependencies are fetched if
+/// need be, and `z.lock` is updated when they resolve to other commits.
+pub fn resolve(manifest: &Manifest) -> Result<Vec<(String, PathBuf)>> {
+    let lock_path = manifest.dir.join(LOCK_FILE);
+    let locked = read_lock(&lock_path)?;
+    let mut resolver = Resolver {
+        locked: &locked,
+        resolved: Vec::new(),
+        sources: Vec::new(),
+        packages: Vec::new(),
+    };
+    resolver.add(&manifest.dependencies)?;
+
+    if resolver.resolved != locked {
+        fs::write(&lock_path, lock_text(&resolver.resolved))?;
+    }
+    Ok(resolver.packages)
+}
+
+struct Resolver<'a> {
+    locked: &'a [Locked],
+    resolved: Vec<Locked>,
+    // The source of each package, to find two of the same name
+    sources: Vec<(String, Source)>,
+    packages: Vec<(String, PathBuf)>,
+}
+
+impl Resolver<'_> {
+    fn add(&mut self, dependencies: &[Dependency]) -> Result<()> {
+        for dependency in dependencies {
+            if let Some((_, source)) = self.sources.iter().find(|(name, _)| *name == dependency.name) {
+                if *source != dependency.source {
+                    return Err(CompilerError::ManifestError(format!(
+                        "Two different packages are named {}",
+                        dependency.name
+                    )));
+                }
+                continue;
+            }
+            self.sources.push((dependency.name.clone(), dependency.source.clone()));
+
+            let dir = match &dependency.source {
+                Source::Path(path) => path.clone(),
+                Source::Git { url, rev } => self.checkout(&dependency.name, url, rev.as_deref())?,
+            };
+            if !dir.is_dir() {
+                return Err(CompilerError::ManifestError(format!(
+                    "The dependency {} is not a directory: {}",
+                    dependency.name,
+                    dir.display()
+                )));
+            }
+            // A project's modules are in its first source directory, and its
+            // dependencies come along
+            if dir.join(MANIFEST).is_file() {
+                let package = Manifest::load(&dir)?;
+                let modules = package.source_dirs.first().cloned().unwrap_or_else(|| dir.clone());
+                self.packages.push((dependency.name.clone(), modules));
+                self.add(&package.dependencies)?;
+            } else {
+                self.packages.push((dependency.name.clone(), dir));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks out the commit of git dependency `name` the lock file names,
+    /// or else the one `rev` names now, returning its directory
+    fn checkout(&mut self, name: &str, url: &str, rev: Option<&str>) -> Result<PathBuf> {
+        let cache = cache_dir()?.join("git");
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        let db = cache.join("db").join(format!("{}-{:016x}", name, hasher.finish()));
+        let cloned = !db.exists();
+        if cloned {
+            diagnostic::note(&format!("Fetching {} from {}", name, url));
+            fs::create_dir_all(cache.join("db"))?;
+            git(None, &["clone", "--quiet", "--bare", "--", url, &db.to_string_lossy()])?;
+        }
+
+        let locked = self
+            .locked
+            .iter()
+            .find(|locked| locked.name == name && locked.url == url && locked.rev.as_deref() == rev);
+        let commit = match locked {
+            Some(locked) => {
+                if !has_commit(&db, &locked.commit) {
+                    fetch(&db, name, url)?;
+                }
+                if !has_commit(&db, &locked.commit) {
+                    return Err(CompilerError::ManifestError(format!(
+                        "{} pins {} to commit {}, which {} does not have",
+                        LOCK_FILE, name, locked.commit, url
+                    )));
+                }
+                locked.commit.clone()
+            }
+            None => {
+                // Branches move, so a clone made for another project is updated
+                if !cloned {
+                    fetch(&db, name, url)?;
+                }
+                let rev = rev.unwrap_or("HEAD");
+                let spec = format!("{}^{{commit}}", rev);
+                git(Some(&db), &["rev-parse", "--verify", "--quiet", &spec]).map_err(|_| {
+                    CompilerError::ManifestError(format!("{} has no branch, tag or commit {}", url, rev))
+                })?
+            }
+        };
+
+        let checkout = cache.join("checkouts").join(format!("{}-{}", name, &commit[..12.min(commit.len())]));
+        if !checkout.exists() {
+            // Checked out next to its place and moved there once complete
+            let partial = checkout.with_extension("partial");
+            let _ = fs::remove_dir_all(&partial);
+            fs::create_dir_all(cache.join("checkouts"))?;
+            git(
+                None,
+                &["clone", "--quiet", "--shared", "--no-checkout", "--", &db.to_string_lossy(), &partial.to_string_lossy()],
+            )?;
+            git(Some(&partial), &["checkout", "--quiet", "--detach", &commit])?;
+            fs::rename(&partial, &checkout)?;
+        }
+
+        self.resolved.push(Locked {
+            name: name.to_string(),
+            url: url.to_string(),
+            rev: rev.map(str::to_string),
+            commit,
+        });
+        Ok(checkout)
+    }
+}
+
+/// The directory packages are cached in
+pub(crate) fn cache_dir() -> Result<PathBuf> {
+    if let Some(home) = std::env::var_os("ZC_HOME") {
+        return Ok(PathBuf::from(home));
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".zc"))
+        .ok_or_else(|| CompilerError::ManifestError("Set ZC_HOME to a directory to cache packages in".to_string()))
+}
+
+fn has_commit(db: &Path, rev: &str) -> bool {
+    git(Some(db), &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)]).is_ok()
+}
+
+fn fetch(db: &Path, name: &str, url: &str) -> Result<()> {
+    diagnostic::note(&format!("Updating {} from {}", name, url));
+    git(Some(db), &["fetch", "--quiet", "--tags", "--", url, "+refs/heads/*:refs/heads/*"]).map(|_| ())
+}
+
+/// Runs git in `dir`, returning what it prints
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command
+        .args(args)
+        .output()
+        .map_err(|e| CompilerError::ManifestError(format!("Failed to run git, which fetches packages: {}", e)))?;
+    if !output.status.success() {
+        return Err(CompilerError::ManifestError(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn read_lock(path: &Path) -> Result<Vec<Locked>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let invalid = |message: &str| CompilerError::ManifestError(format!("{}: {}", path.display(), message));
+    let table: Table = text.parse().map_err(|e: toml::de::Error| invalid(e.message()))?;
+    let Some(Value::Array(entries)) = table.get("git") else {
+        return Ok(Vec::new());
+    };
+    entries
+        .iter()
+        .map(|entry| {
+            let field = |key: &str| entry.get(key).and_then(Value::as_str).map(str::to_string);
+            Ok(Locked {
+                name: field("name").ok_or_else(|| invalid("an entry has no name"))?,
+                url: field("url").ok_or_else(|| invalid("an entry has no url"))?,
+                rev: field("rev"),
+                commit: field("commit").ok_or_else(|| invalid("an entry has no commit"))?,
+            })
+        })
+        .collect()
+}
+
+fn lock_text(locked: &[Locked]) -> String {
+    let mut text = String::from("# Written by zc to pin git dependencies to a commit. Delete it to update them.\n");
+    for entry in locked {
+        text.push_str("\n[[git]]\n");
+        text.push_str(&format!("name = {}\n", Value::String(entry.name.clone())));
+        text.push_str(&format!("url = {}\n", Value::String(entry.url.clone())));
+        if let Some(rev) = &entry.rev {
+            text.push_str(&format!("rev = {}\n", Value::String(rev.clone())));
+        }
+        text.push_str(&format!("commit = {}\n", Value::String(entry.commit.clone())));
+    }
+    text
+}
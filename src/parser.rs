@@ -1,32 +1,46 @@
-use crate::ast::{BinaryOp, Expr, Literal, Program, Span, Stmt, Type, UnaryOp};
+use crate::ast::{Attribute, BinaryOp, Expr, Literal, MatchArm, Pattern, Program, Span, Stmt, Type, UnaryOp};
 use crate::lexer::{Span as LexerSpan, Token};
+use crate::source_map::FileId;
 use std::iter::Peekable;
+use std::ops::Range;
 use std::vec::IntoIter;
 
 pub struct Parser {
     tokens: Peekable<IntoIter<LexerSpan>>,
     current_token: Option<LexerSpan>,
+    // The file the tokens are from, which every span points into
+    file: FileId,
+    // End position of the most recently consumed token, used for statement spans
+    prev_end: usize,
+    // Type parameters of the declaration being parsed
+    type_params: Vec<String>,
 }
 
 #[derive(Debug)]
 pub struct ParseError {
+    pub code: &'static str,
     pub message: String,
     pub span: Span,
+    /// Another place the error is about, as in the typechecker's errors
+    pub label: Option<(String, Span)>,
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Parse error at position {}: {}", self.span.start, self.message)
+        write!(f, "Parse error[{}] at position {}: {}", self.code, self.span.start, self.message)
     }
 }
 
 type Result<T> = std::result::Result<T, ParseError>;
 
 impl Parser {
-    pub fn new(tokens: Vec<LexerSpan>) -> Self {
+    pub fn new(tokens: Vec<LexerSpan>, file: FileId) -> Self {
         let mut parser = Self {
             tokens: tokens.into_iter().peekable(),
             current_token: None,
+            file,
+            prev_end: 0,
+            type_params: Vec::new(),
         };
         parser.advance();
         parser
@@ -34,11 +48,37 @@ impl Parser {
 
     fn advance(&mut self) -> Option<LexerSpan> {
         let token = self.tokens.next();
-        std::mem::replace(&mut self.current_token, token)
+        let previous = std::mem::replace(&mut self.current_token, token);
+        if let Some(previous) = &previous {
+            self.prev_end = previous.span.end;
+        }
+        previous
+    }
+
+    fn check(&self, expected: &Token) -> bool {
+        matches!(&self.current_token, Some(token) if &token.token == expected)
+    }
+
+    /// Consumes the current token if it matches, returning whether it did
+    fn eat(&mut self, expected: &Token) -> bool {
+        if self.check(expected) {
+            self.advance();
+            true
+        } else {
+            false
+        }
     }
 
-    fn peek(&mut self) -> Option<&LexerSpan> {
-        self.tokens.peek()
+    /// The span of the bytes `range` of the file
+    fn span(&self, range: Range<usize>) -> Span {
+        Span::new(self.file, range)
+    }
+
+    fn current_span(&self) -> Span {
+        match &self.current_token {
+            Some(token) => self.span(token.span.clone()),
+            None => Span { file: self.file, start: self.prev_end, end: self.prev_end },
+        }
     }
 
     fn expect(&mut self, expected: Token) -> Result<LexerSpan> {
@@ -47,25 +87,44 @@ impl Parser {
                 Ok(self.advance().unwrap())
             } else {
                 Err(ParseError {
+                    code: "E0002",
                     message: format!("Expected {:?}, found {:?}", expected, token.token),
-                    span: token.span.clone().into(),
+                    span: self.span(token.span.clone()),
+                    label: None,
                 })
             }
         } else {
             Err(ParseError {
+                code: "E0003",
                 message: format!("Expected {:?}, found end of file", expected),
-                span: Span { start: 0, end: 0 },
+                span: self.current_span(),
+                label: None,
             })
         }
     }
 
+    fn expect_identifier(&mut self, context: &str) -> Result<(String, Span)> {
+        match &self.current_token {
+            Some(token) if matches!(token.token, Token::Identifier) => {
+                let token = self.advance().unwrap();
+                Ok((token.text, self.span(token.span)))
+            },
+            _ => Err(ParseError {
+                code: "E0002",
+                message: format!("Expected identifier {}", context),
+                span: self.current_span(),
+                label: None,
+            }),
+        }
+    }
+
     fn parse_program(&mut self) -> Result<Program> {
         let mut statements = Vec::new();
-        
+
         while self.current_token.is_some() {
             statements.push(self.parse_statement()?);
         }
-        
+
         Ok(Program::new(statements))
     }
 
@@ -73,10 +132,12 @@ impl Parser {
         match &self.current_token {
             Some(token) => match &token.token {
                 Token::Let => self.parse_let_statement(),
-                Token::Fn => self.parse_function_declaration(),
+                Token::Const => self.parse_const_declaration(),
+                Token::Fn | Token::Pub | Token::Extern | Token::At => self.parse_declaration(),
                 Token::Return => self.parse_return_statement(),
                 Token::If => {
                     let expr = self.parse_if_expression()?;
+                    self.eat(&Token::Semicolon);
                     Ok(Stmt::Expr(expr))
                 },
                 Token::While => self.parse_while_statement(),
@@ -86,305 +147,728 @@ impl Parser {
                     Ok(Stmt::Expr(expr))
                 },
                 Token::Struct => self.parse_struct_declaration(),
+                Token::Enum => self.parse_enum_declaration(),
                 Token::Import => self.parse_import_statement(),
                 _ => {
                     let expr = self.parse_expression()?;
-                    
+
                     // Check if this is an assignment
-                    if let Some(token) = &self.current_token {
-                        if token.token == Token::Assign {
-                            let span = token.span.clone();
-                            self.advance(); // Consume '='
-                            let value = self.parse_expression()?;
-                            return Ok(Stmt::Assign(expr, value, span.into()));
-                        }
+                    if self.eat(&Token::Assign) {
+                        let value = self.parse_expression()?;
+                        self.eat(&Token::Semicolon);
+                        let span = Span { file: self.file, start: expr.span().start, end: self.prev_end };
+                        return Ok(Stmt::Assign(expr, value, span));
                     }
-                    
+
                     // Consume semicolon if present
-                    if let Some(token) = &self.current_token {
-                        if token.token == Token::Semicolon {
-                            self.advance();
-                        }
-                    }
-                    
+                    self.eat(&Token::Semicolon);
+
                     Ok(Stmt::Expr(expr))
                 }
             },
             None => Err(ParseError {
+                code: "E0003",
                 message: "Unexpected end of file".to_string(),
-                span: Span { start: 0, end: 0 },
+                span: self.current_span(),
+                label: None,
             }),
         }
     }
 
-    // Placeholder implementations for statement parsing methods
     fn parse_let_statement(&mut self) -> Result<Stmt> {
         let let_token = self.advance().unwrap();
-        let span = let_token.span.clone();
-        
+        let start = let_token.span.start;
+
         // Parse identifier
-        let _identifier = match &self.current_token {
-            Some(token) if matches!(token.token, Token::Identifier) => {
-                let id_span = token.span.clone();
-                let _id = self.advance().unwrap();
-                id_span
-            },
-            _ => return Err(ParseError {
-                message: "Expected identifier after 'let'".to_string(),
-                span: span.clone().into(),
-            }),
-        };
-        
+        let (name, _) = self.expect_identifier("after 'let'")?;
+
         // Parse optional type annotation
         let mut type_ann = None;
-        if let Some(token) = &self.current_token {
-            if token.token == Token::Colon {
-                self.advance(); // Consume ':'
-                type_ann = Some(self.parse_type()?);
-            }
+        if self.eat(&Token::Colon) {
+            type_ann = Some(self.parse_type()?);
         }
-        
+
         // Parse optional initializer
         let mut initializer = None;
-        if let Some(token) = &self.current_token {
-            if token.token == Token::Assign {
-                self.advance(); // Consume '='
-                initializer = Some(self.parse_expression()?);
-            }
+        if self.eat(&Token::Assign) {
+            initializer = Some(self.parse_expression()?);
         }
-        
-        // Expect semicolon
-        if let Some(token) = &self.current_token {
-            if token.token == Token::Semicolon {
-                self.advance();
-            }
-        }
-        
+
+        // Semicolon is optional
+        self.eat(&Token::Semicolon);
+
         Ok(Stmt::Let(
-            "identifier".to_string(), // Placeholder
+            name,
             type_ann,
             initializer,
-            span.into(),
+            Span { file: self.file, start, end: self.prev_end },
         ))
     }
 
-    // Placeholder implementations for other parsing methods
-    fn parse_function_declaration(&mut self) -> Result<Stmt> {
-        // Parse 'fn' keyword
-        let fn_token = self.advance().unwrap();
-        let start_pos = fn_token.span.start;
-        
-        // Parse function name
-        let name = match &self.current_token {
-            Some(token) if matches!(token.token, Token::Identifier) => {
-                let name_token = self.advance().unwrap();
-                // Since we don't have direct access to the source, we'll just use a placeholder name
-                "main".to_string() // Placeholder for now
-            },
-            _ => return Err(ParseError {
-                message: "Expected function name after 'fn'".to_string(),
-                span: Span { start: start_pos, end: start_pos + 2 },
-            }),
-        };
-        
-        // Parse parameter list
-        if let Some(token) = &self.current_token {
-            if !matches!(token.token, Token::LeftParen) {
+    fn parse_const_declaration(&mut self) -> Result<Stmt> {
+        let const_token = self.advance().unwrap();
+        let start = const_token.span.start;
+
+        let (name, _) = self.expect_identifier("after 'const'")?;
+
+        let mut type_ann = None;
+        if self.eat(&Token::Colon) {
+            type_ann = Some(self.parse_type()?);
+        }
+
+        // Unlike `let`, a constant must have a value
+        self.expect(Token::Assign)?;
+        let value = self.parse_expression()?;
+        self.eat(&Token::Semicolon);
+
+        Ok(Stmt::Const(name, type_ann, value, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    /// Parses a function or extern declaration with the attributes before
+    /// it: `@inline`, `@noinline`, `@optimize(level)`, `@cold` or `@bench` on
+    /// functions, and `@link("library")` on externs
+    fn parse_declaration(&mut self) -> Result<Stmt> {
+        let start = self.current_span().start;
+
+        let mut attributes: Vec<(Attribute, Span)> = Vec::new();
+        let mut library = None;
+        while self.eat(&Token::At) {
+            let (attribute, span) = self.expect_identifier("after '@'")?;
+            match attribute.as_str() {
+                "inline" => attributes.push((Attribute::Inline, span)),
+                "noinline" => attributes.push((Attribute::NoInline, span)),
+                "cold" => attributes.push((Attribute::Cold, span)),
+                "bench" => attributes.push((Attribute::Bench, span)),
+                "optimize" => {
+                    self.expect(Token::LeftParen)?;
+                    let token = self.expect(Token::IntLiteral)?;
+                    let level = match token.text.parse::<u8>() {
+                        Ok(level) if level <= 3 => level,
+                        _ => {
+                            return Err(ParseError {
+                                code: "E0005",
+                                message: format!("Expected an optimization level from 0 to 3, found {}", token.text),
+                                span: self.span(token.span),
+                                label: None,
+                            })
+                        }
+                    };
+                    self.expect(Token::RightParen)?;
+                    let conflicting = attributes
+                        .iter()
+                        .any(|(attribute, _)| matches!(attribute, Attribute::Optimize(other) if *other != level));
+                    if conflicting {
+                        return Err(ParseError {
+                            code: "E0005",
+                            message: "A function has only one optimization level".to_string(),
+                            span,
+                            label: None,
+                        });
+                    }
+                    attributes.push((Attribute::Optimize(level), span));
+                }
+                "link" => {
+                    self.expect(Token::LeftParen)?;
+                    let token = self.expect(Token::StringLiteral)?;
+                    library = Some((unescape_string(&token.text[1..token.text.len() - 1]), span));
+                    self.expect(Token::RightParen)?;
+                }
+                _ => {
+                    return Err(ParseError {
+                        code: "E0004",
+                        message: format!("Unknown attribute @{}", attribute),
+                        span,
+                        label: None,
+                    })
+                }
+            }
+        }
+
+        if self.check(&Token::Extern) {
+            if let Some((attribute, span)) = attributes.into_iter().next() {
                 return Err(ParseError {
-                    message: "Expected '(' after function name".to_string(),
-                    span: Span { start: token.span.start, end: token.span.end },
+                    code: "E0005",
+                    message: format!("{} applies to Z functions only, not extern ones", attribute),
+                    span,
+                    label: None,
                 });
             }
-        } else {
+            return self.parse_extern_declaration(start, library.map(|(library, _)| library));
+        }
+        if let Some((_, span)) = library {
             return Err(ParseError {
-                message: "Expected '(' after function name".to_string(),
-                span: Span { start: start_pos, end: start_pos + 2 },
+                code: "E0005",
+                message: "@link applies to extern functions only".to_string(),
+                span,
+                label: None,
             });
         }
-        
-        // Consume the left parenthesis
-        self.advance();
-        
-        // For now, we'll just skip the parameter list
-        let params = Vec::new();
-        
-        // Skip until we find the closing parenthesis
-        while let Some(token) = &self.current_token {
-            if matches!(token.token, Token::RightParen) {
+        let inline = attributes.iter().find(|(attribute, _)| *attribute == Attribute::Inline);
+        let noinline = attributes.iter().find(|(attribute, _)| *attribute == Attribute::NoInline);
+        if let (Some(_), Some((_, span))) = (inline, noinline) {
+            return Err(ParseError {
+                code: "E0005",
+                message: "A function cannot be both @inline and @noinline".to_string(),
+                span: span.clone(),
+                label: None,
+            });
+        }
+        let bench = attributes.iter().find(|(attribute, _)| *attribute == Attribute::Bench).map(|(_, span)| span.clone());
+        let mut attributes: Vec<Attribute> = attributes.into_iter().map(|(attribute, _)| attribute).collect();
+        attributes.dedup();
+        let function = self.parse_function_declaration(start, attributes)?;
+        // The benchmark runner calls it with nothing to pass
+        if let (Some(span), Stmt::Function(_, type_params, params, ..)) = (bench, &function) {
+            if !type_params.is_empty() || !params.is_empty() {
+                return Err(ParseError {
+                    code: "E0005",
+                    message: "A @bench function takes no parameters".to_string(),
+                    span,
+                    label: None,
+                });
+            }
+        }
+        Ok(function)
+    }
+
+    fn parse_function_declaration(&mut self, start_pos: usize, attributes: Vec<Attribute>) -> Result<Stmt> {
+        let public = self.eat(&Token::Pub);
+
+        // Parse 'fn' keyword
+        self.expect(Token::Fn)?;
+
+        // Parse function name
+        let (name, _) = self.expect_identifier("after 'fn'")?;
+        let type_params = self.parse_type_parameters()?;
+        self.type_params = type_params.clone();
+
+        let (params, return_type) = self.parse_signature(&name)?;
+
+        // Parse function body
+        let (stmts, body_span) = self.parse_block()?;
+        let body = Box::new(Stmt::Block(stmts, body_span));
+        self.type_params.clear();
+
+        Ok(Stmt::Function(
+            name,
+            type_params,
+            params,
+            return_type,
+            body,
+            public,
+            attributes,
+            Span { file: self.file, start: start_pos, end: self.prev_end },
+        ))
+    }
+
+    /// Parses a parameter list and an optional return type for the function
+    /// `name`
+    fn parse_signature(&mut self, name: &str) -> Result<(Vec<(String, Type)>, Type)> {
+        self.expect(Token::LeftParen)?;
+        let mut params = Vec::new();
+        let mut spans: Vec<Span> = Vec::new();
+        while !self.check(&Token::RightParen) {
+            let (param_name, span) = self.expect_identifier("in parameter list")?;
+            if let Some(first) = params.iter().position(|(param, _)| *param == param_name) {
+                return Err(ParseError {
+                    code: "E0104",
+                    message: format!("Function {} has more than one parameter named {}", name, param_name),
+                    span,
+                    label: Some(("first defined".to_string(), spans[first].clone())),
+                });
+            }
+            spans.push(span);
+            self.expect(Token::Colon)?;
+            let param_type = self.parse_type()?;
+            params.push((param_name, param_type));
+
+            if !self.eat(&Token::Comma) {
                 break;
             }
-            self.advance();
         }
-        
-        // Expect closing parenthesis
-        if let Some(token) = &self.current_token {
-            if !matches!(token.token, Token::RightParen) {
+        self.expect(Token::RightParen)?;
+
+        let return_type = if self.eat(&Token::Arrow) {
+            self.parse_type()?
+        } else {
+            Type::Void
+        };
+        Ok((params, return_type))
+    }
+
+    /// Parses `extern fn name(params) -> type;`, from the library named by
+    /// its `@link` attribute, if any
+    fn parse_extern_declaration(&mut self, start: usize, library: Option<String>) -> Result<Stmt> {
+        self.expect(Token::Extern)?;
+        self.expect(Token::Fn)?;
+        let (name, _) = self.expect_identifier("after 'fn'")?;
+        let (params, return_type) = self.parse_signature(&name)?;
+        self.eat(&Token::Semicolon);
+
+        Ok(Stmt::Extern(name, params, return_type, library, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    /// Parses an optional `<T, U>` list of type parameter names
+    fn parse_type_parameters(&mut self) -> Result<Vec<String>> {
+        let mut type_params = Vec::new();
+        if !self.eat(&Token::Less) {
+            return Ok(type_params);
+        }
+
+        while !self.check(&Token::Greater) {
+            let (param, span) = self.expect_identifier("in type parameter list")?;
+            if type_params.contains(&param) {
                 return Err(ParseError {
-                    message: "Expected ')' after parameter list".to_string(),
-                    span: Span { start: token.span.start, end: token.span.end },
+                    code: "E0104",
+                    message: format!("Duplicate type parameter: {}", param),
+                    span,
+                    label: None,
                 });
             }
+            type_params.push(param);
+
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::Greater)?;
+
+        Ok(type_params)
+    }
+
+    fn parse_return_statement(&mut self) -> Result<Stmt> {
+        let return_token = self.advance().unwrap();
+        let start = return_token.span.start;
+
+        let value = if self.check(&Token::Semicolon) || self.check(&Token::RightBrace) || self.current_token.is_none() {
+            None
         } else {
+            Some(self.parse_expression()?)
+        };
+
+        self.eat(&Token::Semicolon);
+
+        Ok(Stmt::Return(value, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Stmt> {
+        let while_token = self.advance().unwrap();
+        let start = while_token.span.start;
+
+        let condition = self.parse_expression()?;
+        let (stmts, body_span) = self.parse_block()?;
+
+        Ok(Stmt::While(
+            condition,
+            Box::new(Stmt::Block(stmts, body_span)),
+            Span { file: self.file, start, end: self.prev_end },
+        ))
+    }
+
+    fn parse_for_statement(&mut self) -> Result<Stmt> {
+        let for_token = self.advance().unwrap();
+        let start = for_token.span.start;
+
+        let (variable, variable_span) = self.expect_identifier("after 'for'")?;
+        self.expect(Token::In)?;
+        let iterable = self.parse_expression()?;
+        let (stmts, body_span) = self.parse_block()?;
+
+        Ok(Stmt::For(
+            variable,
+            variable_span,
+            iterable,
+            Box::new(Stmt::Block(stmts, body_span)),
+            Span { file: self.file, start, end: self.prev_end },
+        ))
+    }
+
+    fn parse_struct_declaration(&mut self) -> Result<Stmt> {
+        let struct_token = self.advance().unwrap();
+        let start = struct_token.span.start;
+
+        let (name, _) = self.expect_identifier("after 'struct'")?;
+        let type_params = self.parse_type_parameters()?;
+        self.type_params = type_params.clone();
+        self.expect(Token::LeftBrace)?;
+
+        let mut fields = Vec::new();
+        while !self.check(&Token::RightBrace) {
+            let (field_name, _) = self.expect_identifier("in struct body")?;
+            self.expect(Token::Colon)?;
+            let field_type = self.parse_type()?;
+            fields.push((field_name, field_type));
+
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::RightBrace)?;
+        self.type_params.clear();
+
+        Ok(Stmt::Struct(name, type_params, fields, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    fn parse_enum_declaration(&mut self) -> Result<Stmt> {
+        let enum_token = self.advance().unwrap();
+        let start = enum_token.span.start;
+
+        let (name, _) = self.expect_identifier("after 'enum'")?;
+        if self.check(&Token::Less) {
             return Err(ParseError {
-                message: "Expected ')' after parameter list".to_string(),
-                span: Span { start: start_pos, end: start_pos + 2 },
+                code: "E0006",
+                message: format!("Enum {} cannot have type parameters; generic enums are not supported yet", name),
+                span: self.current_span(),
+                label: None,
             });
         }
-        
-        // Consume the right parenthesis
-        self.advance();
-        
-        // Parse return type (optional)
-        let return_type = if let Some(token) = &self.current_token {
-            if matches!(token.token, Token::Arrow) {
-                self.advance(); // Consume the arrow
-                
-                // For now, we'll just assume it's a simple type
-                if let Some(type_token) = &self.current_token {
-                    if matches!(type_token.token, Token::Identifier) {
-                        self.advance(); // Consume the type
-                        Type::Int // Placeholder, we're not actually parsing the type yet
-                    } else {
-                        return Err(ParseError {
-                            message: "Expected return type after '->'".to_string(),
-                            span: Span { start: type_token.span.start, end: type_token.span.end },
-                        });
+        self.expect(Token::LeftBrace)?;
+
+        let mut variants = Vec::new();
+        while !self.check(&Token::RightBrace) {
+            let (variant, _) = self.expect_identifier("in enum body")?;
+            let mut payload = Vec::new();
+            if self.eat(&Token::LeftParen) {
+                while !self.check(&Token::RightParen) {
+                    payload.push(self.parse_type()?);
+                    if !self.eat(&Token::Comma) {
+                        break;
                     }
-                } else {
+                }
+                self.expect(Token::RightParen)?;
+            }
+            variants.push((variant, payload));
+
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::RightBrace)?;
+
+        Ok(Stmt::Enum(name, variants, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    fn parse_import_statement(&mut self) -> Result<Stmt> {
+        let import_token = self.advance().unwrap();
+        let start = import_token.span.start;
+
+        // Module paths are dotted identifiers, e.g. `import std.math;`
+        let (mut path, _) = self.expect_identifier("after 'import'")?;
+        while self.eat(&Token::Dot) {
+            let (segment, _) = self.expect_identifier("in module path")?;
+            path.push('.');
+            path.push_str(&segment);
+        }
+
+        self.eat(&Token::Semicolon);
+
+        Ok(Stmt::Import(path, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    fn parse_match_expression(&mut self) -> Result<Expr> {
+        let match_token = self.expect(Token::Match)?;
+        let start = match_token.span.start;
+
+        let scrutinee = self.parse_expression()?;
+        self.expect(Token::LeftBrace)?;
+
+        let mut arms = Vec::new();
+        while !self.check(&Token::RightBrace) {
+            let pattern = self.parse_pattern()?;
+            let arm_start = match &pattern {
+                Pattern::Variant(_, _, _, span) | Pattern::Wildcard(span) => span.start,
+            };
+            self.expect(Token::FatArrow)?;
+            let body = self.parse_expression()?;
+            let body_is_block = matches!(body, Expr::Block(..));
+            arms.push(MatchArm {
+                pattern,
+                body,
+                span: Span { file: self.file, start: arm_start, end: self.prev_end },
+            });
+
+            // Arms are separated by commas, which are optional after a block
+            if !self.eat(&Token::Comma) && !body_is_block {
+                break;
+            }
+        }
+        self.expect(Token::RightBrace)?;
+
+        Ok(Expr::Match(Box::new(scrutinee), arms, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    /// Parses `|a, b: int| body`, or `|| body` without parameters, where the
+    /// parameters may be followed by `-> type`
+    fn parse_lambda(&mut self) -> Result<Expr> {
+        let start = self.current_span().start;
+        let mut params = Vec::new();
+        let mut spans: Vec<Span> = Vec::new();
+        if !self.eat(&Token::Or) {
+            self.expect(Token::Pipe)?;
+            while !self.check(&Token::Pipe) {
+                let (name, span) = self.expect_identifier("as lambda parameter")?;
+                if let Some(first) = params.iter().position(|(param, _)| *param == name) {
                     return Err(ParseError {
-                        message: "Expected return type after '->'".to_string(),
-                        span: Span { start: start_pos, end: start_pos + 2 },
+                        code: "E0104",
+                        message: format!("Lambda has more than one parameter named {}", name),
+                        span,
+                        label: Some(("first defined".to_string(), spans[first].clone())),
                     });
                 }
-            } else {
-                Type::Void
+                spans.push(span);
+                let ty = if self.eat(&Token::Colon) {
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
+                params.push((name, ty));
+
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
             }
+            self.expect(Token::Pipe)?;
+        }
+
+        let return_type = if self.eat(&Token::Arrow) {
+            Some(self.parse_type()?)
         } else {
-            Type::Void
+            None
         };
-        
-        // Parse function body
-        if let Some(token) = &self.current_token {
-            if !matches!(token.token, Token::LeftBrace) {
-                return Err(ParseError {
-                    message: "Expected '{' to begin function body".to_string(),
-                    span: Span { start: token.span.start, end: token.span.end },
-                });
-            }
-        } else {
-            return Err(ParseError {
-                message: "Expected '{' to begin function body".to_string(),
-                span: Span { start: start_pos, end: start_pos + 2 },
-            });
+        let body = self.parse_expression()?;
+
+        Ok(Expr::Lambda(params, return_type, Box::new(body), Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    /// Parses `_`, `Variant`, `Enum.Variant` or either of the latter followed
+    /// by payload bindings such as `(x, y)`
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let (first, first_span) = self.expect_identifier("in match pattern")?;
+        let start = first_span.start;
+        if first == "_" {
+            return Ok(Pattern::Wildcard(first_span));
         }
-        
-        // Consume the left brace
-        self.advance();
-        
-        // For now, we'll just create an empty block
-        let body = Box::new(Stmt::Block(Vec::new(), Span { start: start_pos, end: start_pos + 2 }));
-        
-        // Skip until we find the closing brace
-        let mut brace_count = 1;
-        while let Some(token) = &self.current_token {
-            if matches!(token.token, Token::LeftBrace) {
-                brace_count += 1;
-            } else if matches!(token.token, Token::RightBrace) {
-                brace_count -= 1;
-                if brace_count == 0 {
+
+        let (qualifier, variant) = if self.eat(&Token::Dot) {
+            let (variant, _) = self.expect_identifier("after '.' in match pattern")?;
+            (Some(first), variant)
+        } else {
+            (None, first)
+        };
+
+        let mut bindings = Vec::new();
+        if self.eat(&Token::LeftParen) {
+            while !self.check(&Token::RightParen) {
+                let (binding, _) = self.expect_identifier("in match pattern")?;
+                bindings.push(binding);
+                if !self.eat(&Token::Comma) {
                     break;
                 }
             }
-            self.advance();
+            self.expect(Token::RightParen)?;
         }
-        
-        // Expect closing brace
-        if let Some(token) = &self.current_token {
-            if !matches!(token.token, Token::RightBrace) {
+
+        Ok(Pattern::Variant(qualifier, variant, bindings, Span { file: self.file, start, end: self.prev_end }))
+    }
+
+    fn parse_if_expression(&mut self) -> Result<Expr> {
+        let if_token = self.expect(Token::If)?;
+        let start = if_token.span.start;
+
+        let condition = self.parse_expression()?;
+        let then_branch = self.parse_block_expression()?;
+
+        let else_branch = if self.eat(&Token::Else) {
+            if self.check(&Token::If) {
+                Some(Box::new(self.parse_if_expression()?))
+            } else {
+                Some(Box::new(self.parse_block_expression()?))
+            }
+        } else {
+            None
+        };
+
+        Ok(Expr::If(
+            Box::new(condition),
+            Box::new(then_branch),
+            else_branch,
+            Span { file: self.file, start, end: self.prev_end },
+        ))
+    }
+
+    fn parse_block(&mut self) -> Result<(Vec<Stmt>, Span)> {
+        let left_brace = self.expect(Token::LeftBrace)?;
+        let start = left_brace.span.start;
+
+        let mut statements = Vec::new();
+        while !self.check(&Token::RightBrace) {
+            if self.current_token.is_none() {
                 return Err(ParseError {
-                    message: "Expected '}' to end function body".to_string(),
-                    span: Span { start: token.span.start, end: token.span.end },
+                    code: "E0003",
+                    message: "Expected '}' to close block, found end of file".to_string(),
+                    span: self.current_span(),
+                    label: None,
                 });
             }
-        } else {
-            return Err(ParseError {
-                message: "Expected '}' to end function body".to_string(),
-                span: Span { start: start_pos, end: start_pos + 2 },
-            });
+            statements.push(self.parse_statement()?);
         }
-        
-        // Consume the right brace
-        self.advance();
-        
-        // Create the function statement
-        let end_pos = if let Some(token) = &self.current_token {
-            token.span.start
-        } else {
-            start_pos + 10 // Just a placeholder
-        };
-        
-        Ok(Stmt::Function(name, params, return_type, body, Span { start: start_pos, end: end_pos }))
+        self.expect(Token::RightBrace)?;
+
+        Ok((statements, Span { file: self.file, start, end: self.prev_end }))
     }
 
-    fn parse_return_statement(&mut self) -> Result<Stmt> {
-        // Placeholder implementation
-        Err(ParseError {
-            message: "Return statement parsing not implemented yet".to_string(),
-            span: Span { start: 0, end: 0 },
-        })
+    fn parse_block_expression(&mut self) -> Result<Expr> {
+        let (statements, span) = self.parse_block()?;
+        Ok(Expr::Block(statements, span))
     }
 
-    fn parse_while_statement(&mut self) -> Result<Stmt> {
-        // Placeholder implementation
-        Err(ParseError {
-            message: "While statement parsing not implemented yet".to_string(),
-            span: Span { start: 0, end: 0 },
-        })
+    fn parse_expression(&mut self) -> Result<Expr> {
+        let start = self.parse_or()?;
+
+        // Ranges bind looser than every binary operator: `0..n + 1`
+        if self.eat(&Token::DotDot) {
+            let end = self.parse_or()?;
+            let span = Span { file: self.file, start: start.span().start, end: end.span().end };
+            return Ok(Expr::Range(Box::new(start), Box::new(end), span));
+        }
+
+        Ok(start)
     }
 
-    fn parse_for_statement(&mut self) -> Result<Stmt> {
-        // Placeholder implementation
-        Err(ParseError {
-            message: "For statement parsing not implemented yet".to_string(),
-            span: Span { start: 0, end: 0 },
-        })
+    /// Parses a left-associative chain of binary operators at one precedence level
+    fn parse_binary(
+        &mut self,
+        operand: fn(&mut Self) -> Result<Expr>,
+        operators: &[(Token, BinaryOp)],
+    ) -> Result<Expr> {
+        let mut left = operand(self)?;
+
+        'outer: loop {
+            for (token, op) in operators {
+                if self.eat(token) {
+                    let right = operand(self)?;
+                    let span = Span { file: self.file, start: left.span().start, end: right.span().end };
+                    left = Expr::Binary(Box::new(left), op.clone(), Box::new(right), span);
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
     }
 
-    fn parse_struct_declaration(&mut self) -> Result<Stmt> {
-        // Placeholder implementation
-        Err(ParseError {
-            message: "Struct declaration parsing not implemented yet".to_string(),
-            span: Span { start: 0, end: 0 },
-        })
+    fn parse_or(&mut self) -> Result<Expr> {
+        self.parse_binary(Self::parse_and, &[(Token::Or, BinaryOp::Or)])
     }
 
-    fn parse_import_statement(&mut self) -> Result<Stmt> {
-        // Placeholder implementation
-        Err(ParseError {
-            message: "Import statement parsing not implemented yet".to_string(),
-            span: Span { start: 0, end: 0 },
-        })
+    fn parse_and(&mut self) -> Result<Expr> {
+        self.parse_binary(Self::parse_equality, &[(Token::And, BinaryOp::And)])
     }
 
-    fn parse_if_expression(&mut self) -> Result<Expr> {
-        // Placeholder implementation
-        Err(ParseError {
-            message: "If expression parsing not implemented yet".to_string(),
-            span: Span { start: 0, end: 0 },
-        })
+    fn parse_equality(&mut self) -> Result<Expr> {
+        self.parse_binary(
+            Self::parse_comparison,
+            &[(Token::Equal, BinaryOp::Eq), (Token::NotEqual, BinaryOp::Neq)],
+        )
     }
 
-    fn parse_block_expression(&mut self) -> Result<Expr> {
-        // Placeholder implementation
-        Err(ParseError {
-            message: "Block expression parsing not implemented yet".to_string(),
-            span: Span { start: 0, end: 0 },
-        })
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        self.parse_binary(
+            Self::parse_additive,
+            &[
+                (Token::Less, BinaryOp::Lt),
+                (Token::LessEqual, BinaryOp::Lte),
+                (Token::Greater, BinaryOp::Gt),
+                (Token::GreaterEqual, BinaryOp::Gte),
+            ],
+        )
     }
 
-    fn parse_expression(&mut self) -> Result<Expr> {
-        // Placeholder implementation
-        self.parse_primary_expression()
+    fn parse_additive(&mut self) -> Result<Expr> {
+        self.parse_binary(
+            Self::parse_multiplicative,
+            &[(Token::Plus, BinaryOp::Add), (Token::Minus, BinaryOp::Sub)],
+        )
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr> {
+        self.parse_binary(
+            Self::parse_cast,
+            &[
+                (Token::Star, BinaryOp::Mul),
+                (Token::Slash, BinaryOp::Div),
+                (Token::Percent, BinaryOp::Mod),
+            ],
+        )
+    }
+
+    /// Parses `expr as type`, which binds tighter than binary operators
+    /// but looser than unary ones: `-x as float` casts `-x`
+    fn parse_cast(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_unary()?;
+
+        while self.eat(&Token::As) {
+            let target = self.parse_type()?;
+            let span = Span { file: self.file, start: expr.span().start, end: self.prev_end };
+            expr = Expr::Cast(Box::new(expr), target, span);
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        let start = self.current_span().start;
+        let op = if self.eat(&Token::Minus) {
+            UnaryOp::Neg
+        } else if self.eat(&Token::Not) {
+            UnaryOp::Not
+        } else {
+            return self.parse_postfix_expression();
+        };
+
+        let operand = self.parse_unary()?;
+        let span = Span { file: self.file, start, end: operand.span().end };
+        // A negated integer literal keeps its sign, so that the smallest
+        // integer can be written at all
+        if let (UnaryOp::Neg, Expr::Literal(Literal::Int(value), _)) = (&op, &operand) {
+            return Ok(Expr::Literal(Literal::Int(-value), span));
+        }
+        Ok(Expr::Unary(op, Box::new(operand), span))
+    }
+
+    fn parse_postfix_expression(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_primary_expression()?;
+
+        loop {
+            let start = expr.span().start;
+            if self.eat(&Token::LeftParen) {
+                let args = self.parse_expression_list(Token::RightParen)?;
+                expr = Expr::Call(Box::new(expr), args, Span { file: self.file, start, end: self.prev_end });
+            } else if self.eat(&Token::LeftBracket) {
+                let index = self.parse_expression()?;
+                self.expect(Token::RightBracket)?;
+                expr = Expr::Index(Box::new(expr), Box::new(index), Span { file: self.file, start, end: self.prev_end });
+            } else if self.eat(&Token::Dot) {
+                let (field, _) = self.expect_identifier("after '.'")?;
+                expr = Expr::Field(Box::new(expr), field, Span { file: self.file, start, end: self.prev_end });
+            } else if self.eat(&Token::Question) {
+                expr = Expr::Try(Box::new(expr), None, Span { file: self.file, start, end: self.prev_end });
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parses comma-separated expressions up to and including the closing token
+    fn parse_expression_list(&mut self, close: Token) -> Result<Vec<Expr>> {
+        let mut items = Vec::new();
+        while !self.check(&close) {
+            items.push(self.parse_expression()?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(close)?;
+        Ok(items)
     }
 
     fn parse_primary_expression(&mut self) -> Result<Expr> {
@@ -393,60 +877,128 @@ impl Parser {
                 let span = token.span.clone();
                 match &token.token {
                     Token::IntLiteral => {
-                        self.advance();
-                        Ok(Expr::Literal(Literal::Int(42), span.into())) // Placeholder
+                        let token = self.advance().unwrap();
+                        // The lexer only accepts digits, so parsing can only fail
+                        // on overflow. Whether the value fits the type it is
+                        // used as is up to the typechecker.
+                        let value = token.text.parse::<u64>().map_err(|_| ParseError {
+                            code: "E0007",
+                            message: format!(
+                                "Integer literal {} is out of range; no integer type goes beyond {}",
+                                token.text,
+                                u64::MAX
+                            ),
+                            span: self.span(span.clone()),
+                            label: None,
+                        })?;
+                        Ok(Expr::Literal(Literal::Int(value as i128), self.span(span)))
                     },
                     Token::FloatLiteral => {
-                        self.advance();
-                        Ok(Expr::Literal(Literal::Float(3.14), span.into())) // Placeholder
+                        let token = self.advance().unwrap();
+                        let value = token.text.parse::<f64>().map_err(|_| ParseError {
+                            code: "E0007",
+                            message: format!("Invalid float literal: {}", token.text),
+                            span: self.span(span.clone()),
+                            label: None,
+                        })?;
+                        Ok(Expr::Literal(Literal::Float(value), self.span(span)))
                     },
                     Token::StringLiteral => {
-                        self.advance();
-                        Ok(Expr::Literal(Literal::String("string".to_string()), span.into())) // Placeholder
+                        let token = self.advance().unwrap();
+                        let value = unescape_string(&token.text[1..token.text.len() - 1]);
+                        Ok(Expr::Literal(Literal::String(value), self.span(span)))
                     },
                     Token::True => {
                         self.advance();
-                        Ok(Expr::Literal(Literal::Bool(true), span.into()))
+                        Ok(Expr::Literal(Literal::Bool(true), self.span(span)))
                     },
                     Token::False => {
                         self.advance();
-                        Ok(Expr::Literal(Literal::Bool(false), span.into()))
+                        Ok(Expr::Literal(Literal::Bool(false), self.span(span)))
                     },
                     Token::Null => {
                         self.advance();
-                        Ok(Expr::Literal(Literal::Null, span.into()))
+                        Ok(Expr::Literal(Literal::Null, self.span(span)))
                     },
                     Token::Identifier => {
+                        let token = self.advance().unwrap();
+                        Ok(Expr::Identifier(token.text, self.span(span)))
+                    },
+                    Token::LeftParen => {
                         self.advance();
-                        Ok(Expr::Identifier("identifier".to_string(), span.into())) // Placeholder
+                        let expr = self.parse_expression()?;
+                        self.expect(Token::RightParen)?;
+                        Ok(expr)
                     },
+                    Token::LeftBracket => {
+                        self.advance();
+                        let elements = self.parse_expression_list(Token::RightBracket)?;
+                        Ok(Expr::Array(elements, Span { file: self.file, start: span.start, end: self.prev_end }))
+                    },
+                    Token::If => self.parse_if_expression(),
+                    Token::Match => self.parse_match_expression(),
+                    Token::Pipe | Token::Or => self.parse_lambda(),
+                    Token::LeftBrace => self.parse_block_expression(),
                     _ => Err(ParseError {
+                        code: "E0002",
                         message: format!("Unexpected token: {:?}", token.token),
-                        span: span.into(),
+                        span: self.span(span),
+                        label: None,
                     }),
                 }
             },
             None => Err(ParseError {
+                code: "E0003",
                 message: "Unexpected end of file".to_string(),
-                span: Span { start: 0, end: 0 },
+                span: self.current_span(),
+                label: None,
             }),
         }
     }
 
     fn parse_type(&mut self) -> Result<Type> {
+        let mut ty = self.parse_base_type()?;
+        // `T?` is an optional `T`, which may also be null
+        while self.eat(&Token::Question) {
+            ty = Type::Optional(Box::new(ty));
+        }
+        Ok(ty)
+    }
+
+    fn parse_base_type(&mut self) -> Result<Type> {
         match &self.current_token {
             Some(token) => {
                 match &token.token {
                     Token::Identifier => {
-                        let type_name = "type".to_string(); // Placeholder
-                        self.advance();
-                        
+                        let type_name = self.advance().unwrap().text;
+
                         match type_name.as_str() {
                             "int" => Ok(Type::Int),
+                            "i8" => Ok(Type::I8),
+                            "i16" => Ok(Type::I16),
+                            "i32" => Ok(Type::I32),
+                            "i64" => Ok(Type::I64),
+                            "u8" => Ok(Type::U8),
+                            "u16" => Ok(Type::U16),
+                            "u32" => Ok(Type::U32),
+                            "u64" => Ok(Type::U64),
                             "float" => Ok(Type::Float),
                             "bool" => Ok(Type::Bool),
                             "string" => Ok(Type::String),
                             "void" => Ok(Type::Void),
+                            _ if self.type_params.contains(&type_name) => Ok(Type::Generic(type_name)),
+                            _ if self.check(&Token::Less) => {
+                                self.advance(); // Consume '<'
+                                let mut args = Vec::new();
+                                while !self.check(&Token::Greater) {
+                                    args.push(self.parse_type()?);
+                                    if !self.eat(&Token::Comma) {
+                                        break;
+                                    }
+                                }
+                                self.expect(Token::Greater)?; // Expect '>'
+                                Ok(Type::Applied(type_name, args))
+                            },
                             _ => Ok(Type::Struct(type_name)),
                         }
                     },
@@ -459,41 +1011,89 @@ impl Parser {
                     Token::Fn => {
                         self.advance(); // Consume 'fn'
                         self.expect(Token::LeftParen)?; // Expect '('
-                        
+
                         let mut param_types = Vec::new();
-                        if self.current_token.as_ref().map(|t| t.token.clone()) != Some(Token::RightParen) {
+                        if !self.check(&Token::RightParen) {
                             loop {
                                 param_types.push(self.parse_type()?);
-                                
-                                if self.current_token.as_ref().map(|t| t.token.clone()) == Some(Token::Comma) {
-                                    self.advance(); // Consume ','
-                                } else {
+
+                                if !self.eat(&Token::Comma) {
                                     break;
                                 }
                             }
                         }
-                        
+
                         self.expect(Token::RightParen)?; // Expect ')'
                         self.expect(Token::Arrow)?; // Expect '->'
                         let return_type = self.parse_type()?;
-                        
+
                         Ok(Type::Function(param_types, Box::new(return_type)))
                     },
                     _ => Err(ParseError {
+                        code: "E0002",
                         message: format!("Expected type, found {:?}", token.token),
-                        span: token.span.clone().into(),
+                        span: self.span(token.span.clone()),
+                        label: None,
                     }),
                 }
             },
             None => Err(ParseError {
+                code: "E0003",
                 message: "Unexpected end of file while parsing type".to_string(),
-                span: Span { start: 0, end: 0 },
+                span: self.current_span(),
+                label: None,
             }),
         }
     }
 }
 
+/// Resolves backslash escapes in the body of a string literal
+fn unescape_string(raw: &str) -> String {
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+/// Parses the tokens of a file on its own, the first of its source map
 pub fn parse(tokens: Vec<LexerSpan>) -> Result<Program> {
-    let mut parser = Parser::new(tokens);
+    parse_file(tokens, FileId::default())
+}
+
+/// Parses the tokens of `file`, a file of a source map
+pub fn parse_file(tokens: Vec<LexerSpan>, file: FileId) -> Result<Program> {
+    let mut parser = Parser::new(tokens, file);
     parser.parse_program()
-}
\ No newline at end of file
+}
+
+/// Parses a single standalone expression, rejecting any trailing tokens
+pub fn parse_expression(tokens: Vec<LexerSpan>) -> Result<Expr> {
+    let mut parser = Parser::new(tokens, FileId::default());
+    let expr = parser.parse_expression()?;
+
+    if let Some(token) = &parser.current_token {
+        return Err(ParseError {
+            code: "E0002",
+            message: format!("Unexpected token after expression: {:?}", token.token),
+            span: parser.span(token.span.clone()),
+            label: None,
+        });
+    }
+
+    Ok(expr)
+}
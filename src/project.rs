@@ -0,0 +1,326 @@
+// Projects, described by a `z.toml` manifest in their directory:
+//
+//     [package]
+//     name = "app"
+//     entry = "src/main.z"
+//     source-dirs = ["src"]
+//     output-dir = "build"
+//     opt-level = 3
+//
+//     [dependencies]
+//     geometry = { path = "../geometry" }
+//     json = { git = "https://example.com/z-json.git", rev = "v1.2" }
+//
+//     [lint]
+//     naming = "deny"
+//     max-function-lines = 80
+//
+// Only the name is required; the rest defaults to the values above, and the
+// rules of `zc lint` to those in `lint`. Paths are
+// relative to the project directory. A dependency is a directory of modules,
+// or the source directory of another project, whose own dependencies the
+// project then depends on too. Git dependencies are fetched into a cache and
+// pinned to a commit in `z.lock`, see `packages`.
+
+use crate::lint::{Level, LintConfig};
+use crate::modules::ImportPaths;
+use crate::{packages, CompilerError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// The file describing a project
+pub const MANIFEST: &str = "z.toml";
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// The directory of `z.toml`
+    pub dir: PathBuf,
+    pub name: String,
+    /// The file with `main`
+    pub entry: PathBuf,
+    /// Directories modules are imported from, in the order searched
+    pub source_dirs: Vec<PathBuf>,
+    pub output_dir: PathBuf,
+    /// The optimization level unless the command line sets one
+    pub opt_level: u8,
+    pub dependencies: Vec<Dependency>,
+    /// The rules of `zc lint`
+    pub lint: LintConfig,
+}
+
+/// A package the project imports modules from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    pub source: Source,
+}
+
+/// Where a package comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A directory on this machine
+    Path(PathBuf),
+    /// A git repository, at a branch, tag or commit, or else its default
+    /// branch
+    Git { url: String, rev: Option<String> },
+}
+
+impl Manifest {
+    /// The manifest in `dir` or the nearest directory above it
+    pub fn find(dir: &Path) -> Result<Manifest> {
+        Manifest::search(dir)?.ok_or_else(|| {
+            CompilerError::ManifestError(format!("No {} found in this directory or above it", MANIFEST))
+        })
+    }
+
+    /// The manifest in `dir` or the nearest directory above it, if there is
+    /// one
+    pub fn search(dir: &Path) -> Result<Option<Manifest>> {
+        let mut dir = dir;
+        loop {
+            if dir.join(MANIFEST).is_file() {
+                return Manifest::load(dir).map(Some);
+            }
+            match dir.parent() {
+                Some(parent) => dir = parent,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The manifest of the project in `dir`
+    pub fn load(dir: &Path) -> Result<Manifest> {
+        let path = dir.join(MANIFEST);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| CompilerError::ManifestError(format!("Cannot read {}: {}", path.display(), e)))?;
+        Manifest::parse(&text, dir)
+            .map_err(|message| CompilerError::ManifestError(format!("{}: {}", path.display(), message)))
+    }
+
+    /// Reads the manifest text of the project in `dir`
+    pub fn parse(text: &str, dir: &Path) -> std::result::Result<Manifest, String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        check_keys(&table, "the manifest", &["package", "dependencies", "lint"])?;
+        let package = match table.get("package") {
+            Some(Value::Table(package)) => package,
+            Some(_) => return Err("package must be a table".to_string()),
+            None => return Err("The manifest needs a [package] table".to_string()),
+        };
+        check_keys(package, "[package]", &["name", "entry", "source-dirs", "output-dir", "opt-level"])?;
+
+        let name = string(package, "name")?.ok_or("The package needs a name")?;
+        check_name(name)?;
+        let entry = dir.join(string(package, "entry")?.unwrap_or("src/main.z"));
+        let source_dirs = match package.get("source-dirs") {
+            Some(Value::Array(dirs)) => dirs
+                .iter()
+                .map(|source_dir| match source_dir {
+                    Value::String(source_dir) => Ok(dir.join(source_dir)),
+                    _ => Err("source-dirs must be a list of strings".to_string()),
+                })
+                .collect::<std::result::Result<_, _>>()?,
+            Some(_) => return Err("source-dirs must be a list of strings".to_string()),
+            None => vec![dir.join("src")],
+        };
+        let output_dir = dir.join(string(package, "output-dir")?.unwrap_or("build"));
+        let opt_level = match package.get("opt-level") {
+            Some(Value::Integer(level @ 0..=3)) => *level as u8,
+            Some(_) => return Err("opt-level must be a number from 0 to 3".to_string()),
+            None => 3,
+        };
+
+        let mut dependencies = Vec::new();
+        match table.get("dependencies") {
+            Some(Value::Table(table)) => {
+                for (name, spec) in table {
+                    dependencies.push(dependency(name, spec, dir)?);
+                }
+            }
+            Some(_) => return Err("dependencies must be a table".to_string()),
+            None => {}
+        }
+        let mut lint = LintConfig::default();
+        match table.get("lint") {
+            Some(Value::Table(table)) => read_lint(table, &mut lint)?,
+            Some(_) => return Err("lint must be a table".to_string()),
+            None => {}
+        }
+
+        Ok(Manifest {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            entry,
+            source_dirs,
+            output_dir,
+            opt_level,
+            dependencies,
+            lint,
+        })
+    }
+
+    /// The executable a build writes
+    pub fn output(&self) -> PathBuf {
+        self.output_dir.join(format!("{}{}", self.name, std::env::consts::EXE_SUFFIX))
+    }
+
+    /// Where the program imports modules from: the directory of the entry
+    /// point, then the source directories, and the dependencies by name,
+    /// fetching those not fetched yet
+    pub fn import_paths(&self) -> Result<ImportPaths> {
+        let mut roots = vec![self.entry.parent().map(Path::to_path_buf).unwrap_or_default()];
+        for dir in &self.source_dirs {
+            if !roots.contains(dir) {
+                roots.push(dir.clone());
+            }
+        }
+        Ok(ImportPaths { roots, packages: packages::resolve(self)? })
+    }
+
+    /// Adds `dependency` to the manifest file, keeping the rest of it as it
+    /// is, and fetches it. The file is left as it was if that fails.
+    pub fn add_dependency(&self, dependency: &Dependency) -> Result<()> {
+        if self.dependencies.iter().any(|existing| existing.name == dependency.name) {
+            return Err(CompilerError::ManifestError(format!("{} is a dependency already", dependency.name)));
+        }
+        let spec = match &dependency.source {
+            Source::Path(path) => format!("{{ path = {} }}", Value::String(path.display().to_string())),
+            Source::Git { url, rev: None } => format!("{{ git = {} }}", Value::String(url.clone())),
+            Source::Git { url, rev: Some(rev) } => {
+                format!("{{ git = {}, rev = {} }}", Value::String(url.clone()), Value::String(rev.clone()))
+            }
+        };
+        let line = format!("{} = {}\n", dependency.name, spec);
+
+        let path = self.dir.join(MANIFEST);
+        let original = fs::read_to_string(&path)?;
+        let mut text = original.clone();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        // The table may end the file or be followed by another
+        let mut lines: Vec<&str> = text.split_inclusive('\n').collect();
+        match lines.iter().position(|existing| existing.trim() == "[dependencies]") {
+            Some(header) => {
+                let end = lines[header + 1..]
+                    .iter()
+                    .position(|existing| existing.trim_start().starts_with('['))
+                    .map_or(lines.len(), |next| header + 1 + next);
+                // Before the blank lines separating it from the next table
+                let end = (header + 1..end).rev().find(|&i| !lines[i].trim().is_empty()).map_or(header + 1, |i| i + 1);
+                lines.insert(end, &line);
+                text = lines.concat();
+            }
+            None => text.push_str(&format!("\n[dependencies]\n{}", line)),
+        }
+        fs::write(&path, text)?;
+        if let Err(e) = Manifest::load(&self.dir).and_then(|manifest| packages::resolve(&manifest)) {
+            fs::write(&path, original)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+/// Creates a project named `name` in `dir`, which is created unless it
+/// exists: its manifest, a `src/main.z` printing a greeting and a
+/// `.gitignore` for what builds write. Files already there are kept, except
+/// that a directory with a manifest is a project already.
+pub fn create(dir: &Path, name: &str) -> Result<Manifest> {
+    check_name(name).map_err(CompilerError::ManifestError)?;
+    if dir.join(MANIFEST).exists() {
+        return Err(CompilerError::ManifestError(format!("{} is a project already", dir.display())));
+    }
+    fs::create_dir_all(dir.join("src"))?;
+    let files = [
+        (dir.join(MANIFEST), format!("[package]\nname = {}\n", Value::String(name.to_string()))),
+        (dir.join("src/main.z"), "fn main() {\n    print(\"Hello, world!\");\n}\n".to_string()),
+        (dir.join(".gitignore"), "/build/\n.zc-cache/\n".to_string()),
+    ];
+    for (path, text) in files {
+        if !path.exists() {
+            fs::write(path, text)?;
+        }
+    }
+    Manifest::load(dir)
+}
+
+fn check_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("{} is not a package name: use letters, digits, _ and -", name));
+    }
+    Ok(())
+}
+
+fn dependency(name: &str, spec: &Value, dir: &Path) -> std::result::Result<Dependency, String> {
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("The dependency {} is imported by its name, which must be an identifier", name));
+    }
+    let Value::Table(spec) = spec else {
+        return Err(format!("The dependency {} must be a table, as in {{ path = \"../{}\" }}", name, name));
+    };
+    check_keys(spec, &format!("the dependency {}", name), &["path", "git", "rev"])?;
+    let source = match (string(spec, "path")?, string(spec, "git")?, string(spec, "rev")?) {
+        (Some(path), None, None) => Source::Path(dir.join(path)),
+        (None, Some(url), rev) => Source::Git { url: url.to_string(), rev: rev.map(str::to_string) },
+        (Some(_), Some(_), _) => return Err(format!("The dependency {} has both a path and a git repository", name)),
+        (Some(_), None, Some(_)) => return Err(format!("The dependency {} has a rev but no git repository", name)),
+        (None, None, _) => return Err(format!("The dependency {} needs a path or a git repository", name)),
+    };
+    // git would take them for options
+    if let Source::Git { url, rev } = &source {
+        if url.starts_with('-') {
+            return Err(format!("The git repository of the dependency {} cannot start with -: {}", name, url));
+        }
+        if let Some(rev) = rev.as_deref().filter(|rev| rev.starts_with('-')) {
+            return Err(format!("The rev of the dependency {} cannot start with -: {}", name, rev));
+        }
+    }
+    Ok(Dependency { name: name.to_string(), source })
+}
+
+/// Sets the rules of `config` that a `[lint]` table sets
+pub(crate) fn read_lint(table: &Table, config: &mut LintConfig) -> std::result::Result<(), String> {
+    check_keys(
+        table,
+        "[lint]",
+        &["shadowing", "naming", "long-functions", "max-function-lines", "constant-conditions"],
+    )?;
+    let level = |key: &str| -> std::result::Result<Option<Level>, String> {
+        string(table, key)?.map(|level| level.parse::<Level>().map_err(|e| format!("{}: {}", key, e))).transpose()
+    };
+    if let Some(level) = level("shadowing")? {
+        config.shadowing = level;
+    }
+    if let Some(level) = level("naming")? {
+        config.naming = level;
+    }
+    if let Some(level) = level("long-functions")? {
+        config.long_functions = level;
+    }
+    if let Some(level) = level("constant-conditions")? {
+        config.constant_conditions = level;
+    }
+    match table.get("max-function-lines") {
+        Some(Value::Integer(lines @ 1..)) => config.max_function_lines = *lines as usize,
+        Some(_) => return Err("max-function-lines must be a positive number".to_string()),
+        None => {}
+    }
+    Ok(())
+}
+
+pub(crate) fn string<'a>(table: &'a Table, key: &str) -> std::result::Result<Option<&'a str>, String> {
+    match table.get(key) {
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("{} must be a string", key)),
+        None => Ok(None),
+    }
+}
+
+/// Rejects keys other than `known`, which are most likely misspelled
+pub(crate) fn check_keys(table: &Table, place: &str, known: &[&str]) -> std::result::Result<(), String> {
+    match table.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) => Err(format!("Unknown key {} in {}", key, place)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,365 @@
+// Limits on the programs `zc run` runs, so that servers can run programs they
+// do not trust, such as graders and online playgrounds. A program running
+// past its time limit is killed. On Linux, a sandboxed program also runs in
+// namespaces of its own, with no network, and a seccomp filter stops it
+// from writing, creating or removing files, starting processes,
+// signalling or tracing others, taking their files and changing their
+// limits or priority. It can still read files and write to the terminal.
+
+use crate::{diagnostic, CompilerError, Result};
+use std::io::Read;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// What a program `zc run` runs may do
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunLimits {
+    /// How long it may run before it is killed
+    pub timeout: Option<Duration>,
+    /// Run it without network or write access, on Linux
+    pub sandbox: bool,
+}
+
+impl RunLimits {
+    /// Whether the program is limited in any way, and so has to run in a
+    /// process of its own
+    pub fn limited(&self) -> bool {
+        self.timeout.is_some() || self.sandbox
+    }
+}
+
+/// The exit code of a program killed for running out of time, as with
+/// `timeout(1)`
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Runs `command` within `limits`, with its output collected and printed
+/// once it exits when `capture` is set. Returns its status, or none when it
+/// ran out of time.
+pub(crate) fn run(mut command: Command, capture: bool, limits: &RunLimits) -> Result<Option<ExitStatus>> {
+    if limits.sandbox {
+        restrict(&mut command)?;
+    }
+    if capture {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let mut child = command.spawn().map_err(|e| match limits.sandbox {
+        true => CompilerError::EvalError(format!("Cannot start the program in a sandbox: {}", e)),
+        false => CompilerError::IoError(e),
+    })?;
+    // Read as the program runs, so that it never waits on a full pipe
+    let stdout = child.stdout.take().map(|pipe| std::thread::spawn(move || read_all(pipe)));
+    let stderr = child.stderr.take().map(|pipe| std::thread::spawn(move || read_all(pipe)));
+
+    let status = match limits.timeout {
+        Some(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break Some(status);
+                }
+                if Instant::now() >= deadline {
+                    child.kill()?;
+                    child.wait()?;
+                    break None;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        }
+        None => Some(child.wait()?),
+    };
+
+    // Print the output, and pass any panic the program reported to the sink
+    if let Some(stdout) = stdout {
+        print!("{}", String::from_utf8_lossy(&stdout.join().unwrap_or_default()));
+    }
+    if let Some(stderr) = stderr {
+        diagnostic::program_stderr(&stderr.join().unwrap_or_default());
+    }
+    Ok(status)
+}
+
+fn read_all(mut pipe: impl Read) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let _ = pipe.read_to_end(&mut bytes);
+    bytes
+}
+
+#[cfg(not(target_os = "linux"))]
+fn restrict(_command: &mut Command) -> Result<()> {
+    Err(CompilerError::EvalError("--sandbox is only supported on Linux".to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn restrict(command: &mut Command) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let mut filter = linux::filter()?;
+    // Runs in the child between fork and exec, where only system calls are
+    // safe: the filter is built beforehand
+    unsafe {
+        command.pre_exec(move || linux::enter(&mut filter));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use crate::{CompilerError, Result};
+    use std::os::raw::{c_int, c_ulong};
+
+    extern "C" {
+        fn getpid() -> c_int;
+        fn unshare(flags: c_int) -> c_int;
+        fn prctl(option: c_int, ...) -> c_int;
+    }
+
+    const CLONE_NEWIPC: c_int = 0x0800_0000;
+    const CLONE_NEWUSER: c_int = 0x1000_0000;
+    const CLONE_NEWNET: c_int = 0x4000_0000;
+    const PR_SET_SECCOMP: c_int = 22;
+    const PR_SET_NO_NEW_PRIVS: c_int = 38;
+    const SECCOMP_MODE_FILTER: c_ulong = 2;
+
+    const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+    const EPERM: u32 = 1;
+    const EACCES: u32 = 13;
+
+    // Stands for the process id of the program, which `enter` fills in
+    const OWN_PID: u32 = u32::MAX;
+
+    // Opening a file with any of these may change it
+    const O_WRONLY: u32 = 0o1;
+    const O_RDWR: u32 = 0o2;
+    const O_CREAT: u32 = 0o100;
+    const O_TRUNC: u32 = 0o1000;
+    const O_APPEND: u32 = 0o2000;
+    const WRITE_FLAGS: u32 = O_WRONLY | O_RDWR | O_CREAT | O_TRUNC | O_APPEND;
+
+    // Offsets into `struct seccomp_data`
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    const ARGS: u32 = 16;
+
+    #[cfg(target_arch = "x86_64")]
+    mod syscalls {
+        pub const AUDIT_ARCH: u32 = 0xc000_003e;
+        // System calls of the x32 ABI have this bit set
+        pub const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
+        /// System calls opening files, with the index of their flags
+        pub const OPEN: &[(u32, u32)] = &[(2, 1), (257, 2)];
+        /// System calls signalling a thread, with its process or itself first
+        pub const SIGNAL_THREAD: &[u32] = &[200, 234];
+        /// System calls refused outright
+        pub const DENIED: &[u32] = &[
+            437, // openat2, whose flags the filter cannot see
+            85,  // creat
+            76,  // truncate
+            82,  // rename
+            83,  // mkdir
+            84,  // rmdir
+            86,  // link
+            87,  // unlink
+            88,  // symlink
+            90,  // chmod
+            91,  // fchmod
+            92,  // chown
+            93,  // fchown
+            94,  // lchown
+            132, // utime
+            133, // mknod
+            188, // setxattr
+            189, // lsetxattr
+            190, // fsetxattr
+            197, // removexattr
+            198, // lremovexattr
+            199, // fremovexattr
+            235, // utimes
+            258, // mkdirat
+            259, // mknodat
+            260, // fchownat
+            263, // unlinkat
+            264, // renameat
+            265, // linkat
+            261, // futimesat
+            266, // symlinkat
+            268, // fchmodat
+            280, // utimensat
+            316, // renameat2
+            452, // fchmodat2
+            41,  // socket
+            42,  // connect
+            49,  // bind
+            56,  // clone
+            57,  // fork
+            58,  // vfork
+            435, // clone3
+            62,  // kill
+            129, // rt_sigqueueinfo
+            297, // rt_tgsigqueueinfo
+            424, // pidfd_send_signal
+            434, // pidfd_open
+            438, // pidfd_getfd
+            302, // prlimit64
+            141, // setpriority
+            101, // ptrace
+            310, // process_vm_readv
+            311, // process_vm_writev
+            165, // mount
+            425, // io_uring_setup
+        ];
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    mod syscalls {
+        pub const AUDIT_ARCH: u32 = 0xc000_00b7;
+        pub const X32_SYSCALL_BIT: Option<u32> = None;
+        /// System calls opening files, with the index of their flags
+        pub const OPEN: &[(u32, u32)] = &[(56, 2)];
+        /// System calls signalling a thread, with its process or itself first
+        pub const SIGNAL_THREAD: &[u32] = &[130, 131];
+        /// System calls refused outright
+        pub const DENIED: &[u32] = &[
+            437, // openat2, whose flags the filter cannot see
+            45,  // truncate
+            5,   // setxattr
+            6,   // lsetxattr
+            7,   // fsetxattr
+            14,  // removexattr
+            15,  // lremovexattr
+            16,  // fremovexattr
+            33,  // mknodat
+            34,  // mkdirat
+            35,  // unlinkat
+            36,  // symlinkat
+            37,  // linkat
+            38,  // renameat
+            52,  // fchmod
+            53,  // fchmodat
+            54,  // fchownat
+            55,  // fchown
+            88,  // utimensat
+            276, // renameat2
+            452, // fchmodat2
+            198, // socket
+            200, // bind
+            203, // connect
+            220, // clone
+            435, // clone3
+            129, // kill
+            138, // rt_sigqueueinfo
+            240, // rt_tgsigqueueinfo
+            424, // pidfd_send_signal
+            434, // pidfd_open
+            438, // pidfd_getfd
+            261, // prlimit64
+            140, // setpriority
+            117, // ptrace
+            270, // process_vm_readv
+            271, // process_vm_writev
+            40,  // mount
+            425, // io_uring_setup
+        ];
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    mod syscalls {
+        pub const AUDIT_ARCH: u32 = 0;
+        pub const X32_SYSCALL_BIT: Option<u32> = None;
+        pub const OPEN: &[(u32, u32)] = &[];
+        pub const SIGNAL_THREAD: &[u32] = &[];
+        pub const DENIED: &[u32] = &[];
+    }
+
+    /// An instruction of a classic BPF program, as `struct sock_filter`
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Instruction {
+        code: u16,
+        jt: u8,
+        jf: u8,
+        k: u32,
+    }
+
+    #[repr(C)]
+    struct Program {
+        len: u16,
+        filter: *const Instruction,
+    }
+
+    const LD_ABS: u16 = 0x20;
+    const JEQ: u16 = 0x15;
+    const JGE: u16 = 0x35;
+    const JSET: u16 = 0x45;
+    const RET: u16 = 0x06;
+
+    fn op(code: u16, k: u32, jt: u8, jf: u8) -> Instruction {
+        Instruction { code, jt, jf, k }
+    }
+
+    /// The seccomp filter of a sandboxed program
+    pub fn filter() -> Result<Vec<Instruction>> {
+        if syscalls::AUDIT_ARCH == 0 {
+            return Err(CompilerError::EvalError(
+                "--sandbox is only supported on x86_64 and aarch64".to_string(),
+            ));
+        }
+        let mut filter = vec![
+            // System calls of another architecture are not what they seem
+            op(LD_ABS, ARCH, 0, 0),
+            op(JEQ, syscalls::AUDIT_ARCH, 1, 0),
+            op(RET, SECCOMP_RET_KILL_PROCESS, 0, 0),
+            op(LD_ABS, NR, 0, 0),
+        ];
+        if let Some(bit) = syscalls::X32_SYSCALL_BIT {
+            filter.push(op(JGE, bit, 0, 1));
+            filter.push(op(RET, SECCOMP_RET_KILL_PROCESS, 0, 0));
+        }
+        for &(nr, flags) in syscalls::OPEN {
+            filter.push(op(JEQ, nr, 0, 4));
+            filter.push(op(LD_ABS, ARGS + 8 * flags, 0, 0));
+            filter.push(op(JSET, WRITE_FLAGS, 0, 1));
+            filter.push(op(RET, SECCOMP_RET_ERRNO | EACCES, 0, 0));
+            filter.push(op(LD_ABS, NR, 0, 0));
+        }
+        // Threads of the program may signal each other, as `raise` and
+        // `abort` do, but not threads of other processes
+        for &nr in syscalls::SIGNAL_THREAD {
+            filter.push(op(JEQ, nr, 0, 4));
+            filter.push(op(LD_ABS, ARGS, 0, 0));
+            filter.push(op(JEQ, OWN_PID, 1, 0));
+            filter.push(op(RET, SECCOMP_RET_ERRNO | EPERM, 0, 0));
+            filter.push(op(LD_ABS, NR, 0, 0));
+        }
+        for &nr in syscalls::DENIED {
+            filter.push(op(JEQ, nr, 0, 1));
+            filter.push(op(RET, SECCOMP_RET_ERRNO | EPERM, 0, 0));
+        }
+        filter.push(op(RET, SECCOMP_RET_ALLOW, 0, 0));
+        Ok(filter)
+    }
+
+    /// Moves the calling process into namespaces of its own and installs
+    /// `filter`. Exec is left allowed, to start the program: what it runs in
+    /// its place stays as restricted, with the same process id.
+    pub fn enter(filter: &mut [Instruction]) -> std::io::Result<()> {
+        let pid = unsafe { getpid() } as u32;
+        for instruction in filter.iter_mut().filter(|instruction| instruction.code == JEQ && instruction.k == OWN_PID) {
+            instruction.k = pid;
+        }
+        let program = Program {
+            len: filter.len() as u16,
+            filter: filter.as_ptr(),
+        };
+        unsafe {
+            if unshare(CLONE_NEWUSER | CLONE_NEWNET | CLONE_NEWIPC) != 0
+                || prctl(PR_SET_NO_NEW_PRIVS, 1 as c_ulong, 0 as c_ulong, 0 as c_ulong, 0 as c_ulong) != 0
+                || prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &program as *const Program) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
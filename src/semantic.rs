@@ -0,0 +1,92 @@
+// Semantic model of a checked Z program, used by debugger and REPL tooling
+
+use crate::ast::Program;
+use crate::interpreter::{Interpreter, Value};
+use crate::source_map::{FileId, SourceMap};
+use crate::symbols::SymbolTable;
+use crate::{lexer, parser, typechecker, CompilerError, Result};
+
+pub struct SemanticModel {
+    files: SourceMap,
+    program: Program,
+    symbols: SymbolTable,
+}
+
+// The names the model's source and evaluated expressions go by in errors
+const SOURCE: &str = "<source>";
+const EXPRESSION: &str = "<expression>";
+
+impl SemanticModel {
+    /// Lexes, parses and typechecks `source` into a model that can be queried
+    pub fn new(source: &str) -> Result<Self> {
+        let tokens = lexer::lex(source)
+            .map_err(|e| CompilerError::at(SOURCE, source, &[e]))?;
+        let ast = parser::parse(tokens)
+            .map_err(|e| CompilerError::at(SOURCE, source, &[e]))?;
+        let checked = typechecker::typecheck(ast)
+            .map_err(|errors| CompilerError::at(SOURCE, source, &errors))?;
+
+        let mut files = SourceMap::new();
+        files.add(SOURCE, source);
+        Ok(Self {
+            files,
+            program: checked.program,
+            symbols: checked.symbols,
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// The source of the model, as the one file the spans of `program` are
+    /// in
+    pub fn files(&self) -> &SourceMap {
+        &self.files
+    }
+
+    /// Definitions and references of every name in the program
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Evaluates `expr_src` as if it were written at `location`, a byte offset
+    /// into the model's source.
+    ///
+    /// The program is interpreted until execution first reaches the statement
+    /// at `location`; the expression is then typechecked and evaluated against
+    /// the variables in scope at that point. Output printed by the program
+    /// while running up to the location is discarded.
+    pub fn eval_in_scope(&self, expr_src: &str, location: usize) -> Result<Value> {
+        let tokens = lexer::lex(expr_src)
+            .map_err(|e| CompilerError::at(EXPRESSION, expr_src, &[e]))?;
+        let expr = parser::parse_expression(tokens)
+            .map_err(|e| CompilerError::at(EXPRESSION, expr_src, &[e]))?;
+
+        let mut interpreter = Interpreter::new(&self.program);
+        let scope = interpreter
+            .run_until(&self.program, location)
+            .map_err(|e| CompilerError::EvalError(e.to_string()))?
+            .ok_or_else(|| {
+                CompilerError::EvalError(format!(
+                    "Location {} is never reached when running the program",
+                    location
+                ))
+            })?;
+
+        let mut checker = typechecker::TypeChecker::new();
+        checker
+            .register_declarations(&self.program)
+            .map_err(|errors| self.files.errors(FileId::default(), &errors))?;
+        for (name, value) in &scope {
+            checker.declare_variable(name.clone(), value.type_of());
+        }
+        let (expr, _) = checker
+            .check_expression(expr)
+            .map_err(|e| CompilerError::at(EXPRESSION, expr_src, &[e]))?;
+
+        interpreter
+            .evaluate(&expr, scope)
+            .map_err(|e| CompilerError::EvalError(e.to_string()))
+    }
+}
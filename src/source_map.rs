@@ -0,0 +1,112 @@
+// The files a compilation reads: the file compiled and the modules it
+// imports, each added once and named by a `FileId`. The spans of a syntax
+// tree carry the id of their file, so that diagnostics, and tools such as
+// editors working on several files, find the file any span is in.
+
+use crate::ast::{self, Span};
+use crate::diagnostic::ToDiagnostic;
+use crate::{CompilerError, Diagnostic};
+
+/// A file of a `SourceMap`. The default id is that of the first file added,
+/// which is the file parsed when one is parsed on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(u32);
+
+impl FileId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// The file as it was named to the compiler
+    pub name: String,
+    pub source: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    /// The one-based line and column of byte `offset`, counting characters
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = ast::line_of(&self.line_starts, offset);
+        let before = self.source.get(self.line_starts[line]..offset).unwrap_or_default();
+        (line + 1, before.chars().count() + 1)
+    }
+}
+
+/// The files loaded, by id
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the file `name` whose text is `source`. A file of that name
+    /// added before keeps its id and has its text replaced, as when an
+    /// editor changes it.
+    pub fn add(&mut self, name: &str, source: &str) -> FileId {
+        let file = SourceFile {
+            name: name.to_string(),
+            source: source.to_string(),
+            line_starts: ast::line_starts(source),
+        };
+        match self.find(name) {
+            Some(id) => {
+                self.files[id.index()] = file;
+                id
+            }
+            None => {
+                self.files.push(file);
+                FileId(self.files.len() as u32 - 1)
+            }
+        }
+    }
+
+    /// The file `id`, which has to be of this map
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.index()]
+    }
+
+    /// The id of the file `name`, if it was added
+    pub fn find(&self, name: &str) -> Option<FileId> {
+        self.files.iter().position(|file| file.name == name).map(|index| FileId(index as u32))
+    }
+
+    /// The files in the order they were added
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files.iter().enumerate().map(|(index, file)| (FileId(index as u32), file))
+    }
+
+    /// The name of the file `span` is in, and the line and column it
+    /// starts at
+    pub fn location(&self, span: &Span) -> (&str, usize, usize) {
+        let file = self.file(span.file);
+        let (line, column) = file.position(span.start);
+        (&file.name, line, column)
+    }
+
+    /// The errors `problems`, each shown in the file of its span, or in
+    /// `file` when it has none
+    pub(crate) fn errors<T: ToDiagnostic>(&self, file: FileId, problems: &[T]) -> CompilerError {
+        CompilerError::Diagnostics(self.diagnostics(file, problems))
+    }
+
+    /// The diagnostics of `problems`, each in the file of its span, or in
+    /// `file` when it has none
+    pub(crate) fn diagnostics<T: ToDiagnostic>(&self, file: FileId, problems: &[T]) -> Vec<Diagnostic> {
+        problems
+            .iter()
+            .map(|problem| {
+                let file = problem.file().and_then(|id| self.files.get(id.index())).unwrap_or(self.file(file));
+                problem.to_diagnostic(&file.name, &file.source)
+            })
+            .collect()
+    }
+}
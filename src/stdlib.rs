@@ -0,0 +1,65 @@
+// The standard library: modules of Z shipped inside the compiler, which
+// `import std.io;` imports like any other module, wherever the program is.
+// What Z cannot do itself, a module does in C of its own, declared to it with
+// `extern` and compiled into its object, so that programs importing it link
+// it without further flags. Those externs are named with the runtime's `z_`
+// prefix, which has them take and return strings as `z_string`s rather than
+// as the C library's `const char*`.
+//
+// `std.math` is the exception: its functions take ints and floats alike,
+// which functions of Z cannot, so the compiler provides them itself, mapped
+// onto libm, once a program imports it.
+
+use std::path::PathBuf;
+
+struct StdModule {
+    name: &'static str,
+    source: &'static str,
+    /// The C the module's externs are defined in
+    #[cfg_attr(not(feature = "c"), allow(dead_code))]
+    runtime: &'static str,
+}
+
+const MODULES: &[StdModule] = &[
+    StdModule {
+        name: "std.io",
+        source: include_str!("../std/io.z"),
+        runtime: include_str!("../std/io.c"),
+    },
+    StdModule {
+        name: "std.random",
+        source: include_str!("../std/random.z"),
+        runtime: include_str!("../std/random.c"),
+    },
+];
+
+/// The module the compiler provides itself, which has no file
+pub(crate) const MATH: &str = "std.math";
+
+/// The functions of `std.math`
+pub(crate) const MATH_FUNCTIONS: &[&str] = &["sqrt", "pow", "abs", "floor", "ceil", "min", "max"];
+
+fn find(name: &str) -> Option<&'static StdModule> {
+    MODULES.iter().find(|module| module.name == name)
+}
+
+/// Whether `name` is a module of the standard library
+pub(crate) fn contains(name: &str) -> bool {
+    find(name).is_some() || name == MATH
+}
+
+/// The file module `name` of the standard library is shown as, and its
+/// source
+pub(crate) fn load(name: &str) -> Option<(PathBuf, &'static str)> {
+    let module = find(name)?;
+    let path = module.name.trim_start_matches("std.").replace('.', "/");
+    let file = PathBuf::from("<std>").join(path).with_extension("z");
+    Some((file, module.source))
+}
+
+/// The C defining the externs of module `name`, if it is of the standard
+/// library
+#[cfg(feature = "c")]
+pub(crate) fn runtime(name: &str) -> Option<&'static str> {
+    find(name).map(|module| module.runtime)
+}
@@ -0,0 +1,91 @@
+// Symbol table produced by the typechecker, for editor tooling
+
+use crate::ast::{Span, Type};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Constant,
+    Function,
+    Struct,
+    Enum,
+}
+
+/// A named entity of the program, where it is defined and where it is used
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub ty: Type,
+    /// Span of the declaration that introduces the symbol. Parameters share
+    /// the span of their function.
+    pub definition: Span,
+    /// Spans of the identifiers that refer to the symbol, in source order
+    pub references: Vec<Span>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+}
+
+fn contains(span: &Span, offset: usize) -> bool {
+    span.start <= offset && offset < span.end
+}
+
+impl SymbolTable {
+    /// Adds a symbol, merging it with an existing one with the same name and
+    /// definition (as happens for generic functions, which are checked once
+    /// per instantiation)
+    pub(crate) fn add(&mut self, mut symbol: Symbol) {
+        let existing = self
+            .symbols
+            .iter_mut()
+            .find(|existing| existing.name == symbol.name && existing.definition == symbol.definition);
+        let target = match existing {
+            Some(existing) => {
+                existing.references.append(&mut symbol.references);
+                existing
+            }
+            None => {
+                self.symbols.push(symbol);
+                self.symbols.last_mut().unwrap()
+            }
+        };
+        target.references.sort_by_key(|span| span.start);
+        target.references.dedup();
+    }
+
+    /// The symbol referred to or defined at byte `offset`. References win
+    /// over definitions, and among definitions the innermost one wins.
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        let referenced = self
+            .symbols
+            .iter()
+            .find(|symbol| symbol.references.iter().any(|span| contains(span, offset)));
+        referenced.or_else(|| {
+            self.symbols
+                .iter()
+                .filter(|symbol| contains(&symbol.definition, offset))
+                // Parameters share their function's span; prefer the function
+                .min_by_key(|symbol| {
+                    (symbol.definition.end - symbol.definition.start, symbol.kind == SymbolKind::Parameter)
+                })
+        })
+    }
+
+    /// Where the symbol at `offset` is defined
+    pub fn definition_at(&self, offset: usize) -> Option<&Span> {
+        self.symbol_at(offset).map(|symbol| &symbol.definition)
+    }
+
+    /// Every reference to the symbol at `offset`
+    pub fn references_at(&self, offset: usize) -> &[Span] {
+        self.symbol_at(offset).map(|symbol| symbol.references.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn lookup<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Symbol> + 'a {
+        self.symbols.iter().filter(move |symbol| symbol.name == name)
+    }
+}
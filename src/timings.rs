@@ -0,0 +1,191 @@
+// Self-profiling for `--timings`: the wall time of each stage the compiler
+// goes through, the most memory the process has used by the end of it, and
+// the size of what the stage generated, such as C or an executable.
+//
+// Stages are recorded on the thread that called `start`, and only once it
+// has, so that compiling without `--timings` costs nothing more than a check.
+
+use serde_json::{json, Value as Json};
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// How `--timings` prints what it recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingsFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for TimingsFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        match name {
+            "text" => Ok(TimingsFormat::Text),
+            "json" => Ok(TimingsFormat::Json),
+            _ => Err(format!("unknown timings format {}, expected text or json", name)),
+        }
+    }
+}
+
+/// A stage of the compiler, as it went
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub name: &'static str,
+    pub wall: Duration,
+    /// The most memory the process has used, in bytes, by the end of the
+    /// stage; the C compiler runs in processes of its own and is left out.
+    /// Unknown except on Linux.
+    pub peak_memory: Option<u64>,
+    /// The size in bytes of what the stage generated, for those that
+    /// generate code
+    pub output_size: Option<u64>,
+}
+
+/// The stages of a command, in the order they ran
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub stages: Vec<Stage>,
+    /// From `start` to `finish`, including what no stage covers
+    pub total: Duration,
+    pub peak_memory: Option<u64>,
+}
+
+impl Timings {
+    /// The timings as a table, a line per stage and one for the total
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{:<10} {:>12} {:>12} {:>12}\n", "stage", "time", "peak memory", "output");
+        let rows = self.stages.iter().map(|stage| (stage.name, stage.wall, stage.peak_memory, stage.output_size));
+        for (name, wall, peak_memory, output_size) in rows.chain([("total", self.total, self.peak_memory, None)]) {
+            let row = format!(
+                "{:<10} {:>10.3}ms {:>12} {:>12}",
+                name,
+                wall.as_secs_f64() * 1000.0,
+                peak_memory.map(|bytes| format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))).unwrap_or_default(),
+                output_size.map(|bytes| format!("{} B", bytes)).unwrap_or_default()
+            );
+            text.push_str(row.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The timings as JSON, for tools tracking the compiler's performance
+    pub fn to_json(&self) -> Json {
+        let stages: Vec<Json> = self
+            .stages
+            .iter()
+            .map(|stage| {
+                json!({
+                    "stage": stage.name,
+                    "wall_ms": stage.wall.as_secs_f64() * 1000.0,
+                    "peak_memory_bytes": stage.peak_memory,
+                    "output_bytes": stage.output_size,
+                })
+            })
+            .collect();
+        json!({
+            "stages": stages,
+            "total_ms": self.total.as_secs_f64() * 1000.0,
+            "peak_memory_bytes": self.peak_memory,
+        })
+    }
+}
+
+struct Recording {
+    start: Instant,
+    stages: Vec<Stage>,
+}
+
+thread_local! {
+    static RECORDING: RefCell<Option<Recording>> = const { RefCell::new(None) };
+}
+
+/// Starts recording the stages the compiler goes through on this thread
+pub fn start() {
+    RECORDING.with(|recording| {
+        *recording.borrow_mut() = Some(Recording {
+            start: Instant::now(),
+            stages: Vec::new(),
+        })
+    });
+}
+
+/// Stops recording, returning the stages recorded since `start`
+pub fn finish() -> Timings {
+    match RECORDING.with(|recording| recording.borrow_mut().take()) {
+        Some(recording) => Timings {
+            stages: recording.stages,
+            total: recording.start.elapsed(),
+            peak_memory: peak_memory(),
+        },
+        None => Timings::default(),
+    }
+}
+
+fn recording() -> bool {
+    RECORDING.with(|recording| recording.borrow().is_some())
+}
+
+/// Runs `body` as the stage `name`, recording it if `start` was called
+pub(crate) fn stage<T>(name: &'static str, body: impl FnOnce() -> T) -> T {
+    if !recording() {
+        return body();
+    }
+    let start = Instant::now();
+    let result = body();
+    let stage = Stage {
+        name,
+        wall: start.elapsed(),
+        peak_memory: peak_memory(),
+        output_size: None,
+    };
+    RECORDING.with(|recording| {
+        if let Some(recording) = recording.borrow_mut().as_mut() {
+            recording.stages.push(stage);
+        }
+    });
+    result
+}
+
+/// Runs `body`, returning the stages it went through whether or not `start`
+/// was called
+#[cfg(feature = "c")]
+pub(crate) fn record<T>(body: impl FnOnce() -> T) -> (T, Vec<Stage>) {
+    if recording() {
+        let before = RECORDING.with(|recording| recording.borrow().as_ref().map_or(0, |recording| recording.stages.len()));
+        let result = body();
+        let stages = RECORDING.with(|recording| {
+            recording.borrow().as_ref().map(|recording| recording.stages[before..].to_vec()).unwrap_or_default()
+        });
+        (result, stages)
+    } else {
+        start();
+        let result = body();
+        (result, finish().stages)
+    }
+}
+
+/// Records that the last stage generated `bytes` bytes
+#[cfg(feature = "c")]
+pub(crate) fn output_size(bytes: u64) {
+    RECORDING.with(|recording| {
+        if let Some(stage) = recording.borrow_mut().as_mut().and_then(|recording| recording.stages.last_mut()) {
+            stage.output_size = Some(bytes);
+        }
+    });
+}
+
+/// The most memory the process has used so far, in bytes
+#[cfg(target_os = "linux")]
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_memory() -> Option<u64> {
+    None
+}
@@ -0,0 +1,234 @@
+// What `zc env` and `zc targets` report about the machine: the C compilers
+// installed, what they can build, and where `zc` keeps its caches, to debug
+// builds that work on one machine and not on another.
+
+use crate::codegen::{CBackend, CCompiler, Gc};
+use crate::config::Config;
+use std::path::PathBuf;
+
+/// A C compiler `zc` can build with
+#[derive(Debug, Clone)]
+pub struct CompilerInfo {
+    pub backend: CBackend,
+    pub command: String,
+    /// The first line of its `--version`, or none when it is not installed
+    pub version: Option<String>,
+    /// The target it builds for unless told otherwise
+    pub host: Option<String>,
+}
+
+/// The C compilers of each backend, found by their default commands
+pub fn compilers() -> Vec<CompilerInfo> {
+    CBackend::ALL
+        .into_iter()
+        .map(|backend| {
+            let compiler = CCompiler::of_backend(backend);
+            let version = compiler.version();
+            let host = version.as_ref().and_then(|_| compiler.target_triple());
+            CompilerInfo {
+                backend,
+                command: compiler.command,
+                version,
+                host,
+            }
+        })
+        .collect()
+}
+
+/// Everything `zc env` prints
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub version: &'static str,
+    /// The architecture and operating system `zc` runs on
+    pub host: String,
+    pub compilers: Vec<CompilerInfo>,
+    /// The compiler builds use, with the flags and configuration in effect
+    pub compiler: Option<CCompiler>,
+    /// The features of the runtime, and whether they work with `compiler`
+    pub features: Vec<(&'static str, bool)>,
+    /// The configuration file read, if any
+    pub config: Option<PathBuf>,
+    /// The environment variables `zc` reads that are set
+    pub variables: Vec<(&'static str, String)>,
+    /// Where executables of `zc run` are cached
+    pub run_cache: Option<PathBuf>,
+    /// Where git dependencies are cloned
+    pub package_cache: Option<PathBuf>,
+}
+
+const VARIABLES: [&str; 5] = ["ZC_CC", "ZC_CFLAGS", "ZC_HOME", "XDG_CACHE_HOME", "NO_COLOR"];
+
+// A program using what each feature needs from the C compiler
+const HELLO: &str = "#include <stdio.h>\nint main(void) { puts(\"hello\"); return 0; }\n";
+const BOEHM: &str = "#include <gc.h>\nint main(void) { GC_INIT(); return GC_MALLOC(8) == 0; }\n";
+
+impl Environment {
+    /// Looks at the machine, with `compiler` the one builds would use, if
+    /// one is installed
+    pub fn detect(compiler: Option<CCompiler>, config: &Config) -> Self {
+        let features = match &compiler {
+            Some(compiler) => {
+                let with_flag = |flag: &str| {
+                    let mut compiler = compiler.clone();
+                    compiler.flags.push(flag.to_string());
+                    compiler.can_build(HELLO)
+                };
+                let mut boehm = compiler.clone();
+                boehm.link_gc(Gc::Boehm);
+                vec![
+                    ("gc rc", compiler.can_build(HELLO)),
+                    ("gc boehm (libgc)", boehm.can_build(BOEHM)),
+                    ("address sanitizer", with_flag("-fsanitize=address")),
+                    ("undefined behavior sanitizer", with_flag("-fsanitize=undefined")),
+                    ("jit", cfg!(feature = "jit")),
+                ]
+            }
+            None => vec![("jit", cfg!(feature = "jit"))],
+        };
+        Environment {
+            version: env!("CARGO_PKG_VERSION"),
+            host: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            compilers: compilers(),
+            compiler,
+            features,
+            config: config.path.clone(),
+            variables: VARIABLES
+                .into_iter()
+                .filter_map(|name| std::env::var(name).ok().map(|value| (name, value)))
+                .collect(),
+            run_cache: crate::run_cache_dir(),
+            package_cache: crate::packages::cache_dir().ok(),
+        }
+    }
+
+    /// The environment as text, a section at a time
+    pub fn to_text(&self) -> String {
+        let mut text = format!("zc {}\nhost: {}\n\nC compilers:\n", self.version, self.host);
+        for compiler in &self.compilers {
+            let found = match (&compiler.version, &compiler.host) {
+                (Some(version), Some(host)) => format!("{} ({})", version, host),
+                (Some(version), None) => version.clone(),
+                (None, _) => "not found".to_string(),
+            };
+            text.push_str(&format!("  {:<6} {:<9} {}\n", compiler.backend.name(), compiler.command, found));
+        }
+        match &self.compiler {
+            Some(compiler) => {
+                let mut line = std::iter::once(compiler.command.as_str())
+                    .chain(compiler.flags.iter().map(String::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if let Some(target) = &compiler.target {
+                    line.push_str(&format!(" (target {})", target));
+                }
+                text.push_str(&format!("Builds use: {}\n", line));
+            }
+            None => text.push_str("Builds use: no C compiler found\n"),
+        }
+
+        text.push_str("\nRuntime features:\n");
+        for (feature, available) in &self.features {
+            text.push_str(&format!("  {:<30} {}\n", feature, if *available { "yes" } else { "no" }));
+        }
+
+        text.push_str("\nConfiguration:\n");
+        match &self.config {
+            Some(path) => text.push_str(&format!("  {}\n", path.display())),
+            None => text.push_str("  none (no zc.toml or .zconfig here or above)\n"),
+        }
+        for (name, value) in &self.variables {
+            text.push_str(&format!("  {}={}\n", name, value));
+        }
+
+        let unknown = |dir: &Option<PathBuf>| dir.as_ref().map_or("unknown".to_string(), |dir| dir.display().to_string());
+        text.push_str("\nCaches:\n");
+        text.push_str(&format!("  runs      {}\n", unknown(&self.run_cache)));
+        text.push_str(&format!("  packages  {}\n", unknown(&self.package_cache)));
+        text.push_str("  modules   .zc-cache next to each program\n");
+        text
+    }
+}
+
+/// Target triples builds are commonly asked for, as `--target` takes them
+pub const TARGETS: [&str; 11] = [
+    "x86_64-linux-gnu",
+    "aarch64-linux-gnu",
+    "riscv64-linux-gnu",
+    "arm-linux-gnueabihf",
+    "x86_64-linux-musl",
+    "aarch64-linux-musl",
+    "x86_64-windows-gnu",
+    "x86_64-windows-msvc",
+    "x86_64-macos",
+    "aarch64-macos",
+    "wasm32-wasi",
+];
+
+/// A target, and the backends installed that build for it
+#[derive(Debug, Clone)]
+pub struct TargetSupport {
+    pub triple: String,
+    /// Each backend, with a note on what it needs for the target
+    pub backends: Vec<(CBackend, Option<String>)>,
+}
+
+impl TargetSupport {
+    /// The target and its backends as a line of text
+    pub fn to_text(&self) -> String {
+        let backends = if self.backends.is_empty() {
+            "none installed".to_string()
+        } else {
+            self.backends
+                .iter()
+                .map(|(backend, note)| match note {
+                    Some(note) => format!("{} ({})", backend.name(), note),
+                    None => backend.name().to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!("{:<22} {}", self.triple, backends)
+    }
+}
+
+/// The targets of `TARGETS`, and the hosts of `compilers`, with the backends
+/// among `compilers` that build for each
+pub fn targets(compilers: &[CompilerInfo]) -> Vec<TargetSupport> {
+    let mut triples: Vec<String> = TARGETS.iter().map(|triple| triple.to_string()).collect();
+    for host in compilers.iter().filter_map(|compiler| compiler.host.clone()) {
+        if !triples.contains(&host) {
+            triples.push(host);
+        }
+    }
+    triples
+        .into_iter()
+        .map(|triple| {
+            let backends = compilers
+                .iter()
+                .filter(|compiler| compiler.version.is_some())
+                .filter_map(|compiler| {
+                    let host = compiler.host.as_deref() == Some(triple.as_str());
+                    let windows_msvc = triple.ends_with("-windows-msvc");
+                    let note = match compiler.backend {
+                        _ if host => None,
+                        // Zig ships the headers and libraries of its targets
+                        CBackend::Zig => None,
+                        CBackend::Clang => Some("with a sysroot for the target".to_string()),
+                        CBackend::Msvc if windows_msvc => Some("with the MSVC libraries for the target".to_string()),
+                        CBackend::Gcc if cross_gcc(&triple) => Some(format!("as {}-gcc", triple)),
+                        _ => return None,
+                    };
+                    Some((compiler.backend, note))
+                })
+                .collect();
+            TargetSupport { triple, backends }
+        })
+        .collect()
+}
+
+/// Whether the GCC cross compiler for `triple` is installed
+fn cross_gcc(triple: &str) -> bool {
+    let mut compiler = CCompiler::of_backend(CBackend::Gcc);
+    compiler.command = format!("{}-gcc", triple);
+    compiler.version().is_some()
+}
@@ -1,211 +1,3426 @@
-use crate::ast::{BinaryOp, Expr, Literal, Program, Stmt, Type, UnaryOp};
-use std::collections::HashMap;
+use crate::ast::{Attribute, BinaryOp, Expr, Literal, MatchArm, Pattern, Program, Span, Stmt, Type, UnaryOp};
+use crate::consteval::{self, ConstError, Constant};
+use crate::deadcode::{DeadCodeReport, DeadField, DeadFunction};
+use crate::format::{self, Piece};
+use crate::mangle::{self, Instances};
+use crate::ownership;
+use crate::stdlib;
+use crate::symbols::{Symbol, SymbolKind, SymbolTable};
+use crate::visit::{walk_expr, walk_expr_mut, walk_match_arm_mut, walk_stmt, walk_stmt_mut, Visitor, VisitorMut};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TypeError {
+    /// The stable code `zc explain` describes the error by
+    pub code: &'static str,
     pub message: String,
     pub span: Option<crate::ast::Span>,
+    /// Another place the error is about, such as where a name redefined
+    /// was first defined, and what happened there
+    pub label: Option<(String, crate::ast::Span)>,
 }
 
 impl std::fmt::Display for TypeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.span {
-            Some(span) => write!(f, "Type error at position {}: {}", span.start, self.message),
-            None => write!(f, "Type error: {}", self.message),
+            Some(span) => write!(f, "Type error[{}] at position {}: {}", self.code, span.start, self.message),
+            None => write!(f, "Type error[{}]: {}", self.code, self.message),
         }
     }
 }
 
 type Result<T> = std::result::Result<T, TypeError>;
 
+/// A problem worth reporting that does not stop compilation
+#[derive(Debug)]
+pub struct TypeWarning {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<crate::ast::Span>,
+}
+
+impl std::fmt::Display for TypeWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "Warning[{}] at position {}: {}", self.code, span.start, self.message),
+            None => write!(f, "Warning[{}]: {}", self.code, self.message),
+        }
+    }
+}
+
+/// The output of a successful type check
+#[derive(Debug)]
+pub struct CheckedProgram {
+    pub program: Program,
+    pub warnings: Vec<TypeWarning>,
+    pub symbols: SymbolTable,
+    pub dead_code: DeadCodeReport,
+}
+
+/// A variable introduced by `let`, `const`, a parameter, a loop or a match
+/// arm, tracked so unused ones can be reported and references recorded
+#[derive(Clone)]
+struct Binding {
+    name: String,
+    kind: SymbolKind,
+    ty: Type,
+    span: Span,
+    used: bool,
+    references: Vec<Span>,
+}
+
+/// Variables visible at some point, saved on entering a scope
+struct SavedScope {
+    variables: HashMap<String, Type>,
+    variable_bindings: HashMap<String, usize>,
+    constants: HashMap<String, Constant>,
+    scope_definitions: HashMap<String, Span>,
+}
+
+// Type parameters and variants of a generic enum
+type GenericEnum = (Vec<String>, Vec<(String, Vec<Type>)>);
+
+// Type parameters and fields of a generic struct
+type GenericStruct = (Vec<String>, Vec<(String, Type)>);
+
+/// A part of a program that can be checked apart from the others, so that a
+/// change to one part only checks that part again: the body of a function,
+/// or everything but the bodies of functions
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit {
+    Function(String),
+    TopLevel,
+}
+
+/// What checking one unit of a program found, for `merge` to put together
+/// with the other units
+pub struct CheckedUnit {
+    // The statements of the unit as checked, with their indices in the
+    // program, and the generic instantiations they requested
+    statements: Vec<(usize, Stmt)>,
+    instantiated: Vec<Stmt>,
+    // Uses of moved values, with `CheckOptions::strict_memory`
+    moves: Vec<TypeError>,
+    // The errors, instances and uses of names found
+    typechecker: TypeChecker,
+}
+
 pub struct TypeChecker {
     // Symbol table for variables and their types
     variables: HashMap<String, Type>,
+    // Values of the visible constants, which are also in `variables`
+    constants: HashMap<String, Constant>,
+    // Variables defined directly in the innermost scope, which may not be
+    // defined again there
+    scope_definitions: HashMap<String, Span>,
+    // Index into `bindings` of each visible variable
+    variable_bindings: HashMap<String, usize>,
+    bindings: Vec<Binding>,
+    // User-defined functions, where they are declared, and which are called
+    function_spans: HashMap<String, Span>,
+    function_references: HashMap<String, Vec<Span>>,
+    used_functions: HashSet<String>,
+    // `@bench` functions, which the benchmark runner calls
+    benches: HashSet<String>,
+    // Functions and struct fields referred to by each function, with
+    // top-level code under `None`
+    function_uses: HashMap<Option<String>, HashSet<String>>,
+    field_uses: HashMap<Option<String>, HashSet<(String, String)>>,
+    // Declarations of structs and enums, and identifiers naming them
+    type_spans: HashMap<String, Span>,
+    type_references: HashMap<String, Vec<Span>>,
+    current_function: Option<String>,
+    warnings: Vec<TypeWarning>,
+    // Errors recorded so far; checking continues with the next statement
+    errors: Vec<TypeError>,
     // Symbol table for functions
     functions: HashMap<String, (Vec<Type>, Type)>,
     // Symbol table for structs
     structs: HashMap<String, HashMap<String, Type>>,
+    // Names of every struct declared at the top level, generic or not
+    declared_structs: HashSet<String>,
+    // Variants of each enum and their payload types
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
+    declared_enums: HashSet<String>,
     // Current return type for function checking
     current_return_type: Option<Type>,
+    // Bindings from this index on belong to the innermost lambda being
+    // checked; earlier ones are captured by it
+    lambda_start: Option<usize>,
+    // Generic declarations, which are only checked through their instantiations
+    generic_functions: HashMap<String, Stmt>,
+    generic_structs: HashMap<String, GenericStruct>,
+    // The builtin `Option` and `Result`, unless the program declares types
+    // of the same names
+    generic_enums: HashMap<String, GenericEnum>,
+    // Every instantiation requested so far, with its generic declaration and
+    // type arguments
+    instances: Instances,
+    // Function instantiations still to be checked, with their type arguments
+    pending_instances: Vec<(Stmt, HashMap<String, Type>)>,
+    // Struct and enum instantiations, emitted ahead of the rest of the program
+    struct_instances: Vec<Stmt>,
+    // Type arguments of the instantiation currently being checked
+    type_bindings: HashMap<String, Type>,
+    // Whether the program imports `std.math`, whose functions are then
+    // builtins
+    math: bool,
+    // Functions that never return, so that a path calling one need not
+    // return after it
+    never_returning: HashSet<String>,
+    // The only unit checked, if not the whole program. Statements outside
+    // it are still checked for the names they bind, but left out.
+    unit: Option<Unit>,
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
+            constants: HashMap::new(),
+            scope_definitions: HashMap::new(),
+            variable_bindings: HashMap::new(),
+            bindings: Vec::new(),
+            function_spans: HashMap::new(),
+            function_references: HashMap::new(),
+            used_functions: HashSet::new(),
+            benches: HashSet::new(),
+            function_uses: HashMap::new(),
+            field_uses: HashMap::new(),
+            type_spans: HashMap::new(),
+            type_references: HashMap::new(),
+            current_function: None,
+            warnings: Vec::new(),
+            errors: Vec::new(),
             functions: HashMap::new(),
             structs: HashMap::new(),
+            declared_structs: HashSet::new(),
+            enums: HashMap::new(),
+            declared_enums: HashSet::new(),
             current_return_type: None,
+            lambda_start: None,
+            generic_functions: HashMap::new(),
+            generic_structs: HashMap::new(),
+            generic_enums: builtin_enums(),
+            instances: HashMap::new(),
+            pending_instances: Vec::new(),
+            struct_instances: Vec::new(),
+            type_bindings: HashMap::new(),
+            math: false,
+            never_returning: HashSet::new(),
+            unit: None,
+        }
+    }
+
+    /// Checks the whole program, returning every error found rather than
+    /// stopping at the first one
+    pub fn check_program(&mut self, program: Program) -> std::result::Result<Program, Vec<TypeError>> {
+        let imports = program.imports.clone();
+        let (checked_statements, instantiated) = self.check_statements_of(program);
+
+        if !self.errors.is_empty() {
+            let mut errors = std::mem::take(&mut self.errors);
+            errors.sort_by_key(|error| error.span.as_ref().map(|span| span.start));
+            return Err(errors);
+        }
+
+        self.report_unused();
+
+        let mut statements = std::mem::take(&mut self.struct_instances);
+        statements.extend(checked_statements.into_iter().map(|(_, stmt)| stmt));
+        statements.extend(instantiated);
+        let mut checked = Program::new(statements);
+        checked.instances = std::mem::take(&mut self.instances);
+        checked.imports = imports;
+        Ok(checked)
+    }
+
+    /// Checks the statements of `program`, returning them as checked with
+    /// their indices, and then the generic instantiations they requested.
+    /// Errors are recorded rather than returned.
+    fn check_statements_of(&mut self, program: Program) -> (Vec<(usize, Stmt)>, Vec<Stmt>) {
+        // First pass: register all function and struct declarations, going
+        // on to check the bodies after errors in them
+        if let Err(errors) = self.register_declarations(&program) {
+            self.errors.extend(errors);
+        }
+        self.never_returning = never_returning(&program.statements, self.functions.contains_key("panic"));
+
+        // Second pass: check all statements, but the bodies of the functions
+        // of other units
+        let mut checked_statements = Vec::new();
+        for (index, stmt) in program.statements.into_iter().enumerate() {
+            if matches!(&stmt, Stmt::Function(_, type_params, ..) | Stmt::Struct(_, type_params, ..) if !type_params.is_empty()) {
+                continue;
+            }
+            if matches!(&stmt, Stmt::Function(..)) && !self.owns(&stmt) {
+                continue;
+            }
+            let checked = self.check_statement(stmt);
+            checked_statements.extend(self.recover(checked).map(|checked| (index, checked)));
+        }
+
+        // Check generic instantiations, which may request further instantiations
+        let mut instantiated = Vec::new();
+        while let Some((instance, bindings)) = self.pending_instances.pop() {
+            self.type_bindings = bindings;
+            let checked = self.check_statement(instance);
+            self.type_bindings.clear();
+            instantiated.extend(self.recover(checked));
+        }
+
+        // Instantiation has finished, so every struct's fields are known
+        let struct_spans: HashMap<String, Span> = self
+            .struct_instances
+            .iter()
+            .chain(checked_statements.iter().map(|(_, stmt)| stmt))
+            .chain(&instantiated)
+            .filter_map(|stmt| match stmt {
+                Stmt::Struct(name, _, _, span) => Some((name.clone(), span.clone())),
+                _ => None,
+            })
+            .collect();
+        let size_errors = self.check_struct_sizes(&struct_spans);
+        self.errors.extend(size_errors);
+
+        (checked_statements, instantiated)
+    }
+
+    /// Whether `stmt`, a top-level statement, belongs to the unit checked
+    fn owns(&self, stmt: &Stmt) -> bool {
+        match (&self.unit, stmt) {
+            (None, _) => true,
+            (Some(Unit::Function(unit)), Stmt::Function(name, ..)) => name == unit,
+            (Some(Unit::Function(_)), _) => false,
+            (Some(Unit::TopLevel), stmt) => !matches!(stmt, Stmt::Function(..)),
         }
     }
 
-    pub fn check_program(&mut self, program: Program) -> Result<Program> {
-        // First pass: register all function and struct declarations
+    /// What checking found in `program`, checked without errors
+    fn finish(mut self, program: Program) -> CheckedProgram {
+        let dead_code = self.dead_code_report(&program);
+        self.report_dead_code(&dead_code);
+        CheckedProgram {
+            program,
+            symbols: self.symbol_table(),
+            warnings: self.warnings,
+            dead_code,
+        }
+    }
+
+    /// Warns about variables that are never read and functions that are never
+    /// called. Names starting with an underscore are exempt.
+    fn report_unused(&mut self) {
+        let mut reported = HashSet::new();
+        for binding in &self.bindings {
+            if binding.used || binding.name.starts_with('_') {
+                continue;
+            }
+            // Generic bodies are checked once per instantiation
+            if !reported.insert((binding.name.clone(), binding.span.start)) {
+                continue;
+            }
+            self.warnings.push(TypeWarning {
+                code: "W0001",
+                message: format!(
+                    "Unused variable: {} (prefix it with an underscore to silence this warning)",
+                    binding.name
+                ),
+                span: Some(binding.span.clone()),
+            });
+        }
+
+        for (name, span) in &self.function_spans {
+            if name == "main" || name.starts_with('_') || self.used_functions.contains(name) || self.benches.contains(name) {
+                continue;
+            }
+            self.warnings.push(TypeWarning {
+                code: "W0002",
+                message: format!("Function {} is never called", name),
+                span: Some(span.clone()),
+            });
+        }
+
+        self.warnings.sort_by_key(|warning| warning.span.as_ref().map(|span| span.start));
+    }
+
+    /// Functions and fields of `program` that no code reachable from `main`
+    /// (or from top-level statements, or benchmarks) uses
+    fn dead_code_report(&self, program: &Program) -> DeadCodeReport {
+        let mut reachable: HashSet<Option<String>> = HashSet::new();
+        let mut pending = vec![None, Some("main".to_string())];
+        pending.extend(self.benches.iter().cloned().map(Some));
+        while let Some(function) = pending.pop() {
+            if !reachable.insert(function.clone()) {
+                continue;
+            }
+            if let Some(uses) = self.function_uses.get(&function) {
+                pending.extend(uses.iter().cloned().map(Some));
+            }
+        }
+
+        // Recursive calls don't keep a function alive
+        let referenced = |name: &String| {
+            self.function_uses
+                .iter()
+                .any(|(user, uses)| user.as_ref() != Some(name) && uses.contains(name))
+        };
+        let mut functions: Vec<DeadFunction> = self
+            .function_spans
+            .iter()
+            .filter(|(name, _)| !reachable.contains(&Some(name.to_string())))
+            .map(|(name, span)| DeadFunction {
+                name: name.clone(),
+                span: span.clone(),
+                referenced: referenced(name),
+            })
+            .collect();
+        functions.sort_by_key(|function| function.span.start);
+
+        let used_fields: HashSet<&(String, String)> = reachable
+            .iter()
+            .filter_map(|function| self.field_uses.get(function))
+            .flatten()
+            .collect();
+        let mut fields = Vec::new();
         for stmt in &program.statements {
-            match stmt {
-                Stmt::Function(name, params, return_type, _, _) => {
-                    let param_types: Vec<Type> = params.iter().map(|(_, ty)| ty.clone()).collect();
-                    self.functions.insert(name.clone(), (param_types, return_type.clone()));
+            if let Stmt::Struct(name, _, struct_fields, span) = stmt {
+                // Instances of generic structs are named after their type arguments
+                if !self.type_spans.contains_key(name) {
+                    continue;
                 }
-                Stmt::Struct(name, fields, _) => {
-                    let mut field_types = HashMap::new();
-                    for (field_name, field_type) in fields {
-                        field_types.insert(field_name.clone(), field_type.clone());
+                for (field, _) in struct_fields {
+                    if !used_fields.contains(&(name.clone(), field.clone())) {
+                        fields.push(DeadField {
+                            struct_name: name.clone(),
+                            field: field.clone(),
+                            span: span.clone(),
+                        });
                     }
-                    self.structs.insert(name.clone(), field_types);
                 }
-                _ => {}
             }
         }
 
-        // Second pass: check all statements
-        let mut checked_statements = Vec::new();
-        for stmt in program.statements {
-            checked_statements.push(self.check_statement(stmt)?);
+        DeadCodeReport { functions, fields }
+    }
+
+    /// Warns about dead code not already reported as unused
+    fn report_dead_code(&mut self, report: &DeadCodeReport) {
+        for function in &report.functions {
+            if function.referenced && !function.name.starts_with('_') {
+                self.warnings.push(TypeWarning {
+                    code: "W0003",
+                    message: format!("Function {} is only used by code that main never reaches", function.name),
+                    span: Some(function.span.clone()),
+                });
+            }
         }
+        for field in &report.fields {
+            if !field.field.starts_with('_') {
+                self.warnings.push(TypeWarning {
+                    code: "W0004",
+                    message: format!("Field {}.{} is never used", field.struct_name, field.field),
+                    span: Some(field.span.clone()),
+                });
+            }
+        }
+        self.warnings.sort_by_key(|warning| warning.span.as_ref().map(|span| span.start));
+    }
+
+    /// Rejects structs that contain themselves by value, directly or through
+    /// other structs, since they would be infinitely large
+    fn check_struct_sizes(&self, struct_spans: &HashMap<String, Span>) -> Vec<TypeError> {
+        let mut names: Vec<&String> = struct_spans.keys().collect();
+        names.sort_by_key(|name| struct_spans[*name].start);
 
-        Ok(Program::new(checked_statements))
+        let mut errors = Vec::new();
+        let mut finished = HashSet::new();
+        for name in names {
+            let mut path = Vec::new();
+            if let Some(cycle) = self.find_struct_cycle(name, &mut path, &mut finished) {
+                let mut steps: Vec<String> = cycle
+                    .iter()
+                    .map(|(struct_name, field)| format!("{}.{}", struct_name, field))
+                    .collect();
+                steps.push(name.clone());
+                // Report each cycle once, at the first struct on it
+                finished.extend(cycle.into_iter().map(|(struct_name, _)| struct_name));
+                errors.push(TypeError {
+                    code: "E0220",
+                    message: format!(
+                        "Struct {} contains itself by value ({}) and would be infinitely large; store the value in an array such as `[{}]` instead",
+                        name,
+                        steps.join(" -> "),
+                        name
+                    ),
+                    span: struct_spans.get(name).cloned(),
+                    label: None,
+                });
+            }
+        }
+        errors
     }
 
-    fn check_statement(&mut self, stmt: Stmt) -> Result<Stmt> {
-        match stmt {
-            Stmt::Let(name, type_ann, initializer, span) => {
-                let var_type = match (&type_ann, &initializer) {
-                    (Some(ty), _) => ty.clone(),
-                    (None, Some(expr)) => {
-                        let (_checked_expr, expr_type) = self.check_expression(expr.clone())?;
-                        expr_type
+    /// Depth-first search for a struct reachable from itself through by-value
+    /// fields, returning the (struct, field) steps of the cycle
+    fn find_struct_cycle(
+        &self,
+        name: &str,
+        path: &mut Vec<(String, String)>,
+        finished: &mut HashSet<String>,
+    ) -> Option<Vec<(String, String)>> {
+        if let Some(start) = path.iter().position(|(struct_name, _)| struct_name == name) {
+            return Some(path[start..].to_vec());
+        }
+        if finished.contains(name) {
+            return None;
+        }
+
+        if let Some(fields) = self.structs.get(name) {
+            let mut fields: Vec<(&String, &Type)> = fields.iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            for (field_name, field_type) in fields {
+                if let Type::Struct(field_struct) = field_type {
+                    path.push((name.to_string(), field_name.clone()));
+                    let cycle = self.find_struct_cycle(field_struct, path, finished);
+                    path.pop();
+                    if cycle.is_some() {
+                        return cycle;
                     }
-                    (None, None) => {
-                        return Err(TypeError {
-                            message: "Cannot infer type for variable without initializer".to_string(),
+                }
+            }
+        }
+
+        finished.insert(name.to_string());
+        None
+    }
+
+    /// Records the error of a failed check so that checking can continue
+    fn recover<T>(&mut self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    /// Enters a new scope, returning the state to restore on leaving it
+    fn save_scope(&mut self) -> SavedScope {
+        SavedScope {
+            variables: self.variables.clone(),
+            variable_bindings: self.variable_bindings.clone(),
+            constants: self.constants.clone(),
+            scope_definitions: std::mem::take(&mut self.scope_definitions),
+        }
+    }
+
+    fn restore_scope(&mut self, saved: SavedScope) {
+        self.variables = saved.variables;
+        self.variable_bindings = saved.variable_bindings;
+        self.constants = saved.constants;
+        self.scope_definitions = saved.scope_definitions;
+    }
+
+    /// Rejects a second definition of `name` in the innermost scope; shadowing
+    /// a variable from an enclosing scope is allowed
+    fn check_not_defined(&self, name: &str, span: &Span) -> Result<()> {
+        match self.scope_definitions.get(name) {
+            Some(original) => Err(TypeError {
+                code: "E0103",
+                message: format!("{} is already defined in this scope", name),
+                span: Some(span.clone()),
+                label: Some(("first defined".to_string(), original.clone())),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Declares a variable introduced in the program being checked
+    fn bind_variable(&mut self, name: String, ty: Type, span: &Span, kind: SymbolKind) {
+        // A variable shadows any constant of the same name
+        self.constants.remove(&name);
+        self.scope_definitions.insert(name.clone(), span.clone());
+        self.variable_bindings.insert(name.clone(), self.bindings.len());
+        self.bindings.push(Binding {
+            name: name.clone(),
+            kind,
+            ty: ty.clone(),
+            span: span.clone(),
+            used: false,
+            references: Vec::new(),
+        });
+        self.variables.insert(name, ty);
+    }
+
+    /// Everything declared in the program, with the references found while
+    /// checking it
+    fn symbol_table(&self) -> SymbolTable {
+        let mut table = SymbolTable::default();
+        for binding in &self.bindings {
+            table.add(Symbol {
+                name: binding.name.clone(),
+                kind: binding.kind,
+                ty: binding.ty.clone(),
+                definition: binding.span.clone(),
+                references: binding.references.clone(),
+            });
+        }
+
+        for (name, span) in &self.function_spans {
+            let ty = match self.functions.get(name) {
+                Some((params, ret)) => Type::Function(params.clone(), Box::new(ret.clone())),
+                // Generic functions have no single signature
+                None => Type::Inferred,
+            };
+            table.add(Symbol {
+                name: name.clone(),
+                kind: SymbolKind::Function,
+                ty,
+                definition: span.clone(),
+                references: self.function_references.get(name).cloned().unwrap_or_default(),
+            });
+        }
+
+        for (name, span) in &self.type_spans {
+            let (kind, ty) = if self.declared_enums.contains(name) {
+                (SymbolKind::Enum, Type::Enum(name.clone()))
+            } else {
+                (SymbolKind::Struct, Type::Struct(name.clone()))
+            };
+            table.add(Symbol {
+                name: name.clone(),
+                kind,
+                ty,
+                definition: span.clone(),
+                references: self.type_references.get(name).cloned().unwrap_or_default(),
+            });
+        }
+
+        table.symbols.sort_by_key(|symbol| symbol.definition.start);
+        table
+    }
+
+    /// Registers the signatures of all top-level functions and structs.
+    ///
+    /// Every name is collected before any signature is resolved, so
+    /// declarations may refer to each other regardless of their order.
+    /// Every declaration is registered, even after errors in others, so that
+    /// all of them are reported along with those in the bodies; types that
+    /// cannot be resolved are left to be inferred.
+    pub fn register_declarations(&mut self, program: &Program) -> std::result::Result<(), Vec<TypeError>> {
+        let mut errors = Vec::new();
+        // `std.math` brings its functions and the constant `pi`
+        if program.statements.iter().any(|stmt| matches!(stmt, Stmt::Import(name, _) if name == stdlib::MATH)) {
+            self.math = true;
+            self.constants.insert("pi".to_string(), Constant::Float(std::f64::consts::PI));
+            self.variables.insert("pi".to_string(), Type::Float);
+        }
+
+        for stmt in &program.statements {
+            match stmt {
+                Stmt::Enum(name, _, span) | Stmt::Struct(name, _, _, span) => {
+                    if let Some(original) = self.type_spans.get(name) {
+                        errors.push(TypeError {
+                            code: "E0103",
+                            message: format!("Type {} is already defined", name),
                             span: Some(span.clone()),
+                            label: Some(("first defined".to_string(), original.clone())),
                         });
+                        continue;
                     }
-                };
+                    self.type_spans.insert(name.clone(), span.clone());
+                }
+                Stmt::Function(name, _, _, _, _, _, _, span) | Stmt::Extern(name, _, _, _, span) => {
+                    if let Some(original) = self.function_spans.get(name) {
+                        errors.push(TypeError {
+                            code: "E0103",
+                            message: format!("Function {} is already defined", name),
+                            span: Some(span.clone()),
+                            label: Some(("first defined".to_string(), original.clone())),
+                        });
+                        continue;
+                    }
+                    // The C runtime already defines these
+                    if matches!(stmt, Stmt::Extern(..)) && (name == "main" || format::is_print(name)) {
+                        errors.push(TypeError {
+                            code: "E0108",
+                            message: format!("Extern function {} would clash with the builtin of the same name", name),
+                            span: Some(span.clone()),
+                            label: None,
+                        });
+                        continue;
+                    }
+                    self.function_spans.insert(name.clone(), span.clone());
+                    if matches!(stmt, Stmt::Function(.., attributes, _) if attributes.contains(&Attribute::Bench)) {
+                        self.benches.insert(name.clone());
+                    }
+                }
+                _ => {}
+            }
 
-                // Check that initializer matches the declared type
-                let checked_initializer = if let Some(init) = initializer {
-                    let (checked_init, init_type) = self.check_expression(init)?;
-                    if let Some(ty) = &type_ann {
-                        self.check_type_compatibility(init_type, ty.clone(), &span)?;
+            match stmt {
+                Stmt::Enum(name, _, _) => {
+                    self.declared_enums.insert(name.clone());
+                }
+                Stmt::Function(name, type_params, _, _, _, true, _, span) if !type_params.is_empty() => {
+                    errors.push(TypeError {
+                        code: "E0217",
+                        message: format!("Generic function {} cannot be pub, since C needs a single signature", name),
+                        span: Some(span.clone()),
+                        label: None,
+                    });
+                }
+                // C code may call `pub` functions, so they are never unused
+                Stmt::Function(name, _, _, _, _, true, _, span) => {
+                    if let Some(owner) = mangle::reserved_c_name(name) {
+                        errors.push(TypeError {
+                            code: "E0225",
+                            message: format!("Function {} cannot be pub, since its name belongs to {}", name, owner),
+                            span: Some(span.clone()),
+                            label: None,
+                        });
                     }
-                    Some(checked_init)
-                } else {
-                    None
-                };
+                    self.used_functions.insert(name.clone());
+                    self.function_uses.entry(None).or_default().insert(name.clone());
+                }
+                Stmt::Struct(name, type_params, fields, span) => {
+                    if let Some(duplicate) = first_duplicate(fields.iter().map(|(field, _)| field)) {
+                        errors.push(TypeError {
+                            code: "E0104",
+                            message: format!("Struct {} has more than one field named {}", name, duplicate),
+                            span: Some(span.clone()),
+                            label: None,
+                        });
+                    }
+                    self.declared_structs.insert(name.clone());
+                    if !type_params.is_empty() {
+                        self.generic_structs.insert(name.clone(), (type_params.clone(), fields.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
 
-                // Add variable to symbol table
-                self.variables.insert(name.clone(), var_type.clone());
+        let declared = &self.type_spans;
+        self.generic_enums.retain(|name, _| !declared.contains_key(name));
 
-                Ok(Stmt::Let(name, type_ann, checked_initializer, span))
+        for stmt in &program.statements {
+            match stmt {
+                Stmt::Function(name, type_params, _, _, _, _, _, _) if !type_params.is_empty() => {
+                    self.generic_functions.insert(name.clone(), stmt.clone());
+                }
+                Stmt::Function(name, _, params, return_type, _, _, _, span) => {
+                    let mut param_types = Vec::new();
+                    for (_, ty) in params {
+                        param_types.push(self.resolve_or_infer(ty, span, &mut errors));
+                    }
+                    let return_type = self.resolve_or_infer(return_type, span, &mut errors);
+                    self.functions.entry(name.clone()).or_insert((param_types, return_type));
+                }
+                Stmt::Extern(name, params, return_type, _, span) => {
+                    let mut param_types = Vec::new();
+                    for (_, ty) in params {
+                        param_types.push(self.resolve_or_infer(ty, span, &mut errors));
+                    }
+                    let return_type = self.resolve_or_infer(return_type, span, &mut errors);
+                    let unsupported = param_types
+                        .iter()
+                        .find(|ty| !is_ffi_type(ty))
+                        .or(Some(&return_type).filter(|ty| !is_ffi_type(ty) && **ty != Type::Void));
+                    if let Some(ty) = unsupported {
+                        errors.push(TypeError {
+                            code: "E0218",
+                            message: format!(
+                                "Extern function {} cannot take or return {}; only numbers, bools and strings, optional or not, can be passed to C",
+                                name, ty
+                            ),
+                            span: Some(span.clone()),
+                            label: None,
+                        });
+                    }
+                    self.functions.entry(name.clone()).or_insert((param_types, return_type));
+                }
+                // Top-level constants are visible inside every function; their
+                // initializers are reported by `check_statement`
+                Stmt::Const(name, type_ann, value, span) => {
+                    if let Ok(Some(constant)) = consteval::evaluate(value, &self.constants) {
+                        let ty = match type_ann {
+                            Some(ty) => self.resolve_or_infer(ty, span, &mut errors),
+                            None => constant_type(constant),
+                        };
+                        self.constants.insert(name.clone(), constant);
+                        self.variables.insert(name.clone(), ty);
+                    }
+                }
+                Stmt::Enum(name, variants, span) => {
+                    let mut resolved_variants = Vec::new();
+                    for (variant, payload) in variants {
+                        if resolved_variants.iter().any(|(existing, _)| existing == variant) {
+                            errors.push(TypeError {
+                                code: "E0104",
+                                message: format!("Enum {} has more than one variant named {}", name, variant),
+                                span: Some(span.clone()),
+                                label: None,
+                            });
+                            continue;
+                        }
+                        let mut payload_types = Vec::new();
+                        for ty in payload {
+                            payload_types.push(self.resolve_or_infer(ty, span, &mut errors));
+                        }
+                        resolved_variants.push((variant.clone(), payload_types));
+                    }
+                    self.enums.entry(name.clone()).or_insert(resolved_variants);
+                }
+                Stmt::Struct(name, type_params, fields, span) if type_params.is_empty() => {
+                    let mut field_types = HashMap::new();
+                    for (field_name, field_type) in fields {
+                        let field_type = self.resolve_or_infer(field_type, span, &mut errors);
+                        field_types.entry(field_name.clone()).or_insert(field_type);
+                    }
+                    self.structs.entry(name.clone()).or_insert(field_types);
+                }
+                _ => {}
             }
-            // Placeholder implementations for other statement types
-            _ => Ok(stmt),
         }
-    }
 
-    fn check_expression(&mut self, expr: Expr) -> Result<(Expr, Type)> {
-        match expr {
-            Expr::Literal(lit, span) => {
-                let ty = match lit {
-                    Literal::Int(_) => Type::Int,
-                    Literal::Float(_) => Type::Float,
-                    Literal::Bool(_) => Type::Bool,
-                    Literal::String(_) => Type::String,
-                    Literal::Null => Type::Void,
-                };
-                Ok((Expr::Literal(lit, span), ty))
-            }
-            Expr::Identifier(name, span) => {
-                if let Some(ty) = self.variables.get(&name) {
-                    Ok((Expr::Identifier(name, span), ty.clone()))
-                } else {
-                    Err(TypeError {
-                        message: format!("Undefined variable: {}", name),
-                        span: Some(span),
-                    })
-                }
+        // Imported functions share the namespace of the module's own
+        let mut imported_from: HashMap<&str, &str> = HashMap::new();
+        for import in &program.imports {
+            if let Some(span) = self.function_spans.get(&import.name) {
+                errors.push(TypeError {
+                    code: "E0105",
+                    message: format!("Function {} is already imported from {}", import.name, import.module),
+                    span: Some(span.clone()),
+                    label: None,
+                });
+                continue;
             }
-            Expr::Binary(left, op, right, span) => {
-                let (checked_left, left_type) = self.check_expression(*left)?;
-                let (checked_right, right_type) = self.check_expression(*right)?;
-                
-                let result_type = self.check_binary_op(&op, &left_type, &right_type, &span)?;
-                
-                Ok((
-                    Expr::Binary(Box::new(checked_left), op, Box::new(checked_right), span),
-                    result_type,
-                ))
+            if let Some(other) = imported_from.insert(&import.name, &import.module) {
+                errors.push(TypeError {
+                    code: "E0105",
+                    message: format!("Function {} is imported from both {} and {}", import.name, other, import.module),
+                    span: None,
+                    label: None,
+                });
+                continue;
             }
-            // Placeholder implementations for other expression types
-            _ => Ok((expr, Type::Inferred)),
+            self.functions
+                .insert(import.name.clone(), (import.params.clone(), import.return_type.clone()));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 
-    fn check_binary_op(&self, op: &BinaryOp, left_type: &Type, right_type: &Type, span: &crate::ast::Span) -> Result<Type> {
-        match op {
-            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
-                if left_type == &Type::Int && right_type == &Type::Int {
-                    Ok(Type::Int)
-                } else if left_type == &Type::Float && right_type == &Type::Float {
-                    Ok(Type::Float)
-                } else if left_type == &Type::Int && right_type == &Type::Float 
-                      || left_type == &Type::Float && right_type == &Type::Int {
-                    Ok(Type::Float)
-                } else if op == &BinaryOp::Add && (left_type == &Type::String || right_type == &Type::String) {
-                    Ok(Type::String)
-                } else {
-                    Err(TypeError {
-                        message: format!("Invalid operand types for binary operator: {:?} and {:?}", left_type, right_type),
-                        span: Some(span.clone()),
-                    })
+    /// `ty` resolved, or left to be inferred after adding why it cannot be
+    /// to `errors`
+    fn resolve_or_infer(&mut self, ty: &Type, span: &Span, errors: &mut Vec<TypeError>) -> Type {
+        self.resolve_type(ty, span).unwrap_or_else(|error| {
+            errors.push(error);
+            Type::Inferred
+        })
+    }
+
+    /// Replaces type parameters bound by the current instantiation and
+    /// instantiates applied generic structs and enums, e.g. `Pair<int>`
+    /// becomes the struct `Pair<int>`.
+    fn resolve_type(&mut self, ty: &Type, span: &Span) -> Result<Type> {
+        match ty {
+            Type::Generic(name) => Ok(self.type_bindings.get(name).cloned().unwrap_or_else(|| ty.clone())),
+            Type::Array(elem_type) => Ok(Type::Array(Box::new(self.resolve_type(elem_type, span)?))),
+            Type::Optional(inner) => Ok(Type::Optional(Box::new(self.resolve_type(inner, span)?))),
+            Type::Function(params, ret) => {
+                let mut resolved = Vec::new();
+                for param in params {
+                    resolved.push(self.resolve_type(param, span)?);
                 }
+                Ok(Type::Function(resolved, Box::new(self.resolve_type(ret, span)?)))
             }
-            BinaryOp::Eq | BinaryOp::Neq => {
-                // Most types can be compared for equality
-                Ok(Type::Bool)
-            }
-            BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte => {
-                if (left_type == &Type::Int && right_type == &Type::Int)
-                    || (left_type == &Type::Float && right_type == &Type::Float)
-                    || (left_type == &Type::Int && right_type == &Type::Float)
-                    || (left_type == &Type::Float && right_type == &Type::Int)
-                {
-                    Ok(Type::Bool)
-                } else {
-                    Err(TypeError {
-                        message: format!("Invalid operand types for comparison operator: {:?} and {:?}", left_type, right_type),
-                        span: Some(span.clone()),
-                    })
+            // `Vec<T>` is another name for `[T]`, arrays being growable,
+            // unless the program declares a struct of its own by that name
+            Type::Applied(name, args) if name == "Vec" && !self.generic_structs.contains_key(name) => match args.as_slice() {
+                [element] => Ok(Type::Array(Box::new(self.resolve_type(element, span)?))),
+                _ => Err(TypeError {
+                    code: "E0211",
+                    message: format!("Vec expects 1 type argument, found {}", args.len()),
+                    span: Some(span.clone()),
+                    label: None,
+                }),
+            },
+            Type::Applied(name, args) => {
+                let mut resolved_args = Vec::new();
+                for arg in args {
+                    resolved_args.push(self.resolve_type(arg, span)?);
                 }
-            }
-            BinaryOp::And | BinaryOp::Or => {
-                if left_type == &Type::Bool && right_type == &Type::Bool {
-                    Ok(Type::Bool)
-                } else {
-                    Err(TypeError {
-                        message: format!("Invalid operand types for logical operator: {:?} and {:?}", left_type, right_type),
+                if self.generic_enums.contains_key(name) {
+                    return self.instantiate_enum(name, resolved_args, span);
+                }
+
+                let (type_params, fields) = match self.generic_structs.get(name) {
+                    Some(generic) => generic.clone(),
+                    None => {
+                        return Err(TypeError {
+                            code: "E0102",
+                            message: format!("Unknown generic struct: {}", name),
+                            span: Some(span.clone()),
+                            label: None,
+                        });
+                    }
+                };
+                if type_params.len() != resolved_args.len() {
+                    return Err(TypeError {
+                        code: "E0211",
+                        message: format!(
+                            "Struct {} expects {} type arguments, found {}",
+                            name,
+                            type_params.len(),
+                            resolved_args.len()
+                        ),
                         span: Some(span.clone()),
-                    })
+                        label: None,
+                    });
                 }
+
+                let instance_name = instance_name(name, &resolved_args);
+                if !self.instances.contains_key(&instance_name) {
+                    self.instances.insert(instance_name.clone(), (name.clone(), resolved_args.clone()));
+                    let bindings: HashMap<String, Type> = type_params.into_iter().zip(resolved_args).collect();
+                    let mut instance_fields = Vec::new();
+                    for (field_name, field_type) in fields {
+                        let field_type = self.resolve_type(&substitute(&field_type, &bindings), span)?;
+                        instance_fields.push((field_name, field_type));
+                    }
+                    self.structs.insert(instance_name.clone(), instance_fields.iter().cloned().collect());
+                    self.struct_instances.push(Stmt::Struct(instance_name.clone(), Vec::new(), instance_fields, span.clone()));
+                }
+                Ok(Type::Struct(instance_name))
             }
+            Type::Struct(name) if self.declared_enums.contains(name) => Ok(Type::Enum(name.clone())),
+            Type::Struct(name) if self.generic_enums.contains_key(name) => self.instantiate_enum(name, Vec::new(), span),
+            Type::Struct(name) if !self.declared_structs.contains(name) && !self.structs.contains_key(name) => {
+                Err(TypeError {
+                    code: "E0102",
+                    message: format!("Unknown type: {}", name),
+                    span: Some(span.clone()),
+                    label: None,
+                })
+            }
+            _ => Ok(ty.clone()),
         }
     }
 
-    fn check_type_compatibility(&self, actual: Type, expected: Type, span: &crate::ast::Span) -> Result<()> {
-        if actual == expected {
-            Ok(())
-        } else {
-            Err(TypeError {
-                message: format!("Type mismatch: expected {:?}, found {:?}", expected, actual),
+    /// The instance of the builtin generic enum `name` for `type_args`, e.g.
+    /// `Option<int>`, whose variants are added to the enums on first use
+    fn instantiate_enum(&mut self, name: &str, type_args: Vec<Type>, span: &Span) -> Result<Type> {
+        let (type_params, variants) = self.generic_enums[name].clone();
+        if type_params.len() != type_args.len() {
+            return Err(TypeError {
+                code: "E0211",
+                message: format!(
+                    "Enum {} expects {} type argument{}, found {}",
+                    name,
+                    type_params.len(),
+                    if type_params.len() == 1 { "" } else { "s" },
+                    type_args.len()
+                ),
                 span: Some(span.clone()),
-            })
+                label: None,
+            });
+        }
+
+        let instance_name = instance_name(name, &type_args);
+        if !self.instances.contains_key(&instance_name) {
+            self.instances.insert(instance_name.clone(), (name.to_string(), type_args.clone()));
+            let bindings: HashMap<String, Type> = type_params.into_iter().zip(type_args).collect();
+            let instance_variants: Vec<(String, Vec<Type>)> = variants
+                .into_iter()
+                .map(|(variant, payload)| (variant, payload.iter().map(|ty| substitute(ty, &bindings)).collect()))
+                .collect();
+            self.enums.insert(instance_name.clone(), instance_variants.clone());
+            self.struct_instances.push(Stmt::Enum(instance_name.clone(), instance_variants, span.clone()));
         }
+        Ok(Type::Enum(instance_name))
     }
-}
 
-pub fn typecheck(program: Program) -> Result<Program> {
-    let mut typechecker = TypeChecker::new();
-    typechecker.check_program(program)
-}
\ No newline at end of file
+    /// Makes a variable of the given type visible to subsequent checks
+    pub fn declare_variable(&mut self, name: String, ty: Type) {
+        self.variables.insert(name, ty);
+    }
+
+    fn check_statement(&mut self, stmt: Stmt) -> Result<Stmt> {
+        match stmt {
+            Stmt::Let(name, type_ann, initializer, span) => {
+                let type_ann = match type_ann {
+                    Some(ty) => Some(self.resolve_type(&ty, &span)?),
+                    None => None,
+                };
+                let (checked_initializer, init_type) = match initializer {
+                    Some(init) => {
+                        // A broken initializer still declares the variable, with
+                        // an unknown type, so later uses are not reported too
+                        let checked = self.check_expression_expecting(init.clone(), type_ann.as_ref());
+                        match self.recover(checked) {
+                            Some((checked_init, init_type)) => (Some(checked_init), Some(init_type)),
+                            None => (Some(init), Some(Type::Inferred)),
+                        }
+                    }
+                    None => (None, None),
+                };
+
+                let var_type = match (&type_ann, init_type) {
+                    (Some(ty), Some(init_type)) => {
+                        // Check that initializer matches the declared type
+                        let compatible = self
+                            .coerce_literal(checked_initializer.as_ref(), init_type, ty, &span)
+                            .and_then(|init_type| self.check_type_compatibility(init_type, ty.clone(), &span));
+                        self.recover(compatible);
+                        ty.clone()
+                    }
+                    (Some(ty), None) => ty.clone(),
+                    (None, Some(init_type)) => init_type,
+                    (None, None) => {
+                        return Err(TypeError {
+                            code: "E0209",
+                            message: "Cannot infer type for variable without initializer".to_string(),
+                            span: Some(span.clone()),
+                            label: None,
+                        });
+                    }
+                };
+
+                // Add variable to symbol table
+                self.check_not_defined(&name, &span)?;
+                self.bind_variable(name.clone(), var_type.clone(), &span, SymbolKind::Variable);
+
+                // Record the inferred type so code generation can declare it
+                let type_ann = match var_type {
+                    Type::Inferred => None,
+                    ty => Some(ty),
+                };
+                Ok(Stmt::Let(name, type_ann, checked_initializer, span))
+            }
+            Stmt::Const(name, type_ann, value, span) => {
+                let (checked_value, value_type) = self.check_expression(value)?;
+                let constant = match consteval::evaluate(&checked_value, &self.constants) {
+                    Ok(Some(constant)) => constant,
+                    Ok(None) => {
+                        return Err(TypeError {
+                            code: "E0221",
+                            message: format!("The value of constant {} must be computable at compile time", name),
+                            span: Some(checked_value.span().clone()),
+                            label: None,
+                        });
+                    }
+                    Err(e) => return Err(const_error(e)),
+                };
+
+                let ty = match type_ann {
+                    Some(ty) => {
+                        let ty = self.resolve_type(&ty, &span)?;
+                        let value_type = self.coerce_literal(Some(&checked_value), value_type, &ty, &span)?;
+                        self.check_type_compatibility(value_type, ty.clone(), &span)?;
+                        ty
+                    }
+                    None => value_type,
+                };
+
+                self.check_not_defined(&name, &span)?;
+                self.bind_variable(name.clone(), ty.clone(), &span, SymbolKind::Constant);
+                self.constants.insert(name.clone(), constant);
+
+                let value_span = checked_value.span().clone();
+                Ok(Stmt::Const(name, Some(ty), constant.to_expr(value_span), span))
+            }
+            Stmt::Expr(expr) => {
+                let (checked_expr, _) = self.check_expression(expr)?;
+                Ok(Stmt::Expr(checked_expr))
+            }
+            Stmt::Assign(target, value, span) => {
+                if let Expr::Identifier(name, target_span) = &target {
+                    if self.constants.contains_key(name) {
+                        return Err(TypeError {
+                            code: "E0222",
+                            message: format!("Cannot assign to constant {}", name),
+                            span: Some(target_span.clone()),
+                            label: None,
+                        });
+                    }
+                    if let (Some(start), Some(&index)) = (self.lambda_start, self.variable_bindings.get(name)) {
+                        if index < start {
+                            return Err(TypeError {
+                                code: "E0223",
+                                message: format!(
+                                    "Cannot assign to {} inside a lambda; lambdas capture variables by value",
+                                    name
+                                ),
+                                span: Some(target_span.clone()),
+                                label: None,
+                            });
+                        }
+                    }
+                }
+
+                // Assigning to a variable does not count as reading it
+                let (checked_target, target_type) = match target {
+                    Expr::Identifier(name, target_span) if self.variables.contains_key(&name) => {
+                        let ty = self.variables[&name].clone();
+                        if let Some(&index) = self.variable_bindings.get(&name) {
+                            self.bindings[index].references.push(target_span.clone());
+                        }
+                        (Expr::Identifier(name, target_span), ty)
+                    }
+                    target => self.check_expression(target)?,
+                };
+                let (checked_value, value_type) = self.check_expression_expecting(value, Some(&target_type))?;
+                let value_type = self.coerce_literal(Some(&checked_value), value_type, &target_type, &span)?;
+                self.check_type_compatibility(value_type, target_type, &span)?;
+                Ok(Stmt::Assign(checked_target, checked_value, span))
+            }
+            Stmt::Return(value, span) => {
+                let (checked_value, value_type) = match value {
+                    Some(expr) => {
+                        let expected = self.current_return_type.clone();
+                        let (checked, ty) = self.check_expression_expecting(expr, expected.as_ref())?;
+                        (Some(checked), ty)
+                    }
+                    None => (None, Type::Void),
+                };
+                if let Some(expected) = self.current_return_type.clone() {
+                    let value_type = self.coerce_literal(checked_value.as_ref(), value_type, &expected, &span)?;
+                    self.check_type_compatibility(value_type, expected, &span)?;
+                }
+                Ok(Stmt::Return(checked_value, span))
+            }
+            Stmt::While(cond, body, span) => {
+                let (checked_cond, cond_type) = self.check_expression(cond)?;
+                self.check_type_compatibility(cond_type, Type::Bool, checked_cond.span())?;
+                let checked_body = self.check_statement(*body)?;
+                Ok(Stmt::While(checked_cond, Box::new(checked_body), span))
+            }
+            Stmt::For(variable, variable_span, iterable, body, span) => {
+                let (checked_iterable, item_type) = match iterable {
+                    Expr::Range(start, end, range_span) => {
+                        let (checked_start, start_type) = self.check_expression(*start)?;
+                        self.check_type_compatibility(start_type, Type::Int, checked_start.span())?;
+                        let (checked_end, end_type) = self.check_expression(*end)?;
+                        self.check_type_compatibility(end_type, Type::Int, checked_end.span())?;
+                        (Expr::Range(Box::new(checked_start), Box::new(checked_end), range_span), Type::Int)
+                    }
+                    iterable => {
+                        let (checked_iterable, iterable_type) = self.check_expression(iterable)?;
+                        let item_type = match iterable_type {
+                            Type::Array(elem_type) => *elem_type,
+                            Type::Inferred => Type::Inferred,
+                            Type::Optional(_) => return Err(Self::possibly_null(&iterable_type, checked_iterable.span())),
+                            other => {
+                                return Err(TypeError {
+                                    code: "E0207",
+                                    message: format!("Cannot iterate over value of type {}; loop over an array or a range", other),
+                                    span: Some(checked_iterable.span().clone()),
+                                    label: None,
+                                });
+                            }
+                        };
+                        (checked_iterable, item_type)
+                    }
+                };
+
+                let saved = self.save_scope();
+                self.bind_variable(variable.clone(), item_type, &variable_span, SymbolKind::Variable);
+                let checked_body = self.check_statement(*body);
+                self.restore_scope(saved);
+
+                Ok(Stmt::For(variable, variable_span, checked_iterable, Box::new(checked_body?), span))
+            }
+            Stmt::Block(stmts, span) => {
+                let saved = self.save_scope();
+                let checked = self.check_statements(stmts);
+                self.restore_scope(saved);
+                Ok(Stmt::Block(checked?, span))
+            }
+            Stmt::Function(name, type_params, params, return_type, body, public, attributes, span) => {
+                // `main` is not a C function the compiler could inline
+                if name == "main" {
+                    if let Some(attribute) = attributes.first() {
+                        return Err(TypeError {
+                            code: "E0005",
+                            message: format!("main cannot be {}", attribute),
+                            span: Some(span),
+                            label: None,
+                        });
+                    }
+                }
+                let mut resolved_params = Vec::new();
+                for (param_name, param_type) in params {
+                    resolved_params.push((param_name, self.resolve_type(&param_type, &span)?));
+                }
+                let params = resolved_params;
+                let return_type = self.resolve_type(&return_type, &span)?;
+                // The benchmark runner discards the result, which it could
+                // not release
+                let scalar = return_type.is_integer() || matches!(return_type, Type::Float | Type::Bool | Type::Void);
+                if attributes.contains(&Attribute::Bench) && !scalar {
+                    return Err(TypeError {
+                        code: "E0005",
+                        message: format!("The @bench function {} must return a number, a bool or nothing", name),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+
+                let saved_scope = self.save_scope();
+                for (param_name, param_type) in &params {
+                    self.bind_variable(param_name.clone(), param_type.clone(), &span, SymbolKind::Parameter);
+                }
+                let saved_return = self.current_return_type.replace(return_type.clone());
+                let saved_function = self.current_function.replace(name.clone());
+                let errors_before = self.errors.len();
+
+                let checked_body = self.check_statement(*body);
+
+                self.restore_scope(saved_scope);
+                self.current_return_type = saved_return;
+                self.current_function = saved_function;
+
+                let checked_body = checked_body?;
+                // Statements with errors were dropped, so the body may be
+                // missing a return it actually has
+                let body_ok = self.errors.len() == errors_before;
+                let mut bound = BoundNames(params.iter().map(|(param, _)| param.clone()).collect());
+                bound.visit_stmt(&checked_body);
+                let diverging = self.diverging(&bound.0);
+                if body_ok && return_type != Type::Void && !stmt_always_returns(&checked_body, &diverging) {
+                    return Err(TypeError {
+                        code: "E0210",
+                        message: format!(
+                            "Function {} can reach the end of its body without returning a value of type {}",
+                            name, return_type
+                        ),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+
+                Ok(Stmt::Function(name, type_params, params, return_type, Box::new(checked_body), public, attributes, span))
+            }
+            // Signatures were resolved when the extern was registered, which
+            // only happens at the top level
+            Stmt::Extern(name, params, _, library, span) => {
+                if self.function_spans.get(&name) != Some(&span) {
+                    return Err(TypeError {
+                        code: "E0219",
+                        message: format!("Extern function {} must be declared at the top level", name),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+                let (param_types, return_type) = self.functions[&name].clone();
+                let params = params.into_iter().map(|(param, _)| param).zip(param_types).collect();
+                Ok(Stmt::Extern(name, params, return_type, library, span))
+            }
+            // Payload types were resolved when the enum was registered
+            Stmt::Enum(name, variants, span) => {
+                let variants = self.enums.get(&name).cloned().unwrap_or(variants);
+                Ok(Stmt::Enum(name, variants, span))
+            }
+            // And field types when the struct was
+            Stmt::Struct(name, type_params, fields, span) => {
+                let fields = match self.structs.get(&name) {
+                    Some(types) => fields
+                        .into_iter()
+                        .map(|(field, ty)| {
+                            let ty = types.get(&field).cloned().unwrap_or(ty);
+                            (field, ty)
+                        })
+                        .collect(),
+                    None => fields,
+                };
+                Ok(Stmt::Struct(name, type_params, fields, span))
+            }
+            // Placeholder implementations for other statement types
+            _ => Ok(stmt),
+        }
+    }
+
+    fn check_statements(&mut self, stmts: Vec<Stmt>) -> Result<Vec<Stmt>> {
+        let mut checked = Vec::new();
+        for stmt in stmts {
+            let result = self.check_statement(stmt);
+            checked.extend(self.recover(result));
+        }
+        Ok(checked)
+    }
+
+    pub fn check_expression(&mut self, expr: Expr) -> Result<(Expr, Type)> {
+        if self.generic_variant(&expr).is_some() {
+            return self.check_expression_expecting(expr, None);
+        }
+        match expr {
+            Expr::Literal(lit, span) => {
+                let ty = match lit {
+                    // A literal too large for `int` is a `u64` unless it is
+                    // used as another type, which `coerce_literal` checks
+                    Literal::Int(value) if value > i64::MAX as i128 => Type::U64,
+                    Literal::Int(value) if value < i64::MIN as i128 => {
+                        return Err(TypeError {
+                            code: "E0213",
+                            message: format!(
+                                "Integer literal {} is out of range; no integer type goes below {}",
+                                value,
+                                i64::MIN
+                            ),
+                            span: Some(span),
+                            label: None,
+                        });
+                    }
+                    Literal::Int(_) => Type::Int,
+                    Literal::Float(_) => Type::Float,
+                    Literal::Bool(_) => Type::Bool,
+                    Literal::String(_) => Type::String,
+                    Literal::Null => Type::Optional(Box::new(Type::Inferred)),
+                };
+                Ok((Expr::Literal(lit, span), ty))
+            }
+            Expr::Identifier(name, span) => {
+                if let Some(ty) = self.variables.get(&name) {
+                    let ty = ty.clone();
+                    if let Some(&index) = self.variable_bindings.get(&name) {
+                        self.bindings[index].used = true;
+                        self.bindings[index].references.push(span.clone());
+                    }
+                    // Uses of constants are replaced by their values
+                    match self.constants.get(&name) {
+                        Some(constant) => Ok((constant.to_expr(span), ty)),
+                        None => Ok((Expr::Identifier(name, span), ty)),
+                    }
+                } else if let Some((params, ret)) = self.functions.get(&name) {
+                    let ty = Type::Function(params.clone(), Box::new(ret.clone()));
+                    self.mark_function_used(&name, &span);
+                    Ok((Expr::Identifier(name, span), ty))
+                } else if self.generic_functions.contains_key(&name) {
+                    Err(TypeError {
+                        code: "E0216",
+                        message: format!(
+                            "Generic function {} can only be called, not used as a value; wrap it in a lambda",
+                            name
+                        ),
+                        span: Some(span),
+                        label: None,
+                    })
+                } else {
+                    // The names of `std.math` are only there once it is imported
+                    let hint = if !self.math && (stdlib::MATH_FUNCTIONS.contains(&name.as_str()) || name == "pi") {
+                        format!("; it is in {}, brought in by `import {};`", stdlib::MATH, stdlib::MATH)
+                    } else {
+                        String::new()
+                    };
+                    Err(TypeError {
+                        code: "E0101",
+                        message: format!("Undefined variable: {}{}", name, hint),
+                        span: Some(span),
+                        label: None,
+                    })
+                }
+            }
+            Expr::Binary(left, op, right, span) => {
+                let (checked_left, left_type) = self.check_expression(*left)?;
+                // `x != null && ...` and `x == null || ...` only evaluate their
+                // right side when `x` is not null
+                let narrowed = match (&op, self.null_guard(&checked_left)) {
+                    (BinaryOp::And, Some((name, true))) | (BinaryOp::Or, Some((name, false))) => Some(name),
+                    _ => None,
+                };
+                let (checked_right, right_type) = self.with_narrowed(narrowed, |checker| checker.check_expression(*right))?;
+
+                // A literal operand takes on the sized integer type of the other side
+                let left_type = self.coerce_literal(Some(&checked_left), left_type, &right_type, &span)?;
+                let right_type = self.coerce_literal(Some(&checked_right), right_type, &left_type, &span)?;
+
+                let result_type = self.check_binary_op(&op, &left_type, &right_type, &span)?;
+
+                let expr = Expr::Binary(Box::new(checked_left), op, Box::new(checked_right), span);
+                Ok((self.fold(expr, &result_type)?, result_type))
+            }
+            Expr::Unary(op, operand, span) => {
+                let (checked_operand, operand_type) = self.check_expression(*operand)?;
+                if let Type::Optional(_) = operand_type {
+                    return Err(Self::possibly_null(&operand_type, &span));
+                }
+                let result_type = match (&op, &operand_type) {
+                    (UnaryOp::Neg, ty) if ty.is_integer() && !ty.is_unsigned() => operand_type,
+                    (UnaryOp::Neg, Type::Float) => operand_type,
+                    (UnaryOp::Not, Type::Bool) => Type::Bool,
+                    (_, Type::Inferred) => Type::Inferred,
+                    _ => {
+                        return Err(TypeError {
+                            code: "E0204",
+                            message: format!("Invalid operand type for unary operator {:?}: {}", op, operand_type),
+                            span: Some(span),
+                            label: None,
+                        });
+                    }
+                };
+                let expr = Expr::Unary(op, Box::new(checked_operand), span);
+                Ok((self.fold(expr, &result_type)?, result_type))
+            }
+            Expr::Call(callee, args, span) => self.check_call(*callee, args, span),
+            Expr::Cast(operand, target, span) => {
+                let target = self.resolve_type(&target, &span)?;
+                let (checked_operand, operand_type) = self.check_expression(*operand)?;
+                let is_numeric = |ty: &Type| ty.is_integer() || *ty == Type::Float;
+                let allowed = operand_type == target
+                    || operand_type == Type::Inferred
+                    || (is_numeric(&operand_type) && is_numeric(&target))
+                    || (operand_type.is_integer() && target == Type::Bool)
+                    || (operand_type == Type::Bool && target.is_integer());
+                if !allowed {
+                    return Err(TypeError {
+                        code: "E0206",
+                        message: format!("Cannot cast {} to {}", operand_type, target),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+                let expr = Expr::Cast(Box::new(checked_operand), target.clone(), span);
+                Ok((self.fold(expr, &target)?, target))
+            }
+            Expr::Array(elements, span) => {
+                let mut elem_type = Type::Inferred;
+                let mut checked_elements = Vec::new();
+                for element in elements {
+                    let (checked, ty) = self.check_expression(element)?;
+                    if elem_type == Type::Inferred {
+                        elem_type = ty;
+                    } else {
+                        self.check_type_compatibility(ty, elem_type.clone(), checked.span())?;
+                    }
+                    checked_elements.push(checked);
+                }
+                Ok((Expr::Array(checked_elements, span), Type::Array(Box::new(elem_type))))
+            }
+            Expr::Index(array, index, span) => {
+                let (checked_array, array_type) = self.check_expression(*array)?;
+                let (checked_index, index_type) = self.check_expression(*index)?;
+                self.check_type_compatibility(index_type, Type::Int, checked_index.span())?;
+                let elem_type = match array_type {
+                    Type::Array(elem_type) => *elem_type,
+                    Type::Inferred => Type::Inferred,
+                    Type::Optional(_) => return Err(Self::possibly_null(&array_type, &span)),
+                    other => {
+                        return Err(TypeError {
+                            code: "E0207",
+                            message: format!("Cannot index into value of type {}", other),
+                            span: Some(span),
+                            label: None,
+                        });
+                    }
+                };
+                Ok((Expr::Index(Box::new(checked_array), Box::new(checked_index), span), elem_type))
+            }
+            Expr::If(cond, then_branch, else_branch, span) => {
+                self.check_if(*cond, *then_branch, else_branch.map(|branch| *branch), span, None)
+            }
+            Expr::Field(object, field, span) => {
+                // `Shape.Empty` is a variant without a payload
+                if let Expr::Identifier(name, name_span) = object.as_ref() {
+                    if self.is_enum_name(name) {
+                        self.type_references.entry(name.clone()).or_default().push(name_span.clone());
+                        return self.check_variant(name.clone(), field, Vec::new(), span);
+                    }
+                }
+
+                let (checked_object, object_type) = self.check_expression(*object)?;
+                self.check_field(checked_object, object_type, field, span)
+            }
+            Expr::Match(scrutinee, arms, span) => self.check_match(*scrutinee, arms, span, None),
+            Expr::Try(value, _, span) => self.check_try(*value, span),
+            Expr::Lambda(params, return_type, body, span) => self.check_lambda(params, return_type, *body, span, None),
+            Expr::Block(stmts, span) => {
+                let saved = self.save_scope();
+                let result = self.check_block(stmts, None);
+                self.restore_scope(saved);
+                let (checked, ty) = result?;
+                Ok((Expr::Block(checked, span), ty))
+            }
+            // Placeholder implementations for other expression types
+            _ => Ok((expr, Type::Inferred)),
+        }
+    }
+
+    /// Checks `expr` where a value of type `expected` is wanted, which lets a
+    /// lambda leave out the types of its parameters, and `None`, `Ok(x)` and
+    /// `Err(e)` the type arguments they do not determine
+    fn check_expression_expecting(&mut self, expr: Expr, expected: Option<&Type>) -> Result<(Expr, Type)> {
+        if let Some((enum_name, variant)) = self.generic_variant(&expr) {
+            let (args, span) = match expr {
+                Expr::Call(_, args, span) => (args, span),
+                expr => (Vec::new(), expr.span().clone()),
+            };
+            return self.check_generic_variant(&enum_name, variant, args, span, expected);
+        }
+        match (expr, expected) {
+            (Expr::Lambda(params, return_type, body, span), Some(Type::Function(param_types, ret))) => {
+                let expected = (param_types.as_slice(), ret.as_ref());
+                self.check_lambda(params, return_type, *body, span, Some(expected))
+            }
+            (Expr::If(cond, then_branch, else_branch, span), Some(_)) => {
+                self.check_if(*cond, *then_branch, else_branch.map(|branch| *branch), span, expected)
+            }
+            (Expr::Match(scrutinee, arms, span), Some(_)) => self.check_match(*scrutinee, arms, span, expected),
+            // An integer literal is checked against the integer type wanted,
+            // even where it fits no integer type at all
+            (expr @ Expr::Literal(Literal::Int(_), _), Some(expected)) if expected.integer_range().is_some() => {
+                let span = expr.span().clone();
+                let ty = self.coerce_literal(Some(&expr), Type::Int, expected, &span)?;
+                Ok((expr, ty))
+            }
+            // The elements of an array literal are each used as the element
+            // type, so `[1]` can be an array of `int?`
+            (Expr::Array(elements, span), Some(Type::Array(element))) if **element != Type::Inferred => {
+                let mut checked_elements = Vec::new();
+                for element_expr in elements {
+                    let (checked, ty) = self.check_expression_expecting(element_expr, Some(element))?;
+                    self.check_type_compatibility(ty, (**element).clone(), checked.span())?;
+                    checked_elements.push(checked);
+                }
+                Ok((Expr::Array(checked_elements, span), Type::Array(element.clone())))
+            }
+            (Expr::Block(stmts, span), Some(_)) => {
+                let saved = self.save_scope();
+                let result = self.check_block(stmts, expected);
+                self.restore_scope(saved);
+                let (checked, ty) = result?;
+                Ok((Expr::Block(checked, span), ty))
+            }
+            (expr, _) => self.check_expression(expr),
+        }
+    }
+
+    /// Checks an `if`, whose value is that of its branches, with the type
+    /// the `then` branch has wanted of the `else` branch too
+    fn check_if(
+        &mut self,
+        cond: Expr,
+        then_branch: Expr,
+        else_branch: Option<Expr>,
+        span: Span,
+        expected: Option<&Type>,
+    ) -> Result<(Expr, Type)> {
+        let (checked_cond, cond_type) = self.check_expression(cond)?;
+        self.check_type_compatibility(cond_type, Type::Bool, checked_cond.span())?;
+
+        // Within a branch guarded by a null check, the variable is known
+        // not to be null
+        let (then_narrowed, else_narrowed) = match self.null_guard(&checked_cond) {
+            Some((name, true)) => (Some(name), None),
+            Some((name, false)) => (None, Some(name)),
+            None => (None, None),
+        };
+
+        let (checked_then, then_type) =
+            self.with_narrowed(then_narrowed, |checker| checker.check_expression_expecting(then_branch, expected))?;
+        let (checked_else, result_type) = match else_branch {
+            Some(else_branch) => {
+                let expected = expected.cloned().unwrap_or_else(|| then_type.clone());
+                let (checked, _) = self.with_narrowed(else_narrowed, |checker| {
+                    checker.check_expression_expecting(else_branch, Some(&expected))
+                })?;
+                (Some(Box::new(checked)), then_type)
+            }
+            None => (None, Type::Void),
+        };
+        Ok((Expr::If(Box::new(checked_cond), Box::new(checked_then), checked_else, span), result_type))
+    }
+
+    /// Checks a lambda, taking the types of unannotated parameters and the
+    /// return type from `expected` if given. The checked lambda has every
+    /// type filled in.
+    fn check_lambda(
+        &mut self,
+        params: Vec<(String, Option<Type>)>,
+        return_type: Option<Type>,
+        body: Expr,
+        span: Span,
+        expected: Option<(&[Type], &Type)>,
+    ) -> Result<(Expr, Type)> {
+        if let Some((expected_params, _)) = expected {
+            if expected_params.len() != params.len() {
+                return Err(TypeError {
+                    code: "E0202",
+                    message: format!(
+                        "Lambda takes {} parameter{}, expected {}",
+                        params.len(),
+                        if params.len() == 1 { "" } else { "s" },
+                        expected_params.len()
+                    ),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+        }
+
+        let mut param_types = Vec::new();
+        for (i, (name, ty)) in params.iter().enumerate() {
+            let ty = match (ty, expected) {
+                (Some(ty), _) => self.resolve_type(ty, &span)?,
+                (None, Some((expected_params, _))) => expected_params[i].clone(),
+                (None, None) => {
+                    return Err(TypeError {
+                        code: "E0209",
+                        message: format!("Cannot infer the type of lambda parameter {}; annotate it", name),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+            };
+            param_types.push(ty);
+        }
+        let declared_return = match (return_type, expected) {
+            (Some(ty), _) => Some(self.resolve_type(&ty, &span)?),
+            (None, Some((_, ret))) if *ret != Type::Inferred => Some(ret.clone()),
+            (None, _) => None,
+        };
+
+        let saved_scope = self.save_scope();
+        let saved_lambda = self.lambda_start.replace(self.bindings.len());
+        // `return` in the body leaves the lambda, not the enclosing function
+        let saved_return = self
+            .current_return_type
+            .replace(declared_return.clone().unwrap_or(Type::Inferred));
+        for (name, ty) in params.iter().zip(&param_types) {
+            self.bind_variable(name.0.clone(), ty.clone(), &span, SymbolKind::Parameter);
+        }
+        let result = match &declared_return {
+            Some(ret) => self.check_expression_expecting(body, Some(ret)),
+            None => self.check_expression(body),
+        };
+        self.restore_scope(saved_scope);
+        self.lambda_start = saved_lambda;
+        self.current_return_type = saved_return;
+        let (checked_body, body_type) = result?;
+
+        let return_type = match declared_return {
+            Some(ret) => {
+                let mut bound = BoundNames(params.iter().map(|(param, _)| param.clone()).collect());
+                bound.visit_expr(&checked_body);
+                if ret != Type::Void && !expr_always_returns(&checked_body, &self.diverging(&bound.0)) {
+                    let body_type = self.coerce_literal(Some(&checked_body), body_type, &ret, checked_body.span())?;
+                    self.check_type_compatibility(body_type, ret.clone(), checked_body.span())?;
+                }
+                ret
+            }
+            None => body_type,
+        };
+
+        let checked_params = params
+            .into_iter()
+            .zip(param_types.iter().cloned())
+            .map(|((name, _), ty)| (name, Some(ty)))
+            .collect();
+        let ty = Type::Function(param_types, Box::new(return_type.clone()));
+        let lambda = Expr::Lambda(checked_params, Some(return_type), Box::new(checked_body), span);
+        Ok((lambda, ty))
+    }
+
+    /// The names a body can end a path by calling: `panic` and the functions
+    /// that never return, less those of variables in scope or `bound` in the
+    /// body
+    fn diverging(&self, bound: &HashSet<String>) -> HashSet<String> {
+        let panic = (!self.functions.contains_key("panic")).then(|| "panic".to_string());
+        self.never_returning
+            .iter()
+            .cloned()
+            .chain(panic)
+            .filter(|name| !self.variables.contains_key(name) && !bound.contains(name))
+            .collect()
+    }
+
+    /// Recognises `x != null` and `x == null` (either way round) where `x` is
+    /// an optional variable. Returns the variable's name and whether the
+    /// condition being true means it is not null.
+    fn null_guard(&self, cond: &Expr) -> Option<(String, bool)> {
+        let (left, op, right) = match cond {
+            Expr::Binary(left, op @ (BinaryOp::Eq | BinaryOp::Neq), right, _) => (left, op, right),
+            _ => return None,
+        };
+        let name = match (left.as_ref(), right.as_ref()) {
+            (Expr::Identifier(name, _), Expr::Literal(Literal::Null, _))
+            | (Expr::Literal(Literal::Null, _), Expr::Identifier(name, _)) => name,
+            _ => return None,
+        };
+        match self.variables.get(name) {
+            Some(Type::Optional(_)) => Some((name.clone(), *op == BinaryOp::Neq)),
+            _ => None,
+        }
+    }
+
+    /// Runs `check` with the optional variable `name` (if any) treated as
+    /// its non-null type
+    fn with_narrowed<T>(&mut self, name: Option<String>, check: impl FnOnce(&mut Self) -> T) -> T {
+        let (name, optional) = match name.and_then(|name| self.variables.get(&name).cloned().map(|ty| (name, ty))) {
+            Some((name, Type::Optional(inner))) => {
+                self.variables.insert(name.clone(), *inner.clone());
+                (name, Type::Optional(inner))
+            }
+            _ => return check(self),
+        };
+        let result = check(self);
+        self.variables.insert(name, optional);
+        result
+    }
+
+    fn possibly_null(ty: &Type, span: &Span) -> TypeError {
+        TypeError {
+            code: "E0212",
+            message: format!(
+                "Value of type {} may be null; check it with `!= null` before using it",
+                ty
+            ),
+            span: Some(span.clone()),
+            label: None,
+        }
+    }
+
+    /// Records a reference to a function. Calls from a function to itself
+    /// do not count as uses.
+    fn mark_function_used(&mut self, name: &str, span: &Span) {
+        if self.function_spans.contains_key(name) {
+            self.function_references.entry(name.to_string()).or_default().push(span.clone());
+        }
+        if self.current_function.as_deref() != Some(name) {
+            self.used_functions.insert(name.to_string());
+        }
+        self.function_uses.entry(self.current_function.clone()).or_default().insert(name.to_string());
+    }
+
+    /// Checks a call of the `len` builtin, which takes an array of any
+    /// element type
+    fn check_len(&mut self, callee: Expr, args: Vec<Expr>, span: crate::ast::Span) -> Result<(Expr, Type)> {
+        if args.len() != 1 {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!("Function len expects 1 argument, found {}", args.len()),
+                span: Some(span),
+                label: None,
+            });
+        }
+        let (checked_arg, arg_type) = self.check_expression(args.into_iter().next().unwrap())?;
+        match arg_type {
+            Type::Array(_) | Type::Inferred => {}
+            Type::Optional(_) => return Err(Self::possibly_null(&arg_type, &span)),
+            other => {
+                return Err(TypeError {
+                    code: "E0203",
+                    message: format!("Function len expects an array, found {}", other),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+        }
+        Ok((Expr::Call(Box::new(callee), vec![checked_arg], span), Type::Int))
+    }
+
+    /// Checks a call of the `panic` builtin, which takes the message to stop
+    /// the program with
+    fn check_panic(&mut self, callee: Expr, args: Vec<Expr>, span: crate::ast::Span) -> Result<(Expr, Type)> {
+        if args.len() != 1 {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!("Function panic expects 1 argument, found {}", args.len()),
+                span: Some(span),
+                label: None,
+            });
+        }
+        let checked_arg = self.check_string_arg("panic", args.into_iter().next().unwrap())?;
+        Ok((Expr::Call(Box::new(callee), vec![checked_arg], span), Type::Void))
+    }
+
+    /// Checks a call of the `assert` builtin, which takes a condition and,
+    /// optionally, the message to stop the program with when it is false
+    fn check_assert(&mut self, callee: Expr, args: Vec<Expr>, span: crate::ast::Span) -> Result<(Expr, Type)> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!("Function assert expects 1 or 2 arguments, found {}", args.len()),
+                span: Some(span),
+                label: None,
+            });
+        }
+        let mut args = args.into_iter();
+        let (checked_cond, cond_type) = self.check_expression(args.next().unwrap())?;
+        self.check_type_compatibility(cond_type, Type::Bool, checked_cond.span())?;
+        let mut checked_args = vec![checked_cond];
+        if let Some(message) = args.next() {
+            checked_args.push(self.check_string_arg("assert", message)?);
+        }
+        Ok((Expr::Call(Box::new(callee), checked_args, span), Type::Void))
+    }
+
+    /// Checks an argument of the builtin `function` that must be a string
+    fn check_string_arg(&mut self, function: &str, arg: Expr) -> Result<Expr> {
+        let (checked_arg, arg_type) = self.check_expression(arg)?;
+        match arg_type {
+            Type::String | Type::Inferred => Ok(checked_arg),
+            Type::Optional(_) => Err(Self::possibly_null(&arg_type, checked_arg.span())),
+            other => Err(TypeError {
+                code: "E0203",
+                message: format!("Function {} expects a string, found {}", function, other),
+                span: Some(checked_arg.span().clone()),
+                label: None,
+            }),
+        }
+    }
+
+    /// Checks a call of `args()`, the arguments the program was run with, or
+    /// of `env(name)`, the environment variable `name` or null when it is
+    /// not set
+    fn check_process(&mut self, callee: Expr, args: Vec<Expr>, span: crate::ast::Span) -> Result<(Expr, Type)> {
+        let (name, params, return_type) = match &callee {
+            Expr::Identifier(name, _) if name == "args" => (name.clone(), 0, Type::Array(Box::new(Type::String))),
+            Expr::Identifier(name, _) => (name.clone(), 1, Type::Optional(Box::new(Type::String))),
+            _ => unreachable!("process builtins are called by name"),
+        };
+        if args.len() != params {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!(
+                    "Function {} expects {} argument{}, found {}",
+                    name,
+                    params,
+                    if params == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span: Some(span),
+                label: None,
+            });
+        }
+        let mut checked_args = Vec::new();
+        for arg in args {
+            checked_args.push(self.check_string_arg(&name, arg)?);
+        }
+        Ok((Expr::Call(Box::new(callee), checked_args, span), return_type))
+    }
+
+    /// Checks a call of a function of `std.math`. `sqrt`, `pow`, `floor` and
+    /// `ceil` take and return floats, while `abs`, `min` and `max` take ints
+    /// or floats and return the type they take.
+    fn check_math(&mut self, callee: Expr, args: Vec<Expr>, span: crate::ast::Span) -> Result<(Expr, Type)> {
+        let name = match &callee {
+            Expr::Identifier(name, _) => name.clone(),
+            _ => unreachable!("math builtins are called by name"),
+        };
+        let arity = if matches!(name.as_str(), "pow" | "min" | "max") { 2 } else { 1 };
+        if args.len() != arity {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!(
+                    "Function {} expects {} argument{}, found {}",
+                    name,
+                    arity,
+                    if arity == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span: Some(span),
+                label: None,
+            });
+        }
+
+        let mut checked_args = Vec::new();
+        let mut arg_types = Vec::new();
+        for arg in args {
+            let (checked_arg, arg_type) = self.check_expression(arg)?;
+            checked_args.push(checked_arg);
+            arg_types.push(arg_type);
+        }
+        let floats_only = !matches!(name.as_str(), "abs" | "min" | "max");
+        if let [left, right] = arg_types.as_mut_slice() {
+            // A literal argument takes on the sized integer type of the other
+            *left = self.coerce_literal(checked_args.first(), left.clone(), right, &span)?;
+            *right = self.coerce_literal(checked_args.last(), right.clone(), left, &span)?;
+        }
+        for (arg, ty) in checked_args.iter().zip(&arg_types) {
+            match ty {
+                Type::Float | Type::Inferred => {}
+                ty if ty.is_integer() && !floats_only => {}
+                Type::Optional(_) => return Err(Self::possibly_null(ty, arg.span())),
+                ty if ty.is_integer() => {
+                    return Err(TypeError {
+                        code: "E0205",
+                        message: format!("Function {} expects a float, found {}; convert it with `as float`", name, ty),
+                        span: Some(arg.span().clone()),
+                        label: None,
+                    });
+                }
+                other => {
+                    return Err(TypeError {
+                        code: "E0203",
+                        message: format!(
+                            "Function {} expects {}, found {}",
+                            name,
+                            if floats_only { "a float" } else { "an int or a float" },
+                            other
+                        ),
+                        span: Some(arg.span().clone()),
+                        label: None,
+                    });
+                }
+            }
+        }
+        if let [left, right] = arg_types.as_slice() {
+            if !floats_only && is_numeric_conversion(left, right) {
+                return Err(TypeError {
+                    code: "E0205",
+                    message: format!(
+                        "Cannot mix {} and {} arguments; convert one explicitly with `as`",
+                        left, right
+                    ),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+        }
+
+        let return_type = match arg_types.as_slice() {
+            _ if floats_only => Type::Float,
+            [Type::Inferred, other] => other.clone(),
+            [first, ..] => first.clone(),
+            [] => Type::Inferred,
+        };
+        Ok((Expr::Call(Box::new(callee), checked_args, span), return_type))
+    }
+
+    /// Checks a call of `print`, which takes one value of any printable type,
+    /// or `printf`, whose format must be a string literal with a placeholder
+    /// for each value that follows it
+    fn check_print(&mut self, callee: Expr, args: Vec<Expr>, span: crate::ast::Span) -> Result<(Expr, Type)> {
+        let name = match &callee {
+            Expr::Identifier(name, _) => name.clone(),
+            _ => unreachable!("print builtins are called by name"),
+        };
+        let mut args = args.into_iter();
+        let mut checked_args = Vec::new();
+        let placeholders = if name == "print" {
+            1
+        } else {
+            let format = match args.next() {
+                Some(Expr::Literal(Literal::String(format), format_span)) => {
+                    checked_args.push(Expr::Literal(Literal::String(format.clone()), format_span.clone()));
+                    format::parse(&format).map_err(|message| TypeError {
+                        code: "E0215",
+                        message,
+                        span: Some(format_span),
+                        label: None,
+                    })?
+                }
+                Some(other) => {
+                    return Err(TypeError {
+                        code: "E0215",
+                        message: "The format of printf must be a string literal".to_string(),
+                        span: Some(other.span().clone()),
+                        label: None,
+                    });
+                }
+                None => {
+                    return Err(TypeError {
+                        code: "E0202",
+                        message: "Function printf expects a format".to_string(),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+            };
+            format.iter().filter(|piece| **piece == Piece::Value).count()
+        };
+
+        if args.len() != placeholders {
+            return Err(TypeError {
+                code: "E0202",
+                message: if name == "print" {
+                    format!("Function print expects 1 argument, found {}", args.len())
+                } else {
+                    format!(
+                        "Format of printf has {} placeholder{}, found {} value{}",
+                        placeholders,
+                        if placeholders == 1 { "" } else { "s" },
+                        args.len(),
+                        if args.len() == 1 { "" } else { "s" }
+                    )
+                },
+                span: Some(span),
+                label: None,
+            });
+        }
+
+        for arg in args {
+            let (checked_arg, arg_type) = self.check_expression(arg)?;
+            match arg_type {
+                Type::Optional(_) => return Err(Self::possibly_null(&arg_type, checked_arg.span())),
+                ref ty if !format::is_printable(ty) => {
+                    return Err(TypeError {
+                        code: "E0214",
+                        message: format!("Cannot print a value of type {}", ty),
+                        span: Some(checked_arg.span().clone()),
+                        label: None,
+                    });
+                }
+                _ => {}
+            }
+            checked_args.push(checked_arg);
+        }
+        Ok((Expr::Call(Box::new(callee), checked_args, span), Type::Void))
+    }
+
+    /// Resolves the callee of a call, then checks argument count and types
+    /// against its signature. Returns the callee's declared return type.
+    fn check_call(&mut self, callee: Expr, args: Vec<Expr>, span: crate::ast::Span) -> Result<(Expr, Type)> {
+        // `Shape.Circle(2.0)` constructs a variant with a payload
+        if let Expr::Field(object, variant, _) = &callee {
+            if let Expr::Identifier(name, name_span) = object.as_ref() {
+                if self.is_enum_name(name) {
+                    self.type_references.entry(name.clone()).or_default().push(name_span.clone());
+                    return self.check_variant(name.clone(), variant.clone(), args, span);
+                }
+            }
+        }
+
+        if let Expr::Identifier(name, callee_span) = &callee {
+            if !self.variables.contains_key(name) && self.generic_functions.contains_key(name) {
+                return self.check_generic_call(name.clone(), callee_span.clone(), args, span);
+            }
+            if name == "len" && !self.variables.contains_key(name) && !self.functions.contains_key(name) {
+                return self.check_len(callee, args, span);
+            }
+            if format::is_print(name) && !self.variables.contains_key(name) && !self.functions.contains_key(name) {
+                return self.check_print(callee, args, span);
+            }
+            if name == "panic" && !self.variables.contains_key(name) && !self.functions.contains_key(name) {
+                return self.check_panic(callee, args, span);
+            }
+            if name == "assert" && !self.variables.contains_key(name) && !self.functions.contains_key(name) {
+                return self.check_assert(callee, args, span);
+            }
+            if matches!(name.as_str(), "args" | "env") && !self.variables.contains_key(name) && !self.functions.contains_key(name) {
+                return self.check_process(callee, args, span);
+            }
+            if self.math
+                && stdlib::MATH_FUNCTIONS.contains(&name.as_str())
+                && !self.variables.contains_key(name)
+                && !self.functions.contains_key(name)
+            {
+                return self.check_math(callee, args, span);
+            }
+        }
+
+        let (checked_callee, callee_type) = match callee {
+            // `text.len()` calls a method of a string, `items.push(x)` one
+            // of an array
+            Expr::Field(object, field, field_span) => {
+                let (checked_object, object_type) = self.check_expression(*object)?;
+                let method = match &object_type {
+                    Type::String => Some(("Strings", string_method(&field))),
+                    Type::Array(element) => Some(("Arrays", array_method(&field, element))),
+                    _ => None,
+                };
+                match method {
+                    Some((_, Some((params, ret)))) => {
+                        if let Type::Array(element) = &object_type {
+                            if matches!(field.as_str(), "push" | "pop") {
+                                self.check_array_receiver(&checked_object, element, &field, &field_span)?;
+                            }
+                        }
+                        (Expr::Field(Box::new(checked_object), field, field_span), Type::Function(params, Box::new(ret)))
+                    }
+                    Some((kind, None)) => {
+                        return Err(TypeError {
+                            code: "E0207",
+                            message: format!("{} have no method {}", kind, field),
+                            span: Some(field_span),
+                            label: None,
+                        });
+                    }
+                    None => self.check_field(checked_object, object_type, field, field_span)?,
+                }
+            }
+            callee => self.check_expression(callee)?,
+        };
+        let callee_name = match &checked_callee {
+            Expr::Identifier(name, _) | Expr::Field(_, name, _) => name.clone(),
+            _ => "expression".to_string(),
+        };
+
+        let (param_types, return_type) = match callee_type {
+            Type::Function(params, ret) => (params, *ret),
+            Type::Inferred => {
+                // The callee's type is unknown, so only the arguments can be checked
+                let mut checked_args = Vec::new();
+                for arg in args {
+                    checked_args.push(self.check_expression(arg)?.0);
+                }
+                return Ok((Expr::Call(Box::new(checked_callee), checked_args, span), Type::Inferred));
+            }
+            other => {
+                return Err(TypeError {
+                    code: "E0208",
+                    message: format!("{} is not a function, found {}", callee_name, other),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+        };
+
+        if args.len() != param_types.len() {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!(
+                    "Function {} expects {} argument{}, found {}",
+                    callee_name,
+                    param_types.len(),
+                    if param_types.len() == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span: Some(span),
+                label: None,
+            });
+        }
+
+        let mut checked_args = Vec::new();
+        for (i, (arg, param_type)) in args.into_iter().zip(param_types).enumerate() {
+            let (checked_arg, arg_type) = self.check_expression_expecting(arg, Some(&param_type))?;
+            let arg_type = self.coerce_literal(Some(&checked_arg), arg_type, &param_type, checked_arg.span())?;
+            if !Self::is_compatible(&arg_type, &param_type) {
+                return Err(TypeError {
+                    code: "E0203",
+                    message: format!(
+                        "Argument {} of {} has type {}, expected {}",
+                        i + 1,
+                        callee_name,
+                        arg_type,
+                        param_type
+                    ),
+                    span: Some(checked_arg.span().clone()),
+                    label: None,
+                });
+            }
+            checked_args.push(checked_arg);
+        }
+
+        Ok((Expr::Call(Box::new(checked_callee), checked_args, span), return_type))
+    }
+
+    /// Checks the access of `field` on `object`, already checked as a value
+    /// of `object_type`
+    fn check_field(&mut self, object: Expr, object_type: Type, field: String, span: Span) -> Result<(Expr, Type)> {
+        let field_type = match &object_type {
+            Type::Struct(name) => match self.structs.get(name) {
+                Some(fields) => match fields.get(&field) {
+                    Some(ty) => {
+                        self.field_uses
+                            .entry(self.current_function.clone())
+                            .or_default()
+                            .insert((name.clone(), field.clone()));
+                        ty.clone()
+                    }
+                    None => {
+                        return Err(TypeError {
+                            code: "E0106",
+                            message: format!("Struct {} has no field {}", name, field),
+                            span: Some(span),
+                            label: None,
+                        });
+                    }
+                },
+                // A generic struct used without type arguments
+                None => Type::Inferred,
+            },
+            Type::Optional(_) => return Err(Self::possibly_null(&object_type, &span)),
+            Type::Inferred => Type::Inferred,
+            Type::String if string_method(&field).is_some() => {
+                return Err(TypeError {
+                    code: "E0207",
+                    message: format!("Method {} of strings must be called, as in `text.{}()`", field, field),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+            Type::Array(element) if array_method(&field, element).is_some() => {
+                return Err(TypeError {
+                    code: "E0207",
+                    message: format!("Method {} of arrays must be called, as in `items.{}()`", field, field),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+            other => {
+                return Err(TypeError {
+                    code: "E0207",
+                    message: format!("Cannot access field {} on value of type {}", field, other),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+        };
+        Ok((Expr::Field(Box::new(object), field, span), field_type))
+    }
+
+    /// Checks that `push` or `pop` can change the array `object`, whose
+    /// elements are of type `element`: it must be a variable, or an element
+    /// or field of one, and the type of its elements known
+    fn check_array_receiver(&self, object: &Expr, element: &Type, method: &str, span: &Span) -> Result<()> {
+        if *element == Type::Inferred {
+            return Err(TypeError {
+                code: "E0209",
+                message: format!(
+                    "Cannot infer the element type of the array to {}; declare it, as in `let items: Vec<int> = [];`",
+                    method
+                ),
+                span: Some(span.clone()),
+                label: None,
+            });
+        }
+        // An element or field is changed where it is, in the variable
+        // holding it
+        let mut place = object;
+        while let Expr::Index(holder, _, _) | Expr::Field(holder, _, _) = place {
+            place = holder;
+        }
+        match place {
+            Expr::Identifier(name, name_span) => {
+                if let (Some(start), Some(&index)) = (self.lambda_start, self.variable_bindings.get(name)) {
+                    if index < start {
+                        return Err(TypeError {
+                            code: "E0223",
+                            message: format!(
+                                "Cannot {} {} inside a lambda; lambdas capture variables by value",
+                                method, name
+                            ),
+                            span: Some(name_span.clone()),
+                            label: None,
+                        });
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(TypeError {
+                code: "E0207",
+                message: format!("Cannot {} a temporary array; store it in a variable first", method),
+                span: Some(span.clone()),
+                label: None,
+            }),
+        }
+    }
+
+    /// Whether `name` refers to an enum rather than a variable
+    fn is_enum_name(&self, name: &str) -> bool {
+        !self.variables.contains_key(name) && self.enums.contains_key(name)
+    }
+
+    /// Checks the construction of an enum variant against its payload types
+    fn check_variant(&mut self, enum_name: String, variant: String, args: Vec<Expr>, span: Span) -> Result<(Expr, Type)> {
+        let payload = self.variant_payload(&enum_name, &variant, &span)?;
+
+        if args.len() != payload.len() {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!(
+                    "Variant {}.{} expects {} value{}, found {}",
+                    enum_name,
+                    variant,
+                    payload.len(),
+                    if payload.len() == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span: Some(span),
+                label: None,
+            });
+        }
+
+        let mut checked_args = Vec::new();
+        for (i, (arg, expected)) in args.into_iter().zip(payload).enumerate() {
+            let (checked_arg, arg_type) = self.check_expression_expecting(arg, Some(&expected))?;
+            let arg_type = self.coerce_literal(Some(&checked_arg), arg_type, &expected, checked_arg.span())?;
+            if !Self::is_compatible(&arg_type, &expected) {
+                return Err(TypeError {
+                    code: "E0203",
+                    message: format!(
+                        "Value {} of {}.{} has type {}, expected {}",
+                        i + 1,
+                        enum_name,
+                        variant,
+                        arg_type,
+                        expected
+                    ),
+                    span: Some(checked_arg.span().clone()),
+                    label: None,
+                });
+            }
+            checked_args.push(checked_arg);
+        }
+
+        let ty = Type::Enum(enum_name.clone());
+        Ok((Expr::Variant(enum_name, variant, checked_args, span), ty))
+    }
+
+    /// The builtin generic enum and variant `expr` constructs, as `Some(x)`,
+    /// `None` and `Result.Ok(x)` do, unless a variable or function hides
+    /// the name
+    fn generic_variant(&self, expr: &Expr) -> Option<(String, String)> {
+        let callee = match expr {
+            Expr::Call(callee, _, _) => callee.as_ref(),
+            expr => expr,
+        };
+        match callee {
+            Expr::Identifier(name, _)
+                if !self.variables.contains_key(name)
+                    && !self.functions.contains_key(name)
+                    && !self.generic_functions.contains_key(name) =>
+            {
+                self.generic_enums
+                    .iter()
+                    .find(|(_, (_, variants))| variants.iter().any(|(variant, _)| variant == name))
+                    .map(|(enum_name, _)| (enum_name.clone(), name.clone()))
+            }
+            Expr::Field(object, variant, _) => match object.as_ref() {
+                Expr::Identifier(name, _) if !self.variables.contains_key(name) && self.generic_enums.contains_key(name) => {
+                    Some((name.clone(), variant.clone()))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Checks the construction of a variant of a builtin generic enum. The
+    /// type arguments come from `expected` where it is an instance of the
+    /// enum, and otherwise from the payload.
+    fn check_generic_variant(
+        &mut self,
+        enum_name: &str,
+        variant: String,
+        args: Vec<Expr>,
+        span: Span,
+        expected: Option<&Type>,
+    ) -> Result<(Expr, Type)> {
+        let (type_params, variants) = self.generic_enums[enum_name].clone();
+        let payload = match variants.into_iter().find(|(name, _)| *name == variant) {
+            Some((_, payload)) => payload,
+            None => {
+                return Err(TypeError {
+                    code: "E0107",
+                    message: format!("Enum {} has no variant {}", enum_name, variant),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+        };
+        if args.len() != payload.len() {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!(
+                    "Variant {} expects {} value{}, found {}",
+                    variant,
+                    payload.len(),
+                    if payload.len() == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span: Some(span),
+                label: None,
+            });
+        }
+
+        let mut bindings: HashMap<String, Type> = match expected {
+            Some(Type::Enum(name)) => match self.instances.get(name) {
+                Some((generic, type_args)) if generic == enum_name => type_params.iter().cloned().zip(type_args.clone()).collect(),
+                _ => HashMap::new(),
+            },
+            _ => HashMap::new(),
+        };
+
+        let mut checked_args = Vec::new();
+        for (arg, param_type) in args.into_iter().zip(&payload) {
+            let wanted = substitute(param_type, &bindings);
+            let known = !matches!(wanted, Type::Generic(_));
+            let (checked_arg, arg_type) = self.check_expression_expecting(arg, Some(&wanted).filter(|_| known))?;
+            let compatible = if known {
+                let arg_type = self.coerce_literal(Some(&checked_arg), arg_type.clone(), &wanted, checked_arg.span())?;
+                Self::is_compatible(&arg_type, &wanted)
+            } else {
+                unify(param_type, &arg_type, &self.instances, &mut bindings)
+            };
+            if !compatible {
+                return Err(TypeError {
+                    code: "E0203",
+                    message: format!("Value of {} has type {}, expected {}", variant, arg_type, wanted),
+                    span: Some(checked_arg.span().clone()),
+                    label: None,
+                });
+            }
+            checked_args.push(checked_arg);
+        }
+
+        let mut type_args = Vec::new();
+        for type_param in &type_params {
+            match bindings.get(type_param) {
+                Some(ty) if *ty != Type::Inferred => type_args.push(ty.clone()),
+                _ => {
+                    return Err(TypeError {
+                        code: "E0209",
+                        message: format!(
+                            "Cannot infer type parameter {} of {} from {}; declare the type, as in `let r: Result<int, string> = Ok(1);`",
+                            type_param, enum_name, variant
+                        ),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+            }
+        }
+
+        let instance = instance_name(enum_name, &type_args);
+        let ty = self.instantiate_enum(enum_name, type_args, &span)?;
+        Ok((Expr::Variant(instance, variant, checked_args, span), ty))
+    }
+
+    fn variant_payload(&self, enum_name: &str, variant: &str, span: &Span) -> Result<Vec<Type>> {
+        self.enums
+            .get(enum_name)
+            .and_then(|variants| variants.iter().find(|(name, _)| name == variant))
+            .map(|(_, payload)| payload.clone())
+            .ok_or_else(|| TypeError {
+                code: "E0107",
+                message: format!("Enum {} has no variant {}", enum_name, variant),
+                span: Some(span.clone()),
+                label: None,
+            })
+    }
+
+    /// Checks a match on an enum value. Each arm's bindings get the types of
+    /// the variant's payload, all arms must have the same type, and every
+    /// variant must be covered unless there is a `_` arm.
+    fn check_match(&mut self, scrutinee: Expr, arms: Vec<MatchArm>, span: Span, expected: Option<&Type>) -> Result<(Expr, Type)> {
+        let (checked_scrutinee, scrutinee_type) = self.check_expression(scrutinee)?;
+        let enum_name = match &scrutinee_type {
+            Type::Enum(name) => Some(name.clone()),
+            Type::Inferred => None,
+            other => {
+                return Err(TypeError {
+                    code: "E0301",
+                    message: format!("Cannot match on a value of type {}; only enums can be matched", other),
+                    span: Some(checked_scrutinee.span().clone()),
+                    label: None,
+                });
+            }
+        };
+
+        let mut covered = HashSet::new();
+        let mut has_wildcard = false;
+        let mut result_type: Option<Type> = None;
+        let mut checked_arms = Vec::new();
+
+        for arm in arms {
+            let saved = self.save_scope();
+            let arm_expected = expected.cloned().or_else(|| result_type.clone());
+            let result = self.check_match_arm(&arm.pattern, enum_name.as_deref(), arm.body, arm_expected.as_ref());
+            self.restore_scope(saved);
+            let (checked_body, body_type) = result?;
+
+            match &arm.pattern {
+                Pattern::Wildcard(_) => has_wildcard = true,
+                Pattern::Variant(_, variant, _, _) => {
+                    covered.insert(variant.clone());
+                }
+            }
+
+            match &result_type {
+                None => result_type = Some(body_type),
+                Some(expected) if !Self::is_compatible(&body_type, expected) => {
+                    return Err(TypeError {
+                        code: "E0302",
+                        message: format!("Match arms have different types: expected {}, found {}", expected, body_type),
+                        span: Some(checked_body.span().clone()),
+                        label: None,
+                    });
+                }
+                Some(_) => {}
+            }
+
+            checked_arms.push(MatchArm {
+                pattern: arm.pattern,
+                body: checked_body,
+                span: arm.span,
+            });
+        }
+
+        if let (Some(enum_name), false) = (&enum_name, has_wildcard) {
+            let missing: Vec<String> = self.enums[enum_name]
+                .iter()
+                .filter(|(variant, _)| !covered.contains(variant))
+                // Variants of `Option` and `Result` go by their own names
+                .map(|(variant, _)| {
+                    if self.instances.contains_key(enum_name) {
+                        variant.clone()
+                    } else {
+                        format!("{}.{}", enum_name, variant)
+                    }
+                })
+                .collect();
+            if !missing.is_empty() {
+                return Err(TypeError {
+                    code: "E0303",
+                    message: format!("Match does not cover {}; add the missing arms or a `_` arm", missing.join(", ")),
+                    span: Some(span),
+                    label: None,
+                });
+            }
+        }
+
+        Ok((
+            Expr::Match(Box::new(checked_scrutinee), checked_arms, span),
+            result_type.unwrap_or(Type::Void),
+        ))
+    }
+
+    /// Binds the payload of an arm's pattern, then checks the arm's body
+    fn check_match_arm(
+        &mut self,
+        pattern: &Pattern,
+        enum_name: Option<&str>,
+        body: Expr,
+        expected: Option<&Type>,
+    ) -> Result<(Expr, Type)> {
+        if let Pattern::Variant(qualifier, variant, bindings, span) = pattern {
+            let payload = match enum_name {
+                Some(enum_name) => {
+                    // `Option.Some(x)` matches any instance of `Option`
+                    let generic = self.instances.get(enum_name).map(|(generic, _)| generic.as_str());
+                    if let Some(qualifier) =
+                        qualifier.as_deref().filter(|qualifier| *qualifier != enum_name && Some(*qualifier) != generic)
+                    {
+                        return Err(TypeError {
+                            code: "E0304",
+                            message: format!("Pattern {}.{} cannot match a value of type {}", qualifier, variant, enum_name),
+                            span: Some(span.clone()),
+                            label: None,
+                        });
+                    }
+                    self.variant_payload(enum_name, variant, span)?
+                }
+                None => vec![Type::Inferred; bindings.len()],
+            };
+
+            if bindings.len() != payload.len() {
+                return Err(TypeError {
+                    code: "E0305",
+                    message: format!(
+                        "Variant {} has {} value{}, but the pattern binds {}",
+                        variant,
+                        payload.len(),
+                        if payload.len() == 1 { "" } else { "s" },
+                        bindings.len()
+                    ),
+                    span: Some(span.clone()),
+                    label: None,
+                });
+            }
+
+            if let Some(duplicate) = first_duplicate(bindings.iter().filter(|binding| *binding != "_")) {
+                return Err(TypeError {
+                    code: "E0104",
+                    message: format!("Pattern binds {} more than once", duplicate),
+                    span: Some(span.clone()),
+                    label: None,
+                });
+            }
+
+            for (binding, ty) in bindings.iter().zip(payload) {
+                if binding != "_" {
+                    self.bind_variable(binding.clone(), ty, span, SymbolKind::Variable);
+                }
+            }
+        }
+
+        self.check_expression_expecting(body, expected)
+    }
+
+    /// Checks `value?`, which returns `None` or the `Err` of `value` from
+    /// the function and is otherwise the value of its `Some` or `Ok`
+    fn check_try(&mut self, value: Expr, span: Span) -> Result<(Expr, Type)> {
+        let (checked_value, value_type) = self.check_expression(value)?;
+        let (generic, type_args) = match &value_type {
+            Type::Inferred => return Ok((Expr::Try(Box::new(checked_value), None, span), Type::Inferred)),
+            Type::Enum(name) => match self.instances.get(name) {
+                Some((generic, type_args)) if self.generic_enums.contains_key(generic) => (generic.clone(), type_args.clone()),
+                _ => return Err(Self::untryable(&value_type, &span)),
+            },
+            _ => return Err(Self::untryable(&value_type, &span)),
+        };
+
+        // A `Result` can only pass its error on to a function returning
+        // errors of the same type
+        let return_type = self.current_return_type.clone();
+        let returns = match &return_type {
+            Some(Type::Enum(name)) => self.instances.get(name),
+            _ => None,
+        };
+        let compatible =
+            matches!(returns, Some((returned, return_args)) if *returned == generic && return_args.get(1) == type_args.get(1));
+        if !compatible {
+            let wanted = match type_args.get(1) {
+                Some(error_type) => format!("a Result with errors of type {}", error_type),
+                None => "an Option".to_string(),
+            };
+            let message = match &return_type {
+                None => format!("? can only be used in a function returning {}", wanted),
+                Some(Type::Inferred) => {
+                    format!("? can only be used in a lambda whose return type is declared as {}", wanted)
+                }
+                Some(other) => format!("? needs the function to return {}, but it returns {}", wanted, other),
+            };
+            return Err(TypeError {
+                code: "E0306",
+                message,
+                span: Some(span),
+                label: None,
+            });
+        }
+
+        Ok((Expr::Try(Box::new(checked_value), return_type, span), type_args[0].clone()))
+    }
+
+    fn untryable(ty: &Type, span: &Span) -> TypeError {
+        TypeError {
+            code: "E0306",
+            message: format!("Cannot apply ? to a value of type {}; only an Option or a Result can be", ty),
+            span: Some(span.clone()),
+            label: None,
+        }
+    }
+
+    /// Checks a call to a generic function by unifying its parameter types
+    /// with the argument types, then requests the matching instantiation and
+    /// redirects the call to it.
+    fn check_generic_call(&mut self, name: String, callee_span: Span, args: Vec<Expr>, span: Span) -> Result<(Expr, Type)> {
+        self.mark_function_used(&name, &callee_span);
+        let (type_params, params, return_type, body, attributes, decl_span) = match self.generic_functions.get(&name) {
+            Some(Stmt::Function(_, type_params, params, return_type, body, _, attributes, decl_span)) => (
+                type_params.clone(),
+                params.clone(),
+                return_type.clone(),
+                body.clone(),
+                attributes.clone(),
+                decl_span.clone(),
+            ),
+            _ => {
+                return Err(TypeError {
+                    code: "E0101",
+                    message: format!("Undefined function: {}", name),
+                    span: Some(callee_span),
+                    label: None,
+                });
+            }
+        };
+
+        if args.len() != params.len() {
+            return Err(TypeError {
+                code: "E0202",
+                message: format!(
+                    "Function {} expects {} argument{}, found {}",
+                    name,
+                    params.len(),
+                    if params.len() == 1 { "" } else { "s" },
+                    args.len()
+                ),
+                span: Some(span),
+                label: None,
+            });
+        }
+
+        let mut bindings = HashMap::new();
+        let mut checked_args = Vec::new();
+        for (i, (arg, (_, param_type))) in args.into_iter().zip(&params).enumerate() {
+            let (checked_arg, arg_type) = self.check_expression(arg)?;
+            if !unify(param_type, &arg_type, &self.instances, &mut bindings) {
+                return Err(TypeError {
+                    code: "E0203",
+                    message: format!(
+                        "Argument {} of {} has type {}, expected {}",
+                        i + 1,
+                        name,
+                        arg_type,
+                        substitute(param_type, &bindings)
+                    ),
+                    span: Some(checked_arg.span().clone()),
+                    label: None,
+                });
+            }
+            checked_args.push(checked_arg);
+        }
+
+        let mut type_args = Vec::new();
+        for type_param in &type_params {
+            match bindings.get(type_param) {
+                Some(ty) => type_args.push(ty.clone()),
+                None => {
+                    return Err(TypeError {
+                        code: "E0209",
+                        message: format!("Cannot infer type parameter {} of {}", type_param, name),
+                        span: Some(span),
+                        label: None,
+                    });
+                }
+            }
+        }
+
+        let instance_name = instance_name(&name, &type_args);
+        // The instance's body is checked under its own name
+        self.function_uses.entry(Some(name.clone())).or_default().insert(instance_name.clone());
+        if !self.instances.contains_key(&instance_name) {
+            self.instances.insert(instance_name.clone(), (name.clone(), type_args));
+            let instance_params = params
+                .iter()
+                .map(|(param_name, ty)| (param_name.clone(), substitute(ty, &bindings)))
+                .collect();
+            let instance = Stmt::Function(
+                instance_name.clone(),
+                Vec::new(),
+                instance_params,
+                substitute(&return_type, &bindings),
+                body,
+                false,
+                attributes,
+                decl_span,
+            );
+            self.pending_instances.push((instance, bindings.clone()));
+        }
+
+        let result_type = self.resolve_type(&substitute(&return_type, &bindings), &span)?;
+        let callee = Expr::Identifier(instance_name, callee_span);
+        Ok((Expr::Call(Box::new(callee), checked_args, span), result_type))
+    }
+
+    /// Checks the statements of a block expression, whose value is its
+    /// trailing expression (if any)
+    fn check_block(&mut self, stmts: Vec<Stmt>, expected: Option<&Type>) -> Result<(Vec<Stmt>, Type)> {
+        let count = stmts.len();
+        let mut checked = Vec::new();
+        let mut block_type = Type::Void;
+
+        for (i, stmt) in stmts.into_iter().enumerate() {
+            match stmt {
+                Stmt::Expr(expr) if i + 1 == count => {
+                    let (checked_expr, ty) = self.check_expression_expecting(expr, expected)?;
+                    block_type = ty;
+                    checked.push(Stmt::Expr(checked_expr));
+                }
+                other => {
+                    let result = self.check_statement(other);
+                    checked.extend(self.recover(result));
+                }
+            }
+        }
+
+        Ok((checked, block_type))
+    }
+
+    fn check_binary_op(&self, op: &BinaryOp, left_type: &Type, right_type: &Type, span: &crate::ast::Span) -> Result<Type> {
+        // An operand of unknown type has already been reported (or cannot be
+        // checked), so don't report the operator as well
+        if left_type == &Type::Inferred || right_type == &Type::Inferred {
+            return Ok(match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => Type::Inferred,
+                _ => Type::Bool,
+            });
+        }
+
+        // Numeric conversions must be spelled out with `as`
+        if is_numeric_conversion(left_type, right_type) && !matches!(op, BinaryOp::And | BinaryOp::Or) {
+            return Err(TypeError {
+                code: "E0205",
+                message: format!(
+                    "Cannot mix {} and {} operands; convert one side explicitly with `as`",
+                    left_type, right_type
+                ),
+                span: Some(span.clone()),
+                label: None,
+            });
+        }
+
+        if !matches!(op, BinaryOp::Eq | BinaryOp::Neq) {
+            for ty in [left_type, right_type] {
+                if let Type::Optional(_) = ty {
+                    return Err(Self::possibly_null(ty, span));
+                }
+            }
+        }
+
+        if left_type == &Type::String || right_type == &Type::String {
+            return Self::check_string_op(op, left_type, right_type, span);
+        }
+
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                if left_type.is_integer() && left_type == right_type {
+                    Ok(left_type.clone())
+                } else if left_type == &Type::Float && right_type == &Type::Float {
+                    Ok(Type::Float)
+                } else {
+                    Err(TypeError {
+                        code: "E0204",
+                        message: format!("Invalid operand types for binary operator: {} and {}", left_type, right_type),
+                        span: Some(span.clone()),
+                        label: None,
+                    })
+                }
+            }
+            BinaryOp::Eq | BinaryOp::Neq => {
+                // Most types can be compared for equality, but not structs as
+                // a whole, which C cannot compare
+                if let Some(ty) = [left_type, right_type].into_iter().find(|ty| matches!(ty, Type::Struct(_))) {
+                    return Err(TypeError {
+                        code: "E0204",
+                        message: format!("Cannot compare structs of type {}; compare their fields instead", ty),
+                        span: Some(span.clone()),
+                        label: None,
+                    });
+                }
+                Ok(Type::Bool)
+            }
+            BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte => {
+                if (left_type.is_integer() && left_type == right_type)
+                    || (left_type == &Type::Float && right_type == &Type::Float)
+                {
+                    Ok(Type::Bool)
+                } else {
+                    Err(TypeError {
+                        code: "E0204",
+                        message: format!("Invalid operand types for comparison operator: {} and {}", left_type, right_type),
+                        span: Some(span.clone()),
+                        label: None,
+                    })
+                }
+            }
+            BinaryOp::And | BinaryOp::Or => {
+                if left_type == &Type::Bool && right_type == &Type::Bool {
+                    Ok(Type::Bool)
+                } else {
+                    Err(TypeError {
+                        code: "E0204",
+                        message: format!("Invalid operand types for logical operator: {} and {}", left_type, right_type),
+                        span: Some(span.clone()),
+                        label: None,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Strings are concatenated with `+`, where the other operand may also be
+    /// a number or bool, and compared with other strings by content, with
+    /// `<` and friends ordering them byte by byte
+    fn check_string_op(op: &BinaryOp, left_type: &Type, right_type: &Type, span: &Span) -> Result<Type> {
+        let other = if left_type == &Type::String { right_type } else { left_type };
+        let message = match op {
+            BinaryOp::Add => {
+                if other == &Type::String || other.is_integer() || matches!(other, Type::Float | Type::Bool) {
+                    return Ok(Type::String);
+                }
+                format!("Cannot concatenate string and {}; only strings, numbers and bools can be joined with +", other)
+            }
+            BinaryOp::Eq | BinaryOp::Neq | BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte => {
+                // `name == null` is allowed, though it is always false
+                let null = matches!(other, Type::Optional(inner) if matches!(**inner, Type::String | Type::Inferred));
+                if other == &Type::String || (null && matches!(op, BinaryOp::Eq | BinaryOp::Neq)) {
+                    return Ok(Type::Bool);
+                }
+                format!("Cannot compare {} with {}", left_type, right_type)
+            }
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                format!("Operator {:?} is not defined for strings", op)
+            }
+            BinaryOp::And | BinaryOp::Or => {
+                format!("Invalid operand types for logical operator: {} and {}", left_type, right_type)
+            }
+        };
+        Err(TypeError {
+            code: "E0204",
+            message,
+            span: Some(span.clone()),
+            label: None,
+        })
+    }
+
+    /// Replaces a constant expression by its value, reporting overflow of
+    /// its type and other errors that would otherwise happen at run time
+    fn fold(&self, expr: Expr, ty: &Type) -> Result<Expr> {
+        let constant = match consteval::evaluate(&expr, &self.constants) {
+            Ok(Some(constant)) => constant,
+            Ok(None) => return Ok(expr),
+            Err(e) => return Err(const_error(e)),
+        };
+        // Infinity and NaN have no literal spelling
+        if let Constant::Float(value) = constant {
+            if !value.is_finite() {
+                return Ok(expr);
+            }
+        }
+        if let (Constant::Int(value), Some((min, max))) = (constant, ty.integer_range()) {
+            if (value as i128) < min || (value as i128) > max {
+                return Err(TypeError {
+                    code: "E0213",
+                    message: format!(
+                        "Constant expression evaluates to {}, which is out of range for {} ({} to {})",
+                        value, ty, min, max
+                    ),
+                    span: Some(expr.span().clone()),
+                    label: None,
+                });
+            }
+        }
+        Ok(constant.to_expr(expr.span().clone()))
+    }
+
+    /// Lets an integer literal take on the integer type it is used as,
+    /// provided its value fits in that type
+    fn coerce_literal(&self, expr: Option<&Expr>, actual: Type, expected: &Type, span: &Span) -> Result<Type> {
+        let value = match expr {
+            Some(Expr::Literal(Literal::Int(value), _)) => *value,
+            _ => return Ok(actual),
+        };
+        let (min, max) = match expected.integer_range() {
+            Some(range) if matches!(actual, Type::Int | Type::U64) => range,
+            _ => return Ok(actual),
+        };
+        if value < min || value > max {
+            return Err(TypeError {
+                code: "E0213",
+                message: format!(
+                    "Integer literal {} is out of range for {} ({} to {})",
+                    value, expected, min, max
+                ),
+                span: Some(span.clone()),
+                label: None,
+            });
+        }
+        Ok(expected.clone())
+    }
+
+    /// Types are compatible when equal or when either side is not yet known.
+    /// A value may also be used where an optional of its type is expected.
+    fn is_compatible(actual: &Type, expected: &Type) -> bool {
+        match (actual, expected) {
+            _ if actual == expected => true,
+            (Type::Inferred, _) | (_, Type::Inferred) => true,
+            (Type::Optional(actual), Type::Optional(expected)) => Self::is_compatible(actual, expected),
+            (actual, Type::Optional(expected)) => Self::is_compatible(actual, expected),
+            // An empty array literal takes the element type it is used as
+            (Type::Array(actual), Type::Array(_)) => **actual == Type::Inferred,
+            _ => false,
+        }
+    }
+
+    fn check_type_compatibility(&self, actual: Type, expected: Type, span: &crate::ast::Span) -> Result<()> {
+        if Self::is_compatible(&actual, &expected) {
+            return Ok(());
+        }
+        let hint = match &expected {
+            Type::Optional(inner) if is_numeric_conversion(&actual, inner) => "; convert it explicitly with `as`",
+            expected if is_numeric_conversion(&actual, expected) => "; convert it explicitly with `as`",
+            _ => "",
+        };
+        Err(TypeError {
+            code: "E0201",
+            message: format!("Type mismatch: expected {}, found {}{}", expected, actual, hint),
+            span: Some(span.clone()),
+            label: None,
+        })
+    }
+}
+
+/// Whether using a value of type `from` as `to` would convert between
+/// numeric types, which Z never does implicitly: not in operators, and not
+/// when assigning, passing arguments or returning values
+fn is_numeric_conversion(from: &Type, to: &Type) -> bool {
+    let is_numeric = |ty: &Type| ty.is_integer() || *ty == Type::Float;
+    is_numeric(from) && is_numeric(to) && from != to
+}
+
+/// The parameter and return types of the method `name` of strings
+pub(crate) fn string_method(name: &str) -> Option<(Vec<Type>, Type)> {
+    let (params, ret) = match name {
+        "len" => (vec![], Type::Int),
+        "substring" => (vec![Type::Int, Type::Int], Type::String),
+        "split" => (vec![Type::String], Type::Array(Box::new(Type::String))),
+        "contains" => (vec![Type::String], Type::Bool),
+        "to_upper" => (vec![], Type::String),
+        "to_int" => (vec![], Type::Int),
+        "to_float" => (vec![], Type::Float),
+        _ => return None,
+    };
+    Some((params, ret))
+}
+
+/// The parameter and return types of the method `name` of arrays of
+/// `element`
+pub(crate) fn array_method(name: &str, element: &Type) -> Option<(Vec<Type>, Type)> {
+    match name {
+        "len" => Some((vec![], Type::Int)),
+        "push" => Some((vec![element.clone()], Type::Void)),
+        "pop" => Some((vec![], element.clone())),
+        _ => None,
+    }
+}
+
+/// Whether values of `ty` can be passed to and returned from C functions
+fn is_ffi_type(ty: &Type) -> bool {
+    ty.is_integer()
+        || matches!(ty, Type::Float | Type::Bool | Type::String)
+        || matches!(ty, Type::Optional(inner) if **inner == Type::String)
+}
+
+/// The first name that occurs more than once, if any
+fn first_duplicate<'a>(mut names: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    let mut seen = HashSet::new();
+    names.find(|name| !seen.insert(*name))
+}
+
+fn constant_type(constant: Constant) -> Type {
+    match constant {
+        Constant::Int(_) => Type::Int,
+        Constant::Float(_) => Type::Float,
+        Constant::Bool(_) => Type::Bool,
+    }
+}
+
+fn const_error(error: ConstError) -> TypeError {
+    TypeError {
+        code: "E0224",
+        message: error.message,
+        span: Some(error.span),
+        label: None,
+    }
+}
+
+/// Whether every control-flow path through `stmt` ends in a return or in a
+/// call of one of the `diverging` functions, which never come back
+fn stmt_always_returns(stmt: &Stmt, diverging: &HashSet<String>) -> bool {
+    match stmt {
+        Stmt::Return(_, _) => true,
+        Stmt::Block(stmts, _) => stmts.iter().any(|stmt| stmt_always_returns(stmt, diverging)),
+        Stmt::Expr(expr) => expr_always_returns(expr, diverging),
+        // There is no `break`, so `while true` can only be left by returning
+        Stmt::While(Expr::Literal(Literal::Bool(true), _), _, _) => true,
+        // Other loops may run zero times
+        _ => false,
+    }
+}
+
+fn expr_always_returns(expr: &Expr, diverging: &HashSet<String>) -> bool {
+    match expr {
+        Expr::If(_, then_branch, Some(else_branch), _) => {
+            expr_always_returns(then_branch, diverging) && expr_always_returns(else_branch, diverging)
+        }
+        Expr::Block(stmts, _) => stmts.iter().any(|stmt| stmt_always_returns(stmt, diverging)),
+        // Matches are exhaustive, so returning from every arm is enough
+        Expr::Match(_, arms, _) => {
+            !arms.is_empty() && arms.iter().all(|arm| expr_always_returns(&arm.body, diverging))
+        }
+        Expr::Call(callee, _, _) => matches!(callee.as_ref(), Expr::Identifier(name, _) if diverging.contains(name)),
+        _ => false,
+    }
+}
+
+/// The functions returning nothing that never return at all: every path
+/// through one ends in a panic or a call of another such function, and none
+/// of them is a `return`. `panic` is the builtin unless `panic_defined`.
+fn never_returning(statements: &[Stmt], panic_defined: bool) -> HashSet<String> {
+    let candidates: Vec<(&String, &Stmt, HashSet<String>)> = statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Function(name, type_params, params, Type::Void, body, ..) if type_params.is_empty() => {
+                let mut returns = Returns(false);
+                returns.visit_stmt(body);
+                let mut bound = BoundNames(params.iter().map(|(param, _)| param.clone()).collect());
+                bound.visit_stmt(body);
+                (!returns.0).then_some((name, body.as_ref(), bound.0))
+            }
+            _ => None,
+        })
+        .collect();
+    let mut found = HashSet::new();
+    // Each round may find functions that only call those found in the last
+    loop {
+        let before = found.len();
+        for (name, body, bound) in &candidates {
+            let diverging: HashSet<String> = found
+                .iter()
+                .cloned()
+                .chain((!panic_defined).then(|| "panic".to_string()))
+                .filter(|name| !bound.contains(name))
+                .collect();
+            if stmt_always_returns(body, &diverging) {
+                found.insert((*name).clone());
+            }
+        }
+        if found.len() == before {
+            return found;
+        }
+    }
+}
+
+/// The names a piece of code binds to variables: those of its `let`s and
+/// loops, and the parameters of its lambdas and payloads of its match arms
+struct BoundNames(HashSet<String>);
+
+impl Visitor for BoundNames {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Let(name, ..) | Stmt::Const(name, ..) | Stmt::For(name, ..) = stmt {
+            self.0.insert(name.clone());
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Lambda(params, ..) = expr {
+            self.0.extend(params.iter().map(|(param, _)| param.clone()));
+        }
+        walk_expr(self, expr);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        if let Pattern::Variant(_, _, bindings, _) = pattern {
+            self.0.extend(bindings.iter().cloned());
+        }
+    }
+}
+
+/// Adds the errors a unit found to `merged`, but those other units found
+/// too, such as errors in the top-level statements every unit checks
+fn add_errors(merged: &mut Vec<TypeError>, errors: &[TypeError]) {
+    for (index, error) in errors.iter().enumerate() {
+        // A unit may find the same error twice, as checking the program
+        // whole does
+        let found = errors[..=index].iter().filter(|other| *other == error).count();
+        if merged.iter().filter(|other| *other == error).count() < found {
+            merged.push(error.clone());
+        }
+    }
+}
+
+/// Adds the references a unit found to those of the same name other units
+/// found, which may include them
+fn add_references(merged: &mut Vec<Span>, references: &[Span]) {
+    for reference in references {
+        if !merged.contains(reference) {
+            merged.push(reference.clone());
+        }
+    }
+}
+
+/// Moves the spans of a piece of code as an edit elsewhere in its file moved
+/// its text
+struct MoveSpans<F>(F);
+
+impl<F: Fn(usize) -> usize> MoveSpans<F> {
+    fn span(&self, span: &mut Span) {
+        span.start = (self.0)(span.start);
+        span.end = (self.0)(span.end);
+    }
+}
+
+impl<F: Fn(usize) -> usize> VisitorMut for MoveSpans<F> {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        // That of an expression statement is the expression's
+        if !matches!(stmt, Stmt::Expr(_)) {
+            self.span(stmt.span_mut());
+        }
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        self.span(expr.span_mut());
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        self.span(&mut arm.span);
+        walk_match_arm_mut(self, arm);
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Variant(_, _, _, span) | Pattern::Wildcard(span) => self.span(span),
+        }
+    }
+}
+
+/// Whether a piece of code can return, by `return` or `?`
+struct Returns(bool);
+
+impl Visitor for Returns {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.0 |= matches!(stmt, Stmt::Return(..));
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.0 |= matches!(expr, Expr::Try(..));
+        walk_expr(self, expr);
+    }
+}
+
+/// Replaces type parameters in `ty` with their bound types
+fn substitute(ty: &Type, bindings: &HashMap<String, Type>) -> Type {
+    match ty {
+        Type::Generic(name) => bindings.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Array(elem_type) => Type::Array(Box::new(substitute(elem_type, bindings))),
+        Type::Optional(inner) => Type::Optional(Box::new(substitute(inner, bindings))),
+        Type::Function(params, ret) => Type::Function(
+            params.iter().map(|param| substitute(param, bindings)).collect(),
+            Box::new(substitute(ret, bindings)),
+        ),
+        Type::Applied(name, args) => {
+            Type::Applied(name.clone(), args.iter().map(|arg| substitute(arg, bindings)).collect())
+        }
+        _ => ty.clone(),
+    }
+}
+
+/// Binds the type parameters in `param` so that it matches `arg`, returning
+/// false if the two types cannot be made equal. An applied type such as
+/// `Option<T>` matches the instances in `instances` of the same declaration.
+fn unify(param: &Type, arg: &Type, instances: &Instances, bindings: &mut HashMap<String, Type>) -> bool {
+    match (param, arg) {
+        (_, Type::Inferred) => true,
+        (Type::Generic(name), _) => match bindings.get(name) {
+            Some(bound) => bound == arg,
+            None => {
+                bindings.insert(name.clone(), arg.clone());
+                true
+            }
+        },
+        (Type::Array(param_elem), Type::Array(arg_elem)) => unify(param_elem, arg_elem, instances, bindings),
+        (Type::Optional(param_inner), Type::Optional(arg_inner)) => unify(param_inner, arg_inner, instances, bindings),
+        (Type::Function(param_params, param_ret), Type::Function(arg_params, arg_ret)) => {
+            param_params.len() == arg_params.len()
+                && param_params.iter().zip(arg_params).all(|(p, a)| unify(p, a, instances, bindings))
+                && unify(param_ret, arg_ret, instances, bindings)
+        }
+        (Type::Applied(name, params), Type::Struct(instance) | Type::Enum(instance)) => match instances.get(instance) {
+            Some((generic, args)) if generic == name && args.len() == params.len() => {
+                params.iter().zip(args).all(|(p, a)| unify(p, a, instances, bindings))
+            }
+            _ => false,
+        },
+        _ => param == arg,
+    }
+}
+
+/// `Option<T>` and `Result<T, E>`, with the variant holding a value first
+/// and the one standing for its absence second
+fn builtin_enums() -> HashMap<String, GenericEnum> {
+    let param = |name: &str| Type::Generic(name.to_string());
+    HashMap::from([
+        (
+            "Option".to_string(),
+            (vec!["T".to_string()], vec![("Some".to_string(), vec![param("T")]), ("None".to_string(), Vec::new())]),
+        ),
+        (
+            "Result".to_string(),
+            (
+                vec!["T".to_string(), "E".to_string()],
+                vec![("Ok".to_string(), vec![param("T")]), ("Err".to_string(), vec![param("E")])],
+            ),
+        ),
+    ])
+}
+
+/// Name of the instantiation of a generic declaration, e.g. `identity<int>`,
+/// which no declaration can share. Code generation turns it into a C symbol
+/// with `mangle::symbol`.
+fn instance_name(name: &str, type_args: &[Type]) -> String {
+    Type::Applied(name.to_string(), type_args.to_vec()).to_string()
+}
+
+/// Options that make the typechecker stricter than the language requires
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    /// Reject uses of strings, arrays and structs after they have been moved
+    pub strict_memory: bool,
+    /// Make the warnings that are not allowed errors
+    pub deny_warnings: bool,
+}
+
+/// Typechecks `program`, returning every error found if it is ill-typed
+pub fn typecheck(program: Program) -> std::result::Result<CheckedProgram, Vec<TypeError>> {
+    typecheck_with(program, &CheckOptions::default())
+}
+
+/// Typechecks `program` with the extra checks enabled in `options`
+pub fn typecheck_with(program: Program, options: &CheckOptions) -> std::result::Result<CheckedProgram, Vec<TypeError>> {
+    let mut typechecker = TypeChecker::new();
+    let program = typechecker.check_program(program)?;
+    if options.strict_memory {
+        let errors = ownership::check(&program);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+    }
+    Ok(typechecker.finish(program))
+}
+
+/// Typechecks the unit `unit` of `program`, leaving the errors found in it
+/// for `merge` to return
+pub fn typecheck_unit(program: Program, unit: Unit, options: &CheckOptions) -> CheckedUnit {
+    let mut typechecker = TypeChecker::new();
+    typechecker.unit = Some(unit);
+    let (checked, instantiated) = typechecker.check_statements_of(program);
+
+    // Moves are followed through the top-level statements the unit's
+    // functions may use
+    let mut moves = Vec::new();
+    if options.strict_memory && typechecker.errors.is_empty() {
+        let mut statements = typechecker.struct_instances.clone();
+        statements.extend(checked.iter().map(|(_, stmt)| stmt.clone()));
+        statements.extend(instantiated.iter().cloned());
+        moves = ownership::check(&Program::new(statements));
+    }
+
+    let statements = checked.into_iter().filter(|(_, stmt)| typechecker.owns(stmt)).collect();
+    CheckedUnit {
+        statements,
+        instantiated,
+        moves,
+        typechecker,
+    }
+}
+
+/// The functions of `program` that never return, which the checks of the
+/// bodies calling them depend on
+pub fn never_returning_of(program: &Program) -> Vec<String> {
+    let mut typechecker = TypeChecker::new();
+    // Errors in the declarations are left to the checks
+    let _ = typechecker.register_declarations(program);
+    let mut functions: Vec<String> =
+        never_returning(&program.statements, typechecker.functions.contains_key("panic")).into_iter().collect();
+    functions.sort();
+    functions
+}
+
+impl CheckedUnit {
+    /// Moves every span of the unit to `position(start)..position(end)`, as
+    /// after an edit to the file outside the unit
+    pub fn move_spans(&mut self, position: impl Fn(usize) -> usize) {
+        let mut spans = MoveSpans(position);
+        for (_, stmt) in &mut self.statements {
+            spans.visit_stmt_mut(stmt);
+        }
+        for stmt in self.instantiated.iter_mut().chain(&mut self.typechecker.struct_instances) {
+            spans.visit_stmt_mut(stmt);
+        }
+        for error in self.moves.iter_mut().chain(&mut self.typechecker.errors) {
+            error.span.iter_mut().chain(error.label.iter_mut().map(|(_, span)| span)).for_each(|span| spans.span(span));
+        }
+        let typechecker = &mut self.typechecker;
+        for binding in &mut typechecker.bindings {
+            binding.references.iter_mut().chain([&mut binding.span]).for_each(|span| spans.span(span));
+        }
+        let references = typechecker.function_references.values_mut().chain(typechecker.type_references.values_mut());
+        references.flatten().for_each(|span| spans.span(span));
+        let declarations = typechecker.function_spans.values_mut().chain(typechecker.type_spans.values_mut());
+        declarations.for_each(|span| spans.span(span));
+    }
+}
+
+/// Puts together what checking the units of a program apart found, which is
+/// what checking the program whole finds. The units are those `program`, the
+/// program as parsed, is made of.
+pub fn merge(program: &Program, units: &[&CheckedUnit]) -> std::result::Result<CheckedProgram, Vec<TypeError>> {
+    let mut typechecker = TypeChecker::new();
+    let mut errors: Vec<TypeError> = Vec::new();
+    let mut moves: Vec<TypeError> = Vec::new();
+    let mut statements = Vec::new();
+    let mut instantiated = Vec::new();
+    // Units instantiating the same generic declarations each check them
+    let mut function_instances = HashSet::new();
+    let mut type_instances = HashSet::new();
+    let mut bindings: HashMap<(String, usize), usize> = HashMap::new();
+    for unit in units {
+        let checked = &unit.typechecker;
+        add_errors(&mut errors, &checked.errors);
+        add_errors(&mut moves, &unit.moves);
+        statements.extend(unit.statements.iter().cloned());
+        for stmt in &unit.instantiated {
+            if let Stmt::Function(name, ..) = stmt {
+                if function_instances.insert(name.clone()) {
+                    instantiated.push(stmt.clone());
+                }
+            }
+        }
+        for stmt in &checked.struct_instances {
+            if let Stmt::Struct(name, ..) | Stmt::Enum(name, ..) = stmt {
+                if type_instances.insert(name.clone()) {
+                    typechecker.struct_instances.push(stmt.clone());
+                }
+            }
+        }
+        typechecker.instances.extend(checked.instances.clone());
+
+        // Each unit sees the variables of the top-level statements, which
+        // are used if any unit uses them
+        for binding in &checked.bindings {
+            match bindings.get(&(binding.name.clone(), binding.span.start)) {
+                Some(&index) => {
+                    let merged = &mut typechecker.bindings[index];
+                    merged.used |= binding.used;
+                    add_references(&mut merged.references, &binding.references);
+                }
+                None => {
+                    bindings.insert((binding.name.clone(), binding.span.start), typechecker.bindings.len());
+                    typechecker.bindings.push(binding.clone());
+                }
+            }
+        }
+        for (name, references) in &checked.function_references {
+            add_references(typechecker.function_references.entry(name.clone()).or_default(), references);
+        }
+        for (name, references) in &checked.type_references {
+            add_references(typechecker.type_references.entry(name.clone()).or_default(), references);
+        }
+        for (user, uses) in &checked.function_uses {
+            typechecker.function_uses.entry(user.clone()).or_default().extend(uses.iter().cloned());
+        }
+        for (user, uses) in &checked.field_uses {
+            typechecker.field_uses.entry(user.clone()).or_default().extend(uses.iter().cloned());
+        }
+        typechecker.used_functions.extend(checked.used_functions.iter().cloned());
+        typechecker.benches.extend(checked.benches.iter().cloned());
+        typechecker.function_spans.extend(checked.function_spans.clone());
+        typechecker.functions.extend(checked.functions.clone());
+        typechecker.type_spans.extend(checked.type_spans.clone());
+        typechecker.declared_enums.extend(checked.declared_enums.iter().cloned());
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| error.span.as_ref().map(|span| span.start));
+        return Err(errors);
+    }
+    if !moves.is_empty() {
+        moves.sort_by_key(|error| error.span.as_ref().map(|span| span.start));
+        return Err(moves);
+    }
+
+    typechecker.report_unused();
+
+    statements.sort_by_key(|(index, _)| *index);
+    let mut checked = Program::new(std::mem::take(&mut typechecker.struct_instances));
+    checked.statements.extend(statements.into_iter().map(|(_, stmt)| stmt));
+    checked.statements.extend(instantiated);
+    checked.instances = std::mem::take(&mut typechecker.instances);
+    checked.imports = program.imports.clone();
+    Ok(typechecker.finish(checked))
+}
@@ -0,0 +1,389 @@
+// Traversals of the syntax tree, for tools such as formatters and analyzers
+// that care about a few kinds of node. A `Visitor` implements the methods of
+// the nodes it looks at and leaves the others to their defaults, which walk
+// into the children of the node. A method that still wants the children
+// visited calls the `walk_` function of its node:
+//
+//     struct Calls(usize);
+//
+//     impl Visitor for Calls {
+//         fn visit_expr(&mut self, expr: &Expr) {
+//             if let Expr::Call(..) = expr {
+//                 self.0 += 1;
+//             }
+//             walk_expr(self, expr);
+//         }
+//     }
+//
+// `VisitorMut` does the same with mutable references, to change the tree in
+// place. Nodes are visited in the order they are written.
+
+use crate::ast::{Expr, MatchArm, Pattern, Program, Stmt, Type};
+
+/// Looks at a syntax tree, node by node
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        walk_match_arm(self, arm);
+    }
+
+    fn visit_pattern(&mut self, _pattern: &Pattern) {}
+
+    /// A type written in the program, such as that of a parameter
+    fn visit_type(&mut self, ty: &Type) {
+        walk_type(self, ty);
+    }
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for stmt in &program.statements {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Expr(expr) => visitor.visit_expr(expr),
+        Stmt::Let(_, ty, value, _) => {
+            if let Some(ty) = ty {
+                visitor.visit_type(ty);
+            }
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        Stmt::Const(_, ty, value, _) => {
+            if let Some(ty) = ty {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_expr(value);
+        }
+        Stmt::Assign(target, value, _) => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        Stmt::Return(value, _) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        Stmt::While(condition, body, _) => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+        }
+        Stmt::For(_, _, iterable, body, _) => {
+            visitor.visit_expr(iterable);
+            visitor.visit_stmt(body);
+        }
+        Stmt::Block(stmts, _) => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Stmt::Function(_, _, params, return_type, body, _, _, _) => {
+            for (_, ty) in params {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_type(return_type);
+            visitor.visit_stmt(body);
+        }
+        Stmt::Struct(_, _, fields, _) => {
+            for (_, ty) in fields {
+                visitor.visit_type(ty);
+            }
+        }
+        Stmt::Enum(_, variants, _) => {
+            for ty in variants.iter().flat_map(|(_, payload)| payload) {
+                visitor.visit_type(ty);
+            }
+        }
+        Stmt::Import(..) => {}
+        Stmt::Extern(_, params, return_type, _, _) => {
+            for (_, ty) in params {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_type(return_type);
+        }
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Literal(..) | Expr::Identifier(..) => {}
+        Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) | Expr::Range(left, right, _) => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        Expr::Unary(_, operand, _) | Expr::Field(operand, _, _) | Expr::Inlined(_, operand, _) => {
+            visitor.visit_expr(operand)
+        }
+        Expr::Call(callee, args, _) => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::Array(items, _) | Expr::Variant(_, _, items, _) => {
+            for item in items {
+                visitor.visit_expr(item);
+            }
+        }
+        Expr::If(condition, then, otherwise, _) => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(then);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_expr(otherwise);
+            }
+        }
+        Expr::Block(stmts, _) => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Expr::Lambda(params, return_type, body, _) => {
+            for ty in params.iter().filter_map(|(_, ty)| ty.as_ref()) {
+                visitor.visit_type(ty);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type(return_type);
+            }
+            visitor.visit_expr(body);
+        }
+        Expr::Cast(value, ty, _) => {
+            visitor.visit_expr(value);
+            visitor.visit_type(ty);
+        }
+        Expr::Match(scrutinee, arms, _) => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_match_arm(arm);
+            }
+        }
+        Expr::Try(value, return_type, _) => {
+            visitor.visit_expr(value);
+            if let Some(return_type) = return_type {
+                visitor.visit_type(return_type);
+            }
+        }
+    }
+}
+
+pub fn walk_match_arm<V: Visitor + ?Sized>(visitor: &mut V, arm: &MatchArm) {
+    visitor.visit_pattern(&arm.pattern);
+    visitor.visit_expr(&arm.body);
+}
+
+/// Visits the types a type is made of, such as the element type of an
+/// array
+pub fn walk_type<V: Visitor + ?Sized>(visitor: &mut V, ty: &Type) {
+    match ty {
+        Type::Array(inner) | Type::Optional(inner) => visitor.visit_type(inner),
+        Type::Function(params, return_type) => {
+            for param in params {
+                visitor.visit_type(param);
+            }
+            visitor.visit_type(return_type);
+        }
+        Type::Applied(_, args) => {
+            for arg in args {
+                visitor.visit_type(arg);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Changes a syntax tree in place, node by node
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm);
+    }
+
+    fn visit_pattern_mut(&mut self, _pattern: &mut Pattern) {}
+
+    /// A type written in the program, such as that of a parameter
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        walk_type_mut(self, ty);
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for stmt in &mut program.statements {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Expr(expr) => visitor.visit_expr_mut(expr),
+        Stmt::Let(_, ty, value, _) => {
+            if let Some(ty) = ty {
+                visitor.visit_type_mut(ty);
+            }
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        Stmt::Const(_, ty, value, _) => {
+            if let Some(ty) = ty {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_expr_mut(value);
+        }
+        Stmt::Assign(target, value, _) => {
+            visitor.visit_expr_mut(target);
+            visitor.visit_expr_mut(value);
+        }
+        Stmt::Return(value, _) => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        Stmt::While(condition, body, _) => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_stmt_mut(body);
+        }
+        Stmt::For(_, _, iterable, body, _) => {
+            visitor.visit_expr_mut(iterable);
+            visitor.visit_stmt_mut(body);
+        }
+        Stmt::Block(stmts, _) => {
+            for stmt in stmts {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        Stmt::Function(_, _, params, return_type, body, _, _, _) => {
+            for (_, ty) in params {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_type_mut(return_type);
+            visitor.visit_stmt_mut(body);
+        }
+        Stmt::Struct(_, _, fields, _) => {
+            for (_, ty) in fields {
+                visitor.visit_type_mut(ty);
+            }
+        }
+        Stmt::Enum(_, variants, _) => {
+            for ty in variants.iter_mut().flat_map(|(_, payload)| payload) {
+                visitor.visit_type_mut(ty);
+            }
+        }
+        Stmt::Import(..) => {}
+        Stmt::Extern(_, params, return_type, _, _) => {
+            for (_, ty) in params {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_type_mut(return_type);
+        }
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Literal(..) | Expr::Identifier(..) => {}
+        Expr::Binary(left, _, right, _) | Expr::Index(left, right, _) | Expr::Range(left, right, _) => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+        Expr::Unary(_, operand, _) | Expr::Field(operand, _, _) | Expr::Inlined(_, operand, _) => {
+            visitor.visit_expr_mut(operand)
+        }
+        Expr::Call(callee, args, _) => {
+            visitor.visit_expr_mut(callee);
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+        }
+        Expr::Array(items, _) | Expr::Variant(_, _, items, _) => {
+            for item in items {
+                visitor.visit_expr_mut(item);
+            }
+        }
+        Expr::If(condition, then, otherwise, _) => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_expr_mut(then);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_expr_mut(otherwise);
+            }
+        }
+        Expr::Block(stmts, _) => {
+            for stmt in stmts {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        Expr::Lambda(params, return_type, body, _) => {
+            for ty in params.iter_mut().filter_map(|(_, ty)| ty.as_mut()) {
+                visitor.visit_type_mut(ty);
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type_mut(return_type);
+            }
+            visitor.visit_expr_mut(body);
+        }
+        Expr::Cast(value, ty, _) => {
+            visitor.visit_expr_mut(value);
+            visitor.visit_type_mut(ty);
+        }
+        Expr::Match(scrutinee, arms, _) => {
+            visitor.visit_expr_mut(scrutinee);
+            for arm in arms {
+                visitor.visit_match_arm_mut(arm);
+            }
+        }
+        Expr::Try(value, return_type, _) => {
+            visitor.visit_expr_mut(value);
+            if let Some(return_type) = return_type {
+                visitor.visit_type_mut(return_type);
+            }
+        }
+    }
+}
+
+pub fn walk_match_arm_mut<V: VisitorMut + ?Sized>(visitor: &mut V, arm: &mut MatchArm) {
+    visitor.visit_pattern_mut(&mut arm.pattern);
+    visitor.visit_expr_mut(&mut arm.body);
+}
+
+/// Visits the types a type is made of, such as the element type of an
+/// array
+pub fn walk_type_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ty: &mut Type) {
+    match ty {
+        Type::Array(inner) | Type::Optional(inner) => visitor.visit_type_mut(inner),
+        Type::Function(params, return_type) => {
+            for param in params {
+                visitor.visit_type_mut(param);
+            }
+            visitor.visit_type_mut(return_type);
+        }
+        Type::Applied(_, args) => {
+            for arg in args {
+                visitor.visit_type_mut(arg);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,54 @@
+// The compiler in the browser, for the online playground. The `wasm` feature
+// builds the frontend and the C generation, with nothing that runs programs
+// or writes files, for `wasm32-unknown-unknown`:
+//
+//     cargo rustc --release --lib --target wasm32-unknown-unknown \
+//         --no-default-features --features wasm --crate-type cdylib
+//     wasm-bindgen --target web target/wasm32-unknown-unknown/release/z_lang.wasm --out-dir web
+//
+// and exports these functions to JavaScript. They are public in
+// `z_lang::wasm` as well, so that they can be tried on the host.
+
+use crate::typechecker::CheckOptions;
+use crate::{check_source, compile_source, diagnostic, CompilerError, Diagnostic, PassManager};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Compiles Z source text to C, as `zc compile --emit c` does. Errors are
+/// thrown as the text `zc` prints for them, with the warnings found before
+/// them.
+#[wasm_bindgen]
+pub fn compile_to_c(source: &str) -> Result<String, String> {
+    match diagnostic::collect(|| compile_source(source)) {
+        (Ok(code), _) => Ok(code),
+        (Err(e), warnings) => Err(report(e, &warnings)),
+    }
+}
+
+/// The warnings and errors `check_source` finds in Z source text, without
+/// generating C, as `zc` prints them, or nothing when there are none
+#[wasm_bindgen]
+pub fn diagnostics(source: &str) -> String {
+    let passes = PassManager::new(3);
+    match diagnostic::collect(|| check_source(source, &passes, &CheckOptions::default())) {
+        (Ok(_), warnings) => render(&warnings),
+        (Err(e), warnings) => report(e, &warnings),
+    }
+}
+
+/// The version of the compiler
+#[wasm_bindgen]
+pub fn version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// `error` as `zc` prints it, after `warnings`
+fn report(error: CompilerError, warnings: &[Diagnostic]) -> String {
+    match error {
+        CompilerError::Diagnostics(errors) => render(warnings.iter().chain(&errors)),
+        e => format!("{}error: {}\n", render(warnings), e),
+    }
+}
+
+fn render<'a>(diagnostics: impl IntoIterator<Item = &'a Diagnostic>) -> String {
+    diagnostics.into_iter().map(|diagnostic| diagnostic.render(false)).collect()
+}
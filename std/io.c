@@ -0,0 +1,85 @@
+// The C side of std.io. Strings are taken and returned as Z has them, with
+// their lengths, so text holding NUL bytes is read and written whole. Z's
+// strings end in a NUL, so a path can be given to fopen as it is once it is
+// known to hold no other.
+
+static const z_string z_std_io_null = { 0, NULL, NULL };
+
+static bool z_std_io_path(z_string path) {
+    return path.data != NULL && memchr(path.data, '\0', path.len) == NULL;
+}
+
+z_string z_std_io_read_line(void) {
+    // A prompt printed without a newline shows before the input is read
+    fflush(stdout);
+    size_t capacity = 128;
+    size_t len = 0;
+    char* line = malloc(capacity);
+    if (line == NULL) return z_std_io_null;
+    int c;
+    while ((c = getchar()) != EOF && c != '\n') {
+        if (len == capacity) {
+            char* grown = realloc(line, capacity * 2);
+            if (grown == NULL) {
+                free(line);
+                return z_std_io_null;
+            }
+            line = grown;
+            capacity *= 2;
+        }
+        line[len++] = (char)c;
+    }
+    if (c == EOF && len == 0) {
+        free(line);
+        return z_std_io_null;
+    }
+    if (len > 0 && line[len - 1] == '\r') len--;
+    z_string result = z_str_new(line, (int64_t)len);
+    free(line);
+    return result;
+}
+
+z_string z_std_io_read_file(z_string path) {
+    if (!z_std_io_path(path)) return z_std_io_null;
+    FILE* file = fopen(path.data, "rb");
+    if (file == NULL) return z_std_io_null;
+    size_t capacity = 4096;
+    size_t len = 0;
+    char* contents = malloc(capacity);
+    while (contents != NULL) {
+        len += fread(contents + len, 1, capacity - len, file);
+        if (len < capacity) break;
+        char* grown = realloc(contents, capacity * 2);
+        if (grown == NULL) free(contents);
+        contents = grown;
+        capacity *= 2;
+    }
+    bool failed = contents == NULL || ferror(file);
+    fclose(file);
+    if (failed) {
+        free(contents);
+        return z_std_io_null;
+    }
+    z_string result = z_str_new(contents, (int64_t)len);
+    free(contents);
+    return result;
+}
+
+bool z_std_io_write_file(z_string path, z_string contents) {
+    if (!z_std_io_path(path)) return false;
+    FILE* file = fopen(path.data, "wb");
+    if (file == NULL) return false;
+    size_t len = (size_t)contents.len;
+    bool written = fwrite(contents.data, 1, len, file) == len;
+    return fclose(file) == 0 && written;
+}
+
+void z_std_io_eprint(z_string message) {
+    fflush(stdout);
+    if (message.data == NULL) {
+        fputs("null", stderr);
+    } else {
+        fwrite(message.data, 1, (size_t)message.len, stderr);
+    }
+    fputc('\n', stderr);
+}
@@ -0,0 +1,65 @@
+// The C side of std.random: xoshiro256**, whose state is filled from the
+// seed by splitmix64, as its authors recommend. rand() is too weak and too
+// different between C libraries to build on.
+
+#include <time.h>
+
+static uint64_t z_std_random_state[4];
+static bool z_std_random_seeded = false;
+
+static uint64_t z_std_random_splitmix(uint64_t* x) {
+    uint64_t z = (*x += 0x9e3779b97f4a7c15u);
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9u;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111ebu;
+    return z ^ (z >> 31);
+}
+
+static void z_std_random_fill(uint64_t seed) {
+    for (int i = 0; i < 4; i++) z_std_random_state[i] = z_std_random_splitmix(&seed);
+    z_std_random_seeded = true;
+}
+
+static uint64_t z_std_random_rotl(uint64_t x, int k) {
+    return (x << k) | (x >> (64 - k));
+}
+
+static uint64_t z_std_random_next(void) {
+    // Unseeded, each run draws different numbers
+    if (!z_std_random_seeded) {
+        z_std_random_fill((uint64_t)time(NULL) ^ ((uint64_t)clock() << 32) ^ (uint64_t)(uintptr_t)&z_std_random_state);
+    }
+    uint64_t* s = z_std_random_state;
+    uint64_t result = z_std_random_rotl(s[1] * 5, 7) * 9;
+    uint64_t t = s[1] << 17;
+    s[2] ^= s[0];
+    s[3] ^= s[1];
+    s[1] ^= s[2];
+    s[0] ^= s[3];
+    s[2] ^= t;
+    s[3] = z_std_random_rotl(s[3], 45);
+    return result;
+}
+
+void z_std_random_seed(int64_t value) {
+    z_std_random_fill((uint64_t)value);
+}
+
+int64_t z_std_random_int(int64_t lo, int64_t hi) {
+    // Draws at or above the largest multiple of the range are thrown away,
+    // so that every value is equally likely. A range of every int64_t takes
+    // any draw.
+    uint64_t span = (uint64_t)hi - (uint64_t)lo;
+    if (span == UINT64_MAX) return (int64_t)z_std_random_next();
+    uint64_t range = span + 1;
+    uint64_t limit = UINT64_MAX - UINT64_MAX % range;
+    uint64_t draw;
+    do {
+        draw = z_std_random_next();
+    } while (draw >= limit);
+    return (int64_t)((uint64_t)lo + draw % range);
+}
+
+double z_std_random_float(void) {
+    // The top 53 bits, as many as a double holds exactly
+    return (double)(z_std_random_next() >> 11) * 0x1.0p-53;
+}
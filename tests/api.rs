@@ -0,0 +1,335 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{Run, TempDir};
+use std::process::Command;
+
+#[test]
+fn source_text_compiles_to_c_or_an_executable_without_a_file() {
+    let source = "fn main() {\n    print(\"hello\");\n}\n";
+    let c = z_lang::compile_source(source).expect("the source compiles");
+    assert!(c.contains("int main("), "{}", c);
+
+    let error = z_lang::compile_source("fn main() {\n    print(missing);\n}\n").expect_err("missing is not declared");
+    assert!(error.to_string().contains("missing"), "{}", error);
+
+    let dir = TempDir::new();
+    let output = dir.path().join("hello");
+    z_lang::compile_source_to_path(source, &output).expect("the source is built");
+    let run: Run = Command::new(&output).output().expect("the program runs").into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "hello\n");
+    assert_eq!(std::fs::read_dir(dir.path()).expect("the directory is read").count(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn the_compiler_builder_sets_each_option_and_compile_file_still_builds() {
+    use std::os::unix::fs::PermissionsExt;
+    use z_lang::{CCompiler, Compiler, CrateType, Emit, Gc, ImportPaths, OptReport, PassManager};
+
+    let dir = TempDir::new();
+    let input = dir.write("main.z", "fn main() {\n    let unused = 1;\n    print(2);\n}\n");
+    let cc = dir.write("logging-cc", "#!/bin/sh\necho \"$@\" >> \"$(dirname \"$0\")/cc.log\"\nexec cc \"$@\"\n");
+    std::fs::set_permissions(&cc, std::fs::Permissions::from_mode(0o755)).expect("the script is executable");
+
+    let c = dir.path().join("out.c");
+    Compiler::new().opt_level(0).emit(Emit::C).compile(&input, &c).expect("the C is generated");
+    assert!(std::fs::read_to_string(&c).expect("the C is written").contains("int main("));
+
+    let error = Compiler::new().warnings_as_errors(true).emit(Emit::C).compile(&input, &c).expect_err("unused warns");
+    assert!(error.to_string().contains("unused"), "{}", error);
+
+    let output = dir.path().join("main");
+    Compiler::new()
+        .cc(&cc.display().to_string())
+        .debug_info(true)
+        .keep_temps(true)
+        .compile(&input, &output)
+        .expect("the program is built");
+    let log = std::fs::read_to_string(dir.path().join("cc.log")).expect("the C compiler ran");
+    assert!(log.split_whitespace().any(|arg| arg == "-g"), "{}", log);
+    assert!(dir.path().join("main.c").exists());
+    let run: Run = Command::new(&output).output().expect("the program runs").into();
+    assert_eq!(run.stdout, "2\n");
+
+    let wrapped = dir.path().join("wrapped");
+    z_lang::compile_file(
+        &input,
+        &wrapped,
+        &ImportPaths::of_file(&input),
+        &PassManager::new(1),
+        false,
+        None,
+        false,
+        Emit::Exe,
+        false,
+        CrateType::Bin,
+        Gc::RefCount,
+        false,
+        &CCompiler::resolve(None, None, None).expect("a C compiler is installed"),
+        &mut OptReport::default(),
+    )
+    .expect("the program is built");
+    let run: Run = Command::new(&wrapped).output().expect("the program runs").into();
+    assert_eq!(run.stdout, "2\n");
+}
+
+#[test]
+fn compile_results_list_the_artifacts_stages_and_warnings() {
+    use z_lang::{Compiler, CrateType};
+
+    let dir = TempDir::new();
+    let input = dir.write("main.z", "pub fn twice(x: int) -> int {\n    let unused = 1;\n    return x * 2;\n}\n");
+    let output = dir.path().join("libtwice.a");
+    let c = dir.path().join("twice.c");
+    let result = Compiler::new()
+        .crate_type(CrateType::Lib)
+        .emit_c(&c)
+        .compile(&input, &output)
+        .expect("the library is built");
+    assert_eq!(result.artifacts, [output.clone(), output.with_extension("h"), c]);
+    assert!(result.artifacts.iter().all(|path| path.exists()), "{:?}", result.artifacts);
+    let stages: Vec<&str> = result.stages.iter().map(|stage| stage.name).collect();
+    assert_eq!(stages, ["read", "lex", "parse", "imports", "typecheck", "optimize", "codegen", "cc"]);
+    assert_eq!(result.diagnostics.len(), 1, "{:?}", result.diagnostics);
+    assert!(result.diagnostics[0].message.contains("unused"), "{:?}", result.diagnostics);
+}
+
+#[test]
+fn observers_see_each_stage_and_can_change_what_follows() {
+    use std::sync::{Arc, Mutex};
+    use z_lang::ast::Program;
+    use z_lang::lexer::Span;
+    use z_lang::{ir, Compiler, Observer};
+
+    #[derive(Default)]
+    struct Record(Vec<String>);
+
+    impl Observer for Record {
+        fn on_tokens(&mut self, tokens: &mut Vec<Span>) {
+            self.0.push(format!("tokens {}", tokens.len()));
+        }
+
+        fn on_ast(&mut self, program: &mut Program) {
+            self.0.push(format!("ast {}", program.statements.len()));
+        }
+
+        fn on_typed_ast(&mut self, program: &mut Program) {
+            self.0.push(format!("typed ast {}", program.statements.len()));
+        }
+
+        fn on_ir(&mut self, module: &mut ir::Module) {
+            self.0.push(format!("ir {}", module.functions.len()));
+        }
+
+        fn on_c(&mut self, code: &mut String) {
+            self.0.push("c".to_string());
+            *code = code.replace("41", "42");
+        }
+    }
+
+    let dir = TempDir::new();
+    let input = dir.write("main.z", "fn main() {\n    print(41);\n}\n");
+    let output = dir.path().join("main");
+    let record = Arc::new(Mutex::new(Record::default()));
+    Compiler::new().opt_level(0).observer(record.clone()).compile(&input, &output).expect("the program is built");
+    assert_eq!(record.lock().expect("the record is kept").0, ["tokens 11", "ast 1", "typed ast 1", "ir 1", "c"]);
+    let run: Run = Command::new(&output).output().expect("the program runs").into();
+    assert_eq!(run.stdout, "42\n");
+}
+
+#[test]
+fn compilations_on_threads_of_their_own_keep_apart() {
+    use z_lang::{Compiler, LintLevel};
+
+    let dir = TempDir::new();
+    std::thread::scope(|scope| {
+        let builds: Vec<_> = (0..6)
+            .map(|i| {
+                let source = format!("fn main() {{\n    let unused = 1;\n    print({});\n}}\n", i);
+                let input = dir.write(&format!("p{}/main.z", i), &source);
+                let output = dir.path().join(format!("p{}/main", i));
+                let level = if i % 2 == 0 { LintLevel::Deny } else { LintLevel::Allow };
+                let mut compiler = Compiler::new();
+                compiler.warning_levels(&[("W0001".to_string(), level)]);
+                scope.spawn(move || (i, compiler.compile(&input, &output).map(|_| output)))
+            })
+            .collect();
+        for build in builds {
+            let (i, built) = build.join().expect("the compilation finishes");
+            if i % 2 == 0 {
+                let error = built.expect_err("unused variables are denied");
+                assert!(error.to_string().contains("W0001"), "{}", error);
+            } else {
+                let run: Run = Command::new(built.expect("the program is built")).output().expect("it runs").into();
+                assert_eq!(run.stdout, format!("{}\n", i));
+            }
+        }
+    });
+}
+
+#[test]
+fn spans_name_the_file_they_are_in_among_those_compiled() {
+    use z_lang::Compiler;
+
+    let dir = TempDir::new();
+    dir.write("util.z", "pub fn twice(x: int) -> int {\n    let spare = x;\n    return x * 2;\n}\n");
+    let input = dir.write("main.z", "import util;\n\nfn main() {\n    let unused = 1;\n    print(twice(2));\n}\n");
+    let result = Compiler::new().compile(&input, &dir.path().join("main")).expect("the program is built");
+    let names: Vec<&str> = result.files.files().map(|(_, file)| file.name.as_str()).collect();
+    assert_eq!(names.len(), 2, "{:?}", names);
+    assert!(names[0].ends_with("main.z") && names[1].ends_with("util.z"), "{:?}", names);
+
+    let mut locations: Vec<(String, usize, usize)> = result
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let (file, line, column) = result.files.location(diagnostic.span.as_ref().expect("warnings have spans"));
+            assert_eq!(file, diagnostic.file);
+            (file.rsplit('/').next().unwrap_or(file).to_string(), line, column)
+        })
+        .collect();
+    locations.sort();
+    assert_eq!(locations, [("main.z".to_string(), 4, 5), ("util.z".to_string(), 2, 5)]);
+
+    dir.write("util.z", "pub fn twice(x: int) -> int {\n    return x * true;\n}\n");
+    let run: Run = common::zc(dir.path(), &["-q", "check", "main.z"]).into();
+    assert_eq!(run.code, Some(1));
+    assert!(run.stderr.contains(" --> util.z:2:12\n"), "{}", run.stderr);
+}
+
+#[test]
+fn registered_passes_run_after_the_built_in_ones_and_are_toggled_by_name() {
+    use std::sync::Arc;
+    use z_lang::ast::{Expr, Literal, Program};
+    use z_lang::visit::{walk_expr_mut, VisitorMut};
+    use z_lang::{ir, Compiler, Pass, PassManager, PassStage, Remark};
+
+    /// Makes every 41 written a 42
+    struct Answer;
+
+    struct Rewrite<'a>(&'a mut Vec<Remark>);
+
+    impl VisitorMut for Rewrite<'_> {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            if let Expr::Literal(Literal::Int(value @ 41), span) = expr {
+                *value = 42;
+                self.0.push(Remark {
+                    pass: "answer",
+                    function: "main".to_string(),
+                    span: span.clone(),
+                    count: 1,
+                    message: "answered".to_string(),
+                });
+            }
+            walk_expr_mut(self, expr);
+        }
+    }
+
+    impl Pass for Answer {
+        fn name(&self) -> &'static str {
+            "answer"
+        }
+
+        fn stage(&self) -> PassStage {
+            PassStage::Ast
+        }
+
+        fn run_ast(&self, program: &mut Program, remarks: &mut Vec<Remark>) {
+            Rewrite(remarks).visit_program_mut(program);
+        }
+    }
+
+    struct CountBlocks;
+
+    impl Pass for CountBlocks {
+        fn name(&self) -> &'static str {
+            "count-blocks"
+        }
+
+        fn stage(&self) -> PassStage {
+            PassStage::Ir
+        }
+
+        fn run_ir(&self, function: &mut ir::Function) -> usize {
+            function.blocks.len()
+        }
+    }
+
+    let dir = TempDir::new();
+    let input = dir.write("main.z", "fn main() {\n    print(41);\n}\n");
+    let output = dir.path().join("main");
+    let result = Compiler::new()
+        .pass(Arc::new(Answer))
+        .pass(Arc::new(CountBlocks))
+        .compile(&input, &output)
+        .expect("the program is built");
+    let totals = result.report.totals();
+    assert!(totals.contains(&("answer", 1)), "{:?}", totals);
+    assert!(totals.iter().any(|&(pass, count)| pass == "count-blocks" && count > 0), "{:?}", totals);
+    assert_eq!(totals.last().map(|&(pass, _)| pass), Some("count-blocks"), "{:?}", totals);
+    let run: Run = Command::new(&output).output().expect("the program runs").into();
+    assert_eq!(run.stdout, "42\n");
+
+    let mut passes = PassManager::new(3);
+    passes.register(Arc::new(Answer));
+    passes.configure("-answer").expect("answer is registered");
+    let error = passes.configure("-answers").expect_err("there is no pass answers");
+    assert!(error.ends_with(", answer"), "{}", error);
+    Compiler::new().passes(passes).compile(&input, &output).expect("the program is built");
+    let run: Run = Command::new(&output).output().expect("the program runs").into();
+    assert_eq!(run.stdout, "41\n");
+}
+
+#[test]
+fn diagnostics_and_notes_go_to_the_sink_that_is_set() {
+    use std::sync::{Arc, Mutex};
+    use z_lang::{CCompiler, Compiler, Gc, JsonSink, MemorySink, OptReport, PassManager, RunLimits, Severity};
+
+    let dir = TempDir::new();
+    let input = dir.write("main.z", "fn main() {\n    let unused = 1;\n    print(2);\n}\n");
+    let memory = Arc::new(Mutex::new(MemorySink::default()));
+    let result = Compiler::new()
+        .diagnostic_sink(memory.clone())
+        .compile(&input, &dir.path().join("main"))
+        .expect("the program is built");
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    let reported = memory.lock().expect("the sink is kept").diagnostics.clone();
+    assert_eq!(reported.len(), 1);
+    assert_eq!((reported[0].severity, reported[0].code), (Severity::Warning, "W0001"));
+
+    let json = Arc::new(Mutex::new(JsonSink(Vec::new())));
+    z_lang::set_diagnostic_sink(Some(json.clone()));
+    z_lang::compile_source("fn main() {\n    let unused = 1;\n}\n").expect("the source compiles");
+    let lines = String::from_utf8(json.lock().expect("the sink is kept").0.clone()).expect("the JSON is text");
+    let warning: serde_json::Value = serde_json::from_str(lines.trim_end()).expect("one line of JSON");
+    assert_eq!((&warning["code"], &warning["line"], &warning["column"]), (&"W0001".into(), &2.into(), &5.into()));
+
+    // What the compiler says as it runs a program, and what the program
+    // writes to stderr, are notes
+    let memory = Arc::new(Mutex::new(MemorySink::default()));
+    z_lang::set_diagnostic_sink(Some(memory.clone()));
+    let input = dir.write("panics.z", "fn main() {\n    assert(1 > 2, \"no\");\n}\n");
+    let code = z_lang::run_file(
+        &input,
+        &[],
+        false,
+        Gc::RefCount,
+        false,
+        &PassManager::new(0),
+        &CCompiler::resolve(None, None, None).expect("a C compiler is installed"),
+        true,
+        false,
+        &RunLimits::default(),
+        true,
+        &mut OptReport::default(),
+    )
+    .expect("the program runs");
+    z_lang::set_diagnostic_sink(None);
+    assert_eq!(code, 1);
+    let notes = memory.lock().expect("the sink is kept").notes.clone();
+    assert!(notes.iter().any(|note| note.contains("panics.z")), "{:?}", notes);
+    assert!(notes.iter().any(|note| note.starts_with("panic: assertion failed: no")), "{:?}", notes);
+}
@@ -0,0 +1,28 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+
+#[test]
+fn bench_functions_are_timed_with_their_mean_median_and_deviation() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "@bench\nfn add() {\n    let _x = 1 + 2;\n}\n\n@bench\nfn mul() {\n    let _y = 3 * 4;\n}\n\nfn main() {\n}\n",
+    );
+    let run: Run = zc(dir.path(), &["-q", "bench", "--samples", "5", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let lines: Vec<&str> = run.stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", run.stdout);
+    for (line, name) in lines.iter().zip(["add  ", "mul  "]) {
+        assert!(line.starts_with(name), "{}", run.stdout);
+        for part in [" mean ", " median ", " stddev ", " (5 samples of "] {
+            assert!(line.contains(part), "{}", run.stdout);
+        }
+    }
+
+    let run: Run = zc(dir.path(), &["-q", "bench", "--samples", "5", "--filter", "mul", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.starts_with("mul  mean ") && run.stdout.lines().count() == 1, "{}", run.stdout);
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "capi")]
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+// Links the library, whose C ABI this declares as include/zlang.h does
+use z_lang as _;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ZOptions {
+    opt_level: u8,
+    release: bool,
+    strict_memory: bool,
+    deny_warnings: bool,
+}
+
+extern "C" {
+    fn z_options_default() -> ZOptions;
+    fn z_compile(source: *const c_char, options: *const ZOptions, out_diag: *mut *mut c_char) -> *mut c_char;
+    fn z_string_free(string: *mut c_char);
+    fn z_version() -> *const c_char;
+}
+
+/// Compiles `source` over the C ABI, returning the C and the diagnostics
+fn compile(source: &str, options: Option<ZOptions>) -> (Option<String>, Option<String>) {
+    let source = CString::new(source).expect("the source has no NUL");
+    let options = options.as_ref().map_or(ptr::null(), |options| options as *const ZOptions);
+    let mut diagnostics = ptr::null_mut();
+    unsafe {
+        let code = z_compile(source.as_ptr(), options, &mut diagnostics);
+        let take = |string: *mut c_char| {
+            let text = (!string.is_null()).then(|| CStr::from_ptr(string).to_string_lossy().into_owned());
+            z_string_free(string);
+            text
+        };
+        (take(code), take(diagnostics))
+    }
+}
+
+#[test]
+fn source_compiles_to_c_over_the_c_abi() {
+    let version = unsafe { CStr::from_ptr(z_version()) };
+    assert_eq!(version.to_str(), Ok(env!("CARGO_PKG_VERSION")));
+
+    let (code, diagnostics) = compile("fn main() {\n    print(1);\n}\n", None);
+    assert!(code.expect("the source compiles").contains("int main("));
+    assert_eq!(diagnostics, None);
+
+    let (code, diagnostics) = compile("fn main() {\n    print(1 + true);\n}\n", None);
+    assert_eq!(code, None);
+    let diagnostics = diagnostics.expect("the error is reported");
+    assert!(diagnostics.starts_with("error[E0204]: Invalid operand types"), "{}", diagnostics);
+
+    let warns = "fn main() {\n    let unused = 1;\n}\n";
+    let (code, diagnostics) = compile(warns, None);
+    assert!(code.is_some());
+    assert!(diagnostics.expect("the warning is reported").starts_with("warning[W0001]"));
+    let options = ZOptions { deny_warnings: true, ..unsafe { z_options_default() } };
+    assert_eq!(options.opt_level, 3);
+    let (code, diagnostics) = compile(warns, Some(options));
+    assert_eq!(code, None);
+    assert!(diagnostics.expect("the warning is reported").contains("W0001"));
+
+    let mut diagnostics = ptr::null_mut();
+    let code = unsafe { z_compile(ptr::null(), ptr::null(), &mut diagnostics) };
+    assert!(code.is_null());
+    assert_eq!(unsafe { CStr::from_ptr(diagnostics) }.to_str(), Ok("error: no source given\n"));
+    unsafe { z_string_free(diagnostics) };
+}
@@ -0,0 +1,869 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::run;
+
+#[test]
+fn bools_are_c_bools_and_print_as_words() {
+    let source = "fn flip(b: bool) -> bool {\n    return !b;\n}\n\nfn main() {\n    let done = false;\n    let flag: bool = 1 < 2;\n    print(flip(done));\n    print(flag == done);\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "true\nfalse\n");
+
+    let c = common::emit(source, "c");
+    assert_eq!(c.code, Some(0), "{}", c.stderr);
+    for declaration in ["bool zN4main4flipE(bool b)", "bool done = false;", "bool flag = "] {
+        assert!(c.stdout.contains(declaration), "{}", c.stdout);
+    }
+}
+
+#[test]
+fn optionals_of_numbers_hold_null_or_a_value() {
+    let run = run(r#"fn find(xs: [int], x: int) -> int? {
+    for i in 0..len(xs) {
+        if xs[i] == x {
+            return i;
+        }
+    }
+    return null;
+}
+
+fn main() {
+    let found = find([4, 5, 6], 6);
+    if found != null {
+        print(found + 1);
+    }
+    let missing: float? = null;
+    print(missing == null);
+    print(find([4], 5) == null || found == 2);
+    let values: [int?] = [];
+    values.push(1);
+    values.push(null);
+    let total = 0;
+    for value in values {
+        if value == null {
+            total = total + 10;
+        } else {
+            total = total + value;
+        }
+    }
+    print(total);
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\ntrue\ntrue\n11\n");
+}
+
+#[test]
+fn optional_strings_can_be_null() {
+    let run = run(r#"fn name(x: int) -> string? {
+    if x > 0 {
+        return "positive";
+    }
+    return null;
+}
+
+fn main() {
+    let none: string? = null;
+    print(none == null);
+    let some = name(1);
+    if some != null {
+        print(some);
+    }
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "true\npositive\n");
+}
+
+#[test]
+fn null_is_assigned_to_and_compared_with_optional_strings_wherever_they_are() {
+    let run = run(r#"struct Labelled {
+    label: string?,
+    count: int?,
+}
+
+fn total(xs: [int?]) -> int {
+    let sum = 0;
+    for x in xs {
+        if x != null {
+            sum = sum + x;
+        }
+    }
+    return sum;
+}
+
+fn main() {
+    let s: string? = "a";
+    s = null;
+    print(s == null);
+    let b: Labelled;
+    b.label = "x";
+    b.count = 2;
+    b.label = null;
+    b.count = null;
+    print(b.label == null && b.count == null);
+    let names: [string?] = ["y"];
+    print(names[0] == null);
+    names[0] = null;
+    print(names[0] == null);
+    let numbers: [int?] = [1];
+    print(numbers[0] == null);
+    print(total([1, null, 2]));
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "true\ntrue\nfalse\ntrue\nfalse\n3\n");
+}
+
+#[test]
+fn struct_fields_are_read_and_written() {
+    let run = run(r#"struct Pair<T> {
+    a: T,
+    b: T,
+}
+
+struct Person {
+    name: string,
+    age: int,
+    scores: Pair<int>,
+}
+
+fn older(person: Person) -> Person {
+    let copy = person;
+    copy.age = copy.age + 1;
+    return copy;
+}
+
+fn main() {
+    let p: Pair<int>;
+    p.a = 3;
+    p.b = p.a * 2;
+    print(p.a + p.b);
+    let ada: Person;
+    ada.name = "Ada";
+    ada.age = 36;
+    ada.scores.b = 9;
+    let next = older(ada);
+    ada.name = "Grace";
+    print(next.name + " " + next.age + " " + ada.age + " " + next.scores.b);
+    let people: [Person] = [];
+    people.push(ada);
+    people[0].age = 50;
+    print(people[0].name + " " + people[0].age);
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "9\nAda 37 36 9\nGrace 50\n");
+}
+
+#[test]
+fn structs_cannot_be_compared_whole() {
+    let run = run("struct Point {\n    x: int,\n}\n\nfn main() {\n    let a: Point;\n    let b: Point;\n    print(a == b);\n}\n");
+    assert_ne!(run.code, Some(0));
+    assert!(run.stderr.contains("Cannot compare structs"), "{}", run.stderr);
+}
+
+#[test]
+fn if_and_block_expressions_have_values() {
+    let run = run(r#"fn main() {
+    let x = 3;
+    let size = if x > 2 { "big" } else { "small" };
+    let maybe: int? = if x > 5 { x } else { null };
+    let total = {
+        let y = x * 2;
+        y + 1
+    };
+    print(size + " " + total);
+    print(maybe == null);
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "big 7\ntrue\n");
+}
+
+#[test]
+fn std_io_reads_files_and_lines_as_optional_strings() {
+    let dir = common::TempDir::new();
+    let path = dir.path().join("note.txt");
+    let run = run(&format!(
+        r#"import std.io;
+
+fn main() {{
+    print(read_file("{0}") == null);
+    print(write_file("{0}", "one\ntwo"));
+    let text = read_file("{0}");
+    if text != null {{
+        print(text);
+    }}
+    print(read_line() == null);
+}}
+"#,
+        path.display()
+    ));
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "true\ntrue\none\ntwo\ntrue\n");
+}
+
+#[test]
+fn closure_environments_are_freed_with_their_last_reference() {
+    let run = common::run_with(
+        r#"fn adder(n: int, label: string) -> fn(int) -> int {
+    return |x: int| {
+        print(label);
+        x + n
+    };
+}
+
+fn main() {
+    let fs: [fn(int) -> int] = [];
+    for i in 0..3 {
+        let f = adder(i, "add " + i);
+        fs.push(f);
+    }
+    let total = 0;
+    for f in fs {
+        total = total + f(10);
+    }
+    let g = fs[1];
+    print(total + g(0));
+}
+"#,
+        &["--cflags", "-g -fsanitize=address"],
+    );
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "add 0\nadd 1\nadd 2\nadd 1\n34\n");
+}
+
+#[test]
+fn converting_text_that_is_not_a_number_panics_where_it_was_converted() {
+    let run = run("fn main() {\n    print(\"12\".to_int() + 1);\n    print(\"1.5\".to_float());\n    let s = \"x1\";\n    print(s.to_int());\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "13\n1.5\n");
+    assert!(run.stderr.starts_with("panic: cannot convert \"x1\" to int at main.z:5\n"), "{}", run.stderr);
+}
+
+#[test]
+fn code_generation_errors_point_at_the_code() {
+    let run = run("fn main() {\n    let digits = 0..10;\n    print(1);\n}\n");
+    assert_ne!(run.code, Some(0));
+    assert!(
+        run.stderr.contains("error[E0501]: A range can only be iterated over by a for loop\n --> main.z:2:18\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(!run.stderr.contains("Code generation error"), "{}", run.stderr);
+
+    let dir = common::TempDir::new();
+    dir.write("lib.z", "pub fn twice(x: int) -> int {\n    return x * 2;\n}\n\nfn main() {}\n");
+    let run: common::Run =
+        common::zc(dir.path(), &["-q", "compile", "--crate-type", "lib", "lib.z", "-o", "lib.a"]).into();
+    assert_ne!(run.code, Some(0));
+    assert!(run.stderr.contains("error[E0501]: A library cannot define main"), "{}", run.stderr);
+    assert!(run.stderr.contains(" --> lib.z:5:1\n"), "{}", run.stderr);
+}
+
+#[test]
+fn stack_traces_keep_the_frames_of_inlined_calls() {
+    // Numbers only, which are generated through the IR
+    let numbers = run("fn f(x: int) -> int {\n    return 100 / x;\n}\n\nfn g(x: int) -> int {\n    return f(x) + 1;\n}\n\nfn main() {\n    print(g(2));\n    print(g(0));\n}\n");
+    assert_eq!(numbers.code, Some(1));
+    assert_eq!(numbers.stdout, "51\n");
+    assert!(
+        numbers.stderr.ends_with("panic: division by zero at main.z:2\nstack backtrace:\n    f at main.z:2\n    g at main.z:6\n    main at main.z:11\n"),
+        "{}",
+        numbers.stderr
+    );
+
+    // Strings, which are generated from the checked program
+    let strings = run("fn check(s: string) {\n    assert(s != \"b\", \"no b\");\n}\n\nfn twice(s: string) -> string {\n    return s + s;\n}\n\nfn main() {\n    print(twice(\"a\"));\n    check(twice(\"a\"));\n    check(\"b\");\n}\n");
+    assert_eq!(strings.code, Some(1));
+    assert_eq!(strings.stdout, "aa\n");
+    assert_eq!(strings.stderr, "panic: assertion failed: no b at main.z:2\nstack backtrace:\n    check at main.z:2\n    main at main.z:12\n");
+}
+
+#[test]
+fn modules_whose_names_differ_get_different_symbols() {
+    let dir = common::TempDir::new();
+    let source = "fn twice(x: int) -> int {\n    return x * 2;\n}\n\nfn main() {\n    print(twice(2));\n}\n";
+    let mut symbols = Vec::new();
+    for file in ["my-util.z", "my_util.z"] {
+        dir.write(file, source);
+        let run: common::Run = common::zc(dir.path(), &["-q", "compile", "-O0", "--emit", "c", file, "-o", "-"]).into();
+        assert_eq!(run.code, Some(0), "{}", run.stderr);
+        let symbol = run.stdout.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').find(|word| word.ends_with("5twiceE"));
+        symbols.push(symbol.expect("twice has a symbol").to_string());
+    }
+    assert_eq!(symbols, ["zN9my_2dutil5twiceE", "zN9my_5futil5twiceE"]);
+}
+
+#[test]
+fn pushing_onto_an_empty_array_copies_nothing() {
+    let run = common::run_with(
+        "fn main() {\n    let xs: [int] = [];\n    xs.push(1);\n    let ys = xs;\n    ys.push(2);\n    print(xs[0] + ys[1]);\n}\n",
+        &["--cflags", "-fsanitize=undefined -fno-sanitize-recover=all"],
+    );
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\n");
+}
+
+#[test]
+fn arrays_in_fields_and_elements_are_pushed_where_they_are() {
+    let run = run(
+        "struct Bag {\n    items: [int],\n}\n\nstruct Shelf {\n    bags: [Bag],\n    top: Bag,\n}\n\nfn main() {\n    let b: Bag;\n    b.items = [1];\n    let copy = b;\n    b.items.push(2);\n    let s: Shelf;\n    s.bags = [b];\n    s.top.items.push(4);\n    s.bags[0].items.push(3);\n    print(s.bags[0].items[2]);\n    print(s.top.items.pop());\n    printf(\"{} {} {}\\n\", len(b.items), len(copy.items), len(s.top.items));\n}\n",
+    );
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\n4\n2 1 0\n");
+}
+
+#[test]
+fn the_absolute_value_of_the_smallest_integer_overflows() {
+    let source = "import std.math;\n\nfn main() {\n    let small: i8 = -127;\n    print(abs(small));\n    print(abs(-5));\n    small = small - 1;\n    print(abs(small));\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "127\n5\n");
+    assert!(run.stderr.starts_with("panic: integer overflow at main.z:8\n"), "{}", run.stderr);
+}
+
+#[test]
+fn integer_literals_at_the_ends_of_i64_and_u64_keep_their_values() {
+    let run = run("fn main() {\n    let big: u64 = 18446744073709551615;\n    let small: i64 = -9223372036854775808;\n    print(big);\n    print(big - 5);\n    print(small);\n    print(9223372036854775807);\n    print(-(-5));\n}\n");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "18446744073709551615\n18446744073709551610\n-9223372036854775808\n9223372036854775807\n5\n");
+}
+
+#[test]
+fn casts_convert_between_numbers_and_bools() {
+    let run = run("fn main() {\n    let x = 7;\n    print(x as float / 2.0);\n    print(3.9 as int);\n    print(true as int + 1);\n    print(300 as u8);\n    print(0 as bool);\n}\n");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3.5\n3\n2\n44\nfalse\n");
+}
+
+#[test]
+fn declarations_can_come_after_their_uses() {
+    let run = run(r#"fn main() {
+    let shape = make(2);
+    print(area(shape.size));
+}
+
+fn area(size: Size) -> int {
+    return size.w * size.h;
+}
+
+fn make(x: int) -> Shape {
+    let shape: Shape;
+    shape.size.w = x;
+    shape.size.h = x + 1;
+    return shape;
+}
+
+struct Shape {
+    size: Size,
+}
+
+struct Size {
+    w: int,
+    h: int,
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "6\n");
+}
+
+#[test]
+fn sized_integers_are_declared_with_the_c_types_of_their_size() {
+    let source = "fn main() {\n    let a: u8 = 200;\n    let b: i16 = -300;\n    let c: u64 = 4000000000;\n    let d: i32 = b as i32 * 1000;\n    print(a);\n    print(b);\n    print(c);\n    print(d);\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "200\n-300\n4000000000\n-300000\n");
+
+    let c = common::emit(source, "c");
+    assert_eq!(c.code, Some(0), "{}", c.stderr);
+    for declaration in ["uint8_t a", "int16_t b", "uint64_t c", "int32_t d"] {
+        assert!(c.stdout.contains(declaration), "{}", c.stdout);
+    }
+}
+
+#[test]
+fn release_arithmetic_wraps_to_the_size_of_its_operands() {
+    let body = "    let x: u8 = 200;\n    let y: u8 = 100;\n    print(x + y);\n    print((x + y) / 2);\n    print(x + y == 44);\n    let p: u8 = 3;\n    print(p - 5);\n    let a: i8 = 100;\n    print(a + a);\n    let b: i8 = -128;\n    print(-b);\n    let w: u16 = 65535;\n    print(w * w);\n    print(w + 1 > 0);\n}\n";
+    // Both through the IR and, as the array is not covered by it, without
+    for prelude in ["", "    let xs = [1];\n    print(xs[0]);\n"] {
+        let source = format!("fn main() {{\n{}{}", prelude, body);
+        let run = common::run_with(&source, &["--release"]);
+        assert_eq!(run.code, Some(0), "{}", run.stderr);
+        assert!(run.stdout.ends_with("44\n22\ntrue\n254\n-56\n-128\n1\nfalse\n"), "{}", run.stdout);
+    }
+}
+
+#[test]
+fn variables_are_declared_with_their_inferred_types() {
+    let source = "fn main() {\n    let ratio = 1.5;\n    let ok = ratio > 1.0;\n    let name = \"z\";\n    let count = 2;\n    print(ratio * 2.0);\n    print(ok);\n    print(name + count);\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\ntrue\nz2\n");
+
+    let c = common::emit(source, "c");
+    assert_eq!(c.code, Some(0), "{}", c.stderr);
+    for declaration in ["double ratio = ", "bool ok = ", "z_string name = ", "int64_t count = "] {
+        assert!(c.stdout.contains(declaration), "{}", c.stdout);
+    }
+}
+
+#[test]
+fn functions_are_declared_before_they_are_defined_and_called() {
+    let source = r#"fn is_even(n: int) -> bool {
+    if n == 0 {
+        return true;
+    }
+    return is_odd(n - 1);
+}
+
+fn is_odd(n: int) -> bool {
+    if n == 0 {
+        return false;
+    }
+    return is_even(n - 1);
+}
+
+fn greet(name: string, times: int) {
+    for _ in 0..times {
+        print("hi " + name);
+    }
+}
+
+fn scale(x: float) -> float {
+    return x * 1.5;
+}
+
+fn main() {
+    print(is_even(10));
+    print(is_odd(7));
+    greet("z", 2);
+    print(scale(2.0));
+}
+"#;
+    let run = run(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "true\ntrue\nhi z\nhi z\n3\n");
+
+    let c = common::emit(source, "c");
+    assert_eq!(c.code, Some(0), "{}", c.stderr);
+    for prototype in [
+        "bool zN4main7is_evenE(int64_t n);\n",
+        "void zN4main5greetE(z_string name, int64_t times);\n",
+        "double zN4main5scaleE(double x);\n",
+    ] {
+        assert!(c.stdout.contains(prototype), "{}", c.stdout);
+    }
+}
+
+#[test]
+fn arrays_are_built_indexed_and_assigned_to() {
+    let run = run(r#"fn sum(xs: [int]) -> int {
+    let total = 0;
+    for i in 0..len(xs) {
+        total = total + xs[i];
+    }
+    return total;
+}
+
+fn main() {
+    let xs = [3, 1, 4];
+    xs[1] = 10;
+    print(sum(xs));
+    print(len(xs));
+    let names = ["a", "b"];
+    names[0] = names[1] + "c";
+    print(names[0]);
+    let grid = [[1, 2], [3, 4]];
+    grid[1][0] = 7;
+    print(grid[1][0] + len(grid[0]));
+    let empty: [float] = [];
+    print(len(empty));
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "17\n3\nbc\n9\n0\n");
+}
+
+#[test]
+fn strings_of_any_origin_are_concatenated_compared_and_converted() {
+    let run = common::run_with(
+        r#"fn join(a: string, b: string) -> string {
+    return a + b;
+}
+
+fn main() {
+    let a = "ab";
+    let b = join(a, "c");
+    print(b + 1 + true + 2.5);
+    print(1 + a);
+    print(a == "ab");
+    print(a != b);
+    print(a < b);
+    print(b >= "b");
+    print("41".to_int() + 1);
+    print("0.5".to_float() * 2.0);
+    let s = "";
+    for i in 0..3 {
+        s = s + i;
+    }
+    print(s);
+}
+"#,
+        &["--cflags", "-g -fsanitize=address"],
+    );
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "abc1true2.5\n1ab\ntrue\ntrue\ntrue\nfalse\n42\n1\n012\n");
+}
+
+#[test]
+fn enums_are_tagged_unions_with_a_constructor_per_variant() {
+    let source = r#"enum Shape {
+    Circle(float),
+    Rect(float, float),
+    Empty,
+}
+
+fn area(shape: Shape) -> float {
+    return match shape {
+        Shape.Circle(r) => 3.0 * r * r,
+        Shape.Rect(w, h) => w * h,
+        Shape.Empty => 0.0,
+    };
+}
+
+fn main() {
+    let shapes: [Shape] = [Shape.Circle(1.0), Shape.Rect(2.0, 3.5), Shape.Empty];
+    for shape in shapes {
+        print(area(shape));
+    }
+}
+"#;
+    let run = run(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\n7\n0\n");
+
+    let c = common::emit(source, "c");
+    assert_eq!(c.code, Some(0), "{}", c.stderr);
+    for part in [
+        "enum { Shape_Circle = 0, Shape_Rect = 1, Shape_Empty = 2 };\n",
+        "    int tag;\n    union {\n        struct { double _0; } Circle;\n        struct { double _0; double _1; } Rect;\n    } payload;\n",
+        "static Shape Shape_Rect_new(double _0, double _1) {\n",
+        "static Shape Shape_Empty_new(void) {\n",
+    ] {
+        assert!(c.stdout.contains(part), "{}", c.stdout);
+    }
+}
+
+#[test]
+fn extern_functions_call_into_c() {
+    let source = "@link(\"m\")\nextern fn cbrt(x: float) -> float;\nextern fn abs(x: i32) -> i32;\nextern fn atoi(s: string) -> int;\n\nfn main() {\n    print(cbrt(27.0));\n    print(abs(-3 as i32));\n    print(atoi(\"41\") + 1);\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\n3\n42\n");
+
+    let c = common::emit(source, "c");
+    assert_eq!(c.code, Some(0), "{}", c.stderr);
+    for prototype in ["double cbrt(double x);\n", "int32_t abs(int32_t x);\n", "int atoi(const char* s);\n"] {
+        assert!(c.stdout.contains(prototype), "{}", c.stdout);
+    }
+}
+
+#[test]
+fn print_and_printf_print_values_of_any_printable_type() {
+    let run = run("fn main() {\n    let x: i8 = -3;\n    print(1);\n    print(2.5);\n    print(true);\n    print(\"s\");\n    print(x);\n    printf(\"{} + {} = {}, {}{{}}\\n\", 1, 2.5, \"x\", false);\n}\n");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "1\n2.5\ntrue\ns\n-3\n1 + 2.5 = x, false{}\n");
+}
+
+#[test]
+fn floats_print_with_the_fewest_digits_that_keep_their_value() {
+    // Numbers only, generated through the IR, then with strings
+    let numbers = run("fn main() {\n    print(123456789.0);\n    print(0.1 + 0.2);\n    print(0.1);\n    print(2.5);\n}\n");
+    assert_eq!(numbers.code, Some(0), "{}", numbers.stderr);
+    assert_eq!(numbers.stdout, "123456789\n0.30000000000000004\n0.1\n2.5\n");
+    let strings = run("fn main() {\n    print(\"x \" + 123456789.0);\n    print(\"y \" + (0.1 + 0.2));\n}\n");
+    assert_eq!(strings.code, Some(0), "{}", strings.stderr);
+    assert_eq!(strings.stdout, "x 123456789\ny 0.30000000000000004\n");
+}
+
+#[test]
+fn strings_arrays_and_structs_are_freed_with_their_last_reference() {
+    let run = common::run_with(
+        r#"struct Person {
+    name: string,
+    tags: [string],
+}
+
+fn named(name: string) -> Person {
+    let person: Person;
+    person.name = name + "!";
+    person.tags = [name, name + name];
+    return person;
+}
+
+fn main() {
+    let people: [Person] = [];
+    for i in 0..100 {
+        let person = named("p" + i);
+        if i % 10 == 0 {
+            people.push(person);
+        }
+    }
+    let longest = "";
+    for person in people {
+        let tag = person.tags[1];
+        if len(person.tags) > 1 {
+            longest = tag;
+        }
+    }
+    people = [];
+    print(longest);
+}
+"#,
+        &["--cflags", "-g -fsanitize=address"],
+    );
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "p90p90\n");
+}
+
+#[test]
+fn enum_payloads_and_optionals_are_freed_with_their_last_reference() {
+    let run = common::run_with(
+        r#"enum Msg {
+    Text(string),
+    Empty,
+}
+
+fn lookup(i: int) -> Option<string> {
+    if i % 2 == 0 {
+        return Option.Some("even" + i);
+    }
+    return Option.None;
+}
+
+fn twice(i: int) -> Option<string> {
+    let s = lookup(i)?;
+    return Option.Some(s + s);
+}
+
+fn main() {
+    let texts = 0;
+    for i in 0..100 {
+        let msg = Msg.Text("m" + i);
+        texts = texts + match msg {
+            Msg.Text(s) => s.len(),
+            Msg.Empty => 0,
+        };
+        let tags: [string]? = null;
+        tags = ["t" + i];
+        texts = texts + match twice(i) {
+            Option.Some(s) => s.len(),
+            Option.None => 0,
+        };
+    }
+    print(texts);
+}
+"#,
+        &["--cflags", "-g -fsanitize=address"],
+    );
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "880\n");
+}
+
+#[test]
+fn indexing_out_of_bounds_panics_unless_built_for_release() {
+    let source = "fn main() {\n    let xs = [1, 2, 3];\n    print(xs[2]);\n    print(xs[len(xs)]);\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "3\n");
+    assert!(run.stderr.starts_with("panic: index out of bounds at main.z:4\n"), "{}", run.stderr);
+
+    let dir = common::TempDir::new();
+    dir.write("main.z", source);
+    let release: common::Run =
+        common::zc(dir.path(), &["-q", "compile", "--release", "--emit", "c", "main.z", "-o", "-"]).into();
+    assert_eq!(release.code, Some(0), "{}", release.stderr);
+    assert!(release.stdout.contains("((int64_t*)(xs).data)[(xs).len]"), "{}", release.stdout);
+}
+
+#[test]
+fn arithmetic_overflow_panics_unless_built_for_release() {
+    let source = "fn double(x: i8) -> i8 {\n    return x * 2;\n}\n\nfn main() {\n    print(double(50));\n    print(double(100));\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "100\n");
+    assert!(run.stderr.starts_with("panic: integer overflow at main.z:2\n"), "{}", run.stderr);
+
+    let run = common::run_with(source, &["--release"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "100\n-56\n");
+}
+
+#[test]
+fn string_literals_are_escaped_in_the_c() {
+    let run = run(r#"fn main() {
+    print("say \"hi\" \\ back\n\tnext ??= */ end");
+    print("a\0b".len());
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "say \"hi\" \\ back\n\tnext ??= */ end\n3\n");
+}
+
+#[test]
+fn inline_and_noinline_attributes_reach_the_c() {
+    let source = "@inline\nfn square(x: int) -> int {\n    return x * x;\n}\n\n@noinline\nfn cube(x: int) -> int {\n    return x * x * x;\n}\n\nfn main() {\n    print(square(3) + cube(2));\n}\n";
+    let run = run(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "17\n");
+
+    let c = common::emit(source, "c");
+    assert_eq!(c.code, Some(0), "{}", c.stderr);
+    for prototype in [
+        "static inline __attribute__((always_inline)) int64_t zN4main6squareE(int64_t x);\n",
+        "__attribute__((noinline)) int64_t zN4main4cubeE(int64_t x);\n",
+    ] {
+        assert!(c.stdout.contains(prototype), "{}", c.stdout);
+    }
+
+    let both = common::check("@inline\n@noinline\nfn f() {\n}\n\nfn main() {\n    f();\n}\n");
+    assert_eq!(both.code, Some(1));
+    assert!(both.stderr.contains("A function cannot be both @inline and @noinline"), "{}", both.stderr);
+}
+
+#[test]
+fn option_and_result_are_matched_and_returned_early_with_the_question_mark() {
+    let run = run(r#"fn parse(s: string) -> Result<int, string> {
+    if s == "" {
+        return Result.Err("empty");
+    }
+    return Result.Ok(s.to_int());
+}
+
+fn sum(a: string, b: string) -> Result<int, string> {
+    let x = parse(a)?;
+    let y = parse(b)?;
+    return Result.Ok(x + y);
+}
+
+fn first(xs: [int]) -> Option<int> {
+    if len(xs) == 0 {
+        return Option.None;
+    }
+    return Option.Some(xs[0]);
+}
+
+fn first_doubled(xs: [int]) -> Option<int> {
+    return Option.Some(first(xs)? * 2);
+}
+
+fn main() {
+    for pair in [["1", "2"], ["", "2"], ["3", ""]] {
+        let message = match sum(pair[0], pair[1]) {
+            Result.Ok(total) => "ok " + total,
+            Result.Err(e) => "err " + e,
+        };
+        print(message);
+    }
+    print(match first_doubled([4, 5]) {
+        Option.Some(x) => x,
+        Option.None => -1,
+    });
+    print(match first_doubled([]) {
+        Some(x) => x,
+        _ => -1,
+    });
+}
+"#);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "ok 3\nerr empty\nerr empty\n8\n-1\n");
+
+    let wrong = common::check("fn f(x: Option<int>) -> int {\n    return x?;\n}\n\nfn main() {\n    print(f(Option.None));\n}\n");
+    assert_eq!(wrong.code, Some(1));
+    assert!(
+        wrong.stderr.contains("error[E0306]: ? needs the function to return an Option, but it returns int"),
+        "{}",
+        wrong.stderr
+    );
+}
+
+#[test]
+fn assert_and_panic_abort_with_their_message_and_location() {
+    let run = run("fn check(n: int) -> int {\n    assert(n >= 0, \"negative\");\n    if n > 9 {\n        panic(\"too big: \" + n);\n    }\n    return n;\n}\n\nfn main() {\n    print(check(3));\n    print(check(12));\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "3\n");
+    assert!(run.stderr.starts_with("panic: too big: 12 at main.z:4\n"), "{}", run.stderr);
+
+    let run = common::run("fn main() {\n    assert(1 > 2, \"never\");\n    print(1);\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "");
+    assert!(run.stderr.starts_with("panic: assertion failed: never at main.z:2\n"), "{}", run.stderr);
+
+    let wrong = common::check("fn main() {\n    assert(1, \"one\");\n    panic(2);\n}\n");
+    assert_eq!(wrong.code, Some(1));
+    for location in [" --> main.z:2:12\n", " --> main.z:3:11\n"] {
+        assert!(wrong.stderr.contains(location), "{}", wrong.stderr);
+    }
+}
+
+#[test]
+fn programs_read_their_arguments_and_environment() {
+    let dir = common::TempDir::new();
+    dir.write(
+        "main.z",
+        "fn main() {\n    let argv = args();\n    print(len(argv));\n    for arg in argv {\n        print(arg);\n    }\n    let name = env(\"Z_TEST_NAME\");\n    if name != null {\n        print(\"name \" + name);\n    }\n    print(env(\"Z_TEST_MISSING\") == null);\n}\n",
+    );
+    let built: common::Run = common::zc(dir.path(), &["-q", "compile", "main.z", "-o", "main"]).into();
+    assert_eq!(built.code, Some(0), "{}", built.stderr);
+    let run: common::Run = std::process::Command::new(dir.path().join("main"))
+        .args(["one", "two words"])
+        .env("Z_TEST_NAME", "zed")
+        .env_remove("Z_TEST_MISSING")
+        .output()
+        .expect("the program runs")
+        .into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "2\none\ntwo words\nname zed\ntrue\n");
+}
+
+#[test]
+fn std_random_draws_in_range_and_repeats_for_a_seed() {
+    let source = r#"import std.random;
+
+fn main() {
+    seed(42);
+    let drawn = "";
+    for _ in 0..5 {
+        drawn = drawn + random_int(1, 100) + " ";
+    }
+    print(drawn);
+    let seen = [false, false, false];
+    let in_range = true;
+    for _ in 0..1000 {
+        let x = random_int(-1, 1);
+        in_range = in_range && x >= -1 && x <= 1;
+        seen[x + 1] = true;
+        let f = random_float();
+        in_range = in_range && f >= 0.0 && f < 1.0;
+    }
+    print(in_range && seen[0] && seen[1] && seen[2]);
+    print(random_int(7, 7));
+    random_int(2, 1);
+}
+"#;
+    let first = run(source);
+    assert_eq!(first.code, Some(1));
+    let lines: Vec<&str> = first.stdout.lines().collect();
+    assert_eq!(lines[1..], ["true", "7"], "{}", first.stdout);
+    assert!(first.stderr.starts_with("panic: random_int needs lo to be at most hi"), "{}", first.stderr);
+
+    let drawn: Vec<i64> = lines[0].split_whitespace().map(|x| x.parse().expect("an int")).collect();
+    assert_eq!(drawn.len(), 5);
+    assert!(drawn.iter().all(|x| (1..=100).contains(x)), "{:?}", drawn);
+    assert_eq!(run(source).stdout, first.stdout);
+    assert_ne!(run(&source.replace("seed(42)", "seed(43)")).stdout, first.stdout);
+}
@@ -0,0 +1,112 @@
+// Helpers shared by the integration tests, which write Z programs to
+// directories of their own and run the zc binary on them.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A directory of its own for a test, removed when it is dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "zc-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("the test directory is created");
+        TempDir(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes `text` to `name` in the directory, returning its path
+    pub fn write(&self, name: &str, text: &str) -> PathBuf {
+        let path = self.0.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("the parent directory is created");
+        }
+        fs::write(&path, text).expect("the file is written");
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs zc with `args` in `dir`
+pub fn zc(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(args)
+        .current_dir(dir)
+        .env("NO_COLOR", "1")
+        .env("ZC_HOME", dir.join(".zc-home"))
+        .output()
+        .expect("zc runs")
+}
+
+/// What a finished command printed and how it exited
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    pub code: Option<i32>,
+}
+
+impl From<Output> for Run {
+    fn from(output: Output) -> Run {
+        Run {
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            code: output.status.code(),
+        }
+    }
+}
+
+/// Runs `source` as a program with `zc run` and the extra `args`
+pub fn run_with(source: &str, args: &[&str]) -> Run {
+    let dir = TempDir::new();
+    dir.write("main.z", source);
+    let mut all = vec!["-q", "run", "--no-cache"];
+    all.extend_from_slice(args);
+    all.push("main.z");
+    zc(dir.path(), &all).into()
+}
+
+/// Runs `source` as a program with `zc run`
+pub fn run(source: &str) -> Run {
+    run_with(source, &[])
+}
+
+/// What `zc compile --emit` prints at `stage`, such as c or ir, for `source`
+pub fn emit(source: &str, stage: &str) -> Run {
+    let dir = TempDir::new();
+    dir.write("main.z", source);
+    zc(dir.path(), &["-q", "compile", "--emit", stage, "main.z", "-o", "-"]).into()
+}
+
+/// What `zc check` with the extra `args` prints for `source`, without
+/// building it
+pub fn check_with(source: &str, args: &[&str]) -> Run {
+    let dir = TempDir::new();
+    dir.write("main.z", source);
+    let mut all = vec!["check"];
+    all.extend_from_slice(args);
+    all.push("main.z");
+    zc(dir.path(), &all).into()
+}
+
+/// What `zc check` prints for `source`, without building it
+pub fn check(source: &str) -> Run {
+    check_with(source, &[])
+}
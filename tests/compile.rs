@@ -0,0 +1,206 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+use std::process::Command;
+
+const SOURCE: &str = "fn main() {\n    print(6 * 7);\n}\n";
+
+/// What the program at `path` prints
+fn output_of(path: &std::path::Path) -> String {
+    let output = Command::new(path).output().expect("the program runs");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn the_generated_c_and_temporaries_can_be_kept() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let run: Run = zc(dir.path(), &["-q", "compile", "main.z", "-o", "main", "--emit-c", "generated.c"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(output_of(&dir.path().join("main")), "42\n");
+    let c = std::fs::read_to_string(dir.path().join("generated.c")).expect("the C is kept");
+    assert!(c.contains("int main("), "{}", c);
+    assert!(!dir.path().join("main.c").exists());
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "main.z", "-o", "kept", "--keep-temps"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(dir.path().join("kept.c").exists());
+    assert!(dir.path().join("kept.o").exists());
+}
+
+#[test]
+fn compiling_can_stop_at_the_assembly() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let run: Run = zc(dir.path(), &["-q", "compile", "--emit", "asm", "main.z", "-o", "main.s"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let assembly = std::fs::read_to_string(dir.path().join("main.s")).expect("the assembly is written");
+    assert!(assembly.contains("main:"), "{}", assembly);
+    assert!(!dir.path().join("main").exists());
+}
+
+#[test]
+fn libraries_of_pub_functions_can_be_linked_into_c_programs() {
+    let dir = TempDir::new();
+    dir.write("twice.z", "pub fn twice(x: int) -> int {\n    return helper(x) * 2;\n}\n\nfn helper(x: int) -> int {\n    return x;\n}\n");
+    let run: Run = zc(dir.path(), &["-q", "compile", "--crate-type", "lib", "twice.z", "-o", "libtwice.a"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let header = std::fs::read_to_string(dir.path().join("libtwice.h")).expect("the header is written");
+    assert!(header.contains("\nint64_t twice(int64_t x);\n"), "{}", header);
+    assert!(!header.contains("helper"), "{}", header);
+
+    dir.write(
+        "main.c",
+        "#include \"libtwice.h\"\n#include <stdio.h>\n\nint main(void) {\n    printf(\"%lld\\n\", (long long)twice(21));\n    return 0;\n}\n",
+    );
+    let status = Command::new("cc")
+        .args(["main.c", "libtwice.a", "-lm", "-o", "main"])
+        .current_dir(dir.path())
+        .status()
+        .expect("cc runs");
+    assert!(status.success());
+    assert_eq!(output_of(&dir.path().join("main")), "42\n");
+}
+
+#[test]
+fn concurrent_compilations_use_temporaries_of_their_own_and_remove_them() {
+    let dir = TempDir::new();
+    let temp = dir.path().join("tmp");
+    std::fs::create_dir(&temp).expect("the temporary directory is created");
+    let children: Vec<_> = (0..4)
+        .map(|i| {
+            let file = format!("main{}.z", i);
+            dir.write(&file, &format!("fn main() {{\n    print({});\n}}\n", i));
+            Command::new(env!("CARGO_BIN_EXE_zc"))
+                .args(["-q", "compile", &file, "-o", &format!("main{}", i)])
+                .current_dir(dir.path())
+                .env("TMPDIR", &temp)
+                .spawn()
+                .expect("zc runs")
+        })
+        .collect();
+    let broken = Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(["-q", "compile", "--cflags", "-Wl,--no-such-flag", "main0.z", "-o", "broken"])
+        .current_dir(dir.path())
+        .env("TMPDIR", &temp)
+        .output()
+        .expect("zc runs");
+    assert!(!broken.status.success());
+    for mut child in children {
+        assert!(child.wait().expect("zc finishes").success());
+    }
+
+    for i in 0..4 {
+        assert_eq!(output_of(&dir.path().join(format!("main{}", i))), format!("{}\n", i));
+    }
+    let left: Vec<_> = std::fs::read_dir(&temp).expect("the directory is read").collect();
+    assert!(left.is_empty(), "{:?}", left);
+}
+
+#[test]
+fn only_the_imported_modules_that_changed_are_compiled_again() {
+    let dir = TempDir::new();
+    dir.write("main.z", "import util;\nimport shapes;\n\nfn main() {\n    print(twice(area(3)));\n}\n");
+    dir.write("util.z", "pub fn twice(x: int) -> int {\n    return x * 2;\n}\n");
+    dir.write("shapes.z", "pub fn area(side: int) -> int {\n    return side * side;\n}\n");
+    let objects = || {
+        let mut objects: Vec<_> = std::fs::read_dir(dir.path().join(".zc-cache"))
+            .expect("the modules are cached")
+            .map(|entry| {
+                let entry = entry.expect("the cache is readable");
+                let modified = entry.metadata().and_then(|metadata| metadata.modified()).expect("objects have a time");
+                (entry.file_name().to_string_lossy().into_owned(), modified)
+            })
+            .collect();
+        objects.sort();
+        objects
+    };
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(output_of(&dir.path().join("main")), "18\n");
+    let before = objects();
+    assert_eq!(before.len(), 2, "{:?}", before);
+    assert!(before[0].0.starts_with("shapes-") && before[1].0.starts_with("util-"), "{:?}", before);
+
+    dir.write("util.z", "pub fn twice(x: int) -> int {\n    return x + x + 1;\n}\n");
+    let run: Run = zc(dir.path(), &["-q", "compile", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(output_of(&dir.path().join("main")), "19\n");
+    let after = objects();
+    assert_eq!(after.len(), 3, "{:?}", after);
+    // The object of shapes is the one the first build made
+    assert_eq!(after[0], before[0]);
+}
+
+#[test]
+fn the_build_can_stop_at_any_stage() {
+    let dir = TempDir::new();
+    dir.write("main.z", "fn main() {\n    let x = 1.5;\n    print(x);\n}\n");
+    let emit = |stage: &str, output: &str| -> Run {
+        zc(dir.path(), &["-q", "compile", "--emit", stage, "main.z", "-o", output]).into()
+    };
+    for (stage, start) in [
+        ("tokens", "1:1       Fn              fn\n"),
+        ("ast", "[\n    Function(\n"),
+        ("ir", "\nfn main() -> int\n"),
+        ("c", "// Z Language code"),
+    ] {
+        let run = emit(stage, "-");
+        assert_eq!(run.code, Some(0), "{}", run.stderr);
+        assert!(run.stdout.starts_with(start), "{}: {}", stage, run.stdout);
+    }
+    // Only the typed tree has the type of x filled in
+    assert!(emit("ast", "-").stdout.contains("\"x\",\n                    None,\n"));
+    assert!(emit("typed-ast", "-").stdout.contains("\"x\",\n                    Some(\n                        Float,\n"));
+
+    let run = emit("obj", "main.o");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let object = std::fs::read(dir.path().join("main.o")).expect("the object is written");
+    assert!(object.starts_with(b"\x7fELF") || cfg!(not(target_os = "linux")));
+    let run = emit("exe", "main");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(output_of(&dir.path().join("main")), "1.5\n");
+
+    // Object files and executables are not written to stdout, nor to a
+    // file named -
+    for (stage, artifacts) in [("obj", "Object files"), ("exe", "Executables")] {
+        let run = emit(stage, "-");
+        assert_eq!(run.code, Some(1), "{}", run.stderr);
+        assert!(run.stderr.contains(&format!("{} cannot be written to stdout", artifacts)), "{}", run.stderr);
+        assert!(!dir.path().join("-").exists());
+    }
+
+    let run = emit("bin", "main");
+    assert_eq!(run.code, Some(2));
+    assert!(run.stderr.contains("unknown stage bin, expected tokens, ast, typed-ast, ir, c, asm, obj or exe"), "{}", run.stderr);
+}
+
+#[test]
+fn timings_report_each_stage_as_text_or_json() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let run: Run = zc(dir.path(), &["-q", "compile", "--timings", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stderr.starts_with("stage              time  peak memory       output\nread "), "{}", run.stderr);
+    assert!(run.stderr.contains("\ntotal "), "{}", run.stderr);
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "--timings=json", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let timings: serde_json::Value = serde_json::from_str(&run.stderr).expect("the timings are JSON");
+    let stages: Vec<&str> = timings["stages"]
+        .as_array()
+        .expect("the stages are listed")
+        .iter()
+        .map(|stage| stage["stage"].as_str().expect("stages are named"))
+        .collect();
+    // LLVM generates the object code in place of the C compiler, which only links it
+    let last = if cfg!(feature = "llvm") { ["llvm", "link"] } else { ["codegen", "cc"] };
+    assert_eq!(stages, ["read", "lex", "parse", "imports", "typecheck", "optimize", last[0], last[1]]);
+    let cc = &timings["stages"][7];
+    let executable = std::fs::metadata(dir.path().join("main")).expect("the program is built").len();
+    assert_eq!(cc["output_bytes"], executable);
+    assert!(cc["wall_ms"].as_f64().is_some() && timings["peak_memory_bytes"].as_u64().is_some_and(|bytes| bytes > 0));
+}
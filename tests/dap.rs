@@ -0,0 +1,126 @@
+#![cfg(all(feature = "cli", feature = "dap"))]
+
+mod common;
+
+use common::TempDir;
+use serde_json::{json, Value as Json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// A session with `zc dap`, sending requests and reading what comes back
+struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: i64,
+}
+
+impl Session {
+    fn start(dir: &TempDir) -> Session {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_zc"))
+            .arg("dap")
+            .current_dir(dir.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("zc runs");
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Session { child, stdin, stdout, seq: 0 }
+    }
+
+    fn send(&mut self, command: &str, arguments: Json) {
+        self.seq += 1;
+        let body = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }).to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).expect("zc reads the request");
+    }
+
+    fn receive(&mut self) -> Json {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).expect("zc sends a header");
+            match line.trim_end() {
+                "" => break,
+                header => {
+                    length = header.trim_start_matches("Content-Length: ").parse().expect("the length is a number");
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).expect("zc sends the body");
+        serde_json::from_slice(&body).expect("the message is JSON")
+    }
+
+    /// Sends `command`, returning the body of its response and the events
+    /// sent before it
+    fn request(&mut self, command: &str, arguments: Json) -> (Json, Vec<Json>) {
+        self.send(command, arguments);
+        let mut events = Vec::new();
+        loop {
+            let message = self.receive();
+            if message["type"] == "response" {
+                assert_eq!(message["command"], command);
+                assert_eq!(message["success"], true, "{}", message);
+                return (message["body"].clone(), events);
+            }
+            events.push(message);
+        }
+    }
+
+    /// Reads messages until the event `name`, returning its body
+    fn wait_for(&mut self, name: &str) -> Json {
+        loop {
+            let message = self.receive();
+            if message["type"] == "event" && message["event"] == name {
+                return message["body"].clone();
+            }
+        }
+    }
+}
+
+#[test]
+fn programs_stop_at_breakpoints_with_their_stack_and_variables() {
+    let dir = TempDir::new();
+    let program = dir.write(
+        "main.z",
+        "fn square(x: int) -> int {\n    let y = x * x;\n    return y;\n}\n\nfn main() {\n    print(square(3));\n    print(square(4));\n}\n",
+    );
+    let mut session = Session::start(&dir);
+    session.request("initialize", json!({ "adapterID": "z" }));
+    session.request("launch", json!({ "program": program }));
+    let (body, _) = session.request("setBreakpoints", json!({ "breakpoints": [{ "line": 3 }] }));
+    assert_eq!(body["breakpoints"], json!([{ "verified": true, "line": 3 }]));
+    session.request("configurationDone", json!({}));
+
+    assert_eq!(session.wait_for("stopped")["reason"], "breakpoint");
+    let (body, _) = session.request("stackTrace", json!({ "threadId": 1 }));
+    let frames: Vec<(&str, i64)> = body["stackFrames"]
+        .as_array()
+        .expect("there are frames")
+        .iter()
+        .map(|frame| (frame["name"].as_str().expect("named"), frame["line"].as_i64().expect("on a line")))
+        .collect();
+    assert_eq!(frames, [("square", 3), ("main", 7), ("<top level>", 1)]);
+    let (body, _) = session.request("variables", json!({ "variablesReference": 1 }));
+    let variables: Vec<(&str, &str)> = body["variables"]
+        .as_array()
+        .expect("there are variables")
+        .iter()
+        .map(|variable| (variable["name"].as_str().expect("named"), variable["value"].as_str().expect("valued")))
+        .collect();
+    assert!(variables.contains(&("x", "3")), "{:?}", variables);
+    assert!(variables.contains(&("y", "9")), "{:?}", variables);
+
+    // Stepping over the return comes back to main
+    session.request("next", json!({ "threadId": 1 }));
+    session.wait_for("stopped");
+    let (body, _) = session.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(body["stackFrames"][0]["name"], "main");
+
+    session.request("setBreakpoints", json!({ "breakpoints": [] }));
+    session.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(session.wait_for("exited")["exitCode"], 0);
+    session.request("disconnect", json!({}));
+    assert!(session.child.wait().expect("zc exits").success());
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{check, zc, Run, TempDir};
+use serde_json::json;
+
+const SOURCE: &str = "struct P {\n    used: int,\n    unused: int,\n}\n\nfn helper() -> int {\n    return 1;\n}\n\nfn only_from_dead() {}\n\nfn dead() {\n    only_from_dead();\n}\n\nfn main() {\n    let p: P;\n    p.used = helper();\n    print(p.used);\n}\n";
+
+#[test]
+fn functions_and_fields_main_never_reaches_are_reported_as_json() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let run: Run = zc(dir.path(), &["dead-code", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let report: serde_json::Value = serde_json::from_str(&run.stdout).expect("the report is JSON");
+    assert_eq!(
+        report,
+        json!({
+            "fields": [{ "struct": "P", "field": "unused", "start": 0, "end": 44 }],
+            "functions": [
+                { "name": "only_from_dead", "referenced": true, "start": 84, "end": 106 },
+                { "name": "dead", "referenced": false, "start": 108, "end": 143 },
+            ],
+        })
+    );
+}
+
+#[test]
+fn code_main_never_reaches_is_warned_about() {
+    let run = check(SOURCE);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let warnings: Vec<&str> = run.stderr.lines().filter(|line| line.starts_with("warning[")).collect();
+    assert_eq!(
+        warnings,
+        [
+            "warning[W0004]: Field P.unused is never used",
+            "warning[W0003]: Function only_from_dead is only used by code that main never reaches",
+            "warning[W0002]: Function dead is never called",
+        ]
+    );
+}
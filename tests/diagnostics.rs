@@ -0,0 +1,169 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{run, run_with};
+
+#[test]
+fn redefinitions_point_at_the_first_definition() {
+    let run = run("fn main() {\n    let x = 1;\n    let x = 2;\n    print(x);\n}\n");
+    assert_ne!(run.code, Some(0));
+    assert_eq!(
+        run.stderr,
+        "error[E0103]: x is already defined in this scope\n --> main.z:3:5\n  |\n3 |     let x = 2;\n  |     ^^^^^^^^^^\n  = first defined at main.z:2:5\n\n"
+    );
+}
+
+#[test]
+fn duplicate_parameters_point_at_the_first_parameter() {
+    let function = run("fn add(a: int, a: int) -> int {\n    return a;\n}\n\nfn main() {\n    print(add(1, 2));\n}\n");
+    assert_ne!(function.code, Some(0));
+    assert_eq!(
+        function.stderr,
+        "error[E0104]: Function add has more than one parameter named a\n --> main.z:1:16\n  |\n1 | fn add(a: int, a: int) -> int {\n  |                ^\n  = first defined at main.z:1:8\n\n"
+    );
+
+    let lambda = run("fn main() {\n    let f = |x, x| x;\n}\n");
+    assert_ne!(lambda.code, Some(0));
+    assert!(lambda.stderr.contains("error[E0104]: Lambda has more than one parameter named x\n --> main.z:2:17\n"), "{}", lambda.stderr);
+    assert!(lambda.stderr.contains("  = first defined at main.z:2:14\n"), "{}", lambda.stderr);
+}
+
+#[test]
+fn uses_of_moved_values_point_at_the_move() {
+    let run = run_with(
+        "fn take(s: string) {}\n\nfn main() {\n    let s = \"a\";\n    take(s);\n    take(s);\n}\n",
+        &["--strict-memory"],
+    );
+    assert_ne!(run.code, Some(0));
+    assert!(run.stderr.contains("error[E0401]: Use of moved value s\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("  = moved at main.z:5:10\n"), "{}", run.stderr);
+}
+
+#[test]
+fn errors_in_declarations_do_not_hide_those_in_bodies() {
+    let run = run("fn f() {}\n\nfn f() {}\n\nstruct P {\n    x: Missing,\n}\n\nfn main() {\n    let s: string = 1;\n    f();\n}\n");
+    assert_ne!(run.code, Some(0));
+    let codes: Vec<&str> = run.stderr.lines().filter(|line| line.starts_with("error[")).collect();
+    assert_eq!(
+        codes,
+        [
+            "error[E0103]: Function f is already defined",
+            "error[E0102]: Unknown type: Missing",
+            "error[E0201]: Type mismatch: expected string, found int",
+        ]
+    );
+}
+
+#[test]
+fn paths_may_end_in_a_panic_instead_of_a_return() {
+    let run = run(r#"import std.random;
+
+fn fail(message: string) {
+    panic("failed: " + message);
+}
+
+fn sign(x: int) -> int {
+    if x > 0 {
+        return 1;
+    }
+    if x < 0 {
+        return -1;
+    }
+    fail("zero has no sign");
+}
+
+fn main() {
+    let half = |x: int| -> int {
+        if x % 2 == 0 {
+            return x / 2;
+        }
+        panic("odd");
+    };
+    print(sign(-3) + half(8));
+    print(random_int(2, 1));
+}
+"#);
+    assert_eq!(run.code, Some(1), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\n");
+    assert!(run.stderr.starts_with("panic: random_int needs lo to be at most hi at <std>/random.z:"), "{}", run.stderr);
+}
+
+#[test]
+fn calls_that_may_come_back_do_not_end_a_path() {
+    let run = run(r#"fn maybe(message: string) {
+    if message == "" {
+        return;
+    }
+    panic(message);
+}
+
+fn shadowed(fail: fn(string) -> void) -> int {
+    fail("x");
+}
+
+fn returns() -> int {
+    maybe("x");
+}
+
+fn main() {}
+"#);
+    assert_ne!(run.code, Some(0));
+    assert!(run.stderr.contains("error[E0210]: Function shadowed can reach the end"), "{}", run.stderr);
+    assert!(run.stderr.contains("error[E0210]: Function returns can reach the end"), "{}", run.stderr);
+}
+
+#[test]
+fn pub_functions_cannot_take_names_of_c_or_the_runtime() {
+    let run = run("pub fn puts(s: string) {\n    print(s);\n}\n\npub fn z_panic() {}\n\npub fn say() {}\n\nfn main() {\n    puts(\"a\");\n}\n");
+    assert_ne!(run.code, Some(0));
+    assert!(
+        run.stderr.contains("error[E0225]: Function puts cannot be pub, since its name belongs to C or its library\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(
+        run.stderr.contains("error[E0225]: Function z_panic cannot be pub, since its name belongs to the runtime\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(!run.stderr.contains("say"), "{}", run.stderr);
+}
+
+#[test]
+fn listing_explanations_into_a_closed_pipe_stops_quietly() {
+    let dir = common::TempDir::new();
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_zc"))
+        .arg("explain")
+        .current_dir(dir.path())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("zc runs");
+    // As head does once it has read enough
+    drop(child.stdout.take());
+    let run: common::Run = child.wait_with_output().expect("zc finishes").into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn diagnostics_are_colored_when_asked_and_plain_in_pipes() {
+    let source = "fn main() {\n    let x: int = \"a\";\n}\n";
+    let plain = "error[E0201]: Type mismatch: expected int, found string\n --> main.z:2:5\n";
+    assert!(common::check(source).stderr.starts_with(plain));
+    assert!(common::check_with(source, &["--color", "never"]).stderr.starts_with(plain));
+    let colored = common::check_with(source, &["--color", "always"]);
+    assert!(colored.stderr.starts_with("\x1b[1;31merror[E0201]\x1b[0m\x1b[1m: Type mismatch"), "{}", colored.stderr);
+
+    // Output into a pipe is plain, even without NO_COLOR
+    let dir = common::TempDir::new();
+    dir.write("main.z", source);
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(["check", "main.z"])
+        .current_dir(dir.path())
+        .env_remove("NO_COLOR")
+        .output()
+        .expect("zc runs");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with(plain));
+}
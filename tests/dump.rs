@@ -0,0 +1,39 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+
+#[test]
+fn tokens_and_the_syntax_tree_can_be_printed() {
+    let dir = TempDir::new();
+    dir.write("main.z", "fn main() {\n    print(1 + 2);\n}\n");
+    let run: Run = zc(dir.path(), &["dump", "--tokens", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let lines: Vec<&str> = run.stdout.lines().collect();
+    assert_eq!(lines[0], "1:1       Fn              fn");
+    assert_eq!(lines[7], "2:11      IntLiteral      1");
+
+    let run: Run = zc(dir.path(), &["dump", "--tokens", "--json", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let tokens: serde_json::Value = serde_json::from_str(&run.stdout).expect("the tokens are JSON");
+    assert_eq!(tokens[1], serde_json::json!({ "kind": "Identifier", "text": "main", "line": 1, "column": 4, "start": 3, "end": 7 }));
+
+    let run: Run = zc(dir.path(), &["dump", "--ast", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.starts_with("[\n    Function(\n        \"main\",\n"), "{}", run.stdout);
+}
+
+#[test]
+fn the_syntax_tree_is_printed_as_json_whose_nodes_name_their_kind() {
+    let dir = TempDir::new();
+    dir.write("main.z", "fn main() {\n    print(1 + 2);\n}\n");
+    let run: Run = zc(dir.path(), &["dump", "--ast", "--json", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let tree: serde_json::Value = serde_json::from_str(&run.stdout).expect("the tree is JSON");
+    let function = &tree[0];
+    assert_eq!(function["kind"], "Function");
+    assert_eq!(function["name"], "main");
+    assert_eq!(function["body"]["stmts"][0]["expr"]["args"][0]["kind"], "Binary");
+    assert_eq!(function["span"], serde_json::json!({ "start": 0, "end": 31 }));
+}
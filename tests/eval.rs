@@ -0,0 +1,64 @@
+use z_lang::ast::Type;
+use z_lang::Value;
+
+#[test]
+fn expressions_and_programs_are_evaluated_to_values() {
+    assert_eq!(z_lang::eval("{ let width = 4; width * 2 }").expect("the block evaluates"), Value::Int(8));
+    assert_eq!(z_lang::eval("\"a\" + 1").expect("the text evaluates"), Value::String("a1".to_string()));
+    assert_eq!(
+        z_lang::eval("[1.5, 2.0]").expect("the array evaluates"),
+        Value::Array(vec![Value::Float(1.5), Value::Float(2.0)])
+    );
+
+    let program = "fn sum(xs: [int]) -> int {\n    let total = 0;\n    for x in xs {\n        total = total + x;\n    }\n    return total;\n}\n\nfn main() -> int {\n    return sum([1, 2, 3]);\n}\n";
+    assert_eq!(z_lang::eval(program).expect("the program runs"), Value::Int(6));
+
+    let error = z_lang::eval("1 + true == 2").expect_err("ints and bools do not add");
+    assert!(error.to_string().contains("Invalid operand types"), "{}", error);
+    let error = z_lang::eval("{ let zero = 0; 10 / zero }").expect_err("dividing by zero fails");
+    assert!(error.to_string().contains("Division by zero"), "{}", error);
+}
+
+#[test]
+fn sized_integers_wrap_when_cast_and_panic_on_overflow_as_compiled_code_does() {
+    assert_eq!(
+        z_lang::eval("(-1) as u64").expect("the cast evaluates"),
+        Value::Sized(Type::U64, u64::MAX as i128)
+    );
+    assert_eq!(z_lang::eval("300 as u8").expect("the cast evaluates"), Value::Sized(Type::U8, 44));
+    assert_eq!(z_lang::eval("200 as i8").expect("the cast evaluates"), Value::Sized(Type::I8, -56));
+    assert_eq!(
+        z_lang::eval("{ let big: u64 = 18446744073709551615; big > 1 }").expect("the comparison evaluates"),
+        Value::Bool(true)
+    );
+
+    let error = z_lang::eval("{ let x: u8 = 255; x + 1 }").expect_err("u8 overflows past 255");
+    assert!(error.to_string().contains("panic: integer overflow"), "{}", error);
+    let error = z_lang::eval("{ let x: u32 = 0; x - 1 }").expect_err("u32 overflows below 0");
+    assert!(error.to_string().contains("panic: integer overflow"), "{}", error);
+    let error = z_lang::eval("{ let x = 9223372036854775807; x + 1 }").expect_err("int overflows past i64::MAX");
+    assert!(error.to_string().contains("panic: integer overflow"), "{}", error);
+
+    let program = "fn next(x: u8) -> u8 {\n    return x + 1;\n}\n\nfn main() -> u8 {\n    let xs: [u8] = [254];\n    xs.push(next(xs[0]));\n    return xs[1];\n}\n";
+    assert_eq!(z_lang::eval(program).expect("the program runs"), Value::Sized(Type::U8, 255));
+}
+
+#[test]
+fn struct_fields_are_read_and_assigned_through_any_path() {
+    let program = "struct Point {\n    x: int,\n    y: i8,\n}\n\nstruct Shape {\n    corners: [Point],\n    origin: Point,\n}\n\nfn main() -> int {\n    let s: Shape;\n    s.origin.x = 3;\n    let p: Point;\n    p.y = 4;\n    let corners: [Point] = [];\n    corners.push(p);\n    s.corners = corners;\n    s.corners[0].x = s.origin.x * 2;\n    return s.corners[0].x + s.corners[0].y as int;\n}\n";
+    assert_eq!(z_lang::eval(program).expect("the program runs"), Value::Int(10));
+
+    let error = z_lang::eval("struct Small {\n    n: i8,\n}\n\nfn main() {\n    let s: Small;\n    s.n = 127;\n    s.n = s.n + 1;\n}\n")
+        .expect_err("the field overflows as an i8");
+    assert!(error.to_string().contains("panic: integer overflow"), "{}", error);
+}
+
+#[test]
+fn what_a_program_prints_and_its_warnings_are_returned_not_written() {
+    let evaluation = z_lang::evaluate("fn main() -> int {\n    let unused = 1;\n    print(\"hi\");\n    return 2;\n}\n")
+        .expect("the program runs");
+    assert_eq!(evaluation.value, Value::Int(2));
+    assert_eq!(evaluation.output, "hi\n");
+    assert_eq!(evaluation.diagnostics.len(), 1);
+    assert!(evaluation.diagnostics[0].message.contains("unused"), "{}", evaluation.diagnostics[0].message);
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{Run, TempDir};
+
+#[test]
+fn the_commands_of_the_backends_are_built_only_with_their_features() {
+    let dir = TempDir::new();
+    let help: Run = common::zc(dir.path(), &["--help"]).into();
+    assert_eq!(help.code, Some(0), "{}", help.stderr);
+    let commands: Vec<&str> = help
+        .stdout
+        .lines()
+        .skip_while(|line| *line != "Commands:")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    for (command, built) in [
+        ("compile", cfg!(feature = "c")),
+        ("run", cfg!(feature = "c")),
+        ("build", cfg!(feature = "c")),
+        ("bench", cfg!(feature = "c")),
+        ("env", cfg!(feature = "c")),
+        ("targets", cfg!(feature = "c")),
+        ("dap", cfg!(feature = "dap")),
+        ("check", true),
+        ("lint", true),
+        ("dump", true),
+    ] {
+        assert_eq!(commands.contains(&command), built, "{} in {:?}", command, commands);
+    }
+
+    // The frontend is there without any of them
+    dir.write("main.z", "fn main() {\n    print(1 + true);\n}\n");
+    let check: Run = common::zc(dir.path(), &["check", "main.z"]).into();
+    assert_eq!(check.code, Some(1));
+    assert!(check.stderr.contains("error[E0204]") && check.stderr.contains(" --> main.z:2:11\n"), "{}", check.stderr);
+}
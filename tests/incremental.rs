@@ -0,0 +1,80 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+use std::path::Path;
+use z_lang::{Database, ImportPaths, Query};
+
+const SOURCE: &str = "fn f() -> int {\n    return 1;\n}\n\nfn g() -> int {\n    let unused = 2;\n    return 3;\n}\n\nfn main() {\n    print(f() + g());\n}\n";
+
+fn database() -> Database {
+    Database::new(ImportPaths::of_file(Path::new("main.z")))
+}
+
+#[test]
+fn editing_a_function_checks_only_that_function_again() {
+    let mut db = database();
+    let file = db.set_source("main.z", SOURCE);
+    db.check(file).expect("the program checks");
+    let executed = db.take_executed();
+    assert!(executed.contains(&(Query::CheckFunction("g".to_string()), file)), "{:?}", executed);
+
+    let edited = SOURCE.replace("    return 1;\n", "    let one = 1;\n    return one;\n");
+    db.set_source("main.z", &edited);
+    let checked = db.check(file).expect("the program checks");
+    assert_eq!(
+        db.take_executed(),
+        [(Query::Parse, file), (Query::Check, file), (Query::CheckFunction("f".to_string()), file)]
+    );
+
+    // What checking g found moved down a line with its text
+    let mut fresh = database();
+    let fresh_file = fresh.set_source("main.z", &edited);
+    let expected = fresh.check(fresh_file).expect("the program checks");
+    assert_eq!(format!("{:?}", checked.warnings), format!("{:?}", expected.warnings));
+    assert_eq!(format!("{:?}", checked.program.statements), format!("{:?}", expected.program.statements));
+    let unused = checked.warnings[0].span.as_ref().expect("the warning has a span");
+    assert_eq!(db.files().location(unused), ("main.z", 7, 5));
+}
+
+#[test]
+fn editing_a_signature_checks_every_function_again() {
+    let mut db = database();
+    let file = db.set_source("main.z", SOURCE);
+    db.check(file).expect("the program checks");
+    db.take_executed();
+
+    db.set_source("main.z", &SOURCE.replace("fn f() -> int", "fn f() -> string"));
+    let errors = match db.check(file) {
+        Err(z_lang::CompilerError::Diagnostics(errors)) => errors,
+        other => panic!("{:?}", other.map(|_| ())),
+    };
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].message, "Type mismatch: expected string, found int");
+    let executed = db.take_executed();
+    for query in [Query::CheckTopLevel, Query::CheckFunction("f".to_string()), Query::CheckFunction("g".to_string())] {
+        assert!(executed.contains(&(query, file)), "{:?}", executed);
+    }
+}
+
+#[test]
+fn zc_check_reports_errors_without_building() {
+    let dir = TempDir::new();
+    dir.write("main.z", "import util;\n\nfn main() {\n    let x = 1;\n    print(twice(true));\n}\n");
+    dir.write("util.z", "pub fn twice(x: int) -> int {\n    return x * 2;\n}\n");
+    let run: Run = zc(dir.path(), &["check", "main.z"]).into();
+    assert_eq!(run.code, Some(1));
+    assert!(
+        run.stderr.contains("error[E0203]: Argument 1 of twice has type bool, expected int\n --> main.z:5:17\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(run.stderr.contains("Checked main.z: 1 errors, 0 warnings\n"), "{}", run.stderr);
+
+    dir.write("main.z", "import util;\n\nfn main() {\n    let x = 1;\n    print(twice(2));\n}\n");
+    let run: Run = zc(dir.path(), &["check", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stderr.contains("warning[W0001]: Unused variable: x"), "{}", run.stderr);
+    assert!(!dir.path().join("main").exists());
+}
@@ -0,0 +1,261 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{emit, zc, Run, TempDir};
+
+#[test]
+fn programs_of_numbers_are_lowered_to_the_ir() {
+    let run = emit("fn square(x: int) -> int {\n    return x * x;\n}\n\nfn main() {\n    print(square(3));\n}\n", "ir");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.contains("fn zN4main6squareE(x: int) -> int\nb0:\n"), "{}", run.stdout);
+}
+
+#[test]
+fn the_optimization_report_says_what_the_ir_does_not_cover() {
+    let source = "fn main() {\n    let s = \"a\";\n    print(s);\n}\n";
+    let run = emit(source, "ir");
+    assert_ne!(run.code, Some(0));
+    assert!(run.stderr.contains("does not"), "{}", run.stderr);
+
+    let dir = TempDir::new();
+    dir.write("main.z", source);
+    // Keeping the C builds through it, which the IR is lowered for, also
+    // with the llvm feature
+    let run: Run = zc(dir.path(), &["-q", "compile", "--opt-report", "--keep-temps", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(
+        run.stderr.contains("main.z:2:5: ir: generated from the checked program, since the intermediate representation does not cover variables of other types than numbers and bools in main"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn for_loops_over_ranges_are_unrolled() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "fn main() {\n    let total = 0;\n    for i in 0..4 {\n        total = total + i * i;\n    }\n    print(total);\n    for j in 1..total / 7 + 1 {\n        j = j * 10;\n        print(j);\n    }\n}\n",
+    );
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O", "2", "--opt-report", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stderr.contains("unroll: 1 loop unrolled in main"), "{}", run.stderr);
+    let output = std::process::Command::new(dir.path().join("main")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "14\n10\n20\n");
+}
+
+#[test]
+fn unrolled_loops_leave_no_loop_in_the_c_and_the_executable() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "fn main() {\n    let total = 0;\n    for i in 0..4 {\n        total = total + i * i;\n    }\n    print(total);\n}\n",
+    );
+    let run: Run = zc(dir.path(), &["-q", "compile", "--emit", "c", "-O3", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let main = &run.stdout[run.stdout.find("int main()").expect("main is emitted")..];
+    assert!(!main.contains("goto"), "{}", main);
+    assert_eq!(main.matches("z_mul_overflow(i, i, ").count(), 4, "{}", main);
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "--emit", "c", "-O0", "main.z", "-o", "-"]).into();
+    assert!(run.stdout.contains("goto z_b1;"), "{}", run.stdout);
+
+    // Keeping the C builds through it, also with the llvm feature
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O3", "--keep-temps", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let c = std::fs::read_to_string(dir.path().join("main.c")).expect("the C is kept");
+    assert!(!c[c.find("int main()").expect("main is emitted")..].contains("goto"), "{}", c);
+    let output = std::process::Command::new(dir.path().join("main")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "14\n");
+}
+
+#[test]
+fn multiplying_by_a_power_of_two_shifts_in_the_c_with_its_check() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "fn scale(x: int) -> int {\n    return x * 8;\n}\n\nfn main() {\n    print(scale(3));\n    print(scale(1152921504606846976));\n}\n",
+    );
+    for args in [&["-O3"][..], &["-O3", "--release"]] {
+        let mut all = vec!["-q", "compile", "--emit", "c", "main.z", "-o", "-"];
+        all.extend_from_slice(args);
+        let run: Run = zc(dir.path(), &all).into();
+        assert_eq!(run.code, Some(0), "{}", run.stderr);
+        assert!(run.stdout.contains("(uint64_t)x << 3LL)"), "{}", run.stdout);
+        assert!(!run.stdout.contains("x * 8") && !run.stdout.contains("(x, 8LL"), "{}", run.stdout);
+    }
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O3", "--keep-temps", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let output: Run = std::process::Command::new(dir.path().join("main")).output().expect("the program runs").into();
+    assert_eq!(output.code, Some(1));
+    assert_eq!(output.stdout, "24\n");
+    assert!(output.stderr.starts_with("panic: integer overflow at main.z:2\n"), "{}", output.stderr);
+}
+
+#[test]
+fn the_passes_on_the_ir_report_the_code_they_changed() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "fn main() {\n    let total = 0;\n    for i in 0..4 {\n        total = total + i;\n    }\n    if false {\n        print(1);\n    }\n    print(total / 4);\n}\n",
+    );
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O", "2", "--opt-report", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    for remark in [
+        "main.z:3:5: unroll: 1 loop unrolled in main",
+        "main.z:6:5: fold-branches: 1 branch folded in main",
+        "main.z:7:9: dce: 1 instruction removed in main",
+        "main.z:9:11: strength-reduce: 1 operation reduced in main",
+    ] {
+        assert!(run.stderr.contains(remark), "{}", run.stderr);
+    }
+}
+
+const CALLS: &str = "fn square(x: int) -> int {\n    return x * x;\n}\n\nfn fact(n: int) -> int {\n    if n <= 1 {\n        return 1;\n    }\n    return n * fact(n - 1);\n}\n\nfn main() {\n    let y = 3;\n    print(square(y) + fact(4));\n}\n";
+
+#[test]
+fn small_functions_that_do_not_call_themselves_are_inlined() {
+    let run = common::run_with(CALLS, &["-O", "1", "--opt-report"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "33\n");
+    assert!(run.stderr.contains("main.z:14:11: inline: inlined call to square in main\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("inline: 1 call inlined"), "{}", run.stderr);
+
+    let run = common::run_with(CALLS, &["-O", "0", "--opt-report"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "33\n");
+    assert!(!run.stderr.contains("inline"), "{}", run.stderr);
+}
+
+#[test]
+fn passes_can_be_turned_on_and_off_one_at_a_time() {
+    let run = common::run_with(CALLS, &["-O", "2", "--passes=-inline", "--opt-report"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "33\n");
+    assert!(!run.stderr.contains("inline"), "{}", run.stderr);
+
+    let run = common::run_with(CALLS, &["-O", "0", "--passes=+inline", "--opt-report"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stderr.contains("inline: 1 call inlined"), "{}", run.stderr);
+
+    let run = common::run_with(CALLS, &["--passes=+fast"]);
+    assert_eq!(run.code, Some(1));
+    assert!(run.stderr.contains("unknown pass fast, expected one of inline, fold-branches"), "{}", run.stderr);
+}
+
+#[test]
+fn functions_computing_only_from_their_arguments_are_pure() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "fn square(x: int) -> int {\n    return x * x;\n}\n\nfn shout(x: int) -> int {\n    print(x);\n    return x;\n}\n\nfn main() {\n    print(square(3) + shout(2));\n}\n",
+    );
+    let run: Run = zc(dir.path(), &["-q", "compile", "--release", "--emit", "ir", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.contains("pure fn zN4main6squareE(x: int) -> int\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("\nfn zN4main5shoutE(x: int) -> int\n"), "{}", run.stdout);
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "--release", "--emit", "c", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.contains("__attribute__((const)) int64_t zN4main6squareE(int64_t x);\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("\nint64_t zN4main5shoutE(int64_t x);\n"), "{}", run.stdout);
+
+    // Checked arithmetic may panic, so debug builds have no pure functions
+    let run: Run = zc(dir.path(), &["-q", "compile", "--emit", "c", "main.z", "-o", "-"]).into();
+    assert!(run.stdout.contains("\nint64_t zN4main6squareE(int64_t x);\n"), "{}", run.stdout);
+}
+
+#[test]
+fn functions_converting_floats_are_not_pure_and_frames_do_not_count() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "@optimize(0)\nfn same(x: int) -> int {\n    return x;\n}\n\nfn whole(x: float) -> int {\n    return x as int;\n}\n\nfn main() {\n    print(same(3) + whole(2.5));\n}\n",
+    );
+    // Debug builds enter a frame in each function, which only the stack
+    // trace of a panic shows
+    let run: Run = zc(dir.path(), &["-q", "compile", "--emit", "ir", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.contains("pure fn zN4main4sameE(x: int) -> int\n"), "{}", run.stdout);
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "--release", "--emit", "ir", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.contains("pure fn zN4main4sameE(x: int) -> int\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("\nfn zN4main5wholeE(x: float) -> int\n"), "{}", run.stdout);
+}
+
+#[test]
+fn values_and_calls_of_pure_functions_are_computed_once() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "fn larger(a: int, b: int) -> int {\n    if a > b {\n        return a;\n    }\n    return b;\n}\n\nfn shout(x: int) -> int {\n    print(x);\n    return x;\n}\n\nfn main() {\n    let y = 3;\n    print(larger(y, 4) + larger(y, 4));\n    print(shout(y) + shout(y));\n    print(y * 2 + y * 2);\n    y = 5;\n    print(larger(y, 4));\n}\n",
+    );
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O", "1", "--opt-report", "--emit", "c", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stderr.contains("main.z:15:26: cse: 1 value reused in main\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("main.z:17:19: cse: 1 value reused in main\n"), "{}", run.stderr);
+    assert!(run.stdout.contains("__attribute__((const)) int64_t zN4main6largerE("), "{}", run.stdout);
+    // Once for each value of `y`, while printing happens twice
+    assert_eq!(run.stdout.matches("zN4main6largerE(y, 4LL);").count(), 2, "{}", run.stdout);
+    assert_eq!(run.stdout.matches("zN4main5shoutE(y);").count(), 2, "{}", run.stdout);
+
+    let run = common::run_with(&std::fs::read_to_string(dir.path().join("main.z")).unwrap(), &["-O", "1"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "8\n3\n3\n6\n12\n5\n");
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O", "0", "--opt-report", "--emit", "c", "main.z", "-o", "-"]).into();
+    assert!(!run.stderr.contains("cse"), "{}", run.stderr);
+    assert_eq!(run.stdout.matches("zN4main6largerE(y, 4LL);").count(), 3, "{}", run.stdout);
+}
+
+#[test]
+fn strength_reduction_keeps_the_results_of_negative_values() {
+    let source = "fn main() {\n    let n = 0 - 7;\n    print(n / 2);\n    print(n * 4);\n    print(n % 2);\n    print(n % 2 == 0);\n}\n";
+    let run = common::run_with(source, &["-O", "2", "--release"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "-3\n-28\n-1\nfalse\n");
+
+    let dir = TempDir::new();
+    dir.write("main.z", source);
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O", "2", "--release", "--emit", "ir", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    // Division rounds towards zero, so a negative value is adjusted before
+    // the shift, and a remainder other than compared with zero is kept
+    for line in [" = n >> 63\n", " >> 1\n", " = n << 2\n", " = n % 2\n", " = n & 1\n"] {
+        assert!(run.stdout.contains(line), "{}", run.stdout);
+    }
+}
+
+#[test]
+fn functions_can_be_optimized_at_levels_of_their_own() {
+    let dir = TempDir::new();
+    dir.write(
+        "main.z",
+        "fn square(x: int) -> int {\n    return x * x;\n}\n\n@optimize(0)\nfn plain(x: int) -> int {\n    return square(x) + 1;\n}\n\n@cold\nfn rare(x: int) -> int {\n    return x - 1;\n}\n\nfn main() {\n    print(plain(3) + square(2) + rare(1));\n}\n",
+    );
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O", "2", "--emit", "c", "main.z", "-o", "-"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    for line in [
+        "Z_OPTIMIZE(0) int64_t zN4main5plainE(int64_t x);\n",
+        "__attribute__((cold)) int64_t zN4main4rareE(int64_t x);\n",
+        // An unoptimized function inlines none of its calls
+        " = zN4main6squareE(x);\n",
+    ] {
+        assert!(run.stdout.contains(line), "{}", run.stdout);
+    }
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "-O", "2", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let output = std::process::Command::new(dir.path().join("main")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "14\n");
+}
+
+#[test]
+fn integer_literals_beyond_i64_stay_unsigned() {
+    let run = common::run_with("fn main() {\n    printf(\"{} {}\\n\", 18446744073709551615, -5);\n}\n", &["--release"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "18446744073709551615 -5\n");
+}
@@ -0,0 +1,72 @@
+#![cfg(all(feature = "cli", feature = "jit"))]
+
+mod common;
+
+use common::run_with;
+
+#[test]
+fn debug_runs_go_through_the_jit_with_their_checks() {
+    let run = run_with(
+        "fn grow(x: i8) -> i8 {\n    return x * 2;\n}\n\nfn main() {\n    let x: i8 = 100;\n    print(grow(50));\n    print(grow(x));\n}\n",
+        &["--timings"],
+    );
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "100\n");
+    assert!(
+        run.stderr.starts_with("panic: integer overflow at main.z:2\nstack backtrace:\n    grow at main.z:2\n    main at main.z:8\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(run.stderr.contains("\njit "), "{}", run.stderr);
+    assert!(!run.stderr.contains("\ncc "), "{}", run.stderr);
+}
+
+#[test]
+fn programs_the_jit_does_not_cover_run_through_c() {
+    let run = run_with("fn main() {\n    let xs = [1, 2];\n    print(xs[0] / (xs[1] - 2));\n}\n", &["--timings"]);
+    assert_eq!(run.code, Some(1));
+    assert!(run.stderr.starts_with("panic: division by zero at main.z:3\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("\ncc "), "{}", run.stderr);
+}
+
+#[test]
+fn the_jit_prints_floats_as_the_c_backend_does() {
+    let run = run_with("fn main() {\n    print(123456789.0);\n    print(0.1 + 0.2);\n    print(0.1);\n}\n", &["--timings"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "123456789\n0.30000000000000004\n0.1\n");
+    assert!(run.stderr.contains("\njit "), "{}", run.stderr);
+}
+
+#[test]
+fn the_jit_runs_the_optimized_ir() {
+    let run = run_with(
+        "fn main() {\n    let total = 0;\n    for i in 0..4 {\n        total = total + i * i;\n    }\n    let x: u8 = 200;\n    print(total);\n    print(x + 100 as u8);\n}\n",
+        &["-O", "2", "--release", "--opt-report", "--timings"],
+    );
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "14\n44\n");
+    assert!(run.stderr.contains("main.z:3:5: unroll: 1 loop unrolled in main"), "{}", run.stderr);
+    assert!(run.stderr.contains("\njit "), "{}", run.stderr);
+    assert!(!run.stderr.contains("\ncc "), "{}", run.stderr);
+}
+
+#[test]
+fn deep_recursion_in_the_jit_panics_instead_of_ending_zc() {
+    let source = "fn down(n: int) -> int {\n    if n == 0 {\n        return 0;\n    }\n    return down(n - 1) + 1;\n}\n\nfn main() {\n    print(1);\n    print(down(100000000));\n    print(2);\n}\n";
+    for args in [&["--timings"][..], &["--timings", "--release"]] {
+        let run = run_with(source, args);
+        assert_eq!(run.code, Some(1), "{}", run.stderr);
+        assert_eq!(run.stdout, "1\n");
+        assert!(run.stderr.starts_with("panic: stack overflow at main.z:1\n"), "{}", run.stderr);
+        assert!(run.stderr.contains("\njit "), "{}", run.stderr);
+        assert!(run.stderr.contains("\ntotal "), "{}", run.stderr);
+    }
+}
+
+#[test]
+fn release_division_by_zero_in_the_jit_panics() {
+    let run = run_with("fn main() {\n    let x = 0;\n    print(1);\n    print(10 / x);\n}\n", &["--release"]);
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "1\n");
+    assert_eq!(run.stderr, "panic: division by zero at main.z:4\n");
+}
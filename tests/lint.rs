@@ -0,0 +1,43 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+
+const SOURCE: &str = "fn badName() -> int {\n    let x = 1;\n    if true {\n        let x = 2;\n        print(x);\n    }\n    return x;\n}\n\nfn main() {\n    print(badName());\n}\n";
+
+/// The first line of each diagnostic in `stderr`
+fn headlines(stderr: &str) -> Vec<&str> {
+    stderr.lines().filter(|line| line.starts_with("error[") || line.starts_with("warning[")).collect()
+}
+
+#[test]
+fn lint_rules_warn_by_default_and_follow_the_manifest() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let run: Run = zc(dir.path(), &["lint", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "warning[L0002]: Function badName should be in snake_case, as bad_name",
+            "warning[L0004]: This if condition is always true",
+            "warning[L0001]: x shadows a variable of an enclosing scope; give one of them another name",
+        ]
+    );
+
+    dir.write(
+        "z.toml",
+        "[package]\nname = \"app\"\nentry = \"main.z\"\nsource-dirs = [\".\"]\n\n[lint]\nnaming = \"deny\"\nshadowing = \"allow\"\n\
+         constant-conditions = \"allow\"\nlong-functions = \"warn\"\nmax-function-lines = 4\n",
+    );
+    let run: Run = zc(dir.path(), &["lint"]).into();
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[L0002]: Function badName should be in snake_case, as bad_name",
+            "warning[L0003]: Function badName is 8 lines long, more than the 4 allowed; split it into smaller functions",
+        ]
+    );
+}
@@ -0,0 +1,95 @@
+#![cfg(all(feature = "cli", feature = "llvm"))]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+use std::process::Command;
+
+/// Compiles `source` with `zc --timings compile` and the extra `args`
+fn compile(dir: &TempDir, source: &str, args: &[&str]) -> Run {
+    dir.write("main.z", source);
+    let mut all = vec!["--timings", "compile", "main.z"];
+    all.extend_from_slice(args);
+    zc(dir.path(), &all).into()
+}
+
+#[test]
+fn object_files_need_no_c_compiler() {
+    let dir = TempDir::new();
+    let built = compile(
+        &dir,
+        "fn main() {\n    print(42);\n}\n",
+        &["--emit", "obj", "-o", "main.o", "--cc", "no-such-cc"],
+    );
+    assert_eq!(built.code, Some(0), "{}", built.stderr);
+    assert!(built.stderr.contains("\nllvm "), "{}", built.stderr);
+    assert!(std::fs::metadata(dir.path().join("main.o")).unwrap().len() > 0);
+}
+
+#[test]
+fn object_files_build_with_no_c_compiler_on_the_path() {
+    let dir = TempDir::new();
+    dir.write("main.z", "fn main() {\n    print(42);\n}\n");
+    std::fs::create_dir(dir.path().join("bin")).unwrap();
+    let built: Run = Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(["compile", "main.z", "--emit", "obj", "-o", "main.o"])
+        .current_dir(dir.path())
+        .env("PATH", dir.path().join("bin"))
+        .env_remove("ZC_CC")
+        .env_remove("CC")
+        .output()
+        .unwrap()
+        .into();
+    assert_eq!(built.code, Some(0), "{}", built.stderr);
+    assert!(std::fs::metadata(dir.path().join("main.o")).unwrap().len() > 0);
+}
+
+#[test]
+fn executables_from_llvm_print_and_panic_as_the_c_backend_does() {
+    let dir = TempDir::new();
+    let built = compile(
+        &dir,
+        "fn grow(x: i8) -> i8 {\n    return x * 2;\n}\n\nfn main() {\n    let x: i8 = 100;\n    print(0.1 + 0.2);\n    printf(\"{} {}\\n\", 18446744073709551615, -5);\n    print(grow(50));\n    print(grow(x));\n}\n",
+        &["-o", "main"],
+    );
+    assert_eq!(built.code, Some(0), "{}", built.stderr);
+    assert!(built.stderr.contains("\nllvm "), "{}", built.stderr);
+    assert!(!built.stderr.contains("\ncc "), "{}", built.stderr);
+
+    let run: Run = Command::new(dir.path().join("main")).output().unwrap().into();
+    assert_eq!(run.code, Some(1));
+    assert_eq!(run.stdout, "0.30000000000000004\n18446744073709551615 -5\n100\n");
+    assert_eq!(
+        run.stderr,
+        "panic: integer overflow at main.z:2\nstack backtrace:\n    grow at main.z:2\n    main at main.z:10\n"
+    );
+}
+
+#[test]
+fn programs_llvm_does_not_cover_compile_through_c() {
+    let dir = TempDir::new();
+    let built = compile(&dir, "fn main() {\n    let xs = [1, 2];\n    print(xs[1]);\n}\n", &["-o", "main"]);
+    assert_eq!(built.code, Some(0), "{}", built.stderr);
+    assert!(built.stderr.contains("\ncc "), "{}", built.stderr);
+
+    let run: Run = Command::new(dir.path().join("main")).output().unwrap().into();
+    assert_eq!(run.stdout, "2\n");
+}
+
+#[test]
+fn llvm_compiles_the_optimized_ir() {
+    let dir = TempDir::new();
+    let built = compile(
+        &dir,
+        "fn main() {\n    let total = 0;\n    for i in 0..4 {\n        total = total + i * i;\n    }\n    let x: u8 = 200;\n    print(total);\n    print(x + 100 as u8);\n}\n",
+        &["-O", "2", "--release", "--opt-report", "-o", "main"],
+    );
+    assert_eq!(built.code, Some(0), "{}", built.stderr);
+    assert!(built.stderr.contains("main.z:3:5: unroll: 1 loop unrolled in main"), "{}", built.stderr);
+    assert!(built.stderr.contains("\nllvm "), "{}", built.stderr);
+    assert!(!built.stderr.contains("\ncc "), "{}", built.stderr);
+
+    let run: Run = Command::new(dir.path().join("main")).output().unwrap().into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "14\n44\n");
+}
@@ -0,0 +1,103 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+use std::process::Command;
+
+fn project(dependency: &str) -> TempDir {
+    let dir = TempDir::new();
+    dir.write(
+        "app/z.toml",
+        &format!("[package]\nname = \"app\"\n\n[dependencies]\n{}\n", dependency),
+    );
+    dir.write("app/src/main.z", "import shapes.square;\n\nfn main() {\n    print(square(3));\n}\n");
+    dir
+}
+
+#[test]
+fn git_urls_starting_with_a_dash_are_rejected() {
+    let dir = project("shapes = { git = \"--upload-pack=touch pwned\" }");
+    let run: Run = zc(&dir.path().join("app"), &["-q", "build"]).into();
+    assert_ne!(run.code, Some(0));
+    assert!(run.stderr.contains("cannot start with -"), "{}", run.stderr);
+    assert!(!dir.path().join("app/pwned").exists());
+}
+
+#[test]
+fn git_revs_starting_with_a_dash_are_rejected() {
+    let dir = project("shapes = { git = \"https://example.com/shapes.git\", rev = \"--output=x\" }");
+    let run: Run = zc(&dir.path().join("app"), &["-q", "build"]).into();
+    assert_ne!(run.code, Some(0));
+    assert!(run.stderr.contains("cannot start with -"), "{}", run.stderr);
+}
+
+#[test]
+fn git_dependencies_are_cloned_and_built() {
+    let dir = TempDir::new();
+    let repo = dir.path().join("shapes");
+    dir.write("shapes/square.z", "pub fn square(x: int) -> int {\n    return x * x;\n}\n");
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&repo)
+            .status()
+            .expect("git runs");
+        assert!(status.success());
+    };
+    git(&["init", "--quiet"]);
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "square"]);
+
+    let app = project(&format!("shapes = {{ git = \"{}\" }}", repo.display()));
+    let run: Run = zc(&app.path().join("app"), &["-q", "build"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let output = Command::new(app.path().join("app/build/app")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "9\n");
+}
+
+#[test]
+fn projects_are_built_as_their_manifest_says() {
+    let dir = TempDir::new();
+    dir.write(
+        "app/z.toml",
+        "[package]\nname = \"app\"\nentry = \"code/main.z\"\nsource-dirs = [\"code\", \"lib\"]\noutput-dir = \"out\"\nopt-level = 2\n\n\
+         [dependencies]\ngeometry = { path = \"../geometry\" }\n",
+    );
+    dir.write("app/code/main.z", "import util;\nimport geometry.shapes;\n\nfn main() {\n    print(twice(area(3)));\n}\n");
+    dir.write("app/lib/util.z", "pub fn twice(x: int) -> int {\n    return x * 2;\n}\n");
+    dir.write("geometry/shapes.z", "pub fn area(side: int) -> int {\n    return side * side;\n}\n");
+
+    // From a directory below the manifest
+    let run: Run = zc(&dir.path().join("app/lib"), &["-q", "build"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let output = Command::new(dir.path().join("app/out/app")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "18\n");
+}
+
+#[test]
+fn new_and_init_create_projects_that_build() {
+    let dir = TempDir::new();
+    let run: Run = zc(dir.path(), &["new", "hello"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let hello = dir.path().join("hello");
+    let read = |path: &str| std::fs::read_to_string(hello.join(path)).expect("the file is created");
+    assert_eq!(read("z.toml"), "[package]\nname = \"hello\"\n");
+    assert_eq!(read(".gitignore"), "/build/\n.zc-cache/\n");
+    let run: Run = zc(&hello, &["-q", "build"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let output = Command::new(hello.join("build/hello")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, world!\n");
+
+    let run: Run = zc(dir.path(), &["new", "hello"]).into();
+    assert_eq!(run.code, Some(1));
+    assert!(run.stderr.contains("hello exists already"), "{}", run.stderr);
+
+    dir.write("tool/notes.txt", "kept\n");
+    let run: Run = zc(&dir.path().join("tool"), &["init"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let manifest = std::fs::read_to_string(dir.path().join("tool/z.toml")).expect("the manifest is created");
+    assert_eq!(manifest, "[package]\nname = \"tool\"\n");
+    assert!(dir.path().join("tool/src/main.z").exists() && dir.path().join("tool/notes.txt").exists());
+}
@@ -0,0 +1,143 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{Run, TempDir};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+const GREETER: &str = "import std.io;\n\nfn main() {\n    print(\"name?\");\n    let name = read_line();\n    if name != null {\n        print(\"hi \" + name);\n    }\n}\n";
+
+#[test]
+fn programs_use_the_terminal_as_they_run_unless_captured() {
+    let dir = TempDir::new();
+    dir.write("main.z", GREETER);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(["-q", "run", "--no-cache", "main.z"])
+        .current_dir(dir.path())
+        .env("ZC_HOME", dir.path().join(".zc-home"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("zc runs");
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let (sender, receiver) = mpsc::channel();
+    let reader = std::thread::spawn(move || {
+        let mut line = String::new();
+        stdout.read_line(&mut line).expect("the prompt is read");
+        sender.send(line).expect("the test waits for the prompt");
+        let mut rest = String::new();
+        stdout.read_to_string(&mut rest).expect("the output is read");
+        rest
+    });
+    // The prompt comes before the program has its input
+    let prompt = receiver.recv_timeout(Duration::from_secs(60)).expect("the prompt is printed while the program runs");
+    assert_eq!(prompt, "name?\n");
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin.write_all(b"bob\n").expect("the input is written");
+    drop(stdin);
+    assert!(child.wait().expect("zc exits").success());
+    assert_eq!(reader.join().expect("the output is read"), "hi bob\n");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(["-q", "run", "--no-cache", "--capture", "main.z"])
+        .current_dir(dir.path())
+        .env("ZC_HOME", dir.path().join(".zc-home"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("zc runs");
+    child.stdin.take().expect("stdin is piped").write_all(b"ann\n").expect("the input is written");
+    let run: Run = child.wait_with_output().expect("zc exits").into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "name?\nhi ann\n");
+}
+
+#[test]
+fn zc_run_exits_with_the_status_of_the_program() {
+    let run = common::run("extern fn exit(code: i32);\n\nfn main() {\n    print(\"partial\");\n    exit(3);\n}\n");
+    assert_eq!(run.code, Some(3));
+    assert_eq!(run.stdout, "partial\n");
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn arguments_after_two_dashes_go_to_the_program() {
+    let dir = TempDir::new();
+    dir.write("main.z", "fn main() {\n    for arg in args() {\n        print(arg);\n    }\n}\n");
+    let run = |args: &[&str]| -> Run {
+        Command::new(env!("CARGO_BIN_EXE_zc"))
+            .args(["-q", "run", "main.z", "--"])
+            .args(args)
+            .current_dir(dir.path())
+            .env("XDG_CACHE_HOME", dir.path().join("cache"))
+            .env("ZC_HOME", dir.path().join(".zc-home"))
+            .output()
+            .expect("zc runs")
+            .into()
+    };
+    // Options of zc after -- are the program's too
+    let first = run(&["one", "--release", "two words"]);
+    assert_eq!(first.code, Some(0), "{}", first.stderr);
+    assert_eq!(first.stdout, "one\n--release\ntwo words\n");
+
+    // An executable from the cache gets them as well
+    let second = run(&["three"]);
+    assert_eq!(second.stdout, "three\n");
+}
+
+#[test]
+fn the_compilers_own_output_goes_to_stderr_and_only_when_asked() {
+    let dir = TempDir::new();
+    dir.write("main.z", "fn main() {\n    print(1 + 2);\n}\n");
+    for (flag, banner) in [(None, "Running main.z\n"), (Some("--quiet"), ""), (Some("-q"), "")] {
+        let mut args: Vec<&str> = flag.into_iter().collect();
+        args.extend(["run", "--no-cache", "main.z"]);
+        let run: Run = common::zc(dir.path(), &args).into();
+        assert_eq!(run.code, Some(0), "{}", run.stderr);
+        assert_eq!(run.stdout, "3\n");
+        assert_eq!(run.stderr, banner);
+    }
+
+    let run: Run = common::zc(dir.path(), &["--verbose", "run", "--no-cache", "main.z"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "3\n");
+    assert!(run.stderr.contains("Source file: main.z\n") && run.stderr.contains("\ntypecheck "), "{}", run.stderr);
+}
+
+#[test]
+fn unchanged_programs_run_from_the_cache_without_compiling() {
+    let dir = TempDir::new();
+    let cache = dir.path().join("cache");
+    let run = |source: &str| -> Run {
+        dir.write("main.z", source);
+        // Captured, so that it is built through C even with the JIT
+        Command::new(env!("CARGO_BIN_EXE_zc"))
+            .args(["-q", "run", "--capture", "--timings", "main.z"])
+            .current_dir(dir.path())
+            .env("XDG_CACHE_HOME", &cache)
+            .env("ZC_HOME", dir.path().join(".zc-home"))
+            .output()
+            .expect("zc runs")
+            .into()
+    };
+    let entries = || std::fs::read_dir(cache.join("zc")).map_or(0, |entries| entries.count());
+
+    let first = run("fn main() {\n    print(\"one\");\n}\n");
+    assert_eq!(first.code, Some(0), "{}", first.stderr);
+    assert_eq!(first.stdout, "one\n");
+    assert!(first.stderr.contains("\ncc "), "{}", first.stderr);
+    assert_eq!(entries(), 1);
+
+    let again = run("fn main() {\n    print(\"one\");\n}\n");
+    assert_eq!(again.stdout, "one\n");
+    assert!(!again.stderr.contains("\ncodegen ") && !again.stderr.contains("\ncc "), "{}", again.stderr);
+
+    let changed = run("fn main() {\n    print(\"two\");\n}\n");
+    assert_eq!(changed.stdout, "two\n");
+    assert!(changed.stderr.contains("\ncc "), "{}", changed.stderr);
+    assert_eq!(entries(), 2);
+}
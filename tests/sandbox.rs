@@ -0,0 +1,169 @@
+#![cfg(all(feature = "cli", feature = "c", target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+
+mod common;
+
+use common::run_with;
+
+// System calls the sandbox refuses, with arguments that would succeed or
+// fail harmlessly outside it. `PID` stands for the test's process.
+#[cfg(target_arch = "x86_64")]
+const DENIED: &[(&str, i64, [i64; 4])] = &[
+    ("openat2", 437, [-100, 0, 0, 0]),
+    ("creat", 85, [0, 0, 0, 0]),
+    ("truncate", 76, [0, 0, 0, 0]),
+    ("rename", 82, [0, 0, 0, 0]),
+    ("mkdir", 83, [0, 0, 0, 0]),
+    ("rmdir", 84, [0, 0, 0, 0]),
+    ("link", 86, [0, 0, 0, 0]),
+    ("unlink", 87, [0, 0, 0, 0]),
+    ("symlink", 88, [0, 0, 0, 0]),
+    ("chmod", 90, [0, 0, 0, 0]),
+    ("fchmod", 91, [-1, 0, 0, 0]),
+    ("chown", 92, [0, 0, 0, 0]),
+    ("fchown", 93, [-1, 0, 0, 0]),
+    ("lchown", 94, [0, 0, 0, 0]),
+    ("utime", 132, [0, 0, 0, 0]),
+    ("mknod", 133, [0, 0, 0, 0]),
+    ("setxattr", 188, [0, 0, 0, 0]),
+    ("lsetxattr", 189, [0, 0, 0, 0]),
+    ("fsetxattr", 190, [-1, 0, 0, 0]),
+    ("removexattr", 197, [0, 0, 0, 0]),
+    ("lremovexattr", 198, [0, 0, 0, 0]),
+    ("fremovexattr", 199, [-1, 0, 0, 0]),
+    ("utimes", 235, [0, 0, 0, 0]),
+    ("mkdirat", 258, [-100, 0, 0, 0]),
+    ("mknodat", 259, [-100, 0, 0, 0]),
+    ("fchownat", 260, [-100, 0, 0, 0]),
+    ("futimesat", 261, [-100, 0, 0, 0]),
+    ("unlinkat", 263, [-100, 0, 0, 0]),
+    ("renameat", 264, [-100, 0, -100, 0]),
+    ("linkat", 265, [-100, 0, -100, 0]),
+    ("symlinkat", 266, [0, -100, 0, 0]),
+    ("fchmodat", 268, [-100, 0, 0, 0]),
+    ("utimensat", 280, [-100, 0, 0, 0]),
+    ("renameat2", 316, [-100, 0, -100, 0]),
+    ("fchmodat2", 452, [-100, 0, 0, 0]),
+    ("socket", 41, [1, 1, 0, 0]),
+    ("connect", 42, [-1, 0, 0, 0]),
+    ("bind", 49, [-1, 0, 0, 0]),
+    // CLONE_SIGHAND without CLONE_VM is invalid
+    ("clone", 56, [0x800, 0, 0, 0]),
+    ("clone3", 435, [0, 0, 0, 0]),
+    ("kill", 62, [PID, 0, 0, 0]),
+    ("rt_sigqueueinfo", 129, [PID, 0, 0, 0]),
+    ("rt_tgsigqueueinfo", 297, [PID, PID, 0, 0]),
+    ("pidfd_send_signal", 424, [-1, 0, 0, 0]),
+    ("pidfd_open", 434, [PID, 0, 0, 0]),
+    ("pidfd_getfd", 438, [-1, 0, 0, 0]),
+    // Neither sets a limit nor reads one
+    ("prlimit64", 302, [0, 0, 0, 0]),
+    ("setpriority", 141, [0, 0, 0, 0]),
+    ("tkill", 200, [PID, 0, 0, 0]),
+    ("tgkill", 234, [PID, PID, 0, 0]),
+    // An unknown request
+    ("ptrace", 101, [-1, PID, 0, 0]),
+    ("process_vm_readv", 310, [PID, 0, 0, 0]),
+    ("process_vm_writev", 311, [PID, 0, 0, 0]),
+    ("mount", 165, [0, 0, 0, 0]),
+    ("io_uring_setup", 425, [0, 0, 0, 0]),
+];
+
+#[cfg(target_arch = "aarch64")]
+const DENIED: &[(&str, i64, [i64; 4])] = &[
+    ("openat2", 437, [-100, 0, 0, 0]),
+    ("truncate", 45, [0, 0, 0, 0]),
+    ("setxattr", 5, [0, 0, 0, 0]),
+    ("lsetxattr", 6, [0, 0, 0, 0]),
+    ("fsetxattr", 7, [-1, 0, 0, 0]),
+    ("removexattr", 14, [0, 0, 0, 0]),
+    ("lremovexattr", 15, [0, 0, 0, 0]),
+    ("fremovexattr", 16, [-1, 0, 0, 0]),
+    ("mknodat", 33, [-100, 0, 0, 0]),
+    ("mkdirat", 34, [-100, 0, 0, 0]),
+    ("unlinkat", 35, [-100, 0, 0, 0]),
+    ("symlinkat", 36, [0, -100, 0, 0]),
+    ("linkat", 37, [-100, 0, -100, 0]),
+    ("renameat", 38, [-100, 0, -100, 0]),
+    ("fchmod", 52, [-1, 0, 0, 0]),
+    ("fchmodat", 53, [-100, 0, 0, 0]),
+    ("fchownat", 54, [-100, 0, 0, 0]),
+    ("fchown", 55, [-1, 0, 0, 0]),
+    ("utimensat", 88, [-100, 0, 0, 0]),
+    ("renameat2", 276, [-100, 0, -100, 0]),
+    ("fchmodat2", 452, [-100, 0, 0, 0]),
+    ("socket", 198, [1, 1, 0, 0]),
+    ("bind", 200, [-1, 0, 0, 0]),
+    ("connect", 203, [-1, 0, 0, 0]),
+    ("clone", 220, [0x800, 0, 0, 0]),
+    ("clone3", 435, [0, 0, 0, 0]),
+    ("kill", 129, [PID, 0, 0, 0]),
+    ("rt_sigqueueinfo", 138, [PID, 0, 0, 0]),
+    ("rt_tgsigqueueinfo", 240, [PID, PID, 0, 0]),
+    ("pidfd_send_signal", 424, [-1, 0, 0, 0]),
+    ("pidfd_open", 434, [PID, 0, 0, 0]),
+    ("pidfd_getfd", 438, [-1, 0, 0, 0]),
+    ("prlimit64", 261, [0, 0, 0, 0]),
+    ("setpriority", 140, [0, 0, 0, 0]),
+    ("tkill", 130, [PID, 0, 0, 0]),
+    ("tgkill", 131, [PID, PID, 0, 0]),
+    ("ptrace", 117, [-1, PID, 0, 0]),
+    ("process_vm_readv", 270, [PID, 0, 0, 0]),
+    ("process_vm_writev", 271, [PID, 0, 0, 0]),
+    ("mount", 40, [0, 0, 0, 0]),
+    ("io_uring_setup", 425, [0, 0, 0, 0]),
+];
+
+const PID: i64 = i64::MIN;
+
+#[test]
+fn denied_system_calls_fail_with_eperm() {
+    let pid = std::process::id() as i64;
+    let mut source = String::from(
+        "extern fn syscall(nr: i64, a: i64, b: i64, c: i64, d: i64) -> i64;\nextern fn perror(s: string);\n\nfn main() {\n",
+    );
+    for (name, nr, args) in DENIED {
+        let args: Vec<String> =
+            args.iter().map(|&arg| format!("{} as i64", if arg == PID { pid } else { arg })).collect();
+        source.push_str(&format!("    syscall({} as i64, {});\n    perror(\"{}\");\n", nr, args.join(", "), name));
+    }
+    source.push_str("}\n");
+
+    let run = run_with(&source, &["--sandbox"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let expected: Vec<String> = DENIED.iter().map(|(name, ..)| format!("{}: Operation not permitted", name)).collect();
+    assert_eq!(run.stderr.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn sandboxed_programs_can_signal_themselves() {
+    let run = run_with(
+        "extern fn raise(sig: int) -> int;\n\nfn main() {\n    print(raise(0));\n}\n",
+        &["--sandbox"],
+    );
+    assert_eq!(run.stdout, "0\n", "{}", run.stderr);
+}
+
+#[test]
+fn sandboxed_programs_cannot_create_files() {
+    let dir = common::TempDir::new();
+    let target = dir.path().join("created");
+    let source = format!(
+        "extern fn mknod(path: string, mode: int, dev: int) -> int;\n\nfn main() {{\n    print(mknod(\"{}\", 4480, 0));\n}}\n",
+        target.display()
+    );
+    let run = run_with(&source, &["--sandbox"]);
+    assert_eq!(run.stdout, "-1\n", "{}", run.stderr);
+    assert!(!target.exists());
+}
+
+#[test]
+fn sandboxed_programs_cannot_take_the_files_of_other_processes() {
+    // The parent's standard output, through a pidfd of it
+    let source = "extern fn syscall(nr: i64, a: i64, b: i64, c: i64) -> i64;\nextern fn getppid() -> int;\n\nfn main() {\n    let pidfd = syscall(434 as i64, getppid() as i64, 0 as i64, 0 as i64);\n    print(pidfd >= 0 as i64);\n    print(syscall(438 as i64, pidfd, 1 as i64, 0 as i64) >= 0 as i64);\n}\n";
+    let run = run_with(source, &[]);
+    assert!(run.stdout.starts_with("true\n"), "{}", run.stderr);
+
+    let run = run_with(source, &["--sandbox"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "false\nfalse\n");
+}
@@ -0,0 +1,37 @@
+use z_lang::ast::Type;
+use z_lang::{SemanticModel, SymbolKind, Value};
+
+#[test]
+fn expressions_are_evaluated_with_the_variables_in_scope_at_a_location() {
+    let source = "fn main() {\n    let x = 20;\n    let name = \"z\";\n    x = x + 1;\n    print(x);\n}\n";
+    let model = SemanticModel::new(source).expect("the program checks");
+    let location = source.find("print").expect("the program prints");
+    assert_eq!(model.eval_in_scope("x * 2", location).expect("x is in scope"), Value::Int(42));
+    assert_eq!(
+        model.eval_in_scope("name + \"c\"", location).expect("name is in scope"),
+        Value::String("zc".to_string())
+    );
+
+    let before = source.find("let name").expect("name is declared");
+    let error = model.eval_in_scope("name", before).expect_err("name is not declared yet");
+    assert!(error.to_string().contains("name"), "{}", error);
+}
+
+#[test]
+fn names_resolve_to_their_definitions_and_references() {
+    let source = "fn twice(x: int) -> int {\n    return x * 2;\n}\n\nfn main() {\n    let n = twice(1);\n    print(twice(n));\n}\n";
+    let model = SemanticModel::new(source).expect("the program checks");
+    let symbols = model.symbols();
+    let text = |start: usize, end: usize| &source[start..end];
+
+    let call = source.rfind("twice").expect("twice is called");
+    let twice = symbols.symbol_at(call).expect("the call names a symbol");
+    assert_eq!((twice.name.as_str(), twice.kind), ("twice", SymbolKind::Function));
+    assert!(text(twice.definition.start, twice.definition.end).starts_with("fn twice"));
+    let references: Vec<usize> = symbols.references_at(call).iter().map(|span| span.start).collect();
+    assert_eq!(references, [source.find("twice(1)").expect("twice is called"), call]);
+
+    let n = symbols.symbol_at(source.find("(n)").expect("n is used") + 1).expect("n names a symbol");
+    assert_eq!((n.kind, &n.ty), (SymbolKind::Variable, &Type::Int));
+    assert_eq!(text(n.definition.start, n.definition.end), "let n = twice(1);");
+}
@@ -0,0 +1,286 @@
+#![cfg(all(feature = "cli", feature = "c"))]
+
+mod common;
+
+use common::{zc, Run, TempDir};
+use std::process::Command;
+
+const SOURCE: &str = "fn main() {\n    print(6 * 7);\n}\n";
+
+#[test]
+fn programs_are_built_through_c_without_an_llvm_backend() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let run: Run = zc(dir.path(), &["-q", "compile", "--backend", "llvm", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(2));
+    assert!(
+        run.stderr.contains("unknown backend llvm, expected one of gcc, clang, tcc, zig, msvc"),
+        "{}",
+        run.stderr
+    );
+    assert!(!dir.path().join("main").exists());
+}
+
+/// Writes a C compiler to `dir` that logs its arguments to cc.log and runs
+/// cc with them, after the `cc` that `zig cc` starts with, returning its path
+#[cfg(unix)]
+fn logging_cc(dir: &TempDir) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.write(
+        "logging-cc",
+        "#!/bin/sh\necho \"$@\" >> \"$(dirname \"$0\")/cc.log\"\nif [ \"$1\" = cc ]; then shift; fi\nexec cc \"$@\"\n",
+    );
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).expect("the script is executable");
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn the_c_compiler_and_its_flags_come_from_options_or_the_environment() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let cc = logging_cc(&dir);
+    let log = dir.path().join("cc.log");
+
+    let run: Run = Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(["-q", "compile", "main.z", "-o", "main", "--cc"])
+        .arg(&cc)
+        .current_dir(dir.path())
+        .env("ZC_CFLAGS", "-O1 -DZ_TEST")
+        .output()
+        .expect("zc runs")
+        .into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler is called");
+    assert!(calls.lines().all(|call| call.starts_with("-O1 -DZ_TEST ")), "{}", calls);
+    assert!(!calls.contains("-O3"), "{}", calls);
+
+    std::fs::remove_file(&log).expect("the log is removed");
+    let run: Run = Command::new(env!("CARGO_BIN_EXE_zc"))
+        .args(["-q", "compile", "main.z", "-o", "main", "--cflags", "-O2"])
+        .current_dir(dir.path())
+        .env("ZC_CC", &cc)
+        .env("ZC_CFLAGS", "-O1")
+        .output()
+        .expect("zc runs")
+        .into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler is called");
+    assert!(calls.lines().all(|call| call.starts_with("-O2 ")), "{}", calls);
+    let output = Command::new(dir.path().join("main")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn each_backend_gets_the_flags_it_understands() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    let cc = logging_cc(&dir);
+    let cc = cc.to_str().expect("the path is UTF-8");
+    let log = dir.path().join("cc.log");
+
+    let run: Run = zc(dir.path(), &["-q", "compile", "--backend", "tcc", "--cc", cc, "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler is called");
+    assert!(!calls.contains("-O3") && !calls.contains("-ffile-prefix-map"), "{}", calls);
+
+    std::fs::remove_file(&log).expect("the log is removed");
+    let run: Run = zc(dir.path(), &["-q", "compile", "--backend", "zig", "--cc", cc, "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler is called");
+    assert!(calls.lines().all(|call| call.starts_with("cc -O3 ")), "{}", calls);
+
+    for (option, message) in [
+        ("--native-cpu", "--native-cpu and --lto are not supported by the tcc backend"),
+        ("--target=aarch64-linux-gnu", "The tcc backend cannot build for aarch64-linux-gnu"),
+    ] {
+        let run: Run = zc(dir.path(), &["-q", "compile", "--backend", "tcc", "--cc", cc, option, "main.z"]).into();
+        assert_eq!(run.code, Some(1));
+        assert!(run.stderr.contains(message), "{}", run.stderr);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn static_and_shared_linking_and_libraries_reach_the_link() {
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    dir.write("lib.z", "pub fn twice(x: int) -> int {\n    return x * 2;\n}\n");
+    let cc = logging_cc(&dir);
+    let cc = cc.to_str().expect("the path is UTF-8");
+    let log = dir.path().join("cc.log");
+    let libraries = dir.path().join("libs");
+    let libraries = libraries.to_str().expect("the path is UTF-8");
+
+    let run: Run =
+        zc(dir.path(), &["-q", "compile", "--static", "-L", libraries, "-lm", "--cc", cc, "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler is called");
+    let link = calls.lines().last().expect("the program is linked");
+    assert!(link.contains(&format!(" -static -L {} ", libraries)) && link.ends_with(" -lm"), "{}", calls);
+    let output = Command::new(dir.path().join("main")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+
+    std::fs::remove_file(&log).expect("the log is removed");
+    let args = ["-q", "compile", "--shared", "--crate-type", "lib", "--cc", cc, "lib.z", "-o", "libtwice.so"];
+    let run: Run = zc(dir.path(), &args).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler is called");
+    assert!(calls.contains(" -fPIC -c ") && calls.contains(" -shared "), "{}", calls);
+    assert!(dir.path().join("libtwice.so").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn the_boehm_collector_allocates_and_is_linked_with_gc_boehm() {
+    let dir = TempDir::new();
+    dir.write("main.z", "fn main() {\n    let words = [\"a\" + 1, \"b\" + 2];\n    print(words[0] + words[1]);\n}\n");
+    // A stand-in for libgc, counting the allocations made through it
+    dir.write(
+        "gc.h",
+        "#include <stdio.h>\n#include <stdlib.h>\nstatic long gc_allocations;\n\
+         static void gc_report(void) { fprintf(stderr, \"allocated %ld\\n\", gc_allocations); }\n\
+         #define GC_INIT() atexit(gc_report)\n#define GC_MALLOC(size) (gc_allocations++, calloc(1, (size)))\n",
+    );
+    dir.write("gc.c", "int gc_stub;\n");
+    let built = Command::new("cc").args(["-c", "gc.c", "-o", "gc.o"]).current_dir(dir.path()).status();
+    assert!(built.expect("cc runs").success());
+    let archived = Command::new("ar").args(["rcs", "libgc.a", "gc.o"]).current_dir(dir.path()).status();
+    assert!(archived.expect("ar runs").success());
+    let cc = logging_cc(&dir);
+    let cc = cc.to_str().expect("the path is UTF-8");
+
+    let args = ["-q", "compile", "--gc", "boehm", "--cflags", "-I.", "-L", ".", "--cc", cc, "main.z", "-o", "main"];
+    let run: Run = zc(dir.path(), &args).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(dir.path().join("cc.log")).expect("the compiler is called");
+    assert!(calls.lines().last().is_some_and(|link| link.contains(" -lgc")), "{}", calls);
+    let output = Command::new(dir.path().join("main")).output().expect("the program runs");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a1b2\n");
+    // The text of each number, the two words, the array and the words joined
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "allocated 6\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn builds_are_byte_identical_and_tuned_to_the_machine_only_when_asked() {
+    let first = TempDir::new();
+    let second = TempDir::new();
+    let mut programs = Vec::new();
+    for dir in [&first, &second] {
+        dir.write("main.z", SOURCE);
+        let cc = logging_cc(dir);
+        let cc = cc.to_str().expect("the path is UTF-8");
+        let run: Run = zc(dir.path(), &["-q", "compile", "--cc", cc, "main.z", "-o", "main"]).into();
+        assert_eq!(run.code, Some(0), "{}", run.stderr);
+        let calls = std::fs::read_to_string(dir.path().join("cc.log")).expect("the compiler is called");
+        assert!(!calls.contains("-march=native") && !calls.contains("-flto"), "{}", calls);
+        programs.push(std::fs::read(dir.path().join("main")).expect("the program is built"));
+    }
+    assert!(programs[0] == programs[1], "the builds differ");
+
+    let cc = logging_cc(&first);
+    let cc = cc.to_str().expect("the path is UTF-8");
+    let args = ["-q", "compile", "--native-cpu", "--lto", "--cc", cc, "main.z", "-o", "tuned"];
+    let run: Run = zc(first.path(), &args).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(first.path().join("cc.log")).expect("the compiler is called");
+    let tuned = calls.lines().last().expect("the program is linked");
+    assert!(tuned.contains("-march=native") && tuned.contains("-flto"), "{}", calls);
+}
+
+#[cfg(unix)]
+#[test]
+fn the_msvc_backend_gets_flags_in_the_style_of_cl() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = TempDir::new();
+    dir.write("main.z", SOURCE);
+    // A stand-in for clang-cl, logging its arguments and writing empty
+    // outputs where /Fo and /Fe name them
+    let cl = dir.write(
+        "fake-cl",
+        "#!/bin/sh\necho \"$@\" >> \"$(dirname \"$0\")/cl.log\"\n\
+         for arg in \"$@\"; do\n  case \"$arg\" in\n    /Fo*|/Fe*) : > \"${arg#/F?}\" ;;\n  esac\ndone\n",
+    );
+    std::fs::set_permissions(&cl, std::fs::Permissions::from_mode(0o755)).expect("the script is executable");
+    let cl = cl.to_str().expect("the path is UTF-8");
+
+    let args = ["-q", "compile", "--backend", "msvc", "--cc", cl, "-L", "libs", "-lsqlite3", "main.z", "-o", "main.exe"];
+    let run: Run = zc(dir.path(), &args).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(dir.path().join("cl.log")).expect("the compiler is called");
+    let calls: Vec<&str> = calls.lines().collect();
+    // With the llvm feature, LLVM generates the object file and cl only links it
+    let compiled = usize::from(!cfg!(feature = "llvm"));
+    assert_eq!(calls.len(), compiled + 1, "{:?}", calls);
+    if compiled == 1 {
+        assert!(calls[0].starts_with("/nologo /O2 /c ") && calls[0].contains(".obj"), "{:?}", calls);
+    }
+    assert!(calls[compiled].contains(".obj /Femain.exe sqlite3.lib /link /LIBPATH:libs"), "{:?}", calls);
+    assert!(calls.iter().all(|call| !call.contains("-lm") && !call.contains("-o ")), "{:?}", calls);
+}
+
+#[cfg(unix)]
+#[test]
+fn settings_come_from_the_nearest_zc_toml_unless_flags_override_them() {
+    let dir = TempDir::new();
+    let cc = logging_cc(&dir);
+    let cc = cc.to_str().expect("the path is UTF-8");
+    dir.write("zc.toml", &format!("cc = \"{}\"\ncflags = \"-O1 -DZ_CONFIG\"\n\n[warnings]\nW0001 = \"deny\"\n", cc));
+    dir.write("app/main.z", SOURCE);
+    let app = dir.path().join("app");
+    let log = dir.path().join("cc.log");
+
+    let run: Run = zc(&app, &["-q", "compile", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler from zc.toml is called");
+    assert!(calls.lines().all(|call| call.starts_with("-O1 -DZ_CONFIG ")), "{}", calls);
+
+    std::fs::remove_file(&log).expect("the log is removed");
+    let run: Run = zc(&app, &["-q", "compile", "--cflags", "-O2", "main.z", "-o", "main"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let calls = std::fs::read_to_string(&log).expect("the compiler from zc.toml is called");
+    assert!(calls.lines().all(|call| call.starts_with("-O2 ")), "{}", calls);
+
+    dir.write("app/unused.z", "fn main() {\n    let x = 1;\n}\n");
+    let run: Run = zc(&app, &["-q", "compile", "unused.z", "-o", "unused"]).into();
+    assert_eq!(run.code, Some(1));
+    assert!(run.stderr.contains("error[W0001]: Unused variable: x"), "{}", run.stderr);
+}
+
+#[test]
+fn targets_and_env_describe_the_toolchain_and_where_settings_come_from() {
+    let dir = TempDir::new();
+    let run: Run = zc(dir.path(), &["targets"]).into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let targets: Vec<&str> = run.stdout.lines().filter_map(|line| line.split_whitespace().next()).collect();
+    for target in ["x86_64-linux-gnu", "aarch64-linux-gnu", "x86_64-windows-msvc", "wasm32-wasi"] {
+        assert!(targets.contains(&target), "{}", run.stdout);
+    }
+
+    dir.write("zc.toml", "opt-level = 1\n");
+    let run: Run = Command::new(env!("CARGO_BIN_EXE_zc"))
+        .arg("env")
+        .current_dir(dir.path())
+        .env("ZC_HOME", dir.path().join("home"))
+        .env("XDG_CACHE_HOME", dir.path().join("cache"))
+        .env_remove("ZC_CC")
+        .env_remove("ZC_CFLAGS")
+        .output()
+        .expect("zc runs")
+        .into();
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stdout.starts_with(concat!("zc ", env!("CARGO_PKG_VERSION"), "\nhost: ")), "{}", run.stdout);
+    for section in ["\nC compilers:\n", "\nRuntime features:\n", "  gc rc ", "  address sanitizer "] {
+        assert!(run.stdout.contains(section), "{}", run.stdout);
+    }
+    let path = |name: &str| dir.path().join(name).display().to_string();
+    for line in [
+        format!("\nConfiguration:\n  {}\n", path("zc.toml")),
+        format!("\n  runs      {}\n", dir.path().join("cache").join("zc").display()),
+        format!("\n  packages  {}\n", path("home")),
+    ] {
+        assert!(run.stdout.contains(&line), "{}", run.stdout);
+    }
+}
@@ -0,0 +1,242 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{check, check_with};
+
+/// The lines of `stderr` that start an error or warning
+fn headlines(stderr: &str) -> Vec<&str> {
+    stderr.lines().filter(|line| line.starts_with("error[") || line.starts_with("warning[")).collect()
+}
+
+#[test]
+fn calls_are_checked_against_the_signature_of_the_function() {
+    let run = check(
+        "fn add(a: int, b: int) -> int {\n    return a + b;\n}\n\nfn main() {\n    let f = add;\n    print(add(1));\n    print(add(1, true));\n    print(f(1, 2, 3));\n    let s: string = add(1, 2);\n}\n",
+    );
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0202]: Function add expects 2 arguments, found 1",
+            "error[E0203]: Argument 2 of add has type bool, expected int",
+            "error[E0202]: Function f expects 2 arguments, found 3",
+            "error[E0201]: Type mismatch: expected string, found int",
+        ]
+    );
+    assert!(run.stderr.contains(" --> main.z:8:18\n"), "{}", run.stderr);
+}
+
+#[test]
+fn ints_and_floats_mix_only_through_casts() {
+    let run = check("fn main() {\n    let x = 1 + 2.5;\n    let y = 1 as float + 2.5;\n    let s = \"a\" as int;\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0205]: Cannot mix int and float operands; convert one side explicitly with `as`",
+            "error[E0206]: Cannot cast string to int",
+        ]
+    );
+}
+
+#[test]
+fn unused_variables_and_functions_are_warned_about_unless_prefixed_with_an_underscore() {
+    let run = check("fn helper() -> int {\n    return 1;\n}\n\nfn _kept() {}\n\nfn main() {\n    let unused = 2;\n    let _ignored = 3;\n    let used = 4;\n    print(used);\n}\n");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "warning[W0002]: Function helper is never called",
+            "warning[W0001]: Unused variable: unused (prefix it with an underscore to silence this warning)",
+        ]
+    );
+    assert!(run.stderr.contains(" --> main.z:8:5\n"), "{}", run.stderr);
+
+    // A loop variable is pointed at by its name, not the whole loop
+    let run = check("fn main() {\n    for item in 0..3 {\n        print(1);\n    }\n}\n");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(run.stderr.contains(" --> main.z:2:9\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("\n  |         ^^^^\n"), "{}", run.stderr);
+}
+
+#[test]
+fn structs_that_contain_themselves_by_value_are_rejected() {
+    let run = check("struct Node {\n    value: int,\n    next: Node,\n}\n\nstruct A {\n    b: B,\n}\n\nstruct B {\n    a: A,\n}\n\nstruct List {\n    items: [List],\n    last: List?,\n}\n\nfn main() {}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0220]: Struct Node contains itself by value (Node.next -> Node) and would be infinitely large; store the value in an array such as `[Node]` instead",
+            "error[E0220]: Struct A contains itself by value (A.b -> B.a -> A) and would be infinitely large; store the value in an array such as `[A]` instead",
+        ]
+    );
+}
+
+#[test]
+fn constant_expressions_are_evaluated_while_checking() {
+    let run = check("const SIZE = 4 * 8;\nconst HALF: i8 = SIZE / 2;\n\nfn main() {\n    let x: i8 = 100 + 100;\n    let y = 1 / (SIZE - 32);\n    let n = 3;\n    const LATER = n + 1;\n    print(HALF + x + y);\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0213]: Integer literal 200 is out of range for i8 (-128 to 127)",
+            "error[E0224]: Division by zero in constant expression",
+            "error[E0221]: The value of constant LATER must be computable at compile time",
+        ]
+    );
+}
+
+#[test]
+fn enum_variants_are_checked_against_their_payloads() {
+    let run = check("enum Shape {\n    Circle(float),\n    Empty,\n}\n\nfn main() {\n    let a = Shape.Circle(1);\n    let b = Shape.Circle(1.0, 2.0);\n    let c = Shape.Square;\n    let d: int = Shape.Empty;\n    match Shape.Empty {\n        Shape.Circle(r) => print(r + 1),\n        Shape.Empty => print(0),\n    }\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0203]: Value 1 of Shape.Circle has type int, expected float",
+            "error[E0202]: Variant Shape.Circle expects 1 value, found 2",
+            "error[E0107]: Enum Shape has no variant Square",
+            "error[E0201]: Type mismatch: expected int, found Shape",
+            // The binding r has the type of the payload
+            "error[E0205]: Cannot mix float and int operands; convert one side explicitly with `as`",
+        ]
+    );
+}
+
+#[test]
+fn integer_literals_out_of_range_of_their_type_are_rejected() {
+    let run = check("fn main() {\n    print(99999999999999999999);\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        ["error[E0007]: Integer literal 99999999999999999999 is out of range; no integer type goes beyond 18446744073709551615"]
+    );
+
+    let run = check("fn main() {\n    let a: u8 = 256;\n    let b: i8 = -129;\n    let c: u32 = -1;\n    let d: i8 = -128;\n    print(a);\n    print(b);\n    print(c);\n    print(d);\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0213]: Integer literal 256 is out of range for u8 (0 to 255)",
+            "error[E0213]: Integer literal -129 is out of range for i8 (-128 to 127)",
+            "error[E0213]: Integer literal -1 is out of range for u32 (0 to 4294967295)",
+        ]
+    );
+}
+
+#[test]
+fn integer_literals_reach_both_ends_of_i64_and_u64() {
+    let run = check("fn main() {\n    let a: u64 = 18446744073709551615;\n    let b: u64 = 0;\n    let c: i64 = 9223372036854775807;\n    let d: i64 = -9223372036854775808;\n    let e = 18446744073709551615;\n    print(a + b + e);\n    print(c + d);\n}\n");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+
+    let run = check("fn main() {\n    let a: u64 = 18446744073709551616;\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        ["error[E0007]: Integer literal 18446744073709551616 is out of range; no integer type goes beyond 18446744073709551615"]
+    );
+
+    let run = check("fn main() {\n    let a: u64 = -1;\n    let b: i64 = 9223372036854775808;\n    let c: i64 = -9223372036854775809;\n    let d: int = 18446744073709551615;\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0213]: Integer literal -1 is out of range for u64 (0 to 18446744073709551615)",
+            "error[E0213]: Integer literal 9223372036854775808 is out of range for i64 (-9223372036854775808 to 9223372036854775807)",
+            "error[E0213]: Integer literal -9223372036854775809 is out of range for i64 (-9223372036854775808 to 9223372036854775807)",
+            "error[E0213]: Integer literal 18446744073709551615 is out of range for int (-9223372036854775808 to 9223372036854775807)",
+        ]
+    );
+}
+
+#[test]
+fn strict_memory_rejects_uses_of_moved_strings_arrays_and_structs() {
+    let source = "struct P {\n    name: string,\n}\n\nfn take(xs: [int]) -> int {\n    return len(xs);\n}\n\nfn main() {\n    let xs = [1, 2];\n    let ys = xs;\n    print(xs[0]);\n    let p: P;\n    let _q = p;\n    print(p.name);\n    let s = \"a\";\n    let t = s;\n    s = \"b\";\n    print(s + t);\n    let n = 1;\n    let m = n;\n    print(n + m + take(ys));\n}\n";
+    let run = check(source);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+
+    let run = check_with(source, &["--strict-memory"]);
+    assert_eq!(run.code, Some(1));
+    // Assigning s again makes it usable, and numbers are copied
+    assert_eq!(headlines(&run.stderr), ["error[E0401]: Use of moved value xs", "error[E0401]: Use of moved value p"]);
+    assert!(run.stderr.contains("  = moved at main.z:11:14\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("  = moved at main.z:14:14\n"), "{}", run.stderr);
+}
+
+#[test]
+fn math_functions_used_without_the_import_name_the_module() {
+    let run = check("fn main() {\n    print(sqrt(2.0));\n    print(pi);\n    print(sqr(2.0));\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0101]: Undefined variable: sqrt; it is in std.math, brought in by `import std.math;`",
+            "error[E0101]: Undefined variable: pi; it is in std.math, brought in by `import std.math;`",
+            "error[E0101]: Undefined variable: sqr",
+        ]
+    );
+}
+
+#[test]
+fn only_arrays_stored_somewhere_can_be_pushed_and_popped() {
+    let run = check(
+        "struct Bag {\n    items: [int],\n}\n\nfn bag() -> Bag {\n    let b: Bag;\n    return b;\n}\n\nfn main() {\n    let b = bag();\n    b.items.push(1);\n    bag().items.push(2);\n    [1].pop();\n}\n",
+    );
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0207]: Cannot push a temporary array; store it in a variable first",
+            "error[E0207]: Cannot pop a temporary array; store it in a variable first",
+        ]
+    );
+}
+
+#[test]
+fn strings_support_only_concatenation_and_comparison() {
+    let run = check("fn main() {\n    let a = \"ab\";\n    print(a + 1 + true);\n    print(a < \"b\");\n    print(a - \"b\");\n    print(a * 2);\n    print(a == 1);\n    print(a[0]);\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0204]: Operator Sub is not defined for strings",
+            "error[E0204]: Operator Mul is not defined for strings",
+            "error[E0204]: Cannot compare string with int",
+            "error[E0207]: Cannot index into value of type string",
+        ]
+    );
+}
+
+#[test]
+fn numbers_of_different_types_are_never_converted_implicitly() {
+    let run = check("fn half(x: float) -> float {\n    return x / 2.0;\n}\n\nfn whole() -> int {\n    return 1.5;\n}\n\nfn main() {\n    let f: float = 1;\n    let g = 2.0;\n    g = 3;\n    print(half(4));\n    print(g > 1);\n    let small: i8 = 1;\n    let big: i64 = small;\n    print(small as i64 + big);\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0201]: Type mismatch: expected int, found float; convert it explicitly with `as`",
+            "error[E0201]: Type mismatch: expected float, found int; convert it explicitly with `as`",
+            "error[E0201]: Type mismatch: expected float, found int; convert it explicitly with `as`",
+            "error[E0203]: Argument 1 of half has type int, expected float",
+            "error[E0205]: Cannot mix float and int operands; convert one side explicitly with `as`",
+            "error[E0201]: Type mismatch: expected i64, found i8; convert it explicitly with `as`",
+        ]
+    );
+}
+
+#[test]
+fn printed_values_must_be_printable_and_match_the_format() {
+    let run = check("fn main() {\n    let xs = [1];\n    let n: int? = null;\n    print(xs);\n    print(n);\n    printf(\"{} {}\", 1);\n    let f = \"{}\";\n    printf(f, 1);\n    print(1, 2);\n}\n");
+    assert_eq!(run.code, Some(1));
+    assert_eq!(
+        headlines(&run.stderr),
+        [
+            "error[E0214]: Cannot print a value of type [int]",
+            "error[E0212]: Value of type int? may be null; check it with `!= null` before using it",
+            "error[E0202]: Format of printf has 2 placeholders, found 1 value",
+            "error[E0215]: The format of printf must be a string literal",
+            "error[E0202]: Function print expects 1 argument, found 2",
+        ]
+    );
+}
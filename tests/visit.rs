@@ -0,0 +1,59 @@
+use z_lang::ast::{Expr, Literal, Type};
+use z_lang::visit::{walk_expr, walk_expr_mut, Visitor, VisitorMut};
+use z_lang::SemanticModel;
+
+/// The names of the functions called, the integers written and the types
+/// of the declarations, in order
+#[derive(Default)]
+struct Collect {
+    calls: Vec<String>,
+    ints: Vec<i128>,
+    types: Vec<String>,
+}
+
+impl Visitor for Collect {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Call(callee, _, _) => {
+                if let Expr::Identifier(name, _) = &**callee {
+                    self.calls.push(name.clone());
+                }
+            }
+            Expr::Literal(Literal::Int(value), _) => self.ints.push(*value),
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+
+    fn visit_type(&mut self, ty: &Type) {
+        self.types.push(ty.to_string());
+    }
+}
+
+struct Double;
+
+impl VisitorMut for Double {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let Expr::Literal(Literal::Int(value), _) = expr {
+            *value *= 2;
+        }
+        walk_expr_mut(self, expr);
+    }
+}
+
+#[test]
+fn visitors_walk_every_node_they_leave_to_the_defaults() {
+    let source = "fn twice(x: int) -> int {\n    return x * 2;\n}\n\nfn main() {\n    let xs: [int] = [1, twice(3)];\n    if len(xs) > 1 {\n        print(twice(xs[0]) + 4);\n    }\n}\n";
+    let model = SemanticModel::new(source).expect("the program checks");
+    let mut collect = Collect::default();
+    collect.visit_program(model.program());
+    assert_eq!(collect.calls, ["twice", "len", "print", "twice"]);
+    assert_eq!(collect.ints, [2, 1, 3, 1, 0, 4]);
+    assert_eq!(collect.types, ["int", "int", "void", "[int]"]);
+
+    let mut program = model.program().clone();
+    Double.visit_program_mut(&mut program);
+    let mut doubled = Collect::default();
+    doubled.visit_program(&program);
+    assert_eq!(doubled.ints, [4, 2, 6, 2, 0, 8]);
+}
@@ -0,0 +1,26 @@
+#![cfg(feature = "wasm")]
+
+use z_lang::wasm::{compile_to_c, diagnostics, version};
+
+#[test]
+fn the_playground_compiles_to_c_and_reports_what_zc_would() {
+    assert_eq!(version(), env!("CARGO_PKG_VERSION"));
+
+    let c = compile_to_c("fn main() {\n    print(\"hi\");\n}\n").expect("the source compiles");
+    assert!(c.contains("int main("), "{}", c);
+
+    let error = compile_to_c("fn main() {\n    let digits = 0..10;\n}\n").expect_err("ranges are only iterated over");
+    let warning = error.find("warning[W0001]").expect("the warning is reported");
+    let range = error.find("error[E0501]").expect("the error is reported");
+    assert!(warning < range, "{}", error);
+    assert!(error.contains(" --> <source>:2:18\n"), "{}", error);
+
+    let error = compile_to_c("fn main() {\n    print(1 + true);\n}\n").expect_err("ints and bools do not add");
+    assert!(error.starts_with("error[E0204]: Invalid operand types"), "{}", error);
+
+    assert_eq!(diagnostics("fn main() {\n    print(1);\n}\n"), "");
+    let found = diagnostics("fn main() {\n    let unused = 1;\n}\n");
+    assert!(found.starts_with("warning[W0001]: Unused variable: unused"), "{}", found);
+    let error = compile_to_c("import std.io;\n\nfn main() {}\n").expect_err("source text cannot import");
+    assert!(error.starts_with("error: "), "{}", error);
+}